@@ -1,10 +1,22 @@
+use anyhow::{anyhow, Error, Result};
 use clap::{
     builder::{
         styling::{AnsiColor, Effects},
         Styles,
-    }, crate_authors, crate_version, crate_description, Args, Parser, Subcommand
+    }, crate_authors, crate_version, crate_description, parser::ValueSource, ArgMatches, Args, CommandFactory, Parser, Subcommand
 };
 
+use crate::core::{
+    builtin::SECRET_MASK,
+    chain::looks_like_secret_name,
+    chain_retry::{ChainRetryOptions, DEFAULT_CHAIN_RETRY_DELAY_MS},
+    command::quote_for_shell,
+    interpreter::Interpreter,
+    options::{FailurePolicy, ProgramSelection},
+};
+use crate::commons::utility::AddOptions;
+use crate::generations::history::split_shell_words;
+
 // Configures Clap v3-style help menu colors
 const STYLES: Styles = Styles::styled()
     .header(AnsiColor::Green.on_default().effects(Effects::BOLD))
@@ -20,25 +32,74 @@ pub struct Arguments {
     /// Groupped features provided by `cchain`
     #[clap(subcommand)]
     pub commands: Commands,
+    /// Raise the limits on the chains loaded, for large chains that are
+    /// trusted, e.g. `--limits max_programs=2000,max_file_size=20M`. The
+    /// limits are max_file_size, max_programs, max_arguments and
+    /// max_placeholders
+    #[arg(long, global = true, value_delimiter = ',', value_parser = parse_key_value)]
+    pub limits: Vec<(String, String)>,
+    /// Parse the chains every time they are loaded, instead of reusing
+    /// those loaded before from unchanged files, in this invocation or
+    /// kept in the cache directory by earlier ones
+    #[arg(long, global = true, default_value = "false")]
+    pub no_cache: bool,
 }
 
 #[derive(Debug, Subcommand)]
 pub enum Commands {
     /// Run a chain
     Run(RunArguments),
+    /// Run a single command with the program options of a chain
+    Exec(ExecArguments),
+    /// Show which chain `cchain run` would select, without running it
+    Which(WhichArguments),
+    /// Run a chain several times, and report the durations of each
+    /// program and of the whole chain
+    Bench(BenchArguments),
     /// Add chain(s) to your bookmark
     Add(AddArguments),
     /// Show chain(s) in your bookmark
     List(ListArguments),
+    /// Configure a chain in your bookmark, e.g. the arguments it always
+    /// runs with
+    Config(ConfigArguments),
     /// Remove chain(s) to your bookmark
     #[clap(short_flag = 'r')]
     Remove(RemoveArguments),
     /// Clean chain(s) that are removed/moved from the original path
     Clean(CleanArguments),
+    /// Report the disk usage of the storage directory and prune what is no longer needed
+    Gc(GcArguments),
     /// Validate the chain syntax
+    ///
+    /// Relative paths in a chain resolve against the directory of the chain
+    /// file, or the `base_directory` in its metadata, not the directory cchain
+    /// runs in. This covers working directories, `equals_file` snapshots, and
+    /// commands written as paths like `./fix.sh` or `../fix.sh`, in programs,
+    /// remedy command lines and ready checks. Commands without a path, e.g.
+    /// `cargo`, are looked up in `PATH`.
     Check(CheckArguments),
+    /// Format chain files canonically
+    Fmt(FmtArguments),
     /// Create a chain template
     New(NewArguments),
+    /// Manage the values of variables shared by all chains, which are the
+    /// default answers at their prompts
+    Preset(PresetArguments),
+    /// Show the values cchain remembers for the variables of a chain, and
+    /// correct them without running the chain
+    Vars(VarsArguments),
+    /// Print the JSON Schema of chain files
+    Schema(SchemaArguments),
+    /// Print the completion script of a shell, e.g.
+    /// `cchain completions bash > /etc/bash_completion.d/cchain`
+    Completions(CompletionsArguments),
+    /// Print the variables of a chain for the completion of `--var`
+    #[command(name = "__complete-vars", hide = true)]
+    CompleteVars(CompleteVarsArguments),
+    /// Show the runs of the chains kept with `CCHAIN_TRACK_USAGE`, and the
+    /// failures that recur across runs
+    Stats(StatsArguments),
     /// Check version info
     #[clap(short_flag = 'v')]
     Version(VersionArguments)
@@ -47,11 +108,446 @@ pub enum Commands {
 #[derive(Debug, Args)]
 #[command(group = clap::ArgGroup::new("sources").required(true).multiple(false))]
 pub struct RunArguments {
-    /// Index of the chain, or a path to a chain, or keyword(s) of a chain. 
+    /// Stable ID or index of the chain, or a path to a chain, or keyword(s) of a chain. 
     /// Single keyword: `cchain run keyword1`.
     /// Multiple keywords: `cchain run "keyword1 keyword2"`.
     #[arg(group = "sources")]
+    pub chain: Option<String>,
+    /// Run every bookmarked chain with the tag, in alphabetical order of the names
+    #[arg(long, group = "sources")]
+    pub tag: Option<String>,
+    /// Run the chain given as JSON, e.g. `'[{"command": "echo", "arguments": ["hi"], "retry": 0}]'`.
+    /// Use `@path` to read it from a file, or `-` to read it from stdin
+    #[arg(long, group = "sources")]
+    pub inline: Option<String>,
+    /// Continue with the remaining chains after a chain fails.
+    /// Only applies with `--tag` or a manifest
+    #[arg(long, default_value = "false")]
+    pub keep_going: bool,
+    /// Continue after programs fail, whatever their `exit_on_failure`,
+    /// unless a program cannot be started at all
+    #[arg(long, default_value = "false")]
+    pub continue_on_failure: bool,
+    /// Stop at the first program that fails, even one with
+    /// `exit_on_failure: false`. Failures whose remedy command line
+    /// succeeds still follow `exit_on_failure`
+    #[arg(long, default_value = "false", conflicts_with = "continue_on_failure")]
+    pub stop_on_first_failure: bool,
+    /// With `--stop-on-first-failure`, also stop at failures whose remedy
+    /// command line succeeds
+    #[arg(long, default_value = "false", requires = "stop_on_first_failure")]
+    pub stop_even_if_remedied: bool,
+    /// Run up to this many tagged chains, or chains of a manifest, at a
+    /// time. Tagged chains are refused when any of them would prompt for
+    /// input. Only applies with `--tag` or a manifest
+    #[arg(long, conflicts_with = "inline")]
+    pub parallel: Option<usize>,
+    /// Rewrite the snapshot files of `equals_file` assertions with
+    /// the actual outputs
+    #[arg(long, default_value = "false")]
+    pub update_snapshots: bool,
+    /// Keep the temporary directory of the run, `<<chain_tmp>>`, and
+    /// show where it is, instead of removing it when the chain finishes
+    #[arg(long, default_value = "false")]
+    pub keep_tmp: bool,
+    /// Show how the environment of each program differs from the one of
+    /// cchain: the variables added, removed and changed, with secrets
+    /// masked. With `--dry-run`, shown under each program
+    #[arg(long, default_value = "false")]
+    pub diff_env: bool,
+    /// When cchain receives SIGTERM or SIGHUP, the seconds that the running
+    /// programs get to finish and the cleanup programs get to run, before
+    /// what is still running is killed
+    #[arg(long, default_value = "30")]
+    pub term_grace_seconds: u64,
+    /// Show the command line of each program without running the chain.
+    /// Variables are shown as placeholders
+    #[arg(long, default_value = "false", conflicts_with = "tag")]
+    pub dry_run: bool,
+    /// Write a JSON document with the exit code, the failed programs and
+    /// the duration to the path when cchain exits
+    #[arg(long)]
+    pub status_file: Option<String>,
+    /// Provide the value of a variable, in the form of `NAME=VALUE`.
+    /// Use `NAME=@path` to read the value from a file.
+    /// Can be specified multiple times
+    #[arg(long = "var", value_parser = parse_key_value)]
+    pub variables: Vec<(String, String)>,
+    /// Load the values of variables from a JSON object or a dotenv-style file.
+    /// `--var` takes precedence. Can be specified multiple times
+    #[arg(long = "var-file")]
+    pub variable_files: Vec<String>,
+    /// Run without reviewing the answers to the prompts asked when
+    /// the chain starts
+    #[arg(short, long, default_value = "false", conflicts_with = "tag")]
+    pub yes: bool,
+    /// Before running, show the value that `--var`, `.cchain.env`, the
+    /// preset and the remembered value supply for each variable, which
+    /// one wins, and pick another source or a new value
+    #[arg(long, default_value = "false", conflicts_with_all = ["tag", "yes"])]
+    pub confirm_vars: bool,
+    /// Show where the value of each variable came from after the run
+    #[arg(long, default_value = "false", conflicts_with = "tag")]
+    pub explain_variables: bool,
+    /// When a program fails for good, offer a shell in its working directory
+    /// with the variables of the chain exported, then retry, skip or abort.
+    /// Ignored without a terminal
+    #[arg(long, default_value = "false", conflicts_with = "tag")]
+    pub debug_on_failure: bool,
+    /// Also export the variables declared as `secret` to the debug shell
+    #[arg(long, default_value = "false", requires = "debug_on_failure")]
+    pub debug_expose_secrets: bool,
+    /// Run programs that delete files or mutate remote state, by their
+    /// `effects` or by their commands, without asking first
+    #[arg(long, default_value = "false")]
+    pub allow_destructive: bool,
+    /// Run only the programs at these indices, e.g. `--only 1,4`. The
+    /// variables used only by the other programs are not asked for
+    #[arg(long, value_delimiter = ',', conflicts_with = "tag")]
+    pub only: Vec<usize>,
+    /// Run the programs from this index on
+    #[arg(long, conflicts_with = "tag")]
+    pub from: Option<usize>,
+    /// Follow the run on a full-screen dashboard of the programs, their
+    /// times and their outputs. Falls back to the normal output without
+    /// a terminal
+    #[arg(long, default_value = "false", conflicts_with_all = ["tag", "debug_on_failure"])]
+    pub tui: bool,
+    /// Show the full output of every program, including those with
+    /// `display_output` set to `summary` or `silent`
+    #[arg(long, default_value = "false")]
+    pub show_all_output: bool,
+    /// Measure the disk reads and writes, all I/O and the CPU time of
+    /// each program with its child processes, shown when it finishes and
+    /// written to the status file. Only supported on Linux
+    #[arg(long, default_value = "false", conflicts_with_all = ["tag", "dry_run"])]
+    pub profile_io: bool,
+    /// Show a status line per finished program, with its duration, exit
+    /// code and bytes of output, instead of the output of the programs.
+    /// The end of the output of failed programs is still shown, e.g. for
+    /// the logs of CI jobs
+    #[arg(long, default_value = "false", conflicts_with_all = ["tag", "tui", "show_all_output"])]
+    pub summary_only: bool,
+    /// Read the keys that pause the run at a terminal: `p` pauses after
+    /// the current program, and while paused, `s` skips the next program
+    /// and `a` aborts the chain. The programs then share the terminal with
+    /// the keys, so only for chains whose programs do not read their input.
+    #[arg(long, default_value = "false", conflicts_with = "tag")]
+    pub pause_keys: bool,
+    /// Do not show the progress of the run in the title of the terminal.
+    /// Otherwise, at a terminal, the title shows the step and the command
+    /// running, and is restored once cchain exits
+    #[arg(long, default_value = "false", conflicts_with = "tag")]
+    pub no_title: bool,
+    /// Write the progress of the run as lines of JSON to this open file
+    /// descriptor, 3 or more, e.g. for the progress bar of a wrapper. The
+    /// events are described by `cchain schema --progress`
+    #[arg(long, conflicts_with_all = ["tag", "tui", "dry_run"])]
+    pub progress_fd: Option<i32>,
+    /// Write the progress of the run as lines of JSON to this file or
+    /// named pipe, where passing a file descriptor is awkward
+    #[arg(long, conflicts_with_all = ["tag", "tui", "dry_run", "progress_fd"])]
+    pub progress_file: Option<String>,
+    /// Do not compare the versions of the commands, the OS and the
+    /// environment variables read by the chain with its last successful
+    /// run. Saves running `<command> --version` for the commands
+    #[arg(long, default_value = "false")]
+    pub no_env_check: bool,
+    /// Run the whole chain again, up to this many times, when it fails
+    /// with what looks like an infrastructure error, e.g. a connection
+    /// reset or a registry answering 503. The answers to the prompts are
+    /// reused.
+    #[arg(long, conflicts_with_all = ["tag", "dry_run"])]
+    pub retry_chain: Option<u32>,
+    /// Also run the chain again when the output of a failed program
+    /// matches this regular expression
+    #[arg(long, requires = "retry_chain")]
+    pub retry_chain_on: Vec<String>,
+    /// Milliseconds to wait before the second attempt of the chain,
+    /// doubling with every attempt after
+    #[arg(long, default_value_t = DEFAULT_CHAIN_RETRY_DELAY_MS, requires = "retry_chain")]
+    pub retry_chain_delay_ms: u64,
+    /// List the programs with the indices that `--from` and `--only` take,
+    /// without running them or asking for variables
+    #[arg(
+        long,
+        default_value = "false",
+        conflicts_with_all = ["tag", "tui", "dry_run", "debug_on_failure", "progress_fd", "progress_file"]
+    )]
+    pub list_programs: bool,
+    /// Format of the program list
+    #[arg(long, value_enum, default_value = "text", requires = "list_programs")]
+    pub output: OutputFormat,
+}
+
+impl RunArguments {
+    /// Get the programs that run this time
+    pub fn get_program_selection(&self) -> ProgramSelection {
+        let mut program_selection: ProgramSelection = ProgramSelection::default();
+        if !self.only.is_empty() {
+            program_selection = program_selection.with_only(self.only.iter().copied().collect());
+        }
+        if let Some(from) = self.from {
+            program_selection = program_selection.with_from(from);
+        }
+
+        program_selection
+    }
+
+    /// Get how the whole chain is run again after infrastructure errors.
+    /// Fails when a `--retry-chain-on` pattern does not compile.
+    pub fn get_chain_retry_options(&self) -> Result<ChainRetryOptions, Error> {
+        Ok(ChainRetryOptions::new(self.retry_chain.unwrap_or(0))
+            .with_patterns(&self.retry_chain_on)?
+            .with_delay_ms(self.retry_chain_delay_ms))
+    }
+
+    /// Get how the failures of programs are handled in this run
+    pub fn get_failure_policy(&self) -> FailurePolicy {
+        if self.continue_on_failure {
+            FailurePolicy::ContinueOnFailure
+        } else if self.stop_on_first_failure {
+            FailurePolicy::StopOnFirstFailure { even_if_remedied: self.stop_even_if_remedied }
+        } else {
+            FailurePolicy::PerProgram
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct BenchArguments {
+    /// Index of the chain, or a path to a chain, or keyword(s) of a chain,
+    /// as taken by `cchain run`
+    pub chain: String,
+    /// Measured runs of the chain
+    #[arg(long, default_value = "5", value_parser = clap::value_parser!(u64).range(1..))]
+    pub iterations: u64,
+    /// Runs before the measured ones, whose durations are discarded
+    #[arg(long, default_value = "0")]
+    pub warmup: u64,
+    /// Flag the steps whose standard deviation is above this percentage
+    /// of their mean as unstable
+    #[arg(long, default_value = "10")]
+    pub unstable_threshold: f64,
+    /// Compare the medians with a report saved from `--output json`
+    #[arg(long)]
+    pub compare: Option<String>,
+    /// Also measure the disk reads and writes, all I/O and the CPU time
+    /// of each step, as the mean of the iterations. Only supported on Linux
+    #[arg(long, default_value = "false")]
+    pub profile_io: bool,
+    /// Format of the report. With `json`, only the report is printed
+    #[arg(long, value_enum, default_value = "text")]
+    pub output: OutputFormat,
+    /// Provide the value of a variable, in the form of `NAME=VALUE`.
+    /// Use `NAME=@path` to read the value from a file.
+    /// Can be specified multiple times
+    #[arg(long = "var", value_parser = parse_key_value)]
+    pub variables: Vec<(String, String)>,
+    /// Load the values of variables from a JSON object or a dotenv-style file.
+    /// `--var` takes precedence. Can be specified multiple times
+    #[arg(long = "var-file")]
+    pub variable_files: Vec<String>,
+}
+
+#[derive(Debug, Args)]
+pub struct WhichArguments {
+    /// Index of the chain, or a path to a chain, or keyword(s) of a chain,
+    /// as taken by `cchain run`
     pub chain: String,
+    /// Format of the resolution trace
+    #[arg(long, value_enum, default_value = "text")]
+    pub output: OutputFormat,
+}
+
+/// Output formats of the subcommands that report on chains
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum OutputFormat {
+    Text,
+    /// Machine-readable output for tooling
+    Json,
+}
+
+#[derive(Debug, Args)]
+pub struct ExecArguments {
+    /// Retry policy for executing the command.
+    /// Use -1 to retry indefinitely
+    #[arg(long, default_value = "0", allow_negative_numbers = true)]
+    pub retry: i32,
+    /// Override an environment variable, in the form of `KEY=VALUE`.
+    /// Can be specified multiple times
+    #[arg(long = "env", value_parser = parse_key_value)]
+    pub environment_variables: Vec<(String, String)>,
+    /// Set the working directory for the command
+    #[arg(long)]
+    pub workdir: Option<String>,
+    /// Interpreter to run the command with
+    #[arg(long, value_enum)]
+    pub interpreter: Option<Interpreter>,
+    /// Stop the command after this many seconds, like a termination
+    /// signal. The time limit covers the retries too
+    #[arg(long)]
+    pub timeout: Option<u64>,
+    /// Write the output of the command to the file
+    #[arg(long)]
+    pub store_to_file: Option<String>,
+    /// Save the command as a new chain, `cchain_<name>.json`
+    #[arg(long)]
+    pub save: Option<String>,
+    /// Format of the result. With `json`, only the status document of
+    /// `--status-file` is printed when the command finishes
+    #[arg(long, value_enum, default_value = "text")]
+    pub output: OutputFormat,
+    /// Write a JSON document with the exit code, the failed programs and
+    /// the duration to the path when cchain exits
+    #[arg(long)]
+    pub status_file: Option<String>,
+    /// The command and its arguments, after `--`
+    #[arg(last = true, required = true)]
+    pub command_line: Vec<String>,
+}
+
+/// The arguments of `cchain run` that select the chain, which cannot be
+/// stored with a chain
+const RUN_SOURCE_ARGUMENTS: [&str; 3] = ["chain", "tag", "inline"];
+
+/// The arguments of `cchain run` whose stored values are combined with
+/// those given on the command line, which win for the same variable
+const COMBINED_RUN_ARGUMENTS: [&str; 2] = ["variables", "variable_files"];
+
+/// Get the `cchain run` subcommand without a required chain, for the
+/// arguments stored with a chain
+fn get_stored_run_command() -> clap::Command {
+    let mut command: clap::Command = RunArguments::augment_args(clap::Command::new("run"))
+        .no_binary_name(true)
+        .mut_group("sources", |group| group.required(false));
+    command.build();
+
+    command
+}
+
+/// Parse the arguments stored with a chain, e.g. `--continue-on-failure --var
+/// env=prod`, as `cchain run` does
+pub fn parse_stored_run_arguments(stored: &str) -> Result<ArgMatches, Error> {
+    let words: Vec<String> =
+        split_shell_words(stored).ok_or_else(|| anyhow!("Run arguments `{}` have unbalanced quotes", stored))?;
+    let matches: ArgMatches = get_stored_run_command().try_get_matches_from(&words).map_err(|error| {
+        let message: String = error.render().to_string();
+        let reason: &str = message.lines().next().unwrap_or_default().trim_start_matches("error: ");
+        anyhow!("Invalid run arguments `{}`: {}", stored, reason)
+    })?;
+    if let Some(id) = RUN_SOURCE_ARGUMENTS.iter().find(|id| matches.value_source(id) == Some(ValueSource::CommandLine)) {
+        return Err(anyhow!("Run arguments cannot select the chain, but `{}` has `{}`", stored, id));
+    }
+
+    Ok(matches)
+}
+
+/// Merge the arguments stored with a chain into the command line of
+/// `cchain run`. A stored argument is left out when the command line
+/// has it, or an argument conflicting with it, except `--var` and
+/// `--var-file`, whose stored values come first so that those on the
+/// command line win.
+///
+/// # Returns
+///
+/// The merged arguments, and the stored arguments applied
+pub fn merge_stored_run_arguments(command_line: &[String], stored: &str) -> Result<(Arguments, Vec<String>), Error> {
+    let stored_matches: ArgMatches = parse_stored_run_arguments(stored)?;
+    let command_line_matches: ArgMatches = Arguments::command().try_get_matches_from(command_line)?;
+    let run_matches: &ArgMatches = command_line_matches
+        .subcommand_matches("run")
+        .ok_or_else(|| anyhow!("Stored run arguments only apply to `cchain run`"))?;
+    let is_given = |id: &str| run_matches.value_source(id) == Some(ValueSource::CommandLine);
+
+    let stored_command: clap::Command = get_stored_run_command();
+    let mut applied: Vec<String> = Vec::new();
+    for argument in stored_command.get_arguments() {
+        let id: &str = argument.get_id().as_str();
+        if stored_matches.value_source(id) != Some(ValueSource::CommandLine) {
+            continue;
+        }
+        // Conflicts are declared on one of the two arguments
+        let is_conflicting = |other: &clap::Arg| {
+            let conflicts_with = |first: &clap::Arg, second: &clap::Arg| {
+                stored_command.get_arg_conflicts_with(first).iter().any(|conflict| conflict.get_id() == second.get_id())
+            };
+            conflicts_with(argument, other) || conflicts_with(other, argument)
+        };
+        let is_overridden: bool = !COMBINED_RUN_ARGUMENTS.contains(&id)
+            && (is_given(id)
+                || stored_command
+                    .get_arguments()
+                    .any(|other| is_given(other.get_id().as_str()) && is_conflicting(other)));
+        if is_overridden {
+            continue;
+        }
+
+        let long: &str = argument.get_long().unwrap_or(id);
+        if !argument.get_action().takes_values() {
+            applied.push(format!("--{}", long));
+            continue;
+        }
+        for occurrence in stored_matches.get_raw_occurrences(id).into_iter().flatten() {
+            let values: Vec<String> = occurrence.map(|value| value.to_string_lossy().to_string()).collect();
+            let delimiter: String = argument.get_value_delimiter().unwrap_or(',').to_string();
+            applied.push(format!("--{}={}", long, values.join(&delimiter)));
+        }
+    }
+
+    // The stored arguments go right after `run`, before those given
+    let position: usize = command_line
+        .iter()
+        .position(|word| word == "run")
+        .map_or(command_line.len(), |position| position + 1);
+    let mut merged: Vec<String> = command_line.to_vec();
+    merged.splice(position..position, applied.iter().cloned());
+    let arguments: Arguments = Arguments::try_parse_from(&merged)?;
+
+    Ok((arguments, applied))
+}
+
+/// Mask the values of the `--var` arguments whose names look like the
+/// ones of secrets, e.g. `--var api_token=...`, before they are shown
+pub fn mask_secret_run_arguments(words: &[String]) -> Vec<String> {
+    let mut masked: Vec<String> = Vec::new();
+    let mut is_variable_value: bool = false;
+    for word in words {
+        let (prefix, assignment): (&str, Option<&str>) = match word.strip_prefix("--var=") {
+            Some(assignment) => ("--var=", Some(assignment)),
+            None if is_variable_value => ("", Some(word.as_str())),
+            None => ("", None),
+        };
+        is_variable_value = word == "--var";
+        masked.push(match assignment.and_then(|assignment| assignment.split_once('=')) {
+            Some((name, _)) if looks_like_secret_name(name) => format!("{}{}={}", prefix, name, SECRET_MASK),
+            _ => word.clone(),
+        });
+    }
+
+    masked
+}
+
+/// Mask the arguments stored with a chain like `mask_secret_run_arguments`.
+/// They are shown as they are stored when nothing is masked.
+pub fn mask_stored_run_arguments(stored: &str) -> String {
+    let Some(words) = split_shell_words(stored) else {
+        return stored.to_string();
+    };
+    let masked: Vec<String> = mask_secret_run_arguments(&words);
+    if masked == words {
+        return stored.to_string();
+    }
+
+    masked.iter().map(|word| quote_for_shell(word)).collect::<Vec<String>>().join(" ")
+}
+
+/// Parse a `KEY=VALUE` pair
+fn parse_key_value(s: &str) -> Result<(String, String), String> {
+    match s.split_once('=') {
+        Some((key, value)) if !key.is_empty() => Ok((key.to_string(), value.to_string())),
+        _ => Err(format!("`{}` is not in the form of `KEY=VALUE`", s)),
+    }
 }
 
 #[derive(Debug, Args)]
@@ -62,18 +558,72 @@ pub struct AddArguments {
     /// or, add all chains under this directory to the bookmark
     #[arg(group = "sources")]
     pub path: String,
+    /// Bookmark a chain file even if its name does not follow `cchain_*.json`
+    #[arg(long, default_value = "false")]
+    pub allow_any_name: bool,
+    /// Only check out these paths of the repository, e.g. the directories
+    /// of a monorepo that keep chains. Applies to repository URLs.
+    #[arg(long, num_args = 1.., hide = !cfg!(feature = "git"))]
+    pub sparse: Vec<String>,
+    /// Show the chains that would be added without adding them. A cloned
+    /// repository is removed afterwards.
+    #[arg(long, default_value = "false")]
+    pub list_only: bool,
 }
 
-#[derive(Debug, Parser)]
-pub struct ListArguments;
+impl AddArguments {
+    pub fn get_add_options(&self) -> AddOptions {
+        AddOptions::new()
+            .with_allow_any_name(self.allow_any_name)
+            .with_sparse_paths(self.sparse.clone())
+            .with_list_only(self.list_only)
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct ListArguments {
+    /// Only show the chains with the tag
+    #[arg(long)]
+    pub tag: Option<String>,
+    /// Show how often each chain was run, and when it was run last.
+    /// Runs are counted on this machine when `CCHAIN_TRACK_USAGE=1` is set
+    #[arg(long, default_value = "false")]
+    pub show_usage: bool,
+    /// Only show the chains that were not run within this many days
+    #[arg(long, value_name = "DAYS")]
+    pub stale: Option<u64>,
+    /// Show the last changes to the bookmark instead, with who made them
+    /// and when. Shows the last 20 changes without a number
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "20")]
+    pub audit: Option<usize>,
+    /// Only show the chains with stored run arguments, and show them
+    #[arg(long, default_value = "false")]
+    pub with_run_args: bool,
+}
+
+#[derive(Debug, Args)]
+#[command(group = clap::ArgGroup::new("changes").required(true).multiple(false))]
+pub struct ConfigArguments {
+    /// Stable ID or index of your chain in the bookmark.
+    /// Can be obtained with `cchain list`
+    pub index: String,
+    /// Arguments that `cchain run` always gets for the chain, e.g.
+    /// `--set-run-args "--continue-on-failure --var env=prod"`. Arguments given
+    /// on the command line take precedence
+    #[arg(long, group = "changes", allow_hyphen_values = true)]
+    pub set_run_args: Option<String>,
+    /// Remove the stored run arguments
+    #[arg(long, default_value = "false", group = "changes")]
+    pub clear: bool,
+}
 
 #[derive(Debug, Args)]
 #[command(group = clap::ArgGroup::new("sources").required(true).multiple(false))]
 pub struct RemoveArguments {
-    /// Index to your chain in the bookmark.
+    /// Stable ID or index of your chain in the bookmark.
     /// Can be obtained with `cchain list`
     #[arg(group = "sources")]
-    pub index: Option<usize>,
+    pub index: Option<String>,
     /// Completely reset the bookmark. This is useful
     /// when `cchain` breaks.
     #[arg(short, long, group = "sources", default_value = "false")]
@@ -82,14 +632,100 @@ pub struct RemoveArguments {
 
 #[derive(Debug, Args)]
 #[command(group = clap::ArgGroup::new("sources").required(false).multiple(false))]
-pub struct CleanArguments;
+pub struct CleanArguments {
+    /// Print what would be removed without saving the bookmark
+    #[arg(long, default_value = "false")]
+    pub dry_run: bool,
+    /// Also remove chains that exist but are no longer valid
+    #[arg(long, default_value = "false")]
+    pub invalid: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct GcArguments {
+    /// Print what would be removed without removing anything
+    #[arg(long, default_value = "false")]
+    pub dry_run: bool,
+    /// Remove entries of the bookmark audit log and runs of the run
+    /// history older than this many days
+    #[arg(long, default_value = "30")]
+    pub log_retention_days: u64,
+    /// Remove cached entries older than this many days
+    #[arg(long, default_value = "7")]
+    pub cache_ttl_days: u64,
+}
+
+#[derive(Debug, Args)]
+pub struct StatsArguments {
+    /// List the failures seen in more than one run, the most frequent first
+    #[arg(long, default_value = "false")]
+    pub failures: bool,
+    /// List the versions of the commands, the OS and the digests of the
+    /// environment variables that each chain last succeeded with
+    #[arg(long, default_value = "false", conflicts_with = "failures")]
+    pub environment: bool,
+}
 
 #[derive(Debug, Args)]
 #[command(group = clap::ArgGroup::new("sources").required(true).multiple(false))]
 pub struct CheckArguments {
-    /// A path to a chain, or an index in the bookmark
+    /// A path to a chain, or a stable ID or an index in the bookmark
     #[arg(group = "sources")]
     pub chain: String,
+    /// Also report arguments that may be handled differently from what
+    /// is intended, e.g. shell metacharacters under an interpreter
+    #[arg(long, default_value = "false")]
+    pub lint: bool,
+    /// With `--lint`, also report the programs identical to an earlier
+    /// program that does not run right before them
+    #[arg(long, default_value = "false", requires = "lint")]
+    pub separated_duplicates: bool,
+    /// Fail when a variable used in the chain is not declared in the
+    /// `variables` section of the metadata
+    #[arg(long, default_value = "false")]
+    pub require_declarations: bool,
+    /// Validate the file against the JSON Schema of chain files first,
+    /// reporting each problem at its JSON pointer
+    #[arg(long, default_value = "false")]
+    pub schema: bool,
+    /// Check again each time the chain, a chain it extends, or its
+    /// `.cchain.env` changes, until `q` is pressed
+    #[arg(long, default_value = "false")]
+    pub watch: bool,
+    /// Fix the problems with a certain fix in place first, e.g. misspelled
+    /// fields, legacy fields and the formatting, after backing up the file
+    /// with a `.bak` extension. The other problems are only explained
+    #[arg(long, default_value = "false", conflicts_with = "watch")]
+    pub fix: bool,
+    /// Show the changes of `--fix` as a diff without writing them
+    #[arg(long, default_value = "false", requires = "fix")]
+    pub dry_run: bool,
+}
+
+#[derive(Debug, Args)]
+#[command(group = clap::ArgGroup::new("sources").required(true).multiple(false))]
+pub struct FmtArguments {
+    /// Paths to chains, or to directories to format the chains in
+    #[arg(group = "sources")]
+    pub paths: Vec<String>,
+    /// Format every bookmarked chain
+    #[arg(long, default_value = "false", group = "sources")]
+    pub all: bool,
+    /// Do not write the files. Print the canonical form of each file that
+    /// is not formatted, and fail if there is any, e.g. in CI
+    #[arg(long, default_value = "false")]
+    pub check: bool,
+    /// Write every field of the programs, including those at their defaults
+    #[arg(long, default_value = "false")]
+    pub explicit_defaults: bool,
+    /// Also fix the problems chosen with the options below, after
+    /// a confirmation
+    #[arg(long, default_value = "false", conflicts_with = "check", requires = "fixes")]
+    pub fix: bool,
+    /// With `--fix`, remove the programs identical to the program right
+    /// before them, e.g. repeated by a generator
+    #[arg(long, default_value = "false", group = "fixes", requires = "fix")]
+    pub dedupe_consecutive: bool,
 }
 
 #[derive(Debug, Args)]
@@ -101,8 +737,120 @@ pub struct NewArguments {
     pub name: Option<String>,
     /// If specified, cchain will generate a chain by using a LLM provided
     /// in the environment variables
-    #[arg(short, long, group = "sources")]
+    #[arg(short, long, group = "sources", hide = !cfg!(feature = "llm"))]
     pub prompt: Option<String>,
+    /// Create the chain from the recent commands of the shell history,
+    /// selecting the commands to include among the last N, 20 by default
+    #[arg(long, value_name = "N", num_args = 0..=1, default_missing_value = "20", group = "sources", conflicts_with = "prompt")]
+    pub from_history: Option<usize>,
+    /// Read the history from this file instead of the one of the shell
+    /// in `$SHELL`. Bash, zsh and fish histories are recognized.
+    #[arg(long, requires = "from_history")]
+    pub history_file: Option<String>,
+    /// Include the prompt in the `generated_by` metadata of the
+    /// generated chain, instead of only its SHA-256 digest
+    #[arg(long, default_value = "false", requires = "prompt", conflicts_with = "no_provenance", hide = !cfg!(feature = "llm"))]
+    pub embed_prompt: bool,
+    /// Do not record the `generated_by` metadata in the generated chain
+    #[arg(long, default_value = "false", requires = "prompt", hide = !cfg!(feature = "llm"))]
+    pub no_provenance: bool,
+}
+
+#[derive(Debug, Args)]
+pub struct PresetArguments {
+    #[command(subcommand)]
+    pub action: PresetAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum PresetAction {
+    /// Set the preset of a variable. Secrets and variables declared with
+    /// `no_preset` never use it
+    Set {
+        /// Name of the variable, as in `<<name>>`
+        name: String,
+        value: String,
+    },
+    /// Show the presets
+    List,
+    /// Remove the preset of a variable
+    Unset {
+        name: String,
+    },
+}
+
+#[derive(Debug, Args)]
+pub struct VarsArguments {
+    #[command(subcommand)]
+    pub action: VarsAction,
+}
+
+#[derive(Debug, Subcommand)]
+pub enum VarsAction {
+    /// Show the variables of a chain, with their cached values, presets
+    /// and when they were last used
+    List {
+        /// Stable ID or index of the chain, or a path to a chain, or
+        /// keyword(s) of a chain, as taken by `cchain run`
+        chain: String,
+        /// Format of the list
+        #[arg(long, value_enum, default_value = "text")]
+        output: OutputFormat,
+    },
+    /// Keep a value for a variable of a chain, offered first at its prompts
+    Set {
+        chain: String,
+        /// Name of the variable, as in `<<name>>`
+        name: String,
+        value: String,
+    },
+    /// Forget the values kept for a variable of a chain
+    Unset {
+        chain: String,
+        name: String,
+    },
+    /// Forget the values kept for all variables of a chain
+    Clear {
+        chain: String,
+    },
+}
+
+impl VarsAction {
+    pub fn get_chain(&self) -> &str {
+        match self {
+            VarsAction::List { chain, .. }
+            | VarsAction::Set { chain, .. }
+            | VarsAction::Unset { chain, .. }
+            | VarsAction::Clear { chain } => chain,
+        }
+    }
+}
+
+/// The shells that `cchain completions` writes scripts for
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum CompletionShell {
+    Bash,
+    Zsh,
+    Fish,
+}
+
+#[derive(Debug, Args)]
+pub struct CompletionsArguments {
+    pub shell: CompletionShell,
+}
+
+#[derive(Debug, Args)]
+pub struct CompleteVarsArguments {
+    /// The chain as typed after `cchain run`
+    pub chain: String,
+}
+
+#[derive(Debug, Args)]
+pub struct SchemaArguments {
+    /// Print the JSON Schema of the lines written by `cchain run
+    /// --progress-fd` instead
+    #[arg(long, default_value = "false")]
+    pub progress: bool,
 }
 
 #[derive(Debug, Args)]
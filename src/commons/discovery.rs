@@ -0,0 +1,148 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use crate::{core::chain::Chain, marker::bookmark::Bookmark};
+
+use super::{errors::PackageError, utility::check_required_packages};
+
+/// What became of a chain file found when adding chains
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiscoveryOutcome {
+    Added,
+    /// Would be added, when only listing
+    Addable,
+    /// The chain is already in the bookmark
+    Duplicate,
+    /// The chain is in the repository, but outside the sparse paths, so
+    /// it is not checked out
+    OutsideSparsePaths,
+    /// Packages required by the chain are not installed
+    MissingPackages(String),
+    /// The chain cannot be loaded, or its name is not accepted
+    Invalid(String),
+}
+
+/// A chain file found when adding chains
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveredChain {
+    /// The path of the file, relative to the added directory
+    pub path: PathBuf,
+    pub outcome: DiscoveryOutcome,
+}
+
+/// The counts of the chain files found in a directory
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DirectorySummary {
+    /// The directory, relative to the added directory, or `.`
+    pub directory: String,
+    pub found: usize,
+    /// Chains added, or that would be added when only listing
+    pub added: usize,
+    pub duplicates: usize,
+    pub outside_sparse_paths: usize,
+    pub missing_packages: usize,
+    pub invalid: usize,
+}
+
+impl DirectorySummary {
+    fn count(&mut self, outcome: &DiscoveryOutcome) {
+        self.found += 1;
+        match outcome {
+            DiscoveryOutcome::Added | DiscoveryOutcome::Addable => self.added += 1,
+            DiscoveryOutcome::Duplicate => self.duplicates += 1,
+            DiscoveryOutcome::OutsideSparsePaths => self.outside_sparse_paths += 1,
+            DiscoveryOutcome::MissingPackages(_) => self.missing_packages += 1,
+            DiscoveryOutcome::Invalid(_) => self.invalid += 1,
+        }
+    }
+}
+
+/// The chain files found when adding a directory or a repository, and
+/// what became of each of them
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiscoverySummary {
+    chains: Vec<DiscoveredChain>,
+    /// Sparse paths that no file of the repository is under
+    unmatched_sparse_paths: Vec<String>,
+}
+
+impl DiscoverySummary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn push(&mut self, path: PathBuf, outcome: DiscoveryOutcome) {
+        self.chains.push(DiscoveredChain { path, outcome });
+    }
+
+    pub fn get_chains(&self) -> &[DiscoveredChain] {
+        &self.chains
+    }
+
+    pub fn set_unmatched_sparse_paths(&mut self, unmatched_sparse_paths: Vec<String>) {
+        self.unmatched_sparse_paths = unmatched_sparse_paths;
+    }
+
+    pub fn get_unmatched_sparse_paths(&self) -> &[String] {
+        &self.unmatched_sparse_paths
+    }
+
+    /// Count the chains of each directory, in the order of the directories
+    pub fn get_directory_summaries(&self) -> Vec<DirectorySummary> {
+        let mut directory_summaries: BTreeMap<String, DirectorySummary> = BTreeMap::new();
+        for chain in &self.chains {
+            let directory: String = match chain.path.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => parent.to_string_lossy().into_owned(),
+                _ => ".".to_string(),
+            };
+            directory_summaries
+                .entry(directory.clone())
+                .or_insert_with(|| DirectorySummary { directory, ..Default::default() })
+                .count(&chain.outcome);
+        }
+
+        directory_summaries.into_values().collect()
+    }
+
+    /// Count the chains of all directories
+    pub fn get_total(&self) -> DirectorySummary {
+        let mut total: DirectorySummary = DirectorySummary { directory: "Total".to_string(), ..Default::default() };
+        for chain in &self.chains {
+            total.count(&chain.outcome);
+        }
+
+        total
+    }
+}
+
+/// Add a chain file found in a directory to the bookmark, or only check
+/// whether it would be added
+pub fn discover_chain(bookmark: &mut Bookmark, path: &Path, list_only: bool) -> DiscoveryOutcome {
+    let path_string: String = match path.canonicalize() {
+        Ok(path) => path.to_string_lossy().into_owned(),
+        Err(error) => return DiscoveryOutcome::Invalid(error.to_string()),
+    };
+    if bookmark
+        .get_chain_references()
+        .iter()
+        .any(|chain_reference| chain_reference.get_chain_path_string() == path_string)
+    {
+        return DiscoveryOutcome::Duplicate;
+    }
+
+    let result = if list_only {
+        Chain::from_file(&path_string).and_then(|chain| check_required_packages(&chain))
+    } else {
+        bookmark.add_chain_reference(path_string)
+    };
+    match result {
+        Ok(_) if list_only => DiscoveryOutcome::Addable,
+        Ok(_) => DiscoveryOutcome::Added,
+        Err(error) if error.downcast_ref::<PackageError>().is_some() => {
+            DiscoveryOutcome::MissingPackages(error.to_string())
+        }
+        Err(error) => DiscoveryOutcome::Invalid(error.to_string()),
+    }
+}
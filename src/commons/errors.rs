@@ -1,3 +1,7 @@
+use crate::core::sanitize::get_tail;
+
+use super::termination::ProcessTermination;
+
 #[derive(Debug, thiserror::Error)]
 pub enum PackageError {
     #[error(
@@ -9,3 +13,85 @@ pub enum PackageError {
         chain_name: String,
     },
 }
+
+#[derive(Debug, thiserror::Error)]
+pub enum CommandLineError {
+    #[error(
+        "Process exited with non-zero status: {status}{}",
+        termination.as_ref().map_or(String::new(), |termination| format!(". {}", termination))
+    )]
+    NonZeroExit {
+        status: String,
+        /// The exit code, None when the process was killed by a signal
+        exit_code: Option<i32>,
+        /// Combined stdout and stderr collected before the exit
+        output: String,
+        /// How the process ended, when it did not exit by itself
+        termination: Option<ProcessTermination>,
+    },
+    #[error("Cancelled because another program in the concurrency group failed")]
+    Cancelled {
+        /// Combined stdout and stderr collected before the cancellation
+        output: String,
+    },
+    /// The process could not be started, e.g. the command does not exist
+    #[error("{message}")]
+    SpawnFailed { kind: SpawnErrorKind, message: String },
+}
+
+/// Why a process could not be started. None of these go away by
+/// starting the process again, so spawn failures are not retried.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SpawnErrorKind {
+    /// The command is not found in `PATH`
+    NotFound,
+    /// The command is not executable by the user
+    PermissionDenied,
+    /// The working directory does not exist, or is not a directory
+    InvalidWorkingDirectory,
+    Other,
+}
+
+impl CommandLineError {
+    /// Get the output collected before the failure
+    pub fn get_output(&self) -> &str {
+        match self {
+            CommandLineError::NonZeroExit { output, .. } | CommandLineError::Cancelled { output } => output,
+            CommandLineError::SpawnFailed { .. } => "",
+        }
+    }
+
+    /// Get how the process ended, when it was killed by a signal or crashed
+    pub fn get_termination(&self) -> Option<&ProcessTermination> {
+        match self {
+            CommandLineError::NonZeroExit { termination, .. } => termination.as_ref(),
+            _ => None,
+        }
+    }
+
+    /// Get the last part of the output, which usually has the reason
+    /// of the failure
+    pub fn get_output_tail(&self, max_characters: usize) -> &str {
+        get_tail(self.get_output(), max_characters)
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum ChainError {
+    /// The chain cannot be loaded, or its syntax is invalid
+    #[error("{0}")]
+    Validation(String),
+    /// A program failed with `exit_on_failure`, so the chain is aborted
+    #[error("{message}")]
+    ProgramFailed {
+        program_index: usize,
+        message: String,
+    },
+    /// A value is needed from the user, but the input stream is closed
+    #[error("Input stream is closed while waiting for: {prompt}")]
+    InputRequired { prompt: String },
+    /// cchain is asked to terminate, e.g. with SIGTERM, so the remaining
+    /// programs are not run
+    #[error("cchain is asked to terminate, so the remaining programs are not run")]
+    Terminated,
+}
@@ -0,0 +1,198 @@
+use std::{
+    collections::BTreeSet,
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+/// Extensions tried for commands on Windows when `PATHEXT` is not set
+pub const DEFAULT_PATHEXT: &str = ".COM;.EXE;.BAT;.CMD";
+
+/// How commands are looked up in `PATH`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExecutableLookup {
+    /// Files with the exact name of the command and an executable bit
+    Unix,
+    /// Files named by the command, or by the command with one of the
+    /// extensions, compared regardless of case as Windows does
+    Windows { extensions: Vec<String> },
+}
+
+impl ExecutableLookup {
+    /// Decide the lookup of the platform cchain runs on
+    pub fn from_environment() -> Self {
+        Self::from_pathext(std::env::var_os("PATHEXT").as_deref())
+    }
+
+    /// Decide the lookup of the platform cchain runs on, with the
+    /// `PATHEXT` a program gets, e.g. from its environment overrides
+    pub fn from_pathext(pathext: Option<&OsStr>) -> Self {
+        if cfg!(windows) {
+            Self::windows(pathext)
+        } else {
+            Self::Unix
+        }
+    }
+
+    /// Decide the Windows lookup from the value of `PATHEXT`
+    pub fn windows(pathext: Option<&OsStr>) -> Self {
+        let pathext: String = pathext
+            .map(|pathext| pathext.to_string_lossy().into_owned())
+            .filter(|pathext| !pathext.trim().is_empty())
+            .unwrap_or_else(|| DEFAULT_PATHEXT.to_string());
+        let extensions: Vec<String> = pathext
+            .split(';')
+            .map(|extension| extension.trim().to_lowercase())
+            .filter(|extension| extension.starts_with('.') && extension.len() > 1)
+            .collect();
+
+        Self::Windows { extensions }
+    }
+
+    /// Resolve a command the way the shell of the platform does: a
+    /// command with a path is taken relative to the current directory,
+    /// and a bare name is looked up in each directory of `PATH` in order.
+    pub fn resolve(&self, command: &str, path: &OsStr, current_directory: &Path) -> Option<PathBuf> {
+        if command.is_empty() {
+            return None;
+        }
+
+        let command_path: &Path = Path::new(command);
+        if command.contains('/') || command.contains('\\') {
+            let candidate: PathBuf = current_directory.join(command_path);
+            let directory: &Path = candidate.parent()?;
+            let name: &str = candidate.file_name()?.to_str()?;
+            return self.find_in_directory(directory, name);
+        }
+
+        std::env::split_paths(path)
+            .filter(|directory| !directory.as_os_str().is_empty())
+            .find_map(|directory| self.find_in_directory(&directory, command))
+    }
+
+    /// List the names of the commands in the directories of `PATH`, as
+    /// they are typed, i.e. without the extensions on Windows
+    pub fn list_commands(&self, path: &OsStr) -> BTreeSet<String> {
+        let mut commands: BTreeSet<String> = BTreeSet::new();
+        for directory in std::env::split_paths(path) {
+            let entries = match std::fs::read_dir(&directory) {
+                Ok(entries) => entries,
+                Err(_) => continue,
+            };
+            for entry in entries.filter_map(|entry| entry.ok()) {
+                let entry_path: PathBuf = entry.path();
+                let name: String = entry.file_name().to_string_lossy().into_owned();
+                match self {
+                    ExecutableLookup::Unix if is_executable_file(&entry_path) => {
+                        commands.insert(name);
+                    }
+                    ExecutableLookup::Windows { extensions } if entry_path.is_file() => {
+                        let lowercase_name: String = name.to_lowercase();
+                        if let Some(extension) =
+                            extensions.iter().find(|extension| lowercase_name.ends_with(extension.as_str()))
+                        {
+                            commands.insert(name[..name.len() - extension.len()].to_string());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        commands
+    }
+
+    fn find_in_directory(&self, directory: &Path, name: &str) -> Option<PathBuf> {
+        match self {
+            ExecutableLookup::Unix => {
+                let candidate: PathBuf = directory.join(name);
+                is_executable_file(&candidate).then_some(candidate)
+            }
+            ExecutableLookup::Windows { extensions } => {
+                // A name with one of the extensions is tried as it is,
+                // others with each extension in the order of `PATHEXT`
+                let lowercase_name: String = name.to_lowercase();
+                let candidates: Vec<String> = if extensions.iter().any(|extension| lowercase_name.ends_with(extension.as_str())) {
+                    vec![lowercase_name]
+                } else {
+                    extensions.iter().map(|extension| format!("{}{}", lowercase_name, extension)).collect()
+                };
+                let entries: Vec<PathBuf> = std::fs::read_dir(directory)
+                    .ok()?
+                    .filter_map(|entry| entry.ok())
+                    .map(|entry| entry.path())
+                    .filter(|entry| entry.is_file())
+                    .collect();
+
+                candidates.iter().find_map(|candidate| {
+                    entries
+                        .iter()
+                        .find(|entry| {
+                            entry.file_name().is_some_and(|file_name| file_name.to_string_lossy().to_lowercase() == *candidate)
+                        })
+                        .cloned()
+                })
+            }
+        }
+    }
+}
+
+/// Resolve the command of an argv for the Windows lookup, so that
+/// commands without extensions run like they do in `cmd`. Batch scripts,
+/// e.g. the `npm.cmd` shim, are spawned as they are, so that the standard
+/// library quotes their arguments with the rules of `cmd`, and `&`, `|`
+/// or `^` in a value are never run. Under the Unix lookup, or when the
+/// command is not found, the argv is kept as it is.
+///
+/// # Arguments
+///
+/// * `current_directory` - The directory the program runs in, which
+///   commands with a path are relative to
+pub fn resolve_argv(argv: Vec<String>, lookup: &ExecutableLookup, path: &OsStr, current_directory: &Path) -> Vec<String> {
+    if *lookup == ExecutableLookup::Unix {
+        return argv;
+    }
+    let resolved: PathBuf = match argv.first().and_then(|command| lookup.resolve(command, path, current_directory)) {
+        Some(resolved) => resolved,
+        None => return argv,
+    };
+
+    let resolved: String = simplify_windows_path(&resolved.to_string_lossy());
+    let mut resolved_argv: Vec<String> = Vec::with_capacity(argv.len());
+    resolved_argv.push(resolved);
+    resolved_argv.extend(argv.into_iter().skip(1));
+
+    resolved_argv
+}
+
+/// Check whether a resolved command is a batch script, which Windows
+/// runs through `cmd`, so that not every argument can be passed to it
+pub fn is_batch_script(path: &Path) -> bool {
+    path.extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase())
+        .is_some_and(|extension| extension == "cmd" || extension == "bat")
+}
+
+/// Turn a verbatim Windows path, e.g. `\\?\C:\work` or `\\?\UNC\server\share`,
+/// into its plain form, as `cmd` does not accept verbatim working directories
+pub fn simplify_windows_path(path: &str) -> String {
+    if let Some(unc_path) = path.strip_prefix(r"\\?\UNC\") {
+        return format!(r"\\{}", unc_path);
+    }
+    match path.strip_prefix(r"\\?\") {
+        // Only drive-letter paths have a plain form
+        Some(drive_path) if drive_path.chars().nth(1) == Some(':') => drive_path.to_string(),
+        _ => path.to_string(),
+    }
+}
+
+#[cfg(unix)]
+fn is_executable_file(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    std::fs::metadata(path).is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable_file(path: &Path) -> bool {
+    path.is_file()
+}
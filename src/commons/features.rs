@@ -0,0 +1,58 @@
+use anyhow::{anyhow, Error};
+
+/// An integration that cchain can be built without, for minimal
+/// containers and air-gapped machines. Each is a cargo feature, on by
+/// default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Feature {
+    /// Generating chains and the `llm_generate` function
+    Llm,
+    /// `git` steps and adding chains from repositories
+    Git,
+    /// Checking that the commands of a chain are installed
+    PackageCheck,
+}
+
+impl Feature {
+    pub const ALL: [Feature; 3] = [Feature::Llm, Feature::Git, Feature::PackageCheck];
+
+    /// The name of the cargo feature
+    pub fn get_name(&self) -> &'static str {
+        match self {
+            Feature::Llm => "llm",
+            Feature::Git => "git",
+            Feature::PackageCheck => "package-check",
+        }
+    }
+
+    /// Whether cchain is built with the integration
+    pub fn is_enabled(&self) -> bool {
+        match self {
+            Feature::Llm => cfg!(feature = "llm"),
+            Feature::Git => cfg!(feature = "git"),
+            Feature::PackageCheck => cfg!(feature = "package-check"),
+        }
+    }
+
+    /// The error of using what needs the integration, e.g.
+    /// "cchain is built without LLM support, so `llm_generate` is
+    /// unavailable. Rebuild it with the `llm` feature."
+    pub fn get_unavailable_error(&self, usage: &str) -> Error {
+        anyhow!(
+            "cchain is built without {}, so {} is unavailable. Rebuild it with the `{}` feature",
+            self,
+            usage,
+            self.get_name()
+        )
+    }
+}
+
+impl std::fmt::Display for Feature {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Feature::Llm => write!(f, "LLM support"),
+            Feature::Git => write!(f, "git support"),
+            Feature::PackageCheck => write!(f, "package checking"),
+        }
+    }
+}
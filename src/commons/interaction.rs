@@ -0,0 +1,166 @@
+use std::{
+    collections::VecDeque,
+    io::IsTerminal,
+    sync::{Arc, Mutex},
+};
+
+use anyhow::{Error, Result};
+
+use super::{errors::ChainError, utility::input_message};
+
+/// Asks the user questions while a chain runs
+pub trait Interactor: Send {
+    /// Whether there is a user to answer the prompts
+    fn is_interactive(&self) -> bool;
+
+    /// Ask the user, and return the answer without surrounding whitespace
+    fn prompt(&mut self, message: &str) -> Result<String, Error>;
+}
+
+/// Prompts on the terminal
+pub struct TerminalInteractor;
+
+impl Interactor for TerminalInteractor {
+    fn is_interactive(&self) -> bool {
+        std::io::stdin().is_terminal()
+    }
+
+    fn prompt(&mut self, message: &str) -> Result<String, Error> {
+        Ok(input_message(message)?.trim().to_string())
+    }
+}
+
+/// Answers the prompts from a script, and records them. This is for
+/// driving chains without a terminal, e.g. in tests.
+pub struct ScriptedInteractor {
+    answers: VecDeque<String>,
+    prompts: Arc<Mutex<Vec<String>>>,
+}
+
+impl ScriptedInteractor {
+    pub fn new(answers: Vec<String>) -> Self {
+        Self {
+            answers: answers.into(),
+            prompts: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    /// Get a handle to the prompts asked so far, which stays valid
+    /// after the interactor is handed to a chain
+    pub fn get_prompts(&self) -> Arc<Mutex<Vec<String>>> {
+        self.prompts.clone()
+    }
+}
+
+impl Interactor for ScriptedInteractor {
+    fn is_interactive(&self) -> bool {
+        true
+    }
+
+    fn prompt(&mut self, message: &str) -> Result<String, Error> {
+        self.prompts.lock().unwrap().push(message.to_string());
+        self.answers
+            .pop_front()
+            .ok_or_else(|| ChainError::InputRequired { prompt: message.to_string() }.into())
+    }
+}
+
+/// Has no user to ask, e.g. for chains running in parallel. Prompts
+/// fail, and values from `.cchain.env` are used without prompting.
+pub struct UnattendedInteractor;
+
+impl Interactor for UnattendedInteractor {
+    fn is_interactive(&self) -> bool {
+        false
+    }
+
+    fn prompt(&mut self, message: &str) -> Result<String, Error> {
+        Err(ChainError::InputRequired { prompt: message.to_string() }.into())
+    }
+}
+
+/// An interactor shared by a chain and its programs. By default,
+/// the terminal is used.
+#[derive(Clone, Default)]
+pub struct SharedInteractor(Option<Arc<Mutex<dyn Interactor>>>);
+
+impl SharedInteractor {
+    pub fn new(interactor: impl Interactor + 'static) -> Self {
+        Self(Some(Arc::new(Mutex::new(interactor))))
+    }
+
+    pub fn is_interactive(&self) -> bool {
+        match &self.0 {
+            Some(interactor) => interactor.lock().unwrap().is_interactive(),
+            None => TerminalInteractor.is_interactive(),
+        }
+    }
+
+    pub fn prompt(&self, message: &str) -> Result<String, Error> {
+        match &self.0 {
+            Some(interactor) => interactor.lock().unwrap().prompt(message),
+            None => TerminalInteractor.prompt(message),
+        }
+    }
+
+    /// Ask the user, offering the suggestions as numbered quick picks.
+    /// Without a user at a terminal, the plain prompt is asked.
+    pub fn prompt_with_quick_picks(&self, message: &str, suggestions: &[String]) -> Result<String, Error> {
+        if suggestions.is_empty() || !self.is_interactive() {
+            return self.prompt(message);
+        }
+
+        let answer: String = self.prompt(&render_quick_picks(message, suggestions))?;
+        Ok(parse_quick_pick(&answer, suggestions))
+    }
+}
+
+/// Add numbered quick picks to a prompt, beneath the description and
+/// the example if there are any, e.g. `1) main  2) develop  or type a new value`
+pub fn render_quick_picks(message: &str, suggestions: &[String]) -> String {
+    let quick_picks: String = suggestions
+        .iter()
+        .enumerate()
+        .map(|(index, suggestion)| format!("{}) {}", index + 1, suggestion))
+        .collect::<Vec<String>>()
+        .join("  ");
+    let message: &str = message.strip_suffix("\n   >").unwrap_or(message);
+
+    format!("{}\n     {}  or type a new value\n   >", message, quick_picks)
+}
+
+/// Turn an answer to a prompt with quick picks into the value. The
+/// number of a quick pick selects it. Any other answer is the value
+/// itself, and a number in quotes, e.g. `"2"`, is the number itself.
+pub fn parse_quick_pick(answer: &str, suggestions: &[String]) -> String {
+    let unquoted: Option<&str> = answer
+        .strip_prefix('"')
+        .and_then(|answer| answer.strip_suffix('"'))
+        .or_else(|| answer.strip_prefix('\'').and_then(|answer| answer.strip_suffix('\'')));
+    if let Some(unquoted) = unquoted.filter(|unquoted| unquoted.parse::<usize>().is_ok()) {
+        return unquoted.to_string();
+    }
+
+    match answer.parse::<usize>() {
+        Ok(number) if (1..=suggestions.len()).contains(&number) => suggestions[number - 1].clone(),
+        _ => answer.to_string(),
+    }
+}
+
+impl std::fmt::Debug for SharedInteractor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.0 {
+            Some(_) => write!(f, "SharedInteractor(custom)"),
+            None => write!(f, "SharedInteractor(terminal)"),
+        }
+    }
+}
+
+/// Interactors do not take part in comparing the programs that hold them
+impl PartialEq for SharedInteractor {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for SharedInteractor {}
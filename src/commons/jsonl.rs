@@ -0,0 +1,110 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, Result};
+use serde::{de::DeserializeOwned, Serialize};
+
+use super::lock::{write_file_atomically, FileLock};
+
+/// A file of JSON records, one per line, e.g. the run counters or the
+/// audit log. Each record is appended with a single write, which does not
+/// interleave with the writes of other processes, so that concurrent runs
+/// do not overwrite each other.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct JsonLinesFile {
+    path: PathBuf,
+}
+
+impl JsonLinesFile {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Append a record. The lock keeps it from being lost to a rewrite.
+    pub fn append<T: Serialize>(&self, record: &T) -> Result<(), Error> {
+        let mut line: String = serde_json::to_string(record)?;
+        line.push('\n');
+
+        let _lock: FileLock = self.lock()?;
+        std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?
+            .write_all(line.as_bytes())?;
+
+        Ok(())
+    }
+
+    /// Read the records in the order they were written. A missing file
+    /// has no records.
+    ///
+    /// # Returns
+    ///
+    /// The records, and the number of lines that could not be read, e.g.
+    /// written halfway
+    pub fn read<T: DeserializeOwned>(&self) -> (Vec<T>, usize) {
+        let content: Vec<u8> = std::fs::read(&self.path).unwrap_or_default();
+        let mut records: Vec<T> = Vec::new();
+        let mut skipped_lines: usize = 0;
+        for line in String::from_utf8_lossy(&content).lines() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            match serde_json::from_str(line) {
+                Ok(record) => records.push(record),
+                Err(_) => skipped_lines += 1,
+            }
+        }
+
+        (records, skipped_lines)
+    }
+
+    /// Take the lock of the file, e.g. to read and rewrite it without
+    /// losing the records appended in between
+    pub fn lock(&self) -> Result<FileLock, Error> {
+        FileLock::acquire(&self.path)
+    }
+
+    /// Replace the records of the file at once. The lock of the file must
+    /// be held.
+    pub fn rewrite<T: Serialize>(&self, records: &[T]) -> Result<(), Error> {
+        let mut content: String = String::new();
+        for record in records {
+            content.push_str(&serde_json::to_string(record)?);
+            content.push('\n');
+        }
+
+        write_file_atomically(&self.path, content.as_bytes())
+    }
+
+    /// Drop the records for which `keep` does not hold. Unreadable lines
+    /// are dropped too, once anything is.
+    ///
+    /// # Returns
+    ///
+    /// The records dropped
+    pub fn retain<T, F>(&self, keep: F) -> Result<Vec<T>, Error>
+    where
+        T: Serialize + DeserializeOwned,
+        F: Fn(&T) -> bool,
+    {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let _lock: FileLock = self.lock()?;
+        let (records, _) = self.read::<T>();
+        let (kept, dropped): (Vec<T>, Vec<T>) = records.into_iter().partition(|record| keep(record));
+        if !dropped.is_empty() {
+            self.rewrite(&kept)?;
+        }
+
+        Ok(dropped)
+    }
+}
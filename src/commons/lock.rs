@@ -0,0 +1,228 @@
+use std::{
+    fs::{File, Permissions},
+    io::{Read, Seek, Write},
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error, Result};
+
+use super::{digest::sha256_hex, storage::Storage};
+
+/// How long to wait for another cchain process to release a file
+pub const FILE_LOCK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// How long to wait between two attempts to take a lock
+const FILE_LOCK_RETRY_INTERVAL: Duration = Duration::from_millis(20);
+
+/// Name of the directory in the data directory that keeps the locks of
+/// the files of the user, e.g. chain files
+pub const USER_FILE_LOCK_DIRECTORY_NAME: &str = "locks";
+
+/// An advisory lock on a shared state file, e.g. the bookmark, held
+/// until it is dropped. The lock is taken on a `.lock` file next to the
+/// state file, which keeps the process id of the holder, so that the
+/// state file itself can be replaced while the lock is held.
+#[derive(Debug)]
+pub struct FileLock {
+    lock_path: PathBuf,
+    /// Kept open while the lock is held, as closing it releases the lock
+    _file: File,
+}
+
+impl FileLock {
+    /// Take the lock of a state file, waiting for `FILE_LOCK_TIMEOUT`
+    /// at most
+    pub fn acquire(path: &Path) -> Result<Self, Error> {
+        Self::acquire_with_timeout(path, FILE_LOCK_TIMEOUT)
+    }
+
+    pub fn acquire_with_timeout(path: &Path, timeout: Duration) -> Result<Self, Error> {
+        Self::acquire_lock_file(path, get_lock_path(path), timeout)
+    }
+
+    /// Take the lock of a file of the user, e.g. a chain file being fixed
+    /// or formatted. Its lock file is kept in the directory instead of
+    /// next to it, named by the digest of its canonical path, so that
+    /// nothing is left beside the files of the user.
+    pub fn acquire_in(path: &Path, lock_directory: &Path) -> Result<Self, Error> {
+        let canonical_path: PathBuf = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+        let name: String = sha256_hex(canonical_path.to_string_lossy().as_bytes());
+
+        Self::acquire_lock_file(path, lock_directory.join(format!("{}.lock", name)), FILE_LOCK_TIMEOUT)
+    }
+
+    fn acquire_lock_file(path: &Path, lock_path: PathBuf, timeout: Duration) -> Result<Self, Error> {
+        if let Some(parent) = lock_path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let started_at: Instant = Instant::now();
+        loop {
+            if let Some(mut file) = try_lock(&lock_path)? {
+                // Tell the processes waiting for the lock who holds it
+                file.set_len(0)?;
+                file.rewind()?;
+                write!(file, "{}", std::process::id())?;
+                return Ok(Self { lock_path, _file: file });
+            }
+
+            if started_at.elapsed() >= timeout {
+                let mut holder: String = String::new();
+                if let Ok(mut file) = File::open(&lock_path) {
+                    let _ = file.read_to_string(&mut holder);
+                }
+                let holder: String = match holder.trim() {
+                    "" => "another process".to_string(),
+                    process_id => format!("process {}", process_id),
+                };
+                return Err(anyhow!(
+                    "Timed out after {} seconds waiting for {}, which is locked by {}. Remove {} if that process no longer runs",
+                    timeout.as_secs(),
+                    path.display(),
+                    holder,
+                    lock_path.display()
+                ));
+            }
+            thread::sleep(FILE_LOCK_RETRY_INTERVAL);
+        }
+    }
+
+    pub fn get_lock_path(&self) -> &Path {
+        &self.lock_path
+    }
+}
+
+impl Drop for FileLock {
+    fn drop(&mut self) {
+        // Locks of Unix and Windows are released when the file is closed.
+        // Elsewhere, the lock is the existence of the file.
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = std::fs::remove_file(&self.lock_path);
+        }
+    }
+}
+
+fn get_lock_path(path: &Path) -> PathBuf {
+    let mut lock_path = path.as_os_str().to_owned();
+    lock_path.push(".lock");
+    PathBuf::from(lock_path)
+}
+
+/// Take the lock if no other process holds it
+#[cfg(unix)]
+fn try_lock(lock_path: &Path) -> Result<Option<File>, Error> {
+    use std::os::unix::io::AsRawFd;
+
+    let file: File = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(lock_path)?;
+    if unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) } == 0 {
+        return Ok(Some(file));
+    }
+
+    Ok(None)
+}
+
+/// Take the lock if no other process holds it. The file is opened
+/// without sharing, which Windows refuses to other processes until it is
+/// closed, including when the holder crashes.
+#[cfg(windows)]
+fn try_lock(lock_path: &Path) -> Result<Option<File>, Error> {
+    use std::os::windows::fs::OpenOptionsExt;
+
+    /// `ERROR_SHARING_VIOLATION`, as another process has the file open
+    const SHARING_VIOLATION: i32 = 32;
+
+    match std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .share_mode(0)
+        .open(lock_path)
+    {
+        Ok(file) => Ok(Some(file)),
+        Err(error) if error.raw_os_error() == Some(SHARING_VIOLATION) => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Take the lock if no other process holds it. Without locks from the
+/// system, the lock is the existence of the file.
+#[cfg(not(any(unix, windows)))]
+fn try_lock(lock_path: &Path) -> Result<Option<File>, Error> {
+    match std::fs::OpenOptions::new().create_new(true).read(true).write(true).open(lock_path) {
+        Ok(file) => Ok(Some(file)),
+        Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => Ok(None),
+        Err(error) => Err(error.into()),
+    }
+}
+
+/// Take the lock of a file of the user, e.g. a chain file, with its lock
+/// file kept in the data directory
+pub fn lock_user_file(path: &Path) -> Result<FileLock, Error> {
+    let storage: Storage = Storage::from_environment()?;
+
+    FileLock::acquire_in(path, &storage.get_data_directory().join(USER_FILE_LOCK_DIRECTORY_NAME))
+}
+
+/// Replace the content of a file that was read earlier, e.g. a chain
+/// file being formatted. The file is locked, and left alone when another
+/// process changed it since it was read.
+pub fn write_file_if_unchanged(path: &Path, original: &str, content: &[u8]) -> Result<(), Error> {
+    let _lock: FileLock = lock_user_file(path)?;
+    check_unchanged(path, original)?;
+
+    write_file_atomically(path, content)
+}
+
+/// Check that a file still has the content it was read with
+pub fn check_unchanged(path: &Path, original: &str) -> Result<(), Error> {
+    if std::fs::read_to_string(path)? != original {
+        return Err(anyhow!("{} was changed since it was read, so it is left as it is", path.display()));
+    }
+
+    Ok(())
+}
+
+/// Replace a file at once, so that it is never seen halfway. The file
+/// is written next to the target first, under the name of the process,
+/// so that processes writing at the same time do not mix their contents.
+/// A file that is replaced keeps its permissions.
+pub fn write_file_atomically(path: &Path, content: &[u8]) -> Result<(), Error> {
+    let permissions: Option<Permissions> = std::fs::metadata(path).ok().map(|metadata| metadata.permissions());
+
+    write_file_atomically_with_permissions(path, content, permissions)
+}
+
+/// Replace a file at once like `write_file_atomically`, with the
+/// permissions given, e.g. those of the file it is a backup of. `None`
+/// leaves the default permissions of new files.
+pub fn write_file_atomically_with_permissions(
+    path: &Path,
+    content: &[u8],
+    permissions: Option<Permissions>,
+) -> Result<(), Error> {
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+
+    let mut staging_path = path.as_os_str().to_owned();
+    staging_path.push(format!(".{}.saving", std::process::id()));
+    let staging_path = PathBuf::from(staging_path);
+    std::fs::write(&staging_path, content)?;
+    // The permissions are set before the file takes the place of the
+    // target, so that it is never readable by more users than before
+    if let Some(permissions) = permissions {
+        std::fs::set_permissions(&staging_path, permissions)?;
+    }
+    std::fs::rename(&staging_path, path)?;
+
+    Ok(())
+}
@@ -3,3 +3,12 @@ pub mod utility;
 pub mod packages;
 pub mod shell;
 pub mod errors;
+pub mod interaction;
+pub mod storage;
+pub mod digest;
+pub mod executable;
+pub mod termination;
+pub mod lock;
+pub mod discovery;
+pub mod features;
+pub mod jsonl;
@@ -1,9 +1,14 @@
-use std::{collections::HashSet, env::consts::OS};
+use std::collections::HashSet;
+#[cfg(feature = "package-check")]
+use std::env::consts::OS;
 use std::path::PathBuf;
-use anyhow::{anyhow, Error, Result};
+#[cfg(feature = "package-check")]
+use anyhow::anyhow;
+use anyhow::{Error, Result};
+#[cfg(feature = "package-check")]
 use which::which;
 
-use super::shell::execute_system_native_script;
+use super::{executable::ExecutableLookup, shell::execute_system_native_script};
 
 /// Represents a package
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
@@ -22,13 +27,21 @@ impl Package {
     }
     
     pub fn get_available_packages() -> Result<HashSet<Package>, Error> {
-        let output: String = if cfg!(target_os = "windows") {
-            // Windows system: use 'where' command to list available commands
-            execute_system_native_script("where /Q *")?
-        } else {
-            // Unix system: use 'compgen -c' to list available commands
-            execute_system_native_script("compgen -c")?
-        };
+        if cfg!(target_os = "windows") {
+            // Windows system: list the commands in PATH the way they are
+            // resolved when running, so that the check and the run agree
+            let path: std::ffi::OsString = std::env::var_os("PATH").unwrap_or_default();
+            return Ok(
+                ExecutableLookup::from_environment()
+                    .list_commands(&path)
+                    .into_iter()
+                    .map(|name| Package { name })
+                    .collect()
+            );
+        }
+
+        // Unix system: use 'compgen -c' to list available commands
+        let output: String = execute_system_native_script("compgen -c")?;
     
         Ok(
             output 
@@ -37,15 +50,70 @@ impl Package {
                 .collect()
         )
     }
+
+    /// Check whether the package is in the available packages, or
+    /// resolves to a command the way it does when running
+    pub fn is_available(&self, available_packages: &HashSet<Package>) -> bool {
+        if available_packages.contains(self) || PathBuf::from(&self.name).exists() {
+            return true;
+        }
+
+        let lookup: ExecutableLookup = ExecutableLookup::from_environment();
+        // Windows compares command names regardless of case
+        if lookup != ExecutableLookup::Unix
+            && available_packages.iter().any(|package| package.name.eq_ignore_ascii_case(&self.name))
+        {
+            return true;
+        }
+        let path: std::ffi::OsString = std::env::var_os("PATH").unwrap_or_default();
+        let current_directory: PathBuf = std::env::current_dir().unwrap_or_default();
+
+        lookup.resolve(&self.name, &path, &current_directory).is_some()
+    }
+
+    /// Find the available package with the closest name, for suggesting
+    /// a command when the one in a chain is mistyped. Names that differ
+    /// in more than `MAX_SUGGESTION_DISTANCE` edits are not suggested.
+    pub fn find_similar_package<'a>(name: &str, available_packages: &'a HashSet<Package>) -> Option<&'a Package> {
+        available_packages
+            .iter()
+            .filter(|package| package.name != name)
+            .map(|package| (get_edit_distance(name, &package.name), package))
+            .filter(|(distance, _)| *distance <= MAX_SUGGESTION_DISTANCE)
+            // Ties are broken by the name, so that suggestions are stable
+            .min_by(|(a, a_package), (b, b_package)| a.cmp(b).then_with(|| a_package.name.cmp(&b_package.name)))
+            .map(|(_, package)| package)
+    }
+}
+
+/// Edits allowed between a mistyped command and a suggested one
+pub const MAX_SUGGESTION_DISTANCE: usize = 2;
+
+/// Get the Levenshtein distance between two names
+pub fn get_edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous_row: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_character) in a.chars().enumerate() {
+        let mut current_row: Vec<usize> = vec![i + 1];
+        for (j, b_character) in b.iter().enumerate() {
+            let substitution: usize = previous_row[j] + usize::from(a_character != *b_character);
+            current_row.push(substitution.min(previous_row[j + 1] + 1).min(current_row[j] + 1));
+        }
+        previous_row = current_row;
+    }
+
+    previous_row[b.len()]
 }
 
 /// Represents a package manager
+#[cfg(feature = "package-check")]
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct PackageManager {
     name: String,
     path: PathBuf,
 }
 
+#[cfg(feature = "package-check")]
 impl PackageManager {
     pub fn install_package(&self, package_name: &str) -> Result<(), Error> {
         let command = match self.name.as_str() {
@@ -126,6 +194,7 @@ impl PackageManager {
 }
 
 /// Impl this trait to verify if the package manager is available.
+#[cfg(feature = "package-check")]
 pub trait AvailablePackageManager {
     fn get_available_package_managers(&self) -> bool;
 }
@@ -142,7 +211,7 @@ pub trait AvailablePackages {
         
         Ok(
             required_packages.into_iter()
-                .filter(|pkg| !available_packages.contains(pkg) && !PathBuf::from(pkg.access_package_name()).exists())
+                .filter(|pkg| !pkg.is_available(&available_packages))
                 .collect()
         )
     }
@@ -74,5 +74,5 @@ pub fn execute_system_native_script(script: &str) -> Result<String, Error> {
             .output()?
     };
     
-    Ok(retrieve_script_output(output)?)
+    retrieve_script_output(output)
 }
@@ -0,0 +1,284 @@
+use std::{
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+
+use anyhow::{anyhow, Error, Result};
+
+/// Overrides the directory of everything cchain stores
+pub const CCHAIN_HOME_VARIABLE: &str = "CCHAIN_HOME";
+
+/// Name of the bookmark file in the data directory
+const BOOKMARK_FILE_NAME: &str = "bookmark.json";
+
+/// Kinds of files cchain keeps, each in its own directory
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum StorageCategory {
+    /// Clones of remote repositories, made by `cchain add <url>`
+    Repos,
+    Cache,
+    Backups,
+}
+
+impl StorageCategory {
+    pub const ALL: [StorageCategory; 3] = [
+        StorageCategory::Repos,
+        StorageCategory::Cache,
+        StorageCategory::Backups,
+    ];
+}
+
+impl std::fmt::Display for StorageCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StorageCategory::Repos => write!(f, "repos"),
+            StorageCategory::Cache => write!(f, "cache"),
+            StorageCategory::Backups => write!(f, "backups"),
+        }
+    }
+}
+
+/// How long `cchain gc` keeps what it prunes by age
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetentionRules {
+    /// Entries of the logs in the data directory, e.g. the audit log of
+    /// the bookmark and the run history
+    pub log_retention: Duration,
+    pub cache_ttl: Duration,
+}
+
+/// Disk usage of a category
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CategoryUsage {
+    pub category: StorageCategory,
+    pub entries: usize,
+    pub bytes: u64,
+}
+
+/// An entry that `cchain gc` removes
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PruneCandidate {
+    pub path: PathBuf,
+    pub category: StorageCategory,
+    pub bytes: u64,
+    pub reason: String,
+}
+
+/// Resolves where cchain stores its files, so that no feature
+/// hard-codes a path under the home directory
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Storage {
+    data_directory: PathBuf,
+    cache_directory: PathBuf,
+    /// Where the bookmark was saved before the storage directory
+    /// existed, if it should be migrated
+    legacy_bookmark_path: Option<PathBuf>,
+}
+
+impl Storage {
+    /// Decide the directories from the environment values.
+    ///
+    /// `CCHAIN_HOME` holds everything, and nothing is migrated into it.
+    /// Otherwise, data goes to `$XDG_DATA_HOME/cchain` and caches to
+    /// `$XDG_CACHE_HOME/cchain` when they are set, and to `~/.cchain/`
+    /// when they are not.
+    pub fn resolve(
+        cchain_home: Option<PathBuf>,
+        xdg_data_home: Option<PathBuf>,
+        xdg_cache_home: Option<PathBuf>,
+        home: PathBuf,
+    ) -> Self {
+        if let Some(cchain_home) = cchain_home {
+            return Self {
+                cache_directory: cchain_home.join("cache"),
+                data_directory: cchain_home,
+                legacy_bookmark_path: None,
+            };
+        }
+
+        let data_directory: PathBuf = match xdg_data_home {
+            Some(xdg_data_home) => xdg_data_home.join("cchain"),
+            None => home.join(".cchain"),
+        };
+        let cache_directory: PathBuf = match xdg_cache_home {
+            Some(xdg_cache_home) => xdg_cache_home.join("cchain"),
+            None => home.join(".cchain").join("cache"),
+        };
+
+        Self {
+            data_directory,
+            cache_directory,
+            legacy_bookmark_path: Some(home.join(".cchain")),
+        }
+    }
+
+    /// Decide the directories from the environment of the process
+    pub fn from_environment() -> Result<Self, Error> {
+        // Empty values are treated as unset, as the XDG specification says
+        let get_path = |name: &str| std::env::var_os(name).filter(|value| !value.is_empty()).map(PathBuf::from);
+        let home: PathBuf = dirs::home_dir().ok_or_else(|| anyhow!("Cannot find the home directory"))?;
+
+        Ok(Self::resolve(
+            get_path(CCHAIN_HOME_VARIABLE),
+            get_path("XDG_DATA_HOME"),
+            get_path("XDG_CACHE_HOME"),
+            home,
+        ))
+    }
+
+    pub fn get_data_directory(&self) -> &Path {
+        &self.data_directory
+    }
+
+    pub fn get_bookmark_path(&self) -> PathBuf {
+        self.data_directory.join(BOOKMARK_FILE_NAME)
+    }
+
+    /// Get the directory of a category. Caches are kept apart from
+    /// the data, so that they can live under `XDG_CACHE_HOME`.
+    pub fn get_directory(&self, category: StorageCategory) -> PathBuf {
+        match category {
+            StorageCategory::Cache => self.cache_directory.clone(),
+            _ => self.data_directory.join(category.to_string()),
+        }
+    }
+
+    /// Move a bookmark saved at the legacy location, `~/.cchain`, into
+    /// the data directory. The legacy file may be in the way of the data
+    /// directory itself, so it is moved aside first.
+    ///
+    /// # Returns
+    ///
+    /// Whether a legacy bookmark was migrated
+    pub fn migrate_legacy_bookmark(&self) -> Result<bool, Error> {
+        let legacy_bookmark_path: &PathBuf = match &self.legacy_bookmark_path {
+            Some(legacy_bookmark_path) if legacy_bookmark_path.is_file() => legacy_bookmark_path,
+            _ => return Ok(false),
+        };
+
+        let mut staging_path = legacy_bookmark_path.as_os_str().to_owned();
+        staging_path.push(".migrating");
+        let staging_path = PathBuf::from(staging_path);
+        move_file(legacy_bookmark_path, &staging_path)?;
+        std::fs::create_dir_all(&self.data_directory)?;
+
+        // A bookmark already at the new location is newer, keep the
+        // legacy one as a backup
+        let bookmark_path: PathBuf = self.get_bookmark_path();
+        if bookmark_path.exists() {
+            let backups_directory: PathBuf = self.get_directory(StorageCategory::Backups);
+            std::fs::create_dir_all(&backups_directory)?;
+            move_file(&staging_path, &backups_directory.join("bookmark.legacy-location.bak"))?;
+        } else {
+            move_file(&staging_path, &bookmark_path)?;
+        }
+
+        Ok(true)
+    }
+
+    /// Measure the disk usage of each category
+    pub fn get_disk_usage(&self) -> Vec<CategoryUsage> {
+        StorageCategory::ALL
+            .iter()
+            .map(|category| {
+                let entries: Vec<PathBuf> = list_entries(&self.get_directory(*category));
+                CategoryUsage {
+                    category: *category,
+                    entries: entries.len(),
+                    bytes: entries.iter().map(|entry| measure_size(entry)).sum(),
+                }
+            })
+            .collect()
+    }
+
+    /// Decide what `cchain gc` removes: caches older than the retention
+    /// rules, and repository clones that no bookmarked chain is in.
+    /// Backups are only reported.
+    pub fn plan_garbage_collection(
+        &self,
+        rules: &RetentionRules,
+        bookmarked_paths: &[PathBuf],
+        now: SystemTime,
+    ) -> Vec<PruneCandidate> {
+        let mut candidates: Vec<PruneCandidate> = Vec::new();
+        let mut add_candidate = |path: PathBuf, category: StorageCategory, reason: String| {
+            let bytes: u64 = measure_size(&path);
+            candidates.push(PruneCandidate { path, category, bytes, reason });
+        };
+
+        for entry in list_entries(&self.get_directory(StorageCategory::Cache)) {
+            let age: Duration = get_modified_time(&entry)
+                .and_then(|modified| now.duration_since(modified).ok())
+                .unwrap_or_default();
+            if age > rules.cache_ttl {
+                add_candidate(
+                    entry,
+                    StorageCategory::Cache,
+                    format!("older than {} days", rules.cache_ttl.as_secs() / 86400),
+                );
+            }
+        }
+
+        for entry in list_entries(&self.get_directory(StorageCategory::Repos)) {
+            let canonical_entry: PathBuf = entry.canonicalize().unwrap_or_else(|_| entry.clone());
+            let is_used: bool = bookmarked_paths.iter().any(|path| {
+                path.canonicalize().unwrap_or_else(|_| path.clone()).starts_with(&canonical_entry)
+            });
+            if !is_used {
+                add_candidate(entry, StorageCategory::Repos, "no bookmarked chain is in the clone".to_string());
+            }
+        }
+
+        candidates
+    }
+}
+
+/// Rename a file, or copy and remove it across file systems
+fn move_file(from: &Path, to: &Path) -> Result<(), Error> {
+    if std::fs::rename(from, to).is_err() {
+        std::fs::copy(from, to)?;
+        std::fs::remove_file(from)?;
+    }
+
+    Ok(())
+}
+
+/// List the entries directly in a directory, which may not exist
+fn list_entries(directory: &Path) -> Vec<PathBuf> {
+    let mut entries: Vec<PathBuf> = match std::fs::read_dir(directory) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok()).map(|entry| entry.path()).collect(),
+        Err(_) => Vec::new(),
+    };
+    entries.sort();
+
+    entries
+}
+
+fn get_modified_time(path: &Path) -> Option<SystemTime> {
+    std::fs::symlink_metadata(path).and_then(|metadata| metadata.modified()).ok()
+}
+
+/// Measure a file, or a directory with everything in it. Symbolic
+/// links are not followed.
+pub fn measure_size(path: &Path) -> u64 {
+    let metadata = match std::fs::symlink_metadata(path) {
+        Ok(metadata) => metadata,
+        Err(_) => return 0,
+    };
+    if !metadata.is_dir() {
+        return metadata.len();
+    }
+
+    list_entries(path).iter().map(|entry| measure_size(entry)).sum()
+}
+
+/// Remove a file, or a directory with everything in it
+pub fn remove_entry(path: &Path) -> Result<(), Error> {
+    if std::fs::symlink_metadata(path)?.is_dir() {
+        std::fs::remove_dir_all(path)?;
+    } else {
+        std::fs::remove_file(path)?;
+    }
+
+    Ok(())
+}
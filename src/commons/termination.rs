@@ -0,0 +1,109 @@
+use std::process::ExitStatus;
+
+use serde::{Deserialize, Serialize};
+
+/// How a failed process ended when it did not exit by itself, e.g. killed
+/// by a signal on Unix, or crashed with an NTSTATUS code on Windows
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProcessTermination {
+    /// Name of the signal, e.g. `SIGKILL`, or of the status code, e.g.
+    /// `STATUS_ACCESS_VIOLATION`
+    pub name: String,
+    /// Number of the signal, or the status code
+    pub code: u32,
+    /// The likely cause, e.g. `possibly out of memory`
+    pub explanation: String,
+    /// Whether the process crashed, e.g. with a segmentation fault. Crashes
+    /// are not retried unless `retry_crashes` is set.
+    pub is_crash: bool,
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub is_core_dumped: bool,
+}
+
+impl ProcessTermination {
+    /// Find out how a failed process ended. `None` for processes that
+    /// exited by themselves with a plain exit code.
+    pub fn from_exit_status(status: &ExitStatus) -> Option<Self> {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+
+            status
+                .signal()
+                .map(|signal| Self::from_signal(signal as u32, status.core_dumped()))
+        }
+
+        #[cfg(windows)]
+        {
+            status.code().and_then(|code| Self::from_windows_status(code as u32))
+        }
+
+        #[cfg(not(any(unix, windows)))]
+        {
+            let _ = status;
+            None
+        }
+    }
+
+    /// Describe a termination by a Unix signal. The numbers are those of
+    /// Linux, which macOS shares except for `SIGBUS`.
+    pub fn from_signal(signal: u32, is_core_dumped: bool) -> Self {
+        let (name, explanation, is_crash): (String, &str, bool) = match signal {
+            1 => ("SIGHUP".to_string(), "the terminal was closed", false),
+            2 => ("SIGINT".to_string(), "interrupted", false),
+            3 => ("SIGQUIT".to_string(), "quit", false),
+            4 => ("SIGILL".to_string(), "crashed on an illegal instruction", true),
+            6 => ("SIGABRT".to_string(), "aborted, e.g. by a failed assertion", true),
+            7 if cfg!(target_os = "linux") => ("SIGBUS".to_string(), "crashed on a bus error", true),
+            10 if cfg!(target_os = "macos") => ("SIGBUS".to_string(), "crashed on a bus error", true),
+            8 => ("SIGFPE".to_string(), "crashed on an arithmetic error", true),
+            9 => ("SIGKILL".to_string(), "possibly out of memory, or killed by another process", false),
+            11 => ("SIGSEGV".to_string(), "crashed on a segmentation fault", true),
+            13 => ("SIGPIPE".to_string(), "the reader of its output went away", false),
+            14 => ("SIGALRM".to_string(), "a timer expired", false),
+            15 => ("SIGTERM".to_string(), "terminated by another process", false),
+            _ => (format!("signal {}", signal), "killed by a signal", false),
+        };
+
+        Self { name, code: signal, explanation: explanation.to_string(), is_crash, is_core_dumped }
+    }
+
+    /// Describe a termination by a Windows NTSTATUS code that the
+    /// process exited with
+    ///
+    /// # Returns
+    ///
+    /// The description, or `None` for an exit code that is not a known
+    /// crash status
+    pub fn from_windows_status(code: u32) -> Option<Self> {
+        let (name, explanation, is_crash): (&str, &str, bool) = match code {
+            0xC0000005 => ("STATUS_ACCESS_VIOLATION", "crashed on an access violation", true),
+            0xC000001D => ("STATUS_ILLEGAL_INSTRUCTION", "crashed on an illegal instruction", true),
+            0xC0000094 => ("STATUS_INTEGER_DIVIDE_BY_ZERO", "crashed on a division by zero", true),
+            0xC00000FD => ("STATUS_STACK_OVERFLOW", "crashed on a stack overflow", true),
+            0xC0000409 => ("STATUS_STACK_BUFFER_OVERRUN", "aborted, e.g. by a failed check", true),
+            0xC0000017 => ("STATUS_NO_MEMORY", "out of memory", false),
+            0xC000013A => ("STATUS_CONTROL_C_EXIT", "interrupted", false),
+            _ => return None,
+        };
+
+        Some(Self {
+            name: name.to_string(),
+            code,
+            explanation: explanation.to_string(),
+            is_crash,
+            is_core_dumped: false,
+        })
+    }
+}
+
+impl std::fmt::Display for ProcessTermination {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Ended by {}: {}", self.name, self.explanation)?;
+        if self.is_core_dumped {
+            write!(f, " (core dumped)")?;
+        }
+
+        Ok(())
+    }
+}
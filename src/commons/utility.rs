@@ -1,33 +1,65 @@
+use std::collections::{HashMap, VecDeque};
+#[cfg(feature = "package-check")]
 use std::collections::HashSet;
 use std::fs::{canonicalize, DirEntry};
-use std::io::Write;
+use std::io::{IsTerminal, Write};
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
 use anyhow::anyhow;
 use anyhow::{Error, Result};
+#[cfg(feature = "git")]
 use git2::build::RepoBuilder;
-use git2::{FetchOptions, ProxyOptions};
+use serde::Serialize;
 
+use crate::arguments::{mask_secret_run_arguments, merge_stored_run_arguments, Commands, ExecArguments, RunArguments};
+#[cfg(feature = "git")]
+use crate::core::git::{clone_sparse, get_fetch_options, get_tree_paths, SparseClone};
+use crate::core::limits::read_chain_stream;
+use crate::core::manifest::ChainManifest;
+use crate::core::options::{FailureHandlingOptions, FailurePolicy, StdoutStorageOptions};
+use crate::core::program::Program;
+use crate::display_control::display_banner;
+use crate::display_control::display_form;
 use crate::display_control::display_message;
 use crate::display_control::display_tree_message;
 use crate::display_control::Level;
-use crate::core::chain::Chain;
-use crate::marker::bookmark::Bookmark;
-use crate::marker::reference::TrackPath;
+use crate::core::chain::{Chain, ChainExecutionResult, INLINE_CHAIN_NAME};
+use crate::core::metadata::format_timestamp;
+use crate::marker::bookmark::{Bookmark, IndexRemapping};
+use crate::marker::environment_fingerprint::EnvironmentFingerprint;
+use crate::marker::run_history::{check_chain_environment, record_chain_failures};
+use crate::marker::usage::record_chain_run;
+use crate::generations::create::ChainCreation;
+use crate::core::chain_retry::{wait_for_chain_attempt, ChainAttempt, ChainFailureClass, ChainRetryOptions};
+use crate::core::status::{set_chain_attempts, set_cancelled_programs, set_failed_verifications, set_failure_policy, set_pinned_environment, set_program_results, write_status_file, RunOutcome};
+use crate::core::shutdown::is_shutting_down;
+use crate::core::traits::Execution;
+use crate::variable::ProvidedValue;
+use crate::marker::reference::{follows_naming_convention, TrackPath, CHAIN_FILE_NAME_PATTERN};
 
-use super::errors::PackageError;
+use super::digest::sha256_hex;
+use super::discovery::{discover_chain, DirectorySummary, DiscoveryOutcome, DiscoverySummary};
+use super::errors::{ChainError, PackageError};
+#[cfg(not(all(feature = "git", feature = "package-check")))]
+use super::features::Feature;
+use super::interaction::{SharedInteractor, UnattendedInteractor};
 use super::naming::HumanReadable;
-use super::packages::{AvailablePackages, Package};
+use super::packages::AvailablePackages;
+use super::storage::{Storage, StorageCategory};
+#[cfg(feature = "package-check")]
+use super::packages::Package;
 
 pub fn get_paths(path: &std::path::Path) -> Result<Vec<DirEntry>, Error> {
     let mut paths: Vec<DirEntry> = Vec::new();
     let entries = std::fs::read_dir(path)?;
     for entry in entries {
         let entry = entry?;
-        if entry.path().is_file()
-            && entry.path().extension().map_or(false, |ext| ext == "json")
-            && entry.file_name().to_string_lossy().starts_with("cchain_")
-        {
+        // The naming convention gates directory scanning
+        if entry.path().is_file() && follows_naming_convention(&entry.path()) {
             paths.push(entry);
         }
     }
@@ -41,61 +73,287 @@ pub fn input_message(prompt: &str) -> Result<String, Error> {
     let mut input = String::new();
     // receive stdin
     std::io::stdout().flush()?;
-    std::io::stdin().read_line(&mut input)?;
+    if std::io::stdin().read_line(&mut input)? == 0 {
+        return Err(ChainError::InputRequired { prompt: prompt.to_string() }.into());
+    }
 
     Ok(input)
 }
 
-/// Resolve a path into a chain
-pub fn read_into_chain(input_string: &str, bookmark: &Bookmark) -> Result<Chain, Error> {
+/// A path checked while resolving a chain argument
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PathCheck {
+    pub path: String,
+    /// Whether the path is accepted as a chain file
+    pub accepted: bool,
+    /// Why the path is accepted or rejected
+    pub reason: String,
+}
+
+/// A bookmarked chain matched by the keywords of a chain argument
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct KeywordMatch {
+    /// Index of the chain in the bookmark
+    pub index: usize,
+    pub name: String,
+    pub path: String,
+    /// Number of keywords found in the name of the chain
+    pub score: usize,
+}
+
+/// Which chain a chain argument resolves to
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum ChainSelection {
+    /// The chain at the path is selected
+    Selected { path: String },
+    /// Multiple chains match, so the user is asked to select one of the paths
+    Ambiguous { candidates: Vec<String> },
+    NotFound { reason: String },
+}
+
+/// The steps taken to resolve a chain argument, as shown by `cchain which`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ChainResolutionTrace {
+    pub input: String,
+    /// The stable ID of the bookmarked chain, when the input is one
+    pub id: Option<String>,
+    /// The bookmark index, when the input is parsed as a positional index
+    pub index: Option<usize>,
+    pub checked_paths: Vec<PathCheck>,
+    pub keyword_matches: Vec<KeywordMatch>,
+    pub selection: ChainSelection,
+}
+
+/// Check whether a path is a chain file
+fn check_chain_path(input_string: &str) -> PathCheck {
     let path = Path::new(input_string);
-    
+    // Explicit paths are loaded regardless of the naming convention
+    let (accepted, reason): (bool, &str) = if !path.exists() {
+        (false, "does not exist")
+    } else if !path.is_file() {
+        (false, "is not a file")
+    } else if path.extension().is_none_or(|extension| extension != "json") {
+        (false, "is not a `.json` file")
+    } else if !follows_naming_convention(path) {
+        (true, "is a `.json` file, loaded although it is not named `cchain_*.json`")
+    } else {
+        (true, "is a chain file")
+    };
+
+    PathCheck {
+        path: input_string.to_string(),
+        accepted,
+        reason: reason.to_string(),
+    }
+}
+
+/// Resolve the chain argument of `cchain run` into a chain path, and
+/// record the steps taken. The input is tried as a stable ID, then as a
+/// positional bookmark index, then as a path to a chain file, and then as
+/// keywords of bookmarked chains.
+pub fn resolve_chain_argument(input_string: &str, bookmark: &Bookmark) -> ChainResolutionTrace {
+    let mut trace = ChainResolutionTrace {
+        input: input_string.to_string(),
+        id: None,
+        index: None,
+        checked_paths: Vec::new(),
+        keyword_matches: Vec::new(),
+        selection: ChainSelection::NotFound { reason: "No chains found".to_string() },
+    };
+
+    // IDs take precedence, so that an ID never changes its meaning
+    if let Some(index) = bookmark.get_index_by_id(input_string) {
+        trace.id = Some(input_string.to_string());
+        trace.selection = ChainSelection::Selected {
+            path: bookmark.get_chain_references()[index].get_chain_path_string(),
+        };
+        return trace;
+    }
+    trace.index = input_string.parse::<usize>().ok();
+
+    // If the input is parsable into an usize, it will use it as an
+    // index to the bookmark
+    if let Some(index) = trace.index {
+        trace.selection = match bookmark.get_chain_reference_by_index(index) {
+            Some(chain_reference) => ChainSelection::Selected { path: chain_reference.get_chain_path_string() },
+            None => ChainSelection::NotFound {
+                reason: format!("Cannot get the chain with the specified index: {}", index),
+            },
+        };
+        return trace;
+    }
+
     // Determine if the input is a valid chain file
-    if path.exists() && path.is_file() {
-        if let Some(extension) = path.extension() {
-            if extension == "json" {
-                if let Some(file_name) = path.file_name() {
-                    if file_name.to_string_lossy().starts_with("cchain_") {
-                        // Load and parse the configuration file
-                        return Ok(Chain::from_file(input_string)?);
-                    }
-                }
+    let path_check: PathCheck = check_chain_path(input_string);
+    let is_chain_file: bool = path_check.accepted;
+    trace.checked_paths.push(path_check);
+    if is_chain_file {
+        trace.selection = ChainSelection::Selected { path: input_string.to_string() };
+        return trace;
+    }
+
+    // If the input is keywords
+    let keywords: Vec<String> = input_string.split_whitespace().map(String::from).collect();
+    for (index, score) in bookmark.score_chains_by_keywords(keywords) {
+        let chain_reference = &bookmark.get_chain_references()[index];
+        trace.keyword_matches.push(KeywordMatch {
+            index,
+            name: chain_reference.get_human_readable_name().trim().to_string(),
+            path: chain_reference.get_chain_path_string(),
+            score,
+        });
+    }
+
+    match trace.keyword_matches.len() {
+        0 => {}
+        1 => trace.selection = ChainSelection::Selected { path: trace.keyword_matches[0].path.clone() },
+        _ => {
+            trace.selection = ChainSelection::Ambiguous {
+                candidates: trace.keyword_matches.iter().map(|keyword_match| keyword_match.path.clone()).collect(),
             }
         }
     }
-    
-    // If the input is keywords
-    let result = bookmark.get_chains_by_keywords(
-        input_string
-            .split_whitespace()
-            .map(String::from)
-            .collect::<Vec<String>>()
+
+    trace
+}
+
+/// Suggest the stable ID of the chain when a positional index is used
+/// without a terminal, e.g. in scripts, as indices shift after
+/// `cchain remove` and `cchain clean`
+fn suggest_stable_id(index: usize, bookmark: &Bookmark) {
+    if std::io::stdin().is_terminal() {
+        return;
+    }
+
+    if let Some(chain_reference) = bookmark.get_chain_reference_by_index(index) {
+        display_message(
+            Level::Warn,
+            &format!(
+                "Index {} may point at another chain after the bookmark changes. Use the stable ID `{}` in scripts instead.",
+                index,
+                chain_reference.get_id()
+            ),
+        );
+    }
+}
+
+/// Tell which positional indices point at other chains after chains are
+/// removed from the bookmark
+pub fn display_index_remapping(index_remapping: &IndexRemapping, is_dry_run: bool) {
+    if index_remapping.is_unchanged() {
+        return;
+    }
+
+    display_message(
+        Level::Logging,
+        &format!(
+            "Indices {}: {}. Stable IDs stay the same.",
+            if is_dry_run { "would change" } else { "changed" },
+            index_remapping
+        ),
     );
-    
-    if let Some(chain_references) = result {
-        // Throw an error if no chains are found
-        if chain_references.len() == 0 {
-            return Err(anyhow!("No chains found"));
-        }
-        
-        // Run the chain if it is exactly one
-        if chain_references.len() == 1 {
-            return Ok(Chain::from_file(&chain_references[0].get_chain_path_string())?);
-        }
-        
-        // Provide selections if multiple chains are found
-        display_message(Level::Logging, "Multiple chains found:");
-        for (index, chain_reference) in chain_references.iter().enumerate() {
-            display_tree_message(1, &format!("{}: {}", index + 1, chain_reference.get_human_readable_name()));
+}
+
+/// Find the bookmark index addressed by a stable ID or by a positional
+/// index. IDs take precedence over indices.
+pub fn find_bookmark_index(input_string: &str, bookmark: &Bookmark) -> Option<usize> {
+    if let Some(index) = bookmark.get_index_by_id(input_string) {
+        return Some(index);
+    }
+
+    let index: usize = input_string.parse::<usize>().ok()?;
+    bookmark.get_chain_reference_by_index(index)?;
+    suggest_stable_id(index, bookmark);
+
+    Some(index)
+}
+
+/// Apply the arguments stored with the bookmarked chain that the chain
+/// argument of `cchain run` resolves to, e.g. by `cchain config 2
+/// --set-run-args`, and tell which of them are applied. Keywords that
+/// match several chains apply none. `command_line` is the command line
+/// that `run_arguments` were parsed from, starting with the binary name.
+pub fn apply_stored_run_arguments(
+    command_line: &[String],
+    run_arguments: RunArguments,
+    bookmark: &Bookmark,
+) -> Result<RunArguments, Error> {
+    let Some(chain_input) = &run_arguments.chain else {
+        return Ok(run_arguments);
+    };
+    let ChainSelection::Selected { path } = resolve_chain_argument(chain_input, bookmark).selection else {
+        return Ok(run_arguments);
+    };
+    let Some(chain_reference) = bookmark.get_chain_reference_by_path(&path) else {
+        return Ok(run_arguments);
+    };
+    let Some(stored) = chain_reference.get_run_arguments() else {
+        return Ok(run_arguments);
+    };
+
+    let (arguments, applied) = merge_stored_run_arguments(command_line, stored)?;
+    if !applied.is_empty() {
+        display_message(
+            Level::Logging,
+            &format!(
+                "Applying the stored run arguments of {}: {}",
+                chain_reference.get_human_readable_name().trim(),
+                mask_secret_run_arguments(&applied).join(" ")
+            ),
+        );
+    }
+
+    match arguments.commands {
+        Commands::Run(run_arguments) => Ok(run_arguments),
+        _ => Err(anyhow!("Stored run arguments only apply to `cchain run`")),
+    }
+}
+
+/// Resolve an ID, an index, a path or keywords into a chain
+pub fn read_into_chain(input_string: &str, bookmark: &Bookmark) -> Result<Chain, Error> {
+    let trace: ChainResolutionTrace = resolve_chain_argument(input_string, bookmark);
+    if let Some(index) = trace.index {
+        suggest_stable_id(index, bookmark);
+    }
+
+    match trace.selection {
+        ChainSelection::Selected { path } => Chain::from_file(&path),
+        ChainSelection::NotFound { reason } => Err(anyhow!(reason)),
+        ChainSelection::Ambiguous { .. } => {
+            // Provide selections if multiple chains are found
+            display_message(Level::Logging, "Multiple chains found:");
+            for (index, keyword_match) in trace.keyword_matches.iter().enumerate() {
+                display_tree_message(1, &format!("{}: {}", index + 1, keyword_match.name));
+            }
+            let selection: usize = input_message("Please select a chain to execute:")?.trim().parse::<usize>()?;
+            let keyword_match: &KeywordMatch = trace
+                .keyword_matches
+                .get(selection.wrapping_sub(1))
+                .ok_or_else(|| anyhow!("Invalid selection: {}", selection))?;
+
+            Chain::from_file(&keyword_match.path)
         }
-        let selection: usize = input_message("Please select a chain to execute:")?.trim().parse::<usize>()?;
-        
-        return Ok(Chain::from_file(&chain_references[selection - 1].get_chain_path_string())?);
     }
-    
-    Err(anyhow!("No chains found"))
 }
 
+/// Read the chain of `cchain run --inline`: the JSON of the chain, or
+/// `@path` to read it from a file, or `-` to read it from stdin
+pub fn read_inline_chain(source: &str) -> Result<Chain, Error> {
+    if source == "-" {
+        let json: String = read_chain_stream(std::io::stdin(), INLINE_CHAIN_NAME)
+            .map_err(|error| ChainError::Validation(error.to_string()))?;
+        return Chain::from_json_str(&json, INLINE_CHAIN_NAME);
+    }
+    // A file is read like a chain given by its path
+    if let Some(path) = source.strip_prefix('@') {
+        return Chain::from_file(path);
+    }
+
+    Chain::from_json_str(source, INLINE_CHAIN_NAME)
+}
+
+#[cfg(feature = "package-check")]
 pub fn check_required_packages(chain: &(impl AvailablePackages + TrackPath)) -> Result<(), Error> {
     let required_packages: HashSet<Package> = chain.get_missing_packages()?;
     
@@ -112,120 +370,187 @@ pub fn check_required_packages(chain: &(impl AvailablePackages + TrackPath)) ->
     Ok(())
 }
 
+/// Without package checking, missing commands are only found when they
+/// run. That is noticed once.
+#[cfg(not(feature = "package-check"))]
+pub fn check_required_packages(_chain: &(impl AvailablePackages + TrackPath)) -> Result<(), Error> {
+    static PACKAGE_CHECK_NOTICE: std::sync::Once = std::sync::Once::new();
+    PACKAGE_CHECK_NOTICE.call_once(|| {
+        display_message(
+            Level::Logging,
+            &format!(
+                "cchain is built without {}, so missing commands are only found when they run",
+                Feature::PackageCheck
+            ),
+        )
+    });
+
+    Ok(())
+}
+
+#[cfg(unix)]
+fn create_symlink(original: &Path, link: &Path) -> Result<(), Error> {
+    Ok(std::os::unix::fs::symlink(original, link)?)
+}
+
+#[cfg(not(unix))]
+fn create_symlink(_original: &Path, _link: &Path) -> Result<(), Error> {
+    Err(anyhow!("Symlinks are only created on Unix, please choose to copy instead"))
+}
+
+/// Offer to bookmark a chain whose file name does not follow the naming
+/// convention through a conforming symlink or copy, or as it is.
+///
+/// # Returns
+///
+/// The path to bookmark, and whether it is bookmarked regardless of its name
+pub fn offer_conforming_name(path: &Path, interactor: &SharedInteractor) -> Result<(String, bool), Error> {
+    let file_name: String = path.file_name().unwrap_or_default().to_string_lossy().into_owned();
+    let conforming_name: String = format!("cchain_{}", file_name.trim_end_matches(".json")) + ".json";
+    let conforming_path: PathBuf = path.with_file_name(&conforming_name);
+    let message: String = format!(
+        "Chain is named `{}`, but chains are expected to be named `{}`",
+        file_name, CHAIN_FILE_NAME_PATTERN
+    );
+
+    if !interactor.is_interactive() {
+        return Err(anyhow!("{}. Rename it to `{}`, or use `--allow-any-name` to bookmark it anyway", message, conforming_name));
+    }
+
+    display_message(Level::Warn, &format!("{}.", message));
+    loop {
+        let answer: String = interactor.prompt(&format!(
+            "Create a [l]ink or a [c]opy named `{}`, [s]tore it anyway, or [a]bort?",
+            conforming_name
+        ))?;
+        match answer.to_lowercase().as_str() {
+            "l" | "link" => {
+                create_symlink(path, &conforming_path)?;
+                return Ok((conforming_path.to_string_lossy().into_owned(), false));
+            }
+            "c" | "copy" => {
+                std::fs::copy(path, &conforming_path)?;
+                return Ok((conforming_path.to_string_lossy().into_owned(), false));
+            }
+            "s" | "store" => return Ok((path.to_string_lossy().into_owned(), true)),
+            "a" | "abort" => return Err(anyhow!("{}. Operation aborted.", message)),
+            _ => display_message(Level::Warn, "Please answer `l`, `c`, `s` or `a`."),
+        }
+    }
+}
+
 /// Handle the case in which the input string is a git repo.
 /// This returns a local path to the cloned git repo. 
-fn handle_remote_url(input_string: &str) -> Result<String, Error> {
-    let current_dir: PathBuf = std::env::current_dir()?
-        .canonicalize()?
-        .join(input_string.split("/").last().unwrap());
-    
-    let mut fetch_options = FetchOptions::new();
-    let mut proxy_options = ProxyOptions::new();
-    proxy_options.auto();
-    fetch_options.proxy_options(proxy_options);
-    
-    let repository = RepoBuilder::new()
-        .fetch_options(
-            fetch_options
-        )
-        .clone(input_string, &current_dir)?;
-    
-    return Ok(repository.workdir().unwrap().to_string_lossy().to_string());
+/// How `cchain add` adds chains to the bookmark
+#[derive(Debug, Clone, Default)]
+pub struct AddOptions {
+    /// Bookmark a chain file even if its name does not follow `cchain_*.json`
+    allow_any_name: bool,
+    /// Paths of a repository to check out, relative to its root
+    sparse_paths: Vec<String>,
+    /// Find the chains without adding them, nor keeping the clone
+    list_only: bool,
 }
 
-pub fn handle_adding_bookmarks_logics(bookmark: &mut Bookmark, input_string: &str) -> Result<(), Error> {
-    
-    let path: PathBuf = if input_string.contains("github") {
-        display_message(Level::Logging, "GitHub repository detected. Try adding bookmarks from there...");
-        let local_path = PathBuf::from(handle_remote_url(input_string)?);
-        display_message(Level::Logging, &format!("Repository cloned to: {}", local_path.display()));
-        
-        local_path
-    } else {
-        PathBuf::from(input_string)
-    };
-    
-    let path = path.as_path();
-    
+impl AddOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_allow_any_name(mut self, allow_any_name: bool) -> Self {
+        self.allow_any_name = allow_any_name;
+        self
+    }
+
+    pub fn with_sparse_paths(mut self, sparse_paths: Vec<String>) -> Self {
+        self.sparse_paths = sparse_paths;
+        self
+    }
+
+    pub fn with_list_only(mut self, list_only: bool) -> Self {
+        self.list_only = list_only;
+        self
+    }
+
+    pub fn is_list_only(&self) -> bool {
+        self.list_only
+    }
+}
+
+/// Whether the input of `cchain add` is the URL of a repository
+fn is_repository_url(input_string: &str) -> bool {
+    input_string.contains("github") || input_string.contains("://") || input_string.starts_with("git@")
+}
+
+/// Get where a repository is cloned to: a directory in the repositories
+/// of the storage, named after the repository and a digest of its URL,
+/// so that repositories of the same name from different owners are kept
+/// apart
+fn get_clone_path(url: &str) -> Result<PathBuf, Error> {
+    let name: &str = url.trim_end_matches('/').rsplit(['/', ':']).next().unwrap_or_default().trim_end_matches(".git");
+    let repos_directory: PathBuf = Storage::from_environment()?.get_directory(StorageCategory::Repos);
+    std::fs::create_dir_all(&repos_directory)?;
+
+    Ok(repos_directory.canonicalize()?.join(format!("{}-{}", name, &sha256_hex(url.as_bytes())[..8])))
+}
+
+/// Add the chains of a directory, a repository or a chain file to the
+/// bookmark. With `list_only`, the chains are only found.
+///
+/// # Returns
+///
+/// What became of each chain file found, unless a single chain file is
+/// added
+pub fn handle_adding_bookmarks_logics(
+    bookmark: &mut Bookmark,
+    input_string: &str,
+    options: &AddOptions,
+) -> Result<Option<DiscoverySummary>, Error> {
+    if is_repository_url(input_string) {
+        display_message(Level::Logging, "Repository URL detected. Try adding bookmarks from there...");
+        // Clones only listed from are not kept, the directory is removed
+        // when dropped
+        if options.list_only {
+            let clone_directory: tempfile::TempDir = tempfile::tempdir()?;
+            return add_chains_from_repository(bookmark, input_string, clone_directory.path(), options).map(Some);
+        }
+
+        return add_chains_from_repository(bookmark, input_string, &get_clone_path(input_string)?, options).map(Some);
+    }
+    if !options.sparse_paths.is_empty() {
+        return Err(anyhow!("`--sparse` only applies to the URL of a repository"));
+    }
+
+    let path: &Path = Path::new(input_string);
     if !path.exists() {
         return Err(anyhow!("Provided path does not exist! Operation aborted."));
     }
 
     if path.is_dir() {
-        let fullpath: std::path::PathBuf = canonicalize(&path)?;
-        let filepaths: Vec<DirEntry> = get_paths(Path::new(&fullpath))?;
-        display_message(
-            Level::Logging,
-            &format!("Registering {} chains to the bookmark", filepaths.len()),
-        );
-        for filepath in filepaths {
-            // Acquire the canonical path of the file
-            let path_string: String =filepath
-                .path()
-                .canonicalize()
-                .unwrap()
-                .to_string_lossy()
-                .to_string();
-            
-            match bookmark.add_chain_reference(
-                path_string
-            ) {
-                Ok(_) => {
-                    display_message(
-                        Level::Logging,
-                        &format!(
-                            "{} is registered successfully.",
-                            filepath.path().canonicalize().unwrap().to_str().unwrap()
-                        ),
-                    );
-                    continue;
-                }
-                Err(error) => {
-                    match error.downcast_ref::<PackageError>() {
-                        Some(
-                            PackageError::MissingPackages { 
-                                number_missed_packages: _, 
-                                missed_packages: _, 
-                                chain_name: _ 
-                            }
-                        ) => {
-                            display_message(
-                                Level::Warn,
-                                &error.to_string()
-                            );
-                        }
-                        _ => {
-                            display_message(
-                                Level::Warn,
-                                &format!("{}, skipped bookmarking.", error.to_string()),
-                            );
-                        }
-                    }
-                    continue;
-                }
-            };
-        }
-        
-        return Ok(());
+        let fullpath: PathBuf = canonicalize(path)?;
+        return add_chains_from_directory(bookmark, &fullpath, options).map(Some);
+    }
+
+    if options.list_only {
+        return Err(anyhow!("`--list-only` only applies to directories and repositories"));
     }
 
     if path.is_file() {
         display_message(Level::Logging, "Registering a chain to the bookmark");
 
-        let filepath: &Path = Path::new(&path);
-        let path_string: String = filepath
-            .canonicalize()
-            .unwrap()
+        let mut path_string: String = path
+            .canonicalize()?
             .to_string_lossy()
             .to_string();
+        let mut allow_any_name: bool = options.allow_any_name;
+        if !allow_any_name && !follows_naming_convention(path) {
+            (path_string, allow_any_name) = offer_conforming_name(Path::new(&path_string), &SharedInteractor::default())?;
+        }
         
-        match bookmark.add_chain_reference(path_string) {
+        match bookmark.add_chain_reference_with_options(path_string.clone(), allow_any_name) {
             Ok(_) => display_message(
                 Level::Logging,
-                &format!(
-                    "{} is registered successfully.",
-                    filepath.canonicalize().unwrap().to_str().unwrap()
-                ),
+                &format!("{} is registered successfully.", path_string),
             ),
             Err(error) => {
                 match error.downcast_ref::<PackageError>() {
@@ -244,15 +569,806 @@ pub fn handle_adding_bookmarks_logics(bookmark: &mut Bookmark, input_string: &st
                     _ => {
                         display_message(
                             Level::Warn,
-                            &format!("{}, skipped bookmarking.", error.to_string()),
+                            &format!("{}, skipped bookmarking.", error),
                         );
                     }
                 }
             }
         };
         
-        return Ok(());
+        return Ok(None);
     }
     
     Err(anyhow!("The specified path is not valid. Please check."))
-}
\ No newline at end of file
+}
+
+/// Clone a repository, in full or only the sparse paths, and add its
+/// chains. A sparse clone has all chains of the repository in its tree,
+/// so those outside the sparse paths are counted too.
+#[cfg(feature = "git")]
+fn add_chains_from_repository(
+    bookmark: &mut Bookmark,
+    url: &str,
+    clone_path: &Path,
+    options: &AddOptions,
+) -> Result<DiscoverySummary, Error> {
+    // Chains are found across the whole tree either way, those outside
+    // the sparse paths are only not checked out
+    let sparse_clone: SparseClone = if options.sparse_paths.is_empty() {
+        let repository = RepoBuilder::new().fetch_options(get_fetch_options()).clone(url, clone_path)?;
+        let workdir: PathBuf = repository.workdir().unwrap_or(clone_path).canonicalize()?;
+        display_message(Level::Logging, &format!("Repository cloned to: {}", workdir.display()));
+        let tree_paths: Vec<PathBuf> = match repository.head() {
+            Ok(head) => get_tree_paths(&head.peel_to_tree()?)?,
+            // An empty repository has no commit
+            Err(_) => Vec::new(),
+        };
+
+        SparseClone { workdir, tree_paths }
+    } else {
+        let sparse_clone: SparseClone = clone_sparse(url, clone_path, &options.sparse_paths)?;
+        display_message(
+            Level::Logging,
+            &format!(
+                "Repository cloned to: {}, with only {} checked out",
+                sparse_clone.workdir.display(),
+                options.sparse_paths.join(", ")
+            ),
+        );
+
+        sparse_clone
+    };
+
+    let mut discovery_summary: DiscoverySummary = DiscoverySummary::new();
+    for tree_path in &sparse_clone.tree_paths {
+        if !follows_naming_convention(tree_path) {
+            continue;
+        }
+        let path: PathBuf = sparse_clone.workdir.join(tree_path);
+        let outcome: DiscoveryOutcome = match path.is_file() {
+            true => discover_chain(bookmark, &path, options.list_only),
+            false => DiscoveryOutcome::OutsideSparsePaths,
+        };
+        display_discovery_outcome(&path, &outcome);
+        discovery_summary.push(tree_path.clone(), outcome);
+    }
+    discovery_summary.set_unmatched_sparse_paths(
+        options
+            .sparse_paths
+            .iter()
+            .filter(|sparse_path| {
+                let sparse_path: &Path = Path::new(sparse_path.trim_matches('/'));
+                !sparse_clone.tree_paths.iter().any(|tree_path| tree_path.starts_with(sparse_path))
+            })
+            .cloned()
+            .collect(),
+    );
+
+    Ok(discovery_summary)
+}
+
+/// Repositories are cloned through git2, which cchain is built without
+#[cfg(not(feature = "git"))]
+fn add_chains_from_repository(
+    _bookmark: &mut Bookmark,
+    _url: &str,
+    _clone_path: &Path,
+    _options: &AddOptions,
+) -> Result<DiscoverySummary, Error> {
+    Err(Feature::Git.get_unavailable_error("adding chains from a repository"))
+}
+
+/// Add the chains directly in a directory
+fn add_chains_from_directory(bookmark: &mut Bookmark, directory: &Path, options: &AddOptions) -> Result<DiscoverySummary, Error> {
+    let filepaths: Vec<DirEntry> = get_paths(directory)?;
+    if !options.list_only {
+        display_message(
+            Level::Logging,
+            &format!("Registering {} chains to the bookmark", filepaths.len()),
+        );
+    }
+
+    let mut discovery_summary: DiscoverySummary = DiscoverySummary::new();
+    for filepath in filepaths {
+        let path: PathBuf = filepath.path();
+        let outcome: DiscoveryOutcome = discover_chain(bookmark, &path, options.list_only);
+        display_discovery_outcome(&path, &outcome);
+        discovery_summary.push(PathBuf::from(filepath.file_name()), outcome);
+    }
+
+    Ok(discovery_summary)
+}
+
+fn display_discovery_outcome(path: &Path, outcome: &DiscoveryOutcome) {
+    match outcome {
+        DiscoveryOutcome::Added => {
+            display_message(Level::Logging, &format!("{} is registered successfully.", path.display()))
+        }
+        DiscoveryOutcome::Duplicate => {
+            display_message(Level::Warn, &format!("{} is already bookmarked, skipped bookmarking.", path.display()))
+        }
+        DiscoveryOutcome::MissingPackages(message) => display_message(Level::Warn, message),
+        DiscoveryOutcome::Invalid(message) => display_message(Level::Warn, &format!("{}, skipped bookmarking.", message)),
+        DiscoveryOutcome::Addable | DiscoveryOutcome::OutsideSparsePaths => {}
+    }
+}
+
+/// Show how many chain files were found in each directory, and what
+/// became of them
+pub fn display_discovery_summary(discovery_summary: &DiscoverySummary, list_only: bool) {
+    let mut directory_summaries: Vec<DirectorySummary> = discovery_summary.get_directory_summaries();
+    if directory_summaries.is_empty() {
+        display_message(Level::Warn, "No chain files are found.");
+    } else {
+        directory_summaries.push(discovery_summary.get_total());
+        let form_data: Vec<Vec<String>> = directory_summaries
+            .iter()
+            .map(|directory_summary| {
+                vec![
+                    directory_summary.directory.clone(),
+                    directory_summary.found.to_string(),
+                    directory_summary.added.to_string(),
+                    directory_summary.duplicates.to_string(),
+                    directory_summary.outside_sparse_paths.to_string(),
+                    directory_summary.missing_packages.to_string(),
+                    directory_summary.invalid.to_string(),
+                ]
+            })
+            .collect();
+        display_form(
+            vec![
+                "Directory",
+                "Found",
+                if list_only { "Would be added" } else { "Added" },
+                "Duplicates",
+                "Outside sparse paths",
+                "Missing packages",
+                "Invalid",
+            ],
+            &form_data,
+        );
+    }
+
+    for sparse_path in discovery_summary.get_unmatched_sparse_paths() {
+        display_message(Level::Warn, &format!("No file of the repository is under the sparse path `{}`", sparse_path));
+    }
+}
+
+
+/// Construct the program to run for `cchain exec`
+pub fn build_exec_program(arguments: &ExecArguments) -> Program {
+    let environment_variables_override: Option<HashMap<String, String>> =
+        if arguments.environment_variables.is_empty() {
+            None
+        } else {
+            Some(arguments.environment_variables.iter().cloned().collect())
+        };
+
+    Program::new(
+        arguments.command_line[0].clone(),
+        arguments.command_line[1..].to_vec(),
+        environment_variables_override,
+        arguments.workdir.clone(),
+        None,
+        StdoutStorageOptions::default(),
+        arguments.interpreter.clone(),
+        FailureHandlingOptions::default(),
+        None,
+        arguments.retry,
+    )
+}
+
+/// Save the program of `cchain exec` as a new chain named `cchain_<name>.json`
+/// in the working directory, and return the filename
+pub fn save_exec_program(program: &Program, name: &str, working_directory: &Path) -> Result<String, Error> {
+    let creation = ChainCreation::new(Some(name.to_string())).with_directory(working_directory.to_path_buf());
+    creation.save(serde_json::to_string_pretty(&[program])?)?;
+
+    Ok(creation.create_filename())
+}
+
+/// Collect the values of variables from `--var` and `--var-file`.
+/// Files are applied in order, then `--var` overrides them.
+pub fn collect_provided_values(
+    variables: &[(String, String)],
+    variable_files: &[String],
+) -> Result<HashMap<String, ProvidedValue>, Error> {
+    let mut provided_values: HashMap<String, ProvidedValue> = HashMap::new();
+    for variable_file in variable_files {
+        provided_values.extend(ProvidedValue::from_file(variable_file)?);
+    }
+    for (name, value) in variables {
+        provided_values.insert(name.clone(), ProvidedValue::from_assignment(name, value)?);
+    }
+
+    Ok(provided_values)
+}
+
+/// How a chain ended in a multi-chain run
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainRunOutcome {
+    Succeeded,
+    Failed(String),
+    /// The chain is not run because an earlier chain failed
+    Skipped,
+    /// The chain of a manifest is not run because a chain it depends on
+    /// did not succeed
+    Blocked(String),
+}
+
+impl std::fmt::Display for ChainRunOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChainRunOutcome::Succeeded => write!(f, "succeeded"),
+            ChainRunOutcome::Failed(reason) => write!(f, "failed: {}", reason),
+            ChainRunOutcome::Skipped => write!(f, "skipped"),
+            ChainRunOutcome::Blocked(dependency) => write!(f, "skipped: `{}` did not succeed", dependency),
+        }
+    }
+}
+
+/// The result of one chain in a multi-chain run
+#[derive(Debug, Clone)]
+pub struct ChainRunRecord {
+    pub name: String,
+    pub path: String,
+    pub outcome: ChainRunOutcome,
+    /// How long the chain ran, None when it did not run
+    pub duration: Option<Duration>,
+    /// Number of programs of the chain that failed
+    pub failed_programs: usize,
+}
+
+/// How the chains of a batch, e.g. `cchain run --tag`, are run
+#[derive(Debug, Clone, Default)]
+pub struct BatchOptions {
+    /// Continue with the remaining chains after a failure, instead of
+    /// skipping them
+    keep_going: bool,
+    /// Run up to this many chains at a time. Only chains that prompt
+    /// for nothing can run in parallel.
+    parallel: Option<usize>,
+    /// Asks for the inputs of the chains before the batch starts
+    interactor: SharedInteractor,
+    /// Run the programs that delete or mutate things without asking
+    allow_destructive: bool,
+    /// How the failures of programs are handled in each chain
+    failure_policy: FailurePolicy,
+}
+
+impl BatchOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_keep_going(mut self, keep_going: bool) -> Self {
+        self.keep_going = keep_going;
+        self
+    }
+
+    pub fn with_parallel(mut self, parallel: usize) -> Self {
+        self.parallel = Some(parallel.max(1));
+        self
+    }
+
+    pub fn with_interactor(mut self, interactor: SharedInteractor) -> Self {
+        self.interactor = interactor;
+        self
+    }
+
+    pub fn with_allow_destructive(mut self, allow_destructive: bool) -> Self {
+        self.allow_destructive = allow_destructive;
+        self
+    }
+
+    pub fn with_failure_policy(mut self, failure_policy: FailurePolicy) -> Self {
+        self.failure_policy = failure_policy;
+        self
+    }
+}
+
+/// A chain of a batch, loaded before any of them runs
+struct BatchEntry {
+    name: String,
+    path: String,
+    chain: Result<Chain, Error>,
+}
+
+/// Run every bookmarked chain with the tag, in alphabetical order of the
+/// names. Chains run one at a time, unless `parallel` is set, and each
+/// starts with a banner.
+///
+/// All the inputs of the chains are asked for before the first chain
+/// runs, one form per chain, so that no prompt shows up between the
+/// outputs of the chains. Chains that would prompt cannot run in parallel.
+///
+/// # Arguments
+///
+/// * `bookmark` - The bookmark to look up the chains from
+/// * `tag` - The tag of the chains to run
+/// * `options` - How the chains are run
+pub fn run_tagged_chains(
+    bookmark: &Bookmark,
+    tag: &str,
+    options: &BatchOptions,
+    provided_values: &HashMap<String, ProvidedValue>,
+) -> Result<Vec<ChainRunRecord>, Error> {
+    let chain_references = bookmark.get_chain_references_by_tag(tag);
+    if chain_references.is_empty() {
+        return Err(anyhow!("No chain in the bookmark is tagged with `{}`", tag));
+    }
+
+    let interactor: SharedInteractor = match options.parallel {
+        Some(_) => SharedInteractor::new(UnattendedInteractor),
+        None => options.interactor.clone(),
+    };
+    let mut entries: Vec<BatchEntry> = chain_references
+        .iter()
+        .map(|chain_reference| {
+            let path: String = chain_reference.get_chain_path_string();
+            let chain: Result<Chain, Error> = Chain::from_file(&path).map(|mut chain| {
+                chain.set_provided_values(provided_values.clone());
+                chain.set_interactor(interactor.clone());
+                chain.set_allow_destructive(options.allow_destructive);
+                chain.set_failure_policy(options.failure_policy);
+                chain
+            });
+            BatchEntry { name: chain_reference.get_human_readable_name(), path, chain }
+        })
+        .collect();
+
+    if options.parallel.is_some() {
+        let offending_chains: Vec<String> = entries
+            .iter()
+            .filter_map(|entry| {
+                let chain: &Chain = entry.chain.as_ref().ok()?;
+                let mut pending_inputs: Vec<String> =
+                    chain.get_pending_input_listing().iter().map(|input| input.to_string()).collect();
+                if !chain.is_allowing_destructive() && !chain.get_destructive_programs().is_empty() {
+                    pending_inputs.push("a confirmation of destructive programs".to_string());
+                }
+                (!pending_inputs.is_empty()).then(|| format!("{} ({})", entry.name.trim(), pending_inputs.join(", ")))
+            })
+            .collect();
+        if !offending_chains.is_empty() {
+            return Err(anyhow!(
+                "Chains that prompt for input cannot run in parallel: {}. Provide the values with `--var` and `--allow-destructive`, or run without `--parallel`",
+                offending_chains.join(", ")
+            ));
+        }
+    } else {
+        collect_batch_inputs(&mut entries);
+    }
+
+    Ok(match options.parallel {
+        Some(parallel) => run_batch_in_parallel(entries, options.keep_going, parallel),
+        None => {
+            let total: usize = entries.len();
+            let mut records: Vec<ChainRunRecord> = Vec::new();
+            let mut is_failed: bool = false;
+            for (index, entry) in entries.into_iter().enumerate() {
+                let record: ChainRunRecord = run_batch_entry(entry, index, total, is_failed && !options.keep_going);
+                is_failed |= matches!(record.outcome, ChainRunOutcome::Failed(_));
+                records.push(record);
+            }
+            records
+        }
+    })
+}
+
+/// Ask for the inputs of the chains of a batch, and the confirmations of
+/// their destructive programs, one form per chain. A chain whose inputs
+/// cannot be collected fails without running.
+fn collect_batch_inputs(entries: &mut [BatchEntry]) {
+    let total: usize = entries.len();
+    for (index, entry) in entries.iter_mut().enumerate() {
+        let chain: &mut Chain = match &mut entry.chain {
+            Ok(chain) => chain,
+            Err(_) => continue,
+        };
+        let is_confirming: bool = !chain.is_allowing_destructive() && !chain.get_destructive_programs().is_empty();
+        if chain.get_pending_inputs().is_empty() && !is_confirming {
+            continue;
+        }
+        display_message(
+            Level::Selection,
+            &format!("Inputs of chain {}/{}: {}", index + 1, total, entry.name.trim()),
+        );
+        if let Err(error) = chain.collect_pending_inputs().and_then(|_| chain.confirm_destructive_programs()) {
+            entry.chain = Err(error);
+        }
+    }
+}
+
+/// Run the chains of a manifest, e.g. `cchain_meta_release.json`. Each
+/// chain starts once the chains it depends on finish, with up to
+/// `parallel` of them at a time, from `--parallel` or else the manifest.
+///
+/// All the inputs of the chains are asked for before the first chain
+/// runs, as in other batches. A chain is skipped when a chain it depends
+/// on does not succeed, unless it is `always_run`. Failures of chains
+/// only skip their dependents, the other chains run to the end.
+///
+/// # Arguments
+///
+/// * `manifest_path` - The path to the manifest
+/// * `bookmark` - The bookmark to look up the chains referenced by IDs
+/// * `options` - How the chains are run. `keep_going` only applies to
+///   the programs of each chain.
+/// * `provided_values` - Values of `--var` and `--var-file`, which take
+///   precedence over the variables of the entries
+pub fn run_manifest(
+    manifest_path: &Path,
+    bookmark: &Bookmark,
+    options: &BatchOptions,
+    provided_values: &HashMap<String, ProvidedValue>,
+) -> Result<Vec<ChainRunRecord>, Error> {
+    let manifest: ChainManifest = ChainManifest::from_file(manifest_path)?;
+    let chain_paths: Vec<Result<String, Error>> = manifest.resolve_chain_paths(manifest_path, bookmark);
+    let mut missing_chains: Vec<String> = Vec::new();
+    for chain_path in &chain_paths {
+        if let Err(error) = chain_path {
+            missing_chains.push(error.to_string());
+        }
+    }
+    if !missing_chains.is_empty() {
+        return Err(anyhow!("{}", missing_chains.join("\n")));
+    }
+
+    let mut entries: Vec<BatchEntry> = chain_paths
+        .into_iter()
+        .flatten()
+        .enumerate()
+        .map(|(index, path)| {
+            let mut chain_provided_values: HashMap<String, ProvidedValue> = manifest.get_provided_values(index);
+            chain_provided_values.extend(provided_values.clone());
+            let chain: Result<Chain, Error> = Chain::from_file(&path).map(|mut chain| {
+                chain.set_provided_values(chain_provided_values);
+                chain.set_interactor(options.interactor.clone());
+                chain.set_allow_destructive(options.allow_destructive);
+                chain.set_failure_policy(options.failure_policy);
+                chain
+            });
+            BatchEntry { name: manifest.get_entries()[index].get_name().to_string(), path, chain }
+        })
+        .collect();
+    collect_batch_inputs(&mut entries);
+
+    // Nothing is left to ask for, and a prompt would show up between
+    // the outputs of the chains running at the same time
+    let parallel: usize = options.parallel.or(manifest.get_parallel()).unwrap_or(1);
+    if parallel > 1 {
+        for entry in &mut entries {
+            if let Ok(chain) = &mut entry.chain {
+                chain.set_interactor(SharedInteractor::new(UnattendedInteractor));
+            }
+        }
+    }
+
+    Ok(run_manifest_entries(&manifest, entries, parallel))
+}
+
+/// What the workers running the chains of a manifest share
+struct ManifestSchedule {
+    entries: Vec<Option<BatchEntry>>,
+    records: Vec<Option<ChainRunRecord>>,
+}
+
+/// Run the chains of a manifest with up to `parallel` workers, each
+/// taking the first chain whose dependencies have finished
+fn run_manifest_entries(manifest: &ChainManifest, entries: Vec<BatchEntry>, parallel: usize) -> Vec<ChainRunRecord> {
+    let total: usize = entries.len();
+    let schedule: Mutex<ManifestSchedule> = Mutex::new(ManifestSchedule {
+        entries: entries.into_iter().map(Some).collect(),
+        records: vec![None; total],
+    });
+    let is_finished: Condvar = Condvar::new();
+
+    thread::scope(|scope| {
+        for _ in 0..parallel.min(total) {
+            scope.spawn(|| {
+                let mut state = schedule.lock().unwrap();
+                loop {
+                    let ready: Option<usize> = (0..total).find(|&index| {
+                        state.entries[index].is_some()
+                            && manifest
+                                .get_dependency_indices(index)
+                                .iter()
+                                .all(|&dependency| state.records[dependency].is_some())
+                    });
+                    let Some(index) = ready else {
+                        if state.entries.iter().all(Option::is_none) {
+                            break;
+                        }
+                        state = is_finished.wait(state).unwrap();
+                        continue;
+                    };
+
+                    let entry: BatchEntry = state.entries[index].take().unwrap();
+                    let failed_dependency: Option<usize> = manifest
+                        .get_dependency_indices(index)
+                        .into_iter()
+                        .find(|&dependency| state.records[dependency].as_ref().is_some_and(|record| record.outcome != ChainRunOutcome::Succeeded));
+                    let record: ChainRunRecord = match failed_dependency {
+                        Some(dependency) if !manifest.get_entries()[index].always_run => ChainRunRecord {
+                            name: entry.name,
+                            path: entry.path,
+                            outcome: ChainRunOutcome::Blocked(manifest.get_entries()[dependency].get_name().to_string()),
+                            duration: None,
+                            failed_programs: 0,
+                        },
+                        _ => {
+                            drop(state);
+                            let mut record: ChainRunRecord = run_batch_entry(entry, index, total, false);
+                            // Programs that fail without stopping the chain
+                            // still fail the chain for its dependents
+                            if record.outcome == ChainRunOutcome::Succeeded && record.failed_programs > 0 {
+                                record.outcome =
+                                    ChainRunOutcome::Failed(format!("{} program(s) failed", record.failed_programs));
+                            }
+                            state = schedule.lock().unwrap();
+                            record
+                        }
+                    };
+                    state.records[index] = Some(record);
+                    is_finished.notify_all();
+                }
+            });
+        }
+    });
+
+    schedule.into_inner().unwrap().records.into_iter().flatten().collect()
+}
+
+/// Run the chains of a batch with up to `parallel` of them at a time,
+/// in the order of the batch
+fn run_batch_in_parallel(entries: Vec<BatchEntry>, keep_going: bool, parallel: usize) -> Vec<ChainRunRecord> {
+    let total: usize = entries.len();
+    let queue: Mutex<VecDeque<(usize, BatchEntry)>> = Mutex::new(entries.into_iter().enumerate().collect());
+    let records: Mutex<Vec<Option<ChainRunRecord>>> = Mutex::new(vec![None; total]);
+    let is_failed: AtomicBool = AtomicBool::new(false);
+
+    thread::scope(|scope| {
+        for _ in 0..parallel.min(total) {
+            scope.spawn(|| loop {
+                let (index, entry) = match queue.lock().unwrap().pop_front() {
+                    Some(next) => next,
+                    None => break,
+                };
+                let is_skipped: bool = is_failed.load(Ordering::SeqCst) && !keep_going;
+                let record: ChainRunRecord = run_batch_entry(entry, index, total, is_skipped);
+                if matches!(record.outcome, ChainRunOutcome::Failed(_)) {
+                    is_failed.store(true, Ordering::SeqCst);
+                }
+                records.lock().unwrap()[index] = Some(record);
+            });
+        }
+    });
+
+    records.into_inner().unwrap().into_iter().flatten().collect()
+}
+
+/// Run a chain of a batch after its banner, unless it is skipped
+fn run_batch_entry(entry: BatchEntry, index: usize, total: usize, is_skipped: bool) -> ChainRunRecord {
+    let BatchEntry { name, path, chain } = entry;
+    if is_skipped {
+        return ChainRunRecord { name, path, outcome: ChainRunOutcome::Skipped, duration: None, failed_programs: 0 };
+    }
+
+    record_chain_run(&path);
+    let started_at: u64 = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    display_banner(&format!(
+        "Chain {}/{}: {} ({}), started at {}",
+        index + 1,
+        total,
+        name.trim(),
+        path,
+        format_timestamp(started_at)
+    ));
+    let started: Instant = Instant::now();
+    let mut failed_programs: usize = 0;
+    let result: Result<(), Error> = chain.and_then(|mut chain| {
+        check_required_packages(&chain)?;
+        let result: Result<(), Error> = match chain.execute() {
+            Ok(_) => {
+                record_chain_failures(&chain, chain.get_failed_program_indices().is_empty(), None);
+                Ok(())
+            }
+            Err(error) => {
+                chain.show_statistics();
+                record_chain_failures(&chain, false, None);
+                Err(error)
+            }
+        };
+        failed_programs = chain.get_failed_program_indices().len();
+        result
+    });
+
+    let outcome: ChainRunOutcome = match result {
+        Ok(_) => ChainRunOutcome::Succeeded,
+        Err(error) => ChainRunOutcome::Failed(error.to_string()),
+    };
+
+    ChainRunRecord { name, path, outcome, duration: Some(started.elapsed()), failed_programs }
+}
+
+/// Run a chain after checking its required packages, and decide how
+/// the run ended.
+///
+/// # Returns
+///
+/// The outcome of the run, and the indices of the programs that failed
+pub fn run_chain(chain: &mut Chain) -> (RunOutcome, Vec<usize>) {
+    run_chain_with_retries(chain, &ChainRetryOptions::default())
+}
+
+/// Run an attempt of a chain that passed the checks before running
+fn run_chain_attempt(chain: &mut Chain) -> (RunOutcome, Vec<usize>) {
+    let execution_result: Result<Vec<ChainExecutionResult>, Error> = chain.execute();
+    set_program_results(&chain.get_program_results());
+    set_cancelled_programs(chain.get_cancelled_program_indices());
+    set_failed_verifications(chain.get_failed_verification_indices());
+    set_failure_policy(chain.get_failure_policy());
+    set_pinned_environment(chain.get_metadata().get_pinned_environment());
+    match execution_result {
+        Ok(results) => {
+            if results.iter().any(|result| result.is_completed_with_failures()) {
+                chain.show_statistics();
+                display_message(
+                    Level::Warn,
+                    "Chain execution finished, but some programs failed",
+                );
+                // Programs stopped by a termination signal fail too
+                let outcome: RunOutcome =
+                    if is_shutting_down() { RunOutcome::Cancelled } else { RunOutcome::CompletedWithFailures };
+                return (outcome, chain.get_failed_program_indices());
+            }
+            if results.iter().any(|result| result.is_unverified()) {
+                chain.show_statistics();
+                if let Err(error) = chain.record_value_history() {
+                    display_message(Level::Warn, &format!("Cannot save the entered values for the next runs: {}", error));
+                }
+                display_message(Level::Warn, "The programs succeeded, but the verification of the chain failed");
+                return (RunOutcome::Unverified, Vec::new());
+            }
+
+            if let Err(error) = chain.record_value_history() {
+                display_message(Level::Warn, &format!("Cannot save the entered values for the next runs: {}", error));
+            }
+            (RunOutcome::Succeeded, Vec::new())
+        }
+        Err(error) => {
+            let outcome: RunOutcome = RunOutcome::from_error(&error);
+            // Failures of programs are already displayed when they occur
+            if outcome != RunOutcome::Aborted {
+                display_message(Level::Error, &error.to_string());
+            }
+            let outcome: RunOutcome = if is_shutting_down() { RunOutcome::Cancelled } else { outcome };
+            chain.show_statistics();
+            display_message(
+                Level::Error,
+                "Chain execution finished with error(s) occurred",
+            );
+            (outcome, chain.get_failed_program_indices())
+        }
+    }
+}
+
+/// Run a chain, and run it again from the start when it fails because of
+/// the infrastructure, up to the retries of the options. Each attempt
+/// starts from the chain as it was before the first one, with the answers
+/// to the prompts of the first attempt. The run is recorded in the run
+/// history once, with the last attempt.
+pub fn run_chain_with_retries(chain: &mut Chain, retry_options: &ChainRetryOptions) -> (RunOutcome, Vec<usize>) {
+    if let Err(error) = check_required_packages(chain) {
+        display_message(Level::Error, &error.to_string());
+        return (RunOutcome::from_error(&error), Vec::new());
+    }
+    if let Err(error) = chain.check_supplied_values() {
+        display_message(Level::Error, &error.to_string());
+        return (RunOutcome::Usage, Vec::new());
+    }
+
+    let environment: Option<EnvironmentFingerprint> = check_chain_environment(chain);
+    let (outcome, failed_programs) = if retry_options.get_retries() == 0 {
+        run_chain_attempt(chain)
+    } else {
+        run_chain_attempts(chain, retry_options)
+    };
+    record_chain_failures(chain, outcome == RunOutcome::Succeeded, environment);
+
+    (outcome, failed_programs)
+}
+
+/// Run the attempts of a chain until one succeeds, fails for another
+/// reason than the infrastructure, or no retries are left
+fn run_chain_attempts(chain: &mut Chain, retry_options: &ChainRetryOptions) -> (RunOutcome, Vec<usize>) {
+    let loaded: Chain = chain.clone_loaded();
+    let attempts: u32 = retry_options.get_retries() + 1;
+    let mut chain_attempts: Vec<ChainAttempt> = Vec::new();
+    let (outcome, failed_programs) = loop {
+        let attempt: u32 = chain_attempts.len() as u32 + 1;
+        if attempt > 1 {
+            display_banner(&format!("Chain attempt #{} of {}", attempt, attempts));
+        }
+        let started_at: Instant = Instant::now();
+        let (outcome, failed_programs) = run_chain_attempt(chain);
+        chain_attempts.push(ChainAttempt {
+            attempt,
+            outcome,
+            failed_programs: failed_programs.clone(),
+            duration_ms: started_at.elapsed().as_millis() as u64,
+            retried_because: None,
+        });
+        if outcome == RunOutcome::Succeeded || is_shutting_down() {
+            break (outcome, failed_programs);
+        }
+
+        let (program_index, pattern) = match retry_options.classify_failure(outcome, chain) {
+            ChainFailureClass::Infrastructure { program_index, pattern } => (program_index, pattern),
+            ChainFailureClass::Genuine(reason) => {
+                display_message(Level::Warn, &format!("Chain attempt #{} is not retried: {}", attempt, reason));
+                break (outcome, failed_programs);
+            }
+        };
+        if attempt == attempts {
+            display_message(
+                Level::Warn,
+                &format!("Chain attempt #{} failed like an infrastructure error, but no more chain retries are left", attempt),
+            );
+            break (outcome, failed_programs);
+        }
+
+        let delay: Duration = retry_options.get_delay(attempt);
+        let reason: String = format!("program #{} matched `{}`", program_index, pattern);
+        display_message(
+            Level::Warn,
+            &format!(
+                "Chain attempt #{} failed like an infrastructure error ({}). Running the chain again in {:.1}s...",
+                attempt,
+                reason,
+                delay.as_secs_f64()
+            ),
+        );
+        if let Some(chain_attempt) = chain_attempts.last_mut() {
+            chain_attempt.retried_because = Some(reason);
+        }
+        if !wait_for_chain_attempt(delay) {
+            break (RunOutcome::Cancelled, failed_programs);
+        }
+        chain.restart_from(&loaded);
+    };
+
+    if chain_attempts.len() > 1 {
+        let form_data: Vec<Vec<String>> = chain_attempts
+            .iter()
+            .map(|chain_attempt| {
+                vec![
+                    chain_attempt.attempt.to_string(),
+                    chain_attempt.outcome.to_string(),
+                    chain_attempt
+                        .failed_programs
+                        .iter()
+                        .map(|index| format!("#{}", index))
+                        .collect::<Vec<String>>()
+                        .join(", "),
+                    format!("{:.1}s", chain_attempt.duration_ms as f64 / 1000.0),
+                    chain_attempt.retried_because.clone().unwrap_or_default(),
+                ]
+            })
+            .collect();
+        display_form(vec!["Attempt", "Outcome", "Failed programs", "Duration", "Retried because"], &form_data);
+    }
+    set_chain_attempts(chain_attempts);
+
+    (outcome, failed_programs)
+}
+
+/// Write the status file, if any, then exit with the code of the outcome
+pub fn exit_with_outcome(outcome: RunOutcome, failed_programs: &[usize]) -> ! {
+    if let Err(error) = write_status_file(outcome, failed_programs) {
+        display_message(Level::Error, &format!("Failed to write the status file: {}", error));
+    }
+
+    std::process::exit(outcome.get_code());
+}
@@ -0,0 +1,280 @@
+use clap::{Command, CommandFactory};
+
+use crate::arguments::{Arguments, CompletionShell};
+
+/// Longest hint shown next to a subcommand or an option
+const HINT_WIDTH: usize = 80;
+
+/// An option of a subcommand, as offered by the completion scripts
+struct CompletionOption {
+    long: String,
+    takes_value: bool,
+    hint: String,
+}
+
+/// A subcommand and its options, as offered by the completion scripts
+struct CompletionSubcommand {
+    name: String,
+    hint: String,
+    options: Vec<CompletionOption>,
+}
+
+/// Shorten a help text to its first sentence on one line
+fn get_hint(help: Option<String>) -> String {
+    let help: String = help.unwrap_or_default().split_whitespace().collect::<Vec<&str>>().join(" ");
+    let sentence: &str = help.split(". ").next().unwrap_or_default().trim_end_matches('.');
+    if sentence.chars().count() <= HINT_WIDTH {
+        return sentence.to_string();
+    }
+
+    let truncated: String = sentence.chars().take(HINT_WIDTH - 1).collect();
+    format!("{}…", truncated)
+}
+
+/// Collect the visible subcommands and their long options from the
+/// definition of the command line, so that the scripts follow it
+fn get_completion_subcommands() -> Vec<CompletionSubcommand> {
+    let mut command: Command = Arguments::command();
+    command.build();
+    command
+        .get_subcommands()
+        .filter(|subcommand| !subcommand.is_hide_set())
+        .map(|subcommand| CompletionSubcommand {
+            name: subcommand.get_name().to_string(),
+            hint: get_hint(subcommand.get_about().map(|about| about.to_string())),
+            options: subcommand
+                .get_arguments()
+                .filter(|argument| !argument.is_hide_set())
+                .filter_map(|argument| {
+                    Some(CompletionOption {
+                        long: format!("--{}", argument.get_long()?),
+                        takes_value: argument.get_action().takes_values(),
+                        hint: get_hint(argument.get_help().map(|help| help.to_string())),
+                    })
+                })
+                .collect(),
+        })
+        .collect()
+}
+
+/// Get the options of `cchain run` that take a value, which are skipped
+/// when looking for the chain argument
+fn get_run_value_options(subcommands: &[CompletionSubcommand]) -> Vec<String> {
+    subcommands
+        .iter()
+        .filter(|subcommand| subcommand.name == "run")
+        .flat_map(|subcommand| subcommand.options.iter())
+        .filter(|option| option.takes_value)
+        .map(|option| option.long.clone())
+        .collect()
+}
+
+/// Quote a text in single quotes for bash and zsh
+fn quote_single(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+/// Quote a text in single quotes for fish, which escapes within them
+fn quote_fish(text: &str) -> String {
+    format!("'{}'", text.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// Get the completion script of the shell. The scripts complete the
+/// subcommands and their options, and the variable names after
+/// `cchain run <chain> --var` by calling `cchain __complete-vars <chain>`.
+pub fn generate_completion_script(shell: CompletionShell) -> String {
+    let subcommands: Vec<CompletionSubcommand> = get_completion_subcommands();
+    match shell {
+        CompletionShell::Bash => generate_bash_script(&subcommands),
+        CompletionShell::Zsh => generate_zsh_script(&subcommands),
+        CompletionShell::Fish => generate_fish_script(&subcommands),
+    }
+}
+
+fn generate_bash_script(subcommands: &[CompletionSubcommand]) -> String {
+    let names: Vec<&str> = subcommands.iter().map(|subcommand| subcommand.name.as_str()).collect();
+    let cases: String = subcommands
+        .iter()
+        .map(|subcommand| {
+            let options: Vec<&str> = subcommand.options.iter().map(|option| option.long.as_str()).collect();
+            format!("        {}) options={} ;;\n", subcommand.name, quote_single(&options.join(" ")))
+        })
+        .collect();
+
+    format!(
+        r#"# bash completion for cchain, generated by `cchain completions bash`
+
+_cchain_chain_argument() {{
+    local index word
+    for (( index = 2; index < COMP_CWORD; index++ )); do
+        word="${{COMP_WORDS[index]}}"
+        case " {value_options} " in
+            *" $word "*) (( index++ )); continue ;;
+        esac
+        [[ "$word" == -* ]] && continue
+        word="${{word#[\"\']}}"
+        printf '%s' "${{word%[\"\']}}"
+        return
+    done
+}}
+
+_cchain() {{
+    local cur="${{COMP_WORDS[COMP_CWORD]}}"
+    local prev="${{COMP_WORDS[COMP_CWORD-1]}}"
+    if (( COMP_CWORD == 1 )); then
+        COMPREPLY=( $(compgen -W "{names}" -- "$cur") )
+        return
+    fi
+    if [[ "${{COMP_WORDS[1]}}" == run && "$prev" == --var ]]; then
+        local IFS=$'\n'
+        COMPREPLY=( $(compgen -W "$(cchain __complete-vars "$(_cchain_chain_argument)" 2>/dev/null | cut -f1)" -- "$cur") )
+        compopt -o nospace 2>/dev/null
+        return
+    fi
+
+    local options=''
+    case "${{COMP_WORDS[1]}}" in
+{cases}    esac
+    if [[ "$cur" == -* ]]; then
+        COMPREPLY=( $(compgen -W "$options" -- "$cur") )
+    else
+        COMPREPLY=( $(compgen -f -- "$cur") )
+    fi
+}}
+
+complete -o filenames -F _cchain cchain
+"#,
+        value_options = get_run_value_options(subcommands).join(" "),
+        names = names.join(" "),
+        cases = cases,
+    )
+}
+
+fn generate_zsh_script(subcommands: &[CompletionSubcommand]) -> String {
+    let described_subcommands: Vec<String> = subcommands
+        .iter()
+        .map(|subcommand| format!("        {}", quote_single(&format!("{}:{}", subcommand.name, subcommand.hint))))
+        .collect();
+    let cases: String = subcommands
+        .iter()
+        .map(|subcommand| {
+            let options: Vec<String> = subcommand
+                .options
+                .iter()
+                .map(|option| quote_single(&format!("{}:{}", option.long, option.hint)))
+                .collect();
+            format!("        {}) options=( {} ) ;;\n", subcommand.name, options.join(" "))
+        })
+        .collect();
+
+    format!(
+        r#"#compdef cchain
+# zsh completion for cchain, generated by `cchain completions zsh`
+
+_cchain_chain_argument() {{
+    local index word
+    for (( index = 3; index < CURRENT; index++ )); do
+        word="${{words[index]}}"
+        if (( ${{_cchain_value_options[(Ie)$word]}} )); then
+            (( index++ ))
+            continue
+        fi
+        [[ "$word" == -* ]] && continue
+        print -r -- "${{(Q)word}}"
+        return
+    done
+}}
+
+_cchain() {{
+    local -a _cchain_value_options=( {value_options} )
+    if (( CURRENT == 2 )); then
+        local -a subcommands=(
+{subcommands}
+        )
+        _describe -t commands 'cchain command' subcommands
+        return
+    fi
+    if [[ "${{words[2]}}" == run && "${{words[CURRENT-1]}}" == --var ]]; then
+        local -a variables
+        local line
+        for line in ${{(f)"$(cchain __complete-vars "$(_cchain_chain_argument)" 2>/dev/null)"}}; do
+            variables+=( "${{line/$'\t'/:}}" )
+        done
+        _describe -t variables 'variable' variables -S ''
+        return
+    fi
+
+    local -a options
+    case "${{words[2]}}" in
+{cases}    esac
+    if [[ "${{words[CURRENT]}}" == -* ]]; then
+        _describe -t options 'option' options
+    else
+        _files
+    fi
+}}
+
+_cchain "$@"
+"#,
+        value_options = get_run_value_options(subcommands).join(" "),
+        subcommands = described_subcommands.join("\n"),
+        cases = cases,
+    )
+}
+
+fn generate_fish_script(subcommands: &[CompletionSubcommand]) -> String {
+    let mut completions: Vec<String> = Vec::new();
+    for subcommand in subcommands {
+        completions.push(format!(
+            "complete -c cchain -n __fish_use_subcommand -f -a {} -d {}",
+            subcommand.name,
+            quote_fish(&subcommand.hint)
+        ));
+    }
+    for subcommand in subcommands {
+        let condition: String = quote_fish(&format!("__fish_seen_subcommand_from {}", subcommand.name));
+        for option in &subcommand.options {
+            let values: &str = match (subcommand.name.as_str(), option.long.as_str()) {
+                ("run", "--var") => " -x -a '(cchain __complete-vars (__cchain_chain_argument) 2>/dev/null)'",
+                _ if option.takes_value => " -r",
+                _ => "",
+            };
+            completions.push(format!(
+                "complete -c cchain -n {} -l {}{} -d {}",
+                condition,
+                option.long.trim_start_matches("--"),
+                values,
+                quote_fish(&option.hint)
+            ));
+        }
+    }
+
+    format!(
+        r#"# fish completion for cchain, generated by `cchain completions fish`
+
+function __cchain_chain_argument
+    set -l tokens (commandline -opc)
+    set -l skip_next 0
+    for token in $tokens[3..-1]
+        if test $skip_next -eq 1
+            set skip_next 0
+            continue
+        end
+        if contains -- $token {value_options}
+            set skip_next 1
+            continue
+        end
+        if string match -q -- '-*' $token
+            continue
+        end
+        echo $token
+        return
+    end
+end
+
+{completions}
+"#,
+        value_options = get_run_value_options(subcommands).join(" "),
+        completions = completions.join("\n"),
+    )
+}
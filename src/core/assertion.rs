@@ -0,0 +1,129 @@
+use std::path::Path;
+
+use anyhow::{anyhow, Error, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use similar::TextDiff;
+
+use crate::display_control::{display_message, Level};
+
+use super::paths::resolve_chain_relative_path;
+
+/// Number of characters of the actual output shown when an assertion fails
+const OUTPUT_SNIPPET_LENGTH: usize = 500;
+
+/// An assertion on the stdout of a program, after the stdout storage
+/// options are applied
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputAssertion {
+    /// The output contains the text
+    Contains(String),
+    /// The output does not contain the text
+    NotContains(String),
+    /// The output matches the regex pattern
+    MatchesRegex(String),
+    /// The output is the same as the content of the snapshot file
+    EqualsFile(String),
+}
+
+impl OutputAssertion {
+    /// Replace the text, the pattern or the path of the assertion with
+    /// what the closure returns for it
+    pub fn rewrite_value(&mut self, rewrite: &mut impl FnMut(&str) -> String) {
+        match self {
+            OutputAssertion::Contains(value)
+            | OutputAssertion::NotContains(value)
+            | OutputAssertion::MatchesRegex(value)
+            | OutputAssertion::EqualsFile(value) => *value = rewrite(value),
+        }
+    }
+
+    /// Resolve the path of a snapshot file against the base directory of the chain
+    pub fn resolve_relative_paths(&mut self, base_directory: &Path) {
+        if let OutputAssertion::EqualsFile(path) = self {
+            *path = resolve_chain_relative_path(path, base_directory);
+        }
+    }
+
+    /// Check the assertion before running the chain: regex patterns
+    /// must compile, and snapshot files must exist unless they are
+    /// going to be updated.
+    pub fn validate(&self, update_snapshots: bool) -> Result<(), Error> {
+        match self {
+            OutputAssertion::MatchesRegex(pattern) => {
+                Regex::new(pattern)
+                    .map_err(|error| anyhow!("Invalid `matches_regex` pattern `{}`: {}", pattern, error))?;
+            }
+            OutputAssertion::EqualsFile(path) if !update_snapshots && !Path::new(path).exists() => {
+                return Err(anyhow!(
+                    "Snapshot file {} does not exist. Run with `--update-snapshots` to create it",
+                    path
+                ));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+
+    /// Check the output against the assertion. When `update_snapshots` is
+    /// set, snapshot files are rewritten with the output instead.
+    pub fn check(&self, output: &str, update_snapshots: bool) -> Result<(), Error> {
+        let is_passed: bool = match self {
+            OutputAssertion::Contains(text) => output.contains(text.as_str()),
+            OutputAssertion::NotContains(text) => !output.contains(text.as_str()),
+            OutputAssertion::MatchesRegex(pattern) => Regex::new(pattern)?.is_match(output),
+            OutputAssertion::EqualsFile(path) => {
+                if update_snapshots {
+                    if let Some(parent) = Path::new(path).parent() {
+                        std::fs::create_dir_all(parent)?;
+                    }
+                    std::fs::write(path, output)?;
+                    display_message(Level::Logging, &format!("Snapshot is updated: {}", path));
+                    return Ok(());
+                }
+
+                let expected: String = std::fs::read_to_string(path)
+                    .map_err(|error| anyhow!("Failed to read snapshot file {}: {}", path, error))?;
+                if expected == output {
+                    return Ok(());
+                }
+
+                let diff: String = TextDiff::from_lines(expected.as_str(), output)
+                    .unified_diff()
+                    .header(path, "actual output")
+                    .to_string();
+                return Err(anyhow!("Assertion failed: {}\n{}", self, diff));
+            }
+        };
+
+        if is_passed {
+            return Ok(());
+        }
+
+        Err(anyhow!(
+            "Assertion failed: {}\nActual output:\n{}",
+            self,
+            Self::get_output_snippet(output)
+        ))
+    }
+
+    fn get_output_snippet(output: &str) -> String {
+        match output.char_indices().nth(OUTPUT_SNIPPET_LENGTH) {
+            Some((index, _)) => format!("{}...", &output[..index]),
+            None => output.to_string(),
+        }
+    }
+}
+
+impl std::fmt::Display for OutputAssertion {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            OutputAssertion::Contains(text) => write!(f, "contains {:?}", text),
+            OutputAssertion::NotContains(text) => write!(f, "not_contains {:?}", text),
+            OutputAssertion::MatchesRegex(pattern) => write!(f, "matches_regex {:?}", pattern),
+            OutputAssertion::EqualsFile(path) => write!(f, "equals_file {}", path),
+        }
+    }
+}
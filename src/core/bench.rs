@@ -0,0 +1,443 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    time::Instant,
+};
+
+use anyhow::{anyhow, Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    display_control::{display_message, Level},
+    variable::{ProvidedValue, VariableSource},
+};
+
+use super::{
+    chain::{Chain, ChainExecutionResult, ProgramListing},
+    io_profile::IoUsage,
+    traits::Execution,
+};
+
+/// Steps whose standard deviation is above this percentage of their
+/// mean are flagged as unstable, unless `--unstable-threshold` is given
+pub const DEFAULT_UNSTABLE_THRESHOLD_PERCENT: f64 = 10.0;
+
+/// Changes smaller than this percentage of the baseline are within
+/// noise, however stable the durations
+const NEGLIGIBLE_CHANGE_PERCENT: f64 = 1.0;
+
+/// Columns of the table of a bench report
+pub const BENCH_FORM_LABELS: [&str; 8] = ["#", "Step", "Min", "Median", "Mean", "P95", "Stddev", "Notes"];
+
+/// Columns of the table of the changes against a baseline report
+pub const COMPARISON_FORM_LABELS: [&str; 6] = ["#", "Step", "Baseline", "Median", "Change", "Hint"];
+
+/// Summary of the durations of a step, in milliseconds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TimingStatistics {
+    pub count: usize,
+    pub min_ms: f64,
+    pub median_ms: f64,
+    pub mean_ms: f64,
+    pub p95_ms: f64,
+    /// The sample standard deviation, 0 with a single sample
+    pub stddev_ms: f64,
+}
+
+impl TimingStatistics {
+    /// Summarize the durations, or `None` when there are none
+    pub fn from_samples(samples: &[f64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+
+        let mut sorted_samples: Vec<f64> = samples.to_vec();
+        sorted_samples.sort_by(f64::total_cmp);
+        let count: usize = sorted_samples.len();
+        let mean_ms: f64 = sorted_samples.iter().sum::<f64>() / count as f64;
+        let variance: f64 = if count > 1 {
+            sorted_samples.iter().map(|sample| (sample - mean_ms).powi(2)).sum::<f64>() / (count - 1) as f64
+        } else {
+            0.0
+        };
+
+        Some(Self {
+            count,
+            min_ms: sorted_samples[0],
+            median_ms: get_percentile(&sorted_samples, 50.0),
+            mean_ms,
+            p95_ms: get_percentile(&sorted_samples, 95.0),
+            stddev_ms: variance.sqrt(),
+        })
+    }
+
+    /// Get the standard deviation as a percentage of the mean
+    pub fn get_variation_percent(&self) -> f64 {
+        if self.mean_ms == 0.0 {
+            return 0.0;
+        }
+        self.stddev_ms / self.mean_ms * 100.0
+    }
+
+    /// Check whether the durations vary more than the threshold, a
+    /// percentage of the mean
+    pub fn is_unstable(&self, threshold_percent: f64) -> bool {
+        self.count > 1 && self.get_variation_percent() > threshold_percent
+    }
+}
+
+/// Get a percentile of sorted samples, interpolated between the two
+/// closest ranks
+pub fn get_percentile(sorted_samples: &[f64], percentile: f64) -> f64 {
+    if sorted_samples.is_empty() {
+        return 0.0;
+    }
+    let rank: f64 = percentile.clamp(0.0, 100.0) / 100.0 * (sorted_samples.len() - 1) as f64;
+    let lower: usize = rank.floor() as usize;
+    let upper: usize = rank.ceil() as usize;
+
+    sorted_samples[lower] + (sorted_samples[upper] - sorted_samples[lower]) * (rank - lower as f64)
+}
+
+/// Render a duration in milliseconds, e.g. `12.3ms` or `4.56s`
+pub fn format_milliseconds(milliseconds: f64) -> String {
+    if milliseconds.abs() >= 1000.0 {
+        format!("{:.2}s", milliseconds / 1000.0)
+    } else {
+        format!("{:.1}ms", milliseconds)
+    }
+}
+
+/// The durations of a program, or of the whole chain, in the measured
+/// iterations
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchStep {
+    /// The index of the program, or `None` for the whole chain
+    pub index: Option<usize>,
+    pub label: String,
+    pub samples_ms: Vec<f64>,
+    pub statistics: Option<TimingStatistics>,
+    /// Whether the durations vary more than the unstable threshold
+    pub unstable: bool,
+    /// The mean disk, I/O and CPU usage per iteration, with `--profile-io`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_usage: Option<IoUsage>,
+}
+
+impl BenchStep {
+    pub fn new(index: Option<usize>, label: String, samples_ms: Vec<f64>, unstable_threshold_percent: f64) -> Self {
+        let statistics: Option<TimingStatistics> = TimingStatistics::from_samples(&samples_ms);
+        let unstable: bool = statistics
+            .as_ref()
+            .is_some_and(|statistics| statistics.is_unstable(unstable_threshold_percent));
+        Self { index, label, samples_ms, statistics, unstable, io_usage: None }
+    }
+
+    pub fn with_io_usage(mut self, io_usage: Option<IoUsage>) -> Self {
+        self.io_usage = io_usage;
+        self
+    }
+
+    /// Render the step as a row of the table of the report
+    fn to_form_row(&self) -> Vec<String> {
+        let index: String = self.index.map(|index| index.to_string()).unwrap_or_default();
+        let Some(statistics) = &self.statistics else {
+            return vec![index, self.label.clone(), String::new(), String::new(), String::new(), String::new(), String::new(), "not run".to_string()];
+        };
+        let mut notes: Vec<String> = Vec::new();
+        if self.unstable {
+            notes.push(format!("unstable, ±{:.0}%", statistics.get_variation_percent()));
+        }
+        if let Some(io_usage) = &self.io_usage {
+            notes.push(io_usage.to_string());
+        }
+        let note: String = notes.join("; ");
+
+        vec![
+            index,
+            self.label.clone(),
+            format_milliseconds(statistics.min_ms),
+            format_milliseconds(statistics.median_ms),
+            format_milliseconds(statistics.mean_ms),
+            format_milliseconds(statistics.p95_ms),
+            format_milliseconds(statistics.stddev_ms),
+            note,
+        ]
+    }
+}
+
+/// The result of `cchain bench`, which `--compare` reads back
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchReport {
+    pub chain: String,
+    /// The measured iterations that finished, without the warmup ones
+    pub iterations: usize,
+    pub warmup: usize,
+    pub unstable_threshold_percent: f64,
+    /// The durations of the whole chain
+    pub overall: BenchStep,
+    /// The durations of each program that ran, in the order of the chain
+    pub steps: Vec<BenchStep>,
+    /// Why the bench stopped before all iterations, e.g. a failed program
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aborted: Option<String>,
+    /// The changes against the report of `--compare`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub comparison: Vec<BenchDelta>,
+}
+
+impl BenchReport {
+    /// Read a report saved from `cchain bench --output json`
+    pub fn from_file(path: &str) -> Result<Self, Error> {
+        let content: String =
+            std::fs::read_to_string(path).map_err(|error| anyhow!("Cannot read the bench report {}: {}", path, error))?;
+        serde_json::from_str(&content).map_err(|error| anyhow!("{} is not a bench report: {}", path, error))
+    }
+
+    /// Render the steps and then the whole chain as the rows of a table
+    pub fn get_form_data(&self) -> Vec<Vec<String>> {
+        self.steps.iter().chain(std::iter::once(&self.overall)).map(BenchStep::to_form_row).collect()
+    }
+
+    /// Compare the medians of the steps and of the whole chain against a
+    /// baseline, for the steps in both
+    pub fn compare(&self, baseline: &BenchReport) -> Vec<BenchDelta> {
+        let mut deltas: Vec<BenchDelta> = Vec::new();
+        for step in self.steps.iter().chain(std::iter::once(&self.overall)) {
+            let baseline_step: Option<&BenchStep> = match step.index {
+                Some(index) => baseline.steps.iter().find(|baseline_step| baseline_step.index == Some(index)),
+                None => Some(&baseline.overall),
+            };
+            let (Some(baseline_statistics), Some(statistics)) =
+                (baseline_step.and_then(|baseline_step| baseline_step.statistics.as_ref()), step.statistics.as_ref())
+            else {
+                continue;
+            };
+            deltas.push(BenchDelta::new(step.index, step.label.clone(), baseline_statistics, statistics));
+        }
+
+        deltas
+    }
+}
+
+/// Whether a step got faster or slower than in the baseline
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeSignificance {
+    Faster,
+    Slower,
+    /// The change is smaller than the variation of the durations
+    WithinNoise,
+}
+
+impl std::fmt::Display for ChangeSignificance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ChangeSignificance::Faster => write!(f, "likely faster"),
+            ChangeSignificance::Slower => write!(f, "likely slower"),
+            ChangeSignificance::WithinNoise => write!(f, "within noise"),
+        }
+    }
+}
+
+impl ChangeSignificance {
+    /// Judge the change of the means against twice their standard error,
+    /// a rough 95% bound. Changes below 1% of the baseline are noise.
+    pub fn from_statistics(baseline: &TimingStatistics, current: &TimingStatistics) -> Self {
+        let difference: f64 = current.mean_ms - baseline.mean_ms;
+        let standard_error: f64 = (baseline.stddev_ms.powi(2) / baseline.count as f64
+            + current.stddev_ms.powi(2) / current.count as f64)
+            .sqrt();
+        let is_negligible: bool = difference.abs() <= baseline.mean_ms.abs() * NEGLIGIBLE_CHANGE_PERCENT / 100.0;
+        if is_negligible || difference.abs() <= 2.0 * standard_error {
+            ChangeSignificance::WithinNoise
+        } else if difference < 0.0 {
+            ChangeSignificance::Faster
+        } else {
+            ChangeSignificance::Slower
+        }
+    }
+}
+
+/// The change of a step against a baseline report
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchDelta {
+    pub index: Option<usize>,
+    pub label: String,
+    pub baseline_median_ms: f64,
+    pub median_ms: f64,
+    pub delta_ms: f64,
+    /// The change as a percentage of the baseline median
+    pub delta_percent: f64,
+    pub significance: ChangeSignificance,
+}
+
+impl BenchDelta {
+    pub fn new(index: Option<usize>, label: String, baseline: &TimingStatistics, current: &TimingStatistics) -> Self {
+        let delta_ms: f64 = current.median_ms - baseline.median_ms;
+        let delta_percent: f64 = if baseline.median_ms == 0.0 { 0.0 } else { delta_ms / baseline.median_ms * 100.0 };
+        Self {
+            index,
+            label,
+            baseline_median_ms: baseline.median_ms,
+            median_ms: current.median_ms,
+            delta_ms,
+            delta_percent,
+            significance: ChangeSignificance::from_statistics(baseline, current),
+        }
+    }
+
+    /// Render the change as a row of the comparison table
+    pub fn to_form_row(&self) -> Vec<String> {
+        vec![
+            self.index.map(|index| index.to_string()).unwrap_or_default(),
+            self.label.clone(),
+            format_milliseconds(self.baseline_median_ms),
+            format_milliseconds(self.median_ms),
+            format!("{:+.1}ms ({:+.1}%)", self.delta_ms, self.delta_percent),
+            self.significance.to_string(),
+        ]
+    }
+}
+
+/// How `cchain bench` runs a chain
+#[derive(Debug, Clone, PartialEq)]
+pub struct BenchOptions {
+    iterations: usize,
+    warmup: usize,
+    unstable_threshold_percent: f64,
+    profile_io: bool,
+}
+
+impl BenchOptions {
+    pub fn new(iterations: usize) -> Self {
+        Self { iterations, warmup: 0, unstable_threshold_percent: DEFAULT_UNSTABLE_THRESHOLD_PERCENT, profile_io: false }
+    }
+
+    /// Run the chain this many times before measuring, e.g. to fill caches
+    pub fn with_warmup(mut self, warmup: usize) -> Self {
+        self.warmup = warmup;
+        self
+    }
+
+    pub fn with_unstable_threshold(mut self, unstable_threshold_percent: f64) -> Self {
+        self.unstable_threshold_percent = unstable_threshold_percent;
+        self
+    }
+
+    /// Measure the disk, I/O and CPU usage of the steps
+    pub fn with_profile_io(mut self, profile_io: bool) -> Self {
+        self.profile_io = profile_io;
+        self
+    }
+}
+
+/// Run the chain at the path for the warmup and the measured iterations,
+/// loading it afresh each time. The values entered at the prompts of the
+/// first iteration are reused by the others. An iteration that fails
+/// stops the bench, and the report covers the iterations before it.
+pub fn bench_chain(path: &str, mut provided_values: HashMap<String, ProvidedValue>, options: &BenchOptions) -> BenchReport {
+    let mut overall_samples: Vec<f64> = Vec::new();
+    let mut step_samples: BTreeMap<usize, Vec<f64>> = BTreeMap::new();
+    let mut step_io_usages: BTreeMap<usize, Vec<IoUsage>> = BTreeMap::new();
+    let mut labels: BTreeMap<usize, String> = BTreeMap::new();
+    let mut aborted: Option<String> = None;
+
+    let total_iterations: usize = options.warmup + options.iterations;
+    for iteration in 0..total_iterations {
+        let is_warmup: bool = iteration < options.warmup;
+        display_message(
+            Level::Logging,
+            &format!(
+                "Bench iteration {}/{}{}",
+                iteration + 1,
+                total_iterations,
+                if is_warmup { ", warming up" } else { "" }
+            ),
+        );
+
+        let mut chain: Chain = match Chain::from_file(path) {
+            Ok(chain) => chain,
+            Err(error) => {
+                aborted = Some(format!("Iteration {} cannot load the chain: {}", iteration + 1, error));
+                break;
+            }
+        };
+        chain.set_provided_values(provided_values.clone());
+        chain.set_skip_review(iteration > 0);
+        chain.set_profile_io(options.profile_io);
+
+        let started_at: Instant = Instant::now();
+        let result: Result<Vec<ChainExecutionResult>, Error> = chain.execute();
+        let elapsed_ms: f64 = started_at.elapsed().as_secs_f64() * 1000.0;
+        let failure: Option<String> = match &result {
+            Err(error) => Some(error.to_string()),
+            Ok(results) if results.iter().any(|result| result.is_completed_with_failures() || result.is_unverified()) => {
+                let failed_indices: Vec<String> = chain
+                    .get_failed_program_indices()
+                    .into_iter()
+                    .chain(chain.get_failed_verification_indices())
+                    .map(|index| format!("#{}", index))
+                    .collect();
+                Some(format!("program(s) {} failed", failed_indices.join(", ")))
+            }
+            Ok(_) => None,
+        };
+        if let Some(failure) = failure {
+            aborted = Some(format!("Iteration {} failed: {}", iteration + 1, failure));
+            break;
+        }
+
+        // The answers are given once for the whole bench
+        if iteration == 0 {
+            for provenance in chain.get_variable_provenances() {
+                if matches!(provenance.get_source(), VariableSource::Prompt) {
+                    provided_values.entry(provenance.name.clone()).or_insert(ProvidedValue {
+                        value: provenance.value.clone(),
+                        source: "the first iteration of the bench".to_string(),
+                    });
+                }
+            }
+        }
+        if is_warmup {
+            continue;
+        }
+
+        overall_samples.push(elapsed_ms);
+        let program_listing: Vec<ProgramListing> = chain.get_program_listing();
+        for result in chain.get_program_results() {
+            let Some(index) = result.program_index() else {
+                continue;
+            };
+            step_samples.entry(index).or_default().push(result.duration().as_secs_f64() * 1000.0);
+            if let Some(io_usage) = result.io_usage() {
+                step_io_usages.entry(index).or_default().push(io_usage);
+            }
+            labels.entry(index).or_insert_with(|| {
+                program_listing
+                    .get(index)
+                    .map(|program| program.alias.clone().unwrap_or_else(|| program.command.clone()))
+                    .unwrap_or_default()
+            });
+        }
+    }
+
+    let steps: Vec<BenchStep> = step_samples
+        .into_iter()
+        .map(|(index, samples)| {
+            let label: String = labels.remove(&index).unwrap_or_default();
+            let io_usage: Option<IoUsage> = step_io_usages.get(&index).and_then(|io_usages| IoUsage::mean(io_usages));
+            BenchStep::new(Some(index), label, samples, options.unstable_threshold_percent).with_io_usage(io_usage)
+        })
+        .collect();
+
+    BenchReport {
+        chain: path.to_string(),
+        iterations: overall_samples.len(),
+        warmup: options.warmup,
+        unstable_threshold_percent: options.unstable_threshold_percent,
+        overall: BenchStep::new(None, "whole chain".to_string(), overall_samples, options.unstable_threshold_percent),
+        steps,
+        aborted,
+        comparison: Vec::new(),
+    }
+}
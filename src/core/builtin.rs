@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+use crate::display_control::display_form;
+
+/// Fields accepted in a built-in step
+pub const BUILTIN_STEP_FIELD_NAMES: &[&str] = &["builtin"];
+
+/// Values longer than this are truncated in the variable table
+pub const DEBUG_VALUE_WIDTH: usize = 60;
+
+/// Shown instead of the values of the variables declared as `secret`
+pub const SECRET_MASK: &str = "********";
+
+/// Steps built into cchain, which take the place of a program in the
+/// chain, e.g. `{ "builtin": "debug_variables" }`. They never fail the chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BuiltinStep {
+    /// Show every variable of the chain with its current value
+    DebugVariables,
+}
+
+impl std::fmt::Display for BuiltinStep {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BuiltinStep::DebugVariables => write!(f, "debug_variables"),
+        }
+    }
+}
+
+/// A variable of a chain at a point of the run, as shown by the
+/// `debug_variables` step
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VariableSnapshot {
+    pub name: String,
+    /// When the variable gets its value, e.g. `startup`
+    pub initialization: String,
+    pub has_value: bool,
+    /// The value, truncated to `DEBUG_VALUE_WIDTH`, or `SECRET_MASK` for secrets
+    pub value: Option<String>,
+    /// The index of the program whose output is stored in the variable
+    pub program_index: Option<usize>,
+}
+
+/// Truncate a value to a number of characters, marking the truncation
+pub fn truncate_value(value: &str, width: usize) -> String {
+    if value.chars().count() <= width {
+        return value.to_string();
+    }
+
+    let truncated: String = value.chars().take(width.saturating_sub(1)).collect();
+    format!("{}…", truncated)
+}
+
+/// Show the variables in a table
+pub fn display_variable_snapshots(snapshots: &[VariableSnapshot]) {
+    let form_data: Vec<Vec<String>> = snapshots
+        .iter()
+        .map(|snapshot| {
+            vec![
+                snapshot.name.clone(),
+                snapshot.initialization.clone(),
+                if snapshot.has_value { "yes".to_string() } else { "no".to_string() },
+                snapshot.value.clone().unwrap_or_default(),
+                snapshot.program_index.map(|index| format!("#{}", index)).unwrap_or_default(),
+            ]
+        })
+        .collect();
+    display_form(vec!["Variable", "Initialization", "Set", "Value", "Program"], &form_data);
+}
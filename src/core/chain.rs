@@ -1,22 +1,274 @@
-use std::{cell::Cell, collections::HashSet, sync::{Arc, Mutex, MutexGuard}, thread};
+use std::{cell::RefCell, collections::{BTreeMap, HashMap, HashSet}, io::PipeReader, path::{Path, PathBuf}, str::FromStr, sync::{mpsc::channel, Arc, Mutex, MutexGuard}, thread};
 
 use anyhow::{anyhow, Error, Result};
+use serde::Serialize;
 
 use crate::{
-    commons::{packages::{AvailablePackages, Package}, utility::input_message}, core::{
-        program::Program,
+    commons::{errors::{ChainError, CommandLineError}, interaction::SharedInteractor, packages::{AvailablePackages, Package}}, core::{
+        builtin::{display_variable_snapshots, truncate_value, BuiltinStep, VariableSnapshot, DEBUG_VALUE_WIDTH, SECRET_MASK},
+        command::{Argument, CommandLine, RenderStyle, StreamEnds},
+        debug::{get_debug_variable_name, DebugDecision, DebugShellOptions, DEBUG_FAILED_COMMAND, DEBUG_VARIABLE_PREFIX},
+        effects::{find_destructive_pattern, KNOWN_EFFECTS},
+        events::{ChainEvent, EventSender},
+        fix::get_closest_fields,
+        group::{CancellationHandle, MemberFailurePolicy},
+        limits::{get_chain_limits, ChainLimits},
+        matrix::{expand_matrix_programs, ExpandedPrograms},
+        metadata::{ChainFile, ChainMetadata},
+        parse_cache::{cache_chain, get_cached_chain, record_parse, ChainFileVersion, StoredChain},
+        options::{FailurePolicy, ProgramSelection},
+        program::{find_duplicate_programs, AttemptRecord, Program, ProgramExecutionResult},
+        scratch::{ScratchDirectory, CHAIN_TMP_VARIABLE_NAME},
+        pause::{PauseControl, PauseDecision},
+        service::{interrupt, Service, SERVICE_STOP_GRACE_PERIOD},
+        shutdown::{is_grace_period_over, is_shutting_down},
         traits::{Execution, ExecutionType},
-    }, display_control::{display_message, display_tree_message, Level}, marker::reference::TrackPath, variable::{Variable, VariableGroupControl, VariableInitializationTime}
+    }, display_control::{display_command_line, display_form, display_message, display_tree_message, is_environment_diff_shown, is_summary_only, Level}, expression::{Expression, TypedValue}, function::VariableParameterValue, marker::{history::ValueHistory, presets::VariablePresets, reference::TrackPath}, variable::{normalize_variable_name, ProvidedValue, Variable, VariableDeclaration, VariableGroupControl, VariableInitializationTime, VariableProvenance, VariableSource, VariableType, ValueCandidate, ValueOrigin, select_winning_value}
 };
 
+/// Name of the file next to a chain that `load_adjacent_env` loads
+pub const ADJACENT_ENV_FILE_NAME: &str = ".cchain.env";
+
+/// Name of a chain given as JSON on the command line, e.g. with `--inline`
+pub const INLINE_CHAIN_NAME: &str = "<inline>";
+
+/// How a chain that ran to the end did
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChainExecutionStatus {
+    Succeeded,
+    /// Programs with `exit_on_failure: false` failed
+    CompletedWithFailures,
+    /// The programs succeeded, but verification steps failed
+    Unverified,
+}
+
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ChainExecutionResult {
-    output: String,
+    /// The results of the programs that ran, in the order of the programs
+    program_results: Vec<ProgramExecutionResult>,
+    /// Indices of the programs that failed with `exit_on_failure: false`,
+    /// while the chain continued to the end
+    failed_program_indices: Vec<usize>,
+    /// The values of the variables and where they came from
+    variable_provenances: Vec<VariableProvenance>,
+    /// Indices of the verification steps that failed after the programs
+    /// succeeded
+    failed_verification_indices: Vec<usize>,
 }
 
 impl ChainExecutionResult {
-    pub fn new(output: String) -> Self {
-        Self { output }
+    pub fn new(program_results: Vec<ProgramExecutionResult>) -> Self {
+        Self {
+            program_results,
+            failed_program_indices: Vec::new(),
+            variable_provenances: Vec::new(),
+            failed_verification_indices: Vec::new(),
+        }
+    }
+
+    pub fn with_variable_provenances(mut self, variable_provenances: Vec<VariableProvenance>) -> Self {
+        self.variable_provenances = variable_provenances;
+        self
+    }
+
+    pub fn get_variable_provenances(&self) -> &Vec<VariableProvenance> {
+        &self.variable_provenances
+    }
+
+    pub fn with_failed_program_indices(mut self, failed_program_indices: Vec<usize>) -> Self {
+        self.failed_program_indices = failed_program_indices;
+        self
+    }
+
+    pub fn get_failed_program_indices(&self) -> &Vec<usize> {
+        &self.failed_program_indices
+    }
+
+    /// Whether the chain ran to the end, but some programs failed
+    pub fn is_completed_with_failures(&self) -> bool {
+        !self.failed_program_indices.is_empty()
+    }
+
+    pub fn with_failed_verification_indices(mut self, failed_verification_indices: Vec<usize>) -> Self {
+        self.failed_verification_indices = failed_verification_indices;
+        self
+    }
+
+    pub fn get_failed_verification_indices(&self) -> &Vec<usize> {
+        &self.failed_verification_indices
+    }
+
+    /// Whether the programs succeeded, but verification steps failed
+    pub fn is_unverified(&self) -> bool {
+        !self.failed_verification_indices.is_empty()
+    }
+
+    pub fn get_program_results(&self) -> &Vec<ProgramExecutionResult> {
+        &self.program_results
+    }
+
+    pub fn get_status(&self) -> ChainExecutionStatus {
+        if self.is_completed_with_failures() {
+            ChainExecutionStatus::CompletedWithFailures
+        } else if self.is_unverified() {
+            ChainExecutionStatus::Unverified
+        } else {
+            ChainExecutionStatus::Succeeded
+        }
+    }
+}
+
+/// Whether the output stored by a program is available to another one
+/// using it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OutputAvailability {
+    /// The producer finishes before the consumer starts
+    Available,
+    /// Both are members of the same concurrency group launch, which run
+    /// in no particular order
+    SameConcurrencyGroup,
+    /// The producer is a member of a concurrency group that starts after
+    /// the consumer, although it comes before it in the chain
+    GroupStartsLater,
+    /// The producer runs after the consumer, which uses the value the
+    /// variable had before, e.g. from `--var`
+    ProducedLater,
+}
+
+/// A variable stored by one program in `stdout_stored_to` and used by
+/// another
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OutputDependency {
+    pub name: String,
+    pub producer: usize,
+    pub consumer: usize,
+    pub availability: OutputAvailability,
+}
+
+/// A value supplied from outside a chain that may not do what is meant,
+/// as found by `Chain::reconcile_supplied_values`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SuppliedValueFinding {
+    /// No variable of the chain has the name, e.g. a typo
+    Unknown { name: String, source: String, suggestions: Vec<String> },
+    /// A program stores its output in the variable before any program
+    /// uses the supplied value
+    Overwritten { name: String, source: String, producer: usize },
+    /// The name looks like the one of a secret, but the variable is not
+    /// declared `secret`, so its value shows up in messages
+    UndeclaredSecret { name: String, source: String },
+}
+
+impl std::fmt::Display for SuppliedValueFinding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            SuppliedValueFinding::Unknown { name, source, suggestions } => {
+                write!(f, "Variable `{}` (from {}) is not used in the chain", name, source)?;
+                match suggestions.as_slice() {
+                    [] => Ok(()),
+                    [suggestion] => write!(f, ". Did you mean `{}`?", suggestion),
+                    _ => write!(
+                        f,
+                        ". Did you mean one of {}?",
+                        suggestions.iter().map(|suggestion| format!("`{}`", suggestion)).collect::<Vec<String>>().join(", ")
+                    ),
+                }
+            }
+            SuppliedValueFinding::Overwritten { name, source, producer } => write!(
+                f,
+                "Variable `{}` (from {}) is overwritten by the output of program #{} before any program uses it",
+                name, source, producer
+            ),
+            SuppliedValueFinding::UndeclaredSecret { name, source } => write!(
+                f,
+                "Variable `{}` (from {}) looks like a secret, but is not declared `secret`, so its value may show in messages",
+                name, source
+            ),
+        }
+    }
+}
+
+/// Parts of the names of variables that usually hold secrets
+const SECRET_NAME_PARTS: [&str; 7] = ["token", "password", "passwd", "secret", "api_key", "apikey", "credential"];
+
+/// Check whether a variable name looks like the one of a secret, e.g.
+/// `github_token`
+pub fn looks_like_secret_name(name: &str) -> bool {
+    let name: String = name.to_lowercase();
+    SECRET_NAME_PARTS.iter().any(|part| name.contains(part))
+}
+
+/// A variable of a chain as offered by the completion of `cchain run --var`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableCompletion {
+    pub name: String,
+    /// When the variable is asked for, e.g. `startup`
+    pub initialization: String,
+    pub description: Option<String>,
+}
+
+impl VariableCompletion {
+    /// Render the candidate as `name=`, a tab, and the hint shown next to
+    /// it by the shells that show one
+    pub fn to_candidate(&self) -> String {
+        let hint: String = match &self.description {
+            Some(description) => format!("{}: {}", self.initialization, description),
+            None => self.initialization.clone(),
+        };
+        format!("{}=\t{}", self.name, hint.split_whitespace().collect::<Vec<&str>>().join(" "))
+    }
+}
+
+/// A variable of a chain with what cchain remembers for it, as listed by
+/// `cchain vars list`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RememberedVariable {
+    pub name: String,
+    /// The declared type, e.g. `integer`
+    pub kind: VariableType,
+    pub description: Option<String>,
+    pub secret: bool,
+    /// The most recent value kept in the value history, masked for secrets
+    pub cached_value: Option<String>,
+    /// The preset that is the default answer at its prompts, if any
+    pub preset: Option<String>,
+    /// When the cached value was last used, in seconds since the Unix epoch
+    pub last_used: Option<u64>,
+}
+
+/// A program of a chain as listed by `cchain run --list-programs`, with
+/// the index that `--from` and `--only` take
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProgramListing {
+    pub index: usize,
+    pub alias: Option<String>,
+    /// The summary of the command line, or the name of a built-in step
+    pub command: String,
+    pub concurrency_group: Option<usize>,
+    pub builtin: bool,
+    pub always_run: bool,
+    /// Whether it is a step of the `verify` section
+    pub verification: bool,
+    /// Whether the program runs with the `--from` and `--only` given
+    pub selected: bool,
+}
+
+/// A variable that the chain would prompt for when it runs
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PendingInput {
+    pub name: String,
+    /// Whether it is only asked for once a program using it starts, so
+    /// that a run stopping before never asks for it: the `on_program_execution`
+    /// variables, and the others with `lazy_prompts`
+    pub conditional: bool,
+}
+
+impl std::fmt::Display for PendingInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.conditional {
+            true => write!(f, "`{}` (conditional)", self.name),
+            false => write!(f, "`{}`", self.name),
+        }
     }
 }
 
@@ -24,22 +276,287 @@ impl ChainExecutionResult {
 pub struct Chain {
     programs: Vec<Arc<Mutex<Program>>>,
     variables: Vec<Arc<Mutex<Variable>>>,
-    failed_program_executions: Cell<usize>,
+    /// Indices of the programs that failed, in the order of failures
+    failed_program_indices: RefCell<Vec<usize>>,
+    /// Indices of the programs cancelled because another member of their
+    /// concurrency group failed, in the order of their indices
+    cancelled_program_indices: RefCell<Vec<usize>>,
+    /// Index of the first step of the `verify` section, which come after
+    /// the programs in `programs`
+    verification_start: usize,
+    /// Indices of the verification steps that failed
+    failed_verification_indices: RefCell<Vec<usize>>,
     path: String,
+    metadata: ChainMetadata,
+    /// Services started by the chain, in start order
+    services: Vec<Service>,
+    /// Descriptions of the services that exited while the chain was running
+    unexpectedly_exited_services: Vec<String>,
+    /// Values provided with `--var` and `--var-file`, keyed by the variable names
+    provided_values: HashMap<String, ProvidedValue>,
+    /// Values loaded from the `.cchain.env` file next to the chain, with
+    /// `load_adjacent_env`. They are the defaults at the prompts, and are
+    /// used without prompting when there is no user to ask.
+    adjacent_env_values: HashMap<String, ProvidedValue>,
+    /// Asks the user for the values of variables and other decisions
+    interactor: SharedInteractor,
+    /// Fail the syntax check when a variable is not declared in the metadata
+    require_declarations: bool,
+    /// Also lint the programs identical to an earlier, not adjacent one
+    lint_separated_duplicates: bool,
+    /// Skip the review of the answers to the startup prompts
+    skip_review: bool,
+    /// Show the value each source supplies for the startup variables,
+    /// and let the user pick another, with `--confirm-vars`
+    confirm_variables: bool,
+    /// The values picked at `--confirm-vars`, with their sources, kept
+    /// when the chain is run again
+    confirmed_values: HashMap<String, (String, VariableSource)>,
+    /// Whether the programs of a generated chain have been edited since
+    is_modified_since_generation: bool,
+    /// The files that the programs came from, when the chain extends
+    /// other chains
+    program_origins: Vec<String>,
+    /// The programs as written, before the values of the variables are
+    /// put in, e.g. to fingerprint their failures
+    program_templates: Vec<String>,
+    /// Indices of the programs that have been started, so that the
+    /// cleanup programs not yet started run when the chain aborts
+    started_program_indices: RefCell<HashSet<usize>>,
+    /// Offer a debug shell when a program fails, with `--debug-on-failure`
+    debug_shell_options: Option<DebugShellOptions>,
+    /// Run programs that delete or mutate things without asking,
+    /// with `--allow-destructive`
+    allow_destructive: bool,
+    /// Overrides `exit_on_failure`, with `--continue-on-failure` or `--stop-on-first-failure`
+    failure_policy: FailurePolicy,
+    /// Values entered at the prompts of earlier runs, offered as quick picks
+    value_history: Option<ValueHistory>,
+    /// Reports the progress of the run, e.g. to the dashboard of `--tui`
+    event_sender: Option<EventSender>,
+    /// The programs run this time, with `--only` and `--from`
+    program_selection: ProgramSelection,
+    /// Values of variables shared by all chains, managed with `cchain preset`
+    variable_presets: Option<VariablePresets>,
+    /// The temporary directory of the run, `<<chain_tmp>>`
+    chain_tmp: Option<ScratchDirectory>,
+    /// Keep the temporary directory after the run, with `--keep-tmp`
+    keep_tmp: bool,
+    /// Pauses the chain between its programs, at the keys pressed
+    /// during `cchain run`
+    pause_control: Option<PauseControl>,
+    /// Answers given at the prompts of an earlier attempt of the chain,
+    /// keyed by the variable names, which are given again instead of
+    /// asking, with `--retry-chain`
+    repeated_answers: HashMap<String, String>,
 }
 
 impl Chain {
     pub fn from_file(path: &str) -> Result<Self, Error> {
-        let programs: Vec<Program> = serde_json::from_str(&std::fs::read_to_string(path)?)?;
+        // Chains loaded before from unchanged files are not parsed again
+        let mut chain: Chain = match get_cached_chain(path) {
+            Some((chain, warnings)) => {
+                for warning in &warnings {
+                    display_message(Level::Warn, warning);
+                }
+                chain
+            }
+            None => {
+                let version: ChainFileVersion = ChainFileVersion::from_path(Path::new(path));
+                record_parse(path);
+                let mut chain_file = ChainFile::from_path(Path::new(path))
+                    .map_err(|error| ChainError::Validation(format!("Chain {} is invalid: {}", path, error)))?;
+                chain_file.resolve_relative_paths(Path::new(path))?;
+                let mut warnings: Vec<String> = Vec::new();
+                let chain: Result<Chain, Error> = Self::build_from_chain_file(chain_file, path, &mut warnings);
+                for warning in &warnings {
+                    display_message(Level::Warn, warning);
+                }
+                let chain: Chain = chain?;
+                cache_chain(path, version, &chain, warnings);
+                chain
+            }
+        };
+        chain.load_adjacent_env(Path::new(path))?;
+
+        Ok(chain)
+    }
+
+    /// Build a chain from the JSON of a chain file, e.g. given on the
+    /// command line. The name is used to refer to the chain in messages.
+    /// Relative paths are left to resolve against the current directory,
+    /// and `extends` is not followed.
+    pub fn from_json_str(json: &str, name: &str) -> Result<Self, Error> {
+        get_chain_limits()
+            .check_size(name, json.len() as u64)
+            .map_err(|error| ChainError::Validation(error.to_string()))?;
+        let chain_file = ChainFile::from_str(json)
+            .map_err(|error| ChainError::Validation(format!("Chain {} is invalid: {}", name, error)))?;
+        if let Some(extends) = &chain_file.metadata.extends {
+            return Err(ChainError::Validation(format!(
+                "Chain {} cannot extend `{}`, as it is not read from a file",
+                name, extends
+            ))
+            .into());
+        }
+
+        Self::from_chain_file(chain_file, name)
+    }
+
+    /// Load the `.cchain.env` file in the directory of the chain file,
+    /// when the metadata opts in with `load_adjacent_env`. The path and
+    /// the names of the loaded values are reported, but not the values.
+    pub fn load_adjacent_env(&mut self, chain_path: &Path) -> Result<(), Error> {
+        if !self.metadata.load_adjacent_env {
+            return Ok(());
+        }
+
+        let path: PathBuf = chain_path.parent().unwrap_or(Path::new("")).join(ADJACENT_ENV_FILE_NAME);
+        if !path.is_file() {
+            display_message(
+                Level::Warn,
+                &format!("`load_adjacent_env` is set, but {} does not exist", path.display()),
+            );
+            return Ok(());
+        }
+
+        let values: Vec<(String, ProvidedValue)> = ProvidedValue::from_adjacent_env_file(&path)
+            .map_err(|error| ChainError::Validation(error.to_string()))?;
+        display_message(
+            Level::Logging,
+            &format!(
+                "Loaded {} from {}: {}",
+                if values.len() == 1 { "1 value".to_string() } else { format!("{} values", values.len()) },
+                path.display(),
+                values.iter().map(|(name, _)| format!("`{}`", name)).collect::<Vec<String>>().join(", ")
+            ),
+        );
+        self.adjacent_env_values = values.into_iter().collect();
+
+        Ok(())
+    }
+
+    /// Build a chain from a chain file that is already parsed.
+    /// The path is used to refer to the chain in messages.
+    pub fn from_chain_file(chain_file: ChainFile, path: &str) -> Result<Self, Error> {
+        let mut warnings: Vec<String> = Vec::new();
+        let chain: Result<Self, Error> = Self::build_from_chain_file(chain_file, path, &mut warnings);
+        for warning in &warnings {
+            display_message(Level::Warn, warning);
+        }
+
+        chain
+    }
+
+    /// Build a chain from a chain file, collecting the warnings about it
+    /// rather than showing them
+    fn build_from_chain_file(chain_file: ChainFile, path: &str, warnings: &mut Vec<String>) -> Result<Self, Error> {
+        // Unknown fields are likely features of a newer cchain
+        if !chain_file.unknown_fields.is_empty() {
+            if chain_file.metadata.strict {
+                return Err(ChainError::Validation(format!(
+                    "Unknown fields: {}. The chain may need a newer cchain than {}.",
+                    chain_file.unknown_fields.join(", "),
+                    clap::crate_version!()
+                )).into());
+            }
+
+            for unknown_field in &chain_file.unknown_fields {
+                warnings.push(format!("Unknown field {} is ignored", unknown_field));
+            }
+        }
+
+        warnings.extend(chain_file.deprecated_fields.iter().cloned());
+
+        // Generated chains are pointed out, so that they are reviewed
+        // before running
+        let is_modified_since_generation: bool = match &chain_file.metadata.generated_by {
+            Some(generated_by) => {
+                warnings.push(format!("Chain {} was generated by {}. Review it before running.", path, generated_by));
+                generated_by.is_modified(&chain_file.programs)?
+            }
+            None => false,
+        };
+
+        let metadata: ChainMetadata = chain_file.metadata;
+        let mut programs: Vec<Program> = chain_file.programs;
+        let mut program_origins: Vec<String> = chain_file.program_origins;
+        // Verification steps share the variables and the indices of the
+        // programs, after them
+        let mut verification_start: usize = programs.len();
+        programs.extend(chain_file.verify);
+
+        metadata.validate_timezone().map_err(|error| ChainError::Validation(error.to_string()))?;
+        // Placeholders with custom delimiters are rewritten with the
+        // default ones, which the rest of cchain scans for
+        if let Some(variable_delimiters) = &metadata.variable_delimiters {
+            variable_delimiters.validate().map_err(|error| ChainError::Validation(error.to_string()))?;
+            for program in &mut programs {
+                program.apply_variable_delimiters(variable_delimiters);
+            }
+        }
+        // Programs with a `matrix` become a program per combination of its
+        // values, before anything refers to the programs by their indices
+        let expanded_programs: ExpandedPrograms =
+            expand_matrix_programs(programs, &metadata.limits.unwrap_or_default())
+                .map_err(|error| ChainError::Validation(error.to_string()))?;
+        programs = expanded_programs.programs;
+        verification_start = expanded_programs.source_indices.iter().filter(|index| **index < verification_start).count();
+        if !program_origins.is_empty() {
+            program_origins = expanded_programs
+                .source_indices
+                .iter()
+                .filter_map(|index| program_origins.get(*index).cloned())
+                .collect();
+        }
+        // A stored variable that is not a plain placeholder would never
+        // connect to the placeholders using it
+        for (index, program) in programs.iter_mut().enumerate() {
+            match program.normalize_stdout_stored_to() {
+                Ok(Some(notice)) => warnings.push(format!("Program #{}: {}", index, notice)),
+                Ok(None) => (),
+                Err(error) => return Err(ChainError::Validation(format!("Program #{}: {}", index, error)).into()),
+            }
+        }
+        let pinned_environment: BTreeMap<String, String> = metadata.get_pinned_environment();
+
+        // Removing a variable that the chain pins is likely a mistake,
+        // though the program takes precedence
+        for (index, program) in programs.iter_mut().enumerate() {
+            for name in program.get_command_line().get_removed_environment_variables() {
+                if pinned_environment.contains_key(name) {
+                    warnings.push(format!(
+                        "Program #{} removes `{}` from its environment, which the chain sets with `timezone` or `locale` in the metadata",
+                        index, name
+                    ));
+                }
+            }
+        }
 
         let mut programs: Vec<Arc<Mutex<Program>>> = programs
             .into_iter()
-            .map(|item| Arc::new(Mutex::new(item)))
+            .map(|mut item| {
+                Self::apply_metadata_to_program(&mut item, &metadata);
+                Arc::new(Mutex::new(item))
+            })
             .collect();
 
+        // Number the anonymous prompts, so that each occurrence is its own variable
+        let mut anonymous_prompt_counter: usize = 0;
+        for program in &programs {
+            for (_, value) in program.lock().unwrap().get_command_line().get_function_fields_mut() {
+                *value = Variable::number_anonymous_prompts(value, &mut anonymous_prompt_counter);
+            }
+        }
+
         // check if there are variables being specified in the programs,
         // if so, register them in the chain.
         let mut variables: Vec<Arc<Mutex<Variable>>> = Vec::new();
+        let stored_variable_names: HashSet<String> = programs
+            .iter()
+            .filter_map(|program| program.lock().unwrap().get_awaitable_variable().clone())
+            .map(|stored_to| Variable::parse_await_variable(&stored_to, 0).get_variable_name().to_string())
+            .collect();
         for (index, program) in programs.iter_mut().enumerate() {
             if let Some(awaitable_variable) = program.lock().unwrap().get_awaitable_variable() {
                 variables.push(Arc::new(Mutex::new(Variable::parse_await_variable(
@@ -48,14 +565,16 @@ impl Chain {
                 ))));
             }
 
-            for argument in program.lock().unwrap().get_command_line().get_arguments() {
+            // The working directory and the environment overrides take
+            // variables as the arguments do
+            for value in program.lock().unwrap().get_placeholder_values() {
                 let variables_in_arguments: Vec<Arc<Mutex<Variable>>> =
-                    Variable::parse_variables_from_str(argument, index)?
+                    Variable::parse_variables_from_str(value, index)?
                         .into_iter()
                         .map(|variable| Arc::new(Mutex::new(variable)))
                         .collect();
 
-                if variables.len() != 0 {
+                if !variables.is_empty() {
                     for item in variables_in_arguments {
                         if !variables.iter().any(|v| {
                             v.lock().unwrap().get_variable_name()
@@ -68,37 +587,1303 @@ impl Chain {
                     variables.extend(variables_in_arguments);
                 }
             }
+
+            // Variables only in remedy command lines are asked for like
+            // the others, unless a program stores them
+            let mut remedy_variables: Vec<Variable> = Vec::new();
+            if let Some(remedy_command_line) = program.lock().unwrap().get_remedy_command_line() {
+                for value in remedy_command_line.get_placeholder_values() {
+                    remedy_variables.extend(Variable::parse_variables_from_str(value, index)?);
+                }
+            }
+            for variable in remedy_variables {
+                let is_registered: bool = variables
+                    .iter()
+                    .any(|item| item.lock().unwrap().get_variable_name() == variable.get_variable_name());
+                if !variable.is_anonymous() && !is_registered && !stored_variable_names.contains(variable.get_variable_name()) {
+                    variables.push(Arc::new(Mutex::new(variable)));
+                }
+            }
+        }
+
+        // Attach the declarations in the metadata to the variables
+        for (name, declaration) in &metadata.variables {
+            declaration
+                .validate()
+                .map_err(|error| ChainError::Validation(format!("Declaration of variable `{}` is invalid: {}", name, error)))?;
+
+            for variable in &variables {
+                let mut variable = variable.lock().unwrap();
+                if !variable.is_anonymous()
+                    && normalize_variable_name(variable.get_variable_name()) == normalize_variable_name(name)
+                {
+                    variable.set_declaration(declaration.clone());
+                }
+            }
+        }
+
+        Ok(Self::new_loaded(programs, verification_start, variables, path, metadata, is_modified_since_generation, program_origins))
+    }
+
+    /// Set what the metadata of the chain decides for a program, which is
+    /// not kept with the program: the escalation command and the pinned
+    /// environment
+    fn apply_metadata_to_program(program: &mut Program, metadata: &ChainMetadata) {
+        let pinned_environment: BTreeMap<String, String> = metadata.get_pinned_environment();
+        if let Some(escalation_command) = &metadata.escalation_command {
+            program.get_command_line().set_escalation_command(escalation_command.clone());
+        }
+        program.get_command_line().set_pinned_environment(pinned_environment.clone());
+        if let Some(remedy_command_line) = program.get_remedy_command_line() {
+            remedy_command_line.set_pinned_environment(pinned_environment);
+        }
+    }
+
+    /// Keep the chain as it was loaded, to be loaded again from the disk
+    /// without parsing
+    pub(crate) fn to_stored(
+        &self,
+        paths: Vec<PathBuf>,
+        content_hash: String,
+        limits: ChainLimits,
+        warnings: Vec<String>,
+    ) -> StoredChain {
+        StoredChain {
+            cchain_version: clap::crate_version!().to_string(),
+            paths,
+            content_hash,
+            limits,
+            programs: self.programs.iter().map(|program| program.lock().unwrap().clone()).collect(),
+            verification_start: self.verification_start,
+            variables: self.variables.iter().map(|variable| variable.lock().unwrap().clone()).collect(),
+            metadata: self.metadata.clone(),
+            is_modified_since_generation: self.is_modified_since_generation,
+            program_origins: self.program_origins.clone(),
+            warnings,
+        }
+    }
+
+    /// Load a chain kept by `to_stored`
+    pub(crate) fn from_stored(stored_chain: StoredChain, path: &str) -> Self {
+        let metadata: ChainMetadata = stored_chain.metadata;
+        let programs: Vec<Arc<Mutex<Program>>> = stored_chain
+            .programs
+            .into_iter()
+            .map(|mut program| {
+                Self::apply_metadata_to_program(&mut program, &metadata);
+                Arc::new(Mutex::new(program))
+            })
+            .collect();
+
+        Self::new_loaded(
+            programs,
+            stored_chain.verification_start,
+            stored_chain.variables.into_iter().map(|variable| Arc::new(Mutex::new(variable))).collect(),
+            path,
+            metadata,
+            stored_chain.is_modified_since_generation,
+            stored_chain.program_origins,
+        )
+    }
+
+    /// Build a chain as it is loaded, before anything is set for a run
+    fn new_loaded(
+        programs: Vec<Arc<Mutex<Program>>>,
+        verification_start: usize,
+        variables: Vec<Arc<Mutex<Variable>>>,
+        path: &str,
+        metadata: ChainMetadata,
+        is_modified_since_generation: bool,
+        program_origins: Vec<String>,
+    ) -> Self {
+        let program_templates: Vec<String> = programs.iter().map(|program| program.lock().unwrap().to_string()).collect();
+        Self {
+            programs,
+            variables,
+            failed_program_indices: RefCell::new(Vec::new()),
+            cancelled_program_indices: RefCell::new(Vec::new()),
+            verification_start,
+            failed_verification_indices: RefCell::new(Vec::new()),
+            path: path.to_string(),
+            metadata,
+            services: Vec::new(),
+            unexpectedly_exited_services: Vec::new(),
+            provided_values: HashMap::new(),
+            adjacent_env_values: HashMap::new(),
+            interactor: SharedInteractor::default(),
+            require_declarations: false,
+            lint_separated_duplicates: false,
+            skip_review: false,
+            confirm_variables: false,
+            confirmed_values: HashMap::new(),
+            is_modified_since_generation,
+            program_origins,
+            program_templates,
+            started_program_indices: RefCell::new(HashSet::new()),
+            debug_shell_options: None,
+            allow_destructive: false,
+            failure_policy: FailurePolicy::default(),
+            value_history: None,
+            event_sender: None,
+            program_selection: ProgramSelection::default(),
+            variable_presets: None,
+            chain_tmp: None,
+            keep_tmp: false,
+            pause_control: None,
+            repeated_answers: HashMap::new(),
+        }
+    }
+
+    /// Copy the chain as it was loaded, with programs and variables of
+    /// its own, e.g. for loading it again without parsing. Anything set
+    /// for a run is not copied.
+    pub(crate) fn clone_loaded(&self) -> Self {
+        Self::new_loaded(
+            self.programs.iter().map(|program| Arc::new(Mutex::new(program.lock().unwrap().clone()))).collect(),
+            self.verification_start,
+            self.variables.iter().map(|variable| Arc::new(Mutex::new(variable.lock().unwrap().clone()))).collect(),
+            &self.path,
+            self.metadata.clone(),
+            self.is_modified_since_generation,
+            self.program_origins.clone(),
+        )
+    }
+
+    /// Start the chain over for another attempt, with the programs and
+    /// variables of a copy taken by `clone_loaded` before the first one.
+    /// The answers to the prompts, and the confirmation of destructive
+    /// programs, are given again rather than asked for.
+    pub(crate) fn restart_from(&mut self, loaded: &Chain) {
+        for variable in &self.variables {
+            let variable = variable.lock().unwrap();
+            if let Some(provenance) = variable.get_provenance() {
+                if *provenance.get_source() == VariableSource::Prompt {
+                    self.repeated_answers.insert(variable.get_variable_name().to_string(), provenance.value);
+                }
+            }
+        }
+
+        let restarted: Chain = loaded.clone_loaded();
+        self.programs = restarted.programs;
+        self.variables = restarted.variables;
+        self.failed_program_indices.borrow_mut().clear();
+        self.cancelled_program_indices.borrow_mut().clear();
+        self.failed_verification_indices.borrow_mut().clear();
+        self.started_program_indices.borrow_mut().clear();
+        self.services.clear();
+        self.unexpectedly_exited_services.clear();
+        self.chain_tmp = None;
+        self.skip_review = true;
+        self.confirm_variables = false;
+        self.allow_destructive = true;
+        if let Some(event_sender) = self.event_sender.clone() {
+            self.set_event_sender(event_sender);
+        }
+    }
+
+    pub fn get_programs(&self) -> &Vec<Arc<Mutex<Program>>> {
+        &self.programs
+    }
+
+    /// Get the variables registered from the programs
+    pub fn get_variables(&self) -> &Vec<Arc<Mutex<Variable>>> {
+        &self.variables
+    }
+
+    /// Check whether the program at the index is a step of the `verify`
+    /// section rather than a program
+    pub fn is_verification_step(&self, index: usize) -> bool {
+        index >= self.verification_start
+    }
+
+    /// Get the indices of the steps of the `verify` section
+    pub fn get_verification_indices(&self) -> std::ops::Range<usize> {
+        self.verification_start..self.programs.len()
+    }
+
+    /// Name the program in messages, e.g. `Program #2`, or `Verification
+    /// step #4` for the steps of the `verify` section
+    pub fn get_program_label(&self, index: usize) -> String {
+        if self.is_verification_step(index) {
+            format!("Verification step #{}", index)
+        } else {
+            format!("Program #{}", index)
+        }
+    }
+
+    /// Check whether the program runs this time. The verification steps
+    /// run whatever `--from` and `--only` select.
+    fn is_scheduled(&self, index: usize) -> bool {
+        self.is_verification_step(index) || self.program_selection.is_selected(index)
+    }
+
+    /// Rewrite the snapshot files of the assertions with the outputs,
+    /// instead of comparing with them
+    pub fn set_update_snapshots(&mut self, update_snapshots: bool) {
+        for program in &self.programs {
+            program.lock().unwrap().set_update_snapshots(update_snapshots);
+        }
+    }
+
+    /// Sample the disk, I/O and CPU usage of the processes of every
+    /// program, with `--profile-io`
+    pub fn set_profile_io(&mut self, profile_io: bool) {
+        for program in &self.programs {
+            program.lock().unwrap().set_profile_io(profile_io);
+        }
+    }
+
+    /// Show the full output of every program, whatever their
+    /// `display_output`, e.g. when debugging
+    pub fn set_show_all_output(&mut self, show_all_output: bool) {
+        for program in &self.programs {
+            program.lock().unwrap().set_show_all_output(show_all_output);
+        }
+    }
+
+    /// Run only some of the programs. The others keep their indices, and
+    /// the variables only they use are not asked for.
+    pub fn set_program_selection(&mut self, program_selection: ProgramSelection) -> Result<(), Error> {
+        program_selection
+            .validate(self.verification_start)
+            .map_err(|error| ChainError::Validation(error.to_string()))?;
+        self.program_selection = program_selection;
+
+        Ok(())
+    }
+
+    /// Get the indices of the programs that run this time, in order
+    pub fn get_scheduled_program_indices(&self) -> Vec<usize> {
+        (0..self.programs.len()).filter(|index| self.is_scheduled(*index)).collect()
+    }
+
+    /// Get the names of the variables used by the programs that run this
+    /// time, in their arguments or in their remedy command lines
+    fn get_scheduled_variable_names(&self) -> HashSet<String> {
+        self.get_scheduled_program_indices()
+            .into_iter()
+            .flat_map(|index| self.get_program_variable_names(index))
+            .collect()
+    }
+
+    /// Get the names of the variables used by the command line of a
+    /// program and by its remedy command line
+    fn get_program_variable_names(&self, index: usize) -> HashSet<String> {
+        let mut variable_names: HashSet<String> = HashSet::new();
+        let mut program = self.programs[index].lock().unwrap();
+        let mut values: Vec<String> =
+            program.get_placeholder_values().into_iter().map(str::to_string).collect();
+        if let Some(remedy_command_line) = program.get_remedy_command_line() {
+            values.extend(remedy_command_line.get_placeholder_values().into_iter().map(str::to_string));
+        }
+
+        for value in values {
+            // Arguments that fail to parse are reported when the program runs
+            for variable in Variable::parse_variables_from_str(&value, index).unwrap_or_default() {
+                variable_names.insert(variable.get_variable_name().to_string());
+            }
+        }
+
+        variable_names
+    }
+
+    /// Report the progress of the run as events, from the start of the
+    /// chain through the starts, outputs and ends of its programs
+    pub fn set_event_sender(&mut self, event_sender: EventSender) {
+        for (index, program) in self.programs.iter().enumerate() {
+            program.lock().unwrap().get_command_line().set_event_sender(event_sender.clone(), index);
+        }
+        self.event_sender = Some(event_sender);
+    }
+
+    fn send_event(&self, event: ChainEvent) {
+        if let Some(event_sender) = &self.event_sender {
+            event_sender.send(event);
+        }
+    }
+
+    /// Wait before starting each program while the control is paused
+    pub fn set_pause_control(&mut self, pause_control: Option<PauseControl>) {
+        self.pause_control = pause_control;
+    }
+
+    /// Ask the questions of the chain and its programs with the interactor
+    pub fn set_interactor(&mut self, interactor: SharedInteractor) {
+        for program in &self.programs {
+            program.lock().unwrap().set_interactor(interactor.clone());
+        }
+        self.interactor = interactor;
+    }
+
+    pub fn get_metadata(&self) -> &ChainMetadata {
+        &self.metadata
+    }
+
+    /// Get the files that the programs came from, which is empty
+    /// unless the chain extends other chains
+    pub fn get_program_origins(&self) -> &Vec<String> {
+        &self.program_origins
+    }
+
+    /// Offer a debug shell when a program fails for good, in the working
+    /// directory of the program and with the variables of the chain exported
+    pub fn set_debug_shell_options(&mut self, debug_shell_options: Option<DebugShellOptions>) {
+        self.debug_shell_options = debug_shell_options;
+    }
+
+    /// Run the programs that delete or mutate things without asking
+    pub fn set_allow_destructive(&mut self, allow_destructive: bool) {
+        self.allow_destructive = allow_destructive;
+    }
+
+    pub fn is_allowing_destructive(&self) -> bool {
+        self.allow_destructive
+    }
+
+    /// Handle the failures of all programs by a policy, instead of their
+    /// `exit_on_failure`
+    pub fn set_failure_policy(&mut self, failure_policy: FailurePolicy) {
+        self.failure_policy = failure_policy;
+    }
+
+    pub fn get_failure_policy(&self) -> FailurePolicy {
+        self.failure_policy
+    }
+
+    /// Get the programs that delete or mutate things, with the reasons
+    pub fn get_destructive_programs(&self) -> Vec<(usize, String)> {
+        self.programs
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.is_scheduled(*index))
+            .filter_map(|(index, program)| program.lock().unwrap().get_destructive_reason().map(|reason| (index, reason)))
+            .collect()
+    }
+
+    /// Ask before running a chain with programs that delete or mutate
+    /// things, unless `--allow-destructive` is given. The answer is kept,
+    /// so that the chain does not ask again when it runs.
+    pub fn confirm_destructive_programs(&mut self) -> Result<(), Error> {
+        let destructive_programs: Vec<(usize, String)> = self.get_destructive_programs();
+        if self.allow_destructive || destructive_programs.is_empty() {
+            return Ok(());
+        }
+
+        display_message(Level::Warn, "The chain has programs that may delete or mutate things:");
+        for (index, reason) in &destructive_programs {
+            let mut program = self.programs[*index].lock().unwrap();
+            display_tree_message(1, &format!("Program #{}: {} ({})", index, program.get_command_line(), reason));
+        }
+        let prompt: &str = "Run the chain anyway? Pass `--allow-destructive` to skip this question [y/n]:";
+        if !self.interactor.is_interactive() {
+            return Err(ChainError::InputRequired { prompt: prompt.to_string() }.into());
+        }
+        let answer: String = self.interactor.prompt(prompt)?;
+        if !matches!(answer.to_lowercase().as_str(), "y" | "yes") {
+            return Err(ChainError::Validation("The chain is not run, as its destructive programs are not confirmed".to_string()).into());
+        }
+        self.allow_destructive = true;
+
+        Ok(())
+    }
+
+    /// Keep the temporary directory of the run, `<<chain_tmp>>`, instead
+    /// of removing it when the chain finishes
+    pub fn set_keep_tmp(&mut self, keep_tmp: bool) {
+        self.keep_tmp = keep_tmp;
+    }
+
+    /// Get the temporary directory of the run, while it runs, or after it
+    /// when the directory is kept
+    pub fn get_chain_tmp(&self) -> Option<&Path> {
+        self.chain_tmp.as_ref().map(|chain_tmp| chain_tmp.get_path())
+    }
+
+    /// Skip the review of the answers to the startup prompts, e.g. for `--yes`
+    pub fn set_skip_review(&mut self, skip_review: bool) {
+        self.skip_review = skip_review;
+    }
+
+    /// Before the chain runs, show the value each source supplies for
+    /// the startup variables and which one wins, and let the user pick
+    /// another source or type a new value. Ignored without a user to ask.
+    pub fn set_confirm_variables(&mut self, confirm_variables: bool) {
+        self.confirm_variables = confirm_variables;
+    }
+
+    /// Fail the syntax check when a placeholder has no declaration in
+    /// the metadata
+    pub fn set_require_declarations(&mut self, require_declarations: bool) {
+        self.require_declarations = require_declarations;
+    }
+
+    /// Also report the programs identical to an earlier program that is
+    /// not right before them in the lint, e.g. with `--separated-duplicates`
+    pub fn set_lint_separated_duplicates(&mut self, lint_separated_duplicates: bool) {
+        self.lint_separated_duplicates = lint_separated_duplicates;
+    }
+
+    /// Get the names of the declared variables that no program uses,
+    /// which are likely typos
+    pub fn get_unused_declarations(&self) -> Vec<String> {
+        self.metadata
+            .variables
+            .keys()
+            .filter(|name| {
+                !self.variables.iter().any(|variable| {
+                    normalize_variable_name(variable.lock().unwrap().get_variable_name()) == normalize_variable_name(name)
+                })
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Get the names of the variables in the placeholders that are not
+    /// declared in the metadata. Anonymous prompts and the variables
+    /// storing outputs are not included.
+    pub fn get_undeclared_variables(&self) -> Vec<String> {
+        self.variables
+            .iter()
+            .filter_map(|variable| {
+                let variable = variable.lock().unwrap();
+                if variable.is_anonymous()
+                    || variable.is_automatic()
+                    || variable.get_declaration().is_some()
+                    || matches!(variable.get_initialization_time(), VariableInitializationTime::Await(_))
+                {
+                    return None;
+                }
+                Some(variable.get_variable_name().to_string())
+            })
+            .collect()
+    }
+
+    /// Get the variables that `--var` can provide, once each in the order
+    /// they appear, for the completion of `cchain run --var`. Anonymous
+    /// prompts and the variables storing outputs are not included.
+    pub fn get_variable_completions(&self) -> Vec<VariableCompletion> {
+        let mut completions: Vec<VariableCompletion> = Vec::new();
+        for variable in &self.variables {
+            let variable = variable.lock().unwrap();
+            let initialization: &str = match variable.get_initialization_time() {
+                _ if variable.is_anonymous() || variable.is_automatic() => continue,
+                VariableInitializationTime::Await(_) => continue,
+                VariableInitializationTime::OnChainStartup(_) => "startup",
+                VariableInitializationTime::OnProgramExecution(_) => "on program execution",
+            };
+            let name: &str = variable.get_variable_name();
+            if completions.iter().any(|completion| completion.name == name) {
+                continue;
+            }
+            completions.push(VariableCompletion {
+                name: name.to_string(),
+                initialization: initialization.to_string(),
+                description: variable.get_declaration().and_then(|declaration| declaration.description.clone()),
+            });
+        }
+
+        completions
+    }
+
+    /// Get the variables that are prompted for, with their values in the
+    /// value history and their presets, as set with `set_value_history`
+    /// and `set_variable_presets`
+    pub fn get_remembered_variables(&self) -> Vec<RememberedVariable> {
+        let mut remembered_variables: Vec<RememberedVariable> = Vec::new();
+        for completion in self.get_variable_completions() {
+            let variable = self
+                .variables
+                .iter()
+                .find(|variable| variable.lock().unwrap().get_variable_name() == completion.name)
+                .unwrap()
+                .lock()
+                .unwrap();
+            let declaration: VariableDeclaration = variable.get_declaration().cloned().unwrap_or_default();
+            let latest_entry = self
+                .value_history
+                .as_ref()
+                .and_then(|value_history| value_history.get_entries(&self.path, &completion.name).first().cloned());
+            let mask = |value: String| if declaration.secret { SECRET_MASK.to_string() } else { value };
+            remembered_variables.push(RememberedVariable {
+                name: completion.name,
+                kind: declaration.variable_type,
+                description: completion.description,
+                secret: declaration.secret,
+                cached_value: latest_entry.as_ref().map(|entry| mask(entry.value.clone())),
+                preset: self.get_preset_value(&variable).map(|preset| mask(preset.value)),
+                last_used: latest_entry.and_then(|entry| entry.used_at),
+            });
+        }
+
+        remembered_variables
+    }
+
+    /// Check that the chain prompts for a variable of the name, e.g.
+    /// before keeping a value for it
+    pub fn check_variable_name(&self, name: &str) -> Result<(), Error> {
+        let names: Vec<String> = self.get_variable_completions().into_iter().map(|completion| completion.name).collect();
+        if names.iter().any(|variable_name| variable_name == name) {
+            return Ok(());
+        }
+
+        let candidates: Vec<&str> = names.iter().map(String::as_str).collect();
+        let suggestion: String = match get_closest_fields(name, &candidates).first() {
+            Some(closest) => format!(". Did you mean `{}`?", closest),
+            None if names.is_empty() => ". It prompts for no variables".to_string(),
+            None => format!(". Its variables are {}", candidates.iter().map(|name| format!("`{}`", name)).collect::<Vec<String>>().join(", ")),
+        };
+        Err(anyhow!("Chain {} has no variable `{}`{}", self.path, name, suggestion))
+    }
+
+    /// Use the values for the variables instead of prompting for them.
+    /// Values of variables that the chain does not use are reported by
+    /// `check_supplied_values`.
+    pub fn set_provided_values(&mut self, provided_values: HashMap<String, ProvidedValue>) {
+        for name in provided_values.keys() {
+            if name == CHAIN_TMP_VARIABLE_NAME {
+                display_message(
+                    Level::Warn,
+                    &format!("Variable `{}` is set by cchain, so the provided value is ignored", name),
+                );
+                continue;
+            }
+        }
+
+        self.provided_values = provided_values;
+    }
+
+    /// Cross-check the values supplied from outside the chain, with
+    /// `--var`, `--var-file` or as presets, against its variables.
+    /// Presets are shared by all chains, so only those whose names are
+    /// close to a variable without a preset are reported.
+    pub fn reconcile_supplied_values(&self) -> Vec<SuppliedValueFinding> {
+        let mut variable_names: Vec<String> = Vec::new();
+        for variable in &self.variables {
+            let variable = variable.lock().unwrap();
+            if !variable.is_anonymous() && !variable_names.iter().any(|name| name == variable.get_variable_name()) {
+                variable_names.push(variable.get_variable_name().to_string());
+            }
+        }
+        let mut producers: Vec<(usize, String)> = Vec::new();
+        for (index, program) in self.programs.iter().enumerate() {
+            let stored_to: Option<String> = program.lock().unwrap().get_awaitable_variable().clone();
+            for variable in Variable::parse_variables_from_str(&stored_to.unwrap_or_default(), index).unwrap_or_default() {
+                producers.push((index, variable.get_variable_name().to_string()));
+            }
+        }
+
+        let mut findings: Vec<SuppliedValueFinding> = Vec::new();
+        let mut provided_values: Vec<(&String, &ProvidedValue)> = self.provided_values.iter().collect();
+        provided_values.sort_by_key(|(name, _)| *name);
+        for (name, provided_value) in provided_values {
+            if name == CHAIN_TMP_VARIABLE_NAME {
+                continue;
+            }
+            if !variable_names.contains(name) {
+                let candidates: Vec<&str> = variable_names.iter().map(String::as_str).collect();
+                findings.push(SuppliedValueFinding::Unknown {
+                    name: name.clone(),
+                    source: provided_value.source.clone(),
+                    suggestions: get_closest_fields(name, &candidates).into_iter().map(String::from).collect(),
+                });
+                continue;
+            }
+
+            // The value is only seen by the programs up to the first one
+            // storing its output in the variable
+            let first_producer: Option<usize> =
+                producers.iter().filter(|(_, stored_to)| stored_to == name).map(|(index, _)| *index).min();
+            if let Some(producer) = first_producer {
+                if !(0..=producer).any(|index| self.get_program_variable_names(index).contains(name)) {
+                    findings.push(SuppliedValueFinding::Overwritten {
+                        name: name.clone(),
+                        source: provided_value.source.clone(),
+                        producer,
+                    });
+                    continue;
+                }
+            }
+
+            let is_secret: bool = self.variables.iter().any(|variable| {
+                let variable = variable.lock().unwrap();
+                variable.get_variable_name() == name
+                    && variable.get_declaration().is_some_and(|declaration| declaration.secret)
+            });
+            if !is_secret && looks_like_secret_name(name) {
+                findings.push(SuppliedValueFinding::UndeclaredSecret {
+                    name: name.clone(),
+                    source: provided_value.source.clone(),
+                });
+            }
+        }
+
+        if let Some(variable_presets) = &self.variable_presets {
+            for preset_name in variable_presets.get_values().keys() {
+                let is_matching: bool =
+                    variable_names.iter().any(|name| normalize_variable_name(name) == *preset_name);
+                if is_matching {
+                    continue;
+                }
+                let candidates: Vec<&str> = variable_names
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|name| variable_presets.get_value(name).is_none())
+                    .collect();
+                let suggestions: Vec<String> =
+                    get_closest_fields(preset_name, &candidates).into_iter().map(String::from).collect();
+                if !suggestions.is_empty() {
+                    findings.push(SuppliedValueFinding::Unknown {
+                        name: preset_name.clone(),
+                        source: "preset".to_string(),
+                        suggestions,
+                    });
+                }
+            }
+        }
+
+        findings
+    }
+
+    /// Show the findings of `reconcile_supplied_values` before the chain
+    /// runs. Without a user to notice the warnings, e.g. in CI, values of
+    /// unknown variables fail the run instead.
+    pub fn check_supplied_values(&self) -> Result<(), Error> {
+        let findings: Vec<SuppliedValueFinding> = self.reconcile_supplied_values();
+        let is_interactive: bool = self.interactor.is_interactive();
+        let mut unknown_names: Vec<String> = Vec::new();
+        for finding in &findings {
+            match finding {
+                SuppliedValueFinding::Unknown { name, .. } if !is_interactive => {
+                    display_message(Level::Error, &finding.to_string());
+                    unknown_names.push(format!("`{}`", name));
+                }
+                SuppliedValueFinding::UndeclaredSecret { .. } => display_message(Level::Logging, &finding.to_string()),
+                _ => display_message(Level::Warn, &finding.to_string()),
+            }
+        }
+        if !unknown_names.is_empty() {
+            return Err(anyhow!(
+                "Values are supplied for {}, which the chain does not use",
+                unknown_names.join(", ")
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Get the variables that the chain would prompt for when it runs,
+    /// before it starts or on the way, given the provided values
+    fn get_pending_variables(&self) -> Vec<Arc<Mutex<Variable>>> {
+        let scheduled_variable_names: HashSet<String> = self.get_scheduled_variable_names();
+        let mut names: HashSet<String> = HashSet::new();
+        self.variables
+            .iter()
+            .filter(|variable| {
+                let variable = variable.lock().unwrap();
+                !matches!(variable.get_initialization_time(), VariableInitializationTime::Await(_))
+                    && !variable.is_automatic()
+                    && variable.get_value().is_err()
+                    && self.get_winning_value(&variable).is_none()
+                    && scheduled_variable_names.contains(variable.get_variable_name())
+                    && names.insert(variable.get_variable_name().to_string())
+            })
+            .cloned()
+            .collect()
+    }
+
+    /// Get the names of the variables that the chain would prompt for
+    /// when it runs. A chain without any runs unattended.
+    pub fn get_pending_inputs(&self) -> Vec<String> {
+        self.get_pending_variables()
+            .iter()
+            .map(|variable| variable.lock().unwrap().get_variable_name().to_string())
+            .collect()
+    }
+
+    /// Get the variables that the chain would prompt for when it runs,
+    /// telling which are only asked for once a program using them starts
+    pub fn get_pending_input_listing(&self) -> Vec<PendingInput> {
+        self.get_pending_variables()
+            .iter()
+            .map(|variable| {
+                let variable = variable.lock().unwrap();
+                let conditional: bool = match variable.get_initialization_time() {
+                    VariableInitializationTime::OnChainStartup(_) => self.metadata.lazy_prompts,
+                    _ => true,
+                };
+                PendingInput { name: variable.get_variable_name().to_string(), conditional }
+            })
+            .collect()
+    }
+
+    /// Ask for all the values that the chain would prompt for, together
+    /// like the startup prompts, so that it runs without stopping for input.
+    /// The answers are used like provided values.
+    pub fn collect_pending_inputs(&mut self) -> Result<(), Error> {
+        let pending_variables: Vec<Arc<Mutex<Variable>>> = self.get_pending_variables();
+        let inputs: Vec<String> = self.request_startup_variable_values(&pending_variables)?;
+        for (variable, input) in pending_variables.iter().zip(inputs) {
+            self.provided_values.insert(
+                variable.lock().unwrap().get_variable_name().to_string(),
+                ProvidedValue { value: input, source: "a prompt before the batch".to_string() },
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Use the presets for the default answers at the prompts, after the
+    /// values loaded from `.cchain.env`
+    pub fn set_variable_presets(&mut self, variable_presets: VariablePresets) {
+        self.variable_presets = Some(variable_presets);
+    }
+
+    /// Get the preset of a variable. Secrets, anonymous prompts and
+    /// variables declared with `no_preset` have none.
+    fn get_preset_value(&self, variable: &Variable) -> Option<ProvidedValue> {
+        let is_excluded: bool = variable.is_anonymous()
+            || variable
+                .get_declaration()
+                .is_some_and(|declaration| declaration.secret || declaration.no_preset);
+        if is_excluded {
+            return None;
+        }
+
+        let value: &str = self.variable_presets.as_ref()?.get_value(variable.get_variable_name())?;
+        Some(ProvidedValue { value: value.to_string(), source: "preset".to_string() })
+    }
+
+    /// Get the default answer at the prompts of a variable: its value
+    /// loaded from `.cchain.env`, or else its preset
+    fn get_default_value(&self, variable: &Variable) -> Option<ProvidedValue> {
+        self.adjacent_env_values
+            .get(variable.get_variable_name())
+            .cloned()
+            .or_else(|| self.get_preset_value(variable))
+    }
+
+    /// Get the last value entered at the prompt of a variable in an
+    /// earlier run. Secrets and anonymous prompts have none.
+    fn get_remembered_value(&self, variable: &Variable) -> Option<ProvidedValue> {
+        let is_secret: bool = variable.get_declaration().is_some_and(|declaration| declaration.secret);
+        if is_secret || variable.is_anonymous() {
+            return None;
+        }
+
+        let value: String = self
+            .value_history
+            .as_ref()?
+            .get_values(&self.path, variable.get_variable_name())
+            .into_iter()
+            .next()?;
+        Some(ProvidedValue { value, source: "remembered value".to_string() })
+    }
+
+    /// Get the value each source supplies for a variable, in the order
+    /// of precedence
+    pub fn get_value_candidates(&self, variable: &Variable) -> Vec<ValueCandidate> {
+        let name: &str = variable.get_variable_name();
+        ValueOrigin::ALL
+            .iter()
+            .filter_map(|origin| {
+                let value: Option<ProvidedValue> = match origin {
+                    ValueOrigin::Provided => self.provided_values.get(name).cloned(),
+                    ValueOrigin::AdjacentEnv => self.adjacent_env_values.get(name).cloned(),
+                    ValueOrigin::Preset => self.get_preset_value(variable),
+                    ValueOrigin::Remembered => self.get_remembered_value(variable),
+                };
+                value.map(|value| ValueCandidate { origin: *origin, value })
+            })
+            .collect()
+    }
+
+    /// Get the value a variable gets without being prompted for, if any
+    fn get_winning_value(&self, variable: &Variable) -> Option<ProvidedValue> {
+        let candidates: Vec<ValueCandidate> = self.get_value_candidates(variable);
+        select_winning_value(&candidates, !self.interactor.is_interactive()).map(|candidate| candidate.value.clone())
+    }
+
+    /// Get the value of a variable from the provided values if any,
+    /// or from the user otherwise, along with where it came from.
+    /// Values from `.cchain.env` come last.
+    fn obtain_variable_value(&self, variable: &Variable) -> Result<(String, VariableSource), Error> {
+        let provided_value: Option<ProvidedValue> = self.get_winning_value(variable);
+        match provided_value {
+            Some(provided_value) => {
+                variable.validate_value(&provided_value.value).map_err(|error| {
+                    ChainError::Validation(format!("{} (from {})", error, provided_value.source))
+                })?;
+                Ok((
+                    provided_value.value.clone(),
+                    VariableSource::Provided { source: provided_value.source.clone() },
+                ))
+            }
+            None => Ok((self.request_variable_value(variable)?, VariableSource::Prompt)),
+        }
+    }
+
+    /// Offer the values entered at the prompts of earlier runs as quick picks
+    pub fn set_value_history(&mut self, value_history: ValueHistory) {
+        self.value_history = Some(value_history);
+    }
+
+    pub fn get_value_history(&self) -> Option<&ValueHistory> {
+        self.value_history.as_ref()
+    }
+
+    /// Add the values entered at the prompts of this run to the value
+    /// history, and save it. Only call this after the chain succeeded,
+    /// so that mistyped values of failed runs are not offered again.
+    pub fn record_value_history(&mut self) -> Result<(), Error> {
+        let value_history: &mut ValueHistory = match &mut self.value_history {
+            Some(value_history) => value_history,
+            None => return Ok(()),
+        };
+
+        for variable in &self.variables {
+            let variable = variable.lock().unwrap();
+            let is_secret: bool = variable.get_declaration().is_some_and(|declaration| declaration.secret);
+            if is_secret || variable.is_anonymous() {
+                continue;
+            }
+            if let Some(provenance) = variable.get_provenance() {
+                if provenance.get_source() == &VariableSource::Prompt {
+                    value_history.record(&self.path, variable.get_variable_name(), &provenance.value);
+                }
+            }
+        }
+
+        value_history.save()
+    }
+
+    /// Get the values of the variables that have one, and where they came from
+    pub fn get_variable_provenances(&self) -> Vec<VariableProvenance> {
+        self.variables
+            .iter()
+            .filter_map(|variable| variable.lock().unwrap().get_provenance())
+            .collect()
+    }
+
+    /// Get the program at the index as written, with the placeholders of
+    /// the variables rather than their values
+    pub fn get_program_template(&self, index: usize) -> Option<&str> {
+        self.program_templates.get(index).map(String::as_str)
+    }
+
+    /// Get the values of the secrets, declared or named like one
+    fn get_secret_values(&self) -> Vec<String> {
+        self.variables
+            .iter()
+            .filter_map(|variable| {
+                let variable = variable.lock().unwrap();
+                let provenance: VariableProvenance = variable.get_provenance()?;
+                let is_secret: bool = variable.get_declaration().is_some_and(|declaration| declaration.secret)
+                    || looks_like_secret_name(&provenance.name);
+                (is_secret && !provenance.value.is_empty()).then_some(provenance.value)
+            })
+            .collect()
+    }
+
+    /// Replace the values of the secrets in a text, e.g. the output of a
+    /// program that is kept after the run
+    pub fn mask_secret_values(&self, text: &str) -> String {
+        self.get_secret_values()
+            .iter()
+            .fold(text.to_string(), |text, secret| text.replace(secret.as_str(), SECRET_MASK))
+    }
+
+    /// Get the provenances of the variables, to be shown. Values of
+    /// secrets, declared or named like one, are masked, and so are the
+    /// secrets that other values contain.
+    pub fn get_masked_variable_provenances(&self) -> Vec<VariableProvenance> {
+        let secret_values: Vec<String> = self.get_secret_values();
+        self.variables
+            .iter()
+            .filter_map(|variable| {
+                let variable = variable.lock().unwrap();
+                let mut provenance: VariableProvenance = variable.get_provenance()?;
+                let is_secret: bool = variable.get_declaration().is_some_and(|declaration| declaration.secret)
+                    || looks_like_secret_name(&provenance.name);
+                provenance.value = match is_secret {
+                    true => SECRET_MASK.to_string(),
+                    false => secret_values
+                        .iter()
+                        .fold(provenance.value, |value, secret| value.replace(secret.as_str(), SECRET_MASK)),
+                };
+                Some(provenance)
+            })
+            .collect()
+    }
+
+    /// Get every variable of the chain with its current value, for the
+    /// `debug_variables` step. Values of secrets are masked.
+    pub fn get_variable_snapshots(&self) -> Vec<VariableSnapshot> {
+        self.variables
+            .iter()
+            .map(|variable| {
+                let variable = variable.lock().unwrap();
+                let name: String = match variable.get_provenance() {
+                    Some(provenance) => provenance.name,
+                    None if variable.is_anonymous() => format!("?{}", variable.get_human_readable_name()),
+                    None => variable.get_variable_name().to_string(),
+                };
+                let initialization: String = match variable.get_initialization_time() {
+                    _ if variable.is_anonymous() => "prompt".to_string(),
+                    VariableInitializationTime::OnChainStartup(_) => "startup".to_string(),
+                    VariableInitializationTime::OnProgramExecution(_) => "on program execution".to_string(),
+                    VariableInitializationTime::Await(_) => "program output".to_string(),
+                };
+                let program_index: Option<usize> = match (variable.get_sources().last(), variable.get_initialization_time()) {
+                    (Some(VariableSource::ProgramOutput { program_index }), _) => Some(*program_index),
+                    (_, VariableInitializationTime::Await(lifetime)) => Some(lifetime.get_initialization_program_index()),
+                    _ => None,
+                };
+                let is_secret: bool = variable.get_declaration().is_some_and(|declaration| declaration.secret);
+                let value: Option<String> = variable.get_value().ok().map(|value| {
+                    if is_secret {
+                        SECRET_MASK.to_string()
+                    } else {
+                        truncate_value(&value, DEBUG_VALUE_WIDTH)
+                    }
+                });
+
+                VariableSnapshot { name, initialization, has_value: value.is_some(), value, program_index }
+            })
+            .collect()
+    }
+
+    /// Store the output of a program in its `stdout_stored_to` variable
+    fn store_program_output(&self, variable_name: &str, value: String, program_index: usize) {
+        for variable in &self.variables {
+            let mut variable = variable.lock().unwrap();
+            if variable.get_raw_variable_name() == variable_name {
+                variable.register_value(value, VariableSource::ProgramOutput { program_index });
+                break;
+            }
+        }
+    }
+
+    /// Collect the lint messages of the programs and their remedy
+    /// command lines, e.g. arguments with ambiguous shell metacharacters
+    pub fn lint(&self) -> Vec<String> {
+        let mut messages: Vec<String> = Vec::new();
+        for (index, program) in self.programs.iter().enumerate() {
+            let mut program = program.lock().unwrap();
+            for message in program.get_command_line().lint() {
+                messages.push(format!("Program #{}: {}", index, message));
+            }
+            if let Some(remedy_command_line) = program.get_remedy_command_line() {
+                for message in remedy_command_line.lint() {
+                    messages.push(format!("Program #{} remedy command: {}", index, message));
+                }
+            }
+            match program.get_effects() {
+                Some(effects) => {
+                    for effect in effects.iter().filter(|effect| !KNOWN_EFFECTS.contains(&effect.as_str())) {
+                        messages.push(format!(
+                            "Program #{}: effect `{}` is not one of {}",
+                            index,
+                            effect,
+                            KNOWN_EFFECTS.join(", ")
+                        ));
+                    }
+                }
+                None => {
+                    if let Some(pattern) = find_destructive_pattern(program.get_command_line()) {
+                        messages.push(format!(
+                            "Program #{}: `{}` deletes or mutates things, but the program has no `effects`, e.g. `\"effects\": [\"deletes-files\"]`",
+                            index, pattern
+                        ));
+                    }
+                }
+            }
+        }
+        let fingerprints: Vec<String> = self.programs[..self.verification_start]
+            .iter()
+            .map(|program| program.lock().unwrap().execution_fingerprint())
+            .collect();
+        for duplicate in find_duplicate_programs(&fingerprints) {
+            if duplicate.is_consecutive() {
+                messages.push(format!(
+                    "Program #{} is identical to program #{} and runs again right after it. Remove it with `cchain fmt --fix --dedupe-consecutive` unless that is intended",
+                    duplicate.index, duplicate.original_index
+                ));
+            } else if self.lint_separated_duplicates {
+                messages.push(format!(
+                    "Program #{} is identical to program #{}",
+                    duplicate.index, duplicate.original_index
+                ));
+            }
+        }
+        if self.is_modified_since_generation {
+            messages.push(
+                "The programs have been modified since the chain was generated. Review the changes, and remove `generated_by` from the metadata once they are trusted.".to_string()
+            );
+        }
+
+        messages
+    }
+
+    /// Describe the command line of each program without running it.
+    /// The command lines are quoted, so that they can be copied and run.
+    pub fn get_dry_run_command_lines(&self) -> Vec<String> {
+        self.programs
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| self.is_scheduled(*index))
+            .map(|(index, program)| {
+                let mut program = program.lock().unwrap();
+                let label: String = self.get_program_label(index);
+                if let Some(builtin) = program.get_builtin() {
+                    return format!("{}: builtin `{}`", label, builtin);
+                }
+                if let Some(git_operation) = program.get_git_operation() {
+                    return format!("{}: {}", label, git_operation);
+                }
+                let mut description: String =
+                    format!("{}: {}", label, program.get_command_line().render_command(RenderStyle::Full));
+                if program.is_always_run() {
+                    description.push_str(" (cleanup, always runs)");
+                }
+                let removed_variables: Vec<&String> = program.get_command_line().get_removed_environment_variables();
+                if !removed_variables.is_empty() {
+                    let removals: Vec<String> = removed_variables.iter().map(|name| format!("UNSET {}", name)).collect();
+                    description.push_str(&format!(" [{}]", removals.join(", ")));
+                }
+                if let Some(effects) = program.get_effects() {
+                    description.push_str(&format!(" [effects: {}]", effects.join(", ")));
+                }
+                if let Some(reason) = program.get_destructive_reason() {
+                    description.push_str(&format!(" (needs `--allow-destructive`: {})", reason));
+                }
+                // The changes are shown as the program would start now, or
+                // left out when it cannot, e.g. with an unknown `run_as` user
+                if is_environment_diff_shown() {
+                    let command_line: &mut CommandLine = program.get_command_line();
+                    if let Ok(changes) = command_line.get_environment_changes() {
+                        for line in command_line.render_environment_changes(&changes) {
+                            description.push_str(&format!("\n    {}", line));
+                        }
+                    }
+                }
+                // Anonymous prompts are shown with their texts
+                Variable::denumber_anonymous_prompts(&description)
+            })
+            .collect()
+    }
+
+    /// List the programs with their indices, without rendering variables
+    /// or asking for them
+    pub fn get_program_listing(&self) -> Vec<ProgramListing> {
+        self.programs
+            .iter()
+            .enumerate()
+            .map(|(index, program)| {
+                let mut program = program.lock().unwrap();
+                let command: String = match (program.get_builtin(), program.get_git_operation()) {
+                    (Some(builtin), _) => builtin.to_string(),
+                    (None, Some(git_operation)) => git_operation.to_string(),
+                    (None, None) => Variable::denumber_anonymous_prompts(
+                        &program.get_command_line().render_command(RenderStyle::Summary),
+                    ),
+                };
+                ProgramListing {
+                    index,
+                    alias: program.get_alias().cloned(),
+                    command,
+                    concurrency_group: program.get_concurrency_group(),
+                    builtin: program.get_builtin().is_some(),
+                    always_run: program.is_always_run(),
+                    verification: self.is_verification_step(index),
+                    selected: self.is_scheduled(index),
+                }
+            })
+            .collect()
+    }
+
+    /// Collect the expressions in a program that reference variables
+    /// which are not declared as integer or boolean.
+    fn find_untyped_expression_references(&self, arguments: &[Argument]) -> Result<Vec<String>, Error> {
+        let mut problems: Vec<String> = Vec::new();
+        for argument in arguments {
+            for raw_variable in Variable::extract_variable_names(argument.get_value()) {
+                if !Expression::is_expression(raw_variable) {
+                    continue;
+                }
+
+                let expression = Expression::from_str(raw_variable)?;
+                for identifier in expression.get_identifiers() {
+                    let is_typed: bool = self
+                        .metadata
+                        .variables
+                        .get(&identifier)
+                        .is_some_and(|declaration| declaration.variable_type != VariableType::String);
+                    if !is_typed {
+                        problems.push(format!(
+                            "Expression <<{}>> references `{}`, which is not declared as an integer or boolean variable",
+                            expression.get_source(),
+                            identifier
+                        ));
+                    }
+                }
+            }
+        }
+
+        Ok(problems)
+    }
+
+    /// Get the values of the typed variables that have been initialized
+    fn get_typed_values(&self) -> HashMap<String, TypedValue> {
+        let mut values: HashMap<String, TypedValue> = HashMap::new();
+        for variable in &self.variables {
+            let variable = variable.lock().unwrap();
+            if let Ok(Some(value)) = variable.get_typed_value() {
+                values.insert(variable.get_variable_name().to_string(), value);
+            }
+        }
+
+        values
+    }
+
+    /// Prompt the user for a variable's value until it satisfies the
+    /// declaration of the variable, if any.
+    fn request_variable_value(&self, variable: &Variable) -> Result<String, Error> {
+        if let Some(answer) = self.repeated_answers.get(variable.get_variable_name()) {
+            return Ok(answer.clone());
+        }
+        let mut message: String = variable.get_prompt_message();
+        // A value from `.cchain.env`, or else a preset, is the answer
+        // to an empty input
+        let default_value: Option<ProvidedValue> = self.get_default_value(variable);
+        if let Some(default_value) = &default_value {
+            let origin: &str = match self.adjacent_env_values.contains_key(variable.get_variable_name()) {
+                true => ADJACENT_ENV_FILE_NAME,
+                false => "preset",
+            };
+            message = format!("{} [{}, from {}]", message, default_value.value, origin);
+        }
+
+        // Values entered in earlier runs are offered, except for secrets
+        let is_secret: bool = variable.get_declaration().is_some_and(|declaration| declaration.secret);
+        let suggestions: Vec<String> = match &self.value_history {
+            Some(value_history) if !is_secret && !variable.is_anonymous() => {
+                value_history.get_values(&self.path, variable.get_variable_name())
+            }
+            _ => Vec::new(),
+        };
+
+        loop {
+            let mut input: String = self.interactor.prompt_with_quick_picks(&message, &suggestions)?;
+            if let (true, Some(default_value)) = (input.is_empty(), &default_value) {
+                input = default_value.value.clone();
+            }
+
+            match variable.validate_value(&input) {
+                Ok(_) => return Ok(input),
+                Err(error) => display_message(Level::Warn, &format!("{}. Please try again.", error)),
+            }
+        }
+    }
+
+    pub fn validate_syntax(&mut self) -> Result<(), Error> {
+        // Collect problematic variables
+        let mut variables_used_without_being_initialized: Vec<Variable> = Vec::new();
+        // Collect expressions that reference untyped variables
+        let mut expression_problems: Vec<String> = Vec::new();
+        // Collect retry patterns that do not compile
+        let mut pattern_problems: Vec<String> = Vec::new();
+        // Collect services that cannot run in the background
+        let mut service_problems: Vec<String> = Vec::new();
+        // Collect git steps that also have a command to run
+        let mut git_problems: Vec<String> = Vec::new();
+        // Collect assertions that cannot be evaluated
+        let mut assertion_problems: Vec<String> = Vec::new();
+        // Collect placeholders without declarations, when they are required
+        let mut declaration_problems: Vec<String> = Vec::new();
+        // Collect pipelines that cannot run
+        let pipeline_problems: Vec<String> = self.get_pipeline_problems();
+        // Collect members of concurrency groups that wait on each other
+        let concurrency_group_problems: Vec<String> = self.get_concurrency_group_problems();
+        // Collect verification steps that are more than checks
+        let verification_problems: Vec<String> = self.get_verification_problems();
+        // Collect remedy command lines using variables they never get
+        let remedy_problems: Vec<String> = self.get_remedy_variable_problems();
+
+        for name in self.get_unused_declarations() {
+            display_message(
+                Level::Warn,
+                &format!("Variable `{}` is declared, but no program uses it. Is the name a typo?", name),
+            );
+        }
+        // Placeholders are shown with the default delimiters once loaded
+        if let Some(variable_delimiters) = self.metadata.variable_delimiters.as_ref().filter(|delimiters| !delimiters.is_default()) {
+            display_message(
+                Level::Logging,
+                &format!(
+                    "Placeholders of this chain are written as `{}`, and shown as `<<name>>` below",
+                    variable_delimiters
+                ),
+            );
+        }
+        if self.metadata.is_locale_available() == Some(false) {
+            display_message(
+                Level::Warn,
+                &format!(
+                    "Locale `{}` is not installed on this machine, so programs may fall back to another one",
+                    self.metadata.locale.as_deref().unwrap_or_default()
+                ),
+            );
+        }
+        if self.require_declarations {
+            for name in self.get_undeclared_variables() {
+                declaration_problems.push(format!("Variable `{}` is used, but not declared in the metadata", name));
+            }
         }
 
-        Ok(Self {
-            programs,
-            variables,
-            failed_program_executions: Cell::new(0),
-            path: path.to_string(),
-        })
-    }
-
-    pub fn validate_syntax(&mut self) -> Result<(), Error> {
-        // Collect problematic variables
-        let mut variables_used_without_being_initialized: Vec<Variable> = Vec::new();
-
-        for (index, program) in self.programs.iter_mut().enumerate() {
+        for (index, program) in self.programs.iter().enumerate() {
             let mut variables_involved: Vec<Variable> = Vec::new();
             let mut program = program.lock().unwrap();
+            if let Err(error) = program.validate_retry_patterns() {
+                pattern_problems.push(format!("Program #{}: {}", index, error));
+            }
+            if let Err(error) = program.validate_capture_filter() {
+                pattern_problems.push(format!("Program #{}: {}", index, error));
+            }
+            if let Err(error) = program.validate_assertions() {
+                assertion_problems.push(format!("Program #{}: {}", index, error));
+            }
+            if program.is_service() && program.get_concurrency_group().is_some() {
+                service_problems.push(format!(
+                    "Program #{}: a service cannot be in a concurrency group",
+                    index
+                ));
+            }
+            if program.get_git_operation().is_some() {
+                if !program.get_command_line().get_command().is_empty() {
+                    git_problems.push(format!("Program #{}: a git step cannot also have a `command`", index));
+                }
+                if program.is_service() {
+                    git_problems.push(format!("Program #{}: a git step cannot run as a service", index));
+                }
+            }
+            expression_problems.extend(
+                self.find_untyped_expression_references(program.get_command_line().get_arguments())?
+            );
             // Get all variables involed in this program
-            // Get the variables in arguments first
-            for argument in program.get_command_line().get_arguments() {
-                variables_involved.extend(Variable::parse_variables_from_str(argument, index)?);
+            // Get the variables in the command line first
+            for value in program.get_placeholder_values() {
+                variables_involved.extend(Variable::parse_variables_from_str(value, index)?);
             }
             // Get the variables in remedy command if any
             if let Some(remedy_command_line) = program.get_remedy_command_line() {
-                for argument in remedy_command_line.get_arguments() {
-                    variables_involved.extend(Variable::parse_variables_from_str(argument, index)?);
+                for value in remedy_command_line.get_placeholder_values() {
+                    variables_involved.extend(Variable::parse_variables_from_str(value, index)?);
                 }
             }
-            // Check the lifetime validity of the variables
+            // Check the lifetime validity of the variables. Anonymous
+            // prompts are always initialized at their own programs.
             for variable_involved in &variables_involved {
-                if matches!(
+                if !variable_involved.is_anonymous() && matches!(
                     variable_involved.get_initialization_time(), 
                     VariableInitializationTime::Await(_) | 
                     VariableInitializationTime::OnProgramExecution(_)
@@ -114,7 +1899,7 @@ impl Chain {
             }
         }
 
-        if variables_used_without_being_initialized.len() != 0 {
+        if !variables_used_without_being_initialized.is_empty() {
             display_message(
                 Level::Error,
                 &format!(
@@ -130,10 +1915,30 @@ impl Chain {
                 );
             }
 
-            return Err(anyhow!("Check is not passed. 😢"));
+            return Err(ChainError::Validation("Check is not passed. 😢".to_string()).into());
+        }
+
+        let problems: Vec<&String> = expression_problems
+            .iter()
+            .chain(pattern_problems.iter())
+            .chain(service_problems.iter())
+            .chain(git_problems.iter())
+            .chain(assertion_problems.iter())
+            .chain(declaration_problems.iter())
+            .chain(pipeline_problems.iter())
+            .chain(concurrency_group_problems.iter())
+            .chain(verification_problems.iter())
+            .chain(remedy_problems.iter())
+            .collect();
+        if !problems.is_empty() {
+            for problem in problems {
+                display_message(Level::Error, problem);
+            }
+
+            return Err(ChainError::Validation("Check is not passed. 😢".to_string()).into());
         }
 
-        display_message(Level::Logging, &format!("Check is passed! 😄"));
+        display_message(Level::Logging, "Check is passed! 😄");
 
         Ok(())
     }
@@ -153,50 +1958,330 @@ impl Chain {
     /// Returns `Ok(())` if all variables are inserted successfully, or an `Error` if any variable's
     /// value retrieval fails.
     pub fn insert_variable(&mut self, program_index: usize) -> Result<(), Error> {
-        for variable in &mut self.variables {
+        let mut program = self.programs[program_index].lock().unwrap();
+        self.inject_values(program.get_command_line())?;
+        if let Some(git) = program.get_git_operation_mut() {
+            for variable in &self.variables {
+                let variable = variable.lock().unwrap();
+                if let Ok(value) = variable.get_value() {
+                    git.inject_value_to_variables(&variable.get_raw_variable_name(), &value);
+                }
+            }
+            git.unescape_placeholders();
+        }
+
+        Ok(())
+    }
+
+    /// Inject the values of the variables that have one into the command
+    /// line, then evaluate its expressions
+    fn inject_values(&self, command_line: &mut CommandLine) -> Result<(), Error> {
+        for variable in &self.variables {
             let variable = variable.lock().unwrap();
             // skip the `None` value variables
             if variable.get_value().is_ok() {
-                let mut program = self.programs[program_index].lock().unwrap();
-                program
-                    .get_command_line()
-                    .inject_value_to_variables(
-                        &variable.get_raw_variable_name(),
-                        variable.get_value()?,
-                    )?;
-
-                if let Some(command_line) = program
-                    .get_remedy_command_line()
-                {
-                    command_line.inject_value_to_variables(
-                        &variable.get_raw_variable_name(),
-                        variable.get_value()?,
-                    )?;
+                command_line.inject_value_to_variables(
+                    &variable.get_raw_variable_name(),
+                    variable.get_value()?,
+                )?;
+                if variable.get_declaration().is_some_and(|declaration| declaration.secret) {
+                    command_line.add_secret_value(variable.get_value()?);
                 }
             }
         }
 
+        // Evaluate expressions over the typed variables
+        let typed_values: HashMap<String, TypedValue> = self.get_typed_values();
+        command_line.inject_expressions(&typed_values)?;
+        command_line.unescape_placeholders();
+
         Ok(())
     }
 
+    /// Get the value of a chain variable that a function call of the
+    /// program takes as a parameter, e.g. `<<diff_output>>` in
+    /// `llm_generate('Summarize this diff', <<diff_output>>)`
+    pub fn get_function_variable_value(&self, name: &str, program_index: usize) -> Result<VariableParameterValue, Error> {
+        let name: &str = name.split(':').next().unwrap_or(name);
+        self.variables
+            .iter()
+            .find_map(|variable| {
+                let variable = variable.lock().unwrap();
+                if variable.get_variable_name() != name {
+                    return None;
+                }
+                let is_secret: bool = variable.get_declaration().is_some_and(|declaration| declaration.secret);
+                variable.get_value().ok().map(|value| VariableParameterValue { value, is_secret })
+            })
+            .ok_or_else(|| {
+                anyhow!(
+                    "Program #{} passes `<<{}>>` to a function, but the variable has no value when the program runs",
+                    program_index,
+                    name
+                )
+            })
+    }
+
     pub fn initialize_variables_on_chain_startup(&mut self) -> Result<(), Error> {
-        for variable in &mut self.variables {
-            let mut variable = variable.lock().unwrap();
+        // Variables only used by programs that do not run this time are
+        // never asked for
+        let scheduled_variable_names: HashSet<String> = self.get_scheduled_variable_names();
+        if self.confirm_variables && self.interactor.is_interactive() {
+            let confirmed_variables: Vec<Arc<Mutex<Variable>>> = self
+                .variables
+                .iter()
+                .filter(|variable| {
+                    let variable = variable.lock().unwrap();
+                    matches!(variable.get_initialization_time(), VariableInitializationTime::OnChainStartup(_))
+                        && !variable.is_automatic()
+                        && scheduled_variable_names.contains(variable.get_variable_name())
+                })
+                .cloned()
+                .collect();
+            self.confirmed_values = self.confirm_variable_values(&confirmed_variables)?;
+        }
+        // Variables without provided values are prompted for together
+        let mut prompted_variables: Vec<Arc<Mutex<Variable>>> = Vec::new();
+        for variable in &self.variables {
+            let mut locked_variable = variable.lock().unwrap();
+            if !scheduled_variable_names.contains(locked_variable.get_variable_name()) {
+                continue;
+            }
             if let VariableInitializationTime::OnChainStartup(_) =
-                variable.get_initialization_time()
+                locked_variable.get_initialization_time()
             {
-                let input: String = input_message(&format!(
-                    "Please input a value for {}:",
-                    variable.get_human_readable_name()
-                ))?;
-                variable
-                    .register_value(input.trim().to_string());
+                if locked_variable.is_automatic() {
+                    if let Some(chain_tmp) = &self.chain_tmp {
+                        let value: String = chain_tmp.get_path().to_string_lossy().to_string();
+                        locked_variable.register_value(value, VariableSource::Automatic);
+                    }
+                    continue;
+                }
+                if let Some((value, source)) = self.confirmed_values.get(locked_variable.get_variable_name()) {
+                    locked_variable.register_value(value, source.clone());
+                    continue;
+                }
+                if self.get_winning_value(&locked_variable).is_none() {
+                    // With `lazy_prompts`, they are asked for right before
+                    // the first program using them starts
+                    if !self.metadata.lazy_prompts {
+                        prompted_variables.push(variable.clone());
+                    }
+                    continue;
+                }
+                let (input, source) = self.obtain_variable_value(&locked_variable)?;
+                locked_variable.register_value(input, source);
             }
         }
 
+        let inputs: Vec<String> = self.request_startup_variable_values(&prompted_variables)?;
+        for (variable, input) in prompted_variables.iter().zip(inputs) {
+            variable.lock().unwrap().register_value(input, VariableSource::Prompt);
+        }
+
         Ok(())
     }
 
+    /// Show the value each source supplies for the variables and which
+    /// one wins, as in any run, then prompt for the variables without a
+    /// winning value as usual and review all the values together, so that
+    /// the user can pick another source or type a new value for any of
+    /// them
+    ///
+    /// # Returns
+    ///
+    /// The values picked, with their sources, keyed by the variable names
+    fn confirm_variable_values(
+        &self,
+        variables: &[Arc<Mutex<Variable>>],
+    ) -> Result<HashMap<String, (String, VariableSource)>, Error> {
+        if variables.is_empty() {
+            return Ok(HashMap::new());
+        }
+
+        let candidates: Vec<Vec<ValueCandidate>> = variables
+            .iter()
+            .map(|variable| self.get_value_candidates(&variable.lock().unwrap()))
+            .collect();
+        let form_data: Vec<Vec<String>> = variables
+            .iter()
+            .enumerate()
+            .map(|(index, variable)| {
+                let variable = variable.lock().unwrap();
+                let mask = |value: &str| mask_secret_value(&variable, value);
+                let mut row: Vec<String> = vec![(index + 1).to_string(), variable.get_human_readable_name().to_string()];
+                for origin in ValueOrigin::ALL {
+                    row.push(
+                        candidates[index]
+                            .iter()
+                            .find(|candidate| candidate.origin == origin)
+                            .map(|candidate| mask(&candidate.value.value))
+                            .unwrap_or_default(),
+                    );
+                }
+                row.push(match (self.get_winning_value(&variable), self.get_default_value(&variable)) {
+                    (Some(winning_value), _) => format!("{} ({})", mask(&winning_value.value), winning_value.source),
+                    (None, Some(default_value)) => format!("(prompt, default {})", mask(&default_value.value)),
+                    (None, None) => "(prompt)".to_string(),
+                });
+                row
+            })
+            .collect();
+        display_form(vec!["#", "Variable", "--var", ".cchain.env", "Preset", "Remembered", "Used"], &form_data);
+
+        let mut values: Vec<(String, VariableSource)> = Vec::new();
+        for variable in variables {
+            let variable = variable.lock().unwrap();
+            match self.get_winning_value(&variable) {
+                Some(winning_value) => {
+                    variable.validate_value(&winning_value.value).map_err(|error| {
+                        ChainError::Validation(format!("{} (from {})", error, winning_value.source))
+                    })?;
+                    values.push((winning_value.value, VariableSource::Provided { source: winning_value.source }));
+                }
+                None => values.push((self.request_variable_value(&variable)?, VariableSource::Prompt)),
+            }
+        }
+        self.review_variable_values(variables, &mut values, |index, variable| {
+            self.pick_variable_value(variable, &candidates[index])
+        })?;
+
+        Ok(variables
+            .iter()
+            .zip(values)
+            .map(|(variable, value)| (variable.lock().unwrap().get_variable_name().to_string(), value))
+            .collect())
+    }
+
+    /// Ask which source the value of a variable comes from at
+    /// `--confirm-vars`, or for a new value
+    ///
+    /// # Returns
+    ///
+    /// The value picked with its source, or none when the answer is
+    /// not understood
+    fn pick_variable_value(
+        &self,
+        variable: &Variable,
+        candidates: &[ValueCandidate],
+    ) -> Result<Option<(String, VariableSource)>, Error> {
+        let options: Vec<String> = candidates
+            .iter()
+            .map(|candidate| candidate.origin.get_choice().to_string())
+            .collect();
+        let message: String = match options.is_empty() {
+            true => format!("Source for {}: [n]ew value:", variable.get_human_readable_name()),
+            false => format!("Source for {}: {}, or [n]ew value:", variable.get_human_readable_name(), options.join("/")),
+        };
+        let answer: String = self.interactor.prompt(&message)?.to_lowercase();
+        if answer == "n" || answer == "new" {
+            return Ok(Some((self.request_variable_value(variable)?, VariableSource::Prompt)));
+        }
+
+        match candidates.iter().find(|candidate| candidate.origin.get_key() == answer) {
+            Some(candidate) => match variable.validate_value(&candidate.value.value) {
+                Ok(_) => Ok(Some((
+                    candidate.value.value.clone(),
+                    VariableSource::Provided {
+                        source: format!("{}, picked at --confirm-vars", candidate.value.source),
+                    },
+                ))),
+                Err(error) => {
+                    display_message(Level::Warn, &format!("{}. Please pick another.", error));
+                    Ok(None)
+                }
+            },
+            None => {
+                display_message(Level::Warn, "Please answer the key of a source, or `n` for a new value.");
+                Ok(None)
+            }
+        }
+    }
+
+    /// Prompt for the startup variables in order. With more than one of
+    /// them and a user to ask, they are listed first, and the answers are
+    /// reviewed before the chain runs, so that a typo can be fixed.
+    fn request_startup_variable_values(&self, variables: &[Arc<Mutex<Variable>>]) -> Result<Vec<String>, Error> {
+        let is_reviewed: bool = variables.len() > 1 && !self.skip_review && self.interactor.is_interactive();
+        if is_reviewed {
+            let form_data: Vec<Vec<String>> = variables
+                .iter()
+                .enumerate()
+                .map(|(index, variable)| {
+                    let variable = variable.lock().unwrap();
+                    let declaration: Option<&VariableDeclaration> = variable.get_declaration();
+                    vec![
+                        (index + 1).to_string(),
+                        variable.get_human_readable_name().to_string(),
+                        declaration.and_then(|declaration| declaration.description.clone()).unwrap_or_default(),
+                        declaration.and_then(|declaration| declaration.example.clone()).unwrap_or_default(),
+                    ]
+                })
+                .collect();
+            display_form(vec!["#", "Variable", "Description", "Example"], &form_data);
+        }
+
+        let mut values: Vec<(String, VariableSource)> = Vec::new();
+        for variable in variables {
+            values.push((self.request_variable_value(&variable.lock().unwrap())?, VariableSource::Prompt));
+        }
+
+        if is_reviewed {
+            self.review_variable_values(variables, &mut values, |_, variable| {
+                Ok(Some((self.request_variable_value(variable)?, VariableSource::Prompt)))
+            })?;
+        }
+
+        Ok(values.into_iter().map(|(value, _)| value).collect())
+    }
+
+    /// Show the values of the variables until the user confirms them,
+    /// replacing the one the user edits with what `edit` returns for it.
+    /// Values that do not come from the prompt show their sources.
+    fn review_variable_values(
+        &self,
+        variables: &[Arc<Mutex<Variable>>],
+        values: &mut [(String, VariableSource)],
+        mut edit: impl FnMut(usize, &Variable) -> Result<Option<(String, VariableSource)>, Error>,
+    ) -> Result<(), Error> {
+        loop {
+            let form_data: Vec<Vec<String>> = variables
+                .iter()
+                .zip(values.iter())
+                .enumerate()
+                .map(|(index, (variable, (value, source)))| {
+                    let variable = variable.lock().unwrap();
+                    let value: String = match source {
+                        VariableSource::Prompt => value.clone(),
+                        _ => format!("{} ({})", mask_secret_value(&variable, value), source),
+                    };
+                    vec![(index + 1).to_string(), variable.get_human_readable_name().to_string(), value]
+                })
+                .collect();
+            display_form(vec!["#", "Variable", "Value"], &form_data);
+
+            let answer: String = self.interactor.prompt("Run with these values? [c]onfirm/[e]dit:")?;
+            match answer.to_lowercase().as_str() {
+                "c" | "confirm" | "" => return Ok(()),
+                "e" | "edit" => {
+                    let number: String = self.interactor.prompt("Number of the value to edit:")?;
+                    match number.parse::<usize>() {
+                        Ok(number) if (1..=variables.len()).contains(&number) => {
+                            if let Some(value) = edit(number - 1, &variables[number - 1].lock().unwrap())? {
+                                values[number - 1] = value;
+                            }
+                        }
+                        _ => display_message(
+                            Level::Warn,
+                            &format!("Please answer a number from 1 to {}.", variables.len()),
+                        ),
+                    }
+                }
+                _ => display_message(Level::Warn, "Please answer `c` to confirm, or `e` to edit."),
+            }
+        }
+    }
+
     /// Initializes variables for the program execution phase.
     ///
     /// This method iterates over each argument of the specified program and extracts variables from these arguments.
@@ -207,7 +2292,7 @@ impl Chain {
     /// # Arguments
     ///
     /// * `program_index` - The index of the program in the chain whose arguments will be inspected for variables
-    ///                     needing initialization during program execution.
+    ///   needing initialization during program execution.
     ///
     /// # Returns
     ///
@@ -216,32 +2301,49 @@ impl Chain {
         &mut self,
         program_index: usize,
     ) -> Result<(), Error> {
-        // Acquire the lock first
-        let mut program = self.programs[program_index].lock().unwrap();
+        self.initialize_variables_on_execution(&[program_index])
+    }
 
-        for argument in program 
-            .get_command_line()
-            .get_arguments()
-        {
-            let mut program_variables: Vec<Variable> =
-                Variable::parse_variables_from_str(argument, program_index)?;
-
-            for program_variable in &mut program_variables {
-                for variable in &mut self.variables {
-                    let mut variable = variable.lock().unwrap();
-
-                    if program_variable.get_raw_variable_name()
-                        == variable.get_raw_variable_name()
-                        && matches!(
-                            program_variable.get_initialization_time(),
-                            VariableInitializationTime::OnProgramExecution(_)
-                        )
+    /// Prompt for the `on_program_execution` variables of programs that
+    /// start together, e.g. the members of a concurrency group, one after
+    /// another and before any of them starts. A variable used by several
+    /// of the programs is prompted for once.
+    fn initialize_variables_on_execution(&mut self, program_indices: &[usize]) -> Result<(), Error> {
+        self.initialize_deferred_variables(program_indices)?;
+
+        let mut initialized_variable_names: HashSet<String> = HashSet::new();
+        for &program_index in program_indices {
+            // Acquire the lock first
+            let program = self.programs[program_index].lock().unwrap();
+
+            let values: Vec<String> =
+                program.get_placeholder_values().into_iter().map(str::to_string).collect();
+            for value in values {
+                let program_variables: Vec<Variable> =
+                    Variable::parse_variables_from_str(&value, program_index)?;
+
+                for program_variable in &program_variables {
+                    if !matches!(
+                        program_variable.get_initialization_time(),
+                        VariableInitializationTime::OnProgramExecution(_)
+                    ) || !initialized_variable_names.insert(program_variable.get_raw_variable_name())
                     {
-                        let input: String = input_message(&format!(
-                            "Please input a value for {}:",
-                            variable.get_human_readable_name()
-                        ))?;
-                        variable.register_value(input.trim().to_string());
+                        continue;
+                    }
+
+                    for variable in &self.variables {
+                        let mut variable = variable.lock().unwrap();
+
+                        if program_variable.get_raw_variable_name() == variable.get_raw_variable_name() {
+                            let (input, source) = match self.obtain_variable_value(&variable) {
+                                Ok(result) => result,
+                                Err(error) if variable.is_anonymous() => {
+                                    return Err(self.list_pending_anonymous_prompts(error, &variable))
+                                }
+                                Err(error) => return Err(error),
+                            };
+                            variable.register_value(input, source);
+                        }
                     }
                 }
             }
@@ -249,60 +2351,312 @@ impl Chain {
         Ok(())
     }
 
+    /// Prompt for the `on_chain_startup` variables used by programs that
+    /// start together, which were deferred with `lazy_prompts`. They are
+    /// asked for together, as at the startup of the chain.
+    fn initialize_deferred_variables(&mut self, program_indices: &[usize]) -> Result<(), Error> {
+        if !self.metadata.lazy_prompts {
+            return Ok(());
+        }
+
+        let variable_names: HashSet<String> = program_indices
+            .iter()
+            .flat_map(|index| self.get_program_variable_names(*index))
+            .collect();
+        let deferred_variables: Vec<Arc<Mutex<Variable>>> = self
+            .variables
+            .iter()
+            .filter(|variable| {
+                let variable = variable.lock().unwrap();
+                matches!(variable.get_initialization_time(), VariableInitializationTime::OnChainStartup(_))
+                    && !variable.is_automatic()
+                    && variable.get_value().is_err()
+                    && variable_names.contains(variable.get_variable_name())
+            })
+            .cloned()
+            .collect();
+
+        let inputs: Vec<String> = self.request_startup_variable_values(&deferred_variables)?;
+        for (variable, input) in deferred_variables.iter().zip(inputs) {
+            variable.lock().unwrap().register_value(input, VariableSource::Prompt);
+        }
+
+        Ok(())
+    }
+
+    /// When the input for an anonymous prompt is closed, list the texts
+    /// of this prompt and the remaining ones, as none of them can be answered
+    fn list_pending_anonymous_prompts(&self, error: Error, current_variable: &Variable) -> Error {
+        if !matches!(error.downcast_ref::<ChainError>(), Some(ChainError::InputRequired { .. })) {
+            return error;
+        }
+
+        let mut prompts: Vec<String> = vec![format!("`{}`", current_variable.get_human_readable_name())];
+        for variable in &self.variables {
+            // The current variable is locked by the caller
+            let variable = match variable.try_lock() {
+                Ok(variable) => variable,
+                Err(_) => continue,
+            };
+            if variable.is_anonymous() && variable.get_value().is_err() {
+                prompts.push(format!("`{}`", variable.get_human_readable_name()));
+            }
+        }
+
+        ChainError::InputRequired { prompt: prompts.join(", ") }.into()
+    }
+
+    /// Record the failure of a program and run its remedy command line,
+    /// then decide whether the chain continues, by the failure policy of
+    /// the run or else by `exit_on_failure`
     pub fn handle_program_execution_failures(
         &self,
+        program_index: usize,
         program: &mut MutexGuard<'_, Program>,
-        error_message: &str,
+        error: &Error,
     ) -> Result<(), Error> {
-        // Increment the failure count
-        self.increment_failed_execution();
+        let error_message: String = error.to_string();
+        // Record the failure
+        self.record_failed_execution(program_index);
         // Display error message
         display_message(Level::Error, &error_message);
 
+        let mut is_remedied: bool = false;
         if let Some(command) = program.get_remedy_command_line() {
+            // The values are injected right before the remedy runs, so that
+            // those stored since its program was prepared are used, e.g.
+            // while a concurrency group waited to start. The placeholders
+            // are kept for the next failure.
+            let template: CommandLine = command.clone();
+            self.inject_values(command)?;
             display_message(
                 Level::Logging,
                 &format!("Remedy command is set. Try executing: {}", command),
             );
             // execute the remedy command line if any
-            program.execute_remedy_command_line()?;
+            let remedy_result: Result<(), Error> =
+                program.execute_remedy_command_line(&|name| self.get_function_variable_value(name, program_index));
+            if let Some(command) = program.get_remedy_command_line() {
+                *command = template;
+            }
+            match remedy_result {
+                Ok(_) => is_remedied = true,
+                Err(remedy_error) if self.failure_policy == FailurePolicy::ContinueOnFailure => {
+                    display_message(Level::Error, &format!("Remedy command failed: {}", remedy_error));
+                }
+                Err(remedy_error) => return Err(remedy_error),
+            }
+        }
+
+        let program_failed: Error = ChainError::ProgramFailed { program_index, message: error_message }.into();
+        match self.failure_policy {
+            FailurePolicy::ContinueOnFailure => {
+                // Nothing can change while the chain runs that lets the
+                // program start, so the rest of the chain is not worth running
+                if matches!(error.downcast_ref::<CommandLineError>(), Some(CommandLineError::SpawnFailed { .. })) {
+                    return Err(program_failed);
+                }
+                display_message(Level::Warn, "`--continue-on-failure` is set. Continue executing the chain...");
+                return Ok(());
+            }
+            FailurePolicy::StopOnFirstFailure { even_if_remedied } if even_if_remedied || !is_remedied => {
+                display_message(Level::Warn, "`--stop-on-first-failure` is set. Stop executing the chain...");
+                return Err(program_failed);
+            }
+            _ => {}
         }
 
         if !program.get_failure_handling_options().exit_on_failure {
             display_message(
                 Level::Warn,
-                &format!("`exit_on_failure` is set to false. Continue executing the chain..."),
+                "`exit_on_failure` is set to false. Continue executing the chain...",
             );
-            return Ok(());
+            Ok(())
         } else {
-            return Err(anyhow!(error_message.to_string()));
+            Err(program_failed)
+        }
+    }
+
+    pub fn record_failed_execution(&self, program_index: usize) {
+        self.failed_program_indices.borrow_mut().push(program_index);
+        self.send_event(ChainEvent::ProgramFinished { program_index, succeeded: false });
+    }
+
+    /// Report the services that have exited since the last check
+    fn check_services(&mut self) {
+        for service in &mut self.services {
+            if let Some(status) = service.poll_unexpected_exit() {
+                let description: String = format!("{} ({})", service.get_name(), status);
+                display_message(
+                    Level::Warn,
+                    &format!("Service exited unexpectedly: {}", description),
+                );
+                self.unexpectedly_exited_services.push(description);
+            }
         }
     }
 
-    pub fn increment_failed_execution(&self) {
-        let number: usize = self.failed_program_executions.get();
-        self.failed_program_executions.set(number + 1);
+    /// Terminate the services in reverse start order
+    fn stop_services(&mut self) {
+        self.check_services();
+        while let Some(mut service) = self.services.pop() {
+            if let Err(error) = service.stop(SERVICE_STOP_GRACE_PERIOD) {
+                display_message(
+                    Level::Error,
+                    &format!("Failed to stop service {}: {}", service.get_name(), error),
+                );
+            }
+        }
     }
 
     pub fn show_statistics(&self) {
+        // The failures are not handled as the chain declares, which
+        // explains why it stopped or continued
+        if self.failure_policy.is_override() {
+            display_message(
+                Level::Warn,
+                &format!("Failures were handled by {}, overriding `exit_on_failure`.", self.failure_policy),
+            );
+        }
         display_message(
             Level::Error,
             &format!(
                 "{} failures occurred when executing programs.",
-                self.failed_program_executions.get()
+                self.failed_program_indices.borrow().len()
             ),
         );
+        let cancelled_program_indices = self.cancelled_program_indices.borrow();
         display_message(
             Level::Logging,
             &format!(
                 "{} successes occurred when executing programs.",
-                (self.programs.len() - self.failed_program_executions.get())
+                (self.verification_start - self.failed_program_indices.borrow().len() - cancelled_program_indices.len())
             ),
         );
+        if !cancelled_program_indices.is_empty() {
+            display_message(
+                Level::Warn,
+                &format!(
+                    "{} programs were cancelled after another member of their concurrency group failed: {}",
+                    cancelled_program_indices.len(),
+                    cancelled_program_indices
+                        .iter()
+                        .map(|index| format!("#{}", index))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+            );
+        }
+        for index in self.failed_program_indices.borrow().iter() {
+            let program = self.programs[*index].lock().unwrap();
+            if program.is_always_run() {
+                display_message(Level::Error, &format!("Cleanup program #{} failed.", index));
+            }
+            // Killed or crashed programs are told apart from those that
+            // exited with an error
+            if let Some(termination) = program.get_attempt_records().last().and_then(|record| record.termination.as_ref()) {
+                display_message(Level::Error, &format!("Program #{}: {}.", index, termination));
+            }
+        }
+        // Verification runs after the programs succeeded, so its failures
+        // are told apart from theirs
+        let failed_verification_indices = self.failed_verification_indices.borrow();
+        if !failed_verification_indices.is_empty() {
+            display_message(
+                Level::Error,
+                &format!(
+                    "Verification: {} of {} steps failed.",
+                    failed_verification_indices.len(),
+                    self.get_verification_indices().len()
+                ),
+            );
+            for index in failed_verification_indices.iter() {
+                let mut program = self.programs[*index].lock().unwrap();
+                let command: String = match program.get_git_operation() {
+                    Some(git_operation) => git_operation.to_string(),
+                    None => Variable::denumber_anonymous_prompts(
+                        &program.get_command_line().render_command(RenderStyle::Summary),
+                    ),
+                };
+                display_tree_message(1, &format!("Verification step #{}: {}", index, command));
+            }
+        }
+        // The output was not shown as the programs ran
+        if is_summary_only() {
+            for index in self.failed_program_indices.borrow().iter().chain(failed_verification_indices.iter()) {
+                let program = self.programs[*index].lock().unwrap();
+                let Some(record) = program.get_attempt_records().last() else {
+                    continue;
+                };
+                display_message(Level::Error, &format!("End of the output of program #{}:", index));
+                display_command_line(&console::Term::stdout(), &record.output_tail);
+            }
+        }
+        for result in self.get_program_results() {
+            if result.was_retried() {
+                display_message(
+                    Level::Logging,
+                    &format!("Program #{} ran {} attempts.", result.program_index().unwrap_or_default(), result.attempts()),
+                );
+                for record in result.get_attempts() {
+                    // The last line of the output is usually the most telling
+                    let last_line: &str = record.output_tail.lines().last().unwrap_or_default();
+                    display_tree_message(
+                        1,
+                        &format!("Attempt {}: {} in {}ms. {}", record.attempt, record.status, record.duration_ms, last_line),
+                    );
+                }
+            }
+        }
+        for service in &self.unexpectedly_exited_services {
+            display_message(
+                Level::Error,
+                &format!("Service exited unexpectedly during the chain: {}", service),
+            );
+        }
+    }
+
+    /// Get the results of the programs that ran, failed ones included,
+    /// in the order of the programs
+    pub fn get_program_results(&self) -> Vec<ProgramExecutionResult> {
+        self.programs
+            .iter()
+            .enumerate()
+            .filter_map(|(index, program)| {
+                let program = program.lock().unwrap();
+                Some(program.get_last_result()?.clone().with_program_index(index))
+            })
+            .collect()
+    }
+
+    /// Get the records of the attempts of each program that ran,
+    /// keyed by the program index
+    #[deprecated(since = "0.3.43", note = "use `get_program_results`")]
+    pub fn get_attempt_records(&self) -> BTreeMap<usize, Vec<AttemptRecord>> {
+        self.get_program_results()
+            .into_iter()
+            .filter_map(|result| Some((result.program_index()?, result.get_attempts().clone())))
+            .collect()
     }
 
+    #[deprecated(since = "0.3.43", note = "use `get_failed_program_indices`")]
     pub fn get_failed_program_execution_number(&self) -> usize {
-        self.failed_program_executions.get()
+        self.failed_program_indices.borrow().len()
+    }
+
+    /// Get the indices of the programs that failed, in the order of failures
+    pub fn get_failed_program_indices(&self) -> Vec<usize> {
+        self.failed_program_indices.borrow().clone()
+    }
+
+    /// Get the indices of the programs cancelled by fail-fast concurrency groups
+    pub fn get_cancelled_program_indices(&self) -> Vec<usize> {
+        self.cancelled_program_indices.borrow().clone()
+    }
+
+    /// Get the indices of the verification steps that failed, in order
+    pub fn get_failed_verification_indices(&self) -> Vec<usize> {
+        self.failed_verification_indices.borrow().clone()
     }
 }
 
@@ -313,7 +2667,7 @@ impl std::fmt::Display for Chain {
             let program = program.lock().unwrap();
             program_names.push_str(&(program.to_string() + "\n"))
         }
-        f.write_str(&format!("{}", program_names))
+        f.write_str(&program_names)
     }
 }
 
@@ -322,18 +2676,18 @@ impl VariableGroupControl for Chain {
         for variable in &self.variables {
             let variable = variable.lock().unwrap();
             if variable.get_variable_name() == variable_name {
-                return Ok(variable.get_value()?);
+                return variable.get_value();
             }
         }
 
-        return Err(anyhow!("Variable {} does not exist!", variable_name));
+        Err(anyhow!("Variable {} does not exist!", variable_name))
     }
 
     fn update_value(&mut self, variable_name: &str, value: String) {
         for variable in &mut self.variables {
             let mut variable = variable.lock().unwrap();
             if variable.get_raw_variable_name() == variable_name {
-                variable.register_value(value);
+                variable.register_value(value, VariableSource::Assigned);
                 break;
             }
         }
@@ -346,22 +2700,178 @@ impl Execution<ChainExecutionResult> for Chain {
     }
 
     fn execute(&mut self) -> Result<Vec<ChainExecutionResult>, Error> {
+        // Nothing runs, not even the cleanup programs, until confirmed
+        self.confirm_destructive_programs()?;
+
+        if self.event_sender.is_some() {
+            let program_labels: Vec<String> = self
+                .programs
+                .iter()
+                .map(|program| program.lock().unwrap().to_string())
+                .collect();
+            self.send_event(ChainEvent::ChainStarted { program_labels });
+        }
+
+        self.create_chain_tmp()?;
+        let mut result = self.execute_programs();
+        // Cleanup programs run before the services they may depend on stop
+        if result.is_err() {
+            self.execute_cleanup_programs();
+        }
+        // Verification steps check what the programs did, so they run
+        // only when all of them succeeded, and before the services stop
+        if let Ok(results) = &mut result {
+            if !results.iter().any(|result| result.is_completed_with_failures()) {
+                self.execute_verification_steps();
+                for result in results.iter_mut() {
+                    result.failed_verification_indices = self.get_failed_verification_indices();
+                    result.program_results = self.get_program_results();
+                }
+            }
+        }
+        // Services are torn down whether the chain succeeds or not
+        self.stop_services();
+        self.remove_chain_tmp();
+        self.send_event(ChainEvent::ChainFinished { succeeded: result.is_ok() });
+
+        result
+    }
+}
+
+impl Chain {
+    /// Create the temporary directory of the run, when a program that
+    /// runs this time uses `<<chain_tmp>>`
+    fn create_chain_tmp(&mut self) -> Result<(), Error> {
+        if !self.get_scheduled_variable_names().contains(CHAIN_TMP_VARIABLE_NAME) {
+            return Ok(());
+        }
+        let is_kept: bool = self.keep_tmp || self.metadata.keep_tmp;
+        self.chain_tmp = Some(ScratchDirectory::create(&self.path, is_kept)?);
+
+        Ok(())
+    }
+
+    /// Remove the temporary directory of the run with its contents, or
+    /// show where it is kept
+    fn remove_chain_tmp(&mut self) {
+        let Some(chain_tmp) = &self.chain_tmp else {
+            return;
+        };
+        if chain_tmp.is_kept() {
+            display_message(
+                Level::Logging,
+                &format!("The temporary directory of the chain is kept at {}", chain_tmp.get_path().display()),
+            );
+            return;
+        }
+        if let Err(error) = chain_tmp.remove() {
+            display_message(Level::Warn, &error.to_string());
+        }
+        self.chain_tmp = None;
+    }
+
+    fn execute_programs(&mut self) -> Result<Vec<ChainExecutionResult>, Error> {
         // See if any program needs input on startup
         self.initialize_variables_on_chain_startup()?;
+
+        let pinned_environment: BTreeMap<String, String> = self.metadata.get_pinned_environment();
+        if !pinned_environment.is_empty() {
+            display_message(
+                Level::Logging,
+                &format!(
+                    "Programs run with {}",
+                    pinned_environment
+                        .iter()
+                        .map(|(name, value)| format!("{}={}", name, value))
+                        .collect::<Vec<String>>()
+                        .join(", ")
+                ),
+            );
+        }
         
+        if !self.program_selection.is_selecting_all() {
+            let scheduled_programs: Vec<String> = self
+                .get_scheduled_program_indices()
+                .iter()
+                .map(|index| format!("#{}", index))
+                .collect();
+            display_message(
+                Level::Logging,
+                &format!("Only program(s) {} run this time", scheduled_programs.join(", ")),
+            );
+        }
+
+        let mut problems: Vec<String> = self.get_pipeline_problems();
+        problems.extend(self.get_concurrency_group_problems());
+        problems.extend(self.get_verification_problems());
+        problems.extend(self.get_remedy_variable_problems());
+        if !problems.is_empty() {
+            return Err(ChainError::Validation(problems.join("; ")).into());
+        }
+        // The members of a concurrency group are prompted for together,
+        // before the group starts
+        let concurrency_group_launches: Vec<Vec<usize>> = self.get_concurrency_group_launches();
+
         // Capture the concurrency groups
         let mut current_concurrency_group_number: usize = 0;
-        let mut concurrency_group: Vec<Arc<Mutex<Program>>> = Vec::new();
+        let mut concurrency_group: Vec<usize> = Vec::new();
+        // Capture the programs of a pipeline, up to the one that receives
+        // the stream without passing it on
+        let mut pipeline: Vec<usize> = Vec::new();
+        // Whether the programs are skipped to the end of a pipeline
+        let mut is_skipping_pipeline: bool = false;
+
+        // Iterate over each program configuration in the chain and execute them sequentially.
+        // For each program, we first process any argument functions, then insert the chain's variables
+        // into the program, and finally execute the program. If the program provides an awaitable variable,
+        // we capture its output and update the corresponding variable in the chain.
+        for i in 0..self.verification_start {
+            if !self.program_selection.is_selected(i) {
+                continue;
+            }
+            // The chain pauses once the programs collected before are
+            // started, so that a pause never splits a group or a pipeline.
+            // Skipping the start of a pipeline skips the whole pipeline.
+            if is_skipping_pipeline
+                || (concurrency_group.is_empty() && pipeline.is_empty() && self.is_skipped_at_pause(i))
+            {
+                is_skipping_pipeline = self.programs[i].lock().unwrap().is_streaming_to_next();
+                continue;
+            }
+            if is_shutting_down() {
+                return Err(ChainError::Terminated.into());
+            }
+            self.check_services();
+
+            // Built-in steps run in place of a program, after the
+            // programs collected before them, and never fail
+            let builtin: Option<BuiltinStep> = self.programs[i].lock().unwrap().get_builtin();
+            if let Some(builtin) = builtin {
+                if !concurrency_group.is_empty() {
+                    self.execute_concurrency_group(&concurrency_group)?;
+                    concurrency_group.clear();
+                }
+                self.started_program_indices.borrow_mut().insert(i);
+                self.send_event(ChainEvent::ProgramStarted { program_index: i });
+                self.execute_builtin_step(i, builtin);
+                self.send_event(ChainEvent::ProgramFinished { program_index: i, succeeded: true });
+                continue;
+            }
+
+            // Programs in concurrency groups are started with their groups
+            if self.programs[i].lock().unwrap().get_concurrency_group().is_none() {
+                self.started_program_indices.borrow_mut().insert(i);
+            }
 
-        // Iterate over each program configuration in the chain and execute them sequentially.
-        // For each program, we first process any argument functions, then insert the chain's variables
-        // into the program, and finally execute the program. If the program provides an awaitable variable,
-        // we capture its output and update the corresponding variable in the chain.
-        for i in 0..self.programs.len() {
             // Check if the current program needs input to a value's intialization
             // time that is `on_program_execution`. If so, prompt the user for
-            // inputting a value
-            self.initialize_variables_on_program_execution(i)?;
+            // inputting a value. The worker threads of a concurrency group
+            // never prompt, so its members are prompted for with the first one.
+            match concurrency_group_launches.iter().find(|members| members.contains(&i)) {
+                Some(members) if members[0] == i => self.initialize_variables_on_execution(members)?,
+                Some(_) => {}
+                None => self.initialize_variables_on_program_execution(i)?,
+            }
 
             // Record awaitable variable if any
             let mut awaitable_variable: Option<String> = None;
@@ -376,9 +2886,9 @@ impl Execution<ChainExecutionResult> for Chain {
                 // Get a mutable reference to the current program.
                 let program = &mut self.programs[i].lock().unwrap();
                 // Process any functions provided as arguments for the program.
-                match program.execute_argument_functions() {
+                match program.execute_argument_functions(&|name| self.get_function_variable_value(name, i)) {
                     Ok(_) => {},
-                    Err(error) => match self.handle_program_execution_failures(program, &error.to_string()) {
+                    Err(error) => match self.handle_program_execution_failures(i, program, &error) {
                         Ok(_) => {},
                         Err(error) => return Err(error)
                     }
@@ -398,53 +2908,63 @@ impl Execution<ChainExecutionResult> for Chain {
                 // Acquire the lock of the program first
                 let mut this_program: MutexGuard<'_, Program> = self.programs[i].lock().unwrap();
 
+                // Services are started without being waited on
+                if this_program.is_service() {
+                    self.send_event(ChainEvent::ProgramStarted { program_index: i });
+                    let output: String = match this_program.start_service() {
+                        Ok((service, output)) => {
+                            self.services.push(service);
+                            self.send_event(ChainEvent::ProgramFinished { program_index: i, succeeded: true });
+                            output
+                        }
+                        Err(error) => match self.handle_program_execution_failures(i, &mut this_program, &error) {
+                            Ok(_) => continue,
+                            Err(error) => return Err(error)
+                        }
+                    };
+
+                    let awaitable_variable_this_program: Option<String> = this_program.get_awaitable_variable().clone();
+                    drop(this_program);
+                    if let Some(variable) = awaitable_variable_this_program {
+                        self.store_program_output(&variable, output, i);
+                    }
+                    continue;
+                }
+
+                // Programs streaming to the next one are started together
+                // with the rest of their pipeline
+                if this_program.is_streaming_to_next() {
+                    pipeline.push(i);
+                    continue;
+                }
+                if !pipeline.is_empty() {
+                    pipeline.push(i);
+                    let awaitable_variable_this_program: Option<String> = this_program.get_awaitable_variable().clone();
+                    drop(this_program);
+                    let output: Option<String> = self.execute_pipeline(&pipeline)?;
+                    pipeline.clear();
+                    if let (Some(variable), Some(output)) = (awaitable_variable_this_program, output) {
+                        self.store_program_output(&variable, output, i);
+                    }
+                    continue;
+                }
+
                 if let Some(concurrency_group_number_for_this_program) = this_program
                     .get_concurrency_group() 
                 {
                     // Determine whetehr the concurrency group can be executed
-                    if number_of_concurrent_programs_to_be_executed > 0 {
-                        if current_concurrency_group_number != concurrency_group_number_for_this_program
-                        {
-                            let mut tasks = Vec::new();
-                            for program in &concurrency_group {
-                                let program_clone = program.clone();
-                                tasks.push(
-                                    thread::spawn(
-                                        move || {
-                                            let mut program_clone = program_clone.lock().unwrap();
-                                            let result = program_clone.execute();
-                                            result
-                                        }
-                                    )
-                                );
-                            }
-
-                            let mut results = Vec::new();
-                            for task in tasks {
-                                results.push(task.join().unwrap());
-                            }
-
-                            for result in results {
-                                match result {
-                                    // The output of concurrency resutls are not going to be recorded
-                                    // for now.
-                                    Ok(_) => continue,
-                                    Err(error) => match self.handle_program_execution_failures(&mut this_program, &error.to_string()) {
-                                        Ok(_) => continue,
-                                        Err(error) => return Err(error)
-                                    }
-                                }
-                            }
-                        
-                            concurrency_group.clear();
-                        }
+                    if number_of_concurrent_programs_to_be_executed > 0
+                        && current_concurrency_group_number != concurrency_group_number_for_this_program
+                    {
+                        self.execute_concurrency_group(&concurrency_group)?;
+                        concurrency_group.clear();
                     }
-                    
+                
                     // Set the concurrent concurrency group number
                     current_concurrency_group_number = concurrency_group_number_for_this_program;
                     // Push the program to the concurrency group, 
                     // if the concurrency group is not eligible for execution
-                    concurrency_group.push(self.programs[i].clone());
+                    concurrency_group.push(i);
                     display_tree_message(
                         0, 
                         &format!(
@@ -460,55 +2980,707 @@ impl Execution<ChainExecutionResult> for Chain {
                 if let Some(variable) = awaitable_variable_this_program
                 {
                     // Execute the program and capture its output.
-                    let output: String = match this_program.execute() {
-                        Ok(result) => result[0].clone().get_output(),
-                        Err(error) => match self.handle_program_execution_failures(&mut this_program, &error.to_string()) {
-                            Ok(_) => continue,
-                            Err(error) => return Err(error)
-                        }
+                    let output: String = match self.execute_sequential_program(i, &mut this_program)? {
+                        Some(result) => result[0].output().to_string(),
+                        None => continue,
                     };
                     // Return the awaitable variable along with the captured output.
                     awaitable_variable = Some(variable.to_string());
                     awaitable_value = Some(output);
                 } else {
                     // If there is no awaitable variable, simply execute the program.
-                    match this_program.execute() {
-                        Ok(result) => result,
-                        Err(error) => match self.handle_program_execution_failures(&mut this_program, &error.to_string()) {
-                            Ok(_) => continue,
-                            Err(error) => return Err(error)
-                        }
-                    };
+                    if self.execute_sequential_program(i, &mut this_program)?.is_none() {
+                        continue;
+                    }
                 }
             }
 
             // If the program returned an awaitable variable and output, update the chain's variable.
-            if awaitable_value.is_some() && awaitable_variable.is_some() {
-                self.update_value(&awaitable_variable.unwrap(), awaitable_value.unwrap());
+            if let (Some(variable), Some(value)) = (awaitable_variable, awaitable_value) {
+                self.store_program_output(&variable, value, i);
             }
         }
 
         // Execute any remaining programs in the concurrency group after the loop
         if !concurrency_group.is_empty() {
-            let mut tasks = Vec::new();
-            for program in &concurrency_group {
-                let program_clone = program.clone();
-                tasks.push(thread::spawn(move || {
-                    let mut program = program_clone.lock().unwrap();
-                    program.execute()
-                }));
+            self.execute_concurrency_group(&concurrency_group)?;
+        }
+
+        Ok(vec![
+            ChainExecutionResult::new(self.get_program_results())
+                .with_failed_program_indices(self.get_failed_program_indices())
+                .with_variable_provenances(self.get_variable_provenances())
+        ])
+    }
+
+    /// Wait while the chain is paused before a program
+    ///
+    /// # Returns
+    ///
+    /// Whether the user skipped the program
+    fn is_skipped_at_pause(&self, program_index: usize) -> bool {
+        let Some(pause_control) = &self.pause_control else {
+            return false;
+        };
+        match pause_control.wait_for_decision() {
+            PauseDecision::Continue => false,
+            PauseDecision::Skip => {
+                display_message(Level::Warn, &format!("Program #{} is skipped.", program_index));
+                true
+            }
+            PauseDecision::Abort => interrupt(),
+        }
+    }
+
+    /// Run a step built into cchain
+    fn execute_builtin_step(&self, program_index: usize, builtin: BuiltinStep) {
+        display_message(Level::Logging, &format!("Program #{}: builtin `{}`", program_index, builtin));
+        match builtin {
+            BuiltinStep::DebugVariables => display_variable_snapshots(&self.get_variable_snapshots()),
+        }
+    }
+
+    /// Execute a program that runs on its own, and handle its failure.
+    /// With `--debug-on-failure`, the user may debug the failure in a shell,
+    /// then retry the program as a new execution, skip it, or abort the chain.
+    ///
+    /// # Returns
+    ///
+    /// The results of the program, or `None` when it failed and the chain continues
+    fn execute_sequential_program(
+        &self,
+        program_index: usize,
+        program: &mut MutexGuard<'_, Program>,
+    ) -> Result<Option<Vec<ProgramExecutionResult>>, Error> {
+        loop {
+            let error: Error = match program.execute() {
+                Ok(results) => return Ok(Some(results)),
+                Err(error) => error,
+            };
+
+            match self.offer_debug_shell(program_index, program, &error)? {
+                Some(DebugDecision::Retry) => continue,
+                Some(DebugDecision::Skip) => {
+                    self.record_failed_execution(program_index);
+                    display_message(Level::Warn, &format!("Program #{} is skipped. Continue executing the chain...", program_index));
+                    return Ok(None);
+                }
+                Some(DebugDecision::Abort) => {
+                    self.record_failed_execution(program_index);
+                    return Err(ChainError::ProgramFailed { program_index, message: error.to_string() }.into());
+                }
+                None => {
+                    self.handle_program_execution_failures(program_index, program, &error)?;
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    /// Offer a debug shell for a failed program, and ask what to do with
+    /// the program after the shell exits
+    ///
+    /// # Returns
+    ///
+    /// The decision, or `None` when there is no debugging, e.g. when the
+    /// user declines or there is no user to ask
+    fn offer_debug_shell(
+        &self,
+        program_index: usize,
+        program: &mut MutexGuard<'_, Program>,
+        error: &Error,
+    ) -> Result<Option<DebugDecision>, Error> {
+        let debug_shell_options: &DebugShellOptions = match &self.debug_shell_options {
+            Some(debug_shell_options) if self.interactor.is_interactive() => debug_shell_options,
+            _ => return Ok(None),
+        };
+
+        display_message(Level::Error, &format!("Program #{} failed: {}", program_index, error));
+        let answer: String = self.interactor.prompt("Open a debug shell for the failed program? [y/n]:")?;
+        if !matches!(answer.to_lowercase().as_str(), "y" | "yes") {
+            return Ok(None);
+        }
+
+        let mut environment: Vec<(String, String)> = Vec::new();
+        let mut hidden_secrets: Vec<String> = Vec::new();
+        for variable in &self.variables {
+            let variable = variable.lock().unwrap();
+            let value: String = match variable.get_value() {
+                Ok(value) => value,
+                Err(_) => continue,
+            };
+            let is_secret: bool = variable.get_declaration().is_some_and(|declaration| declaration.secret);
+            if is_secret && !debug_shell_options.is_exposing_secrets() {
+                hidden_secrets.push(format!("`{}`", variable.get_variable_name()));
+                continue;
+            }
+            environment.push((get_debug_variable_name(variable.get_variable_name()), value));
+        }
+        environment.push((DEBUG_FAILED_COMMAND.to_string(), program.get_command_line().to_string()));
+
+        if !hidden_secrets.is_empty() {
+            display_message(
+                Level::Warn,
+                &format!(
+                    "Secret variables are not exported: {}. Add `--debug-expose-secrets` to export them.",
+                    hidden_secrets.join(", ")
+                ),
+            );
+        }
+        display_message(
+            Level::Logging,
+            &format!(
+                "Opening {}. The variables are exported as `{}*`, and the command line as `{}`. Exit the shell to continue.",
+                debug_shell_options.get_shell(),
+                DEBUG_VARIABLE_PREFIX,
+                DEBUG_FAILED_COMMAND
+            ),
+        );
+        let working_directory: Option<String> = program.get_command_line().get_working_directory().cloned();
+        if let Err(error) = debug_shell_options.open_shell(working_directory.as_ref(), &environment) {
+            display_message(Level::Error, &error.to_string());
+        }
+
+        loop {
+            let answer: String = self.interactor.prompt("[r]etry the program, [s]kip it, or [a]bort the chain?:")?;
+            match answer.to_lowercase().as_str() {
+                "r" | "retry" => return Ok(Some(DebugDecision::Retry)),
+                "s" | "skip" => return Ok(Some(DebugDecision::Skip)),
+                "a" | "abort" => return Ok(Some(DebugDecision::Abort)),
+                _ => display_message(Level::Warn, "Please answer `r` to retry, `s` to skip, or `a` to abort."),
+            }
+        }
+    }
+
+    /// Execute the programs of a concurrency group in parallel, then
+    /// handle the failures of each program.
+    ///
+    /// Under the `fail_fast` policy, the first failure cancels the other
+    /// members that are still running. Cancelled members are recorded
+    /// apart from the failed ones.
+    fn execute_concurrency_group(&self, program_indices: &[usize]) -> Result<(), Error> {
+        // Checked before the chain runs, and again for the members
+        // actually launched together
+        let racing_dependency: Option<OutputDependency> = self.get_output_dependencies().into_iter().find(|dependency| {
+            program_indices.contains(&dependency.producer) && program_indices.contains(&dependency.consumer)
+        });
+        if let Some(dependency) = racing_dependency {
+            return Err(ChainError::Validation(format!(
+                "Program #{} cannot start together with program #{}, which stores `{}` that it uses",
+                dependency.consumer, dependency.producer, dependency.name
+            ))
+            .into());
+        }
+        self.started_program_indices.borrow_mut().extend(program_indices);
+        let concurrency_group: Option<usize> = self.programs[program_indices[0]].lock().unwrap().get_concurrency_group();
+        let policy: MemberFailurePolicy = concurrency_group
+            .map(|concurrency_group| self.metadata.get_member_failure_policy(concurrency_group))
+            .unwrap_or_default();
+
+        let (sender, receiver) = channel();
+        let mut tasks = Vec::new();
+        let mut cancellations: Vec<CancellationHandle> = Vec::new();
+        for &index in program_indices {
+            let program_clone = self.programs[index].clone();
+            let cancellation: Option<CancellationHandle> =
+                (policy == MemberFailurePolicy::FailFast).then(CancellationHandle::new);
+            cancellations.extend(cancellation.clone());
+            let sender = sender.clone();
+            tasks.push(thread::spawn(move || {
+                let mut program_clone = program_clone.lock().unwrap();
+                program_clone.set_cancellation(cancellation);
+                let result = program_clone.execute();
+                program_clone.set_cancellation(None);
+                let _ = sender.send((index, result));
+            }));
+        }
+        drop(sender);
+
+        // Results arrive in the order the programs finish, so that the
+        // first failure cancels the others right away
+        let mut results = BTreeMap::new();
+        for (index, result) in receiver {
+            if matches!(&result, Err(error) if !is_cancellation(error)) {
+                cancellations.iter().for_each(CancellationHandle::cancel);
+            }
+            results.insert(index, result);
+        }
+        for task in tasks {
+            task.join().unwrap();
+        }
+
+        // Record the cancelled members first, as handling a failure
+        // may abort the chain
+        results.retain(|index, result| match result {
+            Err(error) if is_cancellation(error) => {
+                display_message(
+                    Level::Warn,
+                    &format!("Program #{} is cancelled, because another member of its concurrency group failed.", index),
+                );
+                self.cancelled_program_indices.borrow_mut().push(*index);
+                false
+            }
+            _ => true,
+        });
+
+        // Outputs are stored once the whole group finished, so that they
+        // are only available to the programs after the group
+        let mut outputs: Vec<(String, String, usize)> = Vec::new();
+        for (index, result) in results {
+            match result {
+                Ok(results) => {
+                    let stored_to: Option<String> = self.programs[index].lock().unwrap().get_awaitable_variable().clone();
+                    if let (Some(variable), Some(result)) = (stored_to, results.into_iter().next()) {
+                        outputs.push((variable, result.output().to_string(), index));
+                    }
+                }
+                Err(error) => {
+                    let mut program = self.programs[index].lock().unwrap();
+                    self.handle_program_execution_failures(index, &mut program, &error)?;
+                }
+            }
+        }
+        for (variable, output, index) in outputs {
+            self.store_program_output(&variable, output, index);
+        }
+
+        Ok(())
+    }
+}
+
+/// Check whether a program failed because the next program of its
+/// pipeline stopped reading, either killed by `SIGPIPE` or exiting with
+/// 141 as shells report it
+fn is_broken_pipe(error: &Error) -> bool {
+    matches!(
+        error.downcast_ref::<CommandLineError>(),
+        Some(CommandLineError::NonZeroExit { status, .. }) if status.contains("SIGPIPE") || status == "exit status: 141"
+    )
+}
+
+/// Check whether a program failed because its concurrency group cancelled it
+fn is_cancellation(error: &Error) -> bool {
+    matches!(error.downcast_ref::<CommandLineError>(), Some(CommandLineError::Cancelled { .. }))
+}
+
+/// Mask the value of a secret variable to be shown
+fn mask_secret_value(variable: &Variable, value: &str) -> String {
+    match variable.get_declaration().is_some_and(|declaration| declaration.secret) {
+        true => SECRET_MASK.to_string(),
+        false => value.to_string(),
+    }
+}
+
+impl Chain {
+    /// Get the programs of each concurrency group that start together, in
+    /// the order the chain collects them. A group is started when a builtin
+    /// step or a member of another group comes.
+    pub fn get_concurrency_group_launches(&self) -> Vec<Vec<usize>> {
+        let mut launches: Vec<Vec<usize>> = Vec::new();
+        let mut members: Vec<usize> = Vec::new();
+        let mut current_concurrency_group: Option<usize> = None;
+        for (index, program) in self.programs.iter().enumerate() {
+            let program = program.lock().unwrap();
+            let concurrency_group: Option<usize> = program.get_concurrency_group();
+            let is_starting_group: bool = program.get_builtin().is_some()
+                || concurrency_group.is_some_and(|concurrency_group| Some(concurrency_group) != current_concurrency_group);
+            if is_starting_group && !members.is_empty() {
+                launches.push(std::mem::take(&mut members));
+            }
+            if concurrency_group.is_some() && program.get_builtin().is_none() {
+                current_concurrency_group = concurrency_group;
+                members.push(index);
+            }
+        }
+        if !members.is_empty() {
+            launches.push(members);
+        }
+
+        launches
+    }
+
+    /// Find the variables stored by a program's `stdout_stored_to` and used
+    /// by another program, in its arguments or its remedy command line.
+    ///
+    /// An output is only available to the programs that run strictly after
+    /// its producer finished. The members of a concurrency group run in no
+    /// particular order and the group finishes as a whole, so the output of
+    /// a member is only available after the group, which starts when a
+    /// builtin step or a member of another group comes.
+    pub fn get_output_dependencies(&self) -> Vec<OutputDependency> {
+        let launches: Vec<Vec<usize>> = self.get_concurrency_group_launches();
+        let get_launch = |index: usize| launches.iter().position(|members| members.contains(&index));
+        // A launch starts when a builtin step or a member of another group
+        // comes, or at the end of the chain, after the programs between
+        let launch_positions: Vec<usize> = launches
+            .iter()
+            .map(|members| {
+                let last_member: usize = members.last().copied().unwrap_or_default();
+                (last_member + 1..self.programs.len())
+                    .find(|index| {
+                        let program = self.programs[*index].lock().unwrap();
+                        program.get_builtin().is_some() || program.get_concurrency_group().is_some()
+                    })
+                    .unwrap_or(self.programs.len())
+            })
+            .collect();
+        let get_position = |index: usize| get_launch(index).map_or(index, |launch| launch_positions[launch]);
+
+        let mut producers: Vec<(usize, String)> = Vec::new();
+        for (index, program) in self.programs.iter().enumerate() {
+            let stored_to: Option<String> = program.lock().unwrap().get_awaitable_variable().clone();
+            for variable in Variable::parse_variables_from_str(&stored_to.unwrap_or_default(), index).unwrap_or_default() {
+                producers.push((index, variable.get_variable_name().to_string()));
+            }
+        }
+
+        let mut dependencies: Vec<OutputDependency> = Vec::new();
+        for (consumer, program) in self.programs.iter().enumerate() {
+            let mut program = program.lock().unwrap();
+            let mut values: Vec<String> =
+                program.get_placeholder_values().into_iter().map(str::to_string).collect();
+            if let Some(remedy_command_line) = program.get_remedy_command_line() {
+                values.extend(remedy_command_line.get_placeholder_values().into_iter().map(str::to_string));
+            }
+
+            let mut names: Vec<String> = Vec::new();
+            for value in &values {
+                for variable in Variable::parse_variables_from_str(value, consumer).unwrap_or_default() {
+                    if !variable.is_anonymous() && !names.iter().any(|name| name == variable.get_variable_name()) {
+                        names.push(variable.get_variable_name().to_string());
+                    }
+                }
+            }
+            for name in names {
+                for (producer, _) in producers.iter().filter(|(producer, stored)| *stored == name && *producer != consumer) {
+                    let availability: OutputAvailability =
+                        if get_launch(*producer).is_some() && get_launch(*producer) == get_launch(consumer) {
+                            OutputAvailability::SameConcurrencyGroup
+                        } else if get_position(*producer) < get_position(consumer) {
+                            OutputAvailability::Available
+                        } else if *producer > consumer {
+                            OutputAvailability::ProducedLater
+                        } else {
+                            OutputAvailability::GroupStartsLater
+                        };
+                    dependencies.push(OutputDependency { name: name.clone(), producer: *producer, consumer, availability });
+                }
+            }
+        }
+
+        dependencies
+    }
+
+    /// Find the programs that use the output of a member of a concurrency
+    /// group before the whole group finished: other members, which run in
+    /// no particular order, and the programs running before the group starts
+    pub fn get_concurrency_group_problems(&self) -> Vec<String> {
+        self.get_output_dependencies()
+            .iter()
+            .filter_map(|dependency| match dependency.availability {
+                OutputAvailability::SameConcurrencyGroup => Some(format!(
+                    "Program #{}: `{}` is stored by program #{} of the same concurrency group, which may not have finished. The output of a member is only available to the programs after the whole group.",
+                    dependency.consumer, dependency.name, dependency.producer
+                )),
+                OutputAvailability::GroupStartsLater => Some(format!(
+                    "Program #{}: `{}` is stored by program #{}, whose concurrency group only starts after it, when a builtin step or another group comes. The output of a member is only available to the programs after the whole group.",
+                    dependency.consumer, dependency.name, dependency.producer
+                )),
+                OutputAvailability::Available | OutputAvailability::ProducedLater => None,
+            })
+            .collect()
+    }
+
+    /// Find the variables in remedy command lines that have no value when
+    /// the remedy runs. A remedy runs after its program failed, so it can
+    /// only use the variables set before the program started: prompts,
+    /// provided values and the outputs of earlier programs.
+    pub fn get_remedy_variable_problems(&self) -> Vec<String> {
+        let dependencies: Vec<OutputDependency> = self.get_output_dependencies();
+        let is_asked_for = |name: &str| {
+            self.variables.iter().any(|variable| {
+                let variable = variable.lock().unwrap();
+                variable.get_variable_name() == name
+                    && !matches!(variable.get_initialization_time(), VariableInitializationTime::Await(_))
+            })
+        };
+
+        let mut problems: Vec<String> = Vec::new();
+        for (index, program) in self.programs.iter().enumerate() {
+            let mut program = program.lock().unwrap();
+            let stored_to: Vec<Variable> =
+                Variable::parse_variables_from_str(&program.get_awaitable_variable().clone().unwrap_or_default(), index)
+                    .unwrap_or_default();
+            let Some(remedy_command_line) = program.get_remedy_command_line() else {
+                continue;
+            };
+
+            let mut names: Vec<String> = Vec::new();
+            for value in remedy_command_line.get_placeholder_values() {
+                for variable in Variable::parse_variables_from_str(value, index).unwrap_or_default() {
+                    if !variable.is_anonymous() && !names.iter().any(|name| name == variable.get_variable_name()) {
+                        names.push(variable.get_variable_name().to_string());
+                    }
+                }
+            }
+            for name in names {
+                if stored_to.iter().any(|variable| variable.get_variable_name() == name) {
+                    problems.push(format!(
+                        "Program #{} remedy command: `{}` is stored by program #{} itself, so the remedy never gets it. The remedy only runs when the program failed, before its output is stored. Use the variables set before the program starts: prompts, provided values or the outputs of earlier programs.",
+                        index, name, index
+                    ));
+                    continue;
+                }
+
+                let producers: Vec<&OutputDependency> = dependencies
+                    .iter()
+                    .filter(|dependency| dependency.consumer == index && dependency.name == name)
+                    .collect();
+                let is_produced_later: bool = !producers.is_empty()
+                    && producers.iter().all(|dependency| dependency.availability == OutputAvailability::ProducedLater);
+                if is_produced_later && !is_asked_for(&name) {
+                    problems.push(format!(
+                        "Program #{} remedy command: `{}` is only stored by program #{}, which runs after it, so the remedy never gets it.",
+                        index, name, producers[0].producer
+                    ));
+                }
+            }
+        }
+
+        problems
+    }
+
+    /// Find the verification steps that are not plain checks, which
+    /// cannot run in the background, alongside others, or be remedied
+    pub fn get_verification_problems(&self) -> Vec<String> {
+        let mut problems: Vec<String> = Vec::new();
+        for index in self.get_verification_indices() {
+            let mut program = self.programs[index].lock().unwrap();
+            if program.is_service()
+                || program.get_concurrency_group().is_some()
+                || program.is_streaming_to_next()
+                || program.is_always_run()
+            {
+                problems.push(format!(
+                    "Verification step #{}: a verification step cannot be a service, in a concurrency group, in a pipeline or a cleanup program",
+                    index
+                ));
             }
+            if program.get_remedy_command_line().is_some() {
+                problems.push(format!("Verification step #{}: a verification step cannot have a remedy command line", index));
+            }
+        }
+
+        problems
+    }
+
+    /// Find the problems of the pipelines formed by `stream_to_next`
+    pub fn get_pipeline_problems(&self) -> Vec<String> {
+        let mut problems: Vec<String> = Vec::new();
+        let mut is_in_pipeline: bool = false;
+        for (index, program) in self.programs[..self.verification_start].iter().enumerate() {
+            let program = program.lock().unwrap();
+            let is_streaming: bool = program.is_streaming_to_next();
+            if is_in_pipeline && program.get_builtin().is_some() {
+                problems.push(format!("Program #{}: a builtin step cannot receive the stream of a pipeline", index));
+            }
+            if is_streaming && index + 1 == self.verification_start {
+                problems.push(format!("Program #{}: `stream_to_next` is set, but there is no next program", index));
+            }
+            if is_streaming && program.get_awaitable_variable().is_some() {
+                problems.push(format!(
+                    "Program #{}: a program streaming its output to the next one cannot store it in `stdout_stored_to`",
+                    index
+                ));
+            }
+            if is_streaming || is_in_pipeline {
+                if program.get_concurrency_group().is_some() || program.is_service() || program.is_always_run() {
+                    problems.push(format!(
+                        "Program #{}: a program in a pipeline cannot be in a concurrency group, a service or a cleanup program",
+                        index
+                    ));
+                }
+                if *program.get_retry() != 0 {
+                    problems.push(format!(
+                        "Program #{}: a program in a pipeline cannot retry, as the stream cannot be replayed",
+                        index
+                    ));
+                }
+            }
+            is_in_pipeline = is_streaming;
+        }
+
+        problems
+    }
+
+    /// Execute the programs of a pipeline together, with the stdout of
+    /// each program connected to the stdin of the next one, then handle
+    /// the failures of each program.
+    ///
+    /// # Returns
+    ///
+    /// The output of the last program, if it succeeded
+    fn execute_pipeline(&self, program_indices: &[usize]) -> Result<Option<String>, Error> {
+        self.started_program_indices.borrow_mut().extend(program_indices);
+        display_message(
+            Level::Logging,
+            &format!(
+                "Starting the pipeline of programs {}...",
+                program_indices.iter().map(|index| format!("#{}", index)).collect::<Vec<String>>().join(" | ")
+            ),
+        );
 
-            let mut results = Vec::new();
-            for task in tasks {
-                match task.join().unwrap() {
-                    Ok(result) => results.extend(result),
-                    Err(e) => return Err(e),
+        // Wire every pipe before starting any program, so that each end
+        // is held by one program only
+        let (&last_index, streaming_indices) = program_indices.split_last().unwrap();
+        let mut stdin: Option<PipeReader> = None;
+        for &index in streaming_indices {
+            let (reader, writer) = std::io::pipe()?;
+            self.programs[index].lock().unwrap().set_stream_ends(StreamEnds {
+                stdin: stdin.replace(reader),
+                stdout: Some(writer),
+            });
+        }
+        self.programs[last_index].lock().unwrap().set_stream_ends(StreamEnds { stdin, stdout: None });
+
+        let mut tasks = Vec::new();
+        for &index in program_indices {
+            let program_clone = self.programs[index].clone();
+            tasks.push((index, thread::spawn(move || program_clone.lock().unwrap().execute())));
+        }
+        let results: Vec<(usize, Result<Vec<ProgramExecutionResult>, Error>)> = tasks
+            .into_iter()
+            .map(|(index, task)| (index, task.join().unwrap()))
+            .collect();
+
+        let mut output: Option<String> = None;
+        for (index, result) in results {
+            match result {
+                Ok(result) if index == last_index => output = Some(result[0].output().to_string()),
+                Ok(_) => {}
+                // A program that stops reading early, e.g. `head`, breaks the
+                // pipe of the previous one, which is not a failure of it
+                Err(error) if is_broken_pipe(&error) && index != last_index => {
+                    display_message(
+                        Level::Logging,
+                        &format!("Program #{} stopped, because the next program stopped reading its output.", index),
+                    );
+                }
+                Err(error) => {
+                    let mut program = self.programs[index].lock().unwrap();
+                    self.handle_program_execution_failures(index, &mut program, &error)?;
                 }
             }
         }
 
-        Ok(vec![ChainExecutionResult::new("Done".to_string())])
+        Ok(output)
+    }
+
+    /// Run the steps of the `verify` section one by one. All of them run
+    /// whatever fails, without remedies, and their failures are recorded
+    /// apart from those of the programs.
+    fn execute_verification_steps(&mut self) {
+        let verification_indices: std::ops::Range<usize> = self.get_verification_indices();
+        if verification_indices.is_empty() || is_shutting_down() {
+            return;
+        }
+
+        display_message(Level::Logging, &format!("Verifying the chain with {} step(s)...", verification_indices.len()));
+        for index in verification_indices {
+            if is_shutting_down() {
+                break;
+            }
+            self.started_program_indices.borrow_mut().insert(index);
+            if let Err(error) = self.execute_verification_step(index) {
+                self.failed_verification_indices.borrow_mut().push(index);
+                display_message(Level::Error, &format!("Verification step #{} failed: {}", index, error));
+            }
+        }
+    }
+
+    fn execute_verification_step(&mut self, index: usize) -> Result<(), Error> {
+        self.initialize_variables_on_program_execution(index)?;
+        self.insert_variable(index)?;
+
+        let mut program = self.programs[index].lock().unwrap();
+        program.execute_argument_functions(&|name| self.get_function_variable_value(name, index))?;
+        let output: String = program.execute()?[0].output().to_string();
+        let awaitable_variable: Option<String> = program.get_awaitable_variable().clone();
+        drop(program);
+        if let Some(variable) = awaitable_variable {
+            self.store_program_output(&variable, output, index);
+        }
+
+        Ok(())
+    }
+
+    /// After the chain aborts, run the `always_run` programs that have not
+    /// been started, one by one in their declared order, including those in
+    /// concurrency groups. Their failures are reported and recorded, but
+    /// the original error of the chain is kept.
+    fn execute_cleanup_programs(&mut self) {
+        for index in 0..self.programs.len() {
+            if !self.programs[index].lock().unwrap().is_always_run()
+                || self.started_program_indices.borrow().contains(&index)
+                || !self.program_selection.is_selected(index)
+            {
+                continue;
+            }
+
+            if is_grace_period_over() {
+                display_message(Level::Warn, &format!("The grace period is over, so cleanup program #{} is not run", index));
+                continue;
+            }
+
+            self.started_program_indices.borrow_mut().insert(index);
+            display_message(Level::Warn, &format!("Running cleanup program #{}...", index));
+            if let Err(error) = self.execute_cleanup_program(index) {
+                self.record_failed_execution(index);
+                display_message(
+                    Level::Error,
+                    &format!("Cleanup program #{} failed: {}", index, error),
+                );
+            }
+        }
+    }
+
+    fn execute_cleanup_program(&mut self, index: usize) -> Result<(), Error> {
+        self.initialize_variables_on_program_execution(index)?;
+        self.request_unresolved_variable_values(index)?;
+        self.insert_variable(index)?;
+
+        let mut program = self.programs[index].lock().unwrap();
+        program.execute_argument_functions(&|name| self.get_function_variable_value(name, index))?;
+        let output: String = program.execute()?[0].output().to_string();
+        let awaitable_variable: Option<String> = program.get_awaitable_variable().clone();
+        drop(program);
+        if let Some(variable) = awaitable_variable {
+            self.store_program_output(&variable, output, index);
+        }
+
+        Ok(())
+    }
+
+    /// Ask for the values of the variables in the command line of a
+    /// program that are left without values, e.g. because the programs
+    /// storing them did not run before the chain aborted
+    fn request_unresolved_variable_values(&self, index: usize) -> Result<(), Error> {
+        let mut variable_names: Vec<String> = Vec::new();
+        for value in self.programs[index].lock().unwrap().get_placeholder_values() {
+            for variable in Variable::parse_variables_from_str(value, index)? {
+                variable_names.push(variable.get_variable_name().to_string());
+            }
+        }
+
+        for variable in &self.variables {
+            let mut variable = variable.lock().unwrap();
+            if variable.get_value().is_err() && variable_names.iter().any(|name| name == variable.get_variable_name()) {
+                let (input, source) = self.obtain_variable_value(&variable)?;
+                variable.register_value(input, source);
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -517,10 +3689,16 @@ impl AvailablePackages for Chain {
         let mut required_packages: HashSet<Package> = HashSet::new();
         for program in &self.programs {
             let mut program = program.lock().unwrap();
+            if program.get_builtin().is_some() {
+                continue;
+            }
             
-            required_packages.insert(
-                Package::new(program.get_command_line().get_command().to_string())
-            );
+            // Git steps run through git2, so they need no git installed
+            if program.get_git_operation().is_none() {
+                required_packages.insert(
+                    Package::new(program.get_command_line().get_command().to_string())
+                );
+            }
             
             if let Some(remedy_command_line) = program.get_remedy_command_line() {
                 required_packages.insert(
@@ -0,0 +1,186 @@
+use std::{
+    sync::LazyLock,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use super::{chain::Chain, program::AttemptRecord, shutdown::is_shutting_down, status::RunOutcome};
+
+/// Default milliseconds to wait before the second attempt of a chain.
+/// The wait doubles with every attempt after.
+pub const DEFAULT_CHAIN_RETRY_DELAY_MS: u64 = 1000;
+
+/// Longest wait between two attempts of a chain
+const MAX_CHAIN_RETRY_DELAY: Duration = Duration::from_secs(300);
+
+/// How long to sleep at once while waiting for the next attempt, so that
+/// a termination signal is not kept waiting
+const CHAIN_RETRY_WAIT_STEP: Duration = Duration::from_millis(100);
+
+/// Failures that are the fault of the network or of a remote service
+/// rather than of the chain, e.g. a registry answering 503. Matched
+/// against the end of the output of the failed programs.
+pub static INFRASTRUCTURE_FAILURE_PATTERNS: LazyLock<Vec<Regex>> = LazyLock::new(|| {
+    [
+        r"(?i)\b(http|status|error)[ :/]*(502|503|504)\b",
+        r"(?i)\b(bad gateway|service unavailable|gateway time-?out)\b",
+        r"(?i)\b429\b.*too many requests|\brate limit(ed)? exceeded\b",
+        r"(?i)connection (refused|reset|timed out|closed by remote)",
+        r"(?i)could not resolve (host|hostname)|temporary failure in name resolution|name or service not known",
+        r"(?i)network is unreachable|no route to host",
+        r"(?i)tls handshake timeout|i/o timeout|operation timed out",
+        r"(?i)early eof",
+    ]
+    .into_iter()
+    .map(|pattern| Regex::new(pattern).unwrap())
+    .collect()
+});
+
+/// How a failed attempt of a chain is judged
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainFailureClass {
+    /// Every failed program failed in a way that looks like the
+    /// infrastructure's fault, so the chain is worth running again
+    Infrastructure {
+        program_index: usize,
+        /// The pattern the output of the program matched
+        pattern: String,
+    },
+    /// The chain itself is at fault, e.g. it is invalid, an assertion
+    /// failed, or a program failed with an unknown error
+    Genuine(String),
+}
+
+/// An attempt of the whole chain, for the summary and the status file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ChainAttempt {
+    /// The number of the attempt, starting from 1
+    pub attempt: u32,
+    pub outcome: RunOutcome,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_programs: Vec<usize>,
+    pub duration_ms: u64,
+    /// Why the chain ran again after this attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retried_because: Option<String>,
+}
+
+/// How the whole chain is run again when it fails because of the
+/// infrastructure, with `--retry-chain`
+#[derive(Debug, Clone)]
+pub struct ChainRetryOptions {
+    retries: u32,
+    /// Patterns of failures to retry on, besides
+    /// `INFRASTRUCTURE_FAILURE_PATTERNS`, compiled once
+    patterns: Vec<Regex>,
+    delay_ms: u64,
+}
+
+impl Default for ChainRetryOptions {
+    fn default() -> Self {
+        Self { retries: 0, patterns: Vec::new(), delay_ms: DEFAULT_CHAIN_RETRY_DELAY_MS }
+    }
+}
+
+impl ChainRetryOptions {
+    pub fn new(retries: u32) -> Self {
+        Self { retries, ..Default::default() }
+    }
+
+    /// Retry on the `--retry-chain-on` patterns too, which fail to
+    /// compile with an error naming the pattern
+    pub fn with_patterns(mut self, patterns: &[String]) -> Result<Self, Error> {
+        self.patterns = patterns
+            .iter()
+            .map(|pattern| {
+                Regex::new(pattern).map_err(|error| anyhow!("Invalid `--retry-chain-on` pattern `{}`: {}", pattern, error))
+            })
+            .collect::<Result<Vec<Regex>, Error>>()?;
+        Ok(self)
+    }
+
+    pub fn with_delay_ms(mut self, delay_ms: u64) -> Self {
+        self.delay_ms = delay_ms;
+        self
+    }
+
+    pub fn get_retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// Get how long to wait after the attempt before the next one,
+    /// doubling with every attempt
+    pub fn get_delay(&self, attempt: u32) -> Duration {
+        let factor: u64 = 1u64.checked_shl(attempt.saturating_sub(1)).unwrap_or(u64::MAX);
+        Duration::from_millis(self.delay_ms.saturating_mul(factor)).min(MAX_CHAIN_RETRY_DELAY)
+    }
+
+    /// Decide whether the attempt that ended with the outcome failed
+    /// because of the infrastructure. Only programs failing with an
+    /// exit code are considered, and all of them must match.
+    pub fn classify_failure(&self, outcome: RunOutcome, chain: &Chain) -> ChainFailureClass {
+        if !matches!(outcome, RunOutcome::Aborted | RunOutcome::CompletedWithFailures) {
+            return ChainFailureClass::Genuine(format!("the chain {}", outcome));
+        }
+        let failed_programs: Vec<usize> = chain.get_failed_program_indices();
+        if failed_programs.is_empty() {
+            return ChainFailureClass::Genuine("no program failed".to_string());
+        }
+
+        let program_results = chain.get_program_results();
+        let mut infrastructure_failure: Option<ChainFailureClass> = None;
+        for program_index in failed_programs {
+            let record: Option<&AttemptRecord> = program_results
+                .iter()
+                .find(|result| result.program_index() == Some(program_index))
+                .and_then(|result| result.get_attempts().last());
+            // Assertions fail after a successful attempt, and programs
+            // that cannot be started fail the same way every time
+            let Some(record) = record.filter(|record| !record.succeeded && record.exit_code.is_some()) else {
+                return ChainFailureClass::Genuine(format!("program #{} did not fail with an exit code", program_index));
+            };
+            let failure_text: String = format!("{}\n{}", record.status, record.output_tail);
+            match self.find_matching_pattern(&failure_text) {
+                Some(pattern) => {
+                    infrastructure_failure.get_or_insert(ChainFailureClass::Infrastructure { program_index, pattern });
+                }
+                None => {
+                    return ChainFailureClass::Genuine(format!(
+                        "the failure of program #{} does not look like an infrastructure error",
+                        program_index
+                    ));
+                }
+            }
+        }
+
+        infrastructure_failure.unwrap_or_else(|| ChainFailureClass::Genuine("no program failed".to_string()))
+    }
+
+    fn find_matching_pattern(&self, text: &str) -> Option<String> {
+        self.patterns
+            .iter()
+            .chain(INFRASTRUCTURE_FAILURE_PATTERNS.iter())
+            .find(|regex| regex.is_match(text))
+            .map(|regex| regex.as_str().to_string())
+    }
+}
+
+/// Wait before the next attempt of a chain
+///
+/// # Returns
+///
+/// False when cchain is asked to terminate in the meantime
+pub fn wait_for_chain_attempt(delay: Duration) -> bool {
+    let started_at: Instant = Instant::now();
+    while started_at.elapsed() < delay {
+        if is_shutting_down() {
+            return false;
+        }
+        std::thread::sleep(CHAIN_RETRY_WAIT_STEP.min(delay.saturating_sub(started_at.elapsed())));
+    }
+
+    !is_shutting_down()
+}
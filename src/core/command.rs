@@ -1,18 +1,184 @@
-use std::io::{BufReader, Read};
+use std::io::{BufReader, PipeReader, PipeWriter, Read};
+use std::path::Path;
+use std::str::FromStr;
 use std::sync::mpsc::channel;
-use std::{collections::HashMap, process::Command};
+use std::{collections::{BTreeMap, HashMap}, process::Command};
 
 use anyhow::{Error, Result};
 use console::{StyledObject, Term};
 use serde::{Deserialize, Serialize};
 
-use crate::display_control::{display_command_line, display_message, Level};
+use crate::commons::errors::{CommandLineError, SpawnErrorKind};
+use crate::commons::executable::{is_batch_script, resolve_argv, simplify_windows_path, ExecutableLookup};
+use crate::commons::features::Feature;
+use crate::commons::packages::Package;
+use crate::commons::termination::ProcessTermination;
+use crate::display_control::{
+    display_command_line, display_message, get_terminal_width, is_environment_diff_shown, is_summary_only, Level, LiveOutputFilter,
+};
+use crate::expression::{Expression, TypedValue};
+use crate::function::Function;
+use crate::variable::{Variable, VariableDelimiters};
+
+use super::environment::{diff_environment, get_current_environment, EnvironmentChange};
+use super::events::{ChainEvent, EventSender};
+use super::group::CancellationHandle;
+use super::io_profile::{IoProfiler, IoUsage};
+use super::options::DisplayOutput;
 
 use super::{
+    builtin::{truncate_value, SECRET_MASK},
     interpreter::Interpreter,
+    paths::{is_chain_relative_command, resolve_chain_relative_path},
+    privilege::PrivilegeChange,
+    sanitize::OutputChunkBuffer,
+    shutdown::{register_running_process, unregister_running_process},
     traits::{Execution, ExecutionType},
 };
 
+/// Arguments shown after the command in a summary of a command line
+pub const SUMMARY_ARGUMENT_COUNT: usize = 8;
+
+/// Characters that the shell interprets when they are not quoted
+pub(crate) const SHELL_METACHARACTERS: &[&str] = &["&&", "||", "|", "&", ";", ">", "<", "$", "`", "(", ")"];
+
+/// An argument of a command line.
+///
+/// Under the `sh` interpreter, plain arguments are quoted so that the
+/// shell passes them literally, and raw arguments, written as
+/// `{"raw": "a && b"}`, are handed to the shell as they are.
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(untagged)]
+pub enum Argument {
+    Plain(String),
+    Raw { raw: String },
+}
+
+impl Argument {
+    pub fn get_value(&self) -> &str {
+        match self {
+            Argument::Plain(value) => value,
+            Argument::Raw { raw } => raw,
+        }
+    }
+
+    pub fn get_value_mut(&mut self) -> &mut String {
+        match self {
+            Argument::Plain(value) => value,
+            Argument::Raw { raw } => raw,
+        }
+    }
+
+    /// Replace the text of the argument, keeping whether it is raw
+    pub fn set_value(&mut self, value: String) {
+        match self {
+            Argument::Plain(plain) => *plain = value,
+            Argument::Raw { raw } => *raw = value,
+        }
+    }
+
+    pub fn is_raw(&self) -> bool {
+        matches!(self, Argument::Raw { .. })
+    }
+
+    /// Get the text of the argument in a `sh -c` command line
+    pub fn to_shell_word(&self) -> String {
+        match self {
+            Argument::Plain(value) => quote_for_shell(value),
+            Argument::Raw { raw } => raw.clone(),
+        }
+    }
+
+    /// Get the shell metacharacters in the argument
+    pub fn get_shell_metacharacters(&self) -> Vec<&'static str> {
+        let mut value: String = self.get_value().to_string();
+        let mut metacharacters: Vec<&'static str> = Vec::new();
+        // Longer operators are matched first, so that `&&` is not also reported as `&`
+        for metacharacter in SHELL_METACHARACTERS {
+            if value.contains(metacharacter) {
+                metacharacters.push(metacharacter);
+                value = value.replace(metacharacter, " ");
+            }
+        }
+
+        metacharacters
+    }
+}
+
+impl From<String> for Argument {
+    fn from(value: String) -> Self {
+        Argument::Plain(value)
+    }
+}
+
+impl PartialEq<String> for Argument {
+    fn eq(&self, other: &String) -> bool {
+        self.get_value() == other
+    }
+}
+
+impl PartialEq<&str> for Argument {
+    fn eq(&self, other: &&str) -> bool {
+        self.get_value() == *other
+    }
+}
+
+impl std::fmt::Display for Argument {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.get_value())
+    }
+}
+
+/// Pipe ends that connect a command line to the others of a pipeline,
+/// instead of the stdin of cchain and the captured stdout. They are
+/// used by one attempt, and are not kept by clones.
+#[derive(Debug, Default)]
+pub struct StreamEnds {
+    pub stdin: Option<PipeReader>,
+    pub stdout: Option<PipeWriter>,
+}
+
+impl Clone for StreamEnds {
+    fn clone(&self) -> Self {
+        Self::default()
+    }
+}
+
+/// Pipe ends do not take part in comparing the command lines that hold them
+impl PartialEq for StreamEnds {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for StreamEnds {}
+
+/// Quote a string for POSIX shells, so that it is passed as a single
+/// word with no expansion. Strings with only safe characters are kept as is.
+pub fn quote_for_shell(s: &str) -> String {
+    let is_safe: bool = !s.is_empty()
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c));
+    if is_safe {
+        return s.to_string();
+    }
+
+    format!("'{}'", s.replace('\'', "'\\''"))
+}
+
+/// How a command line is rendered in messages
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderStyle {
+    /// Every word, quoted for the shell, so that the command can be
+    /// copied and run as it is. Used by `--dry-run`.
+    Full,
+    /// The command and its first arguments on one line, with the values
+    /// of secrets hidden, capped to the width of the terminal. Used by
+    /// the messages about starting and finishing commands.
+    Summary,
+    /// Like `Full`, with the values of secrets hidden
+    Masked,
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct CommandLineExecutionResult {
     output: String,
@@ -28,25 +194,90 @@ impl CommandLineExecutionResult {
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, Serialize)]
 pub struct CommandLine {
     /// The command to execute.
     /// This should be the path or name of the program.
+    #[serde(default)]
     command: String,
     /// A list of arguments to pass to the program.
-    arguments: Vec<String>,
+    #[serde(default)]
+    arguments: Vec<Argument>,
     /// Allow for declaring the type of interpreter to use when
     /// running a command.
     interpreter: Option<Interpreter>,
     /// Optional environment variable overrides.
     /// Each entry maps a variable name to its override value for this
-    /// execution.
-    environment_variables_override: Option<HashMap<String, String>>,
+    /// execution. A null value removes the variable from the environment
+    /// of the command, which some programs treat differently from empty.
+    environment_variables_override: Option<HashMap<String, Option<String>>>,
     /// Set the working directory for this program. 
     /// Null means the current working directory.
     working_directory: Option<String>,
+    /// Run the command as another user. Null means the current user.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    run_as: Option<String>,
+    /// The command used to run as another user when cchain is
+    /// unprivileged. This is set from the chain metadata.
+    #[serde(skip)]
+    escalation_command: Option<String>,
+    /// Environment variables pinned by the chain metadata, e.g. `TZ`.
+    /// `environment_variables_override` takes precedence over them.
+    #[serde(skip)]
+    pinned_environment: BTreeMap<String, String>,
+    /// Skip the messages about starting and finishing the command,
+    /// e.g. while the same failure repeats
+    #[serde(skip)]
+    quiet: bool,
+    /// How much of the output is shown as it is read
+    #[serde(skip)]
+    display_output: DisplayOutput,
+    /// Lets the coordinator of a fail-fast concurrency group kill the
+    /// process while it runs
+    #[serde(skip)]
+    cancellation: Option<CancellationHandle>,
+    /// Reports the starts and the output of the command as events of
+    /// the program with the index
+    #[serde(skip)]
+    event_sender: Option<(EventSender, usize)>,
+    /// Connects the command line to the others of a pipeline
+    #[serde(skip)]
+    stream_ends: StreamEnds,
+    /// Values of the variables declared as `secret` that were injected,
+    /// which are hidden when the command line is rendered
+    #[serde(skip)]
+    secret_values: Vec<String>,
+    /// Sample the disk, I/O and CPU usage of the processes, with
+    /// `--profile-io`
+    #[serde(skip)]
+    profile_io: bool,
+    /// The usage of the last execution, when it was sampled
+    #[serde(skip)]
+    io_usage: Option<IoUsage>,
+}
+
+/// Command lines are compared by what they run. The handles to the run
+/// in progress, e.g. the event sender, do not take part.
+impl PartialEq for CommandLine {
+    fn eq(&self, other: &Self) -> bool {
+        self.command == other.command
+            && self.arguments == other.arguments
+            && self.interpreter == other.interpreter
+            && self.environment_variables_override == other.environment_variables_override
+            && self.working_directory == other.working_directory
+            && self.run_as == other.run_as
+            && self.escalation_command == other.escalation_command
+            && self.pinned_environment == other.pinned_environment
+            && self.quiet == other.quiet
+            && self.display_output == other.display_output
+            && self.secret_values == other.secret_values
+            && self.profile_io == other.profile_io
+            && self.io_usage == other.io_usage
+    }
 }
 
+impl Eq for CommandLine {}
+
 impl Default for CommandLine {
     fn default() -> Self {
         CommandLine {
@@ -55,6 +286,17 @@ impl Default for CommandLine {
             interpreter: None,
             environment_variables_override: None,
             working_directory: None,
+            run_as: None,
+            escalation_command: None,
+            pinned_environment: BTreeMap::new(),
+            quiet: false,
+            display_output: DisplayOutput::Full,
+            cancellation: None,
+            event_sender: None,
+            stream_ends: StreamEnds::default(),
+            secret_values: Vec::new(),
+            profile_io: false,
+            io_usage: None,
         }
     }
 }
@@ -69,56 +311,417 @@ impl CommandLine {
     ) -> Self {
         Self {
             command,
-            arguments,
+            arguments: arguments.into_iter().map(Argument::from).collect(),
             interpreter,
-            environment_variables_override,
-            working_directory
+            environment_variables_override: environment_variables_override
+                .map(|env_vars| env_vars.into_iter().map(|(name, value)| (name, Some(value))).collect()),
+            working_directory,
+            run_as: None,
+            escalation_command: None,
+            pinned_environment: BTreeMap::new(),
+            quiet: false,
+            display_output: DisplayOutput::Full,
+            cancellation: None,
+            event_sender: None,
+            stream_ends: StreamEnds::default(),
+            secret_values: Vec::new(),
+            profile_io: false,
+            io_usage: None,
+        }
+    }
+
+    /// Run the command as another user
+    pub fn with_run_as(mut self, user: String) -> Self {
+        self.run_as = Some(user);
+        self
+    }
+
+    pub fn set_quiet(&mut self, quiet: bool) {
+        self.quiet = quiet;
+    }
+
+    /// Show less of the output as it is read. The output is still
+    /// captured in full.
+    pub fn set_display_output(&mut self, display_output: DisplayOutput) {
+        self.display_output = display_output;
+    }
+
+    /// Sample the disk, I/O and CPU usage of the processes of the
+    /// command while it runs
+    pub fn set_profile_io(&mut self, profile_io: bool) {
+        self.profile_io = profile_io;
+    }
+
+    /// Take the usage sampled in the last execution
+    pub fn take_io_usage(&mut self) -> Option<IoUsage> {
+        self.io_usage.take()
+    }
+
+    /// Let a concurrency group cancel the command while it runs
+    pub fn set_cancellation(&mut self, cancellation: Option<CancellationHandle>) {
+        self.cancellation = cancellation;
+    }
+
+    /// Report the starts and the output of the command as events of a program
+    pub fn set_event_sender(&mut self, event_sender: EventSender, program_index: usize) {
+        self.event_sender = Some((event_sender, program_index));
+    }
+
+    pub fn get_event_sender(&self) -> Option<&(EventSender, usize)> {
+        self.event_sender.as_ref()
+    }
+
+    /// Connect the stdin and the stdout of the next execution to pipes
+    pub fn set_stream_ends(&mut self, stream_ends: StreamEnds) {
+        self.stream_ends = stream_ends;
+    }
+
+    /// Hide a value in the rendered command line, e.g. the value of a
+    /// secret injected into the arguments
+    pub fn add_secret_value(&mut self, value: String) {
+        if !value.is_empty() && !self.secret_values.contains(&value) {
+            self.secret_values.push(value);
+        }
+    }
+
+    /// Set the command used to run as another user, e.g. `doas`
+    pub fn set_escalation_command(&mut self, escalation_command: String) {
+        self.escalation_command = Some(escalation_command);
+    }
+
+    /// Set the environment variables pinned by the chain metadata
+    pub fn set_pinned_environment(&mut self, pinned_environment: BTreeMap<String, String>) {
+        self.pinned_environment = pinned_environment;
+    }
+
+    /// Decide how the command runs as the user in `run_as`
+    pub fn resolve_privilege_change(&self) -> Result<PrivilegeChange, Error> {
+        match &self.run_as {
+            Some(user) => PrivilegeChange::resolve(user, self.escalation_command.as_deref()),
+            None => Ok(PrivilegeChange::Unchanged),
+        }
+    }
+
+    /// Get the program and the arguments to spawn, before any
+    /// privilege change
+    pub fn get_argv(&self) -> Vec<String> {
+        match self.interpreter {
+            Some(Interpreter::Sh) => {
+                // Use `sh` if the user has specified.
+                vec!["sh".to_string(), "-c".to_string(), self.get_shell_command_line()]
+            }
+            _ => {
+                // On non-Unix systems and not specified cases, execute the command directly.
+                let mut argv: Vec<String> = vec![self.command.clone()];
+                argv.extend(self.arguments.iter().map(|argument| argument.get_value().to_string()));
+                argv
+            }
+        }
+    }
+
+    /// Get the command line handed to `sh -c`. Plain arguments are quoted,
+    /// and raw arguments are kept as they are.
+    pub fn get_shell_command_line(&self) -> String {
+        let mut words: Vec<String> = vec![self.command.clone()];
+        words.extend(self.arguments.iter().map(|argument| argument.to_shell_word()));
+        words.join(" ")
+    }
+
+    /// Render the command line in a style. Plain arguments are quoted as
+    /// they are for `sh -c`, so that `echo "a b"` and `echo a b` differ.
+    /// Summaries fit the current width of the terminal.
+    pub fn render_command(&self, style: RenderStyle) -> String {
+        self.render_command_with_width(style, get_terminal_width())
+    }
+
+    /// Render the command line in a style, with summaries cut to the width
+    pub fn render_command_with_width(&self, style: RenderStyle, width: usize) -> String {
+        match (style, &self.interpreter) {
+            // Summaries under an interpreter show the words of the shell
+            // command line, rather than one long `sh -c` argument
+            (RenderStyle::Summary, Some(_)) => {
+                let mut words: Vec<String> = vec![self.command.clone()];
+                words.extend(self.arguments.iter().map(|argument| argument.to_shell_word()));
+                self.summarize_words(&words, width)
+            }
+            _ => self.render_argv(&self.get_argv(), style, width),
+        }
+    }
+
+    /// Render the words of a command line, e.g. wrapped by an escalation command
+    fn render_argv(&self, argv: &[String], style: RenderStyle, width: usize) -> String {
+        let words: Vec<String> = argv.iter().map(|word| quote_for_shell(word)).collect();
+        match style {
+            RenderStyle::Full => words.join(" "),
+            RenderStyle::Masked => self.mask_secrets(&words.join(" ")),
+            RenderStyle::Summary => self.summarize_words(&words, width),
+        }
+    }
+
+    /// Show the command and its first arguments on one line, hiding secrets
+    fn summarize_words(&self, words: &[String], width: usize) -> String {
+        let shown_count: usize = words.len().min(SUMMARY_ARGUMENT_COUNT + 1);
+        let head: String = self
+            .mask_secrets(&words[..shown_count].join(" "))
+            .replace('\n', "\\n")
+            .replace('\r', "\\r");
+        let hidden_count: usize = words.len() - shown_count;
+        let suffix: String = if hidden_count > 0 { format!(" … (+{} more)", hidden_count) } else { String::new() };
+        let width: usize = width.saturating_sub(suffix.chars().count()).max(SUMMARY_ARGUMENT_COUNT);
+
+        format!("{}{}", truncate_value(&head, width), suffix)
+    }
+
+    /// Replace the values of secrets in rendered words, including the
+    /// form that they take inside single quotes
+    fn mask_secrets(&self, text: &str) -> String {
+        let mut text: String = text.to_string();
+        for secret in &self.secret_values {
+            text = text.replace(secret.as_str(), SECRET_MASK);
+            let quoted_secret: String = secret.replace('\'', "'\\''");
+            if quoted_secret != *secret {
+                text = text.replace(&quoted_secret, SECRET_MASK);
+            }
+        }
+
+        text
+    }
+
+    /// Find the arguments whose shell metacharacters may be ambiguous
+    /// under the interpreter, and explain how they are handled
+    pub fn lint(&self) -> Vec<String> {
+        let mut messages: Vec<String> = Vec::new();
+        if self.interpreter.is_none() {
+            return messages;
+        }
+
+        for argument in &self.arguments {
+            let metacharacters: Vec<&str> = argument.get_shell_metacharacters();
+            if metacharacters.is_empty() {
+                continue;
+            }
+
+            let metacharacters: String = metacharacters
+                .iter()
+                .map(|metacharacter| format!("`{}`", metacharacter))
+                .collect::<Vec<String>>()
+                .join(", ");
+            if argument.is_raw() {
+                messages.push(format!(
+                    "Argument `{}` is raw, so {} will be interpreted by the shell",
+                    argument, metacharacters
+                ));
+            } else {
+                messages.push(format!(
+                    "Argument `{}` contains {}, which will be escaped and passed literally. Write it as {{\"raw\": \"{}\"}} if the shell should interpret it",
+                    argument, metacharacters, argument
+                ));
+            }
+        }
+
+        messages
+    }
+
+    /// Summarize the command that is actually run, including the
+    /// escalation command or the user it runs as
+    pub fn get_effective_command_line(&self, privilege_change: &PrivilegeChange) -> String {
+        match privilege_change {
+            PrivilegeChange::Unchanged => self.render_command(RenderStyle::Summary),
+            PrivilegeChange::Drop { user, .. } => format!("{} (as {})", self.render_command(RenderStyle::Summary), user),
+            PrivilegeChange::Escalate { .. } => {
+                self.render_argv(&privilege_change.wrap_argv(self.get_argv()), RenderStyle::Summary, get_terminal_width())
+            }
         }
     }
     
-    /// Constructs a Tokio process command to execute the configured program.
+    /// Constructs a process command to execute the configured program.
     ///
-    /// It determines the interpreter to use based on the user specification.
+    /// It determines the interpreter to use based on the user specification,
+    /// and applies the privilege change for `run_as`.
     ///
     /// Additionally, if the `environment_variables_override` field is set, its environment variables
     /// are applied to the command.
-    pub fn get_process_command(&mut self) -> Command {
-        let mut command: Command = match self.interpreter {
-            Some(Interpreter::Sh) => {
-                // Use `sh` if the user has specified.
-                let mut cmd = Command::new("sh");
-                
-                let command_line: String = {
-                    let command: String = self.get_command().to_string();
-                    let arguments: String = self.get_arguments().join(" ");
-                    format!("{} {}", command, arguments)
-                };
-                cmd.arg("-c").arg(command_line);
-                cmd
+    pub fn get_process_command(&mut self) -> Result<Command, Error> {
+        let privilege_change: PrivilegeChange = self.resolve_privilege_change()?;
+        Ok(self.build_process_command(&privilege_change))
+    }
+
+    /// Tell why a process could not be started, and suggest a fix
+    fn diagnose_spawn_error(&self, program: &str, error: &std::io::Error) -> (SpawnErrorKind, Option<String>) {
+        // The working directory is checked first, as a missing one is
+        // also reported as `NotFound`
+        if let Some(working_directory) = &self.working_directory {
+            if !Path::new(working_directory).is_dir() {
+                return (
+                    SpawnErrorKind::InvalidWorkingDirectory,
+                    Some(format!("The working directory `{}` does not exist or is not a directory", working_directory)),
+                );
             }
-            _ => {
-                // On non-Unix systems and not specified cases, execute the command directly.
-                let mut cmd = Command::new(self.get_command());
-                cmd.args(self.get_arguments());
-                cmd
+        }
+
+        match error.kind() {
+            std::io::ErrorKind::NotFound if program.contains(std::path::MAIN_SEPARATOR) || program.contains('/') => {
+                (SpawnErrorKind::NotFound, Some(format!("`{}` does not exist", program)))
             }
-        };
+            std::io::ErrorKind::NotFound => {
+                // Listing the commands is part of package checking
+                let suggestion: Option<String> = Feature::PackageCheck
+                    .is_enabled()
+                    .then(Package::get_available_packages)
+                    .and_then(Result::ok)
+                    .and_then(|packages| {
+                        Package::find_similar_package(program, &packages)
+                            .map(|package| package.access_package_name().to_string())
+                    });
+                (SpawnErrorKind::NotFound, Some(get_not_found_cause(program, suggestion.as_deref())))
+            }
+            std::io::ErrorKind::PermissionDenied => (
+                SpawnErrorKind::PermissionDenied,
+                Some(format!("`{}` is not executable, e.g. `chmod +x {}` may be missing", program, program)),
+            ),
+            // The standard library refuses arguments that `cmd` would not
+            // take literally
+            std::io::ErrorKind::InvalidInput if is_batch_script(Path::new(program)) => (
+                SpawnErrorKind::Other,
+                Some(format!("The arguments cannot be passed safely to the batch script `{}`, e.g. because of a newline or `%`", program)),
+            ),
+            _ => (SpawnErrorKind::Other, None),
+        }
+    }
+
+    /// Get how the environment of the command differs from the environment
+    /// of cchain, from the command that would be started
+    pub fn get_environment_changes(&mut self) -> Result<Vec<EnvironmentChange>, Error> {
+        let command: Command = self.get_process_command()?;
+        Ok(diff_environment(&get_current_environment(), &command))
+    }
+
+    /// Render the changes of the environment, one per line, with the
+    /// values of secrets masked
+    pub fn render_environment_changes(&self, changes: &[EnvironmentChange]) -> Vec<String> {
+        changes.iter().map(|change| change.render(|value| self.mask_secrets(value))).collect()
+    }
+
+    /// Get the value of an environment variable as the program gets it:
+    /// from the environment overrides, the pinned environment of the
+    /// chain, or else the environment of cchain
+    fn get_effective_env_var(&self, name: &str) -> Option<std::ffi::OsString> {
+        if let Some(value) = self.environment_variables_override.as_ref().and_then(|env_vars| env_vars.get(name)) {
+            return value.as_ref().map(std::ffi::OsString::from);
+        }
+        if let Some(value) = self.pinned_environment.get(name) {
+            return Some(value.into());
+        }
+
+        std::env::var_os(name)
+    }
+
+    fn build_process_command(&self, privilege_change: &PrivilegeChange) -> Command {
+        let mut argv: Vec<String> = privilege_change.wrap_argv(self.get_argv());
+        // Windows only finds `.exe` files by itself, so the command is
+        // resolved with `PATHEXT` like `cmd` does, in the environment and
+        // the working directory of the program
+        let lookup: ExecutableLookup = ExecutableLookup::from_pathext(self.get_effective_env_var("PATHEXT").as_deref());
+        if lookup != ExecutableLookup::Unix {
+            // A removed `PATH` leaves nothing to search
+            let path: std::ffi::OsString = self.get_effective_env_var("PATH").unwrap_or_default();
+            let mut current_directory: std::path::PathBuf = std::env::current_dir().unwrap_or_default();
+            if let Some(working_directory) = &self.working_directory {
+                current_directory = current_directory.join(simplify_windows_path(working_directory));
+            }
+            argv = resolve_argv(argv, &lookup, &path, &current_directory);
+        }
+        let mut command: Command = Command::new(&argv[0]);
+        command.args(&argv[1..]);
+        privilege_change.apply(&mut command);
         
-        // Set the working directory for the command.
+        // Set the working directory for the command. Verbatim Windows
+        // paths are simplified, as `cmd` does not accept them.
         if let Some(working_directory) = &self.working_directory {
-            command.current_dir(working_directory);
+            command.current_dir(simplify_windows_path(working_directory));
         }
 
-        // Override environment variables if provided.
-        if let Some(ref env_vars) = self.environment_variables_override {
-            command.envs(env_vars);
+        // Override environment variables if provided. The variables
+        // pinned by the chain are set first, so that a program can
+        // override or remove them too.
+        command.envs(&self.pinned_environment);
+        for (name, value) in self.environment_variables_override.iter().flatten() {
+            match value {
+                Some(value) => command.env(name, value),
+                None => command.env_remove(name),
+            };
         }
 
         command
     }
 
+    fn display_environment_changes(&self, changes: &[EnvironmentChange]) {
+        if changes.is_empty() {
+            display_message(Level::Logging, "The environment is the same as the one of cchain");
+            return;
+        }
+        display_message(Level::Logging, &format!("The environment differs from the one of cchain in {} variable(s):", changes.len()));
+        display_command_line(&Term::stdout(), &self.render_environment_changes(changes).join("\n"));
+    }
+
+    /// Get the values that may be function calls, labeled by their
+    /// fields, e.g. `arguments[0]` or `environment_variables_override.TOKEN`
+    pub fn get_function_fields(&self) -> Vec<(String, &str)> {
+        let mut fields: Vec<(String, &str)> = self
+            .arguments
+            .iter()
+            .enumerate()
+            .map(|(index, argument)| (format!("arguments[{}]", index), argument.get_value()))
+            .collect();
+
+        let mut environment_variables: Vec<(&String, &String)> = self
+            .environment_variables_override
+            .iter()
+            .flatten()
+            .filter_map(|(name, value)| Some((name, value.as_ref()?)))
+            .collect();
+        environment_variables.sort();
+        for (name, value) in environment_variables {
+            fields.push((format!("environment_variables_override.{}", name), value.as_str()));
+        }
+
+        if let Some(working_directory) = &self.working_directory {
+            fields.push(("working_directory".to_string(), working_directory.as_str()));
+        }
+
+        fields
+    }
+
+    /// Get the values that may be function calls for replacing them with
+    /// the results, in the order of `get_function_fields`
+    pub fn get_function_fields_mut(&mut self) -> Vec<(String, &mut String)> {
+        let mut fields: Vec<(String, &mut String)> = self
+            .arguments
+            .iter_mut()
+            .enumerate()
+            .map(|(index, argument)| (format!("arguments[{}]", index), argument.get_value_mut()))
+            .collect();
+
+        let mut environment_variables: Vec<(&String, &mut String)> = self
+            .environment_variables_override
+            .iter_mut()
+            .flatten()
+            .filter_map(|(name, value)| Some((name, value.as_mut()?)))
+            .collect();
+        environment_variables.sort_by_key(|(name, _)| name.as_str());
+        for (name, value) in environment_variables {
+            fields.push((format!("environment_variables_override.{}", name), value));
+        }
+
+        if let Some(working_directory) = &mut self.working_directory {
+            fields.push(("working_directory".to_string(), working_directory));
+        }
+
+        fields
+    }
+
     pub fn revise_argument_by_index(&mut self, argument_index: usize, new_argument: String) {
-        self.arguments[argument_index] = new_argument;
+        self.arguments[argument_index].set_value(new_argument);
     }
 
     pub fn inject_value_to_variables(
@@ -126,28 +729,108 @@ impl CommandLine {
         raw_variable_name: &str,
         value: String,
     ) -> Result<(), Error> {
-        // For `on_program_execution` variables, there might be subsequent variables that
-        // does not have the suffix, but are still expected to have values. 
-        // Hence, we should create two variable names to check if it is contained. 
-        let mut raw_variable_name_without_suffix: String = raw_variable_name.split(":").collect::<Vec<&str>>()[0].to_string();
-        raw_variable_name_without_suffix.push_str(">>");
-        
+        // Escaped `<<<<` are kept, and the value is escaped, so that
+        // `unescape_placeholders` gives it back as it is.
+        let mut replace = |text: &str| Variable::replace_variable_placeholders(text, raw_variable_name, &value);
+        // The working directory and the environment overrides take the
+        // values as the arguments do
+        for (_, field) in self.get_function_fields_mut() {
+            // The variables that function calls take as parameters are
+            // resolved when the functions run, as their values may not be
+            // valid in the text of a call
+            *field = match Function::from_str(field) {
+                Ok(mut function) if !function.get_variable_references().is_empty() => {
+                    function.inject_literal_placeholders(&mut replace);
+                    function.to_string()
+                }
+                _ => replace(field),
+            };
+        }
+
+        Ok(())
+    }
+
+    /// Get the values that may hold placeholders of variables: the
+    /// arguments, the values of the environment overrides and the working
+    /// directory
+    pub fn get_placeholder_values(&self) -> Vec<&str> {
+        self.get_function_fields().into_iter().map(|(_, value)| value).collect()
+    }
+
+    /// Rewrite the placeholders of the arguments, the environment
+    /// overrides and the working directory from custom delimiters to the
+    /// default ones
+    pub fn apply_variable_delimiters(&mut self, variable_delimiters: &VariableDelimiters) {
+        self.rewrite_values(&mut |value| variable_delimiters.to_default_syntax(value));
+    }
+
+    /// Replace each argument with what the closure returns for it
+    pub fn rewrite_arguments(&mut self, rewrite: &mut impl FnMut(&str) -> String) {
         for argument in &mut self.arguments {
-            // If the new_argument is exactly the same as the argument, 
-            // it means that the replace has failed. 
-            // Then, we should use a modified string to replace the var. 
-            // And if that replacement still fails, it means they are not meant to be replaced
-            if argument.contains(&raw_variable_name) {
-                *argument = argument.replace(raw_variable_name, &value);    
-                continue;
+            let value: String = rewrite(argument.get_value());
+            argument.set_value(value);
+        }
+    }
+
+    /// Replace each argument, each value of the environment overrides and
+    /// the working directory with what the closure returns for it
+    pub fn rewrite_values(&mut self, rewrite: &mut impl FnMut(&str) -> String) {
+        self.rewrite_arguments(rewrite);
+        for value in self.environment_variables_override.iter_mut().flat_map(|overrides| overrides.values_mut()).flatten() {
+            *value = rewrite(value);
+        }
+        if let Some(working_directory) = &mut self.working_directory {
+            *working_directory = rewrite(working_directory);
+        }
+    }
+
+    /// Turn the escaped `<<<<` in the arguments, the environment overrides
+    /// and the working directory into literal `<<`, once the values and
+    /// expressions are injected
+    pub fn unescape_placeholders(&mut self) {
+        self.rewrite_values(&mut |value| Variable::unescape_placeholders(value));
+    }
+
+    /// Evaluate the expression placeholders in the arguments.
+    ///
+    /// An argument that consists of a single expression evaluating to an
+    /// empty string is removed, so that a conditional flag like
+    /// `<<verbose ? --verbose : >>` produces no argument when false.
+    pub fn inject_expressions(
+        &mut self,
+        values: &HashMap<String, TypedValue>,
+    ) -> Result<(), Error> {
+        let mut arguments: Vec<Argument> = Vec::new();
+
+        for argument in &self.arguments {
+            let mut injected_argument: String = argument.get_value().to_string();
+            let mut is_single_expression: bool = false;
+
+            for raw_variable in Variable::extract_variable_names(argument.get_value()) {
+                if !Expression::is_expression(raw_variable) {
+                    continue;
+                }
+
+                let expression = Expression::from_str(raw_variable)?;
+                let placeholder: String = format!("<<{}>>", raw_variable);
+                is_single_expression = argument.get_value().trim() == placeholder;
+                let evaluated: String = Variable::escape_placeholders(&expression.evaluate(values)?);
+                injected_argument = Variable::replace_placeholders(&injected_argument, |content| {
+                    (content == raw_variable).then(|| evaluated.clone())
+                });
             }
-            
-            if argument.contains(&raw_variable_name_without_suffix) {
-                // Try replacing the var with a modified string
-                *argument = argument.replace(&raw_variable_name_without_suffix, &value);
+
+            if is_single_expression && injected_argument.is_empty() {
+                continue;
             }
+
+            let mut argument: Argument = argument.clone();
+            argument.set_value(injected_argument);
+            arguments.push(argument);
         }
 
+        self.arguments = arguments;
+
         Ok(())
     }
 
@@ -155,7 +838,47 @@ impl CommandLine {
         &self.command
     }
 
-    pub fn get_arguments(&mut self) -> &mut Vec<String> {
+    pub fn get_interpreter(&self) -> Option<&Interpreter> {
+        self.interpreter.as_ref()
+    }
+
+    /// Get the user that the command runs as, if it is not the current one
+    pub fn get_run_as(&self) -> Option<&String> {
+        self.run_as.as_ref()
+    }
+
+    /// Get the names of the environment variables that the command
+    /// removes, in alphabetical order
+    pub fn get_removed_environment_variables(&self) -> Vec<&String> {
+        let mut names: Vec<&String> = self
+            .environment_variables_override
+            .iter()
+            .flatten()
+            .filter(|(_, value)| value.is_none())
+            .map(|(name, _)| name)
+            .collect();
+        names.sort();
+
+        names
+    }
+
+    pub fn get_working_directory(&self) -> Option<&String> {
+        self.working_directory.as_ref()
+    }
+
+    /// Resolve the command, when it is written as a path like `./fix.sh`,
+    /// and the working directory against the base directory of the chain.
+    /// Command names are still looked up in `PATH`.
+    pub fn resolve_relative_paths(&mut self, base_directory: &Path) {
+        if is_chain_relative_command(&self.command) {
+            self.command = resolve_chain_relative_path(&self.command, base_directory);
+        }
+        if let Some(working_directory) = &mut self.working_directory {
+            *working_directory = resolve_chain_relative_path(working_directory, base_directory);
+        }
+    }
+
+    pub fn get_arguments(&mut self) -> &mut Vec<Argument> {
         &mut self.arguments
     }
 }
@@ -166,32 +889,63 @@ impl Execution<CommandLineExecutionResult> for CommandLine {
     }
 
     fn execute(&mut self) -> Result<Vec<CommandLineExecutionResult>, Error> {
-        let mut command: Command = self.get_process_command();
+        // Take the pipe ends first, so that they are closed even when the
+        // command cannot start, and the rest of the pipeline does not hang
+        let StreamEnds { stdin: stream_stdin, stdout: stream_stdout } = std::mem::take(&mut self.stream_ends);
+        self.io_usage = None;
+        let privilege_change: PrivilegeChange = self.resolve_privilege_change()?;
+        privilege_change.check_non_interactive()?;
+        let mut command: Command = self.build_process_command(&privilege_change);
+        if let Some(cancellation) = &self.cancellation {
+            cancellation.prepare_command(&mut command);
+        }
         
-        // Set stdout to piped so that we can capture it
-        command.stdout(std::process::Stdio::piped());
+        // Set stdout to piped so that we can capture it, unless it
+        // streams to the next program of a pipeline
+        if let Some(stdin) = stream_stdin {
+            command.stdin(stdin);
+        }
+        match stream_stdout {
+            Some(stdout) => command.stdout(stdout),
+            None => command.stdout(std::process::Stdio::piped()),
+        };
         command.stderr(std::process::Stdio::piped());
-        let command_in_text: String = format!(r#"{}"#, &self.to_string());
+        let command_in_text: String = self.get_effective_command_line(&privilege_change);
         let command_string: &StyledObject<&String> = &console::style(&command_in_text).bold();
-        display_message(
-            Level::Logging, 
-            &format!("Start executing command: {}", command_string)
-        );
+        if !self.quiet && !is_summary_only() {
+            display_message(
+                Level::Logging, 
+                &format!("Start executing command: {}", command_string)
+            );
+            if is_environment_diff_shown() {
+                self.display_environment_changes(&diff_environment(&get_current_environment(), &command));
+            }
+        }
     
         // Spawn the process
-        let mut child = command.spawn().map_err(|e| {
-            Error::msg(format!(
-                "Failed to execute {}: {}",
-                self.get_execution_type(),
-                e
-            ))
+        let mut child = command.spawn().map_err(|error| {
+            let program: String = command.get_program().to_string_lossy().into_owned();
+            let (kind, cause): (SpawnErrorKind, Option<String>) = self.diagnose_spawn_error(&program, &error);
+            let mut message: String = format!("Failed to execute {}: {}", self.get_execution_type(), error);
+            if let Some(cause) = cause {
+                message.push_str(&format!(". {}", cause));
+            }
+            CommandLineError::SpawnFailed { kind, message }
         })?;
+        // Close the pipe ends held by the command, so that the next
+        // program of a pipeline sees the end of the stream when this one exits
+        drop(command);
+        if let Some(cancellation) = &self.cancellation {
+            cancellation.register_process(child.id());
+        }
+        register_running_process(child.id());
+        let io_profiler: Option<IoProfiler> = if self.profile_io { IoProfiler::start(child.id()) } else { None };
+        if let Some((event_sender, program_index)) = &self.event_sender {
+            event_sender.send(ChainEvent::ProgramStarted { program_index: *program_index });
+        }
     
         // Take the stdout handle
-        let stdout = child
-            .stdout
-            .take()
-            .unwrap();
+        let stdout = child.stdout.take();
         // Take the stderr handle
         let stderr = child
             .stderr
@@ -203,55 +957,102 @@ impl Execution<CommandLineExecutionResult> for CommandLine {
         // Spawn a thread to read stdout
         let tx_clone = tx.clone();
         std::thread::spawn(move || {
+            let Some(stdout) = stdout else {
+                return;
+            };
             let mut reader = BufReader::new(stdout);
             let mut buffer = [0; 1024];
+            let mut chunks = OutputChunkBuffer::new();
             loop {
                 match reader.read(&mut buffer) {
                     Ok(0) => break, // EOF
                     Ok(n) => {
-                        let text = String::from_utf8_lossy(&buffer[..n]).to_string();
-                        tx_clone.send(text).unwrap();
+                        let text = chunks.push(&buffer[..n]);
+                        if !text.is_empty() {
+                            tx_clone.send(text).unwrap();
+                        }
                     },
                     Err(_) => break,
                 }
             }
+            let text = chunks.finish();
+            if !text.is_empty() {
+                tx_clone.send(text).unwrap();
+            }
         });
     
         // Spawn a thread to read stderr
         std::thread::spawn(move || {
             let mut reader = BufReader::new(stderr);
             let mut buffer = [0; 1024];
+            let mut chunks = OutputChunkBuffer::new();
             loop {
                 match reader.read(&mut buffer) {
                     Ok(0) => break, // EOF
                     Ok(n) => {
-                        let text = String::from_utf8_lossy(&buffer[..n]).to_string();
-                        tx.send(text).unwrap();
+                        let text = chunks.push(&buffer[..n]);
+                        if !text.is_empty() {
+                            tx.send(text).unwrap();
+                        }
                     },
                     Err(_) => break,
                 }
             }
+            let text = chunks.finish();
+            if !text.is_empty() {
+                tx.send(text).unwrap();
+            }
         });
         
         let mut collected_output = String::new();
         let terminal = Term::stdout();
+        // `--summary-only` shows none of the output
+        let display_output: DisplayOutput = if is_summary_only() { DisplayOutput::Silent } else { self.display_output };
+        let mut live_output = LiveOutputFilter::new(display_output);
         for received in rx {
-            display_command_line(&terminal, &received);
+            display_command_line(&terminal, &live_output.push(&received));
+            if let Some((event_sender, program_index)) = &self.event_sender {
+                event_sender.send(ChainEvent::ProgramOutput { program_index: *program_index, text: received.clone() });
+            }
             collected_output.push_str(&received);
         }
+        display_command_line(&terminal, &live_output.finish());
     
+        // The last reading is taken after the process exits, but before
+        // it is reaped
+        self.io_usage = io_profiler.and_then(IoProfiler::finish);
+
         // Wait for process completion
         let status = child.wait()
             .map_err(|e| Error::msg(format!("Failed to wait on child process: {}", e)))?;
+        unregister_running_process(child.id());
+        if let Some((event_sender, program_index)) = &self.event_sender {
+            event_sender.send(ChainEvent::ProcessExited {
+                program_index: *program_index,
+                exit_code: status.code(),
+                io_usage: self.io_usage,
+            });
+        }
+        if let Some(cancellation) = &self.cancellation {
+            cancellation.unregister_process();
+            if !status.success() && cancellation.is_cancelled() {
+                return Err(CommandLineError::Cancelled { output: collected_output }.into());
+            }
+        }
         
         if !status.success() {
-            return Err(Error::msg(format!(
-                "Process exited with non-zero status: {}",
-                status
-            )));
+            return Err(CommandLineError::NonZeroExit {
+                status: status.to_string(),
+                exit_code: status.code(),
+                output: collected_output,
+                termination: ProcessTermination::from_exit_status(&status),
+            }.into());
         }
     
-        display_message(Level::Logging, &format!("Finished executing command: {}", command_string));
+        if !self.quiet && !is_summary_only() {
+            let io_usage: String = self.io_usage.map(|io_usage| format!(" ({})", io_usage)).unwrap_or_default();
+            display_message(Level::Logging, &format!("Finished executing command: {}{}", command_string, io_usage));
+        }
     
         Ok(vec![CommandLineExecutionResult::new(collected_output)])
     }
@@ -259,6 +1060,16 @@ impl Execution<CommandLineExecutionResult> for CommandLine {
 
 impl std::fmt::Display for CommandLine {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(f, "{} {}", self.command, self.arguments.join(" "))
+        write!(f, "{}", self.render_command(RenderStyle::Summary))
     }
 }
+
+/// Tell that a command is not found in `PATH`, with the closest
+/// available command as a suggestion if there is one
+pub fn get_not_found_cause(program: &str, suggestion: Option<&str>) -> String {
+    match suggestion {
+        Some(suggestion) => format!("`{}` is not found in PATH. Did you mean `{}`?", program, suggestion),
+        None => format!("`{}` is not found in PATH", program),
+    }
+}
+
@@ -0,0 +1,85 @@
+use std::process::{Command, ExitStatus};
+
+use anyhow::{anyhow, Error, Result};
+
+/// Prefix of the environment variables holding the values of the chain
+/// variables in a debug shell
+pub const DEBUG_VARIABLE_PREFIX: &str = "CCHAIN_VAR_";
+
+/// The environment variable holding the command line of the failed program
+pub const DEBUG_FAILED_COMMAND: &str = "CCHAIN_FAILED_COMMAND";
+
+/// Settings of the shell offered when a program fails,
+/// with `cchain run --debug-on-failure`
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DebugShellOptions {
+    /// Export the values of the variables declared as `secret` too
+    expose_secrets: bool,
+    /// The shell to run. By default, `$SHELL`, or `sh` without it
+    shell: Option<String>,
+}
+
+impl DebugShellOptions {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_expose_secrets(mut self, expose_secrets: bool) -> Self {
+        self.expose_secrets = expose_secrets;
+        self
+    }
+
+    pub fn with_shell(mut self, shell: String) -> Self {
+        self.shell = Some(shell);
+        self
+    }
+
+    pub fn is_exposing_secrets(&self) -> bool {
+        self.expose_secrets
+    }
+
+    /// Get the shell to run
+    pub fn get_shell(&self) -> String {
+        self.shell
+            .clone()
+            .or_else(|| std::env::var("SHELL").ok().filter(|shell| !shell.is_empty()))
+            .unwrap_or_else(|| "sh".to_string())
+    }
+
+    /// Run the shell in the working directory of the failed program, with
+    /// the environment, until the user exits it
+    pub fn open_shell(&self, working_directory: Option<&String>, environment: &[(String, String)]) -> Result<ExitStatus, Error> {
+        let shell: String = self.get_shell();
+        let mut command: Command = Command::new(&shell);
+        command.envs(environment.iter().map(|(name, value)| (name, value)));
+        if let Some(working_directory) = working_directory {
+            command.current_dir(working_directory);
+        }
+
+        command
+            .status()
+            .map_err(|error| anyhow!("Cannot start the debug shell `{}`: {}", shell, error))
+    }
+}
+
+/// What to do with a failed program after its debug shell exits
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DebugDecision {
+    /// Run the program again, as a new attempt
+    Retry,
+    /// Leave the program failed, and continue with the next one
+    Skip,
+    /// Stop the chain
+    Abort,
+}
+
+/// Get the name of the environment variable for a chain variable in a
+/// debug shell, e.g. `CCHAIN_VAR_RELEASE_TAG` for `release-tag`
+pub fn get_debug_variable_name(variable_name: &str) -> String {
+    let name: String = variable_name
+        .chars()
+        .map(|character| if character.is_ascii_alphanumeric() { character.to_ascii_uppercase() } else { '_' })
+        .collect();
+
+    format!("{}{}", DEBUG_VARIABLE_PREFIX, name)
+}
@@ -0,0 +1,78 @@
+use super::{command::CommandLine, privilege::ESCALATION_COMMANDS};
+
+/// Effects that programs are usually annotated with. Other values are
+/// accepted, but reported by `cchain check --lint`.
+pub const KNOWN_EFFECTS: &[&str] = &[
+    "read-only",
+    "writes-files",
+    "deletes-files",
+    "network",
+    "mutates-remote",
+    "privileged",
+];
+
+/// Effects that need `--allow-destructive` or a confirmation to run
+pub const DESTRUCTIVE_EFFECTS: &[&str] = &["deletes-files", "mutates-remote"];
+
+/// Commands that obviously delete or mutate things, as the words they are made of
+const DESTRUCTIVE_PATTERNS: &[&[&str]] = &[
+    &["rm", "-rf"],
+    &["rm", "-fr"],
+    &["rm", "-r"],
+    &["rm", "-R"],
+    &["kubectl", "delete"],
+    &["terraform", "destroy"],
+    &["drop", "table"],
+];
+
+/// Split a command line into the words that the heuristics look at,
+/// including the words of a command handed to a shell
+fn get_words(command_line: &CommandLine) -> Vec<String> {
+    command_line
+        .get_argv()
+        .iter()
+        .flat_map(|argument| argument.split_whitespace())
+        .map(|word| {
+            // Commands are compared by their names, wherever they are
+            let word: &str = word.trim_matches(|character| matches!(character, '"' | '\'' | ';' | '(' | ')'));
+            word.rsplit('/').next().unwrap_or(word).to_string()
+        })
+        .collect()
+}
+
+/// Find an obviously destructive command in a command line, e.g. `rm -rf`
+pub fn find_destructive_pattern(command_line: &CommandLine) -> Option<String> {
+    let words: Vec<String> = get_words(command_line);
+    DESTRUCTIVE_PATTERNS
+        .iter()
+        .find(|pattern| {
+            words.windows(pattern.len()).any(|window| {
+                window.iter().zip(pattern.iter()).all(|(word, expected)| {
+                    // Options are case-sensitive, SQL keywords are not
+                    if expected.starts_with('-') { word == expected } else { word.eq_ignore_ascii_case(expected) }
+                })
+            })
+        })
+        .map(|pattern| pattern.join(" "))
+}
+
+/// Guess why a program without `effects` may be destructive: it looks
+/// like a destructive command, removes files, or runs with privileges
+pub fn guess_destructive_reason(command_line: &CommandLine) -> Option<String> {
+    if let Some(pattern) = find_destructive_pattern(command_line) {
+        return Some(format!("runs `{}`", pattern));
+    }
+
+    let words: Vec<String> = get_words(command_line);
+    if words.iter().any(|word| word == "rm") {
+        return Some("runs `rm`".to_string());
+    }
+    if let Some(escalation_command) = words.iter().find(|word| ESCALATION_COMMANDS.contains(&word.as_str())) {
+        return Some(format!("runs `{}`", escalation_command));
+    }
+    if let Some(user) = command_line.get_run_as() {
+        return Some(format!("runs as {}", user));
+    }
+
+    None
+}
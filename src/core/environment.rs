@@ -0,0 +1,67 @@
+use std::{collections::BTreeMap, process::Command};
+
+use super::builtin::truncate_value;
+
+/// Characters of a value shown in a change of the environment
+pub const ENVIRONMENT_VALUE_WIDTH: usize = 60;
+
+/// How the environment of a program differs from the environment of cchain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvironmentChange {
+    Added { name: String, value: String },
+    Removed { name: String, value: String },
+    Changed { name: String, old_value: String, new_value: String },
+}
+
+impl EnvironmentChange {
+    pub fn get_name(&self) -> &str {
+        match self {
+            EnvironmentChange::Added { name, .. }
+            | EnvironmentChange::Removed { name, .. }
+            | EnvironmentChange::Changed { name, .. } => name,
+        }
+    }
+
+    /// Render the change on one line, e.g. `~ TZ: UTC -> Europe/Berlin`,
+    /// with the values masked and cut to `ENVIRONMENT_VALUE_WIDTH`
+    pub fn render(&self, mask: impl Fn(&str) -> String) -> String {
+        let show = |value: &str| truncate_value(&mask(value).replace('\n', "\\n"), ENVIRONMENT_VALUE_WIDTH);
+        match self {
+            EnvironmentChange::Added { name, value } => format!("+ {}={}", name, show(value)),
+            EnvironmentChange::Removed { name, .. } => format!("- {}", name),
+            EnvironmentChange::Changed { name, old_value, new_value } => {
+                format!("~ {}: {} -> {}", name, show(old_value), show(new_value))
+            }
+        }
+    }
+}
+
+/// Get the environment of cchain, with the names and values that are
+/// not Unicode converted lossily
+pub fn get_current_environment() -> BTreeMap<String, String> {
+    std::env::vars_os()
+        .map(|(name, value)| (name.to_string_lossy().into_owned(), value.to_string_lossy().into_owned()))
+        .collect()
+}
+
+/// Compare the environment that the command would start with against
+/// the base environment, from the variables the command sets and removes.
+/// Variables set to the value they already have are not changes.
+pub fn diff_environment(base_environment: &BTreeMap<String, String>, command: &Command) -> Vec<EnvironmentChange> {
+    let mut changes: Vec<EnvironmentChange> = Vec::new();
+    for (name, value) in command.get_envs() {
+        let name: String = name.to_string_lossy().into_owned();
+        let old_value: Option<&String> = base_environment.get(&name);
+        match (value.map(|value| value.to_string_lossy().into_owned()), old_value) {
+            (Some(value), None) => changes.push(EnvironmentChange::Added { name, value }),
+            (Some(value), Some(old_value)) if &value != old_value => {
+                changes.push(EnvironmentChange::Changed { name, old_value: old_value.clone(), new_value: value })
+            }
+            (None, Some(old_value)) => changes.push(EnvironmentChange::Removed { name, value: old_value.clone() }),
+            _ => {}
+        }
+    }
+    changes.sort_by(|a, b| a.get_name().cmp(b.get_name()));
+
+    changes
+}
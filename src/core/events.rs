@@ -0,0 +1,54 @@
+use std::sync::mpsc::Sender;
+
+use crate::display_control::display_event;
+
+use super::io_profile::IoUsage;
+
+/// What happens while a chain runs, for following a run without
+/// reading its output, e.g. in the dashboard of `cchain run --tui`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ChainEvent {
+    /// The chain starts, with a label of each program
+    ChainStarted { program_labels: Vec<String> },
+    /// An attempt of a program starts. Retries start again.
+    ProgramStarted { program_index: usize },
+    /// An attempt of a program failed, and the attempt with the number
+    /// is to start
+    RetryScheduled { program_index: usize, attempt: u32 },
+    /// Output of a program, as it is read
+    ProgramOutput { program_index: usize, text: String },
+    /// The process of an attempt of a program exited, with its exit code
+    /// unless a signal ended it, and its usage with `--profile-io`
+    ProcessExited { program_index: usize, exit_code: Option<i32>, io_usage: Option<IoUsage> },
+    /// A program finished for good, after its retries
+    ProgramFinished { program_index: usize, succeeded: bool },
+    /// A message that cchain would have displayed
+    Message { text: String },
+    /// The chain finished. Programs that have not started are skipped.
+    ChainFinished { succeeded: bool },
+}
+
+/// Sends the events of a chain. Events sent after the receiver is gone
+/// are dropped, so that following a run never fails it. The events are
+/// also shown as status lines with `--summary-only`.
+#[derive(Debug, Clone)]
+pub struct EventSender(Option<Sender<ChainEvent>>);
+
+impl EventSender {
+    pub fn new(sender: Sender<ChainEvent>) -> Self {
+        Self(Some(sender))
+    }
+
+    /// Only show the events with `--summary-only`, when nothing else
+    /// follows the run
+    pub fn display_only() -> Self {
+        Self(None)
+    }
+
+    pub fn send(&self, event: ChainEvent) {
+        display_event(&event);
+        if let Some(sender) = &self.0 {
+            let _ = sender.send(event);
+        }
+    }
+}
@@ -0,0 +1,401 @@
+use std::{
+    fs::Permissions,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Error, Result};
+use serde_json::{Map, Value};
+use similar::TextDiff;
+
+use crate::{
+    commons::{
+        lock::{check_unchanged, lock_user_file, write_file_atomically_with_permissions, FileLock},
+        packages::get_edit_distance,
+    },
+    marker::reference::follows_naming_convention,
+    variable::{Variable, DEFAULT_CLOSING_DELIMITER, DEFAULT_OPENING_DELIMITER},
+};
+
+use super::{
+    builtin::BUILTIN_STEP_FIELD_NAMES,
+    format::{write_chain_file, write_chain_file_value},
+    matrix::DeclaredMatrixKeys,
+    metadata::{ChainFile, CHAIN_FILE_FIELD_NAMES, METADATA_FIELD_NAMES},
+    migration::RENAMED_PROGRAM_FIELDS,
+    program::Program,
+};
+
+/// Largest edit distance between an unknown field and the known field
+/// it is taken to be a misspelling of
+const MAX_FIELD_EDIT_DISTANCE: usize = 2;
+
+/// The fixes of a chain file found by `cchain check --fix`, and the
+/// problems left as they are because their fix is not certain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChainFileFix {
+    path: PathBuf,
+    /// Where the fixed file is written, which differs from the path when
+    /// the file is renamed
+    fixed_path: PathBuf,
+    original: String,
+    fixed: String,
+    applied: Vec<String>,
+    untouched: Vec<String>,
+}
+
+impl ChainFileFix {
+    /// Find the fixes of the chain file at the path. A bookmarked file
+    /// is not renamed, as the bookmark would lose it.
+    pub fn from_path(path: &Path, is_bookmarked: bool) -> Result<Self, Error> {
+        let original: String = std::fs::read_to_string(path)?;
+        let mut chain_file_fix = Self {
+            path: path.to_path_buf(),
+            fixed_path: path.to_path_buf(),
+            original: original.clone(),
+            fixed: original,
+            applied: Vec::new(),
+            untouched: Vec::new(),
+        };
+        chain_file_fix.fix_content()?;
+        chain_file_fix.fix_file_name(is_bookmarked);
+
+        Ok(chain_file_fix)
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_fixed_path(&self) -> &Path {
+        &self.fixed_path
+    }
+
+    pub fn get_fixed_content(&self) -> &str {
+        &self.fixed
+    }
+
+    /// Get what the fixes change
+    pub fn get_applied(&self) -> &Vec<String> {
+        &self.applied
+    }
+
+    /// Get the problems that are left as they are, and why
+    pub fn get_untouched(&self) -> &Vec<String> {
+        &self.untouched
+    }
+
+    pub fn is_changed(&self) -> bool {
+        self.fixed != self.original || self.fixed_path != self.path
+    }
+
+    /// Get the changes to the content as a unified diff
+    pub fn get_diff(&self) -> String {
+        TextDiff::from_lines(&self.original, &self.fixed)
+            .unified_diff()
+            .header(&self.path.display().to_string(), &self.fixed_path.display().to_string())
+            .to_string()
+    }
+
+    /// Write the fixed file, after backing up the original next to it
+    /// with a `.bak` extension added. Earlier backups are kept, so the
+    /// backup is numbered, e.g. `.bak.1`, when `.bak` already exists.
+    ///
+    /// # Returns
+    ///
+    /// The path of the backup
+    pub fn apply(&self) -> Result<PathBuf, Error> {
+        // The chain file is locked while it is replaced, and left alone
+        // when it changed since it was read
+        let _lock: FileLock = lock_user_file(&self.path)?;
+        check_unchanged(&self.path, &self.original)?;
+        // The backup and a renamed file keep the permissions of the
+        // original, as they have the same content
+        let permissions: Permissions = std::fs::metadata(&self.path)?.permissions();
+        let backup_path: PathBuf = get_backup_path(&self.path);
+        write_file_atomically_with_permissions(&backup_path, self.original.as_bytes(), Some(permissions.clone()))?;
+
+        write_file_atomically_with_permissions(&self.fixed_path, self.fixed.as_bytes(), Some(permissions))?;
+        if self.fixed_path != self.path {
+            std::fs::remove_file(&self.path)?;
+        }
+
+        Ok(backup_path)
+    }
+
+    /// Fix the fields, then the chain through its typed model, and write
+    /// it canonically. Nothing is changed when the chain cannot be parsed
+    /// after the fixes of its fields.
+    fn fix_content(&mut self) -> Result<(), Error> {
+        let mut value: Value = serde_json::from_str(&self.original)
+            .map_err(|error| anyhow!("{} is not valid JSON, so it cannot be fixed: {}", self.path.display(), error))?;
+        let declared_matrix_keys: DeclaredMatrixKeys = DeclaredMatrixKeys::from_content(&self.original);
+        let is_formatted: bool = write_chain_file_value(&value, &declared_matrix_keys) == self.original;
+        let is_array: bool = value.is_array();
+
+        let mut applied: Vec<String> = Vec::new();
+        self.fix_field_names(&mut value, &mut applied);
+        applied.extend(get_legacy_field_fixes(&value));
+
+        let mut chain_file: ChainFile = match ChainFile::from_str(&value.to_string()) {
+            Ok(chain_file) => chain_file,
+            Err(error) => {
+                self.untouched.push(format!("The chain cannot be parsed, so it is left as it is: {}", error));
+                return Ok(());
+            }
+        };
+        chain_file.order_matrix_keys(&declared_matrix_keys);
+        self.fix_placeholders(&mut chain_file, &mut applied);
+        self.check_infinite_retries(&chain_file);
+        // Fields unknown even after the fixes would be lost by rewriting
+        if !chain_file.unknown_fields.is_empty() {
+            self.untouched.push(format!(
+                "The file is not rewritten, as the unknown fields would be lost: {}",
+                chain_file.unknown_fields.join(", ")
+            ));
+            return Ok(());
+        }
+
+        self.fixed = write_chain_file(&chain_file, is_array, false)?;
+        if !is_formatted && self.fixed != self.original {
+            applied.push("Formatted the file canonically".to_string());
+        }
+        self.applied.extend(applied);
+
+        Ok(())
+    }
+
+    /// Rename the unknown fields that are a misspelling of a single known
+    /// field, at the top level, in the metadata and in the programs
+    fn fix_field_names(&mut self, value: &mut Value, applied: &mut Vec<String>) {
+        if let Some(object) = value.as_object_mut() {
+            self.fix_object_field_names(object, CHAIN_FILE_FIELD_NAMES, "the chain file", applied);
+            if let Some(metadata) = object.get_mut("metadata").and_then(Value::as_object_mut) {
+                self.fix_object_field_names(metadata, METADATA_FIELD_NAMES, "the metadata", applied);
+            }
+        }
+
+        let mut sections: Vec<(&str, &mut Vec<Value>)> = Vec::new();
+        match value {
+            Value::Array(programs) => sections.push(("program", programs)),
+            Value::Object(object) => {
+                for (key, section) in object.iter_mut() {
+                    let label: &str = match key.as_str() {
+                        "programs" => "program",
+                        "verify" => "verification step",
+                        _ => continue,
+                    };
+                    if let Some(programs) = section.as_array_mut() {
+                        sections.push((label, programs));
+                    }
+                }
+            }
+            _ => (),
+        }
+        // Verification steps are numbered after the programs
+        let mut start: usize = 0;
+        for (label, programs) in sections {
+            let section_start: usize = start;
+            start += programs.len();
+            for (index, program) in programs.iter_mut().enumerate().map(|(index, program)| (section_start + index, program)) {
+                let Some(program) = program.as_object_mut() else {
+                    continue;
+                };
+                let known_fields: &[&str] =
+                    if program.contains_key("builtin") { BUILTIN_STEP_FIELD_NAMES } else { Program::FIELD_NAMES };
+                self.fix_object_field_names(program, known_fields, &format!("{} #{}", label, index), applied);
+            }
+        }
+    }
+
+    fn fix_object_field_names(
+        &mut self,
+        object: &mut Map<String, Value>,
+        known_fields: &[&str],
+        location: &str,
+        applied: &mut Vec<String>,
+    ) {
+        let unknown_fields: Vec<String> = object
+            .keys()
+            .filter(|key| !known_fields.contains(&key.as_str()))
+            // Legacy fields are rewritten by the migration
+            .filter(|key| !RENAMED_PROGRAM_FIELDS.iter().any(|(legacy_name, _)| legacy_name == key))
+            .cloned()
+            .collect();
+        for unknown_field in unknown_fields {
+            let candidates: Vec<&str> = get_closest_fields(&unknown_field, known_fields);
+            match candidates.as_slice() {
+                [] => self.untouched.push(format!(
+                    "`{}` in {} is unknown, and no known field is close to it",
+                    unknown_field, location
+                )),
+                [field] if object.contains_key(*field) => self.untouched.push(format!(
+                    "`{}` in {} looks like `{}`, which is already set",
+                    unknown_field, location, field
+                )),
+                [field] => {
+                    let value: Value = object.remove(&unknown_field).unwrap_or(Value::Null);
+                    object.insert(field.to_string(), value);
+                    applied.push(format!("Renamed `{}` in {} to `{}`", unknown_field, location, field));
+                }
+                _ => self.untouched.push(format!(
+                    "`{}` in {} could be any of {}",
+                    unknown_field,
+                    location,
+                    candidates.iter().map(|field| format!("`{}`", field)).collect::<Vec<String>>().join(", ")
+                )),
+            }
+        }
+    }
+
+    /// Remove the spaces around the names in placeholders, e.g. `<< name >>`
+    fn fix_placeholders(&mut self, chain_file: &mut ChainFile, applied: &mut Vec<String>) {
+        // Chains with custom delimiters write `<<` as it is
+        if chain_file.metadata.variable_delimiters.as_ref().is_some_and(|delimiters| !delimiters.is_default()) {
+            return;
+        }
+
+        let verification_start: usize = chain_file.programs.len();
+        for (index, program) in chain_file.programs.iter_mut().chain(chain_file.verify.iter_mut()).enumerate() {
+            let label: String = if index < verification_start {
+                format!("program #{}", index)
+            } else {
+                format!("verification step #{}", index)
+            };
+            let mut fixed_placeholders: Vec<String> = Vec::new();
+            let mut untouched_placeholders: Vec<String> = Vec::new();
+            program.rewrite_arguments(|argument| {
+                Variable::replace_placeholders(argument, |content| {
+                    let trimmed: &str = content.trim();
+                    if trimmed == content || content.starts_with('?') {
+                        return None;
+                    }
+                    let placeholder: String =
+                        format!("{}{}{}", DEFAULT_OPENING_DELIMITER, content, DEFAULT_CLOSING_DELIMITER);
+                    // Likely shell text, e.g. `<< >>`, rather than a placeholder
+                    if trimmed.is_empty() {
+                        untouched_placeholders.push(placeholder);
+                        return None;
+                    }
+                    fixed_placeholders.push(placeholder);
+                    Some(format!("{}{}{}", DEFAULT_OPENING_DELIMITER, trimmed, DEFAULT_CLOSING_DELIMITER))
+                })
+            });
+            for placeholder in fixed_placeholders {
+                applied.push(format!("Removed the spaces inside `{}` in {}", placeholder, label));
+            }
+            for placeholder in untouched_placeholders {
+                self.untouched.push(format!(
+                    "`{}` in {} may not be meant as a placeholder. Escape it as `<<<<` if it is text.",
+                    placeholder,
+                    label
+                ));
+            }
+        }
+    }
+
+    /// Programs have no timeout to insert for `retry: -1`, so the
+    /// programs retrying forever are pointed out instead
+    fn check_infinite_retries(&mut self, chain_file: &ChainFile) {
+        let verification_start: usize = chain_file.programs.len();
+        for (index, program) in chain_file.programs.iter().chain(chain_file.verify.iter()).enumerate() {
+            if *program.get_retry() == -1 && program.get_retry_escalation_attempts().is_none() {
+                let label: &str = if index < verification_start { "Program" } else { "Verification step" };
+                self.untouched.push(format!(
+                    "{} #{} retries forever with `retry: -1`, and programs have no timeout to insert. Set a positive `retry`, or `retry_escalation_attempts` to be asked sooner.",
+                    label,
+                    index
+                ));
+            }
+        }
+    }
+
+    /// Add the `cchain_` prefix to the file name, so that the chain is
+    /// found when scanning directories
+    fn fix_file_name(&mut self, is_bookmarked: bool) {
+        if follows_naming_convention(&self.path) {
+            return;
+        }
+        let Some(file_name) = self.path.file_name().map(|file_name| file_name.to_string_lossy().to_string()) else {
+            return;
+        };
+        if !file_name.ends_with(".json") {
+            self.untouched.push(format!("{} does not end with `.json`, so it is not renamed", file_name));
+            return;
+        }
+
+        let fixed_path: PathBuf = self.path.with_file_name(format!("cchain_{}", file_name));
+        if is_bookmarked {
+            self.untouched.push(format!(
+                "{} has no `cchain_` prefix, but it is bookmarked, so it is not renamed",
+                file_name
+            ));
+        } else if fixed_path.exists() {
+            self.untouched.push(format!(
+                "{} has no `cchain_` prefix, but {} already exists",
+                file_name,
+                fixed_path.display()
+            ));
+        } else {
+            self.applied.push(format!("Renamed {} to {}", self.path.display(), fixed_path.display()));
+            self.fixed_path = fixed_path;
+        }
+    }
+}
+
+/// Get the known fields closest to an unknown one, when they are within
+/// `MAX_FIELD_EDIT_DISTANCE` and less than half of its length away
+pub fn get_closest_fields<'a>(field: &str, known_fields: &[&'a str]) -> Vec<&'a str> {
+    let distances: Vec<(usize, &str)> = known_fields
+        .iter()
+        .map(|known_field| (get_edit_distance(field, known_field), *known_field))
+        .filter(|(distance, _)| *distance <= MAX_FIELD_EDIT_DISTANCE && distance * 2 < field.chars().count())
+        .collect();
+    let Some(closest) = distances.iter().map(|(distance, _)| *distance).min() else {
+        return Vec::new();
+    };
+
+    distances
+        .into_iter()
+        .filter(|(distance, _)| *distance == closest)
+        .map(|(_, known_field)| known_field)
+        .collect()
+}
+
+/// Describe the legacy fields that parsing the chain rewrites
+fn get_legacy_field_fixes(value: &Value) -> Vec<String> {
+    let mut fixes: Vec<String> = Vec::new();
+    for (location, program) in ChainFile::get_program_values(value) {
+        for (legacy_name, name) in RENAMED_PROGRAM_FIELDS {
+            if program.get(legacy_name).is_some() {
+                fixes.push(format!("Replaced the legacy `{}` of {} with `{}`", legacy_name, location, name));
+            }
+        }
+        if program["failure_handling_options"].get("continue_on_failure").is_some() {
+            fixes.push(format!(
+                "Replaced the legacy `continue_on_failure` of {} with `exit_on_failure`",
+                location
+            ));
+        }
+    }
+
+    fixes
+}
+
+/// Get the path to back the chain file up to: the path with a `.bak`
+/// extension added, numbered when earlier backups are there
+fn get_backup_path(path: &Path) -> PathBuf {
+    let mut number: usize = 0;
+    loop {
+        let mut backup_path = path.as_os_str().to_os_string();
+        backup_path.push(".bak");
+        if number > 0 {
+            backup_path.push(format!(".{}", number));
+        }
+        let backup_path: PathBuf = PathBuf::from(backup_path);
+        if !backup_path.exists() {
+            return backup_path;
+        }
+        number += 1;
+    }
+}
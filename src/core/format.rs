@@ -0,0 +1,199 @@
+use std::str::FromStr;
+
+use anyhow::{anyhow, Error, Result};
+use serde_json::{Map, Value};
+
+use super::{
+    builtin::BUILTIN_STEP_FIELD_NAMES,
+    matrix::DeclaredMatrixKeys,
+    metadata::{ChainFile, CHAIN_FILE_FIELD_NAMES, METADATA_FIELD_NAMES, REQUIRED_PROGRAM_FIELD_NAMES},
+    program::{find_duplicate_programs, DuplicateProgram, Program},
+};
+
+/// Width of one level of indentation
+const INDENTATION: &str = "  ";
+
+/// Format a chain file canonically: fields in the order of their
+/// declarations, two-space indentation, and no fields at their defaults
+/// unless `explicit_defaults` is set, in which case every program field
+/// is written. A plain array of programs stays a plain array.
+///
+/// Fields of the legacy format are rewritten to the current ones. Files
+/// with fields unknown to this version of cchain are not formatted, as
+/// the fields would be lost.
+pub fn format_chain_file(content: &str, explicit_defaults: bool) -> Result<String, Error> {
+    let chain_file: ChainFile = ChainFile::from_str(content)?;
+    if !chain_file.unknown_fields.is_empty() {
+        return Err(anyhow!(
+            "Unknown fields would be lost: {}",
+            chain_file.unknown_fields.join(", ")
+        ));
+    }
+
+    let is_array: bool = serde_json::from_str::<Value>(content)?.is_array();
+
+    write_chain_file(&chain_file, is_array, explicit_defaults)
+}
+
+/// Remove the programs identical to the program right before them, e.g.
+/// repeated by a generator, and format the chain file. The order of the
+/// other programs is kept. Returns the formatted file and the removed
+/// programs, with their indices before the removal.
+pub fn dedupe_consecutive_programs(content: &str, explicit_defaults: bool) -> Result<(String, Vec<DuplicateProgram>), Error> {
+    let mut chain_file: ChainFile = ChainFile::from_str(content)?;
+    if !chain_file.unknown_fields.is_empty() {
+        return Err(anyhow!(
+            "Unknown fields would be lost: {}",
+            chain_file.unknown_fields.join(", ")
+        ));
+    }
+
+    let fingerprints: Vec<String> = chain_file.programs.iter().map(Program::execution_fingerprint).collect();
+    let duplicates: Vec<DuplicateProgram> = find_duplicate_programs(&fingerprints)
+        .into_iter()
+        .filter(DuplicateProgram::is_consecutive)
+        .collect();
+    for duplicate in duplicates.iter().rev() {
+        chain_file.programs.remove(duplicate.index);
+    }
+
+    let is_array: bool = serde_json::from_str::<Value>(content)?.is_array();
+
+    Ok((write_chain_file(&chain_file, is_array, explicit_defaults)?, duplicates))
+}
+
+/// Write a parsed chain file canonically, as a plain array of programs
+/// when `is_array` is set, e.g. after changing it
+pub fn write_chain_file(chain_file: &ChainFile, is_array: bool, explicit_defaults: bool) -> Result<String, Error> {
+    let mut value: Value = serde_json::to_value(chain_file)?;
+    let default_program: Value = serde_json::to_value(Program::default())?;
+    for section in ["programs", "verify"] {
+        if let Some(programs) = value.get_mut(section).and_then(|programs| programs.as_array_mut()) {
+            for program in programs.iter_mut().filter_map(|program| program.as_object_mut()) {
+                normalize_program(program, &default_program, explicit_defaults);
+            }
+        }
+    }
+    if !explicit_defaults {
+        remove_nulls(&mut value);
+    }
+
+    let declared_matrix_keys: DeclaredMatrixKeys =
+        DeclaredMatrixKeys::from_programs(chain_file.programs.iter().chain(&chain_file.verify));
+    let value: Value = if is_array { value["programs"].take() } else { value };
+    let mut output: String = String::new();
+    let path: &[&str] = if is_array { &["programs"] } else { &[] };
+    write_value(&mut output, &value, &mut path.to_vec(), 0, &declared_matrix_keys);
+    output.push('\n');
+
+    Ok(output)
+}
+
+/// Write a JSON value of a chain file with the fields in the canonical
+/// order and indentation, but as it is otherwise, e.g. to tell whether a
+/// file is laid out canonically. The keys of the matrices are written in
+/// the declared order, which the value does not keep.
+pub fn write_chain_file_value(value: &Value, declared_matrix_keys: &DeclaredMatrixKeys) -> String {
+    let mut output: String = String::new();
+    let path: &[&str] = if value.is_array() { &["programs"] } else { &[] };
+    write_value(&mut output, value, &mut path.to_vec(), 0, declared_matrix_keys);
+    output.push('\n');
+
+    output
+}
+
+/// Drop the fields at their defaults, or fill in the missing fields
+/// with `explicit_defaults`. The fields that a program cannot be parsed
+/// without are always written.
+fn normalize_program(program: &mut Map<String, Value>, default_program: &Value, explicit_defaults: bool) {
+    // Built-in steps have no other fields
+    if program.contains_key("builtin") {
+        program.retain(|key, _| BUILTIN_STEP_FIELD_NAMES.contains(&key.as_str()));
+        return;
+    }
+
+    if explicit_defaults {
+        for field_name in Program::FIELD_NAMES {
+            if !program.contains_key(*field_name) {
+                let default_value: Value = default_program.get(*field_name).cloned().unwrap_or(Value::Null);
+                program.insert(field_name.to_string(), default_value);
+            }
+        }
+        return;
+    }
+
+    program.retain(|key, value| {
+        REQUIRED_PROGRAM_FIELD_NAMES.contains(&key.as_str()) || default_program.get(key) != Some(value)
+    });
+}
+
+/// Remove the null fields of objects, which mean the same as missing ones,
+/// except the environment variables that are removed with null
+fn remove_nulls(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            object.retain(|_, value| !value.is_null());
+            for (key, value) in object.iter_mut() {
+                if key != "environment_variables_override" {
+                    remove_nulls(value);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(remove_nulls),
+        _ => (),
+    }
+}
+
+/// Get the order of the fields of the object at the path. Fields not
+/// in the order, e.g. of nested options, come after in alphabetical order.
+fn get_field_order(path: &[&str]) -> &'static [&'static str] {
+    match path {
+        [] => CHAIN_FILE_FIELD_NAMES,
+        ["metadata"] => METADATA_FIELD_NAMES,
+        ["programs", "[]"] | ["verify", "[]"] => Program::FIELD_NAMES,
+        _ => &[],
+    }
+}
+
+fn write_value<'a>(
+    output: &mut String,
+    value: &'a Value,
+    path: &mut Vec<&'a str>,
+    depth: usize,
+    declared_matrix_keys: &DeclaredMatrixKeys,
+) {
+    let indentation: String = INDENTATION.repeat(depth);
+    match value {
+        Value::Object(object) if !object.is_empty() => {
+            let mut keys: Vec<&String> = object.keys().collect();
+            if path.last() == Some(&"matrix") {
+                declared_matrix_keys.order_object_keys(&mut keys);
+            } else {
+                let field_order: &[&str] = get_field_order(path);
+                keys.sort_by_key(|key| field_order.iter().position(|field| field == key).unwrap_or(field_order.len()));
+            }
+
+            output.push_str("{\n");
+            for (index, key) in keys.iter().enumerate() {
+                output.push_str(&format!("{}{}{}: ", indentation, INDENTATION, Value::String(key.to_string())));
+                path.push(key.as_str());
+                write_value(output, &object[key.as_str()], path, depth + 1, declared_matrix_keys);
+                path.pop();
+                output.push_str(if index + 1 < keys.len() { ",\n" } else { "\n" });
+            }
+            output.push_str(&format!("{}}}", indentation));
+        }
+        Value::Array(items) if !items.is_empty() => {
+            output.push_str("[\n");
+            for (index, item) in items.iter().enumerate() {
+                output.push_str(&format!("{}{}", indentation, INDENTATION));
+                path.push("[]");
+                write_value(output, item, path, depth + 1, declared_matrix_keys);
+                path.pop();
+                output.push_str(if index + 1 < items.len() { ",\n" } else { "\n" });
+            }
+            output.push_str(&format!("{}]", indentation));
+        }
+        _ => output.push_str(&value.to_string()),
+    }
+}
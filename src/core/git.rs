@@ -0,0 +1,401 @@
+use std::path::Path;
+#[cfg(feature = "git")]
+use std::path::PathBuf;
+
+#[cfg(feature = "git")]
+use anyhow::anyhow;
+use anyhow::{Error, Result};
+#[cfg(feature = "git")]
+use git2::{
+    build::{CheckoutBuilder, RepoBuilder},
+    BranchType, Cred, CredentialType, ErrorClass, ErrorCode, FetchOptions, ObjectType, ProxyOptions, RemoteCallbacks, Repository,
+    Tree, TreeWalkMode, TreeWalkResult,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(not(feature = "git"))]
+use crate::commons::features::Feature;
+
+use crate::variable::Variable;
+
+use super::paths::resolve_chain_relative_path;
+
+/// Attempts at the credentials of a remote before giving up, as git2
+/// asks again after credentials are rejected
+#[cfg(feature = "git")]
+const CREDENTIAL_ATTEMPTS: usize = 3;
+
+fn get_head_reference() -> String {
+    "HEAD".to_string()
+}
+
+/// A git operation that a program runs through git2 instead of a command,
+/// e.g. `{ "git": { "op": "rev_parse", "ref": "HEAD", "short": true } }`.
+/// Its result is the output of the program, without a trailing newline,
+/// so that `stdout_stored_to` stores it as it is.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum GitOperation {
+    /// Clone a repository into a path, relative to the chain. The result
+    /// is the path of the working directory.
+    Clone {
+        url: String,
+        path: String,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        branch: Option<String>,
+        /// Fetch only this many commits of history
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        depth: Option<u32>,
+    },
+    /// Check out a branch, a tag or a commit. The result is the commit.
+    Checkout {
+        #[serde(rename = "ref")]
+        reference: String,
+    },
+    /// Fetch the upstream of the current branch and fast-forward to it.
+    /// The result is the commit after the pull.
+    Pull,
+    /// Resolve a revision to its commit, e.g. `HEAD~1`
+    RevParse {
+        #[serde(rename = "ref", default = "get_head_reference")]
+        reference: String,
+        /// Abbreviate the commit, as `git rev-parse --short` does
+        #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+        short: bool,
+    },
+    /// The name of the branch checked out
+    CurrentBranch,
+}
+
+impl std::fmt::Display for GitOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            GitOperation::Clone { url, path, branch, depth } => {
+                write!(f, "git clone {} {}", url, path)?;
+                if let Some(branch) = branch {
+                    write!(f, " --branch {}", branch)?;
+                }
+                if let Some(depth) = depth {
+                    write!(f, " --depth {}", depth)?;
+                }
+                Ok(())
+            }
+            GitOperation::Checkout { reference } => write!(f, "git checkout {}", reference),
+            GitOperation::Pull => write!(f, "git pull --ff-only"),
+            GitOperation::RevParse { reference, short: true } => write!(f, "git rev-parse --short {}", reference),
+            GitOperation::RevParse { reference, short: false } => write!(f, "git rev-parse {}", reference),
+            GitOperation::CurrentBranch => write!(f, "git branch --show-current"),
+        }
+    }
+}
+
+impl GitOperation {
+    /// Replace the URL, the path, the branch and the reference of the
+    /// operation with what the closure returns for them
+    pub fn rewrite_values(&mut self, rewrite: &mut impl FnMut(&str) -> String) {
+        match self {
+            GitOperation::Clone { url, path, branch, .. } => {
+                *url = rewrite(url);
+                *path = rewrite(path);
+                if let Some(branch) = branch {
+                    *branch = rewrite(branch);
+                }
+            }
+            GitOperation::Checkout { reference } | GitOperation::RevParse { reference, .. } => {
+                *reference = rewrite(reference);
+            }
+            GitOperation::Pull | GitOperation::CurrentBranch => {}
+        }
+    }
+
+    /// Get the URL, the path, the branch and the reference of the
+    /// operation, which may hold placeholders of variables
+    pub fn get_placeholder_values(&self) -> Vec<&str> {
+        match self {
+            GitOperation::Clone { url, path, branch, .. } => {
+                let mut values: Vec<&str> = vec![url, path];
+                values.extend(branch.as_deref());
+                values
+            }
+            GitOperation::Checkout { reference } | GitOperation::RevParse { reference, .. } => vec![reference],
+            GitOperation::Pull | GitOperation::CurrentBranch => Vec::new(),
+        }
+    }
+
+    /// Inject the value of a variable into the fields of the operation, as
+    /// `CommandLine::inject_value_to_variables` does into the arguments
+    pub fn inject_value_to_variables(&mut self, raw_variable_name: &str, value: &str) {
+        self.rewrite_values(&mut |text| Variable::replace_variable_placeholders(text, raw_variable_name, value));
+    }
+
+    /// Turn the escaped `<<<<` in the fields into literal `<<`, once the
+    /// values are injected
+    pub fn unescape_placeholders(&mut self) {
+        self.rewrite_values(&mut |text| Variable::unescape_placeholders(text));
+    }
+
+    /// Resolve the path of a clone against the base directory of the chain
+    pub fn resolve_relative_paths(&mut self, base_directory: &Path) {
+        if let GitOperation::Clone { path, .. } = self {
+            *path = resolve_chain_relative_path(path, base_directory);
+        }
+    }
+
+    /// Run the operation in the repository containing the directory, or
+    /// clone into a path relative to it
+    ///
+    /// # Returns
+    ///
+    /// The result of the operation, e.g. a commit or a branch name
+    #[cfg(feature = "git")]
+    pub fn execute(&self, directory: &Path) -> Result<String, Error> {
+        match self {
+            GitOperation::Clone { url, path, branch, depth } => {
+                let path: PathBuf = directory.join(path);
+                let mut fetch_options: FetchOptions = get_fetch_options();
+                if let Some(depth) = depth {
+                    fetch_options.depth(i32::try_from(*depth).unwrap_or(i32::MAX));
+                }
+                let mut builder = RepoBuilder::new();
+                builder.fetch_options(fetch_options);
+                if let Some(branch) = branch {
+                    builder.branch(branch);
+                }
+                let repository: Repository =
+                    builder.clone(url, &path).map_err(|error| describe_git_error(url, error))?;
+                let workdir: &Path = repository.workdir().unwrap_or(&path);
+
+                Ok(workdir.to_string_lossy().trim_end_matches(['/', '\\']).to_string())
+            }
+            GitOperation::Checkout { reference } => {
+                let repository: Repository = Repository::discover(directory)?;
+                let (object, resolved_reference) = repository
+                    .revparse_ext(reference)
+                    .map_err(|error| anyhow!("Cannot resolve `{}`: {}", reference, error.message()))?;
+                let commit_id = object.peel_to_commit()?.id();
+                repository.checkout_tree(&object, Some(CheckoutBuilder::new().safe()))?;
+                match resolved_reference.as_ref().and_then(|resolved| resolved.name()) {
+                    // Local branches are checked out, anything else detaches
+                    Some(name) if name.starts_with("refs/heads/") => repository.set_head(name)?,
+                    _ => repository.set_head_detached(commit_id)?,
+                }
+
+                Ok(commit_id.to_string())
+            }
+            GitOperation::Pull => pull(&Repository::discover(directory)?),
+            GitOperation::RevParse { reference, short } => {
+                let repository: Repository = Repository::discover(directory)?;
+                let object = repository
+                    .revparse_single(reference)
+                    .map_err(|error| anyhow!("Cannot resolve `{}`: {}", reference, error.message()))?;
+                let commit = object.peel_to_commit()?;
+                if *short {
+                    return Ok(commit.as_object().short_id()?.as_str().unwrap_or_default().to_string());
+                }
+
+                Ok(commit.id().to_string())
+            }
+            GitOperation::CurrentBranch => {
+                let repository: Repository = Repository::discover(directory)?;
+                let head = repository.head()?;
+                if !head.is_branch() {
+                    let commit: String = head.peel_to_commit().map(|commit| commit.id().to_string()).unwrap_or_default();
+                    return Err(anyhow!("HEAD is detached at {}, not on a branch", commit));
+                }
+
+                Ok(head.shorthand().unwrap_or_default().to_string())
+            }
+        }
+    }
+}
+
+/// Git operations run through git2, which cchain is built without
+#[cfg(not(feature = "git"))]
+impl GitOperation {
+    pub fn execute(&self, _directory: &Path) -> Result<String, Error> {
+        Err(Feature::Git.get_unavailable_error("the `git` step"))
+    }
+}
+
+/// Fetch the upstream of the current branch, and fast-forward to it
+#[cfg(feature = "git")]
+fn pull(repository: &Repository) -> Result<String, Error> {
+    let head = repository.head()?;
+    let head_name: String = head.name().unwrap_or_default().to_string();
+    let branch_name: String = head
+        .shorthand()
+        .filter(|_| head.is_branch())
+        .ok_or_else(|| anyhow!("HEAD is detached, so there is no branch to pull"))?
+        .to_string();
+    let branch = repository.find_branch(&branch_name, BranchType::Local)?;
+    let upstream_name: String = branch
+        .upstream()
+        .map_err(|_| anyhow!("Branch `{}` has no upstream to pull from", branch_name))?
+        .get()
+        .name()
+        .unwrap_or_default()
+        .to_string();
+    let remote_name: String = repository.branch_upstream_remote(&head_name)?.as_str().unwrap_or_default().to_string();
+
+    let mut remote = repository.find_remote(&remote_name)?;
+    let url: String = remote.url().unwrap_or(&remote_name).to_string();
+    remote
+        .fetch(&[] as &[&str], Some(&mut get_fetch_options()), None)
+        .map_err(|error| describe_git_error(&url, error))?;
+
+    let upstream = repository.find_reference(&upstream_name)?;
+    let upstream_commit = repository.reference_to_annotated_commit(&upstream)?;
+    let (analysis, _) = repository.merge_analysis(&[&upstream_commit])?;
+    if analysis.is_up_to_date() {
+        return Ok(head.peel_to_commit()?.id().to_string());
+    }
+    if !analysis.is_fast_forward() {
+        return Err(anyhow!(
+            "Branch `{}` has diverged from `{}`, and pulls only fast-forward",
+            branch_name,
+            upstream_name
+        ));
+    }
+
+    // The working tree is updated from the old commit first, so that
+    // local changes are kept or reported rather than overwritten
+    let target = repository.find_object(upstream_commit.id(), None)?;
+    repository.checkout_tree(&target, Some(CheckoutBuilder::new().safe()))?;
+    repository
+        .find_reference(&head_name)?
+        .set_target(upstream_commit.id(), &format!("pull: fast-forward to {}", upstream_name))?;
+
+    Ok(upstream_commit.id().to_string())
+}
+
+/// A clone with only some paths checked out
+#[cfg(feature = "git")]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SparseClone {
+    /// The working directory of the clone
+    pub workdir: PathBuf,
+    /// The paths of all files in the commit, checked out or not,
+    /// relative to the working directory
+    pub tree_paths: Vec<PathBuf>,
+}
+
+/// Clone the latest commit of a repository, and check out only the files
+/// under the paths, relative to the root of the repository. The paths
+/// are also written as the sparse-checkout patterns of the clone, so
+/// that `git` keeps to them. Remotes that cannot fetch shallow, e.g.
+/// local ones, are fetched in full.
+#[cfg(feature = "git")]
+pub fn clone_sparse(url: &str, path: &Path, sparse_paths: &[String]) -> Result<SparseClone, Error> {
+    // The checkout is forced, which would overwrite the files of a
+    // directory that is already there
+    if is_occupied(path) {
+        return Err(anyhow!("`{}` exists and is not an empty directory", path.display()));
+    }
+    let repository: Repository = Repository::init(path)?;
+    let mut remote = repository.remote("origin", url)?;
+    let mut fetch_options: FetchOptions = get_fetch_options();
+    fetch_options.depth(1);
+    if remote.fetch(&["HEAD"], Some(&mut fetch_options), None).is_err() {
+        remote
+            .fetch(&["HEAD"], Some(&mut get_fetch_options()), None)
+            .map_err(|error| describe_git_error(url, error))?;
+    }
+    let commit = repository.find_reference("FETCH_HEAD")?.peel_to_commit()?;
+
+    let tree_paths: Vec<PathBuf> = get_tree_paths(&commit.tree()?)?;
+
+    let sparse_paths: Vec<String> = sparse_paths
+        .iter()
+        .map(|sparse_path| sparse_path.trim_matches('/').to_string())
+        .collect();
+    repository.config()?.set_bool("core.sparseCheckout", true)?;
+    let patterns: String = sparse_paths.iter().map(|sparse_path| format!("/{}\n", sparse_path)).collect();
+    std::fs::create_dir_all(repository.path().join("info"))?;
+    std::fs::write(repository.path().join("info").join("sparse-checkout"), patterns)?;
+
+    repository.set_head_detached(commit.id())?;
+    let mut checkout_builder = CheckoutBuilder::new();
+    checkout_builder.force();
+    for sparse_path in &sparse_paths {
+        checkout_builder.path(sparse_path);
+    }
+    repository.checkout_head(Some(&mut checkout_builder))?;
+
+    Ok(SparseClone {
+        workdir: repository.workdir().unwrap_or(path).to_path_buf(),
+        tree_paths,
+    })
+}
+
+/// The paths of all files in a tree, relative to its root
+#[cfg(feature = "git")]
+pub fn get_tree_paths(tree: &Tree) -> Result<Vec<PathBuf>, Error> {
+    let mut tree_paths: Vec<PathBuf> = Vec::new();
+    tree.walk(TreeWalkMode::PreOrder, |root, entry| {
+        if entry.kind() == Some(ObjectType::Blob) {
+            tree_paths.push(Path::new(root).join(entry.name().unwrap_or_default()));
+        }
+        TreeWalkResult::Ok
+    })?;
+
+    Ok(tree_paths)
+}
+
+/// Whether there is something at the path other than an empty directory
+#[cfg(feature = "git")]
+fn is_occupied(path: &Path) -> bool {
+    match std::fs::read_dir(path) {
+        Ok(mut entries) => entries.next().is_some(),
+        Err(_) => path.exists(),
+    }
+}
+
+/// Options of fetches from remotes, with the proxy detected from the git
+/// configuration and the environment, and credentials from ssh-agent or
+/// the credential helpers of git
+#[cfg(feature = "git")]
+pub fn get_fetch_options<'a>() -> FetchOptions<'a> {
+    let mut proxy_options = ProxyOptions::new();
+    proxy_options.auto();
+
+    let mut attempts: usize = 0;
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(move |url, username_from_url, allowed_types| {
+        attempts += 1;
+        if attempts > CREDENTIAL_ATTEMPTS {
+            return Err(git2::Error::new(ErrorCode::Auth, ErrorClass::Net, "the credentials were rejected"));
+        }
+        if allowed_types.contains(CredentialType::SSH_KEY) {
+            return Cred::ssh_key_from_agent(username_from_url.unwrap_or("git"));
+        }
+        if allowed_types.contains(CredentialType::USER_PASS_PLAINTEXT) {
+            let config = git2::Config::open_default()?;
+            return Cred::credential_helper(&config, url, username_from_url);
+        }
+        if allowed_types.contains(CredentialType::DEFAULT) {
+            return Cred::default();
+        }
+
+        Err(git2::Error::new(ErrorCode::Auth, ErrorClass::Net, "no supported way to authenticate"))
+    });
+
+    let mut fetch_options = FetchOptions::new();
+    fetch_options.proxy_options(proxy_options);
+    fetch_options.remote_callbacks(callbacks);
+    fetch_options
+}
+
+/// Explain the errors of remotes, in particular credentials that are
+/// needed but not available
+#[cfg(feature = "git")]
+fn describe_git_error(url: &str, error: git2::Error) -> Error {
+    if error.code() == ErrorCode::Auth || error.class() == ErrorClass::Ssh {
+        return anyhow!(
+            "{} needs credentials, but none were accepted: {}. Add the key to ssh-agent, or configure a git credential helper",
+            url,
+            error.message()
+        );
+    }
+
+    anyhow!("Cannot fetch {}: {}", url, error.message())
+}
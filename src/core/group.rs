@@ -0,0 +1,114 @@
+use std::{
+    process::Command,
+    sync::{Arc, Mutex},
+};
+
+use serde::{Deserialize, Serialize};
+
+use super::service::{kill_process_group, terminate_process_group};
+
+/// Process ids of the running members of fail-fast concurrency groups.
+/// They run in their own process groups, which do not receive Ctrl-C
+/// from the terminal, so the interrupt handler terminates them.
+static RUNNING_MEMBER_PROCESS_IDS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// What happens to the other members of a concurrency group when one
+/// of them fails
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum MemberFailurePolicy {
+    /// Let the other members run to the end
+    #[default]
+    WaitAll,
+    /// Cancel the other members right away
+    FailFast,
+}
+
+/// Settings of a concurrency group, in the `concurrency_groups` section
+/// of the chain metadata
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ConcurrencyGroupOptions {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_member_failure: Option<MemberFailurePolicy>,
+}
+
+#[derive(Debug, Default)]
+struct CancellationState {
+    is_cancelled: bool,
+    /// The process group of the running attempt, if any
+    process_id: Option<u32>,
+}
+
+/// Lets the coordinator of a concurrency group cancel a member while
+/// it runs, by killing the process group of its current attempt
+#[derive(Debug, Clone, Default)]
+pub struct CancellationHandle(Arc<Mutex<CancellationState>>);
+
+impl CancellationHandle {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.lock().unwrap().is_cancelled
+    }
+
+    /// Cancel the member, killing its running process group
+    pub fn cancel(&self) {
+        let mut state = self.0.lock().unwrap();
+        state.is_cancelled = true;
+        if let Some(process_id) = state.process_id {
+            kill_process_group(process_id);
+        }
+    }
+
+    /// Put the process in its own group, so that everything it starts
+    /// can be killed together
+    pub(crate) fn prepare_command(&self, command: &mut Command) {
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+        #[cfg(not(unix))]
+        let _ = command;
+    }
+
+    /// Track the spawned process of an attempt. A member cancelled while
+    /// spawning is killed right away.
+    pub(crate) fn register_process(&self, process_id: u32) {
+        let mut state = self.0.lock().unwrap();
+        state.process_id = Some(process_id);
+        RUNNING_MEMBER_PROCESS_IDS.lock().unwrap().push(process_id);
+        if state.is_cancelled {
+            kill_process_group(process_id);
+        }
+    }
+
+    pub(crate) fn unregister_process(&self) {
+        let mut state = self.0.lock().unwrap();
+        if let Some(process_id) = state.process_id.take() {
+            RUNNING_MEMBER_PROCESS_IDS
+                .lock()
+                .unwrap()
+                .retain(|running_process_id| *running_process_id != process_id);
+        }
+    }
+}
+
+/// Handles do not take part in comparing the command lines that hold them
+impl PartialEq for CancellationHandle {
+    fn eq(&self, _other: &Self) -> bool {
+        true
+    }
+}
+
+impl Eq for CancellationHandle {}
+
+/// Terminate the running members of fail-fast concurrency groups,
+/// when cchain is interrupted
+pub fn terminate_running_members() {
+    for process_id in RUNNING_MEMBER_PROCESS_IDS.lock().unwrap().drain(..) {
+        terminate_process_group(process_id);
+    }
+}
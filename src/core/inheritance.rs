@@ -0,0 +1,257 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error, Result};
+use serde_json::{Map, Value};
+
+use super::{limits::read_chain_file, matrix::DeclaredMatrixKeys};
+
+/// Directives of the programs in an extending chain, which are not
+/// fields of the merged programs
+const INSERT_AFTER_DIRECTIVE: &str = "insert_after";
+const REMOVE_DIRECTIVE: &str = "remove";
+
+/// A chain file with the chains it extends merged in
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResolvedChainFile {
+    /// The merged content, in the object form
+    pub value: Value,
+    /// Where each merged program came from, e.g. `base.json` or
+    /// `base.json, overridden in service.json`
+    pub program_origins: Vec<String>,
+    /// The keys of the matrices in the order the files declare them,
+    /// which the merged content does not keep
+    pub declared_matrix_keys: DeclaredMatrixKeys,
+}
+
+/// Read a chain file, and merge it onto the chain it `extends` in its
+/// metadata, recursively. Files without `extends` are read as they are.
+/// The size limit applies to the files together.
+pub fn resolve_chain_file(path: &Path) -> Result<ResolvedChainFile, Error> {
+    resolve_chain_file_with_ancestors(path, &mut Vec::new(), &mut 0)
+}
+
+fn resolve_chain_file_with_ancestors(
+    path: &Path,
+    ancestors: &mut Vec<PathBuf>,
+    read_size: &mut u64,
+) -> Result<ResolvedChainFile, Error> {
+    let canonical_path: PathBuf = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    if ancestors.contains(&canonical_path) {
+        ancestors.push(canonical_path);
+        return Err(anyhow!("Chain inheritance has a cycle: {}", describe_inheritance(ancestors)));
+    }
+
+    let content: String = read_chain_file(path, read_size).map_err(|error| {
+        ancestors.push(path.to_path_buf());
+        anyhow!("Cannot read chain {}: {} ({})", path.display(), error, describe_inheritance(ancestors))
+    })?;
+    let value: Value = serde_json::from_str(&content)?;
+    let origin: String = path.display().to_string();
+
+    let base_path: Option<String> = value
+        .pointer("/metadata/extends")
+        .and_then(|extends| extends.as_str())
+        .map(|extends| extends.to_string());
+    let base_path: String = match base_path {
+        Some(base_path) => base_path,
+        None => {
+            check_no_directives(&value, &origin)?;
+            let program_count: usize = get_programs(&value).map_or(0, |programs| programs.len());
+            return Ok(ResolvedChainFile {
+                value,
+                program_origins: vec![origin; program_count],
+                declared_matrix_keys: DeclaredMatrixKeys::from_content(&content),
+            });
+        }
+    };
+
+    // The base is relative to the extending file
+    let base_path: PathBuf = path.parent().unwrap_or(Path::new("")).join(base_path);
+    ancestors.push(canonical_path);
+    let base: ResolvedChainFile = resolve_chain_file_with_ancestors(&base_path, ancestors, read_size)?;
+    ancestors.pop();
+
+    let mut resolved: ResolvedChainFile = merge_chain_files(base, value, &origin)?;
+    resolved.declared_matrix_keys.extend(DeclaredMatrixKeys::from_content(&content));
+
+    Ok(resolved)
+}
+
+/// Get the path of a chain file, and of each chain it `extends`, in order.
+/// Unlike resolving the file, this never fails: it stops at the first
+/// file that cannot be read or parsed, or that is seen again.
+pub fn get_inheritance_paths(path: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = vec![path.to_path_buf()];
+    let mut canonical_paths: Vec<PathBuf> = vec![path.canonicalize().unwrap_or_else(|_| path.to_path_buf())];
+    loop {
+        let current_path: &PathBuf = paths.last().unwrap();
+        let base_path: Option<String> = std::fs::read_to_string(current_path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<Value>(&content).ok())
+            .and_then(|value| value.pointer("/metadata/extends")?.as_str().map(|extends| extends.to_string()));
+        let base_path: PathBuf = match base_path {
+            Some(base_path) => current_path.parent().unwrap_or(Path::new("")).join(base_path),
+            None => return paths,
+        };
+
+        let canonical_path: PathBuf = base_path.canonicalize().unwrap_or_else(|_| base_path.clone());
+        if canonical_paths.contains(&canonical_path) {
+            return paths;
+        }
+        canonical_paths.push(canonical_path);
+        paths.push(base_path);
+    }
+}
+
+/// Describe the files of an inheritance, e.g. `a.json -> b.json`
+fn describe_inheritance(paths: &[PathBuf]) -> String {
+    paths.iter().map(|path| path.display().to_string()).collect::<Vec<String>>().join(" -> ")
+}
+
+fn get_programs(value: &Value) -> Option<&Vec<Value>> {
+    match value {
+        Value::Array(programs) => Some(programs),
+        _ => value.get("programs")?.as_array(),
+    }
+}
+
+fn get_alias(program: &Value) -> Option<&str> {
+    program.get("alias")?.as_str()
+}
+
+/// The directives only mean something when there is a base to apply them to
+fn check_no_directives(value: &Value, origin: &str) -> Result<(), Error> {
+    for (index, program) in get_programs(value).into_iter().flatten().enumerate() {
+        for directive in [INSERT_AFTER_DIRECTIVE, REMOVE_DIRECTIVE] {
+            if program.get(directive).is_some() {
+                return Err(anyhow!(
+                    "Program #{} of {} has `{}`, which needs `extends` in the metadata",
+                    index,
+                    origin,
+                    directive
+                ));
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Merge the overrides into the base field by field. Objects are merged
+/// recursively, and anything else in the overrides replaces the base.
+pub fn merge_values(base: &mut Value, overrides: Value) {
+    match (base, overrides) {
+        (Value::Object(base), Value::Object(overrides)) => {
+            for (key, value) in overrides {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_values(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+/// Apply an extending chain file to its resolved base
+fn merge_chain_files(base: ResolvedChainFile, child: Value, origin: &str) -> Result<ResolvedChainFile, Error> {
+    let ResolvedChainFile { value: base_value, mut program_origins, declared_matrix_keys } = base;
+    let mut child: Map<String, Value> = match child {
+        Value::Object(child) => child,
+        _ => return Err(anyhow!("{} extends a chain, so it must be an object with `metadata`", origin)),
+    };
+
+    // Metadata is merged field by field, without the resolved `extends`
+    let mut metadata: Value = match &base_value {
+        Value::Object(base) => base.get("metadata").cloned().unwrap_or(Value::Object(Map::new())),
+        _ => Value::Object(Map::new()),
+    };
+    if let Some(child_metadata) = child.remove("metadata") {
+        merge_values(&mut metadata, child_metadata);
+    }
+    if let Value::Object(metadata) = &mut metadata {
+        metadata.remove("extends");
+    }
+
+    let mut programs: Vec<Value> = get_programs(&base_value).cloned().unwrap_or_default();
+    // The anchors that programs were inserted after, so that programs
+    // inserted after the same anchor keep their order
+    let mut insertion_anchors: Vec<Option<String>> = vec![None; programs.len()];
+    let child_programs: Vec<Value> = match child.remove("programs") {
+        Some(Value::Array(child_programs)) => child_programs,
+        Some(_) => return Err(anyhow!("`programs` of {} must be an array", origin)),
+        None => Vec::new(),
+    };
+
+    for (index, mut child_program) in child_programs.into_iter().enumerate() {
+        let (insert_after, remove): (Option<Value>, Option<Value>) = match &mut child_program {
+            Value::Object(program) => (program.remove(INSERT_AFTER_DIRECTIVE), program.remove(REMOVE_DIRECTIVE)),
+            _ => (None, None),
+        };
+        let is_removed: bool = remove.as_ref().and_then(|remove| remove.as_bool()).unwrap_or(false);
+        let matched_index: Option<usize> = get_alias(&child_program)
+            .and_then(|alias| programs.iter().position(|program| get_alias(program) == Some(alias)));
+
+        match matched_index {
+            Some(matched_index) if is_removed => {
+                programs.remove(matched_index);
+                program_origins.remove(matched_index);
+                insertion_anchors.remove(matched_index);
+            }
+            Some(matched_index) => {
+                merge_values(&mut programs[matched_index], child_program);
+                program_origins[matched_index] = format!("{}, overridden in {}", program_origins[matched_index], origin);
+            }
+            None if is_removed => {
+                return Err(anyhow!(
+                    "Program #{} of {} removes `{}`, but no program of the base has the alias",
+                    index,
+                    origin,
+                    get_alias(&child_program).unwrap_or_default()
+                ));
+            }
+            None => {
+                let position: usize = match insert_after {
+                    Some(anchor) => {
+                        let anchor: String = anchor.as_str().map(|anchor| anchor.to_string()).ok_or_else(|| {
+                            anyhow!("`insert_after` of program #{} of {} must be an alias", index, origin)
+                        })?;
+                        let anchor_index: usize = programs
+                            .iter()
+                            .position(|program| get_alias(program) == Some(anchor.as_str()))
+                            .ok_or_else(|| {
+                                anyhow!("Program #{} of {} is inserted after `{}`, but no program has the alias", index, origin, anchor)
+                            })?;
+                        let mut position: usize = anchor_index + 1;
+                        while insertion_anchors.get(position).is_some_and(|inserted| inserted.as_deref() == Some(anchor.as_str())) {
+                            position += 1;
+                        }
+                        insertion_anchors.insert(position, Some(anchor));
+                        position
+                    }
+                    None => {
+                        insertion_anchors.push(None);
+                        programs.len()
+                    }
+                };
+                programs.insert(position, child_program);
+                program_origins.insert(position, origin.to_string());
+            }
+        }
+    }
+
+    // Other top-level fields, e.g. `$schema`, are taken from the child,
+    // and the verification steps from the base unless the child has its own
+    let mut value: Map<String, Value> = child;
+    if !value.contains_key("verify") {
+        if let Some(verify) = base_value.get("verify") {
+            value.insert("verify".to_string(), verify.clone());
+        }
+    }
+    value.insert("metadata".to_string(), metadata);
+    value.insert("programs".to_string(), Value::Array(programs));
+
+    Ok(ResolvedChainFile { value: Value::Object(value), program_origins, declared_matrix_keys })
+}
@@ -1,8 +1,17 @@
+use clap::ValueEnum;
 use serde::{Deserialize, Serialize};
 
 /// Currently supported interpreters
-#[derive(Debug, Deserialize, Serialize, Eq, PartialEq, PartialOrd)]
+#[derive(Debug, Clone, Deserialize, Serialize, Eq, PartialEq, PartialOrd, ValueEnum)]
 pub enum Interpreter {
     #[serde(alias = "sh")]
     Sh,
 }
+
+impl std::fmt::Display for Interpreter {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Interpreter::Sh => write!(f, "sh"),
+        }
+    }
+}
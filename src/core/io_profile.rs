@@ -0,0 +1,384 @@
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        mpsc::{channel, RecvTimeoutError, Sender},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+/// How often the processes of a program are sampled with `--profile-io`
+pub const IO_SAMPLE_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Disk, I/O and CPU usage of a process, or of all the processes of a
+/// program. The counters of a process include the children it has
+/// waited for.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct IoUsage {
+    /// Bytes read from storage
+    pub read_bytes: u64,
+    /// Bytes written to storage, when they are dirtied
+    pub write_bytes: u64,
+    /// Bytes read by any read call, including the page cache, pipes and
+    /// sockets, so that much more than `read_bytes` hints at the network
+    pub all_read_bytes: u64,
+    /// Bytes written by any write call, including pipes and sockets
+    pub all_write_bytes: u64,
+    pub user_cpu_ms: u64,
+    pub system_cpu_ms: u64,
+}
+
+impl IoUsage {
+    /// Add the usage of another process
+    pub fn add(&self, other: &IoUsage) -> IoUsage {
+        IoUsage {
+            read_bytes: self.read_bytes + other.read_bytes,
+            write_bytes: self.write_bytes + other.write_bytes,
+            all_read_bytes: self.all_read_bytes + other.all_read_bytes,
+            all_write_bytes: self.all_write_bytes + other.all_write_bytes,
+            user_cpu_ms: self.user_cpu_ms + other.user_cpu_ms,
+            system_cpu_ms: self.system_cpu_ms + other.system_cpu_ms,
+        }
+    }
+
+    /// Keep the larger value of each counter. The counters only grow, but
+    /// a reading can miss a process that exited without being waited for.
+    pub fn max(&self, other: &IoUsage) -> IoUsage {
+        IoUsage {
+            read_bytes: self.read_bytes.max(other.read_bytes),
+            write_bytes: self.write_bytes.max(other.write_bytes),
+            all_read_bytes: self.all_read_bytes.max(other.all_read_bytes),
+            all_write_bytes: self.all_write_bytes.max(other.all_write_bytes),
+            user_cpu_ms: self.user_cpu_ms.max(other.user_cpu_ms),
+            system_cpu_ms: self.system_cpu_ms.max(other.system_cpu_ms),
+        }
+    }
+
+    /// Get the mean of each counter, e.g. over the iterations of a bench
+    pub fn mean(usages: &[IoUsage]) -> Option<IoUsage> {
+        if usages.is_empty() {
+            return None;
+        }
+        let total: IoUsage = usages.iter().fold(IoUsage::default(), |total, usage| total.add(usage));
+        let count: u64 = usages.len() as u64;
+
+        Some(IoUsage {
+            read_bytes: total.read_bytes / count,
+            write_bytes: total.write_bytes / count,
+            all_read_bytes: total.all_read_bytes / count,
+            all_write_bytes: total.all_write_bytes / count,
+            user_cpu_ms: total.user_cpu_ms / count,
+            system_cpu_ms: total.system_cpu_ms / count,
+        })
+    }
+}
+
+impl std::fmt::Display for IoUsage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "disk {} read, {} written; all I/O {} in, {} out; CPU {:.2}s user, {:.2}s system",
+            format_bytes(self.read_bytes),
+            format_bytes(self.write_bytes),
+            format_bytes(self.all_read_bytes),
+            format_bytes(self.all_write_bytes),
+            self.user_cpu_ms as f64 / 1000.0,
+            self.system_cpu_ms as f64 / 1000.0
+        )
+    }
+}
+
+/// Render a number of bytes with a binary unit, e.g. `8.0 MiB`
+pub fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+    let mut value: f64 = bytes as f64;
+    let mut unit: &str = UNITS[0];
+    for candidate in UNITS {
+        value /= 1024.0;
+        unit = candidate;
+        if value < 1024.0 {
+            break;
+        }
+    }
+
+    format!("{:.1} {}", value, unit)
+}
+
+/// Where a process is in the process tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProcessEntry {
+    pub process_id: u32,
+    pub parent_process_id: u32,
+    pub process_group_id: u32,
+}
+
+/// Reads the processes and their counters on a platform
+pub trait ProcessSampler: Send + Sync {
+    /// Whether the platform can be sampled at all
+    fn is_supported(&self) -> bool;
+
+    /// List the running processes, to find the descendants of a program
+    fn list_processes(&self) -> Vec<ProcessEntry>;
+
+    /// Read the counters of a process, `None` when it is gone or not
+    /// readable, e.g. a process of another user with `run_as`
+    fn sample(&self, process_id: u32) -> Option<IoUsage>;
+
+    /// Wait until the process exits, without reaping it, so that its
+    /// final counters can still be read. Returns at once where this is
+    /// not possible.
+    fn wait_for_exit(&self, _process_id: u32) {}
+}
+
+/// Reads `/proc/<pid>/io` and `/proc/<pid>/stat` on Linux
+#[cfg(target_os = "linux")]
+pub struct ProcfsSampler {
+    /// Clock ticks per second, the unit of the CPU times in `stat`
+    clock_ticks: u64,
+}
+
+#[cfg(target_os = "linux")]
+impl ProcfsSampler {
+    pub fn new() -> Self {
+        let clock_ticks: i64 = unsafe { libc::sysconf(libc::_SC_CLK_TCK) };
+        Self { clock_ticks: if clock_ticks > 0 { clock_ticks as u64 } else { 100 } }
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl Default for ProcfsSampler {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(target_os = "linux")]
+impl ProcessSampler for ProcfsSampler {
+    fn is_supported(&self) -> bool {
+        true
+    }
+
+    fn list_processes(&self) -> Vec<ProcessEntry> {
+        let Ok(entries) = std::fs::read_dir("/proc") else {
+            return Vec::new();
+        };
+        entries
+            .filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+            .filter_map(|process_id| {
+                let stat: String = std::fs::read_to_string(format!("/proc/{}/stat", process_id)).ok()?;
+                parse_proc_stat(process_id, &stat, self.clock_ticks).map(|(entry, _)| entry)
+            })
+            .collect()
+    }
+
+    fn sample(&self, process_id: u32) -> Option<IoUsage> {
+        let stat: String = std::fs::read_to_string(format!("/proc/{}/stat", process_id)).ok()?;
+        let io: String = std::fs::read_to_string(format!("/proc/{}/io", process_id)).ok()?;
+        let (_, cpu_usage) = parse_proc_stat(process_id, &stat, self.clock_ticks)?;
+
+        Some(parse_proc_io(&io)?.add(&cpu_usage))
+    }
+
+    fn wait_for_exit(&self, process_id: u32) {
+        let mut info: libc::siginfo_t = unsafe { std::mem::zeroed() };
+        loop {
+            let result: libc::c_int = unsafe {
+                libc::waitid(libc::P_PID, process_id as libc::id_t, &mut info, libc::WEXITED | libc::WNOWAIT)
+            };
+            if result == 0 || std::io::Error::last_os_error().kind() != std::io::ErrorKind::Interrupted {
+                return;
+            }
+        }
+    }
+}
+
+/// Stands in on the platforms that cannot be sampled yet
+pub struct UnsupportedSampler;
+
+impl ProcessSampler for UnsupportedSampler {
+    fn is_supported(&self) -> bool {
+        false
+    }
+
+    fn list_processes(&self) -> Vec<ProcessEntry> {
+        Vec::new()
+    }
+
+    fn sample(&self, _process_id: u32) -> Option<IoUsage> {
+        None
+    }
+}
+
+/// Get the sampler of the platform cchain runs on
+pub fn get_platform_sampler() -> Arc<dyn ProcessSampler> {
+    #[cfg(target_os = "linux")]
+    {
+        Arc::new(ProcfsSampler::new())
+    }
+    #[cfg(not(target_os = "linux"))]
+    {
+        Arc::new(UnsupportedSampler)
+    }
+}
+
+/// Whether `--profile-io` can measure anything on this platform
+pub fn is_io_profiling_supported() -> bool {
+    get_platform_sampler().is_supported()
+}
+
+/// Parse the counters of `/proc/<pid>/io`
+pub fn parse_proc_io(content: &str) -> Option<IoUsage> {
+    let counters: HashMap<&str, u64> = content
+        .lines()
+        .filter_map(|line| {
+            let (name, value) = line.split_once(':')?;
+            Some((name.trim(), value.trim().parse::<u64>().ok()?))
+        })
+        .collect();
+
+    Some(IoUsage {
+        read_bytes: *counters.get("read_bytes")?,
+        write_bytes: *counters.get("write_bytes")?,
+        all_read_bytes: *counters.get("rchar")?,
+        all_write_bytes: *counters.get("wchar")?,
+        ..Default::default()
+    })
+}
+
+/// Parse where a process is in the tree, and its CPU times with those of
+/// the children it waited for, from `/proc/<pid>/stat`
+pub fn parse_proc_stat(process_id: u32, content: &str, clock_ticks: u64) -> Option<(ProcessEntry, IoUsage)> {
+    // The command name is in parentheses and may contain anything, so the
+    // fields are counted from its end
+    let fields: Vec<&str> = content[content.rfind(')')? + 1..].split_whitespace().collect();
+    let field = |index: usize| -> Option<u64> { fields.get(index)?.parse::<u64>().ok() };
+    let to_ms = |ticks: u64| ticks * 1000 / clock_ticks.max(1);
+    let entry = ProcessEntry {
+        process_id,
+        parent_process_id: field(1)? as u32,
+        process_group_id: field(2)? as u32,
+    };
+    let cpu_usage = IoUsage {
+        user_cpu_ms: to_ms(field(11)? + field(13)?),
+        system_cpu_ms: to_ms(field(12)? + field(14)?),
+        ..Default::default()
+    };
+
+    Some((entry, cpu_usage))
+}
+
+/// The usage of the processes of a program: its process, the descendants
+/// of it, and the members of its process group when it leads one, e.g.
+/// in a fail-fast concurrency group
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IoProfile {
+    root_process_id: u32,
+    usage: Option<IoUsage>,
+}
+
+impl IoProfile {
+    pub fn new(root_process_id: u32) -> Self {
+        Self { root_process_id, usage: None }
+    }
+
+    /// Find the processes of the program among the running processes
+    pub fn get_members(&self, processes: &[ProcessEntry]) -> Vec<u32> {
+        let mut members: Vec<u32> = vec![self.root_process_id];
+        let mut known: HashSet<u32> = HashSet::from([self.root_process_id]);
+        for process in processes.iter().filter(|process| process.process_group_id == self.root_process_id) {
+            if known.insert(process.process_id) {
+                members.push(process.process_id);
+            }
+        }
+        // Children are found from their parents, however deep
+        let mut index: usize = 0;
+        while index < members.len() {
+            let parent: u32 = members[index];
+            for process in processes.iter().filter(|process| process.parent_process_id == parent) {
+                if known.insert(process.process_id) {
+                    members.push(process.process_id);
+                }
+            }
+            index += 1;
+        }
+
+        members
+    }
+
+    /// Record a reading of the processes of the program. The reading is
+    /// ignored without the process of the program, as the counters of
+    /// the children it waited for are in it.
+    pub fn record(&mut self, readings: &[(u32, IoUsage)]) {
+        if !readings.iter().any(|(process_id, _)| *process_id == self.root_process_id) {
+            return;
+        }
+        let total: IoUsage = readings.iter().fold(IoUsage::default(), |total, (_, usage)| total.add(usage));
+        self.usage = Some(match &self.usage {
+            Some(usage) => usage.max(&total),
+            None => total,
+        });
+    }
+
+    /// The largest usage recorded, `None` before any reading
+    pub fn get_usage(&self) -> Option<IoUsage> {
+        self.usage
+    }
+}
+
+/// Samples the processes of a running program on a thread of its own,
+/// so that waiting for the program is never held up
+pub struct IoProfiler {
+    sampler: Arc<dyn ProcessSampler>,
+    profile: Arc<Mutex<IoProfile>>,
+    stop: Sender<()>,
+}
+
+impl IoProfiler {
+    /// Start sampling the program of the process. The first reading is
+    /// taken after an interval, so that short programs cost nothing.
+    pub fn start(root_process_id: u32) -> Option<Self> {
+        let sampler: Arc<dyn ProcessSampler> = get_platform_sampler();
+        if !sampler.is_supported() {
+            return None;
+        }
+        let profile: Arc<Mutex<IoProfile>> = Arc::new(Mutex::new(IoProfile::new(root_process_id)));
+        let (stop, stopped) = channel::<()>();
+
+        let thread_sampler: Arc<dyn ProcessSampler> = sampler.clone();
+        let thread_profile: Arc<Mutex<IoProfile>> = profile.clone();
+        thread::spawn(move || {
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(IO_SAMPLE_INTERVAL) {
+                let members: Vec<u32> =
+                    thread_profile.lock().unwrap().get_members(&thread_sampler.list_processes());
+                let readings: Vec<(u32, IoUsage)> = members
+                    .into_iter()
+                    .filter_map(|process_id| Some((process_id, thread_sampler.sample(process_id)?)))
+                    .collect();
+                thread_profile.lock().unwrap().record(&readings);
+            }
+        });
+
+        Some(Self { sampler, profile, stop })
+    }
+
+    /// Stop sampling, and take the final reading once the process of the
+    /// program exits, before it is reaped. Call it right before waiting
+    /// for the process.
+    pub fn finish(self) -> Option<IoUsage> {
+        let _ = self.stop.send(());
+        let root_process_id: u32 = self.profile.lock().unwrap().root_process_id;
+        self.sampler.wait_for_exit(root_process_id);
+        let mut profile = self.profile.lock().unwrap();
+        if let Some(usage) = self.sampler.sample(root_process_id) {
+            profile.record(&[(root_process_id, usage)]);
+        }
+
+        profile.get_usage()
+    }
+}
@@ -0,0 +1,255 @@
+use std::{io::Read, path::Path, sync::Mutex};
+
+use anyhow::{anyhow, Error, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::variable::DEFAULT_OPENING_DELIMITER;
+
+/// The limits of the run, e.g. raised with `--limits`
+static CHAIN_LIMITS: Mutex<ChainLimits> = Mutex::new(ChainLimits::new());
+
+/// Limits on the chains that cchain loads, so that a huge or malicious
+/// chain file, e.g. one fetched from a remote repository, cannot exhaust
+/// the memory or the time of cchain
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ChainLimits {
+    /// Bytes of a chain file, together with the chains it extends. It is
+    /// checked before the file is read.
+    pub max_file_size: u64,
+    /// Programs in a chain, after merging the chains it extends
+    pub max_programs: usize,
+    /// Arguments of one program
+    pub max_arguments: usize,
+    /// Placeholders of variables, e.g. `<<name>>`, in all the programs
+    pub max_placeholders: usize,
+}
+
+impl Default for ChainLimits {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Limits that a chain acknowledges in its metadata, to be loaded even
+/// though it is larger than usual. The file size is checked before the
+/// metadata is read, so it can only be raised with `--limits`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AcknowledgedLimits {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_programs: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_arguments: Option<usize>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_placeholders: Option<usize>,
+}
+
+impl ChainLimits {
+    pub const fn new() -> Self {
+        Self {
+            max_file_size: 5 * 1024 * 1024,
+            max_programs: 500,
+            max_arguments: 1000,
+            max_placeholders: 10_000,
+        }
+    }
+
+    /// Change the limits given as `NAME=VALUE`, e.g. with `--limits
+    /// max_programs=2000`. File sizes take a `K`, `M` or `G` suffix.
+    pub fn with_overrides(mut self, overrides: &[(String, String)]) -> Result<Self, Error> {
+        for (name, value) in overrides {
+            let invalid_value = || anyhow!("`{}` is not a valid value for the limit `{}`", value, name);
+            match name.as_str() {
+                "max_file_size" => self.max_file_size = parse_size(value).ok_or_else(invalid_value)?,
+                "max_programs" => self.max_programs = value.parse().map_err(|_| invalid_value())?,
+                "max_arguments" => self.max_arguments = value.parse().map_err(|_| invalid_value())?,
+                "max_placeholders" => self.max_placeholders = value.parse().map_err(|_| invalid_value())?,
+                _ => {
+                    return Err(anyhow!(
+                        "Unknown limit `{}`, expected one of max_file_size, max_programs, max_arguments and max_placeholders",
+                        name
+                    ))
+                }
+            }
+        }
+
+        Ok(self)
+    }
+
+    /// Raise the limits to those that a chain acknowledges
+    pub fn with_acknowledged(self, acknowledged: &AcknowledgedLimits) -> Self {
+        Self {
+            max_file_size: self.max_file_size,
+            max_programs: self.max_programs.max(acknowledged.max_programs.unwrap_or(0)),
+            max_arguments: self.max_arguments.max(acknowledged.max_arguments.unwrap_or(0)),
+            max_placeholders: self.max_placeholders.max(acknowledged.max_placeholders.unwrap_or(0)),
+        }
+    }
+
+    /// Check the size of a chain before it is read
+    ///
+    /// # Arguments
+    ///
+    /// * `name` - The path of the chain, or the name of an inline chain
+    /// * `size` - Bytes of the chain, together with those read before it,
+    ///   e.g. of the chains that extend it
+    pub fn check_size(&self, name: &str, size: u64) -> Result<(), Error> {
+        if size > self.max_file_size {
+            return Err(anyhow!(
+                "Chain {} is larger than the limit of {} bytes, counting the chains it extends. If the chain is trusted, raise it with `--limits max_file_size=<bytes>`",
+                name,
+                self.max_file_size
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check the programs of a chain, in the JSON of a chain file, against
+    /// the limits as raised by the `limits` in its metadata
+    pub fn check_programs(&self, value: &Value) -> Result<(), Error> {
+        let acknowledged: AcknowledgedLimits = value
+            .pointer("/metadata/limits")
+            .and_then(|limits| serde_json::from_value(limits.clone()).ok())
+            .unwrap_or_default();
+        let limits: ChainLimits = self.with_acknowledged(&acknowledged);
+        let programs: &[Value] = match value {
+            Value::Array(programs) => programs,
+            _ => value.get("programs").and_then(Value::as_array).map_or(&[], |programs| programs.as_slice()),
+        };
+
+        if programs.len() > limits.max_programs {
+            return Err(get_limit_error(
+                &format!("{} programs", programs.len()),
+                "max_programs",
+                limits.max_programs,
+                programs.len(),
+            ));
+        }
+        for (index, program) in programs.iter().enumerate() {
+            let argument_count: usize = program.get("arguments").and_then(Value::as_array).map_or(0, |arguments| arguments.len());
+            if argument_count > limits.max_arguments {
+                return Err(get_limit_error(
+                    &format!("{} arguments in program #{}", argument_count, index),
+                    "max_arguments",
+                    limits.max_arguments,
+                    argument_count,
+                ));
+            }
+        }
+        let opening_delimiter: &str = value
+            .pointer("/metadata/variable_delimiters/open")
+            .and_then(Value::as_str)
+            .filter(|open| !open.is_empty())
+            .unwrap_or(DEFAULT_OPENING_DELIMITER);
+        let placeholder_count: usize = programs.iter().map(|program| count_placeholders(program, opening_delimiter)).sum();
+        if placeholder_count > limits.max_placeholders {
+            return Err(get_limit_error(
+                &format!("{} placeholders of variables", placeholder_count),
+                "max_placeholders",
+                limits.max_placeholders,
+                placeholder_count,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check the programs of a chain once its matrices are expanded,
+    /// against the limits as raised by the `limits` in its metadata
+    pub fn check_expanded_program_count(&self, acknowledged: &AcknowledgedLimits, program_count: usize) -> Result<(), Error> {
+        let limits: ChainLimits = self.with_acknowledged(acknowledged);
+        if program_count > limits.max_programs {
+            return Err(get_limit_error(
+                &format!("{} programs once its matrices are expanded", program_count),
+                "max_programs",
+                limits.max_programs,
+                program_count,
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// Tell which limit is exceeded, and the ways to raise it
+fn get_limit_error(exceeding: &str, limit_name: &str, limit: usize, needed: usize) -> Error {
+    anyhow!(
+        "The chain has {}, more than the limit of {}. If the chain is trusted, raise it with `\"limits\": {{\"{}\": {}}}` in the metadata, or `--limits {}={}`",
+        exceeding,
+        limit,
+        limit_name,
+        needed,
+        limit_name,
+        needed
+    )
+}
+
+/// Count the delimiters, e.g. `<<`, that start placeholders in the
+/// strings of a value
+fn count_placeholders(value: &Value, opening_delimiter: &str) -> usize {
+    match value {
+        Value::String(text) => text.matches(opening_delimiter).count(),
+        Value::Array(values) => values.iter().map(|value| count_placeholders(value, opening_delimiter)).sum(),
+        Value::Object(fields) => fields.values().map(|value| count_placeholders(value, opening_delimiter)).sum(),
+        _ => 0,
+    }
+}
+
+/// Parse a size in bytes, e.g. `2048`, `512K`, `20M` or `1G`
+fn parse_size(size: &str) -> Option<u64> {
+    let size: &str = size.trim();
+    let (number, multiplier): (&str, u64) = match size.chars().last()?.to_ascii_uppercase() {
+        'K' => (&size[..size.len() - 1], 1024),
+        'M' => (&size[..size.len() - 1], 1024 * 1024),
+        'G' => (&size[..size.len() - 1], 1024 * 1024 * 1024),
+        _ => (size, 1),
+    };
+
+    number.trim().parse::<u64>().ok()?.checked_mul(multiplier)
+}
+
+/// Set the limits of the chains loaded from now on
+pub fn set_chain_limits(chain_limits: ChainLimits) {
+    *CHAIN_LIMITS.lock().unwrap() = chain_limits;
+}
+
+pub fn get_chain_limits() -> ChainLimits {
+    *CHAIN_LIMITS.lock().unwrap()
+}
+
+/// Read a chain file, without reading more than the size limit allows
+///
+/// # Arguments
+///
+/// * `read_size` - Bytes of the chains read before, e.g. of the chains
+///   that extend this one, which is increased by the size of this file
+pub fn read_chain_file(path: &Path, read_size: &mut u64) -> Result<String, Error> {
+    let name: String = path.display().to_string();
+    let chain_limits: ChainLimits = get_chain_limits();
+    // The size is known without reading the file, which is only read
+    // within the limit in case it grows meanwhile
+    let file_size: u64 = std::fs::metadata(path)?.len();
+    chain_limits.check_size(&name, *read_size + file_size)?;
+
+    let content: String = read_within_size_limit(std::fs::File::open(path)?, &name, *read_size)?;
+    *read_size += content.len() as u64;
+
+    Ok(content)
+}
+
+/// Read a chain from a stream, e.g. stdin, without reading more than the
+/// size limit allows
+pub fn read_chain_stream(reader: impl Read, name: &str) -> Result<String, Error> {
+    read_within_size_limit(reader, name, 0)
+}
+
+fn read_within_size_limit(reader: impl Read, name: &str, read_size: u64) -> Result<String, Error> {
+    let chain_limits: ChainLimits = get_chain_limits();
+    let remaining_size: u64 = chain_limits.max_file_size.saturating_sub(read_size);
+    let mut content: String = String::new();
+    reader.take(remaining_size + 1).read_to_string(&mut content)?;
+    chain_limits.check_size(name, read_size + content.len() as u64)?;
+
+    Ok(content)
+}
@@ -0,0 +1,209 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+};
+
+use anyhow::{anyhow, Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{marker::bookmark::Bookmark, variable::ProvidedValue};
+
+/// The beginning of the file names of manifests by convention, e.g.
+/// `cchain_meta_release.json`
+pub const MANIFEST_FILE_NAME_PREFIX: &str = "cchain_meta_";
+
+/// Check whether a file is a manifest, by its content: a JSON object with
+/// a top-level `chains` key, which no chain file has. Files that cannot be
+/// read or parsed are left to be reported as chains.
+pub fn is_manifest_file(path: &Path) -> bool {
+    std::fs::read_to_string(path)
+        .ok()
+        .and_then(|content| serde_json::from_str::<serde_json::Value>(&content).ok())
+        .is_some_and(|value| value.get("chains").is_some())
+}
+
+/// A chain of a manifest
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ManifestEntry {
+    /// Name that other entries depend on the chain by. Defaults to
+    /// `chain`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// Stable ID of a bookmarked chain, or path to a chain file,
+    /// relative to the manifest
+    pub chain: String,
+    /// Names of the entries that finish before the chain starts. The
+    /// chain is skipped unless all of them succeed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub depends_on: Vec<String>,
+    /// Run the chain once its dependencies finish, even when they fail
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub always_run: bool,
+    /// Values of the variables of the chain, like `--var`. Those given
+    /// on the command line take precedence.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, String>,
+}
+
+impl ManifestEntry {
+    pub fn get_name(&self) -> &str {
+        self.name.as_deref().unwrap_or(&self.chain)
+    }
+}
+
+/// Chains that run together, each as a unit, in the order of their
+/// dependencies, as in a `cchain_meta_*.json` file
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct ChainManifest {
+    /// Run up to this many chains at a time. `--parallel` takes
+    /// precedence.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub parallel: Option<usize>,
+    pub chains: Vec<ManifestEntry>,
+}
+
+impl ChainManifest {
+    pub fn from_file(path: &Path) -> Result<Self, Error> {
+        let content: String = std::fs::read_to_string(path)
+            .map_err(|error| anyhow!("Cannot read the manifest {}: {}", path.display(), error))?;
+
+        Self::from_json_str(&content, &path.display().to_string())
+    }
+
+    /// Parse and validate a manifest. `source` names it in the errors.
+    pub fn from_json_str(content: &str, source: &str) -> Result<Self, Error> {
+        let manifest: Self = serde_json::from_str(content)
+            .map_err(|error| anyhow!("Manifest {} is invalid: {}", source, error))?;
+        manifest
+            .validate()
+            .map_err(|error| anyhow!("Manifest {} is invalid: {}", source, error))?;
+
+        Ok(manifest)
+    }
+
+    pub fn get_entries(&self) -> &Vec<ManifestEntry> {
+        &self.chains
+    }
+
+    pub fn get_parallel(&self) -> Option<usize> {
+        self.parallel
+    }
+
+    /// Check that the names are unique, and that the dependencies exist
+    /// and do not form a cycle
+    fn validate(&self) -> Result<(), Error> {
+        if self.chains.is_empty() {
+            return Err(anyhow!("it has no chains"));
+        }
+        if self.parallel == Some(0) {
+            return Err(anyhow!("`parallel` must be at least 1"));
+        }
+
+        let mut indices: HashMap<&str, usize> = HashMap::new();
+        for (index, entry) in self.chains.iter().enumerate() {
+            if indices.insert(entry.get_name(), index).is_some() {
+                return Err(anyhow!(
+                    "more than one entry is named `{}`. Give them distinct `name`s",
+                    entry.get_name()
+                ));
+            }
+        }
+        for entry in &self.chains {
+            for dependency in &entry.depends_on {
+                if dependency == entry.get_name() {
+                    return Err(anyhow!("`{}` depends on itself", dependency));
+                }
+                if !indices.contains_key(dependency.as_str()) {
+                    return Err(anyhow!("`{}` depends on `{}`, which is not in the manifest", entry.get_name(), dependency));
+                }
+            }
+        }
+        self.get_stages()?;
+
+        Ok(())
+    }
+
+    /// Get the indices of the entries that the entry depends on
+    pub fn get_dependency_indices(&self, index: usize) -> Vec<usize> {
+        self.chains[index]
+            .depends_on
+            .iter()
+            .filter_map(|dependency| self.chains.iter().position(|entry| entry.get_name() == dependency))
+            .collect()
+    }
+
+    /// Group the entries into stages: the first stage depends on
+    /// nothing, and every later one only on the stages before it. The
+    /// entries of a stage can run at the same time.
+    pub fn get_stages(&self) -> Result<Vec<Vec<usize>>, Error> {
+        let mut stage_of: Vec<Option<usize>> = vec![None; self.chains.len()];
+        let mut stages: Vec<Vec<usize>> = Vec::new();
+        while stage_of.iter().any(Option::is_none) {
+            let stage: Vec<usize> = (0..self.chains.len())
+                .filter(|&index| {
+                    stage_of[index].is_none()
+                        && self
+                            .get_dependency_indices(index)
+                            .iter()
+                            .all(|&dependency| stage_of[dependency].is_some())
+                })
+                .collect();
+            if stage.is_empty() {
+                let names: Vec<String> = (0..self.chains.len())
+                    .filter(|&index| stage_of[index].is_none())
+                    .map(|index| format!("`{}`", self.chains[index].get_name()))
+                    .collect();
+                return Err(anyhow!("the dependencies of {} form a cycle", names.join(", ")));
+            }
+            for &index in &stage {
+                stage_of[index] = Some(stages.len());
+            }
+            stages.push(stage);
+        }
+
+        Ok(stages)
+    }
+
+    /// Resolve the chains of the entries into paths. An entry is tried
+    /// as a stable ID first, so that an ID never changes its meaning, and
+    /// then as a path relative to the manifest.
+    pub fn resolve_chain_paths(&self, manifest_path: &Path, bookmark: &Bookmark) -> Vec<Result<String, Error>> {
+        let directory: &Path = manifest_path.parent().unwrap_or(Path::new(""));
+        self.chains
+            .iter()
+            .map(|entry| {
+                if let Some(index) = bookmark.get_index_by_id(&entry.chain) {
+                    return Ok(bookmark.get_chain_references()[index].get_chain_path_string());
+                }
+                let path: PathBuf = directory.join(&entry.chain);
+                if path.is_file() {
+                    return Ok(path.to_string_lossy().to_string());
+                }
+
+                Err(anyhow!(
+                    "Chain `{}` of `{}` is neither a bookmarked ID nor a file next to the manifest",
+                    entry.chain,
+                    entry.get_name()
+                ))
+            })
+            .collect()
+    }
+
+    /// Get the variables of an entry as provided values
+    pub fn get_provided_values(&self, index: usize) -> HashMap<String, ProvidedValue> {
+        let entry: &ManifestEntry = &self.chains[index];
+        entry
+            .variables
+            .iter()
+            .map(|(name, value)| {
+                let provided_value = ProvidedValue {
+                    value: value.clone(),
+                    source: format!("the variables of `{}` in the manifest", entry.get_name()),
+                };
+                (name.clone(), provided_value)
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,446 @@
+use std::collections::BTreeSet;
+
+use anyhow::{anyhow, Error, Result};
+use serde::{
+    de::{DeserializeSeed, IgnoredAny, MapAccess, SeqAccess, Visitor},
+    ser::SerializeMap,
+    Deserialize, Deserializer, Serialize, Serializer,
+};
+
+use super::{
+    limits::{get_chain_limits, AcknowledgedLimits},
+    program::Program,
+};
+
+/// The start of the placeholders of the values of a matrix, e.g. `<<matrix.os>>`
+pub const MATRIX_PLACEHOLDER_PREFIX: &str = "<<matrix.";
+
+const PLACEHOLDER_SUFFIX: &str = ">>";
+
+/// The escape of a literal `<<`, which never starts a placeholder
+const ESCAPED_OPENING_DELIMITER: &str = "<<<<";
+
+/// The values of a matrix by key, in the order the keys are declared, as
+/// the order decides the aliases and the variables of the expanded
+/// programs
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Matrix(Vec<(String, Vec<String>)>);
+
+impl Matrix {
+    pub fn new(entries: Vec<(String, Vec<String>)>) -> Self {
+        Self(entries)
+    }
+
+    pub fn get_entries(&self) -> &[(String, Vec<String>)] {
+        &self.0
+    }
+
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.0.iter().any(|(name, _)| name == key)
+    }
+
+    /// Put the keys back in the order they are declared in the chain
+    /// files, which is lost when the matrix is read from a JSON value
+    pub fn order_keys(&mut self, declared_matrix_keys: &DeclaredMatrixKeys) {
+        let keys: Vec<&str> = self.0.iter().map(|(key, _)| key.as_str()).collect();
+        let declared_keys: Vec<String> = declared_matrix_keys.get_order(&keys);
+        self.0.sort_by_key(|(key, _)| declared_keys.iter().position(|declared_key| declared_key == key));
+    }
+}
+
+impl Serialize for Matrix {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut map = serializer.serialize_map(Some(self.0.len()))?;
+        for (key, values) in &self.0 {
+            map.serialize_entry(key, values)?;
+        }
+        map.end()
+    }
+}
+
+impl<'de> Deserialize<'de> for Matrix {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct MatrixVisitor;
+
+        impl<'de> Visitor<'de> for MatrixVisitor {
+            type Value = Matrix;
+
+            fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+                formatter.write_str("an object of the values of each key")
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Matrix, A::Error> {
+                let mut entries: Vec<(String, Vec<String>)> = Vec::new();
+                while let Some((key, values)) = map.next_entry::<String, Vec<String>>()? {
+                    // A key given twice takes its last values, as in other objects
+                    entries.retain(|(name, _)| *name != key);
+                    entries.push((key, values));
+                }
+                Ok(Matrix(entries))
+            }
+        }
+
+        deserializer.deserialize_map(MatrixVisitor)
+    }
+}
+
+/// The keys of the matrices in chain files, in the order they are
+/// declared. Chains are parsed through JSON values, whose objects keep
+/// their keys sorted, so the order is read from the files themselves.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DeclaredMatrixKeys(Vec<Vec<String>>);
+
+impl DeclaredMatrixKeys {
+    /// Read the keys of each `matrix` in the JSON of a chain file. JSON
+    /// that does not parse declares none.
+    pub fn from_content(content: &str) -> Self {
+        let mut declared_keys: Vec<Vec<String>> = Vec::new();
+        let mut deserializer = serde_json::Deserializer::from_str(content);
+        let _ = MatrixKeyCollector { declared_keys: &mut declared_keys, is_matrix: false }.deserialize(&mut deserializer);
+
+        Self(declared_keys)
+    }
+
+    /// Get the keys of the matrices of parsed programs, which are in the
+    /// declared order already
+    pub fn from_programs<'a>(programs: impl IntoIterator<Item = &'a Program>) -> Self {
+        Self(
+            programs
+                .into_iter()
+                .filter_map(Program::get_matrix)
+                .map(|matrix| matrix.get_entries().iter().map(|(key, _)| key.clone()).collect())
+                .collect(),
+        )
+    }
+
+    /// Add the keys declared in another file, e.g. one extending the
+    /// chain of this one
+    pub fn extend(&mut self, other: DeclaredMatrixKeys) {
+        self.0.extend(other.0);
+    }
+
+    /// Get the keys in the order they are declared. Keys of a matrix merged
+    /// from several files follow the files they are first declared in,
+    /// and keys declared nowhere come last, as they are.
+    pub fn get_order(&self, keys: &[&str]) -> Vec<String> {
+        let is_same_keys = |declared_keys: &&Vec<String>| {
+            declared_keys.len() == keys.len() && keys.iter().all(|key| declared_keys.iter().any(|declared_key| declared_key == key))
+        };
+        if let Some(declared_keys) = self.0.iter().find(is_same_keys) {
+            return declared_keys.clone();
+        }
+
+        let mut ordered_keys: Vec<String> = Vec::new();
+        for declared_key in self.0.iter().flatten() {
+            if keys.contains(&declared_key.as_str()) && !ordered_keys.contains(declared_key) {
+                ordered_keys.push(declared_key.clone());
+            }
+        }
+        for key in keys {
+            if !ordered_keys.iter().any(|ordered_key| ordered_key == key) {
+                ordered_keys.push(key.to_string());
+            }
+        }
+
+        ordered_keys
+    }
+
+    /// Order the keys of a `matrix` object of a JSON value as declared,
+    /// e.g. for writing it back
+    pub fn order_object_keys(&self, keys: &mut [&String]) {
+        let names: Vec<&str> = keys.iter().map(|key| key.as_str()).collect();
+        let declared_keys: Vec<String> = self.get_order(&names);
+        keys.sort_by_key(|key| declared_keys.iter().position(|declared_key| declared_key == *key));
+    }
+}
+
+/// Walks the JSON of a chain file, and records the keys of each `matrix`
+/// object in the order they come
+struct MatrixKeyCollector<'a> {
+    declared_keys: &'a mut Vec<Vec<String>>,
+    /// Whether the value walked is a `matrix`
+    is_matrix: bool,
+}
+
+impl<'de> DeserializeSeed<'de> for MatrixKeyCollector<'_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_any(self)
+    }
+}
+
+impl<'de> Visitor<'de> for MatrixKeyCollector<'_> {
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("any JSON value")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut keys: Vec<String> = Vec::new();
+        while let Some(key) = map.next_key::<String>()? {
+            if self.is_matrix {
+                map.next_value::<IgnoredAny>()?;
+            } else {
+                map.next_value_seed(MatrixKeyCollector { declared_keys: self.declared_keys, is_matrix: key == "matrix" })?;
+            }
+            keys.push(key);
+        }
+        if self.is_matrix {
+            self.declared_keys.push(keys);
+        }
+
+        Ok(())
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<(), A::Error> {
+        while seq.next_element_seed(MatrixKeyCollector { declared_keys: self.declared_keys, is_matrix: false })?.is_some() {}
+
+        Ok(())
+    }
+
+    fn visit_bool<E>(self, _: bool) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_i64<E>(self, _: i64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_u64<E>(self, _: u64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_f64<E>(self, _: f64) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_str<E>(self, _: &str) -> Result<(), E> {
+        Ok(())
+    }
+
+    fn visit_unit<E>(self) -> Result<(), E> {
+        Ok(())
+    }
+}
+
+/// One combination of the values of a matrix, in the order of its keys
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MatrixCombination(Vec<(String, String)>);
+
+impl MatrixCombination {
+    pub fn get_value(&self, key: &str) -> Option<&str> {
+        self.0.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+    }
+
+    /// Get the values joined for the alias of the expanded program, e.g.
+    /// `linux,release`
+    pub fn get_label(&self) -> String {
+        self.0.iter().map(|(_, value)| value.as_str()).collect::<Vec<&str>>().join(",")
+    }
+
+    /// Get the suffix of the variables that the expanded program stores,
+    /// e.g. `_linux_release`. Characters other than letters, digits and
+    /// `_` are replaced with `_`.
+    pub fn get_variable_suffix(&self) -> String {
+        self.0
+            .iter()
+            .map(|(_, value)| {
+                let value: String = value
+                    .chars()
+                    .map(|character| if character.is_alphanumeric() || character == '_' { character } else { '_' })
+                    .collect();
+                format!("_{}", value)
+            })
+            .collect()
+    }
+
+    /// Replace the placeholders of the matrix in a text with the values of
+    /// the combination. Placeholders of keys out of the matrix are kept.
+    pub fn substitute(&self, text: &str) -> String {
+        replace_matrix_placeholders(text, |key| self.get_value(key))
+    }
+}
+
+/// Remove the placeholders of matrix values from a text, whatever their keys
+pub fn remove_matrix_placeholders(text: &str) -> String {
+    replace_matrix_placeholders(text, |_| Some(""))
+}
+
+/// Replace the placeholders of matrix values in a text with the values
+/// of their keys. Placeholders of keys without a value are kept.
+fn replace_matrix_placeholders<'a>(text: &str, get_value: impl Fn(&str) -> Option<&'a str>) -> String {
+    let mut substituted: String = String::new();
+    let mut rest: &str = text;
+    while let Some((start, key, end)) = find_matrix_placeholder(rest) {
+        substituted.push_str(&rest[..start]);
+        match get_value(key) {
+            Some(value) => substituted.push_str(value),
+            None => substituted.push_str(&rest[start..end]),
+        }
+        rest = &rest[end..];
+    }
+    substituted.push_str(rest);
+
+    substituted
+}
+
+/// Find the first placeholder of a matrix value in a text, skipping the
+/// escaped `<<<<`
+///
+/// # Returns
+///
+/// The start of the placeholder, the key and the end of the placeholder
+fn find_matrix_placeholder(text: &str) -> Option<(usize, &str, usize)> {
+    let mut offset: usize = 0;
+    loop {
+        let rest: &str = &text[offset..];
+        let escape: Option<usize> = rest.find(ESCAPED_OPENING_DELIMITER);
+        let start: usize = rest.find(MATRIX_PLACEHOLDER_PREFIX)?;
+        if let Some(escape) = escape.filter(|escape| *escape <= start) {
+            offset += escape + ESCAPED_OPENING_DELIMITER.len();
+            continue;
+        }
+
+        let key_start: usize = offset + start + MATRIX_PLACEHOLDER_PREFIX.len();
+        let key_length: usize = text[key_start..].find(PLACEHOLDER_SUFFIX)?;
+        let key: &str = &text[key_start..key_start + key_length];
+        return Some((offset + start, key, key_start + key_length + PLACEHOLDER_SUFFIX.len()));
+    }
+}
+
+/// Get the keys of the matrix placeholders in a text, e.g. `os` of `<<matrix.os>>`
+pub fn get_matrix_placeholder_keys(text: &str) -> Vec<String> {
+    let mut keys: Vec<String> = Vec::new();
+    let mut rest: &str = text;
+    while let Some((_, key, end)) = find_matrix_placeholder(rest) {
+        keys.push(key.to_string());
+        rest = &rest[end..];
+    }
+
+    keys
+}
+
+/// Suffix the name of a stored variable, e.g. `<<artifact>>` to
+/// `<<artifact_linux>>`. A bare name is suffixed as it is.
+pub fn add_variable_suffix(stored_to: &str, suffix: &str) -> String {
+    match stored_to.strip_suffix(PLACEHOLDER_SUFFIX) {
+        Some(name) if stored_to.starts_with("<<") => format!("{}{}{}", name, suffix, PLACEHOLDER_SUFFIX),
+        _ => format!("{}{}", stored_to, suffix),
+    }
+}
+
+/// Get the combinations of the values of a matrix. The values of the
+/// first key change the slowest.
+pub fn get_matrix_combinations(matrix: &Matrix) -> Vec<MatrixCombination> {
+    let mut combinations: Vec<Vec<(String, String)>> = vec![Vec::new()];
+    for (key, values) in &matrix.0 {
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination: Vec<(String, String)> = combination.clone();
+                    combination.push((key.clone(), value.clone()));
+                    combination
+                })
+            })
+            .collect();
+    }
+
+    combinations.into_iter().map(MatrixCombination).collect()
+}
+
+/// Count the combinations of a matrix without listing them
+pub fn count_matrix_combinations(matrix: &Matrix) -> usize {
+    matrix.0.iter().fold(1, |count: usize, (_, values)| count.saturating_mul(values.len()))
+}
+
+/// Check that a matrix has keys, that each key is a name and has values,
+/// and that the values of a key are distinct
+pub fn validate_matrix(matrix: &Matrix) -> Result<(), Error> {
+    if matrix.0.is_empty() {
+        return Err(anyhow!("`matrix` has no keys"));
+    }
+    for (key, values) in &matrix.0 {
+        if key.is_empty() || !key.chars().all(|character| character.is_alphanumeric() || character == '_') {
+            return Err(anyhow!("the key `{}` of `matrix` may only contain letters, digits and `_`", key));
+        }
+        if values.is_empty() {
+            return Err(anyhow!("the key `{}` of `matrix` has no values", key));
+        }
+        let mut seen_values: BTreeSet<&String> = BTreeSet::new();
+        if let Some(value) = values.iter().find(|value| !seen_values.insert(*value)) {
+            return Err(anyhow!("the key `{}` of `matrix` has the value `{}` twice", key, value));
+        }
+    }
+
+    Ok(())
+}
+
+/// Programs of a chain with their matrices expanded
+#[derive(Debug)]
+pub struct ExpandedPrograms {
+    pub programs: Vec<Program>,
+    /// The index of the program each expanded program came from
+    pub source_indices: Vec<usize>,
+}
+
+/// Expand the programs with a `matrix` into a program per combination of
+/// its values. Programs with `matrix_parallel` are put in concurrency
+/// groups of their own, numbered after the groups of the chain.
+///
+/// # Arguments
+///
+/// * `programs` - The programs and the verification steps of the chain
+/// * `acknowledged_limits` - The `limits` in the metadata of the chain
+pub fn expand_matrix_programs(
+    programs: Vec<Program>,
+    acknowledged_limits: &AcknowledgedLimits,
+) -> Result<ExpandedPrograms, Error> {
+    let mut program_count: usize = 0;
+    for (index, program) in programs.iter().enumerate() {
+        program.validate_matrix().map_err(|error| anyhow!("Program #{}: {}", index, error))?;
+        program_count = program_count.saturating_add(program.get_matrix().map_or(1, count_matrix_combinations));
+    }
+    // The combinations are counted before they are listed, so that a
+    // huge matrix never takes the memory
+    get_chain_limits().check_expanded_program_count(acknowledged_limits, program_count)?;
+
+    let mut next_concurrency_group: usize = programs
+        .iter()
+        .filter_map(Program::get_concurrency_group)
+        .max()
+        .map_or(0, |concurrency_group| concurrency_group + 1);
+    let mut expanded_programs = ExpandedPrograms { programs: Vec::new(), source_indices: Vec::new() };
+    for (index, program) in programs.into_iter().enumerate() {
+        if program.get_matrix().is_none() {
+            expanded_programs.programs.push(program);
+            expanded_programs.source_indices.push(index);
+            continue;
+        }
+
+        let concurrency_group: Option<usize> = program.is_matrix_parallel().then(|| {
+            next_concurrency_group += 1;
+            next_concurrency_group - 1
+        });
+        let mut stored_variables: BTreeSet<String> = BTreeSet::new();
+        for expanded_program in program.expand_matrix(concurrency_group) {
+            // Values that only differ in the characters left out of the
+            // names, e.g. `x-y` and `x_y`, would store the same variable
+            if let Some(stdout_stored_to) = expanded_program.get_awaitable_variable() {
+                if !stored_variables.insert(stdout_stored_to.clone()) {
+                    return Err(anyhow!(
+                        "Program #{}: more than one combination of `matrix` stores `{}`. Use the placeholders of the values in `stdout_stored_to`, so that each combination stores its own variable",
+                        index,
+                        stdout_stored_to
+                    ));
+                }
+            }
+            expanded_programs.programs.push(expanded_program);
+            expanded_programs.source_indices.push(index);
+        }
+    }
+
+    Ok(expanded_programs)
+}
@@ -0,0 +1,514 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+use anyhow::{anyhow, Error, Result};
+use semver::{Version, VersionReq};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    commons::digest::sha256_hex,
+    variable::{VariableDeclaration, VariableDelimiters},
+};
+
+use super::{
+    builtin::BUILTIN_STEP_FIELD_NAMES,
+    group::{ConcurrencyGroupOptions, MemberFailurePolicy},
+    inheritance::{resolve_chain_file, ResolvedChainFile},
+    limits::{get_chain_limits, AcknowledgedLimits},
+    matrix::DeclaredMatrixKeys,
+    migration::migrate_legacy_fields,
+    paths::resolve_chain_relative_path,
+    program::Program,
+};
+
+/// Fields accepted at the top level of a chain file
+pub const CHAIN_FILE_FIELD_NAMES: &[&str] = &["$schema", "metadata", "programs", "verify"];
+
+/// Fields of a program that cannot be omitted, except in built-in steps
+pub const REQUIRED_PROGRAM_FIELD_NAMES: &[&str] = &["command", "arguments", "retry"];
+
+/// Fields accepted in the metadata section
+pub const METADATA_FIELD_NAMES: &[&str] = &[
+    "variables",
+    "requires_cchain",
+    "strict",
+    "escalation_command",
+    "tags",
+    "on_member_failure",
+    "concurrency_groups",
+    "load_adjacent_env",
+    "generated_by",
+    "extends",
+    "base_directory",
+    "timezone",
+    "locale",
+    "limits",
+    "variable_delimiters",
+    "keep_tmp",
+    "lazy_prompts",
+];
+
+/// Chain-level settings that apply to all programs in a chain
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ChainMetadata {
+    /// Declarations of the variables used in the chain, keyed by
+    /// the variable name
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, VariableDeclaration>,
+    /// A semver requirement on the cchain version running the chain,
+    /// e.g. `>=0.4.0`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub requires_cchain: Option<String>,
+    /// Reject fields that this version of cchain does not know about,
+    /// instead of warning about them
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub strict: bool,
+    /// The command used by programs with `run_as` to run as another user
+    /// when cchain is unprivileged, e.g. `doas`. By default, `sudo` or
+    /// `doas` is detected.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub escalation_command: Option<String>,
+    /// Tags for operating on bookmarked chains as groups,
+    /// e.g. `cchain run --tag release`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    /// What happens to the other members of a concurrency group when
+    /// one of them fails, unless the group sets its own policy
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub on_member_failure: Option<MemberFailurePolicy>,
+    /// Settings of the concurrency groups, keyed by the group numbers
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub concurrency_groups: BTreeMap<usize, ConcurrencyGroupOptions>,
+    /// Load default values of variables from the `.cchain.env` file next
+    /// to the chain file. Off by default, so that a chain in an untrusted
+    /// directory cannot inject values.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub load_adjacent_env: bool,
+    /// How the chain was generated by `cchain new --prompt`, for review.
+    /// Ignored when running.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub generated_by: Option<GenerationProvenance>,
+    /// The chain file that this chain builds on, relative to this file.
+    /// Programs with the alias of a base program override its fields,
+    /// and the others are added.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub extends: Option<String>,
+    /// The directory that relative paths in the chain resolve against,
+    /// relative to the chain file. Defaults to the directory of the chain file.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub base_directory: Option<String>,
+    /// The IANA time zone that every program runs in, e.g.
+    /// `Europe/Berlin`, set as `TZ`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub timezone: Option<String>,
+    /// The locale that every program runs in, e.g. `C.UTF-8`, set as
+    /// `LANG` and `LC_ALL`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub locale: Option<String>,
+    /// Limits that the chain is known to exceed, e.g. a chain of more than
+    /// 500 programs, so that it is loaded anyway
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub limits: Option<AcknowledgedLimits>,
+    /// The delimiters of the placeholders, e.g. `{"open": "[[", "close": "]]"}`
+    /// for chains whose arguments contain `<<` and `>>`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub variable_delimiters: Option<VariableDelimiters>,
+    /// Keep the temporary directory of each run, `<<chain_tmp>>`, for
+    /// debugging, instead of removing it when the chain finishes
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub keep_tmp: bool,
+    /// Ask for each `on_chain_startup` variable right before the first
+    /// program using it starts, instead of all of them before the chain
+    /// starts, so that a run that stops earlier never asks for it
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub lazy_prompts: bool,
+}
+
+/// Where a generated chain came from
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct GenerationProvenance {
+    /// The cchain version that generated the chain
+    pub tool: String,
+    /// The API base of the LLM provider
+    pub provider: String,
+    pub model: String,
+    /// When the chain was generated, in seconds since the Unix epoch
+    pub generated_at: u64,
+    pub prompt_sha256: String,
+    /// The prompt itself, with `--embed-prompt`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prompt: Option<String>,
+    /// The digest of the programs as generated, to detect later edits
+    pub programs_sha256: String,
+}
+
+impl GenerationProvenance {
+    /// Compute the digest of programs. Formatting and fields left at
+    /// their defaults do not change it.
+    pub fn get_programs_digest(programs: &[Program]) -> Result<String, Error> {
+        Ok(sha256_hex(serde_json::to_string(&serde_json::to_value(programs)?)?.as_bytes()))
+    }
+
+    /// Check whether the programs are different from the generated ones
+    pub fn is_modified(&self, programs: &[Program]) -> Result<bool, Error> {
+        Ok(Self::get_programs_digest(programs)? != self.programs_sha256)
+    }
+}
+
+impl std::fmt::Display for GenerationProvenance {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{} with {} ({}) at {}, from a prompt with SHA-256 {}",
+            self.tool,
+            self.model,
+            self.provider,
+            format_timestamp(self.generated_at),
+            self.prompt_sha256
+        )
+    }
+}
+
+/// Format seconds since the Unix epoch as a UTC date and time
+pub fn format_timestamp(timestamp: u64) -> String {
+    let days: i64 = (timestamp / 86400) as i64;
+    let seconds_of_day: u64 = timestamp % 86400;
+
+    // Convert the days to a civil date, counting in 400-year eras from 0000-03-01
+    let days: i64 = days + 719468;
+    let era: i64 = days.div_euclid(146097);
+    let day_of_era: i64 = days - era * 146097;
+    let year_of_era: i64 = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+    let day_of_year: i64 = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_index: i64 = (5 * day_of_year + 2) / 153;
+    let day: i64 = day_of_year - (153 * month_index + 2) / 5 + 1;
+    let month: i64 = if month_index < 10 { month_index + 3 } else { month_index - 9 };
+    let year: i64 = year_of_era + era * 400 + i64::from(month <= 2);
+
+    format!(
+        "{:04}-{:02}-{:02} {:02}:{:02}:{:02} UTC",
+        year,
+        month,
+        day,
+        seconds_of_day / 3600,
+        seconds_of_day % 3600 / 60,
+        seconds_of_day % 60
+    )
+}
+
+impl ChainMetadata {
+    /// Get the failure policy of a concurrency group
+    pub fn get_member_failure_policy(&self, concurrency_group: usize) -> MemberFailurePolicy {
+        self.concurrency_groups
+            .get(&concurrency_group)
+            .and_then(|options| options.on_member_failure)
+            .or(self.on_member_failure)
+            .unwrap_or_default()
+    }
+
+    /// Get the environment variables pinned by `timezone` and `locale`.
+    /// Programs override them with `environment_variables_override`.
+    pub fn get_pinned_environment(&self) -> BTreeMap<String, String> {
+        let mut environment: BTreeMap<String, String> = BTreeMap::new();
+        if let Some(timezone) = &self.timezone {
+            environment.insert("TZ".to_string(), timezone.clone());
+        }
+        if let Some(locale) = &self.locale {
+            environment.insert("LANG".to_string(), locale.clone());
+            environment.insert("LC_ALL".to_string(), locale.clone());
+        }
+
+        environment
+    }
+
+    /// Check that `timezone` is written as an IANA time zone name,
+    /// e.g. `UTC` or `America/Argentina/Buenos_Aires`. Whether the
+    /// zone exists depends on the time zone database of the machine.
+    pub fn validate_timezone(&self) -> Result<(), Error> {
+        let timezone: &String = match &self.timezone {
+            Some(timezone) => timezone,
+            None => return Ok(()),
+        };
+
+        let is_valid: bool = timezone.split('/').all(|part| {
+            part.chars().next().is_some_and(|character| character.is_ascii_alphabetic())
+                && part.chars().all(|character| character.is_ascii_alphanumeric() || matches!(character, '_' | '-' | '+'))
+        });
+        if !is_valid {
+            return Err(anyhow!(
+                "Invalid `timezone` `{}`. Please use an IANA time zone name, e.g. `UTC` or `Europe/Berlin`",
+                timezone
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Check whether `locale` is installed, as listed by `locale -a`.
+    /// `None` when no locale is set, or the locales cannot be listed,
+    /// e.g. on Windows.
+    pub fn is_locale_available(&self) -> Option<bool> {
+        let locale: &String = self.locale.as_ref()?;
+        // Codesets are listed in various spellings, e.g. `UTF-8` as `utf8`
+        let normalize = |name: &str| name.to_lowercase().replace('-', "");
+        if matches!(locale.as_str(), "C" | "POSIX") {
+            return Some(true);
+        }
+
+        let output = std::process::Command::new("locale").arg("-a").output().ok()?;
+        if !output.status.success() {
+            return None;
+        }
+
+        Some(
+            String::from_utf8_lossy(&output.stdout)
+                .lines()
+                .any(|available_locale| normalize(available_locale.trim()) == normalize(locale)),
+        )
+    }
+
+    /// Check the `requires_cchain` requirement against a cchain version
+    pub fn check_version_requirement(&self, current_version: &str) -> Result<(), Error> {
+        let requirement: &String = match &self.requires_cchain {
+            Some(requirement) => requirement,
+            None => return Ok(()),
+        };
+
+        let version_requirement = VersionReq::parse(requirement).map_err(|error| {
+            anyhow!("Invalid `requires_cchain` requirement `{}`: {}", requirement, error)
+        })?;
+        let version = Version::parse(current_version)?;
+
+        if !version_requirement.matches(&version) {
+            return Err(anyhow!(
+                "This chain requires cchain {}, but the current version is {}. Please upgrade with `cargo install cchain`.",
+                requirement,
+                current_version
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+/// The content of a chain file.
+///
+/// A chain file is either a plain array of programs, or an object
+/// with a `metadata` section next to the `programs` array.
+#[derive(Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ChainFile {
+    /// The JSON Schema of the file, for editors. Ignored when running
+    #[serde(rename = "$schema", default, skip_serializing_if = "Option::is_none")]
+    pub schema: Option<String>,
+    #[serde(default)]
+    pub metadata: ChainMetadata,
+    pub programs: Vec<Program>,
+    /// Read-only checks that run after all programs succeeded, e.g. that
+    /// a deployment is healthy. All of them run, and their failures mark
+    /// the run as unverified rather than failed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub verify: Vec<Program>,
+    /// Fields in the file that are not known to this version of cchain
+    #[serde(skip)]
+    pub unknown_fields: Vec<String>,
+    /// Notices of the fields of the legacy format that were rewritten to
+    /// the current ones
+    #[serde(skip)]
+    pub deprecated_fields: Vec<String>,
+    /// The files that the programs came from, when the chain extends
+    /// other chains
+    #[serde(skip)]
+    pub program_origins: Vec<String>,
+}
+
+impl ChainFile {
+    /// Collect the keys of an object that are not in the known fields
+    fn collect_unknown_fields(value: &Value, known_fields: &[&str], location: &str) -> Vec<String> {
+        let mut unknown_fields: Vec<String> = Vec::new();
+        if let Some(object) = value.as_object() {
+            for key in object.keys() {
+                if !known_fields.contains(&key.as_str()) {
+                    unknown_fields.push(format!("`{}` in {}", key, location));
+                }
+            }
+        }
+
+        unknown_fields
+    }
+
+    /// Find the fields in a chain file that are not known to this
+    /// version of cchain, at the top level, in the metadata and in the programs
+    fn find_unknown_fields(value: &Value) -> Vec<String> {
+        let mut unknown_fields: Vec<String> =
+            Self::collect_unknown_fields(value, CHAIN_FILE_FIELD_NAMES, "the chain file");
+        unknown_fields.extend(Self::collect_unknown_fields(
+            &value["metadata"],
+            METADATA_FIELD_NAMES,
+            "the metadata",
+        ));
+
+        for (label, program) in Self::get_program_values(value) {
+            let known_fields: &[&str] =
+                if program.get("builtin").is_some() { BUILTIN_STEP_FIELD_NAMES } else { Program::FIELD_NAMES };
+            unknown_fields.extend(Self::collect_unknown_fields(program, known_fields, &label));
+        }
+
+        unknown_fields
+    }
+
+    /// Check that the programs other than built-in steps have the fields
+    /// needed to run them
+    fn check_required_program_fields(value: &Value) -> Result<(), Error> {
+        for (label, program) in Self::get_program_values(value) {
+            let Some(program) = program.as_object() else {
+                continue;
+            };
+            if program.contains_key("builtin") {
+                continue;
+            }
+            // Git steps run no command, and retry unless `retry` is set
+            if program.contains_key("git") {
+                continue;
+            }
+            for field_name in REQUIRED_PROGRAM_FIELD_NAMES {
+                if !program.contains_key(*field_name) {
+                    return Err(anyhow!("missing field `{}` in {}", field_name, label));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the programs and the verification steps in a chain file, with
+    /// labels for messages, e.g. `program #2`
+    pub(crate) fn get_program_values(value: &Value) -> Vec<(String, &Value)> {
+        let programs: &Value = if value.is_array() { value } else { &value["programs"] };
+        let mut program_values: Vec<(String, &Value)> = programs
+            .as_array()
+            .into_iter()
+            .flatten()
+            .enumerate()
+            .map(|(index, program)| (format!("program #{}", index), program))
+            .collect();
+        // Verification steps are numbered after the programs
+        let verification_start: usize = program_values.len();
+        program_values.extend(
+            value["verify"]
+                .as_array()
+                .into_iter()
+                .flatten()
+                .enumerate()
+                .map(|(index, program)| (format!("verification step #{}", verification_start + index), program)),
+        );
+
+        program_values
+    }
+}
+
+impl ChainFile {
+    /// Read a chain file, with the chains that it `extends` merged in
+    pub fn from_path(path: &Path) -> Result<Self, Error> {
+        let resolved: ResolvedChainFile = resolve_chain_file(path)?;
+        let mut chain_file: ChainFile = Self::from_value(resolved.value)?;
+        chain_file.order_matrix_keys(&resolved.declared_matrix_keys);
+        let origin: String = path.display().to_string();
+        if resolved.program_origins.iter().any(|program_origin| program_origin != &origin) {
+            chain_file.program_origins = resolved.program_origins;
+        }
+
+        Ok(chain_file)
+    }
+
+    /// Resolve the relative paths in the programs once, so that they do
+    /// not depend on the directory cchain runs in. Paths resolve against
+    /// `base_directory`, or else the directory of the file that the program
+    /// comes from.
+    pub fn resolve_relative_paths(&mut self, chain_path: &Path) -> Result<(), Error> {
+        let chain_directory: PathBuf = get_parent_directory(chain_path)?;
+        let base_directory: Option<PathBuf> = self
+            .metadata
+            .base_directory
+            .as_ref()
+            .map(|base_directory| PathBuf::from(resolve_chain_relative_path(base_directory, &chain_directory)));
+
+        for (index, program) in self.programs.iter_mut().enumerate() {
+            let program_directory: PathBuf = match (&base_directory, self.program_origins.get(index)) {
+                (Some(base_directory), _) => base_directory.clone(),
+                (None, Some(origin)) => get_parent_directory(Path::new(origin))?,
+                (None, None) => chain_directory.clone(),
+            };
+            program.resolve_relative_paths(&program_directory);
+        }
+        for program in &mut self.verify {
+            program.resolve_relative_paths(base_directory.as_ref().unwrap_or(&chain_directory));
+        }
+
+        Ok(())
+    }
+
+    /// Put the keys of the matrices in the order they are declared in the
+    /// files, as the JSON values the chain is parsed through sort them
+    pub fn order_matrix_keys(&mut self, declared_matrix_keys: &DeclaredMatrixKeys) {
+        for program in self.programs.iter_mut().chain(&mut self.verify) {
+            program.order_matrix_keys(declared_matrix_keys);
+        }
+    }
+
+    fn from_value(mut value: Value) -> Result<Self, Error> {
+        get_chain_limits().check_programs(&value)?;
+        let deprecated_fields: Vec<String> = migrate_legacy_fields(&mut value)?;
+        let unknown_fields: Vec<String> = Self::find_unknown_fields(&value);
+
+        // Plain arrays of programs are the original format
+        if value.is_array() {
+            Self::check_required_program_fields(&value)?;
+            return Ok(Self {
+                schema: None,
+                metadata: ChainMetadata::default(),
+                programs: serde_json::from_value(value)?,
+                verify: Vec::new(),
+                unknown_fields,
+                deprecated_fields,
+                program_origins: Vec::new(),
+            });
+        }
+
+        // Check the version requirement before the programs, as programs
+        // written for a newer cchain may not parse
+        let metadata: ChainMetadata = match value.get("metadata") {
+            Some(metadata) => serde_json::from_value(metadata.clone())?,
+            None => ChainMetadata::default(),
+        };
+        metadata.check_version_requirement(clap::crate_version!())?;
+        Self::check_required_program_fields(&value)?;
+
+        let mut chain_file: ChainFile = serde_json::from_value(value)?;
+        chain_file.unknown_fields = unknown_fields;
+        chain_file.deprecated_fields = deprecated_fields;
+
+        Ok(chain_file)
+    }
+}
+
+/// Get the absolute directory of a file
+fn get_parent_directory(path: &Path) -> Result<PathBuf, Error> {
+    let path: PathBuf = std::path::absolute(path)?;
+    Ok(path.parent().map(Path::to_path_buf).unwrap_or(path))
+}
+
+impl FromStr for ChainFile {
+    type Err = Error;
+
+    /// Parse a chain file as it is. Use `from_path` to resolve `extends`.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut chain_file: ChainFile = Self::from_value(serde_json::from_str(s)?)?;
+        chain_file.order_matrix_keys(&DeclaredMatrixKeys::from_content(s));
+
+        Ok(chain_file)
+    }
+}
@@ -0,0 +1,135 @@
+use anyhow::{anyhow, Error, Result};
+use serde_json::{Map, Value};
+
+/// Fields of programs that were renamed without a change in meaning, as
+/// the legacy name and the current one
+pub const RENAMED_PROGRAM_FIELDS: &[(&str, &str)] = &[("awaitable_variable", "stdout_stored_to")];
+
+/// Rewrite the fields of the legacy format in the JSON of a chain file to
+/// the current ones, so that old chains keep the meaning they were written
+/// with instead of losing the fields as unknown
+///
+/// # Returns
+///
+/// A deprecation notice for each field rewritten, naming the program and
+/// the field to use instead. Fails when a legacy field contradicts the
+/// current one next to it.
+pub fn migrate_legacy_fields(value: &mut Value) -> Result<Vec<String>, Error> {
+    let mut sections: Vec<(&str, &mut Vec<Value>)> = Vec::new();
+    match value {
+        Value::Array(programs) => sections.push(("Program", programs)),
+        Value::Object(object) => {
+            for (key, section) in object.iter_mut() {
+                let label: &str = match key.as_str() {
+                    "programs" => "Program",
+                    "verify" => "Verification step",
+                    _ => continue,
+                };
+                if let Some(programs) = section.as_array_mut() {
+                    sections.push((label, programs));
+                }
+            }
+        }
+        _ => (),
+    }
+
+    let mut notices: Vec<String> = Vec::new();
+    // Verification steps are numbered after the programs
+    let mut start: usize = 0;
+    for (label, programs) in sections {
+        let section_start: usize = start;
+        start += programs.len();
+        for (index, program) in programs.iter_mut().enumerate() {
+            let Some(program) = program.as_object_mut() else {
+                continue;
+            };
+            let location: String = format!("{} #{}", label, section_start + index);
+            for (legacy_name, name) in RENAMED_PROGRAM_FIELDS {
+                if let Some(notice) = migrate_renamed_field(program, &location, legacy_name, name)? {
+                    notices.push(notice);
+                }
+            }
+            if let Some(options) = program.get_mut("failure_handling_options").and_then(Value::as_object_mut) {
+                if let Some(notice) = migrate_continue_on_failure(options, &location)? {
+                    notices.push(notice);
+                }
+            }
+        }
+    }
+
+    Ok(notices)
+}
+
+fn migrate_renamed_field(
+    program: &mut Map<String, Value>,
+    location: &str,
+    legacy_name: &str,
+    name: &str,
+) -> Result<Option<String>, Error> {
+    let Some(legacy_value) = program.remove(legacy_name) else {
+        return Ok(None);
+    };
+
+    match program.get(name) {
+        Some(value) if !value.is_null() && !legacy_value.is_null() && value != &legacy_value => {
+            return Err(anyhow!(
+                "{} has both `{}`: {} and `{}`: {}. `{}` is the deprecated name of `{}`, remove one of them.",
+                location,
+                legacy_name,
+                legacy_value,
+                name,
+                value,
+                legacy_name,
+                name
+            ));
+        }
+        Some(value) if !value.is_null() => (),
+        _ => {
+            program.insert(name.to_string(), legacy_value);
+        }
+    }
+
+    Ok(Some(format!(
+        "{}: `{}` is deprecated, use `{}` instead. `cchain fmt` rewrites the file with it.",
+        location, legacy_name, name
+    )))
+}
+
+/// `continue_on_failure` was replaced by its negation, `exit_on_failure`
+fn migrate_continue_on_failure(options: &mut Map<String, Value>, location: &str) -> Result<Option<String>, Error> {
+    let Some(continue_on_failure) = options.remove("continue_on_failure") else {
+        return Ok(None);
+    };
+    let continue_on_failure: bool = match continue_on_failure {
+        Value::Bool(continue_on_failure) => continue_on_failure,
+        // Not set, as in fixtures that list every field
+        Value::Null => return Ok(None),
+        other => {
+            return Err(anyhow!(
+                "{}: `failure_handling_options.continue_on_failure` must be true or false, not {}",
+                location,
+                other
+            ))
+        }
+    };
+
+    match options.get("exit_on_failure").and_then(Value::as_bool) {
+        Some(exit_on_failure) if exit_on_failure == continue_on_failure => {
+            return Err(anyhow!(
+                "{} has both `continue_on_failure`: {} and `exit_on_failure`: {} in `failure_handling_options`, which contradict each other. `continue_on_failure` is deprecated, remove it and keep `exit_on_failure` as intended.",
+                location,
+                continue_on_failure,
+                exit_on_failure
+            ));
+        }
+        Some(_) => (),
+        None => {
+            options.insert("exit_on_failure".to_string(), Value::Bool(!continue_on_failure));
+        }
+    }
+
+    Ok(Some(format!(
+        "{}: `failure_handling_options.continue_on_failure` is deprecated, use `\"exit_on_failure\": {}` instead. `cchain fmt` rewrites the file with it.",
+        location, !continue_on_failure
+    )))
+}
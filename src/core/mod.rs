@@ -3,4 +3,34 @@ pub mod interpreter;
 pub mod options;
 pub mod program;
 pub mod traits;
-pub mod chain;
\ No newline at end of file
+pub mod chain;
+pub mod metadata;
+pub mod service;
+pub mod privilege;
+pub mod assertion;
+pub mod status;
+pub mod schema;
+pub mod group;
+pub mod inheritance;
+pub mod format;
+pub mod debug;
+pub mod builtin;
+pub mod paths;
+pub mod effects;
+pub mod events;
+pub mod progress;
+pub mod limits;
+pub mod migration;
+pub mod parse_cache;
+pub mod git;
+pub mod scratch;
+pub mod environment;
+pub mod shutdown;
+pub mod bench;
+pub mod sanitize;
+pub mod fix;
+pub mod manifest;
+pub mod chain_retry;
+pub mod io_profile;
+pub mod pause;
+pub mod matrix;
@@ -1,21 +1,110 @@
+use std::collections::BTreeSet;
+
+use anyhow::{anyhow, Error, Result};
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use super::command::CommandLine;
+use super::sanitize::sanitize_output;
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct StdoutStorageOptions {
     pub without_newline_characters: bool,
+    /// Store only the lines of the output matching the pattern, joined
+    /// by newlines. The output is still displayed in full.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_filter: Option<String>,
+    /// Store only this capture group of each matching line
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub capture_group: Option<usize>,
+    /// Fail the program when no line matches `capture_filter`,
+    /// instead of storing an empty value
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub required_capture: bool,
+    /// Store the output with its colors, cursor movements and carriage
+    /// returns, instead of the plain text a terminal would show
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub preserve_ansi: bool,
 }
 
 impl Default for StdoutStorageOptions {
     fn default() -> Self {
         Self {
             without_newline_characters: true,
+            capture_filter: None,
+            capture_group: None,
+            required_capture: false,
+            preserve_ansi: false,
         }
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+impl StdoutStorageOptions {
+    /// Check that the capture filter compiles, and has the capture group
+    pub fn validate_capture_filter(&self) -> Result<(), Error> {
+        let pattern: &String = match &self.capture_filter {
+            Some(pattern) => pattern,
+            None if self.capture_group.is_some() || self.required_capture => {
+                return Err(anyhow!("`capture_group` and `required_capture` need a `capture_filter`"));
+            }
+            None => return Ok(()),
+        };
+
+        let regex: Regex = Regex::new(pattern)
+            .map_err(|error| anyhow!("Invalid `capture_filter` pattern `{}`: {}", pattern, error))?;
+        if let Some(capture_group) = self.capture_group {
+            if capture_group >= regex.captures_len() {
+                return Err(anyhow!(
+                    "`capture_filter` pattern `{}` has no capture group {}",
+                    pattern,
+                    capture_group
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Remove the escape sequences and the overwritten progress lines
+    /// from the output, unless they are preserved
+    pub fn sanitize(&self, output: String) -> String {
+        if self.preserve_ansi {
+            return output;
+        }
+
+        sanitize_output(&output)
+    }
+
+    /// Keep the lines of the output matching the capture filter, or the
+    /// capture group of each of them. Without a filter, the output is
+    /// kept as it is.
+    pub fn filter_capture(&self, output: String) -> Result<String, Error> {
+        let pattern: &String = match &self.capture_filter {
+            Some(pattern) => pattern,
+            None => return Ok(output),
+        };
+
+        let regex: Regex = Regex::new(pattern)?;
+        let captured: Vec<&str> = output
+            .lines()
+            .filter_map(|line| {
+                let captures = regex.captures(line)?;
+                match self.capture_group {
+                    // A group that does not take part in the match is empty
+                    Some(capture_group) => Some(captures.get(capture_group).map_or("", |capture| capture.as_str())),
+                    None => Some(line),
+                }
+            })
+            .collect();
+        if captured.is_empty() && self.required_capture {
+            return Err(anyhow!("No line of the output matches the `capture_filter` pattern `{}`", pattern));
+        }
+
+        Ok(captured.join("\n"))
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
 pub struct FailureHandlingOptions {
     /// Indicates whether the chain will exit when a failure is captured
     pub exit_on_failure: bool,
@@ -34,3 +123,132 @@ impl Default for FailureHandlingOptions {
         }
     }
 }
+
+/// How much of the output of a program is shown as it runs. The output
+/// is captured in full either way.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DisplayOutput {
+    /// Every line
+    #[default]
+    Full,
+    /// The first and the last lines, with the number of lines left out
+    Summary,
+    /// Nothing but the messages about starting and finishing the command
+    Silent,
+}
+
+/// How the failures of programs are handled for a whole run, e.g. with
+/// `cchain run --continue-on-failure`, regardless of `exit_on_failure`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "mode", rename_all = "snake_case")]
+pub enum FailurePolicy {
+    /// Each program decides with its `exit_on_failure`
+    #[default]
+    PerProgram,
+    /// Continue after any failure, unless the program cannot be started
+    ContinueOnFailure,
+    /// Abort at the first failure. A failure whose remedy command line
+    /// succeeded is handled by `exit_on_failure`, unless `even_if_remedied`
+    StopOnFirstFailure { even_if_remedied: bool },
+}
+
+impl FailurePolicy {
+    /// Check whether the policy overrides `exit_on_failure`
+    pub fn is_override(&self) -> bool {
+        *self != FailurePolicy::PerProgram
+    }
+}
+
+/// The programs run this time, e.g. with `cchain run --only 1,4` or
+/// `--from 3`. Without either, all programs run.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProgramSelection {
+    only: Option<BTreeSet<usize>>,
+    from: Option<usize>,
+}
+
+impl ProgramSelection {
+    /// Run only the programs at the indices
+    pub fn with_only(mut self, indices: BTreeSet<usize>) -> Self {
+        self.only = Some(indices);
+        self
+    }
+
+    /// Run the programs from the index on
+    pub fn with_from(mut self, index: usize) -> Self {
+        self.from = Some(index);
+        self
+    }
+
+    pub fn is_selecting_all(&self) -> bool {
+        self.only.is_none() && self.from.is_none()
+    }
+
+    pub fn is_selected(&self, program_index: usize) -> bool {
+        self.only.as_ref().is_none_or(|indices| indices.contains(&program_index))
+            && self.from.is_none_or(|from| program_index >= from)
+    }
+
+    /// Check that the indices are indices of the programs of a chain
+    pub fn validate(&self, number_of_programs: usize) -> Result<(), Error> {
+        let out_of_range: Vec<String> = self
+            .only
+            .iter()
+            .flatten()
+            .chain(self.from.iter())
+            .filter(|index| **index >= number_of_programs)
+            .map(|index| format!("#{}", index))
+            .collect();
+        if !out_of_range.is_empty() {
+            return Err(anyhow!(
+                "Program {} is selected, but the chain has programs #0 to #{}",
+                out_of_range.join(", "),
+                number_of_programs.saturating_sub(1)
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+impl std::fmt::Display for FailurePolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FailurePolicy::PerProgram => write!(f, "`exit_on_failure` of each program"),
+            FailurePolicy::ContinueOnFailure => write!(f, "`--continue-on-failure`"),
+            FailurePolicy::StopOnFirstFailure { even_if_remedied: false } => write!(f, "`--stop-on-first-failure`"),
+            FailurePolicy::StopOnFirstFailure { even_if_remedied: true } => {
+                write!(f, "`--stop-on-first-failure --stop-even-if-remedied`")
+            }
+        }
+    }
+}
+
+/// Options for probing whether a service is ready for the next programs
+#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ReadyCheckOptions {
+    /// A command line that exits successfully once the service is ready
+    #[serde(flatten)]
+    pub command_line: CommandLine,
+    /// Milliseconds to wait between two probes
+    #[serde(default = "ReadyCheckOptions::default_interval_ms")]
+    pub interval_ms: u64,
+    /// Seconds to wait for the service before giving up
+    #[serde(default = "ReadyCheckOptions::default_timeout_seconds")]
+    pub timeout_seconds: u64,
+    /// Store the stdout of the successful probe in `stdout_stored_to`,
+    /// instead of the output of the service up to the ready point
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub store_output: bool,
+}
+
+impl ReadyCheckOptions {
+    fn default_interval_ms() -> u64 {
+        500
+    }
+
+    fn default_timeout_seconds() -> u64 {
+        30
+    }
+}
@@ -0,0 +1,265 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commons::{digest::sha256_hex, lock::write_file_atomically},
+    variable::Variable,
+};
+
+use super::{
+    chain::Chain,
+    inheritance::get_inheritance_paths,
+    limits::{get_chain_limits, ChainLimits},
+    metadata::ChainMetadata,
+    program::Program,
+};
+
+/// Chains kept at most. The cache is emptied when it is full, as the
+/// chains of one run are few, and batches rarely come back to a chain.
+pub const PARSE_CACHE_CAPACITY: usize = 256;
+
+/// Name of the directory of the parsed chains in the cache directory
+pub const PARSE_CACHE_DIRECTORY_NAME: &str = "parsed_chains";
+
+/// The chains parsed in this process, e.g. for the batch runs and the
+/// watch modes that load the same files again
+static PARSE_CACHE: Mutex<ParseCache> = Mutex::new(ParseCache::new());
+
+/// Whether the chains parsed before are reused, unset by `--no-cache`
+static IS_PARSE_CACHE_ENABLED: AtomicBool = AtomicBool::new(true);
+
+/// The directory that the parsed chains are kept in for other processes,
+/// if any
+static PARSE_CACHE_DIRECTORY: Mutex<Option<PathBuf>> = Mutex::new(None);
+
+/// A chain as it was loaded from its files
+struct CachedChain {
+    /// The chain file and the chains it extends
+    paths: Vec<PathBuf>,
+    content_hash: String,
+    /// The limits the chain was checked against, as a chain loaded
+    /// with higher limits may exceed the current ones
+    limits: ChainLimits,
+    chain: Chain,
+    /// Warnings shown when the chain was loaded, shown again when it is
+    /// reused
+    warnings: Vec<String>,
+}
+
+struct ParseCache {
+    /// The chains by their canonical paths and the paths they were
+    /// loaded with, which they refer to themselves by
+    chains: BTreeMap<(PathBuf, String), CachedChain>,
+    /// How many times each chain file was parsed, by its canonical path
+    parse_counts: BTreeMap<PathBuf, usize>,
+    /// How many times each chain file was reused from the cache in this
+    /// process or on the disk, by its canonical path
+    hit_counts: BTreeMap<PathBuf, usize>,
+}
+
+impl ParseCache {
+    const fn new() -> Self {
+        Self { chains: BTreeMap::new(), parse_counts: BTreeMap::new(), hit_counts: BTreeMap::new() }
+    }
+
+    fn insert(&mut self, key: (PathBuf, String), cached_chain: CachedChain) {
+        if self.chains.len() >= PARSE_CACHE_CAPACITY {
+            self.chains.clear();
+        }
+        self.chains.insert(key, cached_chain);
+    }
+}
+
+/// A chain as it is kept on the disk: the programs and the variables it
+/// was built with, which are loaded again without parsing
+#[derive(Debug, Deserialize, Serialize)]
+pub(crate) struct StoredChain {
+    /// The version of cchain that parsed the chain. Chains parsed by
+    /// other versions are parsed again.
+    pub(crate) cchain_version: String,
+    pub(crate) paths: Vec<PathBuf>,
+    pub(crate) content_hash: String,
+    pub(crate) limits: ChainLimits,
+    pub(crate) programs: Vec<Program>,
+    pub(crate) verification_start: usize,
+    pub(crate) variables: Vec<Variable>,
+    pub(crate) metadata: ChainMetadata,
+    pub(crate) is_modified_since_generation: bool,
+    pub(crate) program_origins: Vec<String>,
+    pub(crate) warnings: Vec<String>,
+}
+
+/// Reuse the chains parsed before, unless their files changed
+pub fn set_parse_cache_enabled(is_enabled: bool) {
+    IS_PARSE_CACHE_ENABLED.store(is_enabled, Ordering::SeqCst);
+    if !is_enabled {
+        PARSE_CACHE.lock().unwrap().chains.clear();
+    }
+}
+
+pub fn is_parse_cache_enabled() -> bool {
+    IS_PARSE_CACHE_ENABLED.load(Ordering::SeqCst)
+}
+
+/// Keep the parsed chains in the directory too, e.g. under the cache
+/// directory, so that the next processes do not parse them again. `None`
+/// keeps them in this process only.
+pub fn set_parse_cache_directory(directory: Option<PathBuf>) {
+    *PARSE_CACHE_DIRECTORY.lock().unwrap() = directory;
+}
+
+/// Get how many times the chain file was parsed in this process, as
+/// opposed to reused from the cache
+pub fn get_parse_count(path: &Path) -> usize {
+    PARSE_CACHE.lock().unwrap().parse_counts.get(&get_canonical_path(path)).copied().unwrap_or(0)
+}
+
+/// Get how many times the chain file was reused from the cache in this
+/// process, or from the directory of the parsed chains
+pub fn get_cache_hit_count(path: &Path) -> usize {
+    PARSE_CACHE.lock().unwrap().hit_counts.get(&get_canonical_path(path)).copied().unwrap_or(0)
+}
+
+/// Forget the chain file in this process, e.g. to load it from the
+/// directory of the parsed chains as another process would
+pub fn evict_cached_chain(path: &Path) {
+    let canonical_path: PathBuf = get_canonical_path(path);
+    PARSE_CACHE.lock().unwrap().chains.retain(|(cached_path, _), _| *cached_path != canonical_path);
+}
+
+fn get_canonical_path(path: &Path) -> PathBuf {
+    path.canonicalize().unwrap_or_else(|_| path.to_path_buf())
+}
+
+/// Hash the contents of the files. Missing files have no hash, so that
+/// a chain whose file is gone is loaded again and fails.
+fn hash_contents(paths: &[PathBuf]) -> Option<String> {
+    let mut digests: Vec<String> = Vec::new();
+    for path in paths {
+        digests.push(sha256_hex(&std::fs::read(path).ok()?));
+    }
+
+    Some(sha256_hex(digests.join("\n").as_bytes()))
+}
+
+/// Get the path of the chain loaded with the path in the directory of
+/// the parsed chains
+fn get_stored_chain_path(directory: &Path, key: &(PathBuf, String)) -> PathBuf {
+    let name: String = sha256_hex(format!("{}\n{}", key.0.display(), key.1).as_bytes());
+    directory.join(format!("{}.json", name))
+}
+
+/// The files and the hash of their contents, taken before a chain is
+/// parsed, so that a change while parsing is noticed by the next load
+pub(crate) struct ChainFileVersion {
+    paths: Vec<PathBuf>,
+    content_hash: Option<String>,
+}
+
+impl ChainFileVersion {
+    pub(crate) fn from_path(path: &Path) -> Self {
+        let paths: Vec<PathBuf> = get_inheritance_paths(path);
+        let content_hash: Option<String> = hash_contents(&paths);
+        Self { paths, content_hash }
+    }
+}
+
+/// Get a copy of the chain loaded from the path before, with the
+/// warnings shown then, when none of its files changed since and it was
+/// checked against the current limits. Chains not
+/// loaded in this process are read from the directory of the parsed
+/// chains, if any.
+pub(crate) fn get_cached_chain(path: &str) -> Option<(Chain, Vec<String>)> {
+    if !is_parse_cache_enabled() {
+        return None;
+    }
+
+    let key: (PathBuf, String) = (get_canonical_path(Path::new(path)), path.to_string());
+    let mut parse_cache = PARSE_CACHE.lock().unwrap();
+    if let Some(cached_chain) = parse_cache.chains.get(&key) {
+        if cached_chain.limits == get_chain_limits()
+            && hash_contents(&cached_chain.paths).as_ref() == Some(&cached_chain.content_hash)
+        {
+            let cached: (Chain, Vec<String>) = (cached_chain.chain.clone_loaded(), cached_chain.warnings.clone());
+            *parse_cache.hit_counts.entry(key.0).or_insert(0) += 1;
+            return Some(cached);
+        }
+    }
+
+    let stored_chain: StoredChain = read_stored_chain(&key)?;
+    let warnings: Vec<String> = stored_chain.warnings.clone();
+    let (paths, content_hash): (Vec<PathBuf>, String) = (stored_chain.paths.clone(), stored_chain.content_hash.clone());
+    let stored_chain_limits: ChainLimits = stored_chain.limits;
+    let chain: Chain = Chain::from_stored(stored_chain, path);
+    *parse_cache.hit_counts.entry(key.0.clone()).or_insert(0) += 1;
+    parse_cache.insert(
+        key,
+        CachedChain {
+            paths,
+            content_hash,
+            limits: stored_chain_limits,
+            chain: chain.clone_loaded(),
+            warnings: warnings.clone(),
+        },
+    );
+
+    Some((chain, warnings))
+}
+
+/// Read the chain loaded with the path from the directory of the parsed
+/// chains, when it was parsed by this version of cchain from the files
+/// as they are now, against the current limits
+fn read_stored_chain(key: &(PathBuf, String)) -> Option<StoredChain> {
+    let directory: PathBuf = PARSE_CACHE_DIRECTORY.lock().unwrap().clone()?;
+    let content: String = std::fs::read_to_string(get_stored_chain_path(&directory, key)).ok()?;
+    let stored_chain: StoredChain = serde_json::from_str(&content).ok()?;
+    if stored_chain.cchain_version != clap::crate_version!()
+        || stored_chain.limits != get_chain_limits()
+        || hash_contents(&stored_chain.paths).as_ref() != Some(&stored_chain.content_hash)
+    {
+        return None;
+    }
+
+    Some(stored_chain)
+}
+
+/// Count a parse of the chain file at the path
+pub(crate) fn record_parse(path: &str) {
+    *PARSE_CACHE.lock().unwrap().parse_counts.entry(get_canonical_path(Path::new(path))).or_insert(0) += 1;
+}
+
+/// Keep a copy of a chain just loaded from the files of the version with
+/// the current limits, and
+/// write it to the directory of the parsed chains, if any. The cache is
+/// only a shortcut, so a chain that cannot be written is parsed again.
+pub(crate) fn cache_chain(path: &str, version: ChainFileVersion, chain: &Chain, warnings: Vec<String>) {
+    let Some(content_hash) = version.content_hash else {
+        return;
+    };
+    if !is_parse_cache_enabled() {
+        return;
+    }
+
+    let key: (PathBuf, String) = (get_canonical_path(Path::new(path)), path.to_string());
+    let limits: ChainLimits = get_chain_limits();
+    if let Some(directory) = PARSE_CACHE_DIRECTORY.lock().unwrap().clone() {
+        let stored_chain: StoredChain =
+            chain.to_stored(version.paths.clone(), content_hash.clone(), limits, warnings.clone());
+        if let Ok(content) = serde_json::to_string(&stored_chain) {
+            let _ = write_file_atomically(&get_stored_chain_path(&directory, &key), content.as_bytes());
+        }
+    }
+
+    PARSE_CACHE.lock().unwrap().insert(
+        key,
+        CachedChain { paths: version.paths, content_hash, limits, chain: chain.clone_loaded(), warnings },
+    );
+}
@@ -0,0 +1,42 @@
+use std::{
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+
+use crate::function::Function;
+
+/// Check whether a command is written as a path relative to the chain,
+/// e.g. `./scripts/fix.sh`, rather than a name looked up in `PATH`
+pub fn is_chain_relative_command(command: &str) -> bool {
+    command.starts_with("./") || command.starts_with("../")
+}
+
+/// Resolve a path of a chain against its base directory. Absolute paths,
+/// and values that are only known when the chain runs, e.g. `<<dir>>`
+/// or function calls, are returned as they are.
+pub fn resolve_chain_relative_path(path: &str, base_directory: &Path) -> String {
+    if Path::new(path).is_absolute() || path.contains("<<") || Function::from_str(path).is_ok() {
+        return path.to_string();
+    }
+
+    normalize_path(&base_directory.join(path)).display().to_string()
+}
+
+/// Remove the `.` and `..` components of a path without touching the
+/// file system, so that resolved paths read well in messages
+fn normalize_path(path: &Path) -> PathBuf {
+    let mut normalized: PathBuf = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                if !normalized.pop() {
+                    normalized.push(component);
+                }
+            }
+            _ => normalized.push(component),
+        }
+    }
+
+    normalized
+}
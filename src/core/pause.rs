@@ -0,0 +1,162 @@
+use std::{
+    sync::{Arc, Condvar, Mutex},
+    time::Duration,
+};
+
+use crate::display_control::{display_message, Level};
+
+use super::shutdown::is_shutting_down;
+
+/// The status line shown while the chain waits to be resumed
+pub const PAUSED_STATUS_LINE: &str = "paused — press p to resume, s to skip next, a to abort";
+
+/// How often a paused chain checks whether cchain is asked to terminate
+const PAUSE_CHECK_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A key that controls a running chain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseKey {
+    /// `p`, to pause after the current program, or to resume
+    TogglePause,
+    /// `s`, to skip the next program while paused
+    SkipNext,
+    /// `a`, to abort the chain while paused
+    Abort,
+}
+
+impl PauseKey {
+    /// Get the control of a pressed key, if it is one
+    pub fn from_key(key: u8) -> Option<Self> {
+        match key.to_ascii_lowercase() {
+            b'p' => Some(Self::TogglePause),
+            b's' => Some(Self::SkipNext),
+            b'a' => Some(Self::Abort),
+            _ => None,
+        }
+    }
+}
+
+/// What the chain does before starting its next program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseDecision {
+    Continue,
+    /// Skip the next program, as if it were not selected
+    Skip,
+    /// Cancel the chain, as with Ctrl-C
+    Abort,
+}
+
+/// Where the pause of a chain is. Skipping and aborting only apply while
+/// paused, so that a stray key does not change a running chain.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PauseState {
+    is_paused: bool,
+    is_skipping_next: bool,
+    is_aborted: bool,
+}
+
+impl PauseState {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.is_paused
+    }
+
+    /// Apply a pressed key
+    ///
+    /// # Returns
+    ///
+    /// Whether the key changed anything
+    pub fn press(&mut self, key: PauseKey) -> bool {
+        match key {
+            PauseKey::TogglePause => {
+                self.is_paused = !self.is_paused;
+                true
+            }
+            PauseKey::SkipNext if self.is_paused && !self.is_skipping_next => {
+                self.is_skipping_next = true;
+                true
+            }
+            PauseKey::Abort if self.is_paused && !self.is_aborted => {
+                self.is_aborted = true;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Take what to do before the next program. A skip applies to one
+    /// program, and the chain stays paused before the one after it.
+    ///
+    /// # Returns
+    ///
+    /// The decision, or `None` while the chain is to wait
+    pub fn take_decision(&mut self) -> Option<PauseDecision> {
+        if self.is_aborted {
+            return Some(PauseDecision::Abort);
+        }
+        if self.is_skipping_next {
+            self.is_skipping_next = false;
+            return Some(PauseDecision::Skip);
+        }
+        if self.is_paused {
+            return None;
+        }
+
+        Some(PauseDecision::Continue)
+    }
+}
+
+/// Pauses a chain between its programs. The keys are pressed from another
+/// thread, while the chain waits for a decision before each program.
+#[derive(Debug, Clone, Default)]
+pub struct PauseControl(Arc<(Mutex<PauseState>, Condvar)>);
+
+impl PauseControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply a pressed key, and wake the chain if it waits
+    ///
+    /// # Returns
+    ///
+    /// Whether the key changed anything
+    pub fn press(&self, key: PauseKey) -> bool {
+        let (state, condvar) = &*self.0;
+        let is_changed: bool = state.lock().unwrap().press(key);
+        if is_changed {
+            condvar.notify_all();
+        }
+
+        is_changed
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.0 .0.lock().unwrap().is_paused()
+    }
+
+    /// Wait while the chain is paused, showing the status line when it
+    /// starts waiting. The chain continues when cchain is asked to
+    /// terminate, so that it stops as usual.
+    pub fn wait_for_decision(&self) -> PauseDecision {
+        let (state, condvar) = &*self.0;
+        let mut state = state.lock().unwrap();
+        let mut is_waiting: bool = false;
+        loop {
+            if let Some(decision) = state.take_decision() {
+                return decision;
+            }
+            if is_shutting_down() {
+                return PauseDecision::Continue;
+            }
+            if !is_waiting {
+                display_message(Level::Logging, PAUSED_STATUS_LINE);
+                is_waiting = true;
+            }
+            state = condvar.wait_timeout(state, PAUSE_CHECK_INTERVAL).unwrap().0;
+        }
+    }
+}
@@ -0,0 +1,158 @@
+use std::process::{Command, Stdio};
+
+use anyhow::{anyhow, Error, Result};
+
+/// Escalation commands that are detected when the chain does not specify one
+pub const ESCALATION_COMMANDS: &[&str] = &["sudo", "doas"];
+
+/// How a command line is run as the user in `run_as`
+#[derive(Debug, PartialEq, Eq)]
+pub enum PrivilegeChange {
+    /// cchain already runs as the user
+    Unchanged,
+    /// cchain runs as root, and drops to the user for the child process
+    Drop {
+        user: String,
+        uid: u32,
+        gid: u32,
+        home: String,
+    },
+    /// cchain is unprivileged, and wraps the command with an escalation
+    /// command such as `sudo`
+    Escalate {
+        escalation_command: String,
+        user: String,
+    },
+}
+
+impl PrivilegeChange {
+    /// Decide how to run a command as the user
+    ///
+    /// # Arguments
+    ///
+    /// * `run_as` - The user to run the command as
+    /// * `escalation_command` - The escalation command declared in the chain, if any.
+    ///   Otherwise, the first of `ESCALATION_COMMANDS` found in `PATH` is used.
+    #[cfg(unix)]
+    pub fn resolve(run_as: &str, escalation_command: Option<&str>) -> Result<Self, Error> {
+        let current_uid: u32 = unsafe { libc::geteuid() };
+        let (uid, gid, home) = lookup_user(run_as)?;
+
+        if uid == current_uid {
+            return Ok(Self::Unchanged);
+        }
+
+        if current_uid == 0 {
+            return Ok(Self::Drop {
+                user: run_as.to_string(),
+                uid,
+                gid,
+                home,
+            });
+        }
+
+        let escalation_command: String = match escalation_command {
+            Some(escalation_command) => escalation_command.to_string(),
+            None => ESCALATION_COMMANDS
+                .iter()
+                .find(|command| which::which(command).is_ok())
+                .map(|command| command.to_string())
+                .ok_or_else(|| anyhow!(
+                    "Cannot run as {}: none of {} is found",
+                    run_as,
+                    ESCALATION_COMMANDS.join(", ")
+                ))?,
+        };
+
+        Ok(Self::Escalate {
+            escalation_command,
+            user: run_as.to_string(),
+        })
+    }
+
+    #[cfg(not(unix))]
+    pub fn resolve(run_as: &str, _escalation_command: Option<&str>) -> Result<Self, Error> {
+        Err(anyhow!("Cannot run as {}: `run_as` is only supported on Unix", run_as))
+    }
+
+    /// Wrap the program and arguments with the escalation command, if any.
+    /// The escalation command runs in non-interactive mode, so that it
+    /// fails instead of prompting for a password.
+    pub fn wrap_argv(&self, argv: Vec<String>) -> Vec<String> {
+        match self {
+            Self::Escalate { escalation_command, user } => {
+                let mut wrapped_argv: Vec<String> = vec![
+                    escalation_command.clone(),
+                    "-n".to_string(),
+                    "-u".to_string(),
+                    user.clone(),
+                ];
+                // `sudo` stops parsing its own options at `--`, `doas` has no such marker
+                if escalation_command.ends_with("sudo") {
+                    wrapped_argv.push("--".to_string());
+                }
+                wrapped_argv.extend(argv);
+                wrapped_argv
+            }
+            _ => argv,
+        }
+    }
+
+    /// Fail early when the escalation command would need a password
+    pub fn check_non_interactive(&self) -> Result<(), Error> {
+        if let Self::Escalate { escalation_command, user } = self {
+            let argv: Vec<String> = self.wrap_argv(vec!["true".to_string()]);
+            let status = Command::new(&argv[0])
+                .args(&argv[1..])
+                .stdin(Stdio::null())
+                .stdout(Stdio::null())
+                .stderr(Stdio::null())
+                .status()
+                .map_err(|error| anyhow!("Failed to run {}: {}", escalation_command, error))?;
+
+            if !status.success() {
+                return Err(anyhow!(
+                    "{} cannot run commands as {} without a password. Please authenticate first, e.g. with `{} true`, then run the chain again",
+                    escalation_command,
+                    user,
+                    escalation_command
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Apply the privilege drop to the process command
+    pub fn apply(&self, command: &mut Command) {
+        #[cfg(unix)]
+        if let Self::Drop { user, uid, gid, home } = self {
+            use std::os::unix::process::CommandExt;
+            command.uid(*uid);
+            command.gid(*gid);
+            command.env("USER", user);
+            command.env("LOGNAME", user);
+            command.env("HOME", home);
+        }
+
+        #[cfg(not(unix))]
+        let _ = command;
+    }
+}
+
+/// Look up the uid, gid and home directory of a user
+#[cfg(unix)]
+fn lookup_user(user: &str) -> Result<(u32, u32, String), Error> {
+    let name = std::ffi::CString::new(user)?;
+    let entry = unsafe { libc::getpwnam(name.as_ptr()) };
+    if entry.is_null() {
+        return Err(anyhow!("User {} does not exist", user));
+    }
+
+    let entry = unsafe { &*entry };
+    let home: String = unsafe { std::ffi::CStr::from_ptr(entry.pw_dir) }
+        .to_string_lossy()
+        .into_owned();
+
+    Ok((entry.pw_uid, entry.pw_gid, home))
+}
@@ -1,36 +1,183 @@
-use std::{collections::HashMap, str::FromStr};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::Path,
+    str::FromStr,
+    time::{Duration, Instant},
+};
 
-use anyhow::Error;
+use anyhow::{anyhow, Error};
+use console::Term;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    display_control::{display_message, Level},
-    function::Function,
+    commons::{digest::sha256_hex, errors::CommandLineError, interaction::SharedInteractor, termination::ProcessTermination},
+    display_control::{display_command_line, display_message, display_status_line, end_status_line, is_summary_only, Level},
+    function::{Function, VariableParameterValue},
+    variable::{StoredVariable, VariableDelimiters},
 };
 
+/// Number of characters at the end of a failed program's output that
+/// are matched against the retry patterns
+const FAILURE_OUTPUT_TAIL_LENGTH: usize = 4096;
+
+/// Number of characters at the end of the output of an attempt kept
+/// in its record
+const ATTEMPT_OUTPUT_TAIL_LENGTH: usize = 1000;
+
+/// Minimum delay between the attempts of a program with `retry: -1`,
+/// so that a command that can never succeed does not spin
+const INFINITE_RETRY_MIN_DELAY: Duration = Duration::from_millis(250);
+
+/// Number of attempts of a program with `retry: -1` before asking
+/// whether to keep retrying
+pub const DEFAULT_RETRY_ESCALATION_ATTEMPTS: u32 = 50;
+
 use super::{
-    command::CommandLine,
+    assertion::OutputAssertion,
+    builtin::BuiltinStep,
+    command::{CommandLine, CommandLineExecutionResult, RenderStyle, StreamEnds},
+    effects::{guess_destructive_reason, DESTRUCTIVE_EFFECTS},
+    events::ChainEvent,
+    git::GitOperation,
+    group::CancellationHandle,
     interpreter::Interpreter,
-    options::{FailureHandlingOptions, StdoutStorageOptions},
+    io_profile::IoUsage,
+    matrix::{add_variable_suffix, get_matrix_combinations, DeclaredMatrixKeys, Matrix, get_matrix_placeholder_keys, remove_matrix_placeholders, validate_matrix},
+    options::{DisplayOutput, FailureHandlingOptions, ReadyCheckOptions, StdoutStorageOptions},
+    sanitize::get_tail,
+    service::Service,
+    shutdown::is_shutting_down,
     traits::{Execution, ExecutionType},
 };
 
+/// What happened in one attempt of a program
+#[derive(Debug, PartialEq, Eq, Clone, Deserialize, Serialize)]
+pub struct AttemptRecord {
+    /// The number of the attempt, starting from 1
+    pub attempt: u32,
+    pub duration_ms: u64,
+    pub succeeded: bool,
+    /// `succeeded`, the exit status, or the error of a failed attempt
+    pub status: String,
+    /// The last part of the output of the attempt
+    pub output_tail: String,
+    /// How the process ended, when it was killed by a signal or crashed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub termination: Option<ProcessTermination>,
+    /// The exit code of the process, None when it was not started, or
+    /// was killed by a signal
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exit_code: Option<i32>,
+    /// The disk, I/O and CPU usage of the processes of the attempt,
+    /// with `--profile-io`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub io_usage: Option<IoUsage>,
+}
+
+/// What an execution of a program did, whether it succeeded or not
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct ProgramExecutionResult {
     output: String,
+    attempts: Vec<AttemptRecord>,
+    duration: Duration,
+    command_rendered: String,
+    /// Index of the program in its chain, when it ran in one
+    program_index: Option<usize>,
 }
 
 impl ProgramExecutionResult {
     pub fn new(output: String) -> Self {
-        Self { output }
+        Self {
+            output,
+            attempts: Vec::new(),
+            duration: Duration::ZERO,
+            command_rendered: String::new(),
+            program_index: None,
+        }
+    }
+
+    pub fn with_attempts(mut self, attempts: Vec<AttemptRecord>) -> Self {
+        self.attempts = attempts;
+        self
+    }
+
+    pub fn with_duration(mut self, duration: Duration) -> Self {
+        self.duration = duration;
+        self
+    }
+
+    pub fn with_command_rendered(mut self, command_rendered: String) -> Self {
+        self.command_rendered = command_rendered;
+        self
     }
 
+    pub fn with_program_index(mut self, program_index: usize) -> Self {
+        self.program_index = Some(program_index);
+        self
+    }
+
+    /// Get the records of the attempts, in order
+    pub fn get_attempts(&self) -> &Vec<AttemptRecord> {
+        &self.attempts
+    }
+
+    /// The output of the program, as stored in `stdout_stored_to`. For a
+    /// failed program, the output of its last attempt.
+    pub fn output(&self) -> &str {
+        &self.output
+    }
+
+    /// The exit code of the last attempt
+    pub fn exit_code(&self) -> Option<i32> {
+        self.attempts.last().and_then(|record| record.exit_code)
+    }
+
+    /// How long the program ran, from its first attempt to the end of
+    /// its last one, with the delays between the attempts
+    pub fn duration(&self) -> Duration {
+        self.duration
+    }
+
+    /// The number of attempts
+    pub fn attempts(&self) -> u32 {
+        self.attempts.len() as u32
+    }
+
+    /// The usage of all attempts together, when they were profiled
+    pub fn io_usage(&self) -> Option<IoUsage> {
+        self.attempts
+            .iter()
+            .filter_map(|record| record.io_usage)
+            .reduce(|total, io_usage| total.add(&io_usage))
+    }
+
+    pub fn was_retried(&self) -> bool {
+        self.attempts.len() > 1
+    }
+
+    /// The command line as it ran, with the values of secrets hidden
+    pub fn command_rendered(&self) -> &str {
+        &self.command_rendered
+    }
+
+    pub fn program_index(&self) -> Option<usize> {
+        self.program_index
+    }
+
+    /// Whether the last attempt succeeded
+    pub fn is_succeeded(&self) -> bool {
+        self.attempts.last().is_none_or(|record| record.succeeded)
+    }
+
+    #[deprecated(since = "0.3.43", note = "use `output`, which does not consume the result")]
     pub fn get_output(self) -> String {
         self.output
     }
 }
 
-#[derive(Debug, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, PartialEq, Eq)]
 pub struct Program {
     #[serde(flatten)]
     command_line: CommandLine,
@@ -51,10 +198,150 @@ pub struct Program {
     ///
     /// Use -1 to retry indefinitely, or any non-negative value to specify
     /// the maximum number of retries.
+    #[serde(default)]
     retry: i32,
+    /// Regex patterns matched against the error message and the tail of
+    /// the output of a failed attempt. If set, the program is only retried
+    /// when one of the patterns matches.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retry_on: Option<Vec<String>>,
+    /// Regex patterns that abort the retries immediately when any of
+    /// them matches a failed attempt, regardless of the remaining retries.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    fail_fast_on: Option<Vec<String>>,
+    /// For `retry: -1`, the number of attempts after which the user is
+    /// asked whether to keep retrying, and again every as many attempts.
+    /// Defaults to `DEFAULT_RETRY_ESCALATION_ATTEMPTS`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retry_escalation_attempts: Option<u32>,
+    /// Run the program as a long-lived background service. The chain
+    /// does not wait for it, and terminates it when the chain ends.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    service: Option<bool>,
+    /// A probe that gates the next programs until the service is ready
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    ready_check: Option<ReadyCheckOptions>,
+    /// Assertions on the stdout of the program. The program fails
+    /// when any of them does not hold.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    assertions: Option<Vec<OutputAssertion>>,
+    /// Mark the program as a cleanup step, which still runs when the
+    /// chain aborts before reaching it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    always_run: Option<bool>,
+    /// Store the outputs of all attempts, each under a header, instead
+    /// of only the output of the successful attempt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    store_all_attempts: Option<bool>,
+    /// Retry when the process cannot be started, e.g. the command is
+    /// not found, which is not retried by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retry_spawn_errors: Option<bool>,
+    /// Retry when the process crashes, e.g. on a segmentation fault,
+    /// which is not retried by default
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    retry_crashes: Option<bool>,
+    /// Connect the stdout of the program to the stdin of the next one,
+    /// running both together, instead of capturing it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    stream_to_next: Option<bool>,
+    /// A name of the program, which chains that extend this chain use
+    /// to override or remove it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    alias: Option<String>,
+    /// A step built into cchain, which runs instead of the command.
+    /// Built-in steps have no other fields.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    builtin: Option<BuiltinStep>,
+    /// A git operation, which runs through git2 instead of the command,
+    /// in the working directory of the program
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    git: Option<GitOperation>,
+    /// What the program does to its surroundings, e.g. `deletes-files`
+    /// or `mutates-remote`, for reviewing a chain before running it
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    effects: Option<Vec<String>>,
+    /// How much of the output is shown as the program runs: `full`,
+    /// `summary` or `silent`. Stored variables and failure reports
+    /// always have the full output.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    display_output: Option<DisplayOutput>,
+    /// Values to expand the program over when the chain is loaded, into
+    /// a program per combination, e.g. `{"os": ["linux", "macos"]}`. The
+    /// values replace `<<matrix.os>>`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matrix: Option<Matrix>,
+    /// Run the programs expanded from the matrix together, in a
+    /// concurrency group of their own
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    matrix_parallel: Option<bool>,
+    /// Rewrite the snapshot files of `equals_file` assertions
+    /// with the output, instead of comparing
+    #[serde(skip)]
+    update_snapshots: bool,
+    /// Show the full output whatever `display_output` is, e.g. with
+    /// `cchain run --show-all-output`
+    #[serde(skip)]
+    show_all_output: bool,
+    /// Asks the user whether to keep retrying
+    #[serde(skip)]
+    interactor: SharedInteractor,
+    /// Number of attempts in the last execution
+    #[serde(skip)]
+    attempts: u32,
+    /// Records of the attempts in the last execution
+    #[serde(skip)]
+    attempt_records: Vec<AttemptRecord>,
+    /// The result of the last execution, whether it succeeded or not
+    #[serde(skip)]
+    last_result: Option<ProgramExecutionResult>,
+}
+
+/// What to do after a failed attempt
+enum RetryDecision {
+    /// Retry, with the `retry_on` pattern that allowed it if any
+    Retry(Option<String>),
+    /// Stop retrying, with the reason
+    Stop(String),
 }
 
 impl Program {
+    /// Fields accepted in a program, including the flattened command line fields
+    pub const FIELD_NAMES: &'static [&'static str] = &[
+        "command",
+        "arguments",
+        "interpreter",
+        "environment_variables_override",
+        "working_directory",
+        "run_as",
+        "stdout_stored_to",
+        "stdout_storage_options",
+        "failure_handling_options",
+        "concurrency_group",
+        "retry",
+        "retry_on",
+        "fail_fast_on",
+        "retry_escalation_attempts",
+        "service",
+        "ready_check",
+        "assertions",
+        "always_run",
+        "store_all_attempts",
+        "retry_spawn_errors",
+        "retry_crashes",
+        "stream_to_next",
+        "alias",
+        "effects",
+        "display_output",
+        "git",
+        "matrix",
+        "matrix_parallel",
+    ];
+
+    /// Fields that describe a program without changing how it runs
+    pub const COSMETIC_FIELD_NAMES: &'static [&'static str] = &["alias", "display_output"];
+
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         command: String,
         arguments: Vec<String>,
@@ -80,13 +367,611 @@ impl Program {
             failure_handling_options,
             concurrency_group,
             retry,
+            ..Default::default()
+        }
+    }
+
+    /// Only retry when a failed attempt matches one of the patterns
+    pub fn with_retry_on(mut self, patterns: Vec<String>) -> Self {
+        self.retry_on = Some(patterns);
+        self
+    }
+
+    /// Stop retrying when a failed attempt matches any of the patterns
+    pub fn with_fail_fast_on(mut self, patterns: Vec<String>) -> Self {
+        self.fail_fast_on = Some(patterns);
+        self
+    }
+
+    /// Assert on the stdout of the program
+    pub fn with_assertions(mut self, assertions: Vec<OutputAssertion>) -> Self {
+        self.assertions = Some(assertions);
+        self
+    }
+
+    /// Show the full output whatever `display_output` is
+    pub fn set_show_all_output(&mut self, show_all_output: bool) {
+        self.show_all_output = show_all_output;
+    }
+
+    /// Sample the disk, I/O and CPU usage of the attempts
+    pub fn set_profile_io(&mut self, profile_io: bool) {
+        self.command_line.set_profile_io(profile_io);
+    }
+
+    /// Rewrite the snapshot files instead of comparing with them
+    pub fn set_update_snapshots(&mut self, update_snapshots: bool) {
+        self.update_snapshots = update_snapshots;
+    }
+
+    /// Check that the assertions can be evaluated
+    pub fn validate_assertions(&self) -> Result<(), Error> {
+        for assertion in self.assertions.iter().flatten() {
+            assertion.validate(self.update_snapshots)?;
+        }
+
+        Ok(())
+    }
+
+    /// Check the output against the assertions
+    fn check_assertions(&self, output: &str) -> Result<(), Error> {
+        for assertion in self.assertions.iter().flatten() {
+            assertion.check(output, self.update_snapshots)?;
+        }
+
+        Ok(())
+    }
+
+    /// Run the program as a background service
+    pub fn with_service(mut self, ready_check: Option<ReadyCheckOptions>) -> Self {
+        self.service = Some(true);
+        self.ready_check = ready_check;
+        self
+    }
+
+    /// Check whether the program runs as a background service
+    pub fn is_service(&self) -> bool {
+        self.service.unwrap_or(false)
+    }
+
+    /// Check whether the program is a cleanup step that runs even when
+    /// the chain aborts
+    pub fn is_always_run(&self) -> bool {
+        self.always_run.unwrap_or(false)
+    }
+
+    /// Start the program as a service and wait until its ready check
+    /// passes, if any.
+    ///
+    /// # Returns
+    ///
+    /// The running service, along with the output it produced up to the
+    /// ready point, or the stdout of the successful probe with
+    /// `store_output`, with the stdout storage options applied.
+    pub fn start_service(&mut self) -> Result<(Service, String), Error> {
+        let mut service: Service = Service::start(&mut self.command_line)?;
+
+        let mut probe_output: Option<String> = None;
+        if let Some(ready_check) = &mut self.ready_check {
+            match service.wait_until_ready(ready_check) {
+                Ok(output) if ready_check.store_output => probe_output = Some(output),
+                Ok(_) => {}
+                Err(error) => {
+                    service.stop(super::service::SERVICE_STOP_GRACE_PERIOD)?;
+                    return Err(error);
+                }
+            }
+        }
+
+        let output: String = probe_output.unwrap_or_else(|| service.get_output());
+        let output: String = match self.capture_stdout(output) {
+            Ok(output) => output,
+            Err(error) => {
+                service.stop(super::service::SERVICE_STOP_GRACE_PERIOD)?;
+                return Err(error);
+            }
+        };
+        Ok((service, output))
+    }
+
+    /// Make the program a step built into cchain
+    pub fn with_builtin(mut self, builtin: BuiltinStep) -> Self {
+        self.builtin = Some(builtin);
+        self
+    }
+
+    /// Make the program a git operation
+    pub fn with_git_operation(mut self, git_operation: GitOperation) -> Self {
+        self.git = Some(git_operation);
+        self
+    }
+
+    /// Get the git operation that the program runs, if any
+    pub fn get_git_operation(&self) -> Option<&GitOperation> {
+        self.git.as_ref()
+    }
+
+    /// Get the git operation that the program runs, to inject the values
+    /// of variables into it
+    pub fn get_git_operation_mut(&mut self) -> Option<&mut GitOperation> {
+        self.git.as_mut()
+    }
+
+    /// Get the values of the program that may hold placeholders of
+    /// variables: those of the command line and the fields of the git
+    /// operation
+    pub fn get_placeholder_values(&self) -> Vec<&str> {
+        let mut values: Vec<&str> = self.command_line.get_placeholder_values();
+        if let Some(git) = &self.git {
+            values.extend(git.get_placeholder_values());
+        }
+        values
+    }
+
+    /// Resolve the relative paths of the program, its remedy command line,
+    /// its ready check, its snapshot files and the path of its clone
+    /// against the base directory of the chain
+    pub fn resolve_relative_paths(&mut self, base_directory: &Path) {
+        self.command_line.resolve_relative_paths(base_directory);
+        if let Some(git_operation) = &mut self.git {
+            git_operation.resolve_relative_paths(base_directory);
+        }
+        if let Some(command_line) = &mut self.failure_handling_options.remedy_command_line {
+            command_line.resolve_relative_paths(base_directory);
+        }
+        if let Some(ready_check) = &mut self.ready_check {
+            ready_check.command_line.resolve_relative_paths(base_directory);
+        }
+        for assertion in self.assertions.iter_mut().flatten() {
+            assertion.resolve_relative_paths(base_directory);
+        }
+    }
+
+    /// Rewrite the placeholders of the arguments, the remedy arguments, the
+    /// git operation and `stdout_stored_to` from custom delimiters to the
+    /// default ones
+    pub fn apply_variable_delimiters(&mut self, variable_delimiters: &VariableDelimiters) {
+        self.command_line.apply_variable_delimiters(variable_delimiters);
+        if let Some(command_line) = &mut self.failure_handling_options.remedy_command_line {
+            command_line.apply_variable_delimiters(variable_delimiters);
+        }
+        if let Some(git) = &mut self.git {
+            git.rewrite_values(&mut |value| variable_delimiters.to_default_syntax(value));
+        }
+        if let Some(stdout_stored_to) = &mut self.stdout_stored_to {
+            *stdout_stored_to = variable_delimiters.to_default_syntax(stdout_stored_to);
+        }
+    }
+
+    /// Check that `stdout_stored_to` is exactly one placeholder without a
+    /// qualifier, and rewrite a bare name as a placeholder
+    ///
+    /// # Returns
+    ///
+    /// A deprecation notice when the name was bare
+    pub fn normalize_stdout_stored_to(&mut self) -> Result<Option<String>, Error> {
+        let Some(stdout_stored_to) = &mut self.stdout_stored_to else {
+            return Ok(None);
+        };
+        let stored_variable: StoredVariable = StoredVariable::parse("stdout_stored_to", stdout_stored_to)?;
+        if !stored_variable.is_bare {
+            return Ok(None);
+        }
+
+        let notice: String = format!(
+            "`stdout_stored_to` is the bare name `{}`, which is deprecated. Write it as `{}`.",
+            stored_variable.name,
+            stored_variable.to_placeholder()
+        );
+        *stdout_stored_to = stored_variable.to_placeholder();
+
+        Ok(Some(notice))
+    }
+
+    /// Expand the program over these values, into a program per combination
+    pub fn with_matrix(mut self, matrix: Matrix) -> Self {
+        self.matrix = Some(matrix);
+        self
+    }
+
+    pub fn get_matrix(&self) -> Option<&Matrix> {
+        self.matrix.as_ref()
+    }
+
+    /// Put the keys of the matrix in the order they are declared in the
+    /// chain files
+    pub fn order_matrix_keys(&mut self, declared_matrix_keys: &DeclaredMatrixKeys) {
+        if let Some(matrix) = &mut self.matrix {
+            matrix.order_keys(declared_matrix_keys);
+        }
+    }
+
+    /// Run the programs expanded from the matrix together
+    pub fn with_matrix_parallel(mut self, matrix_parallel: bool) -> Self {
+        self.matrix_parallel = Some(matrix_parallel);
+        self
+    }
+
+    pub fn is_matrix_parallel(&self) -> bool {
+        self.matrix_parallel.unwrap_or(false)
+    }
+
+    /// Check the matrix of the program, and that the placeholders of
+    /// matrix values only use its keys
+    pub fn validate_matrix(&self) -> Result<(), Error> {
+        let placeholder_keys: Vec<String> = get_matrix_placeholder_keys(&serde_json::to_string(self)?);
+        let Some(matrix) = &self.matrix else {
+            if self.matrix_parallel.is_some() {
+                return Err(anyhow!("`matrix_parallel` is set, but there is no `matrix`"));
+            }
+            if let Some(key) = placeholder_keys.first() {
+                return Err(anyhow!("`<<matrix.{}>>` is used, but there is no `matrix`", key));
+            }
+            return Ok(());
+        };
+
+        validate_matrix(matrix)?;
+        if let Some(key) = placeholder_keys.iter().find(|key| !matrix.contains_key(key)) {
+            return Err(anyhow!("`<<matrix.{}>>` is used, but `{}` is not a key of `matrix`", key, key));
+        }
+        // The values are only put in some fields, the placeholders left
+        // elsewhere would stay as they are
+        let mut program: Program = self.clone();
+        program.rewrite_matrix_fields(&mut |text: &str| remove_matrix_placeholders(text));
+        if let Some(key) = get_matrix_placeholder_keys(&serde_json::to_string(&program)?).first() {
+            return Err(anyhow!(
+                "`<<matrix.{}>>` is only replaced in the arguments, the environment overrides, the working directory, the remedy, `ready_check`, `assertions`, `git` and `stdout_stored_to`",
+                key
+            ));
+        }
+        if self.is_matrix_parallel() && self.concurrency_group.is_some() {
+            return Err(anyhow!("`matrix_parallel` puts the programs in a concurrency group of their own, so `concurrency_group` cannot be set"));
+        }
+        if self.is_streaming_to_next() {
+            return Err(anyhow!("`stream_to_next` cannot be set with `matrix`"));
+        }
+
+        Ok(())
+    }
+
+    /// Expand the program into a program per combination of the values of
+    /// its matrix, with the placeholders of the values replaced in the
+    /// command line, the remedy, `ready_check`, `assertions`, `git` and
+    /// `stdout_stored_to`. The programs are aliased after the
+    /// combinations, e.g. `build[linux,release]`, and the
+    /// variable each stores is suffixed with the values, e.g.
+    /// `<<artifact_linux_release>>`, unless `stdout_stored_to` already uses
+    /// the placeholders of the values.
+    ///
+    /// # Arguments
+    ///
+    /// * `concurrency_group` - The group to put the programs in, for `matrix_parallel`
+    pub fn expand_matrix(&self, concurrency_group: Option<usize>) -> Vec<Program> {
+        let Some(matrix) = &self.matrix else {
+            return vec![self.clone()];
+        };
+
+        let name: String = match &self.alias {
+            Some(alias) => alias.clone(),
+            None => self.command_line.clone().get_command().to_string(),
+        };
+        get_matrix_combinations(matrix)
+            .into_iter()
+            .map(|combination| {
+                let mut program: Program = self.clone();
+                program.matrix = None;
+                program.matrix_parallel = None;
+                program.alias = Some(format!("{}[{}]", name, combination.get_label()));
+                if concurrency_group.is_some() {
+                    program.concurrency_group = concurrency_group;
+                }
+
+                if let Some(stdout_stored_to) = &mut program.stdout_stored_to {
+                    if get_matrix_placeholder_keys(stdout_stored_to).is_empty() {
+                        *stdout_stored_to = add_variable_suffix(stdout_stored_to, &combination.get_variable_suffix());
+                    }
+                }
+                program.rewrite_matrix_fields(&mut |text: &str| combination.substitute(text));
+
+                program
+            })
+            .collect()
+    }
+
+    /// Replace the fields that take the values of a matrix with what the
+    /// closure returns for them
+    fn rewrite_matrix_fields(&mut self, rewrite: &mut impl FnMut(&str) -> String) {
+        self.command_line.rewrite_values(rewrite);
+        if let Some(command_line) = &mut self.failure_handling_options.remedy_command_line {
+            command_line.rewrite_values(rewrite);
+        }
+        if let Some(ready_check) = &mut self.ready_check {
+            ready_check.command_line.rewrite_values(rewrite);
+        }
+        for assertion in self.assertions.iter_mut().flatten() {
+            assertion.rewrite_value(rewrite);
+        }
+        if let Some(git) = &mut self.git {
+            git.rewrite_values(rewrite);
+        }
+        if let Some(stdout_stored_to) = &mut self.stdout_stored_to {
+            *stdout_stored_to = rewrite(stdout_stored_to);
+        }
+    }
+
+    /// Replace each argument of the program and of its remedy with what
+    /// the closure returns for it
+    pub fn rewrite_arguments(&mut self, mut rewrite: impl FnMut(&str) -> String) {
+        self.command_line.rewrite_arguments(&mut rewrite);
+        if let Some(command_line) = &mut self.failure_handling_options.remedy_command_line {
+            command_line.rewrite_arguments(&mut rewrite);
+        }
+    }
+
+    pub fn get_retry_escalation_attempts(&self) -> Option<u32> {
+        self.retry_escalation_attempts
+    }
+
+    /// Annotate what the program does to its surroundings
+    pub fn with_effects(mut self, effects: Vec<String>) -> Self {
+        self.effects = Some(effects);
+        self
+    }
+
+    pub fn get_effects(&self) -> Option<&Vec<String>> {
+        self.effects.as_ref()
+    }
+
+    /// Explain why the program needs `--allow-destructive` to run, if it
+    /// does. Annotated programs are judged by their `effects`, the others
+    /// by their command lines.
+    pub fn get_destructive_reason(&self) -> Option<String> {
+        if let Some(effects) = &self.effects {
+            return effects
+                .iter()
+                .find(|effect| DESTRUCTIVE_EFFECTS.contains(&effect.as_str()))
+                .map(|effect| format!("annotated `{}`", effect));
         }
+
+        guess_destructive_reason(&self.command_line).or_else(|| {
+            let remedy_command_line: &CommandLine = self.failure_handling_options.remedy_command_line.as_ref()?;
+            guess_destructive_reason(remedy_command_line).map(|reason| format!("remedy {}", reason))
+        })
+    }
+
+    /// Get the built-in step that the program stands for, if any
+    pub fn get_builtin(&self) -> Option<BuiltinStep> {
+        self.builtin
     }
 
     pub fn get_retry(&self) -> &i32 {
         &self.retry
     }
 
+    /// Set the number of attempts of `retry: -1` before asking
+    /// whether to keep retrying
+    pub fn with_retry_escalation_attempts(mut self, attempts: u32) -> Self {
+        self.retry_escalation_attempts = Some(attempts);
+        self
+    }
+
+    pub fn set_interactor(&mut self, interactor: SharedInteractor) {
+        self.interactor = interactor;
+    }
+
+    /// Get the number of attempts in the last execution
+    pub fn get_attempts(&self) -> u32 {
+        self.attempts
+    }
+
+    /// Get the records of the attempts in the last execution
+    pub fn get_attempt_records(&self) -> &Vec<AttemptRecord> {
+        &self.attempt_records
+    }
+
+    /// Get the result of the last execution, which failed executions
+    /// have too
+    pub fn get_last_result(&self) -> Option<&ProgramExecutionResult> {
+        self.last_result.as_ref()
+    }
+
+    /// Store the outputs of all attempts instead of the successful one
+    pub fn with_store_all_attempts(mut self, store_all_attempts: bool) -> Self {
+        self.store_all_attempts = Some(store_all_attempts);
+        self
+    }
+
+    /// Retry the program when its process cannot be started
+    pub fn with_retry_spawn_errors(mut self, retry_spawn_errors: bool) -> Self {
+        self.retry_spawn_errors = Some(retry_spawn_errors);
+        self
+    }
+
+    /// Retry the program when its process crashes
+    pub fn with_retry_crashes(mut self, retry_crashes: bool) -> Self {
+        self.retry_crashes = Some(retry_crashes);
+        self
+    }
+
+    /// Show less of the output as the program runs
+    pub fn with_display_output(mut self, display_output: DisplayOutput) -> Self {
+        self.display_output = Some(display_output);
+        self
+    }
+
+    /// Stream the stdout of the program to the next program
+    pub fn with_stream_to_next(mut self, stream_to_next: bool) -> Self {
+        self.stream_to_next = Some(stream_to_next);
+        self
+    }
+
+    /// Check whether the stdout of the program streams to the next program
+    pub fn is_streaming_to_next(&self) -> bool {
+        self.stream_to_next.unwrap_or(false)
+    }
+
+    pub fn with_alias(mut self, alias: String) -> Self {
+        self.alias = Some(alias);
+        self
+    }
+
+    pub fn get_alias(&self) -> Option<&String> {
+        self.alias.as_ref()
+    }
+
+    /// Compute a digest of what the program runs and how, e.g. the
+    /// command line, the retries and the stored variable. Fields that do
+    /// not change the execution, in `COSMETIC_FIELD_NAMES`, are left out,
+    /// so that programs with the same digest do the same thing.
+    pub fn execution_fingerprint(&self) -> String {
+        let mut value: serde_json::Value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(fields) = value.as_object_mut() {
+            for field in Self::COSMETIC_FIELD_NAMES {
+                fields.remove(*field);
+            }
+        }
+        sha256_hex(value.to_string().as_bytes())
+    }
+
+    /// Connect the stdin and the stdout of the next execution to the
+    /// other programs of a pipeline
+    pub fn set_stream_ends(&mut self, stream_ends: StreamEnds) {
+        self.command_line.set_stream_ends(stream_ends);
+    }
+
+    /// Let a concurrency group cancel the program while it runs
+    pub fn set_cancellation(&mut self, cancellation: Option<CancellationHandle>) {
+        self.command_line.set_cancellation(cancellation);
+    }
+
+    /// Record the outcome of an attempt
+    fn record_attempt(&mut self, started_at: Instant, output: &str, exit_code: Option<i32>, result: &Result<(), Error>) {
+        let termination: Option<ProcessTermination> = result
+            .as_ref()
+            .err()
+            .and_then(|error| error.downcast_ref::<CommandLineError>())
+            .and_then(|error| error.get_termination())
+            .cloned();
+        let status: String = match result {
+            Ok(_) => "succeeded".to_string(),
+            Err(error) => match error.downcast_ref::<CommandLineError>() {
+                Some(CommandLineError::NonZeroExit { status, .. }) => status.clone(),
+                Some(CommandLineError::Cancelled { .. }) => "cancelled".to_string(),
+                Some(CommandLineError::SpawnFailed { .. }) | None => error.to_string(),
+            },
+        };
+        self.attempt_records.push(AttemptRecord {
+            attempt: self.attempts,
+            duration_ms: started_at.elapsed().as_millis() as u64,
+            succeeded: result.is_ok(),
+            status,
+            output_tail: get_tail(output, ATTEMPT_OUTPUT_TAIL_LENGTH).to_string(),
+            termination,
+            exit_code,
+            io_usage: self.command_line.take_io_usage(),
+        });
+    }
+
+    /// Join the outputs of the attempts, each under a header with the
+    /// number and the status of the attempt
+    fn join_attempt_outputs(&self, outputs: &[String]) -> String {
+        self.attempt_records
+            .iter()
+            .zip(outputs)
+            .map(|(record, output)| format!("--- Attempt {}: {} ---\n{}", record.attempt, record.status, output))
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// Warn that a program with `retry: -1` keeps failing, and ask
+    /// whether to keep retrying. Without a user to ask, it continues.
+    ///
+    /// # Returns
+    ///
+    /// Whether to abort retrying
+    fn escalate_retries(&self) -> Result<bool, Error> {
+        display_message(
+            Level::Error,
+            &format!(
+                "{} has failed {} times in a row. The command may never succeed, e.g. because of a typo.",
+                self, self.attempts
+            ),
+        );
+
+        if !self.interactor.is_interactive() {
+            display_message(Level::Warn, "No user to ask, continue retrying...");
+            return Ok(false);
+        }
+
+        loop {
+            let answer: String = self.interactor.prompt("Keep retrying? [c]ontinue/[a]bort:")?;
+            match answer.to_lowercase().as_str() {
+                "a" | "abort" => return Ok(true),
+                "c" | "continue" | "" => return Ok(false),
+                _ => display_message(Level::Warn, "Please answer `c` to continue, or `a` to abort."),
+            }
+        }
+    }
+
+    /// Check that the capture filter of the stored output compiles
+    pub fn validate_capture_filter(&self) -> Result<(), Error> {
+        self.stdout_storage_options.validate_capture_filter()
+    }
+
+    /// Check that the `retry_on` and `fail_fast_on` patterns compile
+    pub fn validate_retry_patterns(&self) -> Result<(), Error> {
+        for (field, patterns) in [("retry_on", &self.retry_on), ("fail_fast_on", &self.fail_fast_on)] {
+            for pattern in patterns.iter().flatten() {
+                Regex::new(pattern)
+                    .map_err(|error| anyhow!("Invalid `{}` pattern `{}`: {}", field, pattern, error))?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find the first pattern that matches the text
+    fn find_matching_pattern(patterns: &[String], text: &str) -> Result<Option<String>, Error> {
+        for pattern in patterns {
+            if Regex::new(pattern)?.is_match(text) {
+                return Ok(Some(pattern.clone()));
+            }
+        }
+
+        Ok(None)
+    }
+
+    /// Decide whether a failed attempt deserves a retry by consulting
+    /// `fail_fast_on` first, then `retry_on`.
+    fn decide_retry(&self, error: &Error) -> Result<RetryDecision, Error> {
+        // Match against the error message and the output tail
+        let mut failure_text: String = error.to_string();
+        if let Some(command_line_error) = error.downcast_ref::<CommandLineError>() {
+            failure_text.push('\n');
+            failure_text.push_str(command_line_error.get_output_tail(FAILURE_OUTPUT_TAIL_LENGTH));
+        }
+
+        if let Some(patterns) = &self.fail_fast_on {
+            if let Some(pattern) = Self::find_matching_pattern(patterns, &failure_text)? {
+                return Ok(RetryDecision::Stop(format!(
+                    "Failure matches `fail_fast_on` pattern `{}`",
+                    pattern
+                )));
+            }
+        }
+
+        if let Some(patterns) = &self.retry_on {
+            return match Self::find_matching_pattern(patterns, &failure_text)? {
+                Some(pattern) => Ok(RetryDecision::Retry(Some(pattern))),
+                None => Ok(RetryDecision::Stop(
+                    "Failure matches none of the `retry_on` patterns".to_string()
+                )),
+            };
+        }
+
+        Ok(RetryDecision::Retry(None))
+    }
+
     /// Get the Await variable declared in this program
     pub fn get_awaitable_variable(&self) -> &Option<String> {
         &self.stdout_stored_to
@@ -112,57 +997,107 @@ impl Program {
         self.concurrency_group
     }
 
+    /// Get the value to store from the output of a successful execution,
+    /// applying the capture filter to the sanitized output before the
+    /// newline options
+    fn capture_stdout(&self, stdout_string: String) -> Result<String, Error> {
+        let sanitized: String = self.stdout_storage_options.sanitize(stdout_string);
+        let captured: String = self.stdout_storage_options.filter_capture(sanitized)?;
+        Ok(self.trim_newline_characters(captured))
+    }
+
     /// In-place operation on the stdout string.
     /// Directly apply the stdout storage options.
     fn apply_stdout_storage_options(&self, stdout_string: String) -> String {
-        let mut final_string = String::new();
+        self.trim_newline_characters(self.stdout_storage_options.sanitize(stdout_string))
+    }
+
+    /// Trim the newlines around the output when `without_newline_characters`
+    /// is set, and keep it as it is otherwise
+    fn trim_newline_characters(&self, stdout_string: String) -> String {
         if self.stdout_storage_options.without_newline_characters {
-            final_string = stdout_string.trim_matches('\n').to_string();
+            return stdout_string.trim_matches('\n').to_string();
         }
 
-        final_string
+        stdout_string
     }
 
     pub fn get_failure_handling_options(&mut self) -> &mut FailureHandlingOptions {
         &mut self.failure_handling_options
     }
 
-    pub fn execute_argument_functions(&mut self) -> Result<(), Error> {
-        // Iterate over each argument in the configuration
-        for index in 0..self.command_line.get_arguments().len() {
-            // Clone the current argument
-            let argument: String = self.command_line.get_arguments()[index].clone();
+    /// Get the function calls in the program and its remedy command line,
+    /// with the fields they are in
+    pub fn get_function_calls(&self) -> Vec<(String, Function)> {
+        let mut fields: Vec<(String, &str)> = self.command_line.get_function_fields();
+        if let Some(command_line) = &self.failure_handling_options.remedy_command_line {
+            for (field, value) in command_line.get_function_fields() {
+                fields.push((format!("failure_handling_options.remedy_command_line.{}", field), value));
+            }
+        }
+
+        fields
+            .into_iter()
+            .filter_map(|(field, value)| Function::from_str(value).ok().map(|function| (field, function)))
+            .collect()
+    }
 
-            // Attempt to parse the argument as a function
-            let function = match Function::from_str(&argument) {
+    /// Replace the function calls in the arguments, the environment
+    /// variable overrides and the working directory of a command line
+    /// with their results. The chain variables that the calls take as
+    /// parameters are looked up by name.
+    fn execute_functions(command_line: &mut CommandLine, lookup: &dyn Fn(&str) -> Result<VariableParameterValue, Error>) -> Result<(), Error> {
+        let description: String = command_line.to_string();
+        let secret_values: RefCell<Vec<String>> = RefCell::new(Vec::new());
+        let lookup = |name: &str| -> Result<String, Error> {
+            let parameter_value: VariableParameterValue = lookup(name)?;
+            if parameter_value.is_secret {
+                secret_values.borrow_mut().push(parameter_value.value.clone());
+            }
+            Ok(parameter_value.value)
+        };
+        for (field, value) in command_line.get_function_fields_mut() {
+            // Attempt to parse the value as a function
+            let mut function = match Function::from_str(value) {
                 Ok(f) => f,
-                Err(_) => continue, // If parsing fails, skip to the next argument
+                Err(_) => continue, // If parsing fails, skip to the next value
             };
+            function.resolve_variables(&lookup)?;
 
             display_message(
                 Level::Logging,
                 &format!(
-                    "Detected function, {}, when executing command: {}, executing the function...",
+                    "Detected function, {}, in `{}` when executing command: {}, executing the function...",
                     function.get_name(),
-                    self.command_line
+                    field,
+                    description
                 ),
             );
 
             // Execute the function 
-            let result: String = function.execute()?;
-            self.command_line.revise_argument_by_index(index, result);
+            *value = function.execute()?;
             display_message(
                 Level::Logging,
                 &format!("Function, {}, executed successfully", function.get_name()),
             );
         }
-        // Return the result of the function execution
+        for secret_value in secret_values.into_inner() {
+            command_line.add_secret_value(secret_value);
+        }
+
         Ok(())
     }
 
+    /// Execute the function calls of the command line. The functions of
+    /// the remedy command line run only when the remedy does.
+    pub fn execute_argument_functions(&mut self, lookup: &dyn Fn(&str) -> Result<VariableParameterValue, Error>) -> Result<(), Error> {
+        Self::execute_functions(&mut self.command_line, lookup)
+    }
+
     /// This method is supposed to be called when the program fails
-    pub fn execute_remedy_command_line(&mut self) -> Result<(), Error> {
+    pub fn execute_remedy_command_line(&mut self, lookup: &dyn Fn(&str) -> Result<VariableParameterValue, Error>) -> Result<(), Error> {
         if let Some(command_line) = &mut self.failure_handling_options.remedy_command_line {
+            Self::execute_functions(command_line, lookup)?;
             command_line.execute()?;
         }
 
@@ -170,8 +1105,48 @@ impl Program {
     }
 }
 
+/// Tell how many attempts a failure came after. The failure is kept as
+/// the source, so that a `CommandLineError` can still be downcast to.
+fn add_attempt_count(error: Error, attempts: u32) -> Error {
+    let message: String = format!("{} (after {} attempts)", error, attempts);
+    error.context(message)
+}
+
+/// A program that does the same as an earlier program of the chain
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DuplicateProgram {
+    pub index: usize,
+    /// The closest earlier program with the same execution fingerprint
+    pub original_index: usize,
+}
+
+impl DuplicateProgram {
+    /// Whether the duplicate runs right after the original, so that
+    /// the same thing is done twice in a row
+    pub fn is_consecutive(&self) -> bool {
+        self.original_index + 1 == self.index
+    }
+}
+
+/// Find the programs with the same execution fingerprint as an earlier
+/// one, each paired with the closest such program
+pub fn find_duplicate_programs(fingerprints: &[String]) -> Vec<DuplicateProgram> {
+    let mut last_indices: HashMap<&str, usize> = HashMap::new();
+    let mut duplicates: Vec<DuplicateProgram> = Vec::new();
+    for (index, fingerprint) in fingerprints.iter().enumerate() {
+        if let Some(original_index) = last_indices.insert(fingerprint, index) {
+            duplicates.push(DuplicateProgram { index, original_index });
+        }
+    }
+
+    duplicates
+}
+
 impl std::fmt::Display for Program {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if let Some(git_operation) = &self.git {
+            return write!(f, "{}", git_operation);
+        }
         write!(f, "{}", self.command_line)
     }
 }
@@ -207,23 +1182,175 @@ impl Execution<ProgramExecutionResult> for Program {
     }
 
     fn execute(&mut self) -> Result<Vec<ProgramExecutionResult>, anyhow::Error> {
+        let started_at: Instant = Instant::now();
+        self.last_result = None;
+        let mut result = self.execute_with_retries();
+        let command_rendered: String = match &self.git {
+            Some(git_operation) => git_operation.to_string(),
+            None => self.command_line.render_command(RenderStyle::Masked),
+        };
+        let last_result: ProgramExecutionResult = match &mut result {
+            Ok(results) => {
+                for program_result in results.iter_mut() {
+                    *program_result = program_result
+                        .clone()
+                        .with_duration(started_at.elapsed())
+                        .with_command_rendered(command_rendered.clone());
+                }
+                results[0].clone()
+            }
+            Err(error) => {
+                // Errors after retries no longer carry the output
+                let output: String = match error.downcast_ref::<CommandLineError>() {
+                    Some(command_line_error) => command_line_error.get_output().to_string(),
+                    None => self.attempt_records.last().map(|record| record.output_tail.clone()).unwrap_or_default(),
+                };
+                ProgramExecutionResult::new(output)
+                    .with_attempts(self.attempt_records.clone())
+                    .with_duration(started_at.elapsed())
+                    .with_command_rendered(command_rendered)
+            }
+        };
+        self.last_result = Some(last_result);
+        if let Some((event_sender, program_index)) = self.command_line.get_event_sender() {
+            event_sender.send(ChainEvent::ProgramFinished { program_index: *program_index, succeeded: result.is_ok() });
+        }
+
+        result
+    }
+}
+
+impl Program {
+    /// Run the git operation of the program in its working directory,
+    /// reporting it like the execution of a command line
+    fn execute_git_operation(&self, git_operation: &GitOperation) -> Result<Vec<CommandLineExecutionResult>, Error> {
+        let directory = match self.command_line.get_working_directory() {
+            Some(working_directory) => std::path::PathBuf::from(working_directory),
+            None => std::env::current_dir()?,
+        };
+        if !is_summary_only() {
+            display_message(Level::Logging, &format!("Start executing command: {}", git_operation));
+        }
+        if let Some((event_sender, program_index)) = self.command_line.get_event_sender() {
+            event_sender.send(ChainEvent::ProgramStarted { program_index: *program_index });
+        }
+
+        let output: String = git_operation.execute(&directory)?;
+        if !is_summary_only() && self.display_output.unwrap_or_default() != DisplayOutput::Silent {
+            display_command_line(&Term::stdout(), &output);
+        }
+        if let Some((event_sender, program_index)) = self.command_line.get_event_sender() {
+            event_sender.send(ChainEvent::ProgramOutput { program_index: *program_index, text: output.clone() });
+        }
+        if !is_summary_only() {
+            display_message(Level::Logging, &format!("Finished executing command: {}", git_operation));
+        }
+
+        Ok(vec![CommandLineExecutionResult::new(output)])
+    }
+
+    /// Execute the command line until it succeeds or runs out of retries
+    fn execute_with_retries(&mut self) -> Result<Vec<ProgramExecutionResult>, anyhow::Error> {
         let mut attempts: i32 = 0;
+        self.attempts = 0;
+        self.attempt_records.clear();
+        // The full outputs of the attempts, for `store_all_attempts`
+        let mut outputs: Vec<String> = Vec::new();
+        // Repeated identical failures of infinite retries are collapsed
+        // into one updating line on terminals
+        let terminal: Term = Term::stdout();
+        let mut last_failure: Option<String> = None;
+        let mut is_status_line_shown: bool = false;
+        let display_output: DisplayOutput = match self.show_all_output {
+            true => DisplayOutput::Full,
+            false => self.display_output.unwrap_or_default(),
+        };
+        self.command_line.set_display_output(display_output);
         // In the case of retry==0 we never retry, so our only chance is the first attempt.
         // For retry == -1, we reattempt indefinitely.
         loop {
+            self.attempts += 1;
+            let started_at: Instant = Instant::now();
             // Attempt execution through the commandline’s execute method.
-            match self.command_line.execute() {
-                Ok(output_stdout) => {
-                    // On success: apply any stdout storage options
-                    let result: String =
-                        self.apply_stdout_storage_options(output_stdout[0].get_output());
+            // On success: apply any stdout storage options, then check
+            // the assertions against what would be stored. The output is
+            // kept in either case for the attempt record.
+            let execution = match &self.git {
+                Some(git_operation) => self.execute_git_operation(git_operation),
+                None => self.command_line.execute(),
+            };
+            // Git operations run without a process
+            let success_exit_code: Option<i32> = if self.git.is_some() { None } else { Some(0) };
+            let (output, exit_code, result): (String, Option<i32>, Result<(), Error>) = match execution {
+                Ok(output_stdout) => match self.capture_stdout(output_stdout[0].get_output()) {
+                    Ok(output) => {
+                        let result: Result<(), Error> = self.check_assertions(&output);
+                        (output, success_exit_code, result)
+                    }
+                    // The full output is kept for the attempt record, as
+                    // nothing matched the filter
+                    Err(error) => {
+                        (self.apply_stdout_storage_options(output_stdout[0].get_output()), success_exit_code, Err(error))
+                    }
+                },
+                Err(error) => {
+                    let (output, exit_code): (String, Option<i32>) = match error.downcast_ref::<CommandLineError>() {
+                        Some(command_line_error) => {
+                            let exit_code: Option<i32> = match command_line_error {
+                                CommandLineError::NonZeroExit { exit_code, .. } => *exit_code,
+                                _ => None,
+                            };
+                            (self.apply_stdout_storage_options(command_line_error.get_output().to_string()), exit_code)
+                        }
+                        None => (String::new(), None),
+                    };
+                    (output, exit_code, Err(error))
+                }
+            };
+            self.record_attempt(started_at, &output, exit_code, &result);
+            if !self.store_all_attempts.unwrap_or(false) {
+                outputs.clear();
+            }
+            outputs.push(output);
 
-                    return Ok(vec![ProgramExecutionResult::new(result)]);
+            if is_status_line_shown {
+                end_status_line(&terminal);
+                is_status_line_shown = false;
+            }
+
+            match result {
+                Ok(_) => {
+                    self.command_line.set_quiet(false);
+                    let output: String = if self.store_all_attempts.unwrap_or(false) {
+                        self.join_attempt_outputs(&outputs)
+                    } else {
+                        outputs.pop().unwrap_or_default()
+                    };
+                    return Ok(vec![
+                        ProgramExecutionResult::new(output).with_attempts(self.attempt_records.clone())
+                    ]);
                 },
                 Err(err) => {
                     // If retry number is set to 0,
                     // it should not display the retry messages.
-                    if self.retry == 0 {
+                    // A cancelled program is not retried either, nor
+                    // one that cannot be started, which fails the same
+                    // way on every attempt unless `retry_spawn_errors`,
+                    // nor one that crashed unless `retry_crashes`, nor
+                    // anything once cchain is asked to terminate
+                    let is_not_retried: bool = is_shutting_down() || match err.downcast_ref::<CommandLineError>() {
+                        Some(CommandLineError::Cancelled { .. }) => true,
+                        Some(CommandLineError::SpawnFailed { .. }) => !self.retry_spawn_errors.unwrap_or(false),
+                        Some(CommandLineError::NonZeroExit { termination: Some(termination), .. }) if termination.is_crash => {
+                            !self.retry_crashes.unwrap_or(false)
+                        }
+                        _ => false,
+                    };
+                    if self.retry == 0 || is_not_retried {
+                        if self.retry != 0 && !is_shutting_down() && matches!(err.downcast_ref::<CommandLineError>(), Some(CommandLineError::NonZeroExit { .. })) {
+                            display_message(Level::Warn, "The program crashed, which is not retried. Set `retry_crashes` to retry it.");
+                        }
+                        self.command_line.set_quiet(false);
                         return Err(err);
                     }
                     
@@ -232,16 +1359,87 @@ impl Execution<ProgramExecutionResult> for Program {
                     // -1 means unlimited retries.)
                     if self.retry == 0 || (self.retry != -1 && attempts >= self.retry) {
                         display_message(Level::Warn, "No more retries!");
-                        return Err(err);
+                        self.command_line.set_quiet(false);
+                        return Err(add_attempt_count(err, self.attempts));
                     }
                     
-                    let warn_msg: String = format!(
-                        "Retrying {}: {}. {} more retry...",
-                        self.get_execution_type(),
-                        &self,
-                        self.retry - attempts
-                    );
-                    display_message(Level::Warn, &warn_msg);
+                    let matched_pattern: Option<String> = match self.decide_retry(&err)? {
+                        RetryDecision::Retry(pattern) => pattern,
+                        RetryDecision::Stop(reason) => {
+                            display_message(Level::Warn, &format!("{}. No more retries!", reason));
+                            self.command_line.set_quiet(false);
+                            return Err(add_attempt_count(err, self.attempts));
+                        }
+                    };
+
+                    let reason: String = match matched_pattern {
+                        Some(pattern) => format!(" (matched `retry_on` pattern `{}`)", pattern),
+                        None => String::new(),
+                    };
+
+                    if self.retry != -1 {
+                        let warn_msg: String = format!(
+                            "Retrying {}: {}{}. {} more retry...",
+                            self.get_execution_type(),
+                            &self,
+                            reason,
+                            self.retry - attempts
+                        );
+                        display_message(Level::Warn, &warn_msg);
+                    } else {
+                        let failure: String = err.to_string();
+                        if terminal.is_term() && last_failure.as_ref() == Some(&failure) {
+                            // Same failure again, update the counter in place
+                            self.command_line.set_quiet(true);
+                            display_status_line(
+                                &terminal,
+                                &format!("Same failure again, retrying... (attempt {})", self.attempts),
+                            );
+                            is_status_line_shown = true;
+                        } else {
+                            self.command_line.set_quiet(false);
+                            display_message(
+                                Level::Warn,
+                                &format!(
+                                    "Retrying {}: {}: {}{}. Attempt {}, retrying indefinitely...",
+                                    self.get_execution_type(),
+                                    &self,
+                                    failure,
+                                    reason,
+                                    self.attempts
+                                ),
+                            );
+                        }
+                        last_failure = Some(failure);
+
+                        let escalation_attempts: u32 = self
+                            .retry_escalation_attempts
+                            .unwrap_or(DEFAULT_RETRY_ESCALATION_ATTEMPTS)
+                            .max(1);
+                        if self.attempts.is_multiple_of(escalation_attempts) {
+                            if is_status_line_shown {
+                                end_status_line(&terminal);
+                                is_status_line_shown = false;
+                            }
+                            if self.escalate_retries()? {
+                                self.command_line.set_quiet(false);
+                                return Err(anyhow!(
+                                    "Retrying is aborted after {} attempts: {}",
+                                    self.attempts,
+                                    err
+                                ));
+                            }
+                        }
+
+                        std::thread::sleep(INFINITE_RETRY_MIN_DELAY);
+                    }
+
+                    if let Some((event_sender, program_index)) = self.command_line.get_event_sender() {
+                        event_sender.send(ChainEvent::RetryScheduled {
+                            program_index: *program_index,
+                            attempt: self.attempts + 1,
+                        });
+                    }
 
                     // Increase attempt counter.
                     attempts += 1;
@@ -253,15 +1451,3 @@ impl Execution<ProgramExecutionResult> for Program {
     }
 }
 
-impl Default for Program {
-    fn default() -> Self {
-        Self {
-            command_line: CommandLine::default(),
-            stdout_stored_to: None,
-            stdout_storage_options: StdoutStorageOptions::default(),
-            failure_handling_options: FailureHandlingOptions::default(),
-            concurrency_group: None,
-            retry: 0,
-        }
-    }
-}
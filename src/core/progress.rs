@@ -0,0 +1,316 @@
+use std::{
+    collections::BTreeSet,
+    fs::File,
+    io::Write,
+    sync::{
+        mpsc::{channel, sync_channel, Receiver, RecvTimeoutError, SyncSender, TrySendError},
+        Arc, Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::marker::reference::TrackPath;
+
+use super::{
+    chain::Chain,
+    events::{ChainEvent, EventSender},
+};
+
+/// Version of the progress events, raised when they change in a way that
+/// breaks their readers. New fields and events do not raise it.
+pub const PROGRESS_EVENTS_VERSION: u32 = 1;
+
+/// Progress events waiting for a slow reader. Events beyond this are
+/// dropped and counted, rather than holding up the chain.
+pub const PROGRESS_BUFFER_CAPACITY: usize = 1024;
+
+/// How long the last events get to be written when the run is over
+const PROGRESS_FINISH_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How a program or a chain ended
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ProgressStatus {
+    Succeeded,
+    Failed,
+}
+
+impl From<bool> for ProgressStatus {
+    fn from(succeeded: bool) -> Self {
+        if succeeded {
+            Self::Succeeded
+        } else {
+            Self::Failed
+        }
+    }
+}
+
+/// The stable subset of the `ChainEvent`s, written for progress bars of
+/// other programs with `cchain run --progress-fd` or `--progress-file`
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProgressEvent {
+    ChainStarted {
+        chain: String,
+        total_steps: usize,
+    },
+    /// The first attempt of a program starts
+    ProgramStarted {
+        index: usize,
+        alias: Option<String>,
+    },
+    /// A program failed, and is attempted again
+    RetryScheduled {
+        index: usize,
+        alias: Option<String>,
+        /// The number of the next attempt, from 2 on
+        attempt: u32,
+    },
+    /// A program finished for good, after its retries
+    ProgramFinished {
+        index: usize,
+        alias: Option<String>,
+        status: ProgressStatus,
+        duration_ms: u64,
+    },
+    /// The last event, also written when the chain could not start
+    ChainFinished {
+        status: ProgressStatus,
+        /// Indices of the programs that failed
+        failures: Vec<usize>,
+        /// Events dropped because the reader was too slow
+        dropped_events: u64,
+    },
+}
+
+/// A line of the progress stream
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ProgressRecord {
+    pub version: u32,
+    #[serde(flatten)]
+    pub event: ProgressEvent,
+}
+
+impl ProgressRecord {
+    pub fn new(event: ProgressEvent) -> Self {
+        Self { version: PROGRESS_EVENTS_VERSION, event }
+    }
+}
+
+/// Turns the events of a chain into progress events
+#[derive(Debug, Clone, Default)]
+pub struct ProgressConverter {
+    chain_name: String,
+    aliases: Vec<Option<String>>,
+    /// When the programs started, by their indices
+    started_at: Vec<Option<Duration>>,
+    /// Programs already reported as finished, as a failure can be
+    /// reported by both the program and the chain
+    finished: BTreeSet<usize>,
+    failures: BTreeSet<usize>,
+}
+
+impl ProgressConverter {
+    pub fn new(chain_name: &str, aliases: Vec<Option<String>>) -> Self {
+        Self { chain_name: chain_name.to_string(), started_at: vec![None; aliases.len()], aliases, ..Default::default() }
+    }
+
+    fn get_alias(&self, index: usize) -> Option<String> {
+        self.aliases.get(index).cloned().flatten()
+    }
+
+    /// Convert an event that happened at a time since the start of the run
+    ///
+    /// # Returns
+    ///
+    /// The progress event, or `None` for events that are not reported,
+    /// e.g. outputs, the starts of retries, and programs that finished
+    /// already
+    pub fn convert(&mut self, event: &ChainEvent, at: Duration) -> Option<ProgressEvent> {
+        match event {
+            ChainEvent::ChainStarted { program_labels } => {
+                Some(ProgressEvent::ChainStarted { chain: self.chain_name.clone(), total_steps: program_labels.len() })
+            }
+            ChainEvent::ProgramStarted { program_index } => {
+                if self.started_at.len() <= *program_index {
+                    self.started_at.resize(program_index + 1, None);
+                }
+                if self.started_at[*program_index].is_some() {
+                    return None;
+                }
+                self.started_at[*program_index] = Some(at);
+                Some(ProgressEvent::ProgramStarted { index: *program_index, alias: self.get_alias(*program_index) })
+            }
+            ChainEvent::RetryScheduled { program_index, attempt } => Some(ProgressEvent::RetryScheduled {
+                index: *program_index,
+                alias: self.get_alias(*program_index),
+                attempt: *attempt,
+            }),
+            ChainEvent::ProgramFinished { program_index, succeeded } => {
+                if !self.finished.insert(*program_index) {
+                    return None;
+                }
+                if !succeeded {
+                    self.failures.insert(*program_index);
+                }
+                let started_at: Duration =
+                    self.started_at.get(*program_index).copied().flatten().unwrap_or(at);
+                Some(ProgressEvent::ProgramFinished {
+                    index: *program_index,
+                    alias: self.get_alias(*program_index),
+                    status: ProgressStatus::from(*succeeded),
+                    duration_ms: at.saturating_sub(started_at).as_millis() as u64,
+                })
+            }
+            ChainEvent::ChainFinished { succeeded } => Some(self.finish(*succeeded, 0)),
+            ChainEvent::ProgramOutput { .. } | ChainEvent::ProcessExited { .. } | ChainEvent::Message { .. } => None,
+        }
+    }
+
+    /// Build the last event. A chain that kept going after failures has
+    /// failed too.
+    pub fn finish(&self, succeeded: bool, dropped_events: u64) -> ProgressEvent {
+        ProgressEvent::ChainFinished {
+            status: ProgressStatus::from(succeeded && self.failures.is_empty()),
+            failures: self.failures.iter().copied().collect(),
+            dropped_events,
+        }
+    }
+}
+
+/// Writes the progress of a chain as lines of JSON, e.g. to a file
+/// descriptor that a wrapper reads, without ever holding up the chain
+pub struct ProgressReporter {
+    /// The outcome of the run, once it is over
+    finished_outcome: Arc<Mutex<Option<bool>>>,
+    is_written: Receiver<()>,
+}
+
+impl ProgressReporter {
+    /// Report the progress of the chain to the writer
+    pub fn start(chain: &mut Chain, writer: Box<dyn Write + Send>) -> Self {
+        Self::start_with_capacity(chain, writer, PROGRESS_BUFFER_CAPACITY)
+    }
+
+    /// Report the progress with room for this many events waiting for
+    /// the writer
+    pub fn start_with_capacity(chain: &mut Chain, writer: Box<dyn Write + Send>, capacity: usize) -> Self {
+        let aliases: Vec<Option<String>> = chain
+            .get_programs()
+            .iter()
+            .map(|program| program.lock().unwrap().get_alias().cloned())
+            .collect();
+        let converter = ProgressConverter::new(chain.get_path(), aliases);
+
+        let (event_sender, event_receiver) = channel();
+        chain.set_event_sender(EventSender::new(event_sender));
+        let (record_sender, record_receiver) = sync_channel::<ProgressRecord>(capacity);
+        let (written_sender, is_written) = channel();
+        let finished_outcome: Arc<Mutex<Option<bool>>> = Arc::new(Mutex::new(None));
+
+        let relay_finished_outcome: Arc<Mutex<Option<bool>>> = finished_outcome.clone();
+        thread::spawn(move || relay_events(converter, event_receiver, record_sender, relay_finished_outcome));
+        thread::spawn(move || {
+            write_records(writer, record_receiver);
+            let _ = written_sender.send(());
+        });
+
+        Self { finished_outcome, is_written }
+    }
+
+    /// Write the last event, and wait a moment for the reader to take
+    /// the events left. A chain that did not get to start is reported
+    /// as finished with the outcome.
+    pub fn finish(self, succeeded: bool) {
+        *self.finished_outcome.lock().unwrap() = Some(succeeded);
+        let _ = self.is_written.recv_timeout(PROGRESS_FINISH_TIMEOUT);
+    }
+}
+
+/// Convert the events of the chain and pass them to the writer. Events
+/// are dropped while the writer is behind, except the last one.
+fn relay_events(
+    mut converter: ProgressConverter,
+    event_receiver: Receiver<ChainEvent>,
+    record_sender: SyncSender<ProgressRecord>,
+    finished_outcome: Arc<Mutex<Option<bool>>>,
+) {
+    let started_at: Instant = Instant::now();
+    let mut dropped_events: u64 = 0;
+    let last_event: ProgressEvent = loop {
+        let event: ChainEvent = match event_receiver.recv_timeout(Duration::from_millis(50)) {
+            Ok(event) => event,
+            Err(RecvTimeoutError::Timeout) => match *finished_outcome.lock().unwrap() {
+                Some(succeeded) => break converter.finish(succeeded, dropped_events),
+                None => continue,
+            },
+            Err(RecvTimeoutError::Disconnected) => break converter.finish(false, dropped_events),
+        };
+        let progress_event: ProgressEvent = match converter.convert(&event, started_at.elapsed()) {
+            Some(ProgressEvent::ChainFinished { .. }) => {
+                let succeeded: bool = matches!(event, ChainEvent::ChainFinished { succeeded: true });
+                break converter.finish(succeeded, dropped_events);
+            }
+            Some(progress_event) => progress_event,
+            None => continue,
+        };
+        if let Err(TrySendError::Full(_)) = record_sender.try_send(ProgressRecord::new(progress_event)) {
+            dropped_events += 1;
+        }
+    };
+
+    let _ = record_sender.send(ProgressRecord::new(last_event));
+}
+
+/// Write each record on a line. Once the reader is gone, the records are
+/// still taken, so that the chain is never held up.
+fn write_records(mut writer: Box<dyn Write + Send>, record_receiver: Receiver<ProgressRecord>) {
+    let mut is_reader_gone: bool = false;
+    for record in record_receiver {
+        if is_reader_gone {
+            continue;
+        }
+        let line: String = match serde_json::to_string(&record) {
+            Ok(line) => line + "\n",
+            Err(_) => continue,
+        };
+        if writer.write_all(line.as_bytes()).and_then(|_| writer.flush()).is_err() {
+            is_reader_gone = true;
+        }
+    }
+}
+
+/// Open a file descriptor that the parent process passed for the
+/// progress events. The standard streams are refused, so that the events
+/// are not mixed into the output or read from the terminal.
+#[cfg(unix)]
+pub fn open_progress_fd(fd: i32) -> Result<File, Error> {
+    use std::os::fd::FromRawFd;
+
+    if fd < 3 {
+        return Err(anyhow!(
+            "`--progress-fd {}` is a standard stream. Pass a descriptor of 3 or more, e.g. `--progress-fd 3 3>progress.jsonl`",
+            fd
+        ));
+    }
+    // Taking over a descriptor that is not open would close another file
+    // opened later with the same number
+    if unsafe { libc::fcntl(fd, libc::F_GETFD) } == -1 {
+        return Err(anyhow!("File descriptor {} is not open", fd));
+    }
+
+    Ok(unsafe { File::from_raw_fd(fd) })
+}
+
+/// File descriptors are not passed this way on this platform
+#[cfg(not(unix))]
+pub fn open_progress_fd(fd: i32) -> Result<File, Error> {
+    Err(anyhow!(
+        "`--progress-fd {}` is only supported on Unix, use `--progress-file` instead",
+        fd
+    ))
+}
@@ -0,0 +1,197 @@
+/// Bytes of an unfinished escape sequence held back between chunks of
+/// output. A longer sequence is passed on as it is, so that a stray
+/// escape character cannot hold back the rest of the output.
+pub const MAX_PENDING_ESCAPE_BYTES: usize = 256;
+
+const ESCAPE: char = '\u{1b}';
+const BELL: char = '\u{7}';
+
+/// Get the length in bytes of the escape sequence at the start of the
+/// bytes, e.g. `\x1b[1;32m` or `\x1b]0;title\x07`. A sequence always
+/// ends at an ASCII byte, so the text around it stays valid.
+///
+/// # Returns
+///
+/// None when the bytes end before the sequence does
+pub fn get_escape_sequence_length(bytes: &[u8]) -> Option<usize> {
+    debug_assert_eq!(bytes.first(), Some(&0x1b));
+    match *bytes.get(1)? {
+        // Control sequence: parameters, intermediates, then a final byte
+        b'[' => {
+            for (offset, byte) in bytes.iter().enumerate().skip(2) {
+                match byte {
+                    0x20..=0x3f => continue,
+                    0x40..=0x7e => return Some(offset + 1),
+                    // A malformed sequence ends before the unexpected byte
+                    _ => return Some(offset),
+                }
+            }
+            None
+        }
+        // Strings ended by a bell or by `\x1b\`, e.g. a window title
+        b']' | b'P' | b'X' | b'^' | b'_' => {
+            for (offset, byte) in bytes.iter().enumerate().skip(2) {
+                match byte {
+                    0x07 => return Some(offset + 1),
+                    0x1b => {
+                        return match bytes.get(offset + 1) {
+                            Some(b'\\') => Some(offset + 2),
+                            Some(_) => Some(offset),
+                            None => None,
+                        }
+                    }
+                    _ => continue,
+                }
+            }
+            None
+        }
+        // Intermediates then a final byte, e.g. a character set `\x1b(B`
+        0x20..=0x2f => {
+            for (offset, byte) in bytes.iter().enumerate().skip(2) {
+                match byte {
+                    0x20..=0x2f => continue,
+                    0x30..=0x7e => return Some(offset + 1),
+                    _ => return Some(offset),
+                }
+            }
+            None
+        }
+        // A single character, e.g. saving the cursor with `\x1b7`. Any
+        // other byte, e.g. a newline, is not part of the sequence.
+        0x30..=0x7e => Some(2),
+        _ => Some(1),
+    }
+}
+
+/// Remove the escape sequences for colors, cursor movements and window
+/// titles, and the bell. An unfinished sequence at the end is removed
+/// with the rest of the text.
+pub fn strip_ansi_sequences(text: &str) -> String {
+    strip_sequences(text, |_| false)
+}
+
+/// Check whether an escape sequence erases the line, e.g. `\x1b[K` or
+/// `\x1b[2K`
+fn is_erase_line_sequence(sequence: &str) -> bool {
+    sequence.starts_with("\x1b[") && sequence.ends_with('K')
+}
+
+/// Remove the escape sequences and the bell, except the sequences for
+/// which `keep` holds
+fn strip_sequences(text: &str, keep: impl Fn(&str) -> bool) -> String {
+    let mut stripped: String = String::with_capacity(text.len());
+    let mut rest: &str = text;
+    while let Some(start) = rest.find([ESCAPE, BELL]) {
+        stripped.push_str(&rest[..start]);
+        rest = &rest[start..];
+        if rest.starts_with(BELL) {
+            rest = &rest[1..];
+            continue;
+        }
+        match get_escape_sequence_length(rest.as_bytes()) {
+            Some(length) => {
+                if keep(&rest[..length]) {
+                    stripped.push_str(&rest[..length]);
+                }
+                rest = &rest[length..];
+            }
+            None => return stripped,
+        }
+    }
+    stripped.push_str(rest);
+
+    stripped
+}
+
+/// Keep what a terminal would show of each line that is rewritten with
+/// carriage returns, e.g. the last state of a progress bar. Line endings
+/// are made `\n`, and the text after the last carriage return of a line
+/// is kept, or the text before it if a line ends with one.
+pub fn collapse_carriage_returns(text: &str) -> String {
+    if !text.contains('\r') {
+        return text.to_string();
+    }
+
+    text.replace("\r\n", "\n")
+        .split('\n')
+        .map(|line| {
+            line.rsplit('\r')
+                .find(|segment| !segment.is_empty())
+                .unwrap_or("")
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// Get the last characters of a text, e.g. the end of the output of a
+/// failed program, which usually has the reason of the failure
+pub fn get_tail(text: &str, max_characters: usize) -> &str {
+    let start: usize = text
+        .char_indices()
+        .rev()
+        .nth(max_characters.saturating_sub(1))
+        .map_or(0, |(index, _)| index);
+    &text[start..]
+}
+
+/// Make the output of a program plain text to store: the escape
+/// sequences are removed, and the rewritten lines are collapsed. The
+/// sequences erasing a line are only removed once the lines are
+/// collapsed, so that a line erased after its last carriage return ends
+/// up empty, as on a terminal.
+pub fn sanitize_output(text: &str) -> String {
+    let without_styles: String = strip_sequences(text, is_erase_line_sequence);
+    strip_ansi_sequences(&collapse_carriage_returns(&without_styles))
+}
+
+/// Turns the chunks of bytes read from a program into text to display,
+/// holding back a character or an escape sequence split between chunks
+/// until the rest of it is read
+#[derive(Debug, Default)]
+pub struct OutputChunkBuffer {
+    pending: Vec<u8>,
+}
+
+impl OutputChunkBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take a chunk of output
+    ///
+    /// # Returns
+    ///
+    /// The text that can be displayed now, which may be empty
+    pub fn push(&mut self, chunk: &[u8]) -> String {
+        self.pending.extend_from_slice(chunk);
+
+        // Hold back the start of a character split by the chunk
+        let mut shown: usize = match std::str::from_utf8(&self.pending) {
+            Err(error) if error.error_len().is_none() => error.valid_up_to(),
+            _ => self.pending.len(),
+        };
+        // Hold back the start of an escape sequence split by the chunk
+        if let Some(start) = self.pending[..shown].iter().rposition(|byte| *byte == 0x1b) {
+            if shown - start < MAX_PENDING_ESCAPE_BYTES && get_escape_sequence_length(&self.pending[start..shown]).is_none() {
+                shown = start;
+            }
+        }
+
+        let text: String = String::from_utf8_lossy(&self.pending[..shown]).to_string();
+        self.pending.drain(..shown);
+
+        text
+    }
+
+    /// Take the end of the output
+    ///
+    /// # Returns
+    ///
+    /// The text held back, as it is
+    pub fn finish(&mut self) -> String {
+        let text: String = String::from_utf8_lossy(&self.pending).to_string();
+        self.pending.clear();
+
+        text
+    }
+}
@@ -0,0 +1,584 @@
+use serde_json::{json, Map, Value};
+
+/// The dialect of the published schema
+pub const SCHEMA_DIALECT: &str = "https://json-schema.org/draft/2020-12/schema";
+
+/// A place in a chain file that does not conform to the schema
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SchemaViolation {
+    /// JSON pointer to the offending value, e.g. `/programs/0/retry`
+    pub pointer: String,
+    pub message: String,
+}
+
+impl std::fmt::Display for SchemaViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let pointer: &str = if self.pointer.is_empty() { "/" } else { &self.pointer };
+        write!(f, "`{}`: {}", pointer, self.message)
+    }
+}
+
+/// A value of the type, or null for absent optional fields
+fn nullable(schema_type: &str) -> Value {
+    json!({ "type": [schema_type, "null"] })
+}
+
+/// Fields of a command line, which are flattened into programs,
+/// remedy command lines and ready checks
+fn command_line_properties() -> Map<String, Value> {
+    let properties: Value = json!({
+        "command": { "type": "string", "description": "The path or name of the program to execute" },
+        "arguments": {
+            "type": "array",
+            "description": "Arguments passed to the program. `{\"raw\": \"...\"}` is handed to the interpreter as is",
+            "items": { "$ref": "#/$defs/argument" }
+        },
+        "interpreter": {
+            "description": "The interpreter that runs the command line",
+            "enum": ["sh", "Sh", null]
+        },
+        "environment_variables_override": {
+            "type": ["object", "null"],
+            "description": "Environment variables set for the program. A null value removes the variable",
+            "additionalProperties": { "type": ["string", "null"] }
+        },
+        "working_directory": nullable("string"),
+        "run_as": nullable("string"),
+    });
+
+    match properties {
+        Value::Object(properties) => properties,
+        _ => unreachable!(),
+    }
+}
+
+fn command_line_schema(extra_properties: Value) -> Value {
+    let mut properties: Map<String, Value> = command_line_properties();
+    if let Value::Object(extra_properties) = extra_properties {
+        properties.extend(extra_properties);
+    }
+
+    json!({
+        "type": "object",
+        "required": ["command", "arguments"],
+        "properties": properties,
+        "additionalProperties": false
+    })
+}
+
+fn string_patterns() -> Value {
+    json!({ "type": ["array", "null"], "items": { "type": "string" } })
+}
+
+fn program_schema() -> Value {
+    let mut schema: Value = command_line_schema(json!({
+        "stdout_stored_to": {
+            "type": ["string", "null"],
+            "description": "The variable to store the stdout of the program in, as exactly one placeholder without a qualifier, e.g. `<<output>>`"
+        },
+        "stdout_storage_options": {
+            "type": "object",
+            "required": ["without_newline_characters"],
+            "properties": {
+                "without_newline_characters": { "type": "boolean" },
+                "capture_filter": { "type": ["string", "null"], "description": "Store only the lines matching this regular expression" },
+                "capture_group": { "type": ["integer", "null"], "minimum": 0 },
+                "required_capture": { "type": "boolean" },
+                "preserve_ansi": { "type": "boolean", "description": "Store the output with its escape sequences and carriage returns" }
+            },
+            "additionalProperties": false
+        },
+        "failure_handling_options": {
+            "type": "object",
+            "required": ["exit_on_failure"],
+            "properties": {
+                "exit_on_failure": { "type": "boolean" },
+                "remedy_command_line": {
+                    "anyOf": [{ "type": "null" }, { "$ref": "#/$defs/command_line" }]
+                }
+            },
+            "additionalProperties": false
+        },
+        "concurrency_group": { "type": ["integer", "null"], "minimum": 0 },
+        "retry": {
+            "type": "integer",
+            "minimum": -1,
+            "description": "Number of retries. -1 retries indefinitely"
+        },
+        "retry_on": string_patterns(),
+        "fail_fast_on": string_patterns(),
+        "retry_escalation_attempts": { "type": ["integer", "null"], "minimum": 0 },
+        "service": nullable("boolean"),
+        "ready_check": {
+            "anyOf": [{ "type": "null" }, { "$ref": "#/$defs/ready_check" }]
+        },
+        "assertions": {
+            "type": ["array", "null"],
+            "items": { "$ref": "#/$defs/assertion" }
+        },
+        "always_run": nullable("boolean"),
+        "store_all_attempts": nullable("boolean"),
+        "retry_spawn_errors": nullable("boolean"),
+        "retry_crashes": nullable("boolean"),
+        "stream_to_next": nullable("boolean"),
+        "alias": {
+            "type": ["string", "null"],
+            "description": "A name that chains extending this chain use to override or remove the program"
+        },
+        "effects": {
+            "type": ["array", "null"],
+            "items": {
+                "type": "string",
+                "examples": ["read-only", "writes-files", "deletes-files", "network", "mutates-remote", "privileged"]
+            },
+            "description": "What the program does to its surroundings. `deletes-files` and `mutates-remote` need `--allow-destructive` or a confirmation"
+        },
+        "display_output": {
+            "enum": ["full", "summary", "silent", null],
+            "description": "How much of the output is shown as the program runs. `summary` shows the first and the last 10 lines. Stored variables always have the full output"
+        },
+        "git": {
+            "$ref": "#/$defs/git_operation",
+            "description": "A git operation run through git2 instead of the command, whose result is the output of the program"
+        },
+        "matrix": {
+            "type": ["object", "null"],
+            "additionalProperties": { "type": "array", "items": { "type": "string" }, "minItems": 1 },
+            "description": "Values to expand the program over, into a program per combination, e.g. `{\"os\": [\"linux\", \"macos\"]}`. `<<matrix.os>>` is replaced with the values in the arguments, the environment overrides, the working directory and the remedy"
+        },
+        "matrix_parallel": {
+            "type": ["boolean", "null"],
+            "description": "Run the programs expanded from `matrix` together, in a concurrency group of their own"
+        },
+    }));
+    schema["required"] = json!(["command", "arguments", "retry"]);
+
+    schema
+}
+
+/// Programs that run a git operation, which need no command
+fn git_step_schema() -> Value {
+    let mut schema: Value = program_schema();
+    schema["required"] = json!(["git"]);
+
+    schema
+}
+
+fn git_operation_schema() -> Value {
+    let operation = |name: &str, properties: Value, required: Value| {
+        let mut properties = properties;
+        properties["op"] = json!({ "const": name });
+        let mut required = required;
+        required.as_array_mut().unwrap().insert(0, json!("op"));
+        json!({ "type": "object", "required": required, "properties": properties, "additionalProperties": false })
+    };
+
+    json!({
+        "oneOf": [
+            operation("clone", json!({
+                "url": { "type": "string" },
+                "path": { "type": "string", "description": "Where to clone, relative to the chain" },
+                "branch": nullable("string"),
+                "depth": { "type": ["integer", "null"], "minimum": 1 }
+            }), json!(["url", "path"])),
+            operation("checkout", json!({ "ref": { "type": "string" } }), json!(["ref"])),
+            operation("pull", json!({}), json!([])),
+            operation("rev_parse", json!({
+                "ref": { "type": "string", "default": "HEAD" },
+                "short": { "type": "boolean" }
+            }), json!([])),
+            operation("current_branch", json!({}), json!([]))
+        ]
+    })
+}
+
+fn assertion_schema() -> Value {
+    let variants: Vec<Value> = ["contains", "not_contains", "matches_regex", "equals_file"]
+        .iter()
+        .map(|variant| {
+            json!({
+                "type": "object",
+                "required": [variant],
+                "properties": { *variant: { "type": "string" } },
+                "additionalProperties": false
+            })
+        })
+        .collect();
+
+    json!({ "oneOf": variants })
+}
+
+fn metadata_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "variables": {
+                "type": "object",
+                "additionalProperties": { "$ref": "#/$defs/variable_declaration" }
+            },
+            "requires_cchain": { "type": ["string", "null"], "description": "A semver requirement, e.g. `>=0.4.0`" },
+            "strict": { "type": "boolean" },
+            "escalation_command": nullable("string"),
+            "tags": { "type": "array", "items": { "type": "string" } },
+            "on_member_failure": { "enum": ["wait_all", "fail_fast", null] },
+            "concurrency_groups": {
+                "type": "object",
+                "description": "Settings of the concurrency groups, keyed by the group numbers",
+                "additionalProperties": {
+                    "type": "object",
+                    "properties": { "on_member_failure": { "enum": ["wait_all", "fail_fast", null] } },
+                    "additionalProperties": false
+                }
+            },
+            "extends": { "type": ["string", "null"], "description": "The chain file this chain builds on, relative to this file" },
+            "base_directory": { "type": ["string", "null"], "description": "The directory that relative paths in the chain resolve against, relative to this file" },
+            "timezone": { "type": ["string", "null"], "description": "The IANA time zone of every program, set as `TZ`" },
+            "locale": { "type": ["string", "null"], "description": "The locale of every program, set as `LANG` and `LC_ALL`" },
+            "limits": {
+                "type": ["object", "null"],
+                "description": "Limits that the chain is known to exceed, so that it is loaded anyway",
+                "properties": {
+                    "max_programs": { "type": "integer", "minimum": 0 },
+                    "max_arguments": { "type": "integer", "minimum": 0 },
+                    "max_placeholders": { "type": "integer", "minimum": 0 }
+                },
+                "additionalProperties": false
+            },
+            "variable_delimiters": {
+                "type": ["object", "null"],
+                "description": "The delimiters of the placeholders, instead of `<<` and `>>`",
+                "properties": {
+                    "open": { "type": "string", "minLength": 1 },
+                    "close": { "type": "string", "minLength": 1 }
+                },
+                "required": ["open", "close"],
+                "additionalProperties": false
+            },
+            "load_adjacent_env": { "type": "boolean", "description": "Load default values of variables from `.cchain.env` next to the chain" },
+            "keep_tmp": { "type": "boolean", "description": "Keep the temporary directory of each run, `<<chain_tmp>>`, instead of removing it when the chain finishes" },
+            "lazy_prompts": { "type": "boolean", "description": "Ask for each startup variable right before the first program using it starts, instead of before the chain starts" },
+            "generated_by": {
+                "type": ["object", "null"],
+                "description": "How the chain was generated by `cchain new --prompt`. Ignored when running",
+                "required": ["tool", "provider", "model", "generated_at", "prompt_sha256", "programs_sha256"],
+                "properties": {
+                    "tool": { "type": "string" },
+                    "provider": { "type": "string" },
+                    "model": { "type": "string" },
+                    "generated_at": { "type": "integer", "minimum": 0 },
+                    "prompt_sha256": { "type": "string" },
+                    "prompt": nullable("string"),
+                    "programs_sha256": { "type": "string" }
+                },
+                "additionalProperties": false
+            }
+        },
+        "additionalProperties": false
+    })
+}
+
+fn variable_declaration_schema() -> Value {
+    json!({
+        "type": "object",
+        "properties": {
+            "type": { "enum": ["string", "integer", "boolean"] },
+            "min": nullable("integer"),
+            "max": nullable("integer"),
+            "description": nullable("string"),
+            "example": nullable("string"),
+            "human_readable_name": nullable("string"),
+            "secret": { "type": "boolean", "description": "Keep the value out of debug shells unless `--debug-expose-secrets` is given" },
+            "no_preset": { "type": "boolean", "description": "Never use the preset of the variable set with `cchain preset`" }
+        },
+        "additionalProperties": false
+    })
+}
+
+/// The JSON Schema of chain files
+pub fn get_chain_file_schema() -> Value {
+    json!({
+        "$schema": SCHEMA_DIALECT,
+        "title": "cchain chain file",
+        "description": "A plain array of programs, or an object with a `metadata` section next to the `programs` array",
+        "oneOf": [
+            { "$ref": "#/$defs/programs" },
+            {
+                "type": "object",
+                "required": ["programs"],
+                "properties": {
+                    "$schema": { "type": "string" },
+                    "metadata": { "$ref": "#/$defs/metadata" },
+                    "programs": { "$ref": "#/$defs/programs" },
+                    "verify": { "$ref": "#/$defs/programs" }
+                },
+                "additionalProperties": false
+            }
+        ],
+        "$defs": {
+            "programs": {
+                "type": "array",
+                "items": { "anyOf": [{ "$ref": "#/$defs/program" }, { "$ref": "#/$defs/git_step" }, { "$ref": "#/$defs/builtin_step" }] }
+            },
+            "program": program_schema(),
+            "git_step": git_step_schema(),
+            "git_operation": git_operation_schema(),
+            "builtin_step": {
+                "type": "object",
+                "required": ["builtin"],
+                "properties": {
+                    "builtin": {
+                        "enum": ["debug_variables"],
+                        "description": "A step built into cchain, e.g. `debug_variables` to show the variables of the chain"
+                    }
+                },
+                "additionalProperties": false
+            },
+            "command_line": command_line_schema(json!({})),
+            "ready_check": command_line_schema(json!({
+                "interval_ms": { "type": "integer", "minimum": 0 },
+                "timeout_seconds": { "type": "integer", "minimum": 0 },
+                "store_output": {
+                    "type": "boolean",
+                    "description": "Store the stdout of the successful probe in `stdout_stored_to`, instead of the output of the service"
+                }
+            })),
+            "argument": {
+                "oneOf": [
+                    { "type": "string" },
+                    {
+                        "type": "object",
+                        "required": ["raw"],
+                        "properties": { "raw": { "type": "string" } },
+                        "additionalProperties": false
+                    }
+                ]
+            },
+            "assertion": assertion_schema(),
+            "metadata": metadata_schema(),
+            "variable_declaration": variable_declaration_schema()
+        }
+    })
+}
+
+/// An event of the progress stream, with its fields besides `version`
+/// and `event`
+fn progress_event_schema(event: &str, properties: Value) -> Value {
+    let mut all_properties: Map<String, Value> = Map::new();
+    all_properties.insert("version".to_string(), json!({ "type": "integer", "minimum": 1 }));
+    all_properties.insert("event".to_string(), json!({ "enum": [event] }));
+    let mut required: Vec<String> = vec!["version".to_string(), "event".to_string()];
+    if let Value::Object(properties) = properties {
+        required.extend(properties.keys().filter(|name| *name != "alias").cloned());
+        all_properties.extend(properties);
+    }
+
+    json!({
+        "type": "object",
+        "required": required,
+        "properties": all_properties
+    })
+}
+
+/// Get the JSON Schema of the lines that `cchain run --progress-fd`
+/// writes. Readers should ignore events and fields they do not know.
+pub fn get_progress_event_schema() -> Value {
+    let status: Value = json!({ "enum": ["succeeded", "failed"] });
+    let index: Value = json!({ "type": "integer", "minimum": 0 });
+
+    json!({
+        "$schema": SCHEMA_DIALECT,
+        "title": "cchain progress event",
+        "description": "A line of JSON written by `cchain run --progress-fd` or `--progress-file`. `version` is raised when the events change in a way that breaks their readers",
+        "oneOf": [
+            progress_event_schema("chain_started", json!({
+                "chain": { "type": "string" },
+                "total_steps": { "type": "integer", "minimum": 0 }
+            })),
+            progress_event_schema("program_started", json!({
+                "index": index,
+                "alias": nullable("string")
+            })),
+            progress_event_schema("retry_scheduled", json!({
+                "index": index,
+                "alias": nullable("string"),
+                "attempt": { "type": "integer", "minimum": 2, "description": "The number of the next attempt" }
+            })),
+            progress_event_schema("program_finished", json!({
+                "index": index,
+                "alias": nullable("string"),
+                "status": status,
+                "duration_ms": { "type": "integer", "minimum": 0 }
+            })),
+            progress_event_schema("chain_finished", json!({
+                "status": status,
+                "failures": { "type": "array", "items": index },
+                "dropped_events": {
+                    "type": "integer",
+                    "minimum": 0,
+                    "description": "Events left out because the reader was too slow"
+                }
+            }))
+        ]
+    })
+}
+
+/// Escape a key for a JSON pointer
+fn escape_pointer_token(token: &str) -> String {
+    token.replace('~', "~0").replace('/', "~1")
+}
+
+fn get_type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(number) if number.is_i64() || number.is_u64() => "integer",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
+
+fn matches_type(value: &Value, schema_type: &str) -> bool {
+    let type_name: &str = get_type_name(value);
+    type_name == schema_type || (schema_type == "number" && type_name == "integer")
+}
+
+/// Validates instances against the subset of JSON Schema used by
+/// `get_chain_file_schema`
+struct SchemaValidator<'a> {
+    root: &'a Value,
+}
+
+impl SchemaValidator<'_> {
+    fn resolve<'b>(&'b self, schema: &'b Value) -> &'b Value {
+        match schema.get("$ref").and_then(Value::as_str) {
+            Some(reference) => {
+                let pointer: &str = reference.trim_start_matches('#');
+                self.resolve(self.root.pointer(pointer).unwrap_or(&Value::Bool(true)))
+            }
+            None => schema,
+        }
+    }
+
+    fn validate(&self, value: &Value, schema: &Value, pointer: &str, violations: &mut Vec<SchemaViolation>) {
+        let schema: &Value = self.resolve(schema);
+        let mut violate = |message: String| {
+            violations.push(SchemaViolation { pointer: pointer.to_string(), message })
+        };
+
+        if let Some(schema_type) = schema.get("type") {
+            let schema_types: Vec<&str> = match schema_type {
+                Value::Array(schema_types) => schema_types.iter().filter_map(Value::as_str).collect(),
+                _ => schema_type.as_str().into_iter().collect(),
+            };
+            if !schema_types.iter().any(|schema_type| matches_type(value, schema_type)) {
+                violate(format!("expected {}, found {}", schema_types.join(" or "), get_type_name(value)));
+                return;
+            }
+        }
+
+        if let Some(Value::Array(variants)) = schema.get("enum") {
+            if !variants.contains(value) {
+                let variants: Vec<String> = variants.iter().map(Value::to_string).collect();
+                violate(format!("expected one of {}, found {}", variants.join(", "), value));
+                return;
+            }
+        }
+
+        if let (Some(minimum), Some(number)) = (schema.get("minimum").and_then(Value::as_i64), value.as_i64()) {
+            if number < minimum {
+                violate(format!("{} is smaller than the minimum {}", number, minimum));
+            }
+        }
+
+        if let Some(Value::Array(variants)) = schema.get("anyOf").or_else(|| schema.get("oneOf")) {
+            self.validate_variants(value, variants, pointer, violations);
+        }
+
+        if let Value::Object(object) = value {
+            self.validate_object(object, schema, pointer, violations);
+        }
+
+        if let (Value::Array(items), Some(items_schema)) = (value, schema.get("items")) {
+            for (index, item) in items.iter().enumerate() {
+                self.validate(item, items_schema, &format!("{}/{}", pointer, index), violations);
+            }
+        }
+    }
+
+    /// The value must match one of the variants. When none matches, the
+    /// violations of the closest variant are reported, as they are the
+    /// most likely to be the intended ones.
+    fn validate_variants(&self, value: &Value, variants: &[Value], pointer: &str, violations: &mut Vec<SchemaViolation>) {
+        let mut closest_violations: Option<Vec<SchemaViolation>> = None;
+        for variant in variants {
+            let mut variant_violations: Vec<SchemaViolation> = Vec::new();
+            self.validate(value, variant, pointer, &mut variant_violations);
+            if variant_violations.is_empty() {
+                return;
+            }
+
+            let is_closer: bool = match &closest_violations {
+                Some(closest_violations) => {
+                    Self::rank_violations(&variant_violations, pointer) < Self::rank_violations(closest_violations, pointer)
+                }
+                None => true,
+            };
+            if is_closer {
+                closest_violations = Some(variant_violations);
+            }
+        }
+
+        violations.extend(closest_violations.unwrap_or_default());
+    }
+
+    /// A value of another type means the variant is of another shape,
+    /// which is further than any number of problems inside the value
+    fn rank_violations(violations: &[SchemaViolation], pointer: &str) -> (bool, usize) {
+        let is_shape_mismatch: bool = violations
+            .iter()
+            .any(|violation| violation.pointer == pointer && violation.message.starts_with("expected"));
+        (is_shape_mismatch, violations.len())
+    }
+
+    fn validate_object(&self, object: &Map<String, Value>, schema: &Value, pointer: &str, violations: &mut Vec<SchemaViolation>) {
+        for required in schema.get("required").and_then(Value::as_array).into_iter().flatten() {
+            if let Some(required) = required.as_str() {
+                if !object.contains_key(required) {
+                    violations.push(SchemaViolation {
+                        pointer: pointer.to_string(),
+                        message: format!("missing required field `{}`", required),
+                    });
+                }
+            }
+        }
+
+        let properties: Option<&Map<String, Value>> = schema.get("properties").and_then(Value::as_object);
+        for (key, value) in object {
+            let value_pointer: String = format!("{}/{}", pointer, escape_pointer_token(key));
+            match (properties.and_then(|properties| properties.get(key)), schema.get("additionalProperties")) {
+                (Some(property_schema), _) => self.validate(value, property_schema, &value_pointer, violations),
+                (None, Some(Value::Bool(false))) => violations.push(SchemaViolation {
+                    pointer: value_pointer,
+                    message: format!("unknown field `{}`", key),
+                }),
+                (None, Some(additional_schema @ Value::Object(_))) => {
+                    self.validate(value, additional_schema, &value_pointer, violations)
+                }
+                (None, _) => (),
+            }
+        }
+    }
+}
+
+/// Validate a JSON value against a schema, reporting each violation at
+/// its JSON pointer. Only the keywords used by `get_chain_file_schema`
+/// are supported.
+pub fn validate_against_schema(value: &Value, schema: &Value) -> Vec<SchemaViolation> {
+    let validator = SchemaValidator { root: schema };
+    let mut violations: Vec<SchemaViolation> = Vec::new();
+    validator.validate(value, schema, "", &mut violations);
+
+    violations
+}
@@ -0,0 +1,92 @@
+use std::{
+    path::{Path, PathBuf},
+    sync::Mutex,
+};
+
+use anyhow::{anyhow, Error};
+
+/// The variable holding the temporary directory of the running chain,
+/// which is always available without a declaration
+pub const CHAIN_TMP_VARIABLE_NAME: &str = "chain_tmp";
+
+/// Overrides the directory that the temporary directories of chains are
+/// created in, which is the temporary directory of the system by default
+pub const CHAIN_TMP_BASE_ENVIRONMENT_VARIABLE: &str = "CCHAIN_TMPDIR";
+
+/// Temporary directories that are removed when cchain is interrupted
+static ACTIVE_SCRATCH_DIRECTORIES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// A temporary directory that lives as long as a run of a chain
+#[derive(Debug, PartialEq, Eq)]
+pub struct ScratchDirectory {
+    path: PathBuf,
+    /// Keep the directory after the run, e.g. with `--keep-tmp`
+    is_kept: bool,
+}
+
+impl ScratchDirectory {
+    /// Create a unique directory for a run of the chain, under
+    /// `CCHAIN_TMPDIR` or the temporary directory of the system
+    pub fn create(chain_path: &str, is_kept: bool) -> Result<Self, Error> {
+        let base_directory: PathBuf = match std::env::var_os(CHAIN_TMP_BASE_ENVIRONMENT_VARIABLE) {
+            Some(base_directory) if !base_directory.is_empty() => PathBuf::from(base_directory),
+            _ => std::env::temp_dir(),
+        };
+        std::fs::create_dir_all(&base_directory)?;
+
+        let chain_name: String = Path::new(chain_path)
+            .file_stem()
+            .map(|stem| stem.to_string_lossy().chars().filter(|character| character.is_ascii_alphanumeric() || *character == '_').collect())
+            .unwrap_or_default();
+        let prefix: String = format!("cchain-{}-", chain_name);
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(&prefix);
+        // Other users cannot read what the programs leave in it
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(std::fs::Permissions::from_mode(0o700));
+        }
+        let path: PathBuf = builder
+            .tempdir_in(&base_directory)
+            .map_err(|error| anyhow!("Cannot create the temporary directory of the chain in {}: {}", base_directory.display(), error))?
+            .keep();
+        if !is_kept {
+            ACTIVE_SCRATCH_DIRECTORIES.lock().unwrap().push(path.clone());
+        }
+
+        Ok(Self { path, is_kept })
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn is_kept(&self) -> bool {
+        self.is_kept
+    }
+
+    /// Remove the directory with everything in it, unless it is kept
+    pub fn remove(&self) -> Result<(), Error> {
+        if self.is_kept {
+            return Ok(());
+        }
+        ACTIVE_SCRATCH_DIRECTORIES.lock().unwrap().retain(|path| path != &self.path);
+        match std::fs::remove_dir_all(&self.path) {
+            Err(error) if error.kind() != std::io::ErrorKind::NotFound => Err(anyhow!(
+                "Cannot remove the temporary directory of the chain, {}: {}",
+                self.path.display(),
+                error
+            )),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Remove the temporary directories of the running chains, e.g. when
+/// the user presses Ctrl-C
+pub fn remove_active_scratch_directories() {
+    for path in ACTIVE_SCRATCH_DIRECTORIES.lock().unwrap().drain(..) {
+        let _ = std::fs::remove_dir_all(path);
+    }
+}
@@ -0,0 +1,307 @@
+use std::{
+    io::{BufReader, Read},
+    process::{Child, Command, ExitStatus, Stdio},
+    sync::{Arc, Mutex},
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Error, Result};
+
+use crate::display_control::{display_command_line, display_message, Level};
+
+use super::{command::CommandLine, options::ReadyCheckOptions, sanitize::get_tail, shutdown::{shut_down_on_interrupt, tear_down_and_exit}};
+
+/// How long a service gets to exit after being asked to terminate,
+/// before it is killed
+pub const SERVICE_STOP_GRACE_PERIOD: Duration = Duration::from_secs(5);
+
+/// Number of characters of the output of the last failed probe shown
+/// when a service is not ready in time
+const PROBE_OUTPUT_TAIL_LENGTH: usize = 1000;
+
+/// Number of characters of the output of a failed probe logged while
+/// waiting for a service
+const PROBE_LOG_TAIL_LENGTH: usize = 200;
+
+/// Process ids of all running services, which also are their process
+/// group ids on Unix. The interrupt handler terminates them on Ctrl-C.
+static RUNNING_SERVICE_PROCESS_IDS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// A long-lived program that keeps running in the background while
+/// the rest of the chain executes
+#[derive(Debug)]
+pub struct Service {
+    /// The command line of the service, for display
+    name: String,
+    child: Child,
+    /// Combined stdout and stderr of the service so far
+    output: Arc<Mutex<String>>,
+    /// Whether the service has been found to exit before the chain ended
+    exited_unexpectedly: bool,
+}
+
+impl Service {
+    /// Spawn the command line without waiting for it. The service runs in
+    /// its own process group, so that its workers can be terminated with it.
+    pub fn start(command_line: &mut CommandLine) -> Result<Self, Error> {
+        let name: String = command_line.to_string();
+        let mut command: Command = command_line.get_process_command()?;
+        command.stdin(Stdio::null());
+        command.stdout(Stdio::piped());
+        command.stderr(Stdio::piped());
+
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::CommandExt;
+            command.process_group(0);
+        }
+
+        let mut child: Child = command
+            .spawn()
+            .map_err(|error| anyhow!("Failed to start service {}: {}", name, error))?;
+
+        let output: Arc<Mutex<String>> = Arc::new(Mutex::new(String::new()));
+        let stdout = child.stdout.take().unwrap();
+        let stderr = child.stderr.take().unwrap();
+        Self::collect_output(stdout, output.clone());
+        Self::collect_output(stderr, output.clone());
+
+        RUNNING_SERVICE_PROCESS_IDS.lock().unwrap().push(child.id());
+        display_message(Level::Logging, &format!("Service started: {}", name));
+
+        Ok(Self {
+            name,
+            child,
+            output,
+            exited_unexpectedly: false,
+        })
+    }
+
+    fn collect_output<R: Read + Send + 'static>(stream: R, output: Arc<Mutex<String>>) {
+        thread::spawn(move || {
+            let mut reader = BufReader::new(stream);
+            let mut buffer = [0; 1024];
+            loop {
+                match reader.read(&mut buffer) {
+                    Ok(0) => break,
+                    Ok(n) => output
+                        .lock()
+                        .unwrap()
+                        .push_str(&String::from_utf8_lossy(&buffer[..n])),
+                    Err(_) => break,
+                }
+            }
+        });
+    }
+
+    pub fn get_name(&self) -> &str {
+        &self.name
+    }
+
+    /// Get the output collected so far
+    pub fn get_output(&self) -> String {
+        self.output.lock().unwrap().clone()
+    }
+
+    /// Get the exit status if the service has already exited
+    pub fn check_exited(&mut self) -> Option<ExitStatus> {
+        self.child.try_wait().ok().flatten()
+    }
+
+    /// Get the exit status if the service has exited while the chain is
+    /// still running. Each unexpected exit is only reported once.
+    pub fn poll_unexpected_exit(&mut self) -> Option<ExitStatus> {
+        if self.exited_unexpectedly {
+            return None;
+        }
+
+        let status: Option<ExitStatus> = self.check_exited();
+        self.exited_unexpectedly = status.is_some();
+        status
+    }
+
+    /// Probe the ready check until it succeeds, the service exits,
+    /// or the timeout is reached.
+    ///
+    /// # Returns
+    ///
+    /// The stdout of the successful probe. The tail of the output of a
+    /// failed probe is logged unless the probe before printed the same,
+    /// and the tail of the last one ends the error when the service is
+    /// not ready in time.
+    pub fn wait_until_ready(&mut self, ready_check: &mut ReadyCheckOptions) -> Result<String, Error> {
+        let started_at: Instant = Instant::now();
+        let timeout: Duration = Duration::from_secs(ready_check.timeout_seconds);
+        let mut attempts: u32 = 0;
+        let mut last_failed_output: String = String::new();
+
+        loop {
+            if let Some(status) = self.check_exited() {
+                return Err(anyhow!(
+                    "Service {} exited with {} before it was ready",
+                    self.name,
+                    status
+                ));
+            }
+
+            let mut probe: Command = ready_check.command_line.get_process_command()?;
+            probe.stdin(Stdio::null());
+            attempts += 1;
+            let failed_output: String = match probe.output() {
+                Ok(output) => {
+                    let stdout: String = String::from_utf8_lossy(&output.stdout).to_string();
+                    if output.status.success() {
+                        display_message(Level::Logging, &format!("Service is ready: {}", self.name));
+                        return Ok(stdout);
+                    }
+                    stdout + &String::from_utf8_lossy(&output.stderr)
+                }
+                Err(error) => format!("The probe cannot be started: {}", error),
+            };
+            if attempts == 1 || failed_output != last_failed_output {
+                let tail: &str = get_tail(failed_output.trim_end(), PROBE_LOG_TAIL_LENGTH);
+                display_message(
+                    Level::Logging,
+                    &format!(
+                        "Service {} is not ready yet (probe attempt #{}){}{}",
+                        self.name,
+                        attempts,
+                        if tail.is_empty() { "" } else { ": " },
+                        tail
+                    ),
+                );
+            }
+            last_failed_output = failed_output;
+
+            if started_at.elapsed() >= timeout {
+                let mut message: String = format!(
+                    "Service {} is not ready after {} seconds",
+                    self.name,
+                    ready_check.timeout_seconds
+                );
+                let tail: &str = get_tail(last_failed_output.trim_end(), PROBE_OUTPUT_TAIL_LENGTH);
+                if !tail.is_empty() {
+                    message.push_str(&format!(". The last probe (attempt #{}) printed:\n{}", attempts, tail));
+                }
+                return Err(anyhow!(message));
+            }
+
+            thread::sleep(Duration::from_millis(ready_check.interval_ms));
+        }
+    }
+
+    /// Terminate the service and its process group, then display the
+    /// output it has produced.
+    pub fn stop(&mut self, grace_period: Duration) -> Result<(), Error> {
+        if self.check_exited().is_none() {
+            terminate_process_group(self.child.id());
+
+            let started_at: Instant = Instant::now();
+            while self.check_exited().is_none() {
+                if started_at.elapsed() >= grace_period {
+                    kill_process_group(self.child.id());
+                    self.child.wait()?;
+                    break;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+
+        RUNNING_SERVICE_PROCESS_IDS
+            .lock()
+            .unwrap()
+            .retain(|process_id| *process_id != self.child.id());
+
+        display_message(Level::Logging, &format!("Service stopped: {}", self.name));
+        let output: String = self.get_output();
+        if !output.is_empty() {
+            display_command_line(&console::Term::stdout(), &output);
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn terminate_process_group(process_id: u32) {
+    unsafe {
+        libc::kill(-(process_id as i32), libc::SIGTERM);
+    }
+}
+
+#[cfg(unix)]
+pub(crate) fn kill_process_group(process_id: u32) {
+    unsafe {
+        libc::kill(-(process_id as i32), libc::SIGKILL);
+    }
+}
+
+#[cfg(unix)]
+fn is_process_group_alive(process_id: u32) -> bool {
+    unsafe { libc::kill(-(process_id as i32), 0) == 0 }
+}
+
+#[cfg(not(unix))]
+pub(crate) fn terminate_process_group(process_id: u32) {
+    kill_process_group(process_id);
+}
+
+#[cfg(not(unix))]
+pub(crate) fn kill_process_group(process_id: u32) {
+    let _ = Command::new("taskkill")
+        .args(["/PID", &process_id.to_string(), "/T", "/F"])
+        .output();
+}
+
+#[cfg(not(unix))]
+fn is_process_group_alive(_process_id: u32) -> bool {
+    false
+}
+
+/// Terminate every running service, in reverse start order.
+/// This is used when cchain is interrupted, as the chain itself cannot
+/// run its teardown anymore.
+pub fn terminate_running_services(grace_period: Duration) {
+    let process_ids: Vec<u32> = RUNNING_SERVICE_PROCESS_IDS.lock().unwrap().clone();
+
+    for process_id in process_ids.iter().rev() {
+        terminate_process_group(*process_id);
+
+        let started_at: Instant = Instant::now();
+        while is_process_group_alive(*process_id) {
+            if started_at.elapsed() >= grace_period {
+                kill_process_group(*process_id);
+                break;
+            }
+            thread::sleep(Duration::from_millis(50));
+        }
+    }
+
+    RUNNING_SERVICE_PROCESS_IDS.lock().unwrap().clear();
+}
+
+/// Check whether any service started by cchain is still running
+pub(crate) fn has_running_services() -> bool {
+    !RUNNING_SERVICE_PROCESS_IDS.lock().unwrap().is_empty()
+}
+
+/// Stop the running chain, which runs its cleanup programs, when the
+/// user presses Ctrl-C. Otherwise, or on the second Ctrl-C, stop the
+/// running services and exit.
+pub fn install_interrupt_handler() -> Result<(), Error> {
+    ctrlc::set_handler(|| {
+        if !shut_down_on_interrupt() {
+            interrupt();
+        }
+    })?;
+
+    Ok(())
+}
+
+/// Cancel the run as the user asked, with Ctrl-C or by aborting a
+/// paused chain
+pub fn interrupt() -> ! {
+    display_message(Level::Warn, "Interrupted.");
+    tear_down_and_exit();
+}
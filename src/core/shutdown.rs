@@ -0,0 +1,259 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{Error, Result};
+
+use crate::{
+    dashboard::restore_terminal,
+    key_mode::restore_key_mode,
+    display_control::{display_message, Level},
+    terminal_title::restore_terminal_title,
+};
+
+use super::{
+    group::terminate_running_members,
+    scratch::remove_active_scratch_directories,
+    service::{has_running_services, terminate_running_services, SERVICE_STOP_GRACE_PERIOD},
+    status::{write_status_file, RunOutcome},
+};
+
+/// How long the programs get to finish after cchain is asked to
+/// terminate, unless `--term-grace-seconds` is given
+pub const DEFAULT_TERMINATION_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+/// How long the chain gets to finish by itself after the grace period,
+/// once what is still running is killed
+const TEAR_DOWN_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Process ids of the running programs, to forward termination signals to
+static RUNNING_PROCESS_IDS: Mutex<Vec<u32>> = Mutex::new(Vec::new());
+
+/// Set once cchain is asked to terminate, after which no program starts
+/// or retries except the cleanup programs
+static IS_SHUTTING_DOWN: AtomicBool = AtomicBool::new(false);
+
+/// Set once the grace period is over, after which not even the cleanup
+/// programs start
+static IS_GRACE_PERIOD_OVER: AtomicBool = AtomicBool::new(false);
+
+/// Set once the time limit of `cchain exec --timeout` is over
+static IS_TIMED_OUT: AtomicBool = AtomicBool::new(false);
+
+/// Set once the termination handler is installed, by the commands
+/// running a chain, which Ctrl-C then stops gracefully
+static IS_TERMINATION_HANDLED: AtomicBool = AtomicBool::new(false);
+
+static TERMINATION_GRACE_PERIOD: Mutex<Duration> = Mutex::new(DEFAULT_TERMINATION_GRACE_PERIOD);
+
+/// Track a spawned program, so that termination signals reach it
+pub(crate) fn register_running_process(process_id: u32) {
+    RUNNING_PROCESS_IDS.lock().unwrap().push(process_id);
+}
+
+pub(crate) fn unregister_running_process(process_id: u32) {
+    RUNNING_PROCESS_IDS.lock().unwrap().retain(|running_process_id| *running_process_id != process_id);
+}
+
+/// Set how long the programs get to finish, and the cleanup programs to
+/// run, after cchain is asked to terminate
+pub fn set_termination_grace_period(grace_period: Duration) {
+    *TERMINATION_GRACE_PERIOD.lock().unwrap() = grace_period;
+}
+
+/// Check whether cchain has been asked to terminate, e.g. with SIGTERM
+pub fn is_shutting_down() -> bool {
+    IS_SHUTTING_DOWN.load(Ordering::SeqCst)
+}
+
+/// Check whether the grace period after a termination signal is over
+pub fn is_grace_period_over() -> bool {
+    IS_GRACE_PERIOD_OVER.load(Ordering::SeqCst)
+}
+
+/// Stop the services and the members of concurrency groups, write the
+/// status file as cancelled, and exit. Shared by Ctrl-C and by
+/// termination signals once their grace period is over.
+pub fn tear_down_and_exit() -> ! {
+    restore_terminal();
+    restore_key_mode();
+    restore_terminal_title();
+    if has_running_services() {
+        display_message(Level::Warn, "Stopping the running services...");
+        terminate_running_services(SERVICE_STOP_GRACE_PERIOD);
+    }
+    terminate_running_members();
+    remove_active_scratch_directories();
+    let _ = write_status_file(RunOutcome::Cancelled, &[]);
+    std::process::exit(RunOutcome::Cancelled.get_code());
+}
+
+/// Finish gracefully when cchain is asked to terminate with SIGTERM or
+/// SIGHUP, e.g. on shutdown or by a CI runner. The signal is forwarded to
+/// the running programs, and the chain stops, runs its cleanup programs
+/// and reports the run as cancelled. Whatever is still running when the
+/// grace period is over is killed.
+///
+/// There is no equivalent on Windows yet, where closing the console ends
+/// cchain and its programs right away.
+#[cfg(unix)]
+pub fn install_termination_handler() -> Result<(), Error> {
+    use std::sync::atomic::AtomicI32;
+
+    static RECEIVED_SIGNAL: AtomicI32 = AtomicI32::new(0);
+
+    extern "C" fn handle_termination_signal(signal: libc::c_int) {
+        RECEIVED_SIGNAL.store(signal, Ordering::SeqCst);
+    }
+
+    for signal in [libc::SIGTERM, libc::SIGHUP] {
+        let handler = handle_termination_signal as extern "C" fn(libc::c_int) as libc::sighandler_t;
+        if unsafe { libc::signal(signal, handler) } == libc::SIG_ERR {
+            return Err(std::io::Error::last_os_error().into());
+        }
+    }
+
+    IS_TERMINATION_HANDLED.store(true, Ordering::SeqCst);
+
+    // Signal handlers can only set a flag, so the rest is done here
+    thread::spawn(|| loop {
+        let signal: i32 = RECEIVED_SIGNAL.load(Ordering::SeqCst);
+        if signal == 0 {
+            thread::sleep(Duration::from_millis(50));
+            continue;
+        }
+
+        let name: &str = if signal == libc::SIGHUP { "SIGHUP" } else { "SIGTERM" };
+        shut_down(signal, &format!("Received {}. Forwarding it to the running programs", name));
+    });
+
+    Ok(())
+}
+
+/// Stop the chain like a termination signal when the user presses
+/// Ctrl-C, so that its cleanup programs run. Returns false when no chain
+/// is running or it is already stopping, in which case cchain should exit
+/// right away.
+#[cfg(unix)]
+pub fn shut_down_on_interrupt() -> bool {
+    if !IS_TERMINATION_HANDLED.load(Ordering::SeqCst) || IS_SHUTTING_DOWN.swap(true, Ordering::SeqCst) {
+        return false;
+    }
+
+    // The handler returns right away, so that a second Ctrl-C is handled
+    thread::spawn(|| {
+        shut_down(
+            libc::SIGINT,
+            "Interrupted, press Ctrl-C again to exit right away. Forwarding SIGINT to the running programs",
+        )
+    });
+
+    true
+}
+
+/// Ctrl-C exits right away on Windows, like termination signals
+#[cfg(not(unix))]
+pub fn shut_down_on_interrupt() -> bool {
+    false
+}
+
+/// Termination signals are not handled on Windows, e.g. the close event
+/// of the console
+#[cfg(not(unix))]
+pub fn install_termination_handler() -> Result<(), Error> {
+    Ok(())
+}
+
+/// Stop the chain like a termination signal once the time limit is
+/// over, for `cchain exec --timeout`. The time limit covers the retries too.
+pub fn set_time_limit(time_limit: Duration) {
+    thread::spawn(move || {
+        thread::sleep(time_limit);
+        IS_TIMED_OUT.store(true, Ordering::SeqCst);
+        let reason: String = format!("Timed out after {} second(s)", time_limit.as_secs());
+        #[cfg(unix)]
+        shut_down(libc::SIGTERM, &format!("{}. Sending SIGTERM to the running programs", reason));
+        #[cfg(not(unix))]
+        {
+            display_message(Level::Warn, &format!("{}. Stopping the running programs...", reason));
+            IS_SHUTTING_DOWN.store(true, Ordering::SeqCst);
+            for process_id in RUNNING_PROCESS_IDS.lock().unwrap().drain(..) {
+                kill_process(process_id);
+            }
+        }
+    });
+}
+
+/// Check whether the time limit of `set_time_limit` is over
+pub fn is_timed_out() -> bool {
+    IS_TIMED_OUT.load(Ordering::SeqCst)
+}
+
+/// Forward the signal to the running programs and stop the chain, which
+/// runs its cleanup programs. Whatever is still running when the grace
+/// period is over is killed.
+#[cfg(unix)]
+fn shut_down(signal: i32, announcement: &str) -> ! {
+    let grace_period: Duration = *TERMINATION_GRACE_PERIOD.lock().unwrap();
+    display_message(
+        Level::Warn,
+        &format!(
+            "{}, and finishing within {} seconds...",
+            announcement,
+            grace_period.as_secs()
+        ),
+    );
+    IS_SHUTTING_DOWN.store(true, Ordering::SeqCst);
+    for process_id in RUNNING_PROCESS_IDS.lock().unwrap().iter() {
+        forward_signal(*process_id, signal);
+    }
+    terminate_running_members();
+
+    // cchain exits by itself once the chain has finished
+    let started_at: Instant = Instant::now();
+    while started_at.elapsed() < grace_period {
+        thread::sleep(Duration::from_millis(50));
+    }
+    display_message(Level::Warn, "The grace period is over. Stopping what is still running...");
+    IS_GRACE_PERIOD_OVER.store(true, Ordering::SeqCst);
+    // The chain finishes by itself once its programs are killed, and exits
+    // after the cleanup it is in the middle of. It is only cut short when
+    // it does not, e.g. while it waits for an answer.
+    let killed_at: Instant = Instant::now();
+    while killed_at.elapsed() < TEAR_DOWN_TIMEOUT {
+        for process_id in RUNNING_PROCESS_IDS.lock().unwrap().drain(..) {
+            kill_process(process_id);
+        }
+        thread::sleep(Duration::from_millis(50));
+    }
+    tear_down_and_exit();
+}
+
+/// Send the signal to the process, or to its process group when it leads
+/// one, like the members of concurrency groups
+#[cfg(unix)]
+fn forward_signal(process_id: u32, signal: i32) {
+    unsafe {
+        let process_id = process_id as libc::pid_t;
+        if libc::getpgid(process_id) == process_id {
+            libc::kill(-process_id, signal);
+        } else {
+            libc::kill(process_id, signal);
+        }
+    }
+}
+
+#[cfg(unix)]
+fn kill_process(process_id: u32) {
+    forward_signal(process_id, libc::SIGKILL);
+}
+
+#[cfg(not(unix))]
+fn kill_process(process_id: u32) {
+    super::service::kill_process_group(process_id);
+}
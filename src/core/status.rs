@@ -0,0 +1,207 @@
+use std::{
+    collections::BTreeMap,
+    path::PathBuf,
+    sync::Mutex,
+    time::{Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::commons::errors::{ChainError, PackageError};
+
+use super::{chain_retry::ChainAttempt, options::FailurePolicy, program::{AttemptRecord, ProgramExecutionResult}};
+
+/// The status file to write when cchain exits, with the time cchain started
+static STATUS_FILE: Mutex<Option<(PathBuf, Instant)>> = Mutex::new(None);
+
+/// The attempts of the programs of the chain that ran, for the status file
+static PROGRAM_ATTEMPTS: Mutex<BTreeMap<usize, Vec<AttemptRecord>>> = Mutex::new(BTreeMap::new());
+
+/// The programs cancelled by fail-fast concurrency groups, for the status file
+static CANCELLED_PROGRAMS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+/// The failure policy of the run, for the status file
+static FAILURE_POLICY: Mutex<FailurePolicy> = Mutex::new(FailurePolicy::PerProgram);
+
+/// The verification steps that failed, for the status file
+static FAILED_VERIFICATIONS: Mutex<Vec<usize>> = Mutex::new(Vec::new());
+
+/// The environment variables pinned by the chain, for the status file
+static PINNED_ENVIRONMENT: Mutex<BTreeMap<String, String>> = Mutex::new(BTreeMap::new());
+
+/// The attempts of the whole chain, for the status file
+static CHAIN_ATTEMPTS: Mutex<Vec<ChainAttempt>> = Mutex::new(Vec::new());
+
+/// How a run of cchain ended. The exit codes are stable, so that wrapper
+/// scripts can rely on them.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RunOutcome {
+    /// All programs succeeded
+    Succeeded = 0,
+    /// An error that is not covered by the other statuses
+    Failed = 1,
+    /// The chain cannot be loaded, or its syntax is invalid
+    ValidationFailed = 2,
+    /// A program failed with `exit_on_failure`, so the chain stopped mid-way
+    Aborted = 3,
+    /// The chain ran to the end, but programs with `exit_on_failure: false` failed
+    CompletedWithFailures = 4,
+    /// Packages required by the chain are not installed
+    MissingPackages = 5,
+    /// A value is needed from the user, but there is no input to read from
+    InputRequired = 6,
+    /// All programs succeeded, but steps of the `verify` section failed
+    Unverified = 7,
+    /// The command line arguments of cchain are invalid
+    Usage = 64,
+    /// The user pressed Ctrl-C
+    Cancelled = 130,
+}
+
+impl RunOutcome {
+    pub fn get_code(&self) -> i32 {
+        *self as i32
+    }
+
+    /// Decide the outcome from the error that ended the run
+    pub fn from_error(error: &Error) -> Self {
+        if let Some(error) = error.downcast_ref::<ChainError>() {
+            return match error {
+                ChainError::Validation(_) => RunOutcome::ValidationFailed,
+                ChainError::ProgramFailed { .. } => RunOutcome::Aborted,
+                ChainError::InputRequired { .. } => RunOutcome::InputRequired,
+                ChainError::Terminated => RunOutcome::Cancelled,
+            };
+        }
+
+        if error.downcast_ref::<PackageError>().is_some() {
+            return RunOutcome::MissingPackages;
+        }
+
+        RunOutcome::Failed
+    }
+}
+
+impl std::fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let description: &str = match self {
+            RunOutcome::Succeeded => "succeeded",
+            RunOutcome::Failed => "failed",
+            RunOutcome::ValidationFailed => "is invalid",
+            RunOutcome::Aborted => "aborted",
+            RunOutcome::CompletedWithFailures => "completed with failures",
+            RunOutcome::MissingPackages => "misses packages",
+            RunOutcome::InputRequired => "needs input",
+            RunOutcome::Unverified => "is unverified",
+            RunOutcome::Usage => "is misused",
+            RunOutcome::Cancelled => "is cancelled",
+        };
+        write!(f, "{}", description)
+    }
+}
+
+/// The document written to the file of `--status-file`
+#[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
+pub struct RunStatus {
+    pub exit_code: i32,
+    pub outcome: RunOutcome,
+    /// Indices of the programs that failed
+    pub failed_programs: Vec<usize>,
+    /// Indices of the programs cancelled because another member of
+    /// their concurrency group failed. They are not counted as failed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub cancelled_programs: Vec<usize>,
+    /// Indices of the steps of the `verify` section that failed
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub failed_verifications: Vec<usize>,
+    pub duration_seconds: f64,
+    /// When cchain exited, in seconds since the Unix epoch
+    pub timestamp: u64,
+    /// The attempts of each program that ran, keyed by the program index
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub attempts: BTreeMap<usize, Vec<AttemptRecord>>,
+    /// How failures were handled, e.g. overridden by `--continue-on-failure`
+    #[serde(default)]
+    pub failure_policy: FailurePolicy,
+    /// The environment variables pinned by the `timezone` and `locale`
+    /// of the chain, e.g. `TZ`
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub pinned_environment: BTreeMap<String, String>,
+    /// The attempts of the whole chain, with `--retry-chain`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub chain_attempts: Vec<ChainAttempt>,
+}
+
+/// Write the status to the path when cchain exits. The duration is
+/// measured from this call.
+pub fn set_status_file(path: PathBuf) {
+    *STATUS_FILE.lock().unwrap() = Some((path, Instant::now()));
+}
+
+/// Set the results of the programs to write their attempts in the
+/// status file
+pub fn set_program_results(program_results: &[ProgramExecutionResult]) {
+    *PROGRAM_ATTEMPTS.lock().unwrap() = program_results
+        .iter()
+        .filter_map(|result| Some((result.program_index()?, result.get_attempts().clone())))
+        .collect();
+}
+
+/// Set the cancelled programs to write in the status file
+pub fn set_cancelled_programs(cancelled_programs: Vec<usize>) {
+    *CANCELLED_PROGRAMS.lock().unwrap() = cancelled_programs;
+}
+
+/// Set the failed verification steps to write in the status file
+pub fn set_failed_verifications(failed_verifications: Vec<usize>) {
+    *FAILED_VERIFICATIONS.lock().unwrap() = failed_verifications;
+}
+
+/// Set the failure policy to write in the status file
+pub fn set_failure_policy(failure_policy: FailurePolicy) {
+    *FAILURE_POLICY.lock().unwrap() = failure_policy;
+}
+
+/// Set the environment variables pinned by the chain to write in the status file
+pub fn set_pinned_environment(pinned_environment: BTreeMap<String, String>) {
+    *PINNED_ENVIRONMENT.lock().unwrap() = pinned_environment;
+}
+
+/// Set the attempts of the whole chain to write in the status file
+pub fn set_chain_attempts(chain_attempts: Vec<ChainAttempt>) {
+    *CHAIN_ATTEMPTS.lock().unwrap() = chain_attempts;
+}
+
+/// Write the status file, if one is set
+pub fn write_status_file(outcome: RunOutcome, failed_programs: &[usize]) -> Result<(), Error> {
+    let status_file = STATUS_FILE.lock().unwrap();
+    let (path, started_at) = match status_file.as_ref() {
+        Some(status_file) => status_file,
+        None => return Ok(()),
+    };
+
+    let run_status: RunStatus = get_run_status(outcome, failed_programs, *started_at)?;
+    std::fs::write(path, serde_json::to_string_pretty(&run_status)?)?;
+
+    Ok(())
+}
+
+/// Get the status of the run that started at the instant, as written to
+/// the status file
+pub fn get_run_status(outcome: RunOutcome, failed_programs: &[usize], started_at: Instant) -> Result<RunStatus, Error> {
+    Ok(RunStatus {
+        exit_code: outcome.get_code(),
+        outcome,
+        failed_programs: failed_programs.to_vec(),
+        cancelled_programs: CANCELLED_PROGRAMS.lock().unwrap().clone(),
+        failed_verifications: FAILED_VERIFICATIONS.lock().unwrap().clone(),
+        duration_seconds: started_at.elapsed().as_secs_f64(),
+        timestamp: SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs(),
+        attempts: PROGRAM_ATTEMPTS.lock().unwrap().clone(),
+        failure_policy: *FAILURE_POLICY.lock().unwrap(),
+        pinned_environment: PINNED_ENVIRONMENT.lock().unwrap().clone(),
+        chain_attempts: CHAIN_ATTEMPTS.lock().unwrap().clone(),
+    })
+}
@@ -0,0 +1,608 @@
+use std::{
+    io::Write,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        mpsc::{channel, Receiver, RecvTimeoutError},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use console::{style, Term};
+use regex::Regex;
+
+use crate::{
+    commons::interaction::SharedInteractor,
+    core::{
+        chain::Chain,
+        events::{ChainEvent, EventSender},
+    },
+    display_control::set_display_capture,
+    key_mode::{restore_key_mode, take_key_mode, KeyReaderState, SuspendableReader, SuspendingInteractor},
+    marker::reference::TrackPath,
+    terminal_title::{render_progress_title, restore_terminal_title, RunProgress},
+};
+
+/// How often the dashboard is redrawn while nothing happens, for the timers
+const REDRAW_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Characters of output kept for each step. The output pane only shows
+/// the end of it.
+const STEP_OUTPUT_LENGTH: usize = 64 * 1024;
+
+/// Lines scrolled with Page Up and Page Down
+const SCROLL_PAGE_LINES: usize = 10;
+
+/// Escape sequences of the alternate screen, which keeps the screen
+/// before the dashboard to be shown again afterwards
+const ENTER_ALTERNATE_SCREEN: &str = "\x1b[?1049h\x1b[?25l";
+const LEAVE_ALTERNATE_SCREEN: &str = "\x1b[?25h\x1b[?1049l";
+
+/// Whether the dashboard has the screen, so that the terminal is only
+/// restored once, whichever of the panic hook, the interrupt handler or
+/// the dashboard itself comes first
+static IS_TERMINAL_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// Where a step of the chain is
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepStatus {
+    Pending,
+    Running,
+    Succeeded,
+    Failed,
+    /// Not started before the chain finished
+    Skipped,
+}
+
+impl StepStatus {
+    fn get_icon(&self) -> String {
+        match self {
+            StepStatus::Pending => style("·").dim().to_string(),
+            StepStatus::Running => style("▶").yellow().bold().to_string(),
+            StepStatus::Succeeded => style("✓").green().to_string(),
+            StepStatus::Failed => style("✗").red().bold().to_string(),
+            StepStatus::Skipped => style("-").dim().to_string(),
+        }
+    }
+}
+
+/// A program of the chain, as the dashboard shows it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StepState {
+    label: String,
+    status: StepStatus,
+    /// Time of the start of the step since the dashboard started
+    started_at: Option<Duration>,
+    /// How long the step took, once it finished
+    elapsed: Option<Duration>,
+    output: String,
+}
+
+impl StepState {
+    fn new(label: String) -> Self {
+        Self {
+            label,
+            status: StepStatus::Pending,
+            started_at: None,
+            elapsed: None,
+            output: String::new(),
+        }
+    }
+
+    pub fn get_label(&self) -> &str {
+        &self.label
+    }
+
+    pub fn get_status(&self) -> StepStatus {
+        self.status
+    }
+
+    pub fn get_elapsed(&self) -> Option<Duration> {
+        self.elapsed
+    }
+
+    pub fn get_output(&self) -> &str {
+        &self.output
+    }
+
+    /// How long the step has taken so far
+    fn get_running_time(&self, now: Duration) -> Option<Duration> {
+        self.elapsed
+            .or_else(|| self.started_at.map(|started_at| now.saturating_sub(started_at)))
+    }
+}
+
+/// What the dashboard shows, built up from the events of the chain.
+/// Times are measured from the start of the dashboard, so that the
+/// state does not depend on the clock.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DashboardState {
+    title: String,
+    steps: Vec<StepState>,
+    /// Messages that cchain would have displayed, in order
+    messages: Vec<String>,
+    /// The step whose output is shown
+    selected_step: usize,
+    /// Whether the selection follows the running step. It stops when
+    /// the user selects a step.
+    is_following: bool,
+    /// Lines of output scrolled back from its end
+    scrolled_lines: usize,
+    /// Whether the chain succeeded, once it finished
+    outcome: Option<bool>,
+}
+
+impl DashboardState {
+    pub fn new(title: &str) -> Self {
+        Self {
+            title: title.to_string(),
+            steps: Vec::new(),
+            messages: Vec::new(),
+            selected_step: 0,
+            is_following: true,
+            scrolled_lines: 0,
+            outcome: None,
+        }
+    }
+
+    /// Update the state with an event that happened at a time since the
+    /// start of the dashboard
+    pub fn apply(&mut self, event: &ChainEvent, at: Duration) {
+        match event {
+            ChainEvent::ChainStarted { program_labels } => {
+                self.steps = program_labels.iter().cloned().map(StepState::new).collect();
+                self.selected_step = 0;
+                self.scrolled_lines = 0;
+                self.outcome = None;
+            }
+            ChainEvent::ProgramStarted { program_index } => {
+                let step: &mut StepState = match self.steps.get_mut(*program_index) {
+                    Some(step) => step,
+                    None => return,
+                };
+                // A retry is a part of the same run of the step
+                if step.status != StepStatus::Running {
+                    step.started_at = Some(at);
+                    step.elapsed = None;
+                    step.status = StepStatus::Running;
+                }
+                if self.is_following {
+                    self.selected_step = *program_index;
+                    self.scrolled_lines = 0;
+                }
+            }
+            // The next attempt starts the step again
+            ChainEvent::RetryScheduled { .. } | ChainEvent::ProcessExited { .. } => (),
+            ChainEvent::ProgramOutput { program_index, text } => {
+                if let Some(step) = self.steps.get_mut(*program_index) {
+                    step.output.push_str(text);
+                    if step.output.len() > STEP_OUTPUT_LENGTH {
+                        let mut start: usize = step.output.len() - STEP_OUTPUT_LENGTH;
+                        while !step.output.is_char_boundary(start) {
+                            start += 1;
+                        }
+                        step.output.drain(..start);
+                    }
+                }
+            }
+            ChainEvent::ProgramFinished { program_index, succeeded } => {
+                if let Some(step) = self.steps.get_mut(*program_index) {
+                    step.status = if *succeeded { StepStatus::Succeeded } else { StepStatus::Failed };
+                    if step.elapsed.is_none() {
+                        step.elapsed = Some(step.started_at.map_or(Duration::ZERO, |started_at| at.saturating_sub(started_at)));
+                    }
+                }
+            }
+            ChainEvent::Message { text } => self.messages.push(text.clone()),
+            ChainEvent::ChainFinished { succeeded } => {
+                for step in &mut self.steps {
+                    if step.status == StepStatus::Pending {
+                        step.status = StepStatus::Skipped;
+                    }
+                }
+                self.outcome = Some(*succeeded);
+            }
+        }
+    }
+
+    pub fn get_steps(&self) -> &Vec<StepState> {
+        &self.steps
+    }
+
+    pub fn get_messages(&self) -> &Vec<String> {
+        &self.messages
+    }
+
+    pub fn get_selected_step(&self) -> usize {
+        self.selected_step
+    }
+
+    pub fn get_outcome(&self) -> Option<bool> {
+        self.outcome
+    }
+
+    /// Get where the run is, with the running step started last, as in
+    /// the title of the terminal
+    pub fn get_progress(&self) -> RunProgress<'_> {
+        if let Some(succeeded) = self.outcome {
+            return RunProgress::Finished { succeeded };
+        }
+        let running_step: Option<(usize, &StepState)> = self
+            .steps
+            .iter()
+            .enumerate()
+            .filter(|(_, step)| step.status == StepStatus::Running)
+            .max_by_key(|(_, step)| step.started_at);
+        match running_step {
+            Some((step, step_state)) => RunProgress::Running { step, total: self.steps.len(), summary: &step_state.label },
+            None => RunProgress::Starting,
+        }
+    }
+
+    pub fn select_previous_step(&mut self) {
+        self.is_following = false;
+        self.selected_step = self.selected_step.saturating_sub(1);
+        self.scrolled_lines = 0;
+    }
+
+    pub fn select_next_step(&mut self) {
+        self.is_following = false;
+        if self.selected_step + 1 < self.steps.len() {
+            self.selected_step += 1;
+        }
+        self.scrolled_lines = 0;
+    }
+
+    /// Scroll the output back. Scrolling past its start is stopped when
+    /// the frame is rendered.
+    pub fn scroll_up(&mut self, lines: usize) {
+        self.scrolled_lines = self.scrolled_lines.saturating_add(lines);
+    }
+
+    pub fn scroll_down(&mut self, lines: usize) {
+        self.scrolled_lines = self.scrolled_lines.saturating_sub(lines);
+    }
+
+    /// Estimate the time left from the mean time of the finished steps,
+    /// less the time the running steps have taken so far
+    ///
+    /// # Returns
+    ///
+    /// The estimate, or `None` before any step finished
+    pub fn get_eta(&self, now: Duration) -> Option<Duration> {
+        let finished_times: Vec<Duration> = self
+            .steps
+            .iter()
+            .filter(|step| matches!(step.status, StepStatus::Succeeded | StepStatus::Failed))
+            .filter_map(|step| step.elapsed)
+            .collect();
+        if finished_times.is_empty() {
+            return None;
+        }
+        let mean_time: Duration = finished_times.iter().sum::<Duration>() / finished_times.len() as u32;
+
+        let mut eta: Duration = Duration::ZERO;
+        for step in &self.steps {
+            match step.status {
+                StepStatus::Pending => eta += mean_time,
+                StepStatus::Running => {
+                    eta += mean_time.saturating_sub(step.get_running_time(now).unwrap_or_default())
+                }
+                _ => {}
+            }
+        }
+
+        Some(eta)
+    }
+}
+
+/// Render a frame of the dashboard: a header, the steps with their
+/// status and time, the output of the selected step, and a footer with
+/// the progress and the keys. Lines are cut to the width, and there are
+/// no more lines than the height.
+pub fn render_frame(state: &DashboardState, now: Duration, width: usize, height: usize) -> Vec<String> {
+    let width: usize = width.max(20);
+    let height: usize = height.max(6);
+    let mut lines: Vec<String> = Vec::with_capacity(height);
+
+    let outcome: String = match state.outcome {
+        None => style("running").yellow().to_string(),
+        Some(true) => style("succeeded").green().to_string(),
+        Some(false) => style("failed").red().bold().to_string(),
+    };
+    let title: String = render_progress_title(&state.title, state.get_progress());
+    lines.push(format!("{} {}", style(fit(&title, width.saturating_sub(10))).bold(), outcome));
+
+    // The steps take up to half of the rest, around the selected one
+    let step_rows: usize = state.steps.len().min((height - 4) / 2).max(1);
+    let first_step: usize = state
+        .selected_step
+        .saturating_sub(step_rows / 2)
+        .min(state.steps.len().saturating_sub(step_rows));
+    for (index, step) in state.steps.iter().enumerate().skip(first_step).take(step_rows) {
+        let time: String = step.get_running_time(now).map(format_duration).unwrap_or_default();
+        let marker: &str = if index == state.selected_step { ">" } else { " " };
+        let label: String = fit(
+            &format!("#{} {}", index, step.label),
+            width.saturating_sub(time.chars().count() + 6),
+        );
+        let padding: usize = width.saturating_sub(label.chars().count() + time.chars().count() + 5);
+        lines.push(format!("{} {} {} {}{}", marker, step.status.get_icon(), label, " ".repeat(padding), style(time).dim()));
+    }
+
+    let output_rows: usize = height.saturating_sub(lines.len() + 2);
+    match state.steps.get(state.selected_step) {
+        Some(step) => {
+            lines.push(style(fit(&format!("── Output of #{} ", state.selected_step), width)).dim().to_string());
+            let output_lines: Vec<String> = get_display_lines(&step.output);
+            let scrolled_lines: usize = state.scrolled_lines.min(output_lines.len().saturating_sub(output_rows));
+            let end: usize = output_lines.len() - scrolled_lines;
+            let start: usize = end.saturating_sub(output_rows);
+            for line in &output_lines[start..end] {
+                lines.push(fit(line, width));
+            }
+        }
+        None => lines.push(style("Waiting for the chain to start...").dim().to_string()),
+    }
+    while lines.len() < height - 1 {
+        lines.push(String::new());
+    }
+
+    let finished_steps: usize = state
+        .steps
+        .iter()
+        .filter(|step| matches!(step.status, StepStatus::Succeeded | StepStatus::Failed))
+        .count();
+    let failed_steps: usize = state.steps.iter().filter(|step| step.status == StepStatus::Failed).count();
+    let mut footer: String = format!("{}/{} done", finished_steps, state.steps.len());
+    if failed_steps > 0 {
+        footer.push_str(&format!(", {} failed", failed_steps));
+    }
+    if let (None, Some(eta)) = (state.outcome, state.get_eta(now)) {
+        footer.push_str(&format!(" | ETA {}", format_duration(eta)));
+    }
+    footer.push_str(" | ↑↓ select, PgUp/PgDn scroll");
+    lines.push(style(fit(&footer, width)).reverse().to_string());
+
+    lines
+}
+
+/// Split output into the lines a terminal would show, without escape
+/// sequences that would move the cursor out of the pane
+fn get_display_lines(output: &str) -> Vec<String> {
+    let escape_sequence: Regex = Regex::new(r"\x1b\[[0-9;?]*[A-Za-z]|\x1b\][^\x07]*\x07").unwrap();
+    escape_sequence
+        .replace_all(output, "")
+        .lines()
+        // A carriage return starts the line over, e.g. for progress bars
+        .map(|line| line.rsplit('\r').next().unwrap_or_default().replace('\t', "    "))
+        .collect()
+}
+
+/// Cut text to a width in characters
+fn fit(text: &str, width: usize) -> String {
+    if text.chars().count() <= width {
+        return text.to_string();
+    }
+
+    let mut fitted: String = text.chars().take(width.saturating_sub(1)).collect();
+    fitted.push('…');
+    fitted
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds: u64 = duration.as_secs();
+    if seconds >= 3600 {
+        format!("{}:{:02}:{:02}", seconds / 3600, seconds / 60 % 60, seconds % 60)
+    } else {
+        format!("{:02}:{:02}", seconds / 60, seconds % 60)
+    }
+}
+
+/// What the threads of the dashboard share
+struct DashboardShared {
+    state: Mutex<DashboardState>,
+    started_at: Instant,
+    event_sender: EventSender,
+    /// Whether the dashboard gave the screen back for a prompt, and
+    /// whether it stopped
+    key_reader_state: KeyReaderState,
+}
+
+impl DashboardShared {
+    fn draw(&self) {
+        let (height, width): (u16, u16) = Term::stdout().size();
+        let lines: Vec<String> = render_frame(
+            &self.state.lock().unwrap(),
+            self.started_at.elapsed(),
+            width as usize,
+            height as usize,
+        );
+
+        let mut frame: String = String::from("\x1b[H");
+        for (index, line) in lines.iter().enumerate() {
+            if index > 0 {
+                frame.push_str("\r\n");
+            }
+            frame.push_str(line);
+            frame.push_str("\x1b[K");
+        }
+        frame.push_str("\x1b[J");
+
+        let mut stdout = std::io::stdout().lock();
+        let _ = stdout.write_all(frame.as_bytes());
+        let _ = stdout.flush();
+    }
+}
+
+impl SuspendableReader for DashboardShared {
+    /// Give the screen back for a prompt
+    fn suspend(&self) {
+        let mut is_suspended = self.key_reader_state.is_suspended.lock().unwrap();
+        set_display_capture(None);
+        restore_terminal();
+        *is_suspended = true;
+    }
+
+    fn resume(&self) {
+        let mut is_suspended = self.key_reader_state.is_suspended.lock().unwrap();
+        take_terminal();
+        set_display_capture(Some(self.event_sender.clone()));
+        *is_suspended = false;
+        drop(is_suspended);
+        self.draw();
+    }
+}
+
+/// A full-screen dashboard of a running chain, for `cchain run --tui`.
+/// It shows what the events of the chain report, and takes the messages
+/// of cchain while it has the screen.
+pub struct Dashboard {
+    shared: Arc<DashboardShared>,
+    renderer: Option<JoinHandle<()>>,
+}
+
+impl Dashboard {
+    /// Take the screen and follow the chain. Prompts of the chain suspend
+    /// the dashboard until they are answered.
+    pub fn start(chain: &mut Chain) -> Self {
+        let (sender, receiver) = channel();
+        let event_sender: EventSender = EventSender::new(sender);
+        let shared: Arc<DashboardShared> = Arc::new(DashboardShared {
+            state: Mutex::new(DashboardState::new(chain.get_path())),
+            started_at: Instant::now(),
+            event_sender: event_sender.clone(),
+            key_reader_state: KeyReaderState::new(),
+        });
+
+        chain.set_event_sender(event_sender.clone());
+        chain.set_interactor(SharedInteractor::new(SuspendingInteractor::new(shared.clone())));
+        install_panic_hook();
+        take_terminal();
+        set_display_capture(Some(event_sender));
+
+        let renderer_shared: Arc<DashboardShared> = shared.clone();
+        let renderer: JoinHandle<()> = thread::spawn(move || render_events(renderer_shared, receiver));
+        #[cfg(unix)]
+        {
+            use std::io::IsTerminal;
+
+            if std::io::stdin().is_terminal() {
+                let reader_shared: Arc<DashboardShared> = shared.clone();
+                thread::spawn(move || read_keys(reader_shared));
+            }
+        }
+
+        Self { shared, renderer: Some(renderer) }
+    }
+
+    /// Give the screen back, then display the messages of the run that
+    /// the dashboard took
+    pub fn finish(mut self) {
+        set_display_capture(None);
+        self.shared.key_reader_state.is_stopped.store(true, Ordering::SeqCst);
+        if let Some(renderer) = self.renderer.take() {
+            let _ = renderer.join();
+        }
+        restore_terminal();
+
+        for message in self.shared.state.lock().unwrap().get_messages() {
+            println!("{}", message);
+        }
+    }
+}
+
+/// Apply the events as they come, and redraw
+fn render_events(shared: Arc<DashboardShared>, receiver: Receiver<ChainEvent>) {
+    loop {
+        let is_disconnected: bool = match receiver.recv_timeout(REDRAW_INTERVAL) {
+            Ok(event) => {
+                let mut state = shared.state.lock().unwrap();
+                state.apply(&event, shared.started_at.elapsed());
+                // Take what else has come before drawing
+                while let Ok(event) = receiver.try_recv() {
+                    state.apply(&event, shared.started_at.elapsed());
+                }
+                false
+            }
+            Err(RecvTimeoutError::Timeout) => false,
+            Err(RecvTimeoutError::Disconnected) => true,
+        };
+
+        let is_stopped: bool = is_disconnected || shared.key_reader_state.is_stopped.load(Ordering::SeqCst);
+        if is_stopped {
+            while let Ok(event) = receiver.try_recv() {
+                shared.state.lock().unwrap().apply(&event, shared.started_at.elapsed());
+            }
+        }
+        // Held while drawing, so that the screen is not drawn over a prompt
+        let is_suspended = shared.key_reader_state.is_suspended.lock().unwrap();
+        if !*is_suspended {
+            shared.draw();
+        }
+        drop(is_suspended);
+        if is_stopped {
+            break;
+        }
+    }
+}
+
+/// Read the keys for selecting steps and scrolling their output
+#[cfg(unix)]
+fn read_keys(shared: Arc<DashboardShared>) {
+    crate::key_mode::read_keys(&shared.key_reader_state, |keys| {
+        {
+            let mut state = shared.state.lock().unwrap();
+            match keys {
+                b"\x1b[A" | b"k" => state.select_previous_step(),
+                b"\x1b[B" | b"j" => state.select_next_step(),
+                b"\x1b[5~" => state.scroll_up(SCROLL_PAGE_LINES),
+                b"\x1b[6~" => state.scroll_down(SCROLL_PAGE_LINES),
+                _ => return,
+            }
+        }
+        shared.draw();
+    });
+}
+
+/// Switch to the alternate screen, and read keys as they are pressed
+/// without echoing them. Ctrl-C still interrupts.
+pub(crate) fn take_terminal() {
+    if IS_TERMINAL_TAKEN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    take_key_mode();
+
+    let mut stdout = std::io::stdout().lock();
+    let _ = stdout.write_all(ENTER_ALTERNATE_SCREEN.as_bytes());
+    let _ = stdout.flush();
+}
+
+/// Give the terminal back as it was before the dashboard. Does nothing
+/// when the dashboard does not have it, so it is safe to call from the
+/// panic hook and the interrupt handler.
+pub fn restore_terminal() {
+    if !IS_TERMINAL_TAKEN.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    restore_key_mode();
+
+    let mut stdout = std::io::stdout();
+    let _ = stdout.write_all(LEAVE_ALTERNATE_SCREEN.as_bytes());
+    let _ = stdout.flush();
+}
+
+/// Restore the terminal, the mode of its keys and its title before a
+/// panic is reported, so that the report is readable
+pub(crate) fn install_panic_hook() {
+    let previous_hook = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        restore_terminal();
+        restore_key_mode();
+        restore_terminal_title();
+        previous_hook(info);
+    }));
+}
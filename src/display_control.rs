@@ -1,6 +1,62 @@
+use std::{
+    cell::RefCell,
+    collections::{BTreeSet, VecDeque},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Mutex,
+    },
+    time::{Duration, Instant},
+};
+
 use console::{style, Term};
 use prettytable::{Cell, Row, Table};
 
+use crate::{
+    core::{
+        builtin::truncate_value,
+        events::{ChainEvent, EventSender},
+        io_profile::IoUsage,
+        options::DisplayOutput,
+    },
+    terminal_title::update_terminal_title,
+};
+
+/// Width of the output when it is not a terminal, e.g. in logs
+pub const DEFAULT_TERMINAL_WIDTH: usize = 120;
+
+/// How long a measured terminal width is used before measuring again,
+/// so that resizing is picked up without a syscall per line
+const TERMINAL_WIDTH_TTL: Duration = Duration::from_millis(250);
+
+/// Receives the messages instead of the terminal while the dashboard of
+/// `cchain run --tui` owns the screen
+static DISPLAY_CAPTURE: Mutex<Option<EventSender>> = Mutex::new(None);
+
+thread_local! {
+    /// Receives the messages displayed by this thread instead of
+    /// `DISPLAY_CAPTURE`, e.g. the warnings of a check running next to
+    /// other checks
+    static THREAD_DISPLAY_CAPTURE: RefCell<Option<EventSender>> = const { RefCell::new(None) };
+}
+
+/// Drops the messages, e.g. while shell completions print candidates
+static IS_DISPLAY_SILENCED: AtomicBool = AtomicBool::new(false);
+
+/// Shows how the environment of each program differs from the
+/// environment of cchain, with `cchain run --diff-env`
+static IS_ENVIRONMENT_DIFF_SHOWN: AtomicBool = AtomicBool::new(false);
+
+/// Shows a line per finished program instead of the output of the
+/// programs, with `cchain run --summary-only`
+static SUMMARY_DISPLAY: Mutex<Option<(SummaryDisplay, Instant)>> = Mutex::new(None);
+
+/// The width of the terminal and when it was measured
+static TERMINAL_WIDTH: Mutex<Option<(usize, Instant)>> = Mutex::new(None);
+
+/// Characters in the status line shown last, to clear the rows it
+/// wraps into when the terminal is narrowed. `None` once it is ended.
+static STATUS_LINE_LENGTH: Mutex<Option<usize>> = Mutex::new(None);
+
 #[derive(Debug, Clone, Copy)]
 pub enum Level {
     Logging,
@@ -10,7 +66,203 @@ pub enum Level {
     Input
 }
 
+/// Send the messages to the event sender instead of displaying them,
+/// or display them again with `None`. The output of programs is not sent,
+/// as it is reported with their events already.
+pub fn set_display_capture(event_sender: Option<EventSender>) {
+    *DISPLAY_CAPTURE.lock().unwrap() = event_sender;
+}
+
+/// Send the messages displayed by this thread while the function runs to
+/// the event sender, and only them, so that checks running at the same
+/// time each get their own messages
+pub fn with_thread_display_capture<T>(event_sender: EventSender, function: impl FnOnce() -> T) -> T {
+    /// Puts the capture back when the function returns or panics
+    struct CaptureGuard(Option<EventSender>);
+
+    impl Drop for CaptureGuard {
+        fn drop(&mut self) {
+            let previous: Option<EventSender> = self.0.take();
+            THREAD_DISPLAY_CAPTURE.with(|capture| *capture.borrow_mut() = previous);
+        }
+    }
+
+    let _guard = CaptureGuard(THREAD_DISPLAY_CAPTURE.with(|capture| capture.replace(Some(event_sender))));
+    function()
+}
+
+/// Drop the messages instead of displaying them, so that only the
+/// output meant for other programs is printed
+pub fn set_display_silenced(is_silenced: bool) {
+    IS_DISPLAY_SILENCED.store(is_silenced, Ordering::SeqCst);
+}
+
+/// Show how the environment of each program differs from the environment
+/// of cchain, before the program starts and in dry runs
+pub fn set_environment_diff_shown(is_shown: bool) {
+    IS_ENVIRONMENT_DIFF_SHOWN.store(is_shown, Ordering::SeqCst);
+}
+
+pub fn is_environment_diff_shown() -> bool {
+    IS_ENVIRONMENT_DIFF_SHOWN.load(Ordering::SeqCst)
+}
+
+/// Show a line per finished program instead of the output of the
+/// programs and the command lines, whatever their `display_output`
+pub fn set_summary_only(is_summary_only: bool) {
+    *SUMMARY_DISPLAY.lock().unwrap() = is_summary_only.then(|| (SummaryDisplay::default(), Instant::now()));
+}
+
+pub fn is_summary_only() -> bool {
+    SUMMARY_DISPLAY.lock().unwrap().is_some()
+}
+
+/// Follow an event of the chain for `--summary-only` and the title of the
+/// terminal, and show the line it completes, if any
+pub fn display_event(event: &ChainEvent) {
+    update_terminal_title(event);
+    let line: Option<(bool, String)> = match &mut *SUMMARY_DISPLAY.lock().unwrap() {
+        Some((summary_display, started_at)) => summary_display.push(event, started_at.elapsed()),
+        None => None,
+    };
+
+    match line {
+        Some((true, line)) => display_message(Level::Logging, &line),
+        Some((false, line)) => display_message(Level::Error, &line),
+        None => {}
+    }
+}
+
+/// Turns the events of a chain into a header and a status line per
+/// finished program, for `cchain run --summary-only`
+#[derive(Debug, Clone, Default)]
+pub struct SummaryDisplay {
+    program_labels: Vec<String>,
+    /// When the programs first started, by their indices
+    started_at: Vec<Option<Duration>>,
+    /// The bytes of output and the exit code of the last attempt
+    output_bytes: Vec<usize>,
+    exit_codes: Vec<Option<i32>>,
+    /// The usage of the last attempt, with `--profile-io`
+    io_usages: Vec<Option<IoUsage>>,
+    /// Programs already shown as finished, as a failure can be reported
+    /// by both the program and the chain
+    finished: BTreeSet<usize>,
+}
+
+impl SummaryDisplay {
+    fn ensure_program(&mut self, program_index: usize) {
+        if self.started_at.len() <= program_index {
+            self.started_at.resize(program_index + 1, None);
+            self.output_bytes.resize(program_index + 1, 0);
+            self.exit_codes.resize(program_index + 1, None);
+            self.io_usages.resize(program_index + 1, None);
+        }
+    }
+
+    /// Take an event that happened at a time since the start of the run
+    ///
+    /// # Returns
+    ///
+    /// Whether the line tells a success, and the line to show, if any
+    pub fn push(&mut self, event: &ChainEvent, at: Duration) -> Option<(bool, String)> {
+        match event {
+            ChainEvent::ChainStarted { program_labels } => {
+                self.program_labels = program_labels.clone();
+                Some((
+                    true,
+                    format!(
+                        "Running {} program(s). Only their statuses and the output of failures are shown.",
+                        program_labels.len()
+                    ),
+                ))
+            }
+            ChainEvent::ProgramStarted { program_index } => {
+                self.ensure_program(*program_index);
+                self.started_at[*program_index].get_or_insert(at);
+                // Retries count their own output
+                self.output_bytes[*program_index] = 0;
+                self.exit_codes[*program_index] = None;
+                self.io_usages[*program_index] = None;
+                None
+            }
+            ChainEvent::ProgramOutput { program_index, text } => {
+                self.ensure_program(*program_index);
+                self.output_bytes[*program_index] += text.len();
+                None
+            }
+            ChainEvent::ProcessExited { program_index, exit_code, io_usage } => {
+                self.ensure_program(*program_index);
+                self.exit_codes[*program_index] = *exit_code;
+                self.io_usages[*program_index] = *io_usage;
+                None
+            }
+            ChainEvent::ProgramFinished { program_index, succeeded } => {
+                if !self.finished.insert(*program_index) {
+                    return None;
+                }
+                self.ensure_program(*program_index);
+                let duration: Duration = at.saturating_sub(self.started_at[*program_index].unwrap_or(at));
+                let exit_code: String = match self.exit_codes[*program_index] {
+                    Some(exit_code) => format!("exit {}", exit_code),
+                    None => "no exit code".to_string(),
+                };
+                let io_usage: String = self.io_usages[*program_index]
+                    .map(|io_usage| format!(", {}", io_usage))
+                    .unwrap_or_default();
+                Some((
+                    *succeeded,
+                    format!(
+                        "{} #{} {} ({}ms, {}, {} bytes of output{})",
+                        if *succeeded { "✔" } else { "✘" },
+                        program_index,
+                        self.program_labels.get(*program_index).map_or("", String::as_str),
+                        duration.as_millis(),
+                        exit_code,
+                        format_count(self.output_bytes[*program_index]),
+                        io_usage
+                    ),
+                ))
+            }
+            ChainEvent::RetryScheduled { .. } | ChainEvent::Message { .. } | ChainEvent::ChainFinished { .. } => None,
+        }
+    }
+}
+
+/// Send a message to the capture, if any
+///
+/// # Returns
+///
+/// Whether the message is captured, and so is not to be displayed
+fn capture_message(message: &str) -> bool {
+    let is_thread_captured: bool = THREAD_DISPLAY_CAPTURE.with(|capture| match &*capture.borrow() {
+        Some(event_sender) => {
+            event_sender.send(ChainEvent::Message { text: message.to_string() });
+            true
+        }
+        None => false,
+    });
+    if is_thread_captured {
+        return true;
+    }
+
+    match &*DISPLAY_CAPTURE.lock().unwrap() {
+        Some(event_sender) => {
+            event_sender.send(ChainEvent::Message { text: message.to_string() });
+            true
+        }
+        None => false,
+    }
+}
+
+fn is_captured() -> bool {
+    THREAD_DISPLAY_CAPTURE.with(|capture| capture.borrow().is_some()) || DISPLAY_CAPTURE.lock().unwrap().is_some()
+}
+
 pub fn display_command_line(terminal: &Term, message: &str) {
+    if is_captured() {
+        return;
+    }
     let indentation: String = "    ".to_string();
     for line in message.lines() {
         terminal.write_line(
@@ -19,8 +271,115 @@ pub fn display_command_line(terminal: &Term, message: &str) {
     }
 }
 
+/// Lines shown at the start and at the end of the output of programs
+/// with `"display_output": "summary"`
+pub const SUMMARY_OUTPUT_LINES: usize = 10;
+
+/// Picks the output of a command to show as it is read, e.g. only the
+/// first and the last lines of a noisy install
+#[derive(Debug, Clone, Default)]
+pub struct LiveOutputFilter {
+    display_output: DisplayOutput,
+    shown_lines: usize,
+    /// The last lines read after the first ones, shown at the end
+    tail: VecDeque<String>,
+    suppressed_lines: usize,
+    /// The start of a line whose end has not been read yet
+    partial_line: String,
+}
+
+impl LiveOutputFilter {
+    pub fn new(display_output: DisplayOutput) -> Self {
+        Self { display_output, ..Default::default() }
+    }
+
+    /// Take output as it is read
+    ///
+    /// # Returns
+    ///
+    /// The part of the output to show now
+    pub fn push(&mut self, text: &str) -> String {
+        match self.display_output {
+            DisplayOutput::Full => text.to_string(),
+            DisplayOutput::Silent => String::new(),
+            DisplayOutput::Summary => {
+                self.partial_line.push_str(text);
+                let mut shown: String = String::new();
+                while let Some(end) = self.partial_line.find('\n') {
+                    let line: String = self.partial_line.drain(..=end).collect();
+                    self.push_line(line.trim_end_matches(['\n', '\r']).to_string(), &mut shown);
+                }
+                shown
+            }
+        }
+    }
+
+    /// Take the end of the output
+    ///
+    /// # Returns
+    ///
+    /// The part of the output left to show, after a marker of the lines
+    /// that are left out, if any
+    pub fn finish(&mut self) -> String {
+        let mut shown: String = String::new();
+        if self.display_output != DisplayOutput::Summary {
+            return shown;
+        }
+        if !self.partial_line.is_empty() {
+            let line: String = std::mem::take(&mut self.partial_line);
+            self.push_line(line, &mut shown);
+        }
+
+        if self.suppressed_lines > 0 {
+            shown.push_str(&format!("… suppressed {} lines …\n", format_count(self.suppressed_lines)));
+        }
+        for line in self.tail.drain(..) {
+            shown.push_str(&line);
+            shown.push('\n');
+        }
+
+        shown
+    }
+
+    fn push_line(&mut self, line: String, shown: &mut String) {
+        if self.shown_lines < SUMMARY_OUTPUT_LINES {
+            self.shown_lines += 1;
+            shown.push_str(&line);
+            shown.push('\n');
+            return;
+        }
+
+        self.tail.push_back(line);
+        if self.tail.len() > SUMMARY_OUTPUT_LINES {
+            self.tail.pop_front();
+            self.suppressed_lines += 1;
+        }
+    }
+}
+
+/// Format a count with thousands separators, e.g. `4,312`
+fn format_count(count: usize) -> String {
+    let digits: String = count.to_string();
+    let mut formatted: String = String::new();
+    for (index, digit) in digits.chars().enumerate() {
+        if index > 0 && (digits.len() - index).is_multiple_of(3) {
+            formatted.push(',');
+        }
+        formatted.push(digit);
+    }
+
+    formatted
+}
+
 pub fn display_message(level: Level, message: &str) {
+    if IS_DISPLAY_SILENCED.load(Ordering::SeqCst) {
+        return;
+    }
     let indentation: String = ">> ".to_string();
+    // Prompts are asked with the dashboard suspended, so they are never captured
+    if !matches!(level, Level::Input) && capture_message(&format!("{}{}", indentation, message)) {
+        return;
+    }
 
     match level {
         Level::Logging => println!("{}{}", indentation, style(message).green()),
@@ -31,19 +390,129 @@ pub fn display_message(level: Level, message: &str) {
     }
 }
 
+/// Get the width of the terminal, or the default when the output is not a
+/// terminal. The width is measured again after a short while, so that the
+/// output follows when the terminal is resized during a run.
+pub fn get_terminal_width() -> usize {
+    let mut terminal_width = TERMINAL_WIDTH.lock().unwrap();
+    if let Some((width, measured_at)) = *terminal_width {
+        if measured_at.elapsed() < TERMINAL_WIDTH_TTL {
+            return width;
+        }
+    }
+    let width: usize =
+        Term::stdout().size_checked().map_or(DEFAULT_TERMINAL_WIDTH, |(_, columns)| columns as usize);
+    *terminal_width = Some((width, Instant::now()));
+
+    width
+}
+
+/// Cut a status line to the width, so that it stays on one row and can
+/// be replaced in place
+pub fn render_status_line(message: &str, width: usize) -> String {
+    // The cursor waits after the message, so it takes a column too
+    truncate_value(&format!(">> {}", message), width.saturating_sub(1).max(10))
+}
+
+/// Display a message that replaces the previous status line in place,
+/// without ending the line. Only for terminals.
+pub fn display_status_line(terminal: &Term, message: &str) {
+    if is_captured() {
+        return;
+    }
+    let width: usize = get_terminal_width();
+    let line: String = render_status_line(message, width);
+    let mut status_line_length = STATUS_LINE_LENGTH.lock().unwrap();
+    let _ = terminal.clear_line();
+    // A line that was written wider than the terminal is now has wrapped
+    // into more rows, which are cleared too
+    if let Some(length) = *status_line_length {
+        let rows: usize = length.div_ceil(width.max(1));
+        if rows > 1 {
+            let _ = terminal.clear_last_lines(rows - 1);
+        }
+    }
+    *status_line_length = Some(line.chars().count());
+    let _ = terminal.write_str(&style(line).red().to_string());
+}
+
+/// End the status line, so that the next output starts on a new line
+pub fn end_status_line(terminal: &Term) {
+    *STATUS_LINE_LENGTH.lock().unwrap() = None;
+    if is_captured() {
+        return;
+    }
+    let _ = terminal.write_line("");
+}
+
+/// Display a message between rules, to separate what follows from
+/// the output before it
+pub fn display_banner(message: &str) {
+    if capture_message(&format!("== {} ==", message)) {
+        return;
+    }
+    let (rule, line) = render_banner(message, get_terminal_width());
+    println!("{}", style(&rule).cyan());
+    println!("{}", style(line).cyan().bold());
+    println!("{}", style(&rule).cyan());
+}
+
+/// Render the rule and the line of a banner, no wider than the width
+pub fn render_banner(message: &str, width: usize) -> (String, String) {
+    let line: String = truncate_value(&format!("== {} ==", message), width.max(10));
+    let rule: String = "=".repeat(line.chars().count());
+
+    (rule, line)
+}
+
 pub fn display_tree_message(indent_level: usize, message: &str) {
     let indentation: String = "\t".repeat(indent_level);
+    if capture_message(&format!("{}>> {}", indentation, message)) {
+        return;
+    }
     println!("{}>> {}", indentation, style(message).green());
 }
 
-pub fn display_form(column_labels: Vec<&str>, rows: &Vec<Vec<String>>) {
-    let mut table = Table::new();
-    let top_line: Vec<Cell> = column_labels.iter().map(|item| Cell::new(item)).collect();
-    table.add_row(Row::new(top_line));
+pub fn display_form(column_labels: Vec<&str>, rows: &[Vec<String>]) {
+    let table: Table = render_form(&column_labels, rows, get_terminal_width());
+    if !capture_message(table.to_string().trim_end()) {
+        table.printstd();
+    }
+}
 
+/// Lay out a form no wider than the width, when it can be. The widest
+/// columns are cut first, down to a few characters each.
+pub fn render_form(column_labels: &[&str], rows: &[Vec<String>], width: usize) -> Table {
+    const MIN_COLUMN_WIDTH: usize = 6;
+    let cell_width = |cell: &str| cell.lines().map(|line| line.chars().count()).max().unwrap_or(0);
+
+    let mut column_widths: Vec<usize> = column_labels.iter().map(|label| cell_width(label)).collect();
+    for row in rows {
+        for (index, cell) in row.iter().enumerate() {
+            if let Some(column_width) = column_widths.get_mut(index) {
+                *column_width = (*column_width).max(cell_width(cell));
+            }
+        }
+    }
+    // Each column has a border and a space on each side
+    let borders: usize = column_widths.len() * 3 + 1;
+    while column_widths.iter().sum::<usize>() + borders > width {
+        let Some(widest) = column_widths.iter_mut().filter(|column_width| **column_width > MIN_COLUMN_WIDTH).max() else {
+            break;
+        };
+        *widest -= 1;
+    }
+
+    let fit_cell = |cell: &str, index: usize| -> Cell {
+        let column_width: usize = column_widths.get(index).copied().unwrap_or(usize::MAX);
+        let lines: Vec<String> = cell.lines().map(|line| truncate_value(line, column_width)).collect();
+        Cell::new(&lines.join("\n"))
+    };
+    let mut table = Table::new();
+    table.add_row(Row::new(column_labels.iter().enumerate().map(|(index, label)| fit_cell(label, index)).collect()));
     for row in rows {
-        table.add_row(Row::new(row.iter().map(|item| Cell::new(item)).collect()));
+        table.add_row(Row::new(row.iter().enumerate().map(|(index, cell)| fit_cell(cell, index)).collect()));
     }
 
-    table.printstd();
+    table
 }
\ No newline at end of file
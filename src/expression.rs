@@ -0,0 +1,401 @@
+use std::{collections::HashMap, fmt::Display, str::FromStr};
+
+use anyhow::{anyhow, Error, Result};
+
+// note
+// expressions are placeholders like `<<workers * 2>>` or
+// `<<verbose ? --verbose : >>`, which are evaluated over typed variables
+// at injection time.
+// 1. the condition part supports integer arithmetic (`+ - * / %`),
+//    comparisons (`== != < <= > >=`) and boolean logic (`&& || !`).
+// 2. the ternary branches are literal text fragments. A branch may be
+//    wrapped in single quotes to keep leading/trailing spaces or a `:`.
+
+/// Characters that turn a placeholder into an expression
+const OPERATOR_CHARACTERS: [char; 13] = ['?', '+', '*', '/', '%', '(', ')', '=', '!', '<', '>', '&', '|'];
+
+/// A value that a typed variable or an expression can hold
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TypedValue {
+    Integer(i64),
+    Boolean(bool),
+}
+
+impl Display for TypedValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TypedValue::Integer(value) => write!(f, "{}", value),
+            TypedValue::Boolean(value) => write!(f, "{}", value),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Integer(i64),
+    Boolean(bool),
+    Identifier(String),
+    Operator(&'static str),
+    LeftParenthesis,
+    RightParenthesis,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Node {
+    Literal(TypedValue),
+    Identifier(String),
+    Unary(&'static str, Box<Node>),
+    Binary(&'static str, Box<Node>, Box<Node>),
+}
+
+/// Literal text used when the condition is true, and when it is false
+type Branches = (String, String);
+
+/// An inline expression found in a placeholder
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expression {
+    /// The original text between the delimiters
+    source: String,
+    /// The expression to evaluate, or the condition of a ternary
+    condition: Node,
+    /// Literal text fragments used when the condition is true/false
+    branches: Option<Branches>,
+}
+
+impl Expression {
+    /// Determine whether the content of a placeholder should be treated
+    /// as an expression rather than a plain variable name.
+    pub fn is_expression(s: &str) -> bool {
+        let trimmed: &str = s.trim();
+        // Anonymous prompts, like `<<?Name?>>`, are never expressions
+        if trimmed.starts_with('?') {
+            return false;
+        }
+        trimmed.contains(OPERATOR_CHARACTERS) || trimmed.contains(" - ")
+    }
+
+    /// Get the original text of the expression
+    pub fn get_source(&self) -> &str {
+        &self.source
+    }
+
+    /// Get the names of all variables referenced by the expression
+    pub fn get_identifiers(&self) -> Vec<String> {
+        let mut identifiers: Vec<String> = Vec::new();
+        Self::collect_identifiers(&self.condition, &mut identifiers);
+        identifiers
+    }
+
+    fn collect_identifiers(node: &Node, identifiers: &mut Vec<String>) {
+        match node {
+            Node::Literal(_) => {}
+            Node::Identifier(name) => {
+                if !identifiers.contains(name) {
+                    identifiers.push(name.clone());
+                }
+            }
+            Node::Unary(_, operand) => Self::collect_identifiers(operand, identifiers),
+            Node::Binary(_, left, right) => {
+                Self::collect_identifiers(left, identifiers);
+                Self::collect_identifiers(right, identifiers);
+            }
+        }
+    }
+
+    /// Evaluate the expression with the values of the typed variables.
+    ///
+    /// # Returns
+    ///
+    /// The text to splice into the argument. A ternary returns the selected
+    /// branch, which may be empty.
+    pub fn evaluate(&self, values: &HashMap<String, TypedValue>) -> Result<String, Error> {
+        let result: TypedValue = Self::evaluate_node(&self.condition, values)?;
+
+        match &self.branches {
+            Some((when_true, when_false)) => match result {
+                TypedValue::Boolean(true) => Ok(when_true.clone()),
+                TypedValue::Boolean(false) => Ok(when_false.clone()),
+                TypedValue::Integer(_) => Err(anyhow!(
+                    "Condition of `{}` must be a boolean, but an integer is found",
+                    self.source
+                )),
+            },
+            None => Ok(result.to_string()),
+        }
+    }
+
+    fn evaluate_node(node: &Node, values: &HashMap<String, TypedValue>) -> Result<TypedValue, Error> {
+        match node {
+            Node::Literal(value) => Ok(*value),
+            Node::Identifier(name) => values
+                .get(name)
+                .copied()
+                .ok_or_else(|| anyhow!("`{}` is not a typed variable with a value", name)),
+            Node::Unary(operator, operand) => {
+                match (*operator, Self::evaluate_node(operand, values)?) {
+                    ("!", TypedValue::Boolean(value)) => Ok(TypedValue::Boolean(!value)),
+                    ("-", TypedValue::Integer(value)) => value
+                        .checked_neg()
+                        .map(TypedValue::Integer)
+                        .ok_or_else(|| anyhow!("Integer overflow when negating {}", value)),
+                    (operator, value) => Err(anyhow!("Operator `{}` cannot be applied to {}", operator, value)),
+                }
+            }
+            Node::Binary(operator, left, right) => {
+                let left: TypedValue = Self::evaluate_node(left, values)?;
+                let right: TypedValue = Self::evaluate_node(right, values)?;
+                Self::apply_binary_operator(operator, left, right)
+            }
+        }
+    }
+
+    fn apply_binary_operator(operator: &str, left: TypedValue, right: TypedValue) -> Result<TypedValue, Error> {
+        let overflow = || anyhow!("Integer overflow in `{} {} {}`", left, operator, right);
+
+        match (left, right) {
+            (TypedValue::Integer(a), TypedValue::Integer(b)) => match operator {
+                "+" => a.checked_add(b).map(TypedValue::Integer).ok_or_else(overflow),
+                "-" => a.checked_sub(b).map(TypedValue::Integer).ok_or_else(overflow),
+                "*" => a.checked_mul(b).map(TypedValue::Integer).ok_or_else(overflow),
+                "/" | "%" if b == 0 => Err(anyhow!("Division by zero in `{} {} {}`", a, operator, b)),
+                "/" => a.checked_div(b).map(TypedValue::Integer).ok_or_else(overflow),
+                "%" => a.checked_rem(b).map(TypedValue::Integer).ok_or_else(overflow),
+                "==" => Ok(TypedValue::Boolean(a == b)),
+                "!=" => Ok(TypedValue::Boolean(a != b)),
+                "<" => Ok(TypedValue::Boolean(a < b)),
+                "<=" => Ok(TypedValue::Boolean(a <= b)),
+                ">" => Ok(TypedValue::Boolean(a > b)),
+                ">=" => Ok(TypedValue::Boolean(a >= b)),
+                _ => Err(anyhow!("Operator `{}` cannot be applied to integers", operator)),
+            },
+            (TypedValue::Boolean(a), TypedValue::Boolean(b)) => match operator {
+                "&&" => Ok(TypedValue::Boolean(a && b)),
+                "||" => Ok(TypedValue::Boolean(a || b)),
+                "==" => Ok(TypedValue::Boolean(a == b)),
+                "!=" => Ok(TypedValue::Boolean(a != b)),
+                _ => Err(anyhow!("Operator `{}` cannot be applied to booleans", operator)),
+            },
+            _ => Err(anyhow!(
+                "Operator `{}` cannot be applied to {} and {}",
+                operator,
+                left,
+                right
+            )),
+        }
+    }
+
+    fn tokenize(s: &str) -> Result<Vec<Token>, Error> {
+        let characters: Vec<char> = s.chars().collect();
+        let mut tokens: Vec<Token> = Vec::new();
+        let mut index: usize = 0;
+
+        while index < characters.len() {
+            let character: char = characters[index];
+
+            if character.is_whitespace() {
+                index += 1;
+                continue;
+            }
+
+            if character.is_ascii_digit() {
+                let start: usize = index;
+                while index < characters.len() && characters[index].is_ascii_digit() {
+                    index += 1;
+                }
+                let text: String = characters[start..index].iter().collect();
+                let value: i64 = text
+                    .parse::<i64>()
+                    .map_err(|_| anyhow!("Integer literal {} is too large", text))?;
+                tokens.push(Token::Integer(value));
+                continue;
+            }
+
+            if character.is_alphabetic() || character == '_' || character == '$' {
+                let start: usize = index;
+                while index < characters.len() {
+                    let current: char = characters[index];
+                    if current.is_alphanumeric() || current == '_' || current == '$' || current == '.' {
+                        index += 1;
+                    } else if current == ':' && characters.get(index + 1) == Some(&':') {
+                        index += 2;
+                    } else {
+                        break;
+                    }
+                }
+                let text: String = characters[start..index].iter().collect();
+                tokens.push(match text.as_str() {
+                    "true" => Token::Boolean(true),
+                    "false" => Token::Boolean(false),
+                    _ => Token::Identifier(text),
+                });
+                continue;
+            }
+
+            let next: Option<char> = characters.get(index + 1).copied();
+            let (token, width): (Token, usize) = match (character, next) {
+                ('(', _) => (Token::LeftParenthesis, 1),
+                (')', _) => (Token::RightParenthesis, 1),
+                ('=', Some('=')) => (Token::Operator("=="), 2),
+                ('!', Some('=')) => (Token::Operator("!="), 2),
+                ('<', Some('=')) => (Token::Operator("<="), 2),
+                ('>', Some('=')) => (Token::Operator(">="), 2),
+                ('&', Some('&')) => (Token::Operator("&&"), 2),
+                ('|', Some('|')) => (Token::Operator("||"), 2),
+                ('<', _) => (Token::Operator("<"), 1),
+                ('>', _) => (Token::Operator(">"), 1),
+                ('!', _) => (Token::Operator("!"), 1),
+                ('+', _) => (Token::Operator("+"), 1),
+                ('-', _) => (Token::Operator("-"), 1),
+                ('*', _) => (Token::Operator("*"), 1),
+                ('/', _) => (Token::Operator("/"), 1),
+                ('%', _) => (Token::Operator("%"), 1),
+                _ => return Err(anyhow!("Unexpected character `{}` in expression `{}`", character, s)),
+            };
+            tokens.push(token);
+            index += width;
+        }
+
+        Ok(tokens)
+    }
+
+    /// Split `condition ? when_true : when_false` at the top level.
+    fn split_ternary(s: &str) -> Result<(&str, Option<Branches>), Error> {
+        let question_mark: usize = match s.find('?') {
+            Some(position) => position,
+            None => return Ok((s, None)),
+        };
+
+        let rest: &str = &s[question_mark + 1..];
+        let (when_true, when_false): (String, String) = if rest.trim_start().starts_with('\'') {
+            // Quoted branches may contain `:`
+            let rest: &str = rest.trim_start();
+            let closing: usize = rest[1..]
+                .find('\'')
+                .ok_or_else(|| anyhow!("Unterminated quote in expression `{}`", s))?
+                + 1;
+            let after: &str = rest[closing + 1..].trim_start();
+            let when_false: &str = after
+                .strip_prefix(':')
+                .ok_or_else(|| anyhow!("Expected `:` after `?` in expression `{}`", s))?;
+            (rest[1..closing].to_string(), Self::unquote_branch(when_false))
+        } else {
+            let colon: usize = rest
+                .find(':')
+                .ok_or_else(|| anyhow!("Expected `:` after `?` in expression `{}`", s))?;
+            (Self::unquote_branch(&rest[..colon]), Self::unquote_branch(&rest[colon + 1..]))
+        };
+
+        Ok((&s[..question_mark], Some((when_true, when_false))))
+    }
+
+    fn unquote_branch(s: &str) -> String {
+        let trimmed: &str = s.trim();
+        if trimmed.len() >= 2 && trimmed.starts_with('\'') && trimmed.ends_with('\'') {
+            return trimmed[1..trimmed.len() - 1].to_string();
+        }
+
+        trimmed.to_string()
+    }
+}
+
+/// Operators of the same precedence, from the loosest to the tightest
+type PrecedenceLevel = &'static [&'static str];
+
+/// A recursive descent parser over the tokens of an expression
+struct Parser<'a> {
+    tokens: &'a [Token],
+    position: usize,
+    source: &'a str,
+}
+
+impl<'a> Parser<'a> {
+    const BINARY_OPERATORS: [PrecedenceLevel; 6] = [
+        &["||"],
+        &["&&"],
+        &["==", "!="],
+        &["<", "<=", ">", ">="],
+        &["+", "-"],
+        &["*", "/", "%"],
+    ];
+
+    fn parse(&mut self) -> Result<Node, Error> {
+        let node: Node = self.parse_binary(0)?;
+        if self.position < self.tokens.len() {
+            return Err(anyhow!("Unexpected token {:?} in expression `{}`", self.tokens[self.position], self.source));
+        }
+
+        Ok(node)
+    }
+
+    fn parse_binary(&mut self, level: usize) -> Result<Node, Error> {
+        if level >= Self::BINARY_OPERATORS.len() {
+            return self.parse_unary();
+        }
+
+        let mut left: Node = self.parse_binary(level + 1)?;
+        while let Some(Token::Operator(operator)) = self.tokens.get(self.position) {
+            if !Self::BINARY_OPERATORS[level].contains(operator) {
+                break;
+            }
+            self.position += 1;
+            let right: Node = self.parse_binary(level + 1)?;
+            left = Node::Binary(operator, Box::new(left), Box::new(right));
+        }
+
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> Result<Node, Error> {
+        match self.tokens.get(self.position) {
+            Some(Token::Operator(operator)) if *operator == "!" || *operator == "-" => {
+                self.position += 1;
+                Ok(Node::Unary(operator, Box::new(self.parse_unary()?)))
+            }
+            _ => self.parse_primary(),
+        }
+    }
+
+    fn parse_primary(&mut self) -> Result<Node, Error> {
+        let token: Option<&Token> = self.tokens.get(self.position);
+        self.position += 1;
+
+        match token {
+            Some(Token::Integer(value)) => Ok(Node::Literal(TypedValue::Integer(*value))),
+            Some(Token::Boolean(value)) => Ok(Node::Literal(TypedValue::Boolean(*value))),
+            Some(Token::Identifier(name)) => Ok(Node::Identifier(name.clone())),
+            Some(Token::LeftParenthesis) => {
+                let node: Node = self.parse_binary(0)?;
+                match self.tokens.get(self.position) {
+                    Some(Token::RightParenthesis) => {
+                        self.position += 1;
+                        Ok(node)
+                    }
+                    _ => Err(anyhow!("Missing `)` in expression `{}`", self.source)),
+                }
+            }
+            Some(token) => Err(anyhow!("Unexpected token {:?} in expression `{}`", token, self.source)),
+            None => Err(anyhow!("Unexpected end of expression `{}`", self.source)),
+        }
+    }
+}
+
+impl FromStr for Expression {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (condition, branches) = Self::split_ternary(s)?;
+        let tokens: Vec<Token> = Self::tokenize(condition)?;
+        if tokens.is_empty() {
+            return Err(anyhow!("Expression `{}` is empty", s));
+        }
+
+        let mut parser = Parser { tokens: &tokens, position: 0, source: s };
+        let condition: Node = parser.parse()?;
+
+        Ok(Self {
+            source: s.to_string(),
+            condition,
+            branches,
+        })
+    }
+}
@@ -1,22 +1,54 @@
-use std::{process::Command, str::FromStr};
+#[cfg(feature = "llm")]
+use std::process::Command;
+use std::str::FromStr;
 
 use anyhow::anyhow;
+#[cfg(feature = "llm")]
 use console::Term;
 use regex;
 
+#[cfg(feature = "llm")]
 use crate::{commons::utility::input_message, display_control::{display_command_line, display_message, Level}, generations::llm::LLM};
+#[cfg(not(feature = "llm"))]
+use crate::commons::features::Feature;
+
+/// A parameter of a function call: a quoted literal, e.g. `'text'`, or a
+/// chain variable written without quotes, e.g. `<<diff_output>>`
+const PARAMETER_PATTERN: &str = r"'(.*)'|<<([^<>]*)>>";
+
+/// The functions that `execute` runs. A value shaped like a call with a
+/// single parameter or a variable parameter is only a call when it names
+/// one of them, so that arguments like `print('x')` stay text.
+pub const FUNCTION_NAMES: [&str; 3] = ["llm_generate", "file", "env"];
+
+/// The value of a chain variable that a function takes as a parameter
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VariableParameterValue {
+    pub value: String,
+    /// Whether the variable is declared `secret`, so that the value is
+    /// masked in the command line that the function is in
+    pub is_secret: bool,
+}
 
 #[derive(Debug, Clone)]
 pub struct Function {
     name: String,
     parameters: Vec<String>,
+    /// Whether each parameter references a chain variable, and holds
+    /// its name until it is resolved, then its value
+    variable_parameters: Vec<bool>,
 }
 
 impl FromStr for Function {
     type Err = anyhow::Error;
 
+    /// Parse a value that is a function call as a whole. Text around a
+    /// call, e.g. `echo env('HOME')`, is not a function call.
     fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let re = regex::Regex::new(r"(\w+)\s*\(\s*'(.*)'\s*,\s*'(.*)'\s*\)")?;
+        let re = regex::Regex::new(&format!(
+            r"^\s*(\w+)\s*\(\s*(?:{})\s*,\s*(?:{})\s*\)\s*$",
+            PARAMETER_PATTERN, PARAMETER_PATTERN
+        ))?;
 
         if let Some(caps) = re.captures(s) {
             let func_name: String = caps
@@ -24,20 +56,35 @@ impl FromStr for Function {
                 .ok_or_else(|| anyhow::anyhow!("Failed to capture function name"))?
                 .as_str()
                 .to_string();
-            let arg1 = caps
-                .get(2)
-                .ok_or_else(|| anyhow::anyhow!("Failed to capture first argument"))?
-                .as_str()
-                .to_string();
-            let arg2 = caps
-                .get(3)
-                .ok_or_else(|| anyhow::anyhow!("Failed to capture second argument"))?
-                .as_str()
-                .to_string();
+            let (arg1, is_variable1) = Self::get_parameter(&caps, 2)
+                .ok_or_else(|| anyhow::anyhow!("Failed to capture first argument"))?;
+            let (arg2, is_variable2) = Self::get_parameter(&caps, 4)
+                .ok_or_else(|| anyhow::anyhow!("Failed to capture second argument"))?;
+            // Calls with two quoted parameters were always taken as calls
+            if (is_variable1 || is_variable2) && !Self::is_known_function(&func_name) {
+                return Err(anyhow!("`{}` is not a function", func_name));
+            }
 
             return Ok(Function {
                 name: func_name,
                 parameters: vec![arg1, arg2],
+                variable_parameters: vec![is_variable1, is_variable2],
+            });
+        }
+
+        // Functions with a single parameter, e.g. `file('path')`
+        let re = regex::Regex::new(&format!(r"^\s*(\w+)\s*\(\s*(?:{})\s*\)\s*$", PARAMETER_PATTERN))?;
+
+        if let Some(caps) = re.captures(s) {
+            if !Self::is_known_function(&caps[1]) {
+                return Err(anyhow!("`{}` is not a function", &caps[1]));
+            }
+            let (parameter, is_variable) = Self::get_parameter(&caps, 2)
+                .ok_or_else(|| anyhow::anyhow!("Failed to capture the argument"))?;
+            return Ok(Function {
+                name: caps[1].to_string(),
+                parameters: vec![parameter],
+                variable_parameters: vec![is_variable],
             });
         }
 
@@ -45,6 +92,20 @@ impl FromStr for Function {
     }
 }
 
+impl std::fmt::Display for Function {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let parameters: Vec<String> = self
+            .parameters
+            .iter()
+            .zip(&self.variable_parameters)
+            .map(|(parameter, is_variable)| {
+                if *is_variable { format!("<<{}>>", parameter) } else { format!("'{}'", parameter) }
+            })
+            .collect();
+        write!(f, "{}({})", self.name, parameters.join(", "))
+    }
+}
+
 impl Function {
     pub fn get_name(&self) -> &str {
         &self.name
@@ -54,16 +115,101 @@ impl Function {
         &self.parameters
     }
 
+    pub fn is_known_function(name: &str) -> bool {
+        FUNCTION_NAMES.contains(&name)
+    }
+
+    /// Get the parameter captured at the group of the quoted literal,
+    /// or else the variable right after it
+    fn get_parameter(captures: &regex::Captures, group: usize) -> Option<(String, bool)> {
+        if let Some(literal) = captures.get(group) {
+            return Some((literal.as_str().to_string(), false));
+        }
+
+        captures.get(group + 1).map(|variable| (variable.as_str().trim().to_string(), true))
+    }
+
+    /// Get the names of the chain variables that the parameters reference
+    pub fn get_variable_references(&self) -> Vec<&str> {
+        self.parameters
+            .iter()
+            .zip(&self.variable_parameters)
+            .filter(|(_, is_variable)| **is_variable)
+            .map(|(parameter, _)| parameter.as_str())
+            .collect()
+    }
+
+    /// Put the values of the chain variables in the parameters that
+    /// reference them, looked up by name
+    pub fn resolve_variables(&mut self, lookup: &dyn Fn(&str) -> Result<String, anyhow::Error>) -> Result<(), anyhow::Error> {
+        for (parameter, is_variable) in self.parameters.iter_mut().zip(&self.variable_parameters) {
+            if *is_variable {
+                *parameter = lookup(parameter)?;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Put the value of a variable in the placeholders of the quoted
+    /// parameters, leaving the parameters that reference variables to
+    /// `resolve_variables`
+    pub fn inject_literal_placeholders(&mut self, replace: &mut impl FnMut(&str) -> String) {
+        for (parameter, is_variable) in self.parameters.iter_mut().zip(&self.variable_parameters) {
+            if !*is_variable {
+                *parameter = replace(parameter);
+            }
+        }
+    }
+
     pub fn execute(&self) -> Result<String, anyhow::Error> {
         match self.name.as_str() {
             "llm_generate" => self.llm_generate(),
+            "file" => self.file(),
+            "env" => self.env(),
             _ => Err(anyhow::anyhow!("Function not found")),
         }
     }
 
+    /// Read the content of the file at the first parameter, without
+    /// the trailing newlines
+    fn file(&self) -> Result<String, anyhow::Error> {
+        let path: &String = self
+            .parameters
+            .first()
+            .ok_or_else(|| anyhow!("`file` takes the path of a file"))?;
+        let content: String = std::fs::read_to_string(path)
+            .map_err(|error| anyhow!("Failed to read {} for `file`: {}", path, error))?;
+
+        Ok(content.trim_end_matches(['\n', '\r']).to_string())
+    }
+
+    /// Read the environment variable named by the first parameter. When
+    /// it is unset, the second parameter is the default if given.
+    fn env(&self) -> Result<String, anyhow::Error> {
+        let name: &String = self
+            .parameters
+            .first()
+            .ok_or_else(|| anyhow!("`env` takes the name of an environment variable"))?;
+
+        match (std::env::var(name), self.parameters.get(1)) {
+            (Ok(value), _) => Ok(value),
+            (Err(std::env::VarError::NotPresent), Some(default)) => Ok(default.clone()),
+            (Err(std::env::VarError::NotPresent), None) => {
+                Err(anyhow!("Environment variable {} for `env` is not set", name))
+            }
+            (Err(error), _) => Err(anyhow!("Failed to read environment variable {} for `env`: {}", name, error)),
+        }
+    }
+
+    #[cfg(feature = "llm")]
     fn llm_generate(&self) -> Result<String, anyhow::Error> {
-        // execute the second parameter in the terminal and then get the output
-        let command_output: String = if self.parameters.len() > 1 {
+        // A variable is the context itself, e.g. the output of an earlier
+        // program. Otherwise, execute the second parameter in the
+        // terminal and then get the output
+        let command_output: String = if self.variable_parameters.get(1) == Some(&true) {
+            self.parameters[1].clone()
+        } else if self.parameters.len() > 1 {
             let parts: Vec<&str> = self.parameters[1].split_whitespace().collect();
             let output = Command::new(parts[0])
                 .args(&parts[1..])
@@ -100,9 +246,7 @@ impl Function {
 
             display_message(
                 Level::Logging,
-                &format!(
-                    "Function executed successfully with result: "
-                ),
+                "Function executed successfully with result: ",
             );
             display_command_line(&Term::stdout(), &response);
 
@@ -133,4 +277,9 @@ impl Function {
         }
 
     }
+
+    #[cfg(not(feature = "llm"))]
+    fn llm_generate(&self) -> Result<String, anyhow::Error> {
+        Err(Feature::Llm.get_unavailable_error("`llm_generate`"))
+    }
 }
@@ -1,16 +1,29 @@
 use std::collections::HashMap;
+use std::path::PathBuf;
+#[cfg(feature = "llm")]
+use std::time::{SystemTime, UNIX_EPOCH};
 
-use anyhow::{Error, Result};
+use anyhow::{anyhow, Error, Result};
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::commons::digest::sha256_hex;
+#[cfg(not(feature = "llm"))]
+use crate::commons::features::Feature;
+use crate::commons::interaction::SharedInteractor;
+use crate::core::chain::Chain;
 use crate::core::interpreter::Interpreter;
+use crate::core::metadata::{ChainFile, ChainMetadata, GenerationProvenance};
 use crate::core::options::FailureHandlingOptions;
 use crate::core::options::StdoutStorageOptions;
 use crate::core::program::Program;
 use crate::display_control::display_message;
 use crate::display_control::Level;
 
+use super::history::{
+    create_history_program, find_replaceable_literals, replace_literals_with_variables, ReplaceableLiteral,
+};
+#[cfg(feature = "llm")]
 use super::llm::LLM;
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -19,20 +32,44 @@ pub struct ParsedCommands {
 }
 
 pub struct ChainCreation {
-    name: Option<String>
+    name: Option<String>,
+    /// Record how a generated chain was made in `generated_by`
+    provenance: bool,
+    /// Include the prompt itself in `generated_by`, not only its digest
+    embed_prompt: bool,
+    /// The directory to save the chain in, instead of the current directory
+    directory: Option<PathBuf>,
 }
 
 impl ChainCreation {
 
     pub fn new(name: Option<String>) -> Self {
-        Self { name }
+        Self { name, provenance: true, embed_prompt: false, directory: None }
+    }
+
+    /// Record the provenance of generated chains, which is the default
+    pub fn with_provenance(mut self, provenance: bool) -> Self {
+        self.provenance = provenance;
+        self
+    }
+
+    /// Include the prompt in the provenance of generated chains
+    pub fn with_embedded_prompt(mut self, embed_prompt: bool) -> Self {
+        self.embed_prompt = embed_prompt;
+        self
+    }
+
+    /// Save the chain in the directory instead of the current directory
+    pub fn with_directory(mut self, directory: PathBuf) -> Self {
+        self.directory = Some(directory);
+        self
     }
 
     pub fn create_filename(&self) -> String {
         if let Some(name) = &self.name {
-            return "cchain_".to_string() + name + ".json";
+            "cchain_".to_string() + name + ".json"
         } else {
-            return "cchain_template.json".to_string();
+            "cchain_template.json".to_string()
         }
     }
     
@@ -78,6 +115,7 @@ impl ChainCreation {
     }
 
     /// Create a chain by using the LLM
+    #[cfg(feature = "llm")]
     pub fn generate_chain(&self, request: String) -> Result<String, Error> {
         let template = ParsedCommands { commands: self.get_template_objects() };
         let prompt: String = format!(
@@ -89,7 +127,8 @@ impl ChainCreation {
                - `command`: Main executable (e.g., "python")
                - `arguments`: Parameters including <<variable>> placeholders
                - `working_directory`: Path to run the command in. Leave null for current directory. 
-               - `interpreter`: Shell to use (e.g., "sh") or null for direct execution
+               - `interpreter`: Shell to use (e.g., "sh") or null for direct execution.
+                 Arguments are quoted for the shell; write {{"raw": "a | b"}} for text the shell should parse
                - `environment_variables_override`: Key-value pairs to override env vars
                - `stdout_stored_to`: Variable name to store output (supports <<>> syntax)
                - `failure_handling_options`: Configure exit behavior and remedy commands
@@ -121,6 +160,7 @@ impl ChainCreation {
             6. **Environment Setup**:
                - Apply environment overrides before command execution
                - Empty string values override existing vars with empty values
+               - Null values remove the vars from the environment of the command
                - Restore original environment after command completion
 
             Example Flow:
@@ -141,19 +181,77 @@ impl ChainCreation {
         
         // Parse the string 
         let parsed_commands: ParsedCommands = serde_json::from_str(&result)?;
-        let commands_string: String = serde_json::to_string_pretty(&parsed_commands.commands)?;
-        
-        return Ok(commands_string);
+        if !self.provenance {
+            return Ok(serde_json::to_string_pretty(&parsed_commands.commands)?);
+        }
+
+        let generated_at: u64 = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+        self.create_generated_chain_file(&request, parsed_commands.commands, llm.get_api_base(), llm.get_model(), generated_at)
+    }
+
+    /// Chains are generated by LLMs, which cchain is built without
+    #[cfg(not(feature = "llm"))]
+    pub fn generate_chain(&self, _request: String) -> Result<String, Error> {
+        Err(Feature::Llm.get_unavailable_error("generating chains with `--prompt`"))
+    }
+
+    /// Put generated programs in a chain file with their `generated_by`
+    /// provenance in the metadata
+    pub fn create_generated_chain_file(
+        &self,
+        request: &str,
+        programs: Vec<Program>,
+        provider: &str,
+        model: &str,
+        generated_at: u64,
+    ) -> Result<String, Error> {
+        let generated_by = GenerationProvenance {
+            tool: format!("cchain {}", clap::crate_version!()),
+            provider: provider.to_string(),
+            model: model.to_string(),
+            generated_at,
+            prompt_sha256: sha256_hex(request.as_bytes()),
+            prompt: self.embed_prompt.then(|| request.to_string()),
+            programs_sha256: GenerationProvenance::get_programs_digest(&programs)?,
+        };
+        let chain_file = ChainFile {
+            metadata: ChainMetadata { generated_by: Some(generated_by), ..Default::default() },
+            programs,
+            ..Default::default()
+        };
+
+        Ok(serde_json::to_string_pretty(&chain_file)?)
+    }
+
+    /// Create a chain of the commands from the shell history, one program
+    /// per command in their order, offering to replace the paths and
+    /// branch names in them with variables
+    pub fn generate_from_history(&self, commands: &[String], interactor: &SharedInteractor) -> Result<String, Error> {
+        if commands.is_empty() {
+            return Err(anyhow!("No commands were selected"));
+        }
+
+        let mut programs: Vec<Program> = commands.iter().map(|command| create_history_program(command)).collect();
+        let literals: Vec<ReplaceableLiteral> = find_replaceable_literals(&mut programs);
+        replace_literals_with_variables(&mut programs, &literals, interactor)?;
+
+        let json: String = serde_json::to_string_pretty::<Vec<Program>>(&programs)?;
+        Chain::from_json_str(&json, &self.create_filename())?;
+
+        Ok(json)
     }
 
     /// Write the generated chain
     pub fn save(&self, json: String) -> Result<(), Error> {
-        let filename: String = self.create_filename();
+        let path: PathBuf = match &self.directory {
+            Some(directory) => directory.join(self.create_filename()),
+            None => PathBuf::from(self.create_filename()),
+        };
         // Write the template JSON to a file
-        std::fs::write(&filename, json)?;
+        std::fs::write(&path, json)?;
         display_message(
             Level::Logging,
-            &format!("Template chain generated: {}", &filename),
+            &format!("Template chain generated: {}", path.display()),
         );
 
         Ok(())
@@ -0,0 +1,443 @@
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, Error, Result};
+use console::{Key, Term};
+
+use crate::{
+    commons::interaction::SharedInteractor,
+    core::{
+        command::{Argument, SHELL_METACHARACTERS},
+        interpreter::Interpreter,
+        options::{FailureHandlingOptions, StdoutStorageOptions},
+        program::Program,
+    },
+};
+
+/// Commands offered from the history when no count is given
+pub const DEFAULT_HISTORY_COUNT: usize = 20;
+
+/// Subcommands of `git` whose other words are branch names
+const GIT_BRANCH_SUBCOMMANDS: &[&str] = &["checkout", "switch", "merge", "rebase", "push", "pull", "branch"];
+
+/// Names of remotes, which are not branch names
+const GIT_REMOTE_NAMES: &[&str] = &["origin", "upstream"];
+
+/// The formats of the history files of the shells
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HistoryFormat {
+    /// One command per line, with `#1700000000` lines before the commands
+    /// when `HISTTIMEFORMAT` is set
+    Bash,
+    /// One command per line, or `: 1700000000:0;command` with
+    /// `EXTENDED_HISTORY`. Lines ending with `\` continue on the next
+    Zsh,
+    /// YAML-like entries of `- cmd: command` followed by `when:` and `paths:`
+    Fish,
+}
+
+impl HistoryFormat {
+    /// Detect the format from the path of a shell, e.g. `$SHELL`
+    pub fn from_shell(shell: &str) -> Option<Self> {
+        match Path::new(shell).file_name()?.to_str()? {
+            "bash" | "sh" => Some(Self::Bash),
+            "zsh" => Some(Self::Zsh),
+            "fish" => Some(Self::Fish),
+            _ => None,
+        }
+    }
+
+    /// Guess the format from the content of a history file, for files
+    /// given with `--history-file`
+    pub fn from_content(content: &str) -> Self {
+        let first_line: &str = content.lines().find(|line| !line.trim().is_empty()).unwrap_or_default();
+        if first_line.starts_with("- cmd: ") {
+            Self::Fish
+        } else if parse_zsh_extended_line(first_line).is_some() {
+            Self::Zsh
+        } else {
+            Self::Bash
+        }
+    }
+
+    /// Get the standard path of the history file in the home directory
+    pub fn get_default_path(&self, home: &Path) -> PathBuf {
+        match self {
+            Self::Bash => home.join(".bash_history"),
+            Self::Zsh => home.join(".zsh_history"),
+            Self::Fish => home.join(".local").join("share").join("fish").join("fish_history"),
+        }
+    }
+}
+
+/// Find the history file of the shell of the user. `$HISTFILE` is only
+/// exported by some setups, but is preferred when it is.
+pub fn find_history_file() -> Result<(PathBuf, HistoryFormat), Error> {
+    let shell: String = std::env::var("SHELL").unwrap_or_default();
+    let format: HistoryFormat = HistoryFormat::from_shell(&shell).ok_or_else(|| {
+        anyhow!("Cannot tell the shell from `$SHELL` ({}), give the history file with `--history-file`", shell)
+    })?;
+    if let Some(history_file) = std::env::var_os("HISTFILE").filter(|_| format != HistoryFormat::Fish) {
+        return Ok((PathBuf::from(history_file), format));
+    }
+    let home: PathBuf = dirs::home_dir().ok_or_else(|| anyhow!("Cannot find the home directory"))?;
+
+    Ok((format.get_default_path(&home), format))
+}
+
+/// Get the command of a zsh line in the extended format, e.g.
+/// `: 1700000000:0;git status`
+fn parse_zsh_extended_line(line: &str) -> Option<&str> {
+    let (header, command) = line.strip_prefix(": ")?.split_once(';')?;
+    let (timestamp, duration) = header.split_once(':')?;
+    if timestamp.chars().all(|c| c.is_ascii_digit()) && duration.chars().all(|c| c.is_ascii_digit()) {
+        Some(command)
+    } else {
+        None
+    }
+}
+
+/// Read the commands in a history file, oldest first
+pub fn parse_history(content: &str, format: HistoryFormat) -> Vec<String> {
+    let mut commands: Vec<String> = Vec::new();
+    match format {
+        HistoryFormat::Bash => {
+            for line in content.lines() {
+                let is_timestamp: bool =
+                    line.len() > 1 && line.starts_with('#') && line[1..].chars().all(|c| c.is_ascii_digit());
+                if !is_timestamp {
+                    commands.push(line.to_string());
+                }
+            }
+        }
+        HistoryFormat::Zsh => {
+            let mut pending: Option<String> = None;
+            for line in content.lines() {
+                let line: &str = match pending {
+                    Some(_) => line,
+                    None => parse_zsh_extended_line(line).unwrap_or(line),
+                };
+                let (text, continues) = match line.strip_suffix('\\') {
+                    Some(text) => (text, true),
+                    None => (line, false),
+                };
+                let command: String = match pending.take() {
+                    Some(previous) => format!("{}\n{}", previous, text),
+                    None => text.to_string(),
+                };
+                if continues {
+                    pending = Some(command);
+                } else {
+                    commands.push(command);
+                }
+            }
+            commands.extend(pending);
+        }
+        HistoryFormat::Fish => {
+            for line in content.lines() {
+                if let Some(command) = line.strip_prefix("- cmd: ") {
+                    commands.push(command.replace("\\n", "\n").replace("\\\\", "\\"));
+                }
+            }
+        }
+    }
+
+    commands.retain(|command| !command.trim().is_empty());
+    commands
+}
+
+/// Get the last commands, without repeats of the command just before,
+/// oldest first
+pub fn get_recent_commands(commands: &[String], count: usize) -> Vec<String> {
+    let mut recent_commands: Vec<String> = Vec::new();
+    for command in commands.iter().rev() {
+        if recent_commands.len() == count {
+            break;
+        }
+        if recent_commands.last() != Some(command) {
+            recent_commands.push(command.clone());
+        }
+    }
+    recent_commands.reverse();
+
+    recent_commands
+}
+
+/// Split a command line into words as a POSIX shell does, with single and
+/// double quotes and backslashes. Returns `None` for unbalanced quotes.
+pub fn split_shell_words(line: &str) -> Option<Vec<String>> {
+    let mut words: Vec<String> = Vec::new();
+    let mut word: Option<String> = None;
+    let mut characters = line.chars();
+    while let Some(c) = characters.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word: &mut String = word.get_or_insert_with(String::new);
+                loop {
+                    match characters.next()? {
+                        '\'' => break,
+                        c => word.push(c),
+                    }
+                }
+            }
+            '"' => {
+                let word: &mut String = word.get_or_insert_with(String::new);
+                loop {
+                    match characters.next()? {
+                        '"' => break,
+                        '\\' => match characters.next()? {
+                            c @ ('"' | '\\' | '$' | '`') => word.push(c),
+                            '\n' => (),
+                            c => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                        },
+                        c => word.push(c),
+                    }
+                }
+            }
+            '\\' => {
+                if let Some(c) = characters.next() {
+                    if c != '\n' {
+                        word.get_or_insert_with(String::new).push(c);
+                    }
+                }
+            }
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+    words.extend(word);
+
+    Some(words)
+}
+
+/// Tell whether a command line needs a shell, e.g. for pipes or
+/// redirections outside of quotes
+fn needs_shell(line: &str) -> bool {
+    let mut unquoted: String = String::new();
+    let mut quote: Option<char> = None;
+    for c in line.chars() {
+        match (quote, c) {
+            (None, '\'' | '"') => quote = Some(c),
+            (Some(open), c) if c == open => quote = None,
+            // `$` still expands in double quotes
+            (Some('"'), '$' | '`') => unquoted.push(c),
+            (Some(_), _) => (),
+            (None, c) => unquoted.push(c),
+        }
+    }
+
+    line.contains('\n') || SHELL_METACHARACTERS.iter().any(|metacharacter| unquoted.contains(metacharacter))
+}
+
+/// Turn a command from the history into a program. Commands that need a
+/// shell run under `sh` as they were typed, and the others are split
+/// into words.
+pub fn create_history_program(line: &str) -> Program {
+    let line: &str = line.trim();
+    let words: Option<Vec<String>> = split_shell_words(line).filter(|words| !words.is_empty());
+    let is_shell_line: bool = words.is_none() || needs_shell(line);
+    let (command, arguments): (String, Vec<String>) = match words {
+        Some(words) if !is_shell_line => (words[0].clone(), words[1..].to_vec()),
+        _ => {
+            let (command, _) = line.split_once(char::is_whitespace).unwrap_or((line, ""));
+            (command.to_string(), Vec::new())
+        }
+    };
+
+    let mut program = Program::new(
+        command,
+        arguments,
+        None,
+        None,
+        None,
+        StdoutStorageOptions::default(),
+        is_shell_line.then_some(Interpreter::Sh),
+        FailureHandlingOptions::default(),
+        None,
+        0,
+    );
+    if is_shell_line {
+        let rest: &str = line.split_once(char::is_whitespace).map(|(_, rest)| rest.trim()).unwrap_or_default();
+        if !rest.is_empty() {
+            *program.get_command_line().get_arguments() = vec![Argument::Raw { raw: rest.to_string() }];
+        }
+    }
+
+    program
+}
+
+/// A literal in the programs that likely changes between runs
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplaceableLiteral {
+    pub value: String,
+    /// What the literal looks like, e.g. `path` or `branch`
+    pub kind: &'static str,
+}
+
+/// Find the paths and branch names in the plain arguments, in order of
+/// their first appearance
+pub fn find_replaceable_literals(programs: &mut [Program]) -> Vec<ReplaceableLiteral> {
+    let mut literals: Vec<ReplaceableLiteral> = Vec::new();
+    for program in programs.iter_mut() {
+        let command_line = program.get_command_line();
+        let is_git: bool = command_line.get_command() == "git";
+        let arguments: Vec<Argument> = command_line.get_arguments().clone();
+        let subcommand: Option<&str> = arguments.first().map(|argument| argument.get_value());
+        let is_branch_subcommand: bool = is_git && subcommand.is_some_and(|subcommand| GIT_BRANCH_SUBCOMMANDS.contains(&subcommand));
+
+        for (index, argument) in arguments.iter().enumerate() {
+            let value: &str = argument.get_value();
+            if argument.is_raw() || value.starts_with('-') || value.contains("<<") {
+                continue;
+            }
+            let kind: Option<&'static str> = if is_branch_subcommand && index > 0 {
+                (!GIT_REMOTE_NAMES.contains(&value) && value != ".").then_some("branch")
+            } else if is_path_like(value) {
+                Some("path")
+            } else {
+                None
+            };
+            if let Some(kind) = kind {
+                if !literals.iter().any(|literal| literal.value == value) {
+                    literals.push(ReplaceableLiteral { value: value.to_string(), kind });
+                }
+            }
+        }
+    }
+
+    literals
+}
+
+fn is_path_like(value: &str) -> bool {
+    if value.contains("://") || value.contains('@') {
+        return false;
+    }
+
+    value.starts_with('/') || value.starts_with("./") || value.starts_with("../") || value.starts_with("~/") || value.contains('/')
+}
+
+/// Offer to replace each literal with a variable. An answer names the
+/// variable, and an empty answer keeps the literal.
+pub fn replace_literals_with_variables(
+    programs: &mut [Program],
+    literals: &[ReplaceableLiteral],
+    interactor: &SharedInteractor,
+) -> Result<(), Error> {
+    for literal in literals {
+        let answer: String = interactor.prompt(&format!(
+            "Replace the {} `{}` with a variable? Enter a name for the variable, or nothing to keep it\n   >",
+            literal.kind, literal.value
+        ))?;
+        let name: &str = answer.trim().trim_start_matches("<<").trim_end_matches(">>");
+        if name.is_empty() {
+            continue;
+        }
+        if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+            return Err(anyhow!("`{}` is not a valid variable name, use letters, digits and `_`", name));
+        }
+
+        let placeholder: String = format!("<<{}>>", name);
+        for program in programs.iter_mut() {
+            for argument in program.get_command_line().get_arguments() {
+                if !argument.is_raw() && argument.get_value() == literal.value {
+                    argument.set_value(placeholder.clone());
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Parse the numbers of the selected commands, e.g. `1,3-5`, into
+/// indices in order. An empty answer selects every command.
+pub fn parse_selection(answer: &str, count: usize) -> Result<Vec<usize>, Error> {
+    if answer.trim().is_empty() {
+        return Ok((0..count).collect());
+    }
+
+    let mut indices: Vec<usize> = Vec::new();
+    for part in answer.split(',').map(str::trim).filter(|part| !part.is_empty()) {
+        let parse_number = |number: &str| -> Result<usize, Error> {
+            match number.trim().parse::<usize>() {
+                Ok(number) if (1..=count).contains(&number) => Ok(number - 1),
+                _ => Err(anyhow!("`{}` is not a number from 1 to {}", number.trim(), count)),
+            }
+        };
+        let (start, end) = match part.split_once('-') {
+            Some((start, end)) => (parse_number(start)?, parse_number(end)?),
+            None => (parse_number(part)?, parse_number(part)?),
+        };
+        for index in start.min(end)..=start.max(end) {
+            if !indices.contains(&index) {
+                indices.push(index);
+            }
+        }
+    }
+    indices.sort();
+
+    Ok(indices)
+}
+
+/// Ask for the commands to include by their numbers
+pub fn select_commands_by_prompt(commands: &[String], interactor: &SharedInteractor) -> Result<Vec<usize>, Error> {
+    let listing: String = commands
+        .iter()
+        .enumerate()
+        .map(|(index, command)| format!("  {:>2}) {}", index + 1, command.replace('\n', " ⏎ ")))
+        .collect::<Vec<String>>()
+        .join("\n");
+    let answer: String = interactor.prompt(&format!(
+        "Recent commands:\n{}\nNumbers of the commands to include, e.g. 1,3-5, or nothing for all\n   >",
+        listing
+    ))?;
+
+    parse_selection(&answer, commands.len())
+}
+
+/// Let the user toggle the commands to include on the terminal, with the
+/// arrow keys to move, space to toggle and enter to confirm
+pub fn select_commands_on_terminal(commands: &[String]) -> Result<Vec<usize>, Error> {
+    let term: Term = Term::stdout();
+    let mut selected: Vec<bool> = vec![false; commands.len()];
+    let mut cursor: usize = commands.len().saturating_sub(1);
+    let width: usize = term.size().1 as usize;
+    let render = |selected: &[bool], cursor: usize| -> Vec<String> {
+        let mut lines: Vec<String> =
+            vec!["Select the commands with space, move with the arrows, and confirm with enter:".to_string()];
+        for (index, command) in commands.iter().enumerate() {
+            let line: String = format!(
+                "{} [{}] {}",
+                if index == cursor { ">" } else { " " },
+                if selected[index] { "x" } else { " " },
+                command.replace('\n', " ⏎ ")
+            );
+            lines.push(line.chars().take(width.saturating_sub(1).max(10)).collect());
+        }
+        lines
+    };
+
+    let mut lines: Vec<String> = render(&selected, cursor);
+    term.write_line(&lines.join("\n"))?;
+    loop {
+        match term.read_key()? {
+            Key::ArrowUp | Key::Char('k') => cursor = cursor.saturating_sub(1),
+            Key::ArrowDown | Key::Char('j') => cursor = (cursor + 1).min(commands.len().saturating_sub(1)),
+            Key::Char(' ') => selected[cursor] = !selected[cursor],
+            Key::Char('a') => {
+                let is_all_selected: bool = selected.iter().all(|is_selected| *is_selected);
+                selected.iter_mut().for_each(|is_selected| *is_selected = !is_all_selected);
+            }
+            Key::Enter => break,
+            Key::Escape | Key::Char('q') => return Err(anyhow!("No commands were selected")),
+            _ => continue,
+        }
+        term.clear_last_lines(lines.len())?;
+        lines = render(&selected, cursor);
+        term.write_line(&lines.join("\n"))?;
+    }
+
+    Ok((0..commands.len()).filter(|index| selected[*index]).collect())
+}
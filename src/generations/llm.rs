@@ -10,6 +10,7 @@ use async_openai::Client;
 #[derive(Debug)]
 pub struct LLM {
     model: String,
+    api_base: String,
     client: Client<OpenAIConfig>
 }
 
@@ -21,12 +22,21 @@ impl LLM {
 
         let llm_configuration: OpenAIConfig = OpenAIConfig::default()
             .with_api_key(api_key)
-            .with_api_base(api_base);
+            .with_api_base(&api_base);
         let client: Client<OpenAIConfig> = async_openai::Client::with_config(
             llm_configuration
         );
 
-        Ok(Self { model, client})
+        Ok(Self { model, api_base, client})
+    }
+
+    pub fn get_model(&self) -> &str {
+        &self.model
+    }
+
+    /// Get the API base, which identifies the provider
+    pub fn get_api_base(&self) -> &str {
+        &self.api_base
     }
 
     pub fn generate(&self, prompt: String) -> Result<String, Error> {
@@ -58,7 +68,7 @@ impl LLM {
                     return Ok(content);
                 }
 
-                return Err(anyhow!("No response is retrieved from the LLM"));
+                Err(anyhow!("No response is retrieved from the LLM"))
             }
         )?;
 
@@ -95,7 +105,7 @@ impl LLM {
                     return Ok(content);
                 }
 
-                return Err(anyhow!("No response is retrieved from the LLM"));
+                Err(anyhow!("No response is retrieved from the LLM"))
             }
         )?;
 
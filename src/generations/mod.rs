@@ -1,2 +1,4 @@
 pub mod create;
-pub mod llm;
\ No newline at end of file
+pub mod history;
+#[cfg(feature = "llm")]
+pub mod llm;
@@ -0,0 +1,134 @@
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc, Mutex,
+};
+
+use anyhow::Error;
+
+use crate::commons::interaction::{Interactor, TerminalInteractor};
+
+/// Whether the keys are read as they are pressed, by the dashboard or the
+/// listener of the pause keys, so that the terminal is only restored
+/// once, whichever of the panic hook, the interrupt handler or the reader
+/// itself comes first
+static IS_KEY_MODE_TAKEN: AtomicBool = AtomicBool::new(false);
+
+#[cfg(unix)]
+static ORIGINAL_TERMINAL_MODE: Mutex<Option<libc::termios>> = Mutex::new(None);
+
+/// What a reader of the keys shares with the interactor of the chain
+pub(crate) struct KeyReaderState {
+    /// Whether the reader gave the terminal back for a prompt. Held by
+    /// the reader while it reads, so that it never takes the answer.
+    pub(crate) is_suspended: Mutex<bool>,
+    pub(crate) is_stopped: AtomicBool,
+}
+
+impl KeyReaderState {
+    pub(crate) fn new() -> Self {
+        Self { is_suspended: Mutex::new(false), is_stopped: AtomicBool::new(false) }
+    }
+}
+
+/// A reader of the keys that gives the terminal back while the chain
+/// asks a question
+pub(crate) trait SuspendableReader: Send + Sync {
+    /// Give the terminal back for a prompt
+    fn suspend(&self);
+
+    fn resume(&self);
+}
+
+/// Asks the questions of the chain with the reader of the keys suspended
+pub(crate) struct SuspendingInteractor<T: SuspendableReader> {
+    reader: Arc<T>,
+}
+
+impl<T: SuspendableReader> SuspendingInteractor<T> {
+    pub(crate) fn new(reader: Arc<T>) -> Self {
+        Self { reader }
+    }
+}
+
+impl<T: SuspendableReader> Interactor for SuspendingInteractor<T> {
+    fn is_interactive(&self) -> bool {
+        TerminalInteractor.is_interactive()
+    }
+
+    fn prompt(&mut self, message: &str) -> Result<String, Error> {
+        self.reader.suspend();
+        let answer: Result<String, Error> = TerminalInteractor.prompt(message);
+        self.reader.resume();
+
+        answer
+    }
+}
+
+/// Read the keys until the reader is stopped, and pass each read to the
+/// handler. Nothing is read while the reader is suspended.
+#[cfg(unix)]
+pub(crate) fn read_keys(state: &KeyReaderState, mut handle_keys: impl FnMut(&[u8])) {
+    use std::{io::Read, thread, time::Duration};
+
+    while !state.is_stopped.load(Ordering::SeqCst) {
+        let mut buffer = [0u8; 16];
+        let read_length: usize = {
+            let is_suspended = state.is_suspended.lock().unwrap();
+            if *is_suspended {
+                0
+            } else {
+                let mut poll_fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+                let is_readable: bool = unsafe { libc::poll(&mut poll_fd, 1, 100) } > 0;
+                if is_readable {
+                    std::io::stdin().lock().read(&mut buffer).unwrap_or(0)
+                } else {
+                    0
+                }
+            }
+        };
+        if read_length == 0 {
+            thread::sleep(Duration::from_millis(50));
+            continue;
+        }
+
+        handle_keys(&buffer[..read_length]);
+    }
+}
+
+/// Read keys as they are pressed without echoing them. Ctrl-C still
+/// interrupts.
+pub(crate) fn take_key_mode() {
+    if IS_KEY_MODE_TAKEN.swap(true, Ordering::SeqCst) {
+        return;
+    }
+
+    #[cfg(unix)]
+    unsafe {
+        let mut mode: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(libc::STDIN_FILENO, &mut mode) == 0 {
+            *ORIGINAL_TERMINAL_MODE.lock().unwrap() = Some(mode);
+            mode.c_lflag &= !(libc::ICANON | libc::ECHO);
+            mode.c_cc[libc::VMIN] = 1;
+            mode.c_cc[libc::VTIME] = 0;
+            libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &mode);
+        }
+    }
+}
+
+/// Give the terminal back the mode it had before the keys were read.
+/// Does nothing when they are not, so it is safe to call from the panic
+/// hook and the interrupt handler.
+pub fn restore_key_mode() {
+    if !IS_KEY_MODE_TAKEN.swap(false, Ordering::SeqCst) {
+        return;
+    }
+
+    #[cfg(unix)]
+    if let Ok(mut original_mode) = ORIGINAL_TERMINAL_MODE.lock() {
+        if let Some(mode) = original_mode.take() {
+            unsafe {
+                libc::tcsetattr(libc::STDIN_FILENO, libc::TCSANOW, &mode);
+            }
+        }
+    }
+}
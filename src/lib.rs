@@ -1,9 +1,20 @@
 pub mod variable;
 pub mod function;
+pub mod expression;
 pub mod core;
 pub mod display_control;
+pub mod dashboard;
+pub mod key_mode;
+pub mod pause_keys;
+pub mod terminal_title;
+pub mod watch;
+pub mod completions;
 pub mod commons;
+pub mod marker;
+pub mod arguments;
+pub mod generations;
 
-mod arguments;
-mod marker;
-mod generations;
+// The supported types, under stable paths
+pub use crate::core::{chain::Chain, command::CommandLine, program::Program};
+pub use crate::marker::bookmark::Bookmark;
+pub use crate::variable::Variable;
@@ -1,125 +1,815 @@
-mod arguments;
-mod commons;
-mod display_control;
-mod function;
-mod marker;
-mod variable;
-mod core;
-mod generations;
-
-use std::process::exit;
-
-use anyhow::{Error, Result};
-use arguments::*;
-use core::traits::Execution;
-use commons::naming::HumanReadable;
-use {commons::utility::{check_required_packages, handle_adding_bookmarks_logics, read_into_chain}, display_control::{display_form, display_message, Level}, generations::create::ChainCreation};
-use marker::reference::ChainReference;
-use {core::chain::Chain, marker::bookmark::Bookmark};
+use std::{
+    collections::{BTreeMap, BTreeSet, HashMap, HashSet},
+    fs::File,
+    io::IsTerminal,
+    path::{Path, PathBuf},
+    process::exit,
+    str::FromStr,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Context, Error, Result};
+use cchain::{
+    arguments::*,
+    commons::{
+        features::Feature,
+        interaction::SharedInteractor,
+        lock::write_file_if_unchanged,
+        naming::HumanReadable,
+        storage::{remove_entry, PruneCandidate, RetentionRules, Storage, StorageCategory},
+        utility::{
+            apply_stored_run_arguments, build_exec_program, display_discovery_summary, AddOptions, collect_provided_values, display_index_remapping, exit_with_outcome, find_bookmark_index, get_paths, handle_adding_bookmarks_logics, read_inline_chain, read_into_chain,
+            resolve_chain_argument, run_chain, run_chain_with_retries, run_manifest, run_tagged_chains, save_exec_program, ChainResolutionTrace, ChainRunOutcome,
+            BatchOptions, ChainRunRecord, ChainSelection,
+        },
+    },
+    core::{
+        bench::{bench_chain, BenchDelta, BenchOptions, BenchReport, BENCH_FORM_LABELS, COMPARISON_FORM_LABELS},
+        chain::{PendingInput, ProgramListing, RememberedVariable, INLINE_CHAIN_NAME},
+        chain_retry::ChainRetryOptions,
+        debug::DebugShellOptions,
+        events::EventSender,
+        fix::ChainFileFix,
+        format::{dedupe_consecutive_programs, format_chain_file},
+        inheritance::resolve_chain_file,
+        io_profile::is_io_profiling_supported,
+        limits::{set_chain_limits, ChainLimits},
+        manifest::{is_manifest_file, ChainManifest},
+        metadata::{format_timestamp, ChainFile},
+        migration::migrate_legacy_fields,
+        parse_cache::{set_parse_cache_directory, set_parse_cache_enabled, PARSE_CACHE_DIRECTORY_NAME},
+        progress::{open_progress_fd, ProgressReporter},
+        schema::{get_chain_file_schema, get_progress_event_schema, validate_against_schema, SchemaViolation},
+        service::install_interrupt_handler,
+        shutdown::{install_termination_handler, is_timed_out, set_termination_grace_period, set_time_limit},
+        status::{get_run_status, set_status_file, RunOutcome},
+    },
+    dashboard::Dashboard,
+    pause_keys::PauseKeyListener,
+    terminal_title::{restore_terminal_title, start_terminal_title},
+    watch::{watch_chain_checks, CheckOptions},
+    completions::generate_completion_script,
+    display_control::{
+        display_form, display_message, display_tree_message, set_display_capture, set_display_silenced, set_environment_diff_shown, set_summary_only, is_summary_only, Level,
+    },
+    generations::{
+        create::ChainCreation,
+        history::{
+            find_history_file, get_recent_commands, parse_history, select_commands_by_prompt, select_commands_on_terminal,
+            HistoryFormat,
+        },
+    },
+    marker::{
+        bookmark::{CleanedReference, CleaningCategory, IndexRemapping},
+        environment_fingerprint::set_environment_check_enabled,
+        history::ValueHistory,
+        presets::VariablePresets,
+        reference::{ChainReference, TrackPath},
+        audit::{AuditContext, AuditEntry, AuditLog},
+        run_history::{find_last_environment, get_recurring_failures, RecurringFailure, RunHistory, RunHistoryRecord},
+        usage::{
+            get_usage_key, is_stale, is_tracking_usage, record_chain_run, ChainUsage, UsageLog, UsageSummary,
+            TRACK_USAGE_VARIABLE,
+        },
+    },
+    variable::{ProvidedValue, VariableSource},
+    Bookmark, Chain, Program,
+};
 use clap::{crate_version, Parser};
+use console::Term;
 
 fn main() -> Result<(), Error> {
     // Parse command line arguments
-    let arguments = Arguments::parse();
+    let command_line: Vec<String> = std::env::args().collect();
+    let arguments = match Arguments::try_parse_from(&command_line) {
+        Ok(arguments) => arguments,
+        Err(error) => {
+            // Help and version are not errors
+            let outcome: RunOutcome = if error.use_stderr() { RunOutcome::Usage } else { RunOutcome::Succeeded };
+            error.print()?;
+            exit(outcome.get_code());
+        }
+    };
+    // Record the status of the run for wrapper scripts
+    if let Commands::Run(RunArguments { status_file: Some(path), .. })
+        | Commands::Exec(ExecArguments { status_file: Some(path), .. }) = &arguments.commands
+    {
+        set_status_file(path.into());
+    }
+    if !arguments.limits.is_empty() {
+        match ChainLimits::default().with_overrides(&arguments.limits) {
+            Ok(chain_limits) => set_chain_limits(chain_limits),
+            Err(error) => {
+                display_message(Level::Error, &error.to_string());
+                exit(RunOutcome::Usage.get_code());
+            }
+        }
+    }
+    if arguments.no_cache {
+        set_parse_cache_enabled(false);
+    } else if let Ok(storage) = Storage::from_environment() {
+        // The chains parsed are kept for the next invocations too
+        set_parse_cache_directory(Some(storage.get_directory(StorageCategory::Cache).join(PARSE_CACHE_DIRECTORY_NAME)));
+    }
+    // Completions print the candidates only, and never break the shell
+    if let Commands::CompleteVars(subcommand) = &arguments.commands {
+        set_display_silenced(true);
+        if let Ok(bookmark) = Bookmark::from_file() {
+            if let ChainSelection::Selected { path } = resolve_chain_argument(&subcommand.chain, &bookmark).selection {
+                if let Ok(chain) = Chain::from_file(&path) {
+                    for completion in chain.get_variable_completions() {
+                        println!("{}", completion.to_candidate());
+                    }
+                }
+            }
+        }
+        return Ok(());
+    }
+    // Stop the running services when interrupted
+    install_interrupt_handler()?;
+    // Finish gracefully when asked to terminate, e.g. on shutdown. Only
+    // the commands running programs have something to forward it to.
+    if matches!(arguments.commands, Commands::Run(_) | Commands::Exec(_)) {
+        install_termination_handler()?;
+    }
+    // Changes to the bookmark are recorded with the user and the command
+    let audit_context: AuditContext =
+        AuditContext::from_environment().with_command(command_line.join(" "));
     // Instantiate the bookmark
-    let mut bookmark = Bookmark::from_file();
+    let mut bookmark = match Bookmark::from_file() {
+        Ok(mut bookmark) => {
+            bookmark.set_audit_context(audit_context.clone());
+            bookmark
+        }
+        Err(error) => {
+            // Resetting is the way out of an unreadable bookmark
+            if let Commands::Remove(RemoveArguments { reset: true, .. }) = &arguments.commands {
+                Bookmark::reset(&audit_context)?;
+                display_message(Level::Warn, "Bookmark has been reset!");
+                return Ok(());
+            }
+
+            display_message(Level::Error, &error.to_string());
+            exit(1);
+        }
+    };
 
     // Map the arguments to corresponding code logics
     match arguments.commands {
         Commands::Run(subcommand) => {
-            // If the input is parsable into an usize, it will use it as an
-            // index to the bookmark. Otherwise, it will use it as a path
-            let mut chain: Chain = match subcommand.chain.parse::<usize>() {
-                Ok(index) => {
-                    if let Some(chain_reference) = bookmark.get_chain_reference_by_index(index) {
-                        Chain::from_file(&chain_reference.get_chain_path_string())?
-                    } else {
-                        display_message(
-                            Level::Error, 
-                            &format!("Cannot get the chain with the specified index: {}", index)
-                        );
-                        exit(1);
+            // The arguments stored with a bookmarked chain come under
+            // those given on the command line
+            let subcommand: RunArguments = match apply_stored_run_arguments(&command_line, subcommand, &bookmark) {
+                Ok(subcommand) => subcommand,
+                Err(error) => {
+                    display_message(Level::Error, &error.to_string());
+                    exit_with_outcome(RunOutcome::Usage, &[]);
+                }
+            };
+            if let Some(path) = &subcommand.status_file {
+                set_status_file(path.into());
+            }
+            let provided_values: HashMap<String, ProvidedValue> =
+                match collect_provided_values(&subcommand.variables, &subcommand.variable_files) {
+                    Ok(provided_values) => provided_values,
+                    Err(error) => {
+                        display_message(Level::Error, &error.to_string());
+                        exit_with_outcome(RunOutcome::Usage, &[]);
                     }
+                };
+
+            if let Some(tag) = &subcommand.tag {
+                // Tags are cached in the bookmark, refresh them before selecting
+                if bookmark.refresh() {
+                    bookmark.save()?;
                 }
-                Err(_) => {
-                    // If the input is a path to a chain 
-                    match read_into_chain(&subcommand.chain, &bookmark) {
-                        Ok(chain) => chain,
+
+                let mut options: BatchOptions = BatchOptions::new()
+                    .with_keep_going(subcommand.keep_going)
+                    .with_allow_destructive(subcommand.allow_destructive)
+                    .with_failure_policy(subcommand.get_failure_policy());
+                if let Some(parallel) = subcommand.parallel {
+                    options = options.with_parallel(parallel);
+                }
+                let records: Vec<ChainRunRecord> = match run_tagged_chains(&bookmark, tag, &options, &provided_values) {
+                    Ok(records) => records,
+                    Err(error) => {
+                        display_message(Level::Error, &error.to_string());
+                        exit_with_outcome(RunOutcome::Usage, &[]);
+                    }
+                };
+
+                let form_data: Vec<Vec<String>> = records
+                    .iter()
+                    .enumerate()
+                    .map(|(index, record)| {
+                        vec![(index + 1).to_string(), record.name.clone(), record.outcome.to_string(), record.path.clone()]
+                    })
+                    .collect();
+                display_form(vec!["#", "Name", "Outcome", "Path"], &form_data);
+
+                if records.iter().any(|record| record.outcome != ChainRunOutcome::Succeeded) {
+                    display_message(
+                        Level::Error,
+                        &format!("Chain(s) tagged with `{}` finished with error(s) occurred", tag),
+                    );
+                    // With `--keep-going`, every chain ran to the end
+                    let outcome: RunOutcome = if subcommand.keep_going {
+                        RunOutcome::CompletedWithFailures
+                    } else {
+                        RunOutcome::Aborted
+                    };
+                    exit_with_outcome(outcome, &[]);
+                }
+                exit_with_outcome(RunOutcome::Succeeded, &[]);
+            }
+
+            // A manifest runs its chains, each as a unit
+            let manifest_path: Option<String> = subcommand.chain.as_ref().and_then(|chain_input| {
+                match resolve_chain_argument(chain_input, &bookmark).selection {
+                    ChainSelection::Selected { path } if is_manifest_file(Path::new(&path)) => Some(path),
+                    _ => None,
+                }
+            });
+            if let Some(manifest_path) = manifest_path {
+                if subcommand.retry_chain.is_some() {
+                    display_message(Level::Error, "`--retry-chain` does not apply to a manifest");
+                    exit_with_outcome(RunOutcome::Usage, &[]);
+                }
+                let mut options: BatchOptions = BatchOptions::new()
+                    .with_keep_going(subcommand.keep_going)
+                    .with_allow_destructive(subcommand.allow_destructive)
+                    .with_failure_policy(subcommand.get_failure_policy());
+                if let Some(parallel) = subcommand.parallel {
+                    options = options.with_parallel(parallel);
+                }
+                let records: Vec<ChainRunRecord> =
+                    match run_manifest(Path::new(&manifest_path), &bookmark, &options, &provided_values) {
+                        Ok(records) => records,
                         Err(error) => {
                             display_message(Level::Error, &error.to_string());
-                            exit(1);
+                            exit_with_outcome(RunOutcome::ValidationFailed, &[]);
                         }
-                    }
+                    };
+
+                let form_data: Vec<Vec<String>> = records
+                    .iter()
+                    .enumerate()
+                    .map(|(index, record)| {
+                        vec![
+                            (index + 1).to_string(),
+                            record.name.clone(),
+                            record.outcome.to_string(),
+                            record.duration.map(|duration| format!("{:.1}s", duration.as_secs_f64())).unwrap_or_default(),
+                            record.failed_programs.to_string(),
+                            record.path.clone(),
+                        ]
+                    })
+                    .collect();
+                display_form(vec!["#", "Name", "Outcome", "Duration", "Failed programs", "Path"], &form_data);
+
+                if records.iter().any(|record| record.outcome != ChainRunOutcome::Succeeded) {
+                    display_message(
+                        Level::Error,
+                        &format!("Chain(s) of {} finished with error(s) occurred", manifest_path),
+                    );
+                    exit_with_outcome(RunOutcome::CompletedWithFailures, &[]);
                 }
-            };
-            
-            // Check the required packages
-            match check_required_packages(&chain) {
-                Ok(_) => (),
+                exit_with_outcome(RunOutcome::Succeeded, &[]);
+            }
+            if subcommand.parallel.is_some() {
+                display_message(Level::Error, "`--parallel` only applies with `--tag` or a manifest");
+                exit_with_outcome(RunOutcome::Usage, &[]);
+            }
+            if subcommand.keep_going {
+                display_message(
+                    Level::Error,
+                    "`--keep-going` only applies with `--tag` or a manifest. Use `--continue-on-failure` to continue after failed programs.",
+                );
+                exit_with_outcome(RunOutcome::Usage, &[]);
+            }
+            let chain_retry_options: ChainRetryOptions = match subcommand.get_chain_retry_options() {
+                Ok(chain_retry_options) => chain_retry_options,
                 Err(error) => {
                     display_message(Level::Error, &error.to_string());
-                    exit(1);
+                    exit_with_outcome(RunOutcome::Usage, &[]);
+                }
+            };
+
+            let mut chain: Chain = match &subcommand.inline {
+                Some(source) => match read_inline_chain(source) {
+                    Ok(chain) => chain,
+                    Err(error) => {
+                        display_message(Level::Error, &error.to_string());
+                        exit_with_outcome(RunOutcome::from_error(&error), &[]);
+                    }
+                },
+                None => {
+                    // Ensured by the argument group when `--tag` and `--inline` are absent
+                    let chain_input: String = subcommand.chain.clone().unwrap_or_default();
+                    // The input is an index to the bookmark, a path or keywords
+                    match read_into_chain(&chain_input, &bookmark) {
+                        Ok(chain) => chain,
+                        Err(error) => {
+                            display_message(Level::Error, &error.to_string());
+                            // An index out of the bookmark is a usage error
+                            let outcome: RunOutcome = match chain_input.parse::<usize>() {
+                                Ok(index) if bookmark.get_chain_reference_by_index(index).is_none() => RunOutcome::Usage,
+                                _ => RunOutcome::from_error(&error),
+                            };
+                            exit_with_outcome(outcome, &[]);
+                        }
+                    }
                 }
             };
             
-            // Iterate over each configuration and execute the commands
-            match chain.execute() {
-                Ok(_) => return Ok(()),
-                Err(_) => {
-                    chain.show_statistics();
+            chain.set_update_snapshots(subcommand.update_snapshots);
+            chain.set_keep_tmp(subcommand.keep_tmp);
+            chain.set_show_all_output(subcommand.show_all_output);
+            if subcommand.profile_io && !is_io_profiling_supported() {
+                display_message(Level::Warn, "`--profile-io` is only supported on Linux, so nothing is measured.");
+            }
+            chain.set_profile_io(subcommand.profile_io);
+            set_environment_diff_shown(subcommand.diff_env);
+            set_environment_check_enabled(!subcommand.no_env_check);
+            set_termination_grace_period(Duration::from_secs(subcommand.term_grace_seconds));
+            // The statuses are shown from the events of the run, which a
+            // dashboard or a progress reporter may take over later
+            if subcommand.summary_only && subcommand.output != OutputFormat::Json {
+                set_summary_only(true);
+                chain.set_event_sender(EventSender::display_only());
+            }
+            chain.set_provided_values(provided_values);
+            chain.set_skip_review(subcommand.yes);
+            chain.set_confirm_variables(subcommand.confirm_vars);
+            chain.set_allow_destructive(subcommand.allow_destructive);
+            chain.set_failure_policy(subcommand.get_failure_policy());
+            if let Err(error) = chain.set_program_selection(subcommand.get_program_selection()) {
+                display_message(Level::Error, &error.to_string());
+                exit_with_outcome(RunOutcome::Usage, &[]);
+            }
+            if subcommand.debug_on_failure {
+                if std::io::stdin().is_terminal() {
+                    chain.set_debug_shell_options(Some(
+                        DebugShellOptions::new().with_expose_secrets(subcommand.debug_expose_secrets),
+                    ));
+                } else {
+                    display_message(Level::Warn, "`--debug-on-failure` is ignored without a terminal");
+                }
+            }
+
+            if subcommand.list_programs {
+                let program_listing: Vec<ProgramListing> = chain.get_program_listing();
+                if subcommand.output == OutputFormat::Json {
+                    println!("{}", serde_json::to_string_pretty(&program_listing)?);
+                    return Ok(());
+                }
+                // The verification steps are listed apart, as they run
+                // after the programs
+                let (verification_listing, program_listing): (Vec<&ProgramListing>, Vec<&ProgramListing>) =
+                    program_listing.iter().partition(|program| program.verification);
+                let get_form_data = |program_listing: &[&ProgramListing]| -> Vec<Vec<String>> {
+                    program_listing
+                        .iter()
+                        .map(|program| {
+                            let mut notes: Vec<&str> = Vec::new();
+                            if program.builtin {
+                                notes.push("built-in step");
+                            }
+                            if program.always_run {
+                                notes.push("always runs");
+                            }
+                            if !program.selected {
+                                notes.push("not selected");
+                            }
+                            vec![
+                                program.index.to_string(),
+                                program.alias.clone().unwrap_or_default(),
+                                program.command.clone(),
+                                program.concurrency_group.map(|group| group.to_string()).unwrap_or_default(),
+                                notes.join(", "),
+                            ]
+                        })
+                        .collect()
+                };
+                display_form(vec!["#", "Alias", "Command", "Group", "Notes"], &get_form_data(&program_listing));
+                if !verification_listing.is_empty() {
+                    display_message(Level::Logging, "Verification steps, run after all programs succeed:");
+                    display_form(vec!["#", "Alias", "Command", "Group", "Notes"], &get_form_data(&verification_listing));
+                }
+                return Ok(());
+            }
+
+            if subcommand.dry_run {
+                for command_line in chain.get_dry_run_command_lines() {
+                    display_message(Level::Logging, &command_line);
+                }
+                let pending_inputs: Vec<PendingInput> = chain.get_pending_input_listing();
+                if !pending_inputs.is_empty() {
                     display_message(
-                        Level::Error,
-                        "Chain execution finished with error(s) occurred",
+                        Level::Logging,
+                        &format!(
+                            "The chain would prompt for {}",
+                            pending_inputs.iter().map(|input| input.to_string()).collect::<Vec<String>>().join(", ")
+                        ),
                     );
                 }
+                return Ok(());
+            }
+
+            // Iterate over each configuration and execute the commands.
+            // Inline chains have no file to count the runs of, nor to
+            // keep the entered values for.
+            if chain.get_path() != INLINE_CHAIN_NAME {
+                record_chain_run(chain.get_path());
+                if let Ok(storage) = Storage::from_environment() {
+                    chain.set_value_history(ValueHistory::from_storage(&storage));
+                }
+            }
+            // Presets are shared by all chains, inline ones included
+            match Storage::from_environment().and_then(|storage| VariablePresets::from_storage(&storage)) {
+                Ok(variable_presets) => chain.set_variable_presets(variable_presets),
+                Err(error) => display_message(Level::Warn, &format!("Cannot read the variable presets: {}", error)),
+            }
+            let dashboard: Option<Dashboard> = match subcommand.tui {
+                true if Term::stdout().is_term() => Some(Dashboard::start(&mut chain)),
+                true => {
+                    display_message(Level::Warn, "`--tui` is ignored without a terminal");
+                    None
+                }
+                false => None,
+            };
+            let progress_writer: Option<Result<File, Error>> = match (&subcommand.progress_fd, &subcommand.progress_file) {
+                (Some(fd), _) => Some(open_progress_fd(*fd)),
+                (None, Some(path)) => Some(File::create(path).map_err(|error| anyhow!("Cannot open {}: {}", path, error))),
+                (None, None) => None,
             };
+            let progress_reporter: Option<ProgressReporter> = match progress_writer {
+                Some(Ok(file)) => Some(ProgressReporter::start(&mut chain, Box::new(file))),
+                Some(Err(error)) => {
+                    display_message(Level::Error, &error.to_string());
+                    exit_with_outcome(RunOutcome::Usage, &[]);
+                }
+                None => None,
+            };
+            // The keys are read only when asked for, as the terminal is
+            // then in another mode for the programs too, and when the user
+            // follows the run at a terminal and nothing else takes them
+            let is_pausable: bool = subcommand.pause_keys
+                && dashboard.is_none()
+                && progress_reporter.is_none()
+                && !subcommand.debug_on_failure
+                && std::io::stdin().is_terminal()
+                && std::io::stdout().is_terminal();
+            let pause_key_listener: Option<PauseKeyListener> = is_pausable.then(|| PauseKeyListener::start(&mut chain));
+            // The title follows the events of the run, whichever of the
+            // displays above takes them
+            if !subcommand.no_title && std::io::stdout().is_terminal() {
+                if dashboard.is_none() && progress_reporter.is_none() && !is_summary_only() {
+                    chain.set_event_sender(EventSender::display_only());
+                }
+                let chain_name: String = Path::new(chain.get_path())
+                    .file_name()
+                    .map_or_else(|| chain.get_path().to_string(), |name| name.to_string_lossy().to_string());
+                start_terminal_title(&chain_name);
+            }
+            let (outcome, failed_programs) = run_chain_with_retries(&mut chain, &chain_retry_options);
+            restore_terminal_title();
+            if let Some(pause_key_listener) = pause_key_listener {
+                pause_key_listener.finish();
+            }
+            if let Some(dashboard) = dashboard {
+                dashboard.finish();
+            }
+            if let Some(progress_reporter) = progress_reporter {
+                progress_reporter.finish(outcome == RunOutcome::Succeeded);
+            }
+            if subcommand.explain_variables {
+                let form_data: Vec<Vec<String>> = chain
+                    .get_masked_variable_provenances()
+                    .iter()
+                    .map(|provenance| {
+                        let program_index: String = match provenance.get_source() {
+                            VariableSource::ProgramOutput { program_index } => program_index.to_string(),
+                            _ => String::new(),
+                        };
+                        vec![
+                            provenance.name.clone(),
+                            provenance.value.clone(),
+                            provenance.get_source().to_string(),
+                            program_index,
+                        ]
+                    })
+                    .collect();
+                display_form(vec!["Variable", "Value", "Source", "Program"], &form_data);
+            }
+            exit_with_outcome(outcome, &failed_programs);
+        },
+        Commands::Which(subcommand) => {
+            let trace: ChainResolutionTrace = resolve_chain_argument(&subcommand.chain, &bookmark);
+            if subcommand.output == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&trace)?);
+                return Ok(());
+            }
+
+            match (&trace.id, trace.index) {
+                (Some(id), _) => display_message(Level::Logging, &format!("`{}` is the ID of a bookmarked chain.", id)),
+                (None, Some(index)) => display_message(Level::Logging, &format!("`{}` is parsed as the bookmark index {}.", trace.input, index)),
+                (None, None) => display_message(Level::Logging, &format!("`{}` is not a bookmark ID or index.", trace.input)),
+            }
+            for path_check in &trace.checked_paths {
+                display_message(Level::Logging, &format!("Path `{}` {}.", path_check.path, path_check.reason));
+            }
+            // Keywords are tried when the input is neither an index nor a chain file
+            if trace.id.is_none() && trace.index.is_none() && !trace.checked_paths.iter().any(|path_check| path_check.accepted) {
+                display_message(Level::Logging, &format!("{} chain(s) match the keywords:", trace.keyword_matches.len()));
+                let form_data: Vec<Vec<String>> = trace
+                    .keyword_matches
+                    .iter()
+                    .map(|keyword_match| {
+                        vec![
+                            keyword_match.index.to_string(),
+                            keyword_match.name.clone(),
+                            keyword_match.score.to_string(),
+                            keyword_match.path.clone(),
+                        ]
+                    })
+                    .collect();
+                display_form(vec!["Index", "Name", "Score", "Path"], &form_data);
+            }
+
+            match &trace.selection {
+                ChainSelection::Selected { path } => display_message(Level::Logging, &format!("The chain at {} would be run.", path)),
+                ChainSelection::Ambiguous { candidates } => {
+                    display_message(Level::Warn, "You would be asked to select one of the chains:");
+                    for (index, candidate) in candidates.iter().enumerate() {
+                        display_tree_message(1, &format!("{}: {}", index + 1, candidate));
+                    }
+                }
+                ChainSelection::NotFound { reason } => display_message(Level::Error, reason),
+            }
+        },
+        Commands::Bench(subcommand) => {
+            let provided_values: HashMap<String, ProvidedValue> =
+                match collect_provided_values(&subcommand.variables, &subcommand.variable_files) {
+                    Ok(provided_values) => provided_values,
+                    Err(error) => {
+                        display_message(Level::Error, &error.to_string());
+                        exit_with_outcome(RunOutcome::Usage, &[]);
+                    }
+                };
+            // A baseline that cannot be read is reported before the runs
+            let baseline: Option<BenchReport> = match subcommand.compare.as_deref().map(BenchReport::from_file) {
+                Some(Ok(baseline)) => Some(baseline),
+                Some(Err(error)) => {
+                    display_message(Level::Error, &error.to_string());
+                    exit_with_outcome(RunOutcome::Usage, &[]);
+                }
+                None => None,
+            };
+            let path: String = match resolve_chain_argument(&subcommand.chain, &bookmark).selection {
+                ChainSelection::Selected { path } => path,
+                ChainSelection::Ambiguous { .. } => {
+                    display_message(Level::Error, &format!("`{}` matches several chains. Use the index or the path of one.", subcommand.chain));
+                    exit_with_outcome(RunOutcome::Usage, &[]);
+                }
+                ChainSelection::NotFound { reason } => {
+                    display_message(Level::Error, &reason);
+                    exit_with_outcome(RunOutcome::Usage, &[]);
+                }
+            };
+
+            if subcommand.profile_io && !is_io_profiling_supported() {
+                display_message(Level::Warn, "`--profile-io` is only supported on Linux, so nothing is measured.");
+            }
+            let options: BenchOptions = BenchOptions::new(subcommand.iterations as usize)
+                .with_warmup(subcommand.warmup as usize)
+                .with_unstable_threshold(subcommand.unstable_threshold)
+                .with_profile_io(subcommand.profile_io);
+            // Only the report is printed as JSON
+            if subcommand.output == OutputFormat::Json {
+                set_display_capture(Some(EventSender::display_only()));
+            }
+            let mut report: BenchReport = bench_chain(&path, provided_values, &options);
+            set_display_capture(None);
+            if let Some(baseline) = &baseline {
+                report.comparison = report.compare(baseline);
+            }
+
+            if subcommand.output == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&report)?);
+            } else {
+                display_message(
+                    Level::Logging,
+                    &format!("Durations of {} measured run(s) of {}:", report.iterations, report.chain),
+                );
+                display_form(BENCH_FORM_LABELS.to_vec(), &report.get_form_data());
+                if !report.comparison.is_empty() {
+                    display_message(Level::Logging, "Medians compared with the baseline:");
+                    let form_data: Vec<Vec<String>> = report.comparison.iter().map(BenchDelta::to_form_row).collect();
+                    display_form(COMPARISON_FORM_LABELS.to_vec(), &form_data);
+                }
+                if let Some(aborted) = &report.aborted {
+                    display_message(Level::Error, &format!("The bench stopped early. {}", aborted));
+                }
+            }
+            if report.aborted.is_some() {
+                exit_with_outcome(RunOutcome::Aborted, &[]);
+            }
+        },
+        Commands::Exec(subcommand) => {
+            let started_at: Instant = Instant::now();
+            let program: Program = build_exec_program(&subcommand);
+            if let Some(name) = &subcommand.save {
+                save_exec_program(&program, name, &std::env::current_dir()?)?;
+            }
+
+            // Run the command as a chain of one program
+            let chain_file = ChainFile {
+                programs: vec![program],
+                ..Default::default()
+            };
+            let mut chain: Chain = Chain::from_chain_file(chain_file, &subcommand.command_line.join(" "))?;
+
+            if let Some(timeout) = subcommand.timeout {
+                set_time_limit(Duration::from_secs(timeout));
+            }
+            // Only the status document is printed as JSON
+            if subcommand.output == OutputFormat::Json {
+                set_display_capture(Some(EventSender::display_only()));
+            }
+            let (mut outcome, failed_programs) = run_chain(&mut chain);
+            set_display_capture(None);
+            if is_timed_out() {
+                display_message(Level::Error, &format!("`{}` did not finish within {} second(s)", subcommand.command_line.join(" "), subcommand.timeout.unwrap_or_default()));
+                outcome = RunOutcome::Aborted;
+            }
+
+            if let Some(path) = &subcommand.store_to_file {
+                if let Some(result) = chain.get_program_results().last() {
+                    std::fs::write(path, result.output())?;
+                }
+            }
+            if subcommand.output == OutputFormat::Json {
+                println!("{}", serde_json::to_string_pretty(&get_run_status(outcome, &failed_programs, started_at)?)?);
+            }
+            exit_with_outcome(outcome, &failed_programs);
         },
         Commands::Add(subcommand) => {
-            match handle_adding_bookmarks_logics(&mut bookmark, &subcommand.path) {
-                Ok(_) => (),
+            let add_options: AddOptions = subcommand.get_add_options();
+            match handle_adding_bookmarks_logics(&mut bookmark, &subcommand.path, &add_options) {
+                Ok(Some(discovery_summary)) => display_discovery_summary(&discovery_summary, add_options.is_list_only()),
+                Ok(None) => (),
                 Err(error) => {
                     display_message(Level::Error, &error.to_string());
                     exit(1);
                 }
             }
 
+            if add_options.is_list_only() {
+                display_message(Level::Logging, "Nothing is added with `--list-only`.");
+                return Ok(());
+            }
             display_message(Level::Logging, "Bookmark registration is done.");
-            bookmark.save();
+            bookmark.save()?;
             return Ok(());
         },
-        Commands::List(_) => {
-            let references: &Vec<ChainReference> = &bookmark.get_chain_references();
+        Commands::List(subcommand) => {
+            if let Some(count) = subcommand.audit {
+                let entries: Vec<AuditEntry> = bookmark.get_audit_log().read();
+                let form_data: Vec<Vec<String>> = entries[entries.len().saturating_sub(count)..]
+                    .iter()
+                    .map(|entry| {
+                        let chains: Vec<String> = if entry.ids.is_empty() { entry.paths.clone() } else { entry.ids.clone() };
+                        vec![
+                            format_timestamp(entry.timestamp),
+                            entry.user.clone(),
+                            entry.operation.to_string(),
+                            chains.join(", "),
+                            format!(
+                                "{} -> {}",
+                                entry.hash_before.as_deref().map_or("none", |hash| hash.get(..12).unwrap_or(hash)),
+                                entry.hash_after.as_deref().map_or("none", |hash| hash.get(..12).unwrap_or(hash))
+                            ),
+                        ]
+                    })
+                    .collect();
+                display_form(vec!["Time", "User", "Operation", "Chains", "Bookmark hash"], &form_data);
+                return Ok(());
+            }
+
+            if bookmark.refresh() {
+                bookmark.save()?;
+            }
+
+            let references: &Vec<ChainReference> = bookmark.get_chain_references();
             let mut form_data: Vec<Vec<String>> = Vec::new();
+            let usage: UsageSummary = if subcommand.show_usage || subcommand.stale.is_some() {
+                let storage: Storage = Storage::from_environment()?;
+                UsageLog::from_storage(&storage).read()
+            } else {
+                UsageSummary::default()
+            };
+            if usage.skipped_lines > 0 {
+                display_message(
+                    Level::Warn,
+                    &format!("{} unreadable line(s) of the usage file are skipped. `cchain gc` rewrites the file.", usage.skipped_lines),
+                );
+            }
+            let now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
 
             for (index, reference) in references.iter().enumerate() {
-                form_data.push(vec![
+                if let Some(tag) = &subcommand.tag {
+                    if !reference.has_tag(tag) {
+                        continue;
+                    }
+                }
+                if subcommand.with_run_args && reference.get_run_arguments().is_none() {
+                    continue;
+                }
+                let chain_usage: Option<&ChainUsage> = usage.get_usage(&reference.get_chain_path_string());
+                if let Some(days) = subcommand.stale {
+                    if !is_stale(chain_usage, days, now) {
+                        continue;
+                    }
+                }
+
+                let mut row: Vec<String> = vec![
                     index.to_string(),
+                    reference.get_id().to_string(),
                     reference.get_human_readable_name(),
-                    reference.get_chain_path_string(),
-                ]);
+                    reference.get_tags().join(", "),
+                ];
+                if subcommand.show_usage {
+                    row.push(chain_usage.map_or("0".to_string(), |chain_usage| chain_usage.runs.to_string()));
+                    row.push(chain_usage.map_or("never".to_string(), |chain_usage| format_timestamp(chain_usage.last_run)));
+                }
+                if subcommand.with_run_args {
+                    row.push(mask_stored_run_arguments(reference.get_run_arguments().unwrap_or_default()));
+                }
+                row.push(reference.get_chain_path_string());
+                form_data.push(row);
+            }
+
+            let mut labels: Vec<&str> = vec!["Index", "ID", "Name", "Tags"];
+            if subcommand.show_usage {
+                labels.extend(["Runs", "Last run"]);
+            }
+            if subcommand.with_run_args {
+                labels.push("Run arguments");
+            }
+            labels.push("Path");
+            display_form(labels, &form_data);
+        },
+        Commands::Config(subcommand) => {
+            let index: usize = match find_bookmark_index(&subcommand.index, &bookmark) {
+                Some(index) => index,
+                None => {
+                    display_message(Level::Error, &format!("Bookmark index or ID {} is not found", subcommand.index));
+                    exit(RunOutcome::Usage.get_code());
+                }
+            };
+            let name: String = bookmark.get_chain_references()[index].get_human_readable_name().trim().to_string();
+            if let Err(error) = subcommand
+                .set_run_args
+                .as_deref()
+                .map_or(Ok(()), |run_arguments| parse_stored_run_arguments(run_arguments).map(|_| ()))
+                .and_then(|_| bookmark.set_run_arguments(index, subcommand.set_run_args.clone()))
+            {
+                display_message(Level::Error, &error.to_string());
+                exit(RunOutcome::Usage.get_code());
             }
+            bookmark.save()?;
 
-            display_form(vec!["Index", "Name", "Path"], &form_data);
+            match &subcommand.set_run_args {
+                Some(run_arguments) => display_message(
+                    Level::Logging,
+                    &format!("{} always runs with `{}`", name, run_arguments),
+                ),
+                None => display_message(Level::Logging, &format!("The stored run arguments of {} are removed", name)),
+            }
         },
         Commands::Remove(subcommand) => {
             if subcommand.reset {
-                Bookmark::reset()?;
+                Bookmark::reset(&audit_context)?;
                 display_message(Level::Warn, "Bookmark has been reset!");
             } else {
-                if let Some(index) = subcommand.index {
-                    let reference = match bookmark.get_chain_reference_by_index(index) {
-                        Some(result) => result,
+                if let Some(input) = &subcommand.index {
+                    let index: usize = match find_bookmark_index(input, &bookmark) {
+                        Some(index) => index,
                         None => {
                             display_message(
                                 Level::Error,
-                                &format!("Bookmark index {} is not found", index),
+                                &format!("Bookmark index or ID {} is not found", input),
                             );
                             return Ok(());
                         }
                     };
+                    let reference = &bookmark.get_chain_references()[index];
                     let reference_name: String = reference.get_chain_path_string();
                     bookmark.remove_chain_reference_by_index(index)?;
-                    bookmark.save();
+                    let index_remapping: IndexRemapping = bookmark.get_index_remapping();
+                    bookmark.save()?;
 
                     display_message(
                         Level::Warn,
@@ -128,16 +818,30 @@ fn main() -> Result<(), Error> {
                             &reference_name
                         ),
                     );
+                    display_index_remapping(&index_remapping, false);
                 }
             }
 
             return Ok(());
         },
-        Commands::Clean(_) => {
-            let invalid_paths: Vec<String> = bookmark.get_invalid_paths()?;
-            let mut cleaned_invalid_paths: usize = 0;
+        Commands::Clean(subcommand) => {
+            let cleaned_references: Vec<CleanedReference> = bookmark.clean(subcommand.invalid)?;
+
+            // Invalid chains are kept unless `--invalid` is specified
+            if !subcommand.invalid {
+                for (path, reason) in bookmark.get_unparsable_paths() {
+                    display_message(
+                        Level::Warn,
+                        &format!(
+                            "Chain at {} is no longer valid: {}. Use `--invalid` to clean it.",
+                            path,
+                            reason
+                        )
+                    );
+                }
+            }
 
-            if invalid_paths.len() == 0 {
+            if cleaned_references.is_empty() {
                 display_message(
                     Level::Logging, 
                     "No chains need to be cleaned. All good! 😎"
@@ -146,57 +850,449 @@ fn main() -> Result<(), Error> {
                 return Ok(());
             }
 
-            for invalid_path in invalid_paths {
-                match bookmark.remove_chain_reference_by_path(&invalid_path) {
-                    Ok(_) => {
-                        cleaned_invalid_paths += 1;
-                        display_message(
-                            Level::Logging, 
-                            &format!(
-                                "Chain does no longer exist at: {}, cleaned.", 
-                                &invalid_path
-                            )
-                        );
-                    },
-                    Err(error) => display_message(
-                        Level::Error, 
-                        &format!(
-                            "Error has occurred when trying removing chain at: {} 😥", 
-                            error
-                        )
-                    ),
-                };
+            for cleaned_reference in &cleaned_references {
+                let action: &str = if subcommand.dry_run { "would be cleaned" } else { "cleaned" };
+                display_message(
+                    Level::Logging, 
+                    &format!(
+                        "Chain at {} is {}, {}.", 
+                        &cleaned_reference.path,
+                        cleaned_reference.category,
+                        action
+                    )
+                );
+            }
+
+            // Summarize the removals by category
+            let summary: Vec<String> = [CleaningCategory::Missing, CleaningCategory::Duplicate, CleaningCategory::Invalid]
+                .iter()
+                .map(|category| {
+                    let count: usize = cleaned_references
+                        .iter()
+                        .filter(|cleaned_reference| cleaned_reference.category == *category)
+                        .count();
+                    format!("{} {}", count, category)
+                })
+                .collect();
+
+            if subcommand.dry_run {
+                display_message(
+                    Level::Logging, 
+                    &format!(
+                        "Dry run: {} chain paths would be cleaned from the bookmark ({}).", 
+                        cleaned_references.len(),
+                        summary.join(", ")
+                    )
+                );
+                display_index_remapping(&bookmark.get_index_remapping(), true);
+                return Ok(());
             }
 
             display_message(
                 Level::Logging, 
                 &format!(
-                    "{} invalid chains paths are cleaned from the bookmark.", 
-                    cleaned_invalid_paths
+                    "{} chain paths are cleaned from the bookmark ({}).", 
+                    cleaned_references.len(),
+                    summary.join(", ")
                 )
             );
-            bookmark.save();
+            let index_remapping: IndexRemapping = bookmark.get_index_remapping();
+            bookmark.save()?;
+            display_index_remapping(&index_remapping, false);
+        },
+        Commands::Gc(subcommand) => {
+            let storage: Storage = Storage::from_environment()?;
+            let form_data: Vec<Vec<String>> = storage
+                .get_disk_usage()
+                .iter()
+                .map(|usage| {
+                    vec![
+                        usage.category.to_string(),
+                        usage.entries.to_string(),
+                        format!("{:.1} KiB", usage.bytes as f64 / 1024.0),
+                        storage.get_directory(usage.category).display().to_string(),
+                    ]
+                })
+                .collect();
+            display_form(vec!["Category", "Entries", "Size", "Path"], &form_data);
+
+            let rules = RetentionRules {
+                log_retention: Duration::from_secs(subcommand.log_retention_days.saturating_mul(86400)),
+                cache_ttl: Duration::from_secs(subcommand.cache_ttl_days.saturating_mul(86400)),
+            };
+            let bookmarked_paths: Vec<PathBuf> = bookmark
+                .get_chain_references()
+                .iter()
+                .map(|chain_reference| PathBuf::from(chain_reference.get_chain_path_string()))
+                .collect();
+            let candidates: Vec<PruneCandidate> =
+                storage.plan_garbage_collection(&rules, &bookmarked_paths, SystemTime::now());
+
+            // Run counters of chains that are no longer bookmarked are dropped
+            let usage_log: UsageLog = UsageLog::from_storage(&storage);
+            let bookmarked_keys: HashSet<String> =
+                bookmarked_paths.iter().map(|path| get_usage_key(&path.to_string_lossy())).collect();
+            let dropped_usage: Vec<String> = if subcommand.dry_run {
+                usage_log.read().chains.into_keys().filter(|path| !bookmarked_keys.contains(path)).collect()
+            } else {
+                usage_log.compact(|path| bookmarked_keys.contains(path)).unwrap_or_else(|error| {
+                    display_message(
+                        Level::Error,
+                        &format!("Failed to compact {}: {}", usage_log.get_path().display(), error),
+                    );
+                    Vec::new()
+                })
+            };
+            for path in &dropped_usage {
+                let action: &str = if subcommand.dry_run { "would be dropped" } else { "dropped" };
+                display_message(Level::Logging, &format!("Run counter of {} (no longer bookmarked) {}.", path, action));
+            }
+
+            // The audit log of the bookmark follows the retention of logs
+            let audit_log: AuditLog = bookmark.get_audit_log();
+            let oldest_timestamp: u64 = SystemTime::now()
+                .checked_sub(rules.log_retention)
+                .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+                .map_or(0, |duration| duration.as_secs());
+            let dropped_audit_entries: usize = if subcommand.dry_run {
+                audit_log.read().iter().filter(|entry| entry.timestamp < oldest_timestamp).count()
+            } else {
+                audit_log.compact(oldest_timestamp).unwrap_or_else(|error| {
+                    display_message(
+                        Level::Error,
+                        &format!("Failed to compact {}: {}", audit_log.get_path().display(), error),
+                    );
+                    0
+                })
+            };
+            if dropped_audit_entries > 0 {
+                let action: &str = if subcommand.dry_run { "would be dropped" } else { "dropped" };
+                display_message(
+                    Level::Logging,
+                    &format!("{} entries of the bookmark audit log (older than the log retention) {}.", dropped_audit_entries, action),
+                );
+            }
+
+            // So does the run history, except the last successful run of
+            // each chain that the next runs compare their environment with
+            let run_history: RunHistory = RunHistory::from_storage(&storage);
+            let dropped_runs: usize = if subcommand.dry_run {
+                run_history.count_expired(oldest_timestamp)
+            } else {
+                run_history.compact(oldest_timestamp).unwrap_or_else(|error| {
+                    display_message(
+                        Level::Error,
+                        &format!("Failed to compact {}: {}", run_history.get_path().display(), error),
+                    );
+                    0
+                })
+            };
+            if dropped_runs > 0 {
+                let action: &str = if subcommand.dry_run { "would be dropped" } else { "dropped" };
+                display_message(
+                    Level::Logging,
+                    &format!("{} runs of the run history (older than the log retention) {}.", dropped_runs, action),
+                );
+            }
+
+            if candidates.is_empty() {
+                if dropped_usage.is_empty() && dropped_audit_entries == 0 && dropped_runs == 0 {
+                    display_message(Level::Logging, "Nothing needs to be removed. All good! 😎");
+                }
+                return Ok(());
+            }
+
+            for candidate in &candidates {
+                if !subcommand.dry_run {
+                    if let Err(error) = remove_entry(&candidate.path) {
+                        display_message(
+                            Level::Error,
+                            &format!("Failed to remove {}: {}", candidate.path.display(), error),
+                        );
+                        continue;
+                    }
+                }
+                let action: &str = if subcommand.dry_run { "would be removed" } else { "removed" };
+                display_message(
+                    Level::Logging,
+                    &format!(
+                        "{} ({}, {}) {}.",
+                        candidate.path.display(),
+                        candidate.category,
+                        candidate.reason,
+                        action
+                    ),
+                );
+            }
         },
         Commands::Check(subcommand) => {
-            // If the input is parsable into an usize, it will use it as an
-            // index to the bookmark. Otherwise, it will use it as a path
-            match subcommand.chain.parse::<usize>() {
-                Ok(index) => {
-                    if let Some(chain_reference) = bookmark.get_chain_reference_by_index(index) {
-                        let mut chain = Chain::from_file(&chain_reference.get_chain_path_string())?;
-                        chain.validate_syntax()?;
+            // If the input is an ID or an index in the bookmark, it will use
+            // the bookmarked chain. Otherwise, it will use it as a path
+            let mut chain_path: String = match find_bookmark_index(&subcommand.chain, &bookmark) {
+                Some(index) => bookmark.get_chain_references()[index].get_chain_path_string(),
+                None => {
+                    if let Ok(index) = subcommand.chain.parse::<usize>() {
+                        display_message(
+                            Level::Error,
+                            &format!("Cannot get the chain with the specified index: {}", index)
+                        );
+                        exit(RunOutcome::Usage.get_code());
                     }
+                    subcommand.chain.clone()
                 }
-                Err(_) => {
-                    // Load and parse the configuration file
-                    let mut chain = Chain::from_file(&subcommand.chain)?;
-                    chain.validate_syntax()?;
+            };
+            if is_manifest_file(Path::new(&chain_path)) {
+                if subcommand.watch || subcommand.fix {
+                    display_message(Level::Error, "`--watch` and `--fix` do not apply to manifests.");
+                    exit(RunOutcome::Usage.get_code());
+                }
+                let manifest: ChainManifest = match ChainManifest::from_file(Path::new(&chain_path)) {
+                    Ok(manifest) => manifest,
+                    Err(error) => {
+                        display_message(Level::Error, &error.to_string());
+                        exit(RunOutcome::ValidationFailed.get_code());
+                    }
+                };
+
+                // Every chain is checked, so that all problems show at once
+                let mut is_failed: bool = false;
+                let chain_paths: Vec<Result<String, Error>> = manifest.resolve_chain_paths(Path::new(&chain_path), &bookmark);
+                for (entry, path) in manifest.get_entries().iter().zip(&chain_paths) {
+                    let result: Result<(), Error> = path.as_ref().map_err(|error| anyhow!("{}", error)).and_then(|path| {
+                        let mut chain: Chain = Chain::from_file(path)?;
+                        chain.set_require_declarations(subcommand.require_declarations);
+                        chain.validate_syntax()
+                    });
+                    if let Err(error) = result {
+                        display_message(Level::Error, &format!("`{}`: {}", entry.get_name(), error));
+                        is_failed = true;
+                    }
+                }
+                if is_failed {
+                    exit(RunOutcome::ValidationFailed.get_code());
+                }
+
+                // The chains of a stage can run at the same time, once
+                // the stages before it finish
+                let stages: Vec<Vec<usize>> = manifest.get_stages()?;
+                for (stage_index, stage) in stages.iter().enumerate() {
+                    display_message(Level::Logging, &format!("Stage {}:", stage_index + 1));
+                    for &index in stage {
+                        let entry = &manifest.get_entries()[index];
+                        let mut notes: Vec<String> = Vec::new();
+                        if !entry.depends_on.is_empty() {
+                            notes.push(format!("after {}", entry.depends_on.join(", ")));
+                        }
+                        if entry.always_run {
+                            notes.push("always runs".to_string());
+                        }
+                        let path: &str = chain_paths[index].as_deref().unwrap_or_default();
+                        if notes.is_empty() {
+                            display_tree_message(1, &format!("{}: {}", entry.get_name(), path));
+                        } else {
+                            display_tree_message(1, &format!("{}: {} ({})", entry.get_name(), path, notes.join("; ")));
+                        }
+                    }
+                }
+                return Ok(());
+            }
+            if subcommand.watch {
+                if !std::io::stdin().is_terminal() || !std::io::stdout().is_terminal() {
+                    display_message(Level::Error, "`--watch` needs a terminal to show the checks in.");
+                    exit(RunOutcome::Usage.get_code());
+                }
+                let options = CheckOptions {
+                    lint: subcommand.lint,
+                    separated_duplicates: subcommand.separated_duplicates,
+                    require_declarations: subcommand.require_declarations,
+                    schema: subcommand.schema,
+                };
+                watch_chain_checks(&chain_path, &options)?;
+                return Ok(());
+            }
+            if subcommand.fix {
+                let canonical_path: Option<PathBuf> = std::fs::canonicalize(&chain_path).ok();
+                let is_bookmarked: bool = bookmark.get_chain_references().iter().any(|chain_reference| {
+                    std::fs::canonicalize(chain_reference.get_chain_path_string()).ok() == canonical_path
+                });
+                let chain_file_fix: ChainFileFix = match ChainFileFix::from_path(Path::new(&chain_path), is_bookmarked) {
+                    Ok(chain_file_fix) => chain_file_fix,
+                    Err(error) => {
+                        display_message(Level::Error, &error.to_string());
+                        exit(RunOutcome::ValidationFailed.get_code());
+                    }
+                };
+
+                if !chain_file_fix.is_changed() && chain_file_fix.get_untouched().is_empty() {
+                    display_message(Level::Logging, &format!("{} has nothing to fix.", chain_path));
+                } else {
+                    display_message(Level::Logging, &format!("Fixes of {}:", chain_path));
+                }
+                for fix in chain_file_fix.get_applied() {
+                    display_tree_message(1, fix);
+                }
+                for problem in chain_file_fix.get_untouched() {
+                    display_message(Level::Warn, &format!("Left as it is: {}", problem));
+                }
+                if subcommand.dry_run {
+                    print!("{}", chain_file_fix.get_diff());
+                    return Ok(());
+                }
+                if chain_file_fix.is_changed() {
+                    let backup_path: PathBuf = chain_file_fix.apply()?;
+                    display_message(
+                        Level::Logging,
+                        &format!(
+                            "Wrote {}, the original is backed up at {}.",
+                            chain_file_fix.get_fixed_path().display(),
+                            backup_path.display()
+                        ),
+                    );
+                    chain_path = chain_file_fix.get_fixed_path().to_string_lossy().to_string();
+                }
+            }
+            if subcommand.schema {
+                // Chains that extend others are checked after merging, and
+                // legacy fields as the current ones they are rewritten to
+                let resolved = resolve_chain_file(Path::new(&chain_path)).and_then(|mut resolved| {
+                    migrate_legacy_fields(&mut resolved.value)?;
+                    Ok(resolved)
+                });
+                let violations: Vec<SchemaViolation> = match resolved {
+                    Ok(resolved) => validate_against_schema(&resolved.value, &get_chain_file_schema()),
+                    Err(error) => {
+                        display_message(Level::Error, &error.to_string());
+                        exit(RunOutcome::ValidationFailed.get_code());
+                    }
+                };
+                if !violations.is_empty() {
+                    for violation in &violations {
+                        display_message(Level::Error, &violation.to_string());
+                    }
+                    exit(RunOutcome::ValidationFailed.get_code());
+                }
+            }
+
+            // Load and parse the configuration file
+            let chain: Chain = match Chain::from_file(&chain_path).and_then(|mut chain| {
+                chain.set_require_declarations(subcommand.require_declarations);
+                chain.set_lint_separated_duplicates(subcommand.separated_duplicates);
+                chain.validate_syntax()?;
+                Ok(chain)
+            }) {
+                Ok(chain) => chain,
+                Err(error) => {
+                    display_message(Level::Error, &error.to_string());
+                    exit(RunOutcome::from_error(&error).get_code());
+                }
+            };
+
+            if subcommand.lint {
+                for message in chain.lint() {
+                    display_message(Level::Warn, &message);
+                }
+            }
+
+            for (index, reason) in chain.get_destructive_programs() {
+                display_message(
+                    Level::Logging,
+                    &format!("Program #{}: {}, so running the chain needs `--allow-destructive` or a confirmation.", index, reason),
+                );
+            }
+
+            for (index, origin) in chain.get_program_origins().iter().enumerate() {
+                display_message(Level::Logging, &format!("Program #{} comes from {}.", index, origin));
+            }
+
+            // Function calls run when the chain does, list them so that
+            // there are no surprises
+            for (index, program) in chain.get_programs().iter().enumerate() {
+                for (field, function) in program.lock().unwrap().get_function_calls() {
+                    display_message(
+                        Level::Logging,
+                        &format!("Program #{} calls `{}` in `{}`.", index, function.get_name(), field),
+                    );
                 }
             }
         },
+        Commands::Fmt(subcommand) => {
+            let mut paths: Vec<PathBuf> = Vec::new();
+            if subcommand.all {
+                paths.extend(
+                    bookmark
+                        .get_chain_references()
+                        .iter()
+                        .map(|chain_reference| PathBuf::from(chain_reference.get_chain_path_string())),
+                );
+            }
+            for path in subcommand.paths.iter().map(PathBuf::from) {
+                if path.is_dir() {
+                    paths.extend(get_paths(&path)?.iter().map(|entry| entry.path()));
+                } else {
+                    paths.push(path);
+                }
+            }
+
+            let mut is_failed: bool = false;
+            for path in &paths {
+                let formatted: Result<(String, String), Error> = std::fs::read_to_string(path)
+                    .map_err(Error::from)
+                    .and_then(|content| Ok((format_chain_file(&content, subcommand.explicit_defaults)?, content)));
+                let (mut formatted, content) = match formatted {
+                    Ok(result) => result,
+                    Err(error) => {
+                        display_message(Level::Error, &format!("Cannot format {}: {}", path.display(), error));
+                        is_failed = true;
+                        continue;
+                    }
+                };
+                if subcommand.dedupe_consecutive {
+                    let (deduplicated, duplicates) = dedupe_consecutive_programs(&content, subcommand.explicit_defaults)?;
+                    if !duplicates.is_empty() {
+                        let programs: Vec<Program> = ChainFile::from_str(&content)?.programs;
+                        display_message(
+                            Level::Warn,
+                            &format!("{} has programs identical to the program right before them:", path.display()),
+                        );
+                        for duplicate in &duplicates {
+                            display_tree_message(
+                                1,
+                                &format!("Program #{} repeats program #{}: {}", duplicate.index, duplicate.original_index, programs[duplicate.index]),
+                            );
+                        }
+                        let interactor = SharedInteractor::default();
+                        let prompt: &str = "Remove them? [y/n]:";
+                        if !interactor.is_interactive() {
+                            display_message(Level::Error, &format!("Removing the programs needs a confirmation, but there is no user to answer `{}`", prompt));
+                            is_failed = true;
+                        } else if matches!(interactor.prompt(prompt)?.to_lowercase().as_str(), "y" | "yes") {
+                            formatted = deduplicated;
+                        }
+                    }
+                }
+                if formatted == content {
+                    continue;
+                }
+
+                if subcommand.check {
+                    display_message(Level::Warn, &format!("{} is not formatted:", path.display()));
+                    print!("{}", formatted);
+                    is_failed = true;
+                } else {
+                    write_file_if_unchanged(path, &content, formatted.as_bytes())?;
+                    display_message(Level::Logging, &format!("Formatted {}", path.display()));
+                }
+            }
+
+            if is_failed {
+                exit(RunOutcome::ValidationFailed.get_code());
+            }
+        },
         Commands::New(subcommand) => {
             let result: String;
-            let creation = ChainCreation::new(subcommand.name);
+            let creation = ChainCreation::new(subcommand.name)
+                .with_provenance(!subcommand.no_provenance)
+                .with_embedded_prompt(subcommand.embed_prompt);
             display_message(
                 Level::Logging,
                 &format!(
@@ -208,6 +1304,34 @@ fn main() -> Result<(), Error> {
                 result = creation.generate_chain(
                     prompt
                 )?;
+            } else if let Some(count) = subcommand.from_history {
+                let (path, format): (PathBuf, Option<HistoryFormat>) = match subcommand.history_file {
+                    Some(history_file) => (PathBuf::from(history_file), None),
+                    None => {
+                        let (path, format) = find_history_file()?;
+                        (path, Some(format))
+                    }
+                };
+                // Histories may hold bytes that are not UTF-8, e.g. from zsh's metafied lines
+                let content: String = String::from_utf8_lossy(
+                    &std::fs::read(&path).with_context(|| format!("Cannot read the history file {}", path.display()))?,
+                )
+                .to_string();
+                let format: HistoryFormat = format.unwrap_or_else(|| HistoryFormat::from_content(&content));
+                let commands: Vec<String> = get_recent_commands(&parse_history(&content, format), count);
+                if commands.is_empty() {
+                    display_message(Level::Error, &format!("There are no commands in {}.", path.display()));
+                    exit(1);
+                }
+
+                let interactor = SharedInteractor::default();
+                let indices: Vec<usize> = if std::io::stdin().is_terminal() && std::io::stdout().is_terminal() {
+                    select_commands_on_terminal(&commands)?
+                } else {
+                    select_commands_by_prompt(&commands, &interactor)?
+                };
+                let selected_commands: Vec<String> = indices.into_iter().map(|index| commands[index].clone()).collect();
+                result = creation.generate_from_history(&selected_commands, &interactor)?;
             } else {
                 result = creation.generate_template()?;
             }
@@ -215,11 +1339,227 @@ fn main() -> Result<(), Error> {
             creation.save(result)?;
             return Ok(());
         },
+        Commands::Stats(subcommand) => {
+            let storage: Storage = Storage::from_environment()?;
+            let records: Vec<RunHistoryRecord> = RunHistory::from_storage(&storage).read();
+            if !is_tracking_usage() && records.is_empty() {
+                display_message(
+                    Level::Logging,
+                    &format!("No runs are recorded. Set `{}=1` to keep the history of runs.", TRACK_USAGE_VARIABLE),
+                );
+                return Ok(());
+            }
+
+            if subcommand.failures {
+                let failures: Vec<RecurringFailure> = get_recurring_failures(&records);
+                if failures.is_empty() {
+                    display_message(Level::Logging, "No failure recurred across runs.");
+                    return Ok(());
+                }
+                let form_data: Vec<Vec<String>> = failures
+                    .iter()
+                    .map(|failure| {
+                        vec![
+                            failure.count.to_string(),
+                            if failure.is_flaky { "flaky" } else { "persistent" }.to_string(),
+                            failure.chain.clone(),
+                            format!("#{} {}", failure.program_index, failure.command),
+                            format!("{}: {}", failure.classification, failure.summary),
+                            format_timestamp(failure.first_seen),
+                            format_timestamp(failure.last_seen),
+                        ]
+                    })
+                    .collect();
+                display_form(vec!["Runs", "Kind", "Chain", "Program", "Failure", "First seen", "Last seen"], &form_data);
+                return Ok(());
+            }
+
+            if subcommand.environment {
+                let chains: BTreeSet<&str> = records.iter().map(|record| record.chain.as_str()).collect();
+                let mut form_data: Vec<Vec<String>> = Vec::new();
+                for chain in chains {
+                    let Some(environment) = find_last_environment(&records, chain) else {
+                        continue;
+                    };
+                    let mut row = |kind: &str, name: &str, value: &str| {
+                        form_data.push(vec![chain.to_string(), kind.to_string(), name.to_string(), value.to_string()]);
+                    };
+                    row("OS", "", environment.os_release.as_deref().unwrap_or("unknown"));
+                    for (command, version) in &environment.command_versions {
+                        row("Command", command, version.as_deref().unwrap_or("unknown"));
+                    }
+                    for (name, digest) in &environment.environment_digests {
+                        row("Env var", name, digest);
+                    }
+                }
+                if form_data.is_empty() {
+                    display_message(Level::Logging, "No successful run recorded its environment.");
+                    return Ok(());
+                }
+                display_form(vec!["Chain", "Kind", "Name", "Version or digest"], &form_data);
+                return Ok(());
+            }
+
+            // Runs, failed runs and the last run of each chain
+            let mut runs: BTreeMap<&str, (usize, usize, u64)> = BTreeMap::new();
+            for record in &records {
+                let entry = runs.entry(&record.chain).or_insert((0, 0, 0));
+                entry.0 += 1;
+                entry.1 += usize::from(!record.succeeded);
+                entry.2 = entry.2.max(record.timestamp);
+            }
+            let form_data: Vec<Vec<String>> = runs
+                .into_iter()
+                .map(|(chain, (count, failed, last_run))| {
+                    vec![chain.to_string(), count.to_string(), failed.to_string(), format_timestamp(last_run)]
+                })
+                .collect();
+            display_form(vec!["Chain", "Runs", "Failed", "Last run"], &form_data);
+            return Ok(());
+        },
+        Commands::Completions(subcommand) => {
+            print!("{}", generate_completion_script(subcommand.shell));
+            return Ok(());
+        },
+        // Handled before the bookmark is loaded
+        Commands::CompleteVars(_) => return Ok(()),
+        Commands::Preset(subcommand) => {
+            let storage: Storage = Storage::from_environment()?;
+            let mut variable_presets: VariablePresets = VariablePresets::from_storage(&storage)?;
+            match subcommand.action {
+                PresetAction::Set { name, value } => {
+                    variable_presets.set_value(&name, value);
+                    variable_presets.save()?;
+                    display_message(Level::Logging, &format!("Preset of `{}` is set.", name));
+                }
+                PresetAction::List => {
+                    if variable_presets.get_values().is_empty() {
+                        display_message(Level::Logging, "There are no presets. Add one with `cchain preset set <name> <value>`.");
+                        return Ok(());
+                    }
+                    let form_data: Vec<Vec<String>> = variable_presets
+                        .get_values()
+                        .iter()
+                        .map(|(name, value)| vec![name.clone(), value.clone()])
+                        .collect();
+                    display_form(vec!["Variable", "Preset"], &form_data);
+                }
+                PresetAction::Unset { name } => {
+                    if !variable_presets.unset_value(&name) {
+                        display_message(Level::Error, &format!("`{}` has no preset.", name));
+                        exit(1);
+                    }
+                    variable_presets.save()?;
+                    display_message(Level::Logging, &format!("Preset of `{}` is removed.", name));
+                }
+            }
+
+            return Ok(());
+        },
+        Commands::Vars(subcommand) => {
+            let path: String = match resolve_chain_argument(subcommand.action.get_chain(), &bookmark).selection {
+                ChainSelection::Selected { path } => path,
+                ChainSelection::Ambiguous { .. } => {
+                    display_message(
+                        Level::Error,
+                        &format!("`{}` matches several chains. Use the index or the path of one.", subcommand.action.get_chain()),
+                    );
+                    exit_with_outcome(RunOutcome::Usage, &[]);
+                }
+                ChainSelection::NotFound { reason } => {
+                    display_message(Level::Error, &reason);
+                    exit_with_outcome(RunOutcome::Usage, &[]);
+                }
+            };
+            let mut chain: Chain = Chain::from_file(&path)?;
+            let storage: Storage = Storage::from_environment()?;
+            let mut value_history: ValueHistory = ValueHistory::from_storage(&storage);
+            chain.set_value_history(value_history.clone());
+            chain.set_variable_presets(VariablePresets::from_storage(&storage)?);
+            let remembered_variables: Vec<RememberedVariable> = chain.get_remembered_variables();
+            let check_variable_name = |name: &str| {
+                if let Err(error) = chain.check_variable_name(name) {
+                    display_message(Level::Error, &error.to_string());
+                    exit_with_outcome(RunOutcome::Usage, &[]);
+                }
+            };
+
+            match subcommand.action {
+                VarsAction::List { output, .. } => {
+                    if output == OutputFormat::Json {
+                        println!("{}", serde_json::to_string_pretty(&remembered_variables)?);
+                        return Ok(());
+                    }
+                    if remembered_variables.is_empty() {
+                        display_message(Level::Logging, &format!("Chain {} prompts for no variables.", path));
+                        return Ok(());
+                    }
+                    let form_data: Vec<Vec<String>> = remembered_variables
+                        .iter()
+                        .map(|variable| {
+                            vec![
+                                variable.name.clone(),
+                                variable.kind.to_string(),
+                                variable.description.clone().unwrap_or_default(),
+                                variable.cached_value.clone().unwrap_or_default(),
+                                variable.preset.clone().unwrap_or_default(),
+                                variable.last_used.map(format_timestamp).unwrap_or_default(),
+                            ]
+                        })
+                        .collect();
+                    display_form(vec!["Variable", "Type", "Description", "Cached value", "Preset", "Last used"], &form_data);
+                }
+                VarsAction::Set { name, value, .. } => {
+                    check_variable_name(&name);
+                    // Values of secrets are never kept
+                    if remembered_variables.iter().any(|variable| variable.name == name && variable.secret) {
+                        display_message(Level::Error, &format!("`{}` is a secret, so no value is kept for it.", name));
+                        exit_with_outcome(RunOutcome::Usage, &[]);
+                    }
+                    value_history.record(&path, &name, &value);
+                    value_history.save()?;
+                    display_message(Level::Logging, &format!("Value of `{}` is kept for {}.", name, path));
+                }
+                VarsAction::Unset { name, .. } => {
+                    check_variable_name(&name);
+                    if !value_history.unset(&path, &name) {
+                        display_message(Level::Warn, &format!("No value is kept for `{}`.", name));
+                        return Ok(());
+                    }
+                    value_history.save()?;
+                    display_message(Level::Logging, &format!("Values of `{}` are forgotten.", name));
+                }
+                VarsAction::Clear { .. } => {
+                    let variables: usize = value_history.clear(&path);
+                    value_history.save()?;
+                    let noun: &str = if variables == 1 { "variable" } else { "variables" };
+                    display_message(Level::Logging, &format!("Values of {} {} are forgotten for {}.", variables, noun, path));
+                }
+            }
+
+            return Ok(());
+        },
+        Commands::Schema(subcommand) => {
+            let schema = match subcommand.progress {
+                true => get_progress_event_schema(),
+                false => get_chain_file_schema(),
+            };
+            println!("{}", serde_json::to_string_pretty(&schema)?);
+            return Ok(());
+        },
         Commands::Version(_) => {
             display_message(
                 Level::Logging,
                 &format!("cchain version: {}", crate_version!()),
             );
+            let disabled_features: Vec<&str> = Feature::ALL
+                .iter()
+                .filter(|feature| !feature.is_enabled())
+                .map(|feature| feature.get_name())
+                .collect();
+            if !disabled_features.is_empty() {
+                display_message(Level::Logging, &format!("Built without: {}", disabled_features.join(", ")));
+            }
 
             return Ok(());
         }
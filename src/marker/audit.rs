@@ -0,0 +1,173 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::commons::jsonl::JsonLinesFile;
+
+/// Name of the audit log, next to the bookmark
+pub const AUDIT_FILE_NAME: &str = "bookmark_audit.jsonl";
+
+/// A change made to the bookmark
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BookmarkOperation {
+    Add,
+    Remove,
+    Clean,
+    Reset,
+    Configure,
+}
+
+impl std::fmt::Display for BookmarkOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BookmarkOperation::Add => write!(f, "add"),
+            BookmarkOperation::Remove => write!(f, "remove"),
+            BookmarkOperation::Clean => write!(f, "clean"),
+            BookmarkOperation::Reset => write!(f, "reset"),
+            BookmarkOperation::Configure => write!(f, "configure"),
+        }
+    }
+}
+
+/// Who changes the bookmark, and with which command. Recorded with each
+/// change in the audit log.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditContext {
+    user: String,
+    command: Option<String>,
+}
+
+impl AuditContext {
+    /// Take the user from the environment, e.g. `USER`
+    pub fn from_environment() -> Self {
+        let user: String = std::env::var("USER")
+            .or_else(|_| std::env::var("USERNAME"))
+            .unwrap_or_else(|_| "unknown".to_string());
+        Self { user, command: None }
+    }
+
+    pub fn with_user(mut self, user: String) -> Self {
+        self.user = user;
+        self
+    }
+
+    /// Record the command that changes the bookmark, e.g. `cchain add .`
+    pub fn with_command(mut self, command: String) -> Self {
+        self.command = Some(command);
+        self
+    }
+
+    pub fn get_user(&self) -> &str {
+        &self.user
+    }
+}
+
+impl Default for AuditContext {
+    fn default() -> Self {
+        Self::from_environment()
+    }
+}
+
+/// A change to the bookmark, waiting for the bookmark to be saved
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditChange {
+    pub operation: BookmarkOperation,
+    /// Paths of the chains that were added or removed
+    pub paths: Vec<String>,
+    /// IDs of the chains that were added or removed
+    pub ids: Vec<String>,
+}
+
+/// A line of the audit log
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct AuditEntry {
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub user: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub command: Option<String>,
+    pub operation: BookmarkOperation,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub ids: Vec<String>,
+    /// SHA-256 digests of the bookmark file before and after the change.
+    /// `None` when there is no file, e.g. before the first chain is added.
+    pub hash_before: Option<String>,
+    pub hash_after: Option<String>,
+    /// The indices that the remaining chains moved to, and `null` for
+    /// the removed ones, by their indices before the change
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub index_remapping: BTreeMap<usize, Option<usize>>,
+}
+
+impl AuditEntry {
+    pub fn new(context: &AuditContext, change: AuditChange, hash_before: Option<String>, hash_after: Option<String>) -> Self {
+        Self {
+            timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs()),
+            user: context.user.clone(),
+            command: context.command.clone(),
+            operation: change.operation,
+            paths: change.paths,
+            ids: change.ids,
+            hash_before,
+            hash_after,
+            index_remapping: BTreeMap::new(),
+        }
+    }
+
+    /// Record the indices that changed, by the old indices
+    pub fn with_index_remapping(mut self, index_remapping: BTreeMap<usize, Option<usize>>) -> Self {
+        self.index_remapping = index_remapping;
+        self
+    }
+}
+
+/// The audit log of the bookmark. Each change appends a line, so that
+/// the changes made by several users of a machine are all kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuditLog {
+    file: JsonLinesFile,
+}
+
+impl AuditLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self { file: JsonLinesFile::new(path) }
+    }
+
+    /// Get the audit log next to the bookmark file
+    pub fn for_bookmark(bookmark_path: &Path) -> Self {
+        Self::new(bookmark_path.with_file_name(AUDIT_FILE_NAME))
+    }
+
+    pub fn get_path(&self) -> &Path {
+        self.file.get_path()
+    }
+
+    pub fn append(&self, entry: &AuditEntry) -> Result<(), Error> {
+        self.file.append(entry)
+    }
+
+    /// Read the entries in the order they were written. Lines that cannot
+    /// be read are skipped, and a missing file has no entries.
+    pub fn read(&self) -> Vec<AuditEntry> {
+        self.file.read().0
+    }
+
+    /// Drop the entries written before the timestamp
+    ///
+    /// # Returns
+    ///
+    /// The number of entries dropped
+    pub fn compact(&self, oldest_timestamp: u64) -> Result<usize, Error> {
+        let dropped: Vec<AuditEntry> = self.file.retain(|entry: &AuditEntry| entry.timestamp >= oldest_timestamp)?;
+
+        Ok(dropped.len())
+    }
+}
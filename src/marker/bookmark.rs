@@ -1,28 +1,176 @@
-use std::{path::{Path, PathBuf}, str::FromStr};
+use std::{collections::{BTreeMap, HashSet}, path::{Path, PathBuf}, str::FromStr};
 
 use anyhow::{anyhow, Error, Result};
-use dirs;
 use serde::{Deserialize, Serialize};
 
-use crate::{commons::{naming::HumanReadable, utility::check_required_packages}, core::chain::Chain};
+use crate::{commons::{digest::sha256_hex, lock::{write_file_atomically, FileLock}, naming::HumanReadable, storage::Storage, utility::check_required_packages}, core::chain::Chain, display_control::{display_message, Level}};
 
-use super::reference::ChainReference;
+use super::{
+    audit::{AuditChange, AuditContext, AuditEntry, AuditLog, BookmarkOperation},
+    reference::ChainReference,
+};
+
+/// Version of the bookmark format written by this version of cchain.
+/// Bookmarks without a version are the first format with `chain_references`.
+pub const BOOKMARK_FORMAT_VERSION: u32 = 1;
 
 /// `Bookmark` is a collection of references to the chains
 /// `ChainRefenence` is a reference to a chain
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Bookmark {
+    #[serde(default = "Bookmark::default_format_version")]
+    format_version: u32,
     chain_references: Vec<ChainReference>,
     bookmark_path: String,
+    /// The IDs ever assigned, keyed by the canonical paths of the chains,
+    /// so that a chain added again gets its previous ID back
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    id_history: BTreeMap<String, String>,
+    /// Who changes the bookmark, for the audit log
+    #[serde(skip)]
+    audit_context: AuditContext,
+    /// Changes not yet saved, which are written to the audit log on saving
+    #[serde(skip)]
+    audit_changes: Vec<AuditChange>,
+    /// Paths of the chains in the file when it was last loaded or saved,
+    /// which tell the chains removed since from those another process
+    /// added
+    #[serde(skip)]
+    loaded_paths: HashSet<String>,
+    /// IDs of the chains in the file, in order, when it was last loaded
+    /// or saved, which tell where the chains moved since
+    #[serde(skip)]
+    loaded_ids: Vec<String>,
+}
+
+/// The bookmark format used before `chain_references`
+#[derive(Debug, Deserialize)]
+struct LegacyBookmark {
+    configuration_paths: Vec<String>,
+}
+
+/// Why a chain reference was removed when cleaning the bookmark
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CleaningCategory {
+    /// The chain file no longer exists
+    Missing,
+    /// Another reference points to the same file
+    Duplicate,
+    /// The chain file exists but is not a valid chain
+    Invalid,
+}
+
+impl std::fmt::Display for CleaningCategory {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CleaningCategory::Missing => write!(f, "missing"),
+            CleaningCategory::Duplicate => write!(f, "duplicate"),
+            CleaningCategory::Invalid => write!(f, "invalid"),
+        }
+    }
+}
+
+/// The indices that the chains of a bookmark have after some of them
+/// are removed, by the indices they had before
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct IndexRemapping {
+    /// The new index of each old index, or `None` when the chain is removed
+    new_indices: Vec<Option<usize>>,
+}
+
+impl IndexRemapping {
+    pub fn new(new_indices: Vec<Option<usize>>) -> Self {
+        Self { new_indices }
+    }
+
+    /// Get the index a chain has now, or `None` when it is removed or
+    /// the old index is out of bounds
+    pub fn get_new_index(&self, old_index: usize) -> Option<usize> {
+        self.new_indices.get(old_index).copied().flatten()
+    }
+
+    /// Get the old indices whose chains moved or are removed, in order
+    pub fn get_changes(&self) -> Vec<(usize, Option<usize>)> {
+        self.new_indices
+            .iter()
+            .enumerate()
+            .filter(|(old_index, new_index)| **new_index != Some(*old_index))
+            .map(|(old_index, new_index)| (old_index, *new_index))
+            .collect()
+    }
+
+    /// Check whether every chain kept its index
+    pub fn is_unchanged(&self) -> bool {
+        self.get_changes().is_empty()
+    }
+}
+
+impl std::fmt::Display for IndexRemapping {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let changes: Vec<String> = self
+            .get_changes()
+            .into_iter()
+            .map(|(old_index, new_index)| match new_index {
+                Some(new_index) => format!("{} → {}", old_index, new_index),
+                None => format!("{} removed", old_index),
+            })
+            .collect();
+        write!(f, "{}", changes.join(", "))
+    }
+}
+
+/// A chain reference removed when cleaning the bookmark
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CleanedReference {
+    pub path: String,
+    pub category: CleaningCategory,
 }
 
 impl Bookmark {
-    pub fn reset() -> Result<(), Error> {
-        let new_path: PathBuf = dirs::home_dir().unwrap().join(".cchain");
+    /// Create an empty bookmark that will be saved to the path
+    pub fn new(bookmark_path: String) -> Self {
+        Self {
+            format_version: BOOKMARK_FORMAT_VERSION,
+            chain_references: Vec::new(),
+            bookmark_path,
+            id_history: BTreeMap::new(),
+            audit_context: AuditContext::default(),
+            audit_changes: Vec::new(),
+            loaded_paths: HashSet::new(),
+            loaded_ids: Vec::new(),
+        }
+    }
+
+    /// Set who changes the bookmark, as recorded in the audit log
+    pub fn set_audit_context(&mut self, audit_context: AuditContext) {
+        self.audit_context = audit_context;
+    }
+
+    /// Get the audit log of the bookmark, next to the bookmark file
+    pub fn get_audit_log(&self) -> AuditLog {
+        AuditLog::for_bookmark(Path::new(&self.bookmark_path))
+    }
+
+    fn default_format_version() -> u32 {
+        BOOKMARK_FORMAT_VERSION
+    }
+
+    pub fn get_format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    /// Delete the bookmark file of the user, and record it in the audit log
+    pub fn reset(audit_context: &AuditContext) -> Result<(), Error> {
+        let new_path: PathBuf = Storage::from_environment()?.get_bookmark_path();
 
         if new_path.exists() {
+            let hash_before: Option<String> = std::fs::read(&new_path).ok().map(|content| sha256_hex(&content));
             match std::fs::remove_file(&new_path) {
-                Ok(_) => return Ok(()),
+                Ok(_) => {
+                    let change = AuditChange { operation: BookmarkOperation::Reset, paths: Vec::new(), ids: Vec::new() };
+                    write_audit_entry(&AuditLog::for_bookmark(&new_path), AuditEntry::new(audit_context, change, hash_before, None));
+                    return Ok(());
+                }
                 Err(error) => {
                     return Err(anyhow!(
                         "Failed to delete existing bookmark file: {}",
@@ -35,45 +183,305 @@ impl Bookmark {
         Ok(())
     }
 
-    pub fn from_file() -> Self {
-        let bookmark_path = dirs::home_dir().unwrap().join(".cchain");
+    /// Load the bookmark of the user from the storage directory. A
+    /// bookmark at the legacy location is moved there first.
+    pub fn from_file() -> Result<Self, Error> {
+        let storage: Storage = Storage::from_environment()?;
+        if storage.migrate_legacy_bookmark()? {
+            display_message(
+                Level::Logging,
+                &format!("Bookmark is moved to {}", storage.get_bookmark_path().display()),
+            );
+        }
+        std::fs::create_dir_all(storage.get_data_directory())?;
 
-        if bookmark_path.exists() {
-            let bookmark_file = std::fs::read_to_string(&bookmark_path).unwrap();
-            serde_json::from_str(&bookmark_file).unwrap()
-        } else {
-            Bookmark {
-                chain_references: Vec::new(),
-                bookmark_path: bookmark_path.to_string_lossy().into_owned(),
+        Self::from_path(&storage.get_bookmark_path())
+    }
+
+    /// Load the bookmark saved at the path. Bookmarks in the legacy
+    /// format are migrated, with a backup of the original file.
+    pub fn from_path(bookmark_path: &Path) -> Result<Self, Error> {
+        if !bookmark_path.exists() {
+            return Ok(Bookmark::new(bookmark_path.to_string_lossy().into_owned()));
+        }
+
+        let bookmark_file = std::fs::read_to_string(bookmark_path)?;
+        let error = match serde_json::from_str::<Bookmark>(&bookmark_file) {
+            Ok(mut bookmark) => {
+                // The bookmark is saved where it is loaded from, even if
+                // it has been moved since it was written
+                bookmark.bookmark_path = bookmark_path.to_string_lossy().into_owned();
+                bookmark.loaded_paths = bookmark.get_chain_paths();
+                bookmark.loaded_ids = bookmark.get_chain_ids();
+                // Bookmarks saved before IDs existed get them on loading
+                if bookmark.assign_missing_ids() {
+                    bookmark.save()?;
+                }
+                return Ok(bookmark);
             }
+            Err(error) => error,
+        };
+
+        match serde_json::from_str::<LegacyBookmark>(&bookmark_file) {
+            Ok(legacy_bookmark) => Self::migrate_legacy_bookmark(bookmark_path, legacy_bookmark),
+            Err(_) => Err(anyhow!(
+                "Bookmark at {} cannot be read: {}. You may reset it with `cchain remove --reset`",
+                bookmark_path.display(),
+                error
+            )),
         }
     }
 
-    pub fn save(&self) {
-        let bookmark_file: String = serde_json::to_string(&self).unwrap();
+    /// Convert a legacy bookmark into the current format, back up the
+    /// legacy file and save the migrated bookmark in its place
+    fn migrate_legacy_bookmark(bookmark_path: &Path, legacy_bookmark: LegacyBookmark) -> Result<Self, Error> {
+        display_message(
+            Level::Logging,
+            "Bookmark is in a legacy format. Migrating...",
+        );
+
+        let mut bookmark = Bookmark::new(bookmark_path.to_string_lossy().into_owned());
+        for path in legacy_bookmark.configuration_paths {
+            match ChainReference::from_str(&path) {
+                Ok(chain_reference) => bookmark.chain_references.push(chain_reference),
+                Err(error) => display_message(
+                    Level::Warn,
+                    &format!("Skipped migrating {}: {}", path, error),
+                ),
+            }
+        }
+
+        bookmark.assign_missing_ids();
+
+        let mut backup_path = bookmark_path.as_os_str().to_owned();
+        backup_path.push(".legacy.bak");
+        std::fs::copy(bookmark_path, &backup_path)?;
+        bookmark.save()?;
+
+        display_message(
+            Level::Logging,
+            &format!(
+                "{} chains are migrated. The legacy bookmark is backed up at {}",
+                bookmark.chain_references.len(),
+                Path::new(&backup_path).display()
+            ),
+        );
+
+        Ok(bookmark)
+    }
+
+    /// Write the bookmark file under its lock, and record the changes
+    /// since the last save in the audit log. Chains that other processes
+    /// added since the file was loaded are kept, so that concurrent
+    /// `cchain add`s do not lose each other's chains. The audit log never
+    /// stops the bookmark from being saved.
+    pub fn save(&mut self) -> Result<(), Error> {
+        let bookmark_path: PathBuf = PathBuf::from(&self.bookmark_path);
+        let _lock: FileLock = FileLock::acquire(&bookmark_path)?;
+        let saved_content: Option<Vec<u8>> = std::fs::read(&bookmark_path).ok();
+        if let Some(saved_bookmark) = saved_content
+            .as_ref()
+            .and_then(|content| serde_json::from_slice::<Bookmark>(content).ok())
+        {
+            self.merge_saved_bookmark(saved_bookmark);
+        }
+
+        let bookmark_file: String = serde_json::to_string(&self)?;
+        let hash_before: Option<String> = saved_content.map(|content| sha256_hex(&content));
+        write_file_atomically(&bookmark_path, bookmark_file.as_bytes())?;
+        let index_remapping: IndexRemapping = self.get_index_remapping();
+        self.loaded_paths = self.get_chain_paths();
+        self.loaded_ids = self.get_chain_ids();
+
+        let hash_after: Option<String> = Some(sha256_hex(bookmark_file.as_bytes()));
+        let audit_log: AuditLog = self.get_audit_log();
+        let changes: Vec<AuditChange> = std::mem::take(&mut self.audit_changes);
+        // The indices that moved are recorded with the last removal
+        let last_removal: Option<usize> = changes.iter().rposition(|change| {
+            matches!(change.operation, BookmarkOperation::Remove | BookmarkOperation::Clean)
+        });
+        for (position, change) in changes.into_iter().enumerate() {
+            let mut entry = AuditEntry::new(&self.audit_context, change, hash_before.clone(), hash_after.clone());
+            if Some(position) == last_removal {
+                entry = entry.with_index_remapping(index_remapping.get_changes().into_iter().collect());
+            }
+            write_audit_entry(&audit_log, entry);
+        }
 
-        std::fs::write(&self.bookmark_path, bookmark_file).unwrap();
+        Ok(())
+    }
+
+    fn get_chain_ids(&self) -> Vec<String> {
+        self.chain_references
+            .iter()
+            .map(|chain_reference| chain_reference.get_id().to_string())
+            .collect()
+    }
+
+    /// Get where the chains moved since the bookmark was loaded or last
+    /// saved, as removing chains shifts the positional indices of the
+    /// chains after them. Stable IDs never move.
+    pub fn get_index_remapping(&self) -> IndexRemapping {
+        let ids: Vec<String> = self.get_chain_ids();
+        IndexRemapping::new(
+            self.loaded_ids
+                .iter()
+                .map(|loaded_id| ids.iter().position(|id| id == loaded_id))
+                .collect(),
+        )
+    }
+
+    fn get_chain_paths(&self) -> HashSet<String> {
+        self.chain_references
+            .iter()
+            .map(|chain_reference| chain_reference.get_chain_path_string())
+            .collect()
+    }
+
+    /// Add the chains in the saved file that were not there when this
+    /// bookmark was loaded, i.e. added by another process. The chains
+    /// removed here stay removed, and a chain in both is kept as it is
+    /// here.
+    fn merge_saved_bookmark(&mut self, saved_bookmark: Bookmark) {
+        for (path, id) in saved_bookmark.id_history {
+            self.id_history.entry(path).or_insert(id);
+        }
+
+        let paths: HashSet<String> = self.get_chain_paths();
+        for mut chain_reference in saved_bookmark.chain_references {
+            let path: String = chain_reference.get_chain_path_string();
+            if self.loaded_paths.contains(&path) || paths.contains(&path) {
+                continue;
+            }
+            if self.chain_references.iter().any(|item| item.get_id() == chain_reference.get_id()) {
+                let id: String = self.get_available_id(&chain_reference);
+                chain_reference.set_id(id);
+                self.record_id(&chain_reference);
+            }
+            self.chain_references.push(chain_reference);
+        }
     }
 
     pub fn add_chain_reference(&mut self, configuration_path: String) -> Result<(), Error> {
+        self.add_chain_reference_with_options(configuration_path, false)
+    }
+
+    /// Add a chain to the bookmark. With `allow_any_name`, the file name
+    /// does not need to follow the naming convention.
+    pub fn add_chain_reference_with_options(&mut self, configuration_path: String, allow_any_name: bool) -> Result<(), Error> {
         // Check if the file is a valid chain file
         check_required_packages(&Chain::from_file(&configuration_path)?)?;
         
-        let chain_reference = ChainReference::from_str(&configuration_path)?;
-        if self.chain_references.contains(&chain_reference) {
-            return Err(anyhow::anyhow!(
+        let mut chain_reference = ChainReference::from_path(&configuration_path, allow_any_name)?;
+        chain_reference.refresh()?;
+        if self
+            .chain_references
+            .iter()
+            .any(|item| item.get_chain_path_string() == configuration_path)
+        {
+            Err(anyhow::anyhow!(
                 "Configuration is likely duplicated: {}",
                 &configuration_path
-            ));
+            ))
         } else {
+            let id: String = self.get_available_id(&chain_reference);
+            chain_reference.set_id(id.clone());
+            self.record_id(&chain_reference);
+            self.audit_changes.push(AuditChange {
+                operation: BookmarkOperation::Add,
+                paths: vec![chain_reference.get_chain_path_string()],
+                ids: vec![id],
+            });
             self.chain_references.push(chain_reference);
             Ok(())
         }
     }
 
+    /// Get the canonical path of a chain reference, which keys the ID history
+    fn get_canonical_path(chain_reference: &ChainReference) -> String {
+        let path_string: String = chain_reference.get_chain_path_string();
+        std::fs::canonicalize(&path_string)
+            .map(|path| path.to_string_lossy().into_owned())
+            .unwrap_or(path_string)
+    }
+
+    /// Whether the ID is taken by a chain in the bookmark, or was
+    /// assigned to another chain before
+    fn is_id_taken(&self, id: &str, canonical_path: &str) -> bool {
+        self.chain_references.iter().any(|chain_reference| chain_reference.get_id() == id)
+            || self
+                .id_history
+                .iter()
+                .any(|(path, previous_id)| previous_id == id && path != canonical_path)
+    }
+
+    /// Get the ID for a chain reference: its previous ID when it was in
+    /// the bookmark before, otherwise a slug of its name with a short hash
+    /// of its path, e.g. `deploy-a3f2`
+    fn get_available_id(&self, chain_reference: &ChainReference) -> String {
+        let canonical_path: String = Self::get_canonical_path(chain_reference);
+        if let Some(id) = self.id_history.get(&canonical_path) {
+            if !self.chain_references.iter().any(|item| item.get_id() == id) {
+                return id.clone();
+            }
+        }
+
+        let mut slug: String = chain_reference
+            .get_human_readable_name()
+            .split_whitespace()
+            .map(|word| word.to_lowercase())
+            .collect::<Vec<String>>()
+            .join("-");
+        if slug.is_empty() {
+            slug = "chain".to_string();
+        }
+
+        // Rehash with an attempt counter until the ID is free. The hash
+        // is SHA-256, so that IDs are the same across Rust releases.
+        let mut attempt: u32 = 0;
+        loop {
+            let digest: String = sha256_hex(format!("{}\n{}", canonical_path, attempt).as_bytes());
+            let id: String = format!("{}-{}", slug, &digest[..4]);
+            if !self.is_id_taken(&id, &canonical_path) {
+                return id;
+            }
+            attempt += 1;
+        }
+    }
+
+    fn record_id(&mut self, chain_reference: &ChainReference) {
+        self.id_history.insert(
+            Self::get_canonical_path(chain_reference),
+            chain_reference.get_id().to_string(),
+        );
+    }
+
+    /// Assign IDs to the chain references without one.
+    ///
+    /// # Returns
+    ///
+    /// Whether any ID is assigned, i.e. the bookmark needs saving
+    pub fn assign_missing_ids(&mut self) -> bool {
+        let mut is_updated: bool = false;
+        for index in 0..self.chain_references.len() {
+            if !self.chain_references[index].get_id().is_empty() {
+                continue;
+            }
+
+            let id: String = self.get_available_id(&self.chain_references[index]);
+            self.chain_references[index].set_id(id);
+            let chain_reference: &ChainReference = &self.chain_references[index];
+            self.id_history.insert(Self::get_canonical_path(chain_reference), chain_reference.get_id().to_string());
+            is_updated = true;
+        }
+
+        is_updated
+    }
+
     pub fn remove_chain_reference_by_index(&mut self, index: usize) -> Result<(), Error> {
         if index < self.chain_references.len() {
-            self.chain_references.remove(index);
+            let chain_reference: ChainReference = self.chain_references.remove(index);
+            self.record_removal(BookmarkOperation::Remove, vec![chain_reference]);
             Ok(())
         } else {
             Err(anyhow::anyhow!("Index out of bounds: {}", index))
@@ -89,7 +497,8 @@ impl Bookmark {
             .iter()
             .position(|x| x.get_chain_path_string() == configuration_path)
         {
-            self.chain_references.remove(pos);
+            let chain_reference: ChainReference = self.chain_references.remove(pos);
+            self.record_removal(BookmarkOperation::Remove, vec![chain_reference]);
             Ok(())
         } else {
             Err(anyhow::anyhow!(
@@ -99,6 +508,40 @@ impl Bookmark {
         }
     }
 
+    /// Store the arguments that `cchain run` always gets for the chain at
+    /// the index, or remove them with `None`. The caller checks them as
+    /// `cchain run` would parse them, e.g. with `parse_stored_run_arguments`.
+    pub fn set_run_arguments(&mut self, index: usize, run_arguments: Option<String>) -> Result<(), Error> {
+        let chain_reference: &mut ChainReference = self
+            .chain_references
+            .get_mut(index)
+            .ok_or_else(|| anyhow!("Index out of bounds: {}", index))?;
+        chain_reference.set_run_arguments(run_arguments);
+        self.audit_changes.push(AuditChange {
+            operation: BookmarkOperation::Configure,
+            paths: vec![chain_reference.get_chain_path_string()],
+            ids: vec![chain_reference.get_id().to_string()],
+        });
+
+        Ok(())
+    }
+
+    /// Get the chain reference to the chain file at the path
+    pub fn get_chain_reference_by_path(&self, path: &str) -> Option<&ChainReference> {
+        self.chain_references
+            .iter()
+            .find(|chain_reference| chain_reference.get_chain_path_string() == path)
+    }
+
+    /// Record the removal of chain references for the audit log
+    fn record_removal(&mut self, operation: BookmarkOperation, chain_references: Vec<ChainReference>) {
+        self.audit_changes.push(AuditChange {
+            operation,
+            paths: chain_references.iter().map(|chain_reference| chain_reference.get_chain_path_string()).collect(),
+            ids: chain_references.iter().map(|chain_reference| chain_reference.get_id().to_string()).collect(),
+        });
+    }
+
     /// Get all paths of the chains in the bookmark that are no longer exist
     /// in their original positions
     pub fn get_invalid_paths(&self) -> Result<Vec<String>, Error> {
@@ -116,6 +559,133 @@ impl Bookmark {
         Ok(invalid_paths)
     }
 
+    /// Get the paths of the chains that exist but cannot be loaded,
+    /// along with the reason
+    pub fn get_unparsable_paths(&self) -> Vec<(String, String)> {
+        let mut unparsable_paths: Vec<(String, String)> = Vec::new();
+        for chain_reference in &self.chain_references {
+            let path_string: String = chain_reference.get_chain_path_string();
+            if !Path::new(&path_string).exists() {
+                continue;
+            }
+
+            if let Err(error) = Chain::from_file(&path_string) {
+                unparsable_paths.push((path_string, error.to_string()));
+            }
+        }
+
+        unparsable_paths
+    }
+
+    /// Canonicalize the paths of the chain references and collapse the
+    /// references pointing at the same file. The earliest reference is kept.
+    ///
+    /// # Returns
+    ///
+    /// The original paths of the removed references
+    pub fn deduplicate(&mut self) -> Vec<String> {
+        let mut seen_paths: HashSet<PathBuf> = HashSet::new();
+        let mut removed_references: Vec<ChainReference> = Vec::new();
+        let mut chain_references: Vec<ChainReference> = Vec::new();
+
+        for chain_reference in self.chain_references.drain(..) {
+            let path_string: String = chain_reference.get_chain_path_string();
+            // Paths that cannot be canonicalized are compared as they are
+            let canonical_path: PathBuf = std::fs::canonicalize(&path_string)
+                .unwrap_or_else(|_| PathBuf::from(&path_string));
+
+            if seen_paths.insert(canonical_path.clone()) {
+                let mut chain_reference = chain_reference;
+                chain_reference.set_chain_path(canonical_path.to_string_lossy().into_owned());
+                chain_references.push(chain_reference);
+            } else {
+                removed_references.push(chain_reference);
+            }
+        }
+
+        self.chain_references = chain_references;
+        let removed_paths: Vec<String> =
+            removed_references.iter().map(|chain_reference| chain_reference.get_chain_path_string()).collect();
+        if !removed_references.is_empty() {
+            self.record_removal(BookmarkOperation::Clean, removed_references);
+        }
+        removed_paths
+    }
+
+    /// Remove the references to missing chains and the duplicated
+    /// references. References to invalid chains are only removed when
+    /// `remove_invalid` is set.
+    pub fn clean(&mut self, remove_invalid: bool) -> Result<Vec<CleanedReference>, Error> {
+        let mut cleaned_references: Vec<CleanedReference> = Vec::new();
+        let audit_change_count: usize = self.audit_changes.len();
+
+        for path in self.get_invalid_paths()? {
+            self.remove_chain_reference_by_path(&path)?;
+            cleaned_references.push(CleanedReference {
+                path,
+                category: CleaningCategory::Missing,
+            });
+        }
+
+        for path in self.deduplicate() {
+            cleaned_references.push(CleanedReference {
+                path,
+                category: CleaningCategory::Duplicate,
+            });
+        }
+
+        if remove_invalid {
+            for (path, _) in self.get_unparsable_paths() {
+                self.remove_chain_reference_by_path(&path)?;
+                cleaned_references.push(CleanedReference {
+                    path,
+                    category: CleaningCategory::Invalid,
+                });
+            }
+        }
+
+        // The removals are recorded as one change
+        let removals: Vec<AuditChange> = self.audit_changes.split_off(audit_change_count);
+        if !removals.is_empty() {
+            self.audit_changes.push(AuditChange {
+                operation: BookmarkOperation::Clean,
+                paths: removals.iter().flat_map(|removal| removal.paths.clone()).collect(),
+                ids: removals.into_iter().flat_map(|removal| removal.ids).collect(),
+            });
+        }
+
+        Ok(cleaned_references)
+    }
+
+    /// Update the cached data of the chain references whose files have
+    /// changed. References that cannot be refreshed keep their data.
+    ///
+    /// # Returns
+    ///
+    /// Whether any reference is updated, i.e. the bookmark needs saving
+    pub fn refresh(&mut self) -> bool {
+        let mut is_updated: bool = false;
+        for chain_reference in &mut self.chain_references {
+            if let Ok(true) = chain_reference.refresh() {
+                is_updated = true;
+            }
+        }
+
+        is_updated
+    }
+
+    /// Get the chain references with the tag, sorted by name
+    pub fn get_chain_references_by_tag(&self, tag: &str) -> Vec<&ChainReference> {
+        let mut chain_references: Vec<&ChainReference> = self
+            .chain_references
+            .iter()
+            .filter(|chain_reference| chain_reference.has_tag(tag))
+            .collect();
+        chain_references.sort_by_key(|chain_reference| chain_reference.get_human_readable_name());
+
+        chain_references
+    }
+
     pub fn get_chain_references(&self) -> &Vec<ChainReference> {
         &self.chain_references
     }
@@ -123,16 +693,36 @@ impl Bookmark {
     pub fn get_chain_reference_by_index(&self, index: usize) -> Option<&ChainReference> {
         self.chain_references.get(index)
     }
+
+    /// Get the positional index of the chain with the stable ID
+    pub fn get_index_by_id(&self, id: &str) -> Option<usize> {
+        self.chain_references
+            .iter()
+            .position(|chain_reference| chain_reference.get_id() == id)
+    }
     
     /// Search chains by using keywords
     pub fn get_chains_by_keywords(&self, keywords: Vec<String>) -> Option<Vec<&ChainReference>> {
+        let results: Vec<&ChainReference> = self
+            .score_chains_by_keywords(keywords)
+            .into_iter()
+            .map(|(index, _)| &self.chain_references[index])
+            .collect();
+
+        Some(results)
+    }
+
+    /// Score the chains by the number of keywords found in their names.
+    /// Returns the indices of the matched chains with their scores, in
+    /// descending order of the scores
+    pub fn score_chains_by_keywords(&self, keywords: Vec<String>) -> Vec<(usize, usize)> {
         let keywords: Vec<String> = keywords.iter()
             .map(|keyword| keyword.to_lowercase())
             .collect::<Vec<String>>();
-        let mut matched_chains: Vec<(&ChainReference, usize)> = Vec::new();
+        let mut matched_chains: Vec<(usize, usize)> = Vec::new();
         
         // Iterate over the chain references
-        for chain_reference in &self.chain_references {
+        for (index, chain_reference) in self.chain_references.iter().enumerate() {
             // Use human readable name to be searched
             let name: String = chain_reference.get_human_readable_name();
             let words: Vec<String> = name.split(" ")
@@ -148,39 +738,29 @@ impl Bookmark {
                 
                 // When a keyword is found in the name
                 if words.contains(keyword) {
-                    let mut is_existing: bool = false;
                     // Increment the match count if the chain is already in the list
-                    for matched_chain in &mut matched_chains {
-                        if matched_chain.0 == chain_reference {
-                            matched_chain.1 += 1;
-                            is_existing = true;
-                        }
-                    }
-                    
-                    // Add the chain to the list if the chain is not already in the list
-                    if !is_existing {
-                        matched_chains.push(
-                            (chain_reference, 1)
-                        );
+                    match matched_chains.iter_mut().find(|matched_chain| matched_chain.0 == index) {
+                        Some(matched_chain) => matched_chain.1 += 1,
+                        None => matched_chains.push((index, 1)),
                     }
-                    
-                    continue;
                 }
             }
         }
         
         // Sort the chains by match count in descending order
         matched_chains
-            .sort_by(|a, b| b.1.cmp(&a.1));
-        
-        let mut results = Vec::new();
-        for matched_chain in matched_chains {
-            // Skip the chains if the score is zero
-            if matched_chain.1 != 0 {
-                results.push(matched_chain.0);
-            }
-        }
+            .sort_by_key(|matched_chain| std::cmp::Reverse(matched_chain.1));
 
-        Some(results)
+        matched_chains
     }
-}
\ No newline at end of file
+}
+
+/// Append an entry to the audit log, warning instead of failing
+fn write_audit_entry(audit_log: &AuditLog, entry: AuditEntry) {
+    if let Err(error) = audit_log.append(&entry) {
+        display_message(
+            Level::Warn,
+            &format!("Cannot write the audit log at {}: {}", audit_log.get_path().display(), error),
+        );
+    }
+}
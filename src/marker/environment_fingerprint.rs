@@ -0,0 +1,404 @@
+use std::{
+    cell::RefCell,
+    collections::{hash_map::RandomState, BTreeMap},
+    ffi::OsString,
+    hash::{BuildHasher, Hasher},
+    io::{Read, Write},
+    path::{Path, PathBuf},
+    process::{Command, Stdio},
+    sync::atomic::{AtomicBool, Ordering},
+    time::{Duration, Instant, UNIX_EPOCH},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{commons::{digest::sha256_hex, lock::{write_file_atomically, FileLock}, storage::{Storage, StorageCategory}}, core::chain::Chain};
+
+/// Time that `<command> --version` gets to answer. A command taking
+/// longer is recorded without a version.
+pub const VERSION_PROBE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Name of the file of the versions of the commands in the cache directory
+const VERSION_CACHE_FILE_NAME: &str = "command_versions.json";
+
+/// Name of the file of the salt of the environment digests in the data
+/// directory
+const FINGERPRINT_SALT_FILE_NAME: &str = "fingerprint_salt";
+
+/// The commands whose versions are probed: well-known tools that answer
+/// `--version` without doing anything else. Other commands, and scripts
+/// of the chain such as `./deploy.sh`, are never run to probe them.
+pub const VERSION_PROBED_COMMANDS: &[&str] = &[
+    "ansible", "aws", "az", "bash", "bun", "cargo", "clang", "cmake", "composer", "curl", "deno", "docker", "dotnet",
+    "gcc", "gcloud", "git", "go", "gradle", "helm", "java", "javac", "kubectl", "make", "mvn", "node", "npm", "npx",
+    "php", "pip", "pip3", "pnpm", "python", "python3", "ruby", "rustc", "rustup", "terraform", "yarn", "zsh",
+];
+
+/// Check whether the version of a command is probed: a bare name, looked
+/// up in `PATH`, of a tool in [`VERSION_PROBED_COMMANDS`]
+pub fn is_version_probed(command: &str) -> bool {
+    !command.contains('/') && !command.contains('\\') && VERSION_PROBED_COMMANDS.contains(&command)
+}
+
+/// Whether the environment is compared with the last successful run,
+/// unset by `--no-env-check`
+static IS_ENVIRONMENT_CHECKED: AtomicBool = AtomicBool::new(true);
+
+pub fn set_environment_check_enabled(is_enabled: bool) {
+    IS_ENVIRONMENT_CHECKED.store(is_enabled, Ordering::SeqCst);
+}
+
+pub fn is_environment_check_enabled() -> bool {
+    IS_ENVIRONMENT_CHECKED.load(Ordering::SeqCst)
+}
+
+/// What a chain ran with: the versions of its commands, the release of
+/// the OS, and the digests of the environment variables it reads with
+/// `env('NAME')`. Recorded with the successful runs in the run history.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct EnvironmentFingerprint {
+    /// The first line of `<command> --version`, or None when it could
+    /// not be obtained
+    #[serde(default)]
+    pub command_versions: BTreeMap<String, Option<String>>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub os_release: Option<String>,
+    /// The digests of the values, never the values, or `unset`
+    #[serde(default)]
+    pub environment_digests: BTreeMap<String, String>,
+    /// Whether the digests are salted. Those of older runs are not, and
+    /// are not compared.
+    #[serde(default)]
+    pub is_salted: bool,
+}
+
+/// How the environment of a run differs from the last successful one
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum EnvironmentDifference {
+    CommandVersion { command: String, previous: String, current: String },
+    OsRelease { previous: String, current: String },
+    EnvironmentVariable { name: String },
+}
+
+impl EnvironmentFingerprint {
+    /// Collect the fingerprint of the commands and the environment
+    /// variables
+    ///
+    /// # Arguments
+    ///
+    /// * `environment` - The values of the variables, or None when unset
+    /// * `salt` - The salt of the digests, so that short values cannot be
+    ///   guessed from the run history
+    pub fn collect(
+        commands: &[String],
+        environment: &BTreeMap<String, Option<String>>,
+        prober: &dyn VersionProber,
+        salt: &str,
+    ) -> Self {
+        Self {
+            command_versions: commands
+                .iter()
+                .map(|command| (command.clone(), prober.probe_version(command)))
+                .collect(),
+            os_release: get_os_release(),
+            environment_digests: environment
+                .iter()
+                .map(|(name, value)| {
+                    let digest: String = match value {
+                        Some(value) => sha256_hex(format!("{}\0{}", salt, value).as_bytes())[..16].to_string(),
+                        None => "unset".to_string(),
+                    };
+                    (name.clone(), digest)
+                })
+                .collect(),
+            is_salted: true,
+        }
+    }
+
+    /// Collect the fingerprint of the commands of the programs and their
+    /// remedies that are probed, and of the environment variables they
+    /// read
+    pub fn from_chain(chain: &Chain, prober: &dyn VersionProber, salt: &str) -> Self {
+        let mut commands: Vec<String> = Vec::new();
+        let mut environment_names: Vec<String> = Vec::new();
+        for program in chain.get_programs() {
+            let mut program = program.lock().unwrap();
+            let mut program_commands: Vec<String> = vec![program.get_command_line().get_command().to_string()];
+            if let Some(remedy_command_line) = program.get_remedy_command_line() {
+                program_commands.push(remedy_command_line.get_command().to_string());
+            }
+            for command in program_commands {
+                if is_version_probed(&command) && !commands.contains(&command) {
+                    commands.push(command);
+                }
+            }
+            for (_, function) in program.get_function_calls() {
+                if function.get_name() == "env" && !environment_names.contains(&function.get_parameters()[0]) {
+                    environment_names.push(function.get_parameters()[0].clone());
+                }
+            }
+        }
+
+        let environment: BTreeMap<String, Option<String>> = environment_names
+            .into_iter()
+            .map(|name| {
+                let value: Option<String> = std::env::var(&name).ok();
+                (name, value)
+            })
+            .collect();
+
+        Self::collect(&commands, &environment, prober, salt)
+    }
+
+    /// Compare with the fingerprint of an earlier run. Commands without a
+    /// version in either run, and those only one of the runs used, are
+    /// not compared.
+    pub fn get_differences(&self, previous: &Self) -> Vec<EnvironmentDifference> {
+        let mut differences: Vec<EnvironmentDifference> = Vec::new();
+        for (command, version) in &self.command_versions {
+            if let (Some(Some(previous_version)), Some(version)) = (previous.command_versions.get(command), version) {
+                if previous_version != version {
+                    differences.push(EnvironmentDifference::CommandVersion {
+                        command: command.clone(),
+                        previous: previous_version.clone(),
+                        current: version.clone(),
+                    });
+                }
+            }
+        }
+        if let (Some(previous_release), Some(release)) = (&previous.os_release, &self.os_release) {
+            if previous_release != release {
+                differences.push(EnvironmentDifference::OsRelease {
+                    previous: previous_release.clone(),
+                    current: release.clone(),
+                });
+            }
+        }
+        for (name, digest) in self.environment_digests.iter().filter(|_| self.is_salted && previous.is_salted) {
+            if previous.environment_digests.get(name).is_some_and(|previous_digest| previous_digest != digest) {
+                differences.push(EnvironmentDifference::EnvironmentVariable { name: name.clone() });
+            }
+        }
+
+        differences
+    }
+}
+
+/// Describe how the environment differs from the last successful run on
+/// one line, e.g. `node: v18.17.0 → v20.5.1, OS unchanged, 1 env var
+/// changed`
+///
+/// # Returns
+///
+/// None when nothing differs
+pub fn render_environment_notice(previous: &EnvironmentFingerprint, current: &EnvironmentFingerprint) -> Option<String> {
+    let differences: Vec<EnvironmentDifference> = current.get_differences(previous);
+    if differences.is_empty() {
+        return None;
+    }
+
+    let mut parts: Vec<String> = Vec::new();
+    let mut is_os_changed: bool = false;
+    let mut changed_variables: usize = 0;
+    for difference in &differences {
+        match difference {
+            EnvironmentDifference::CommandVersion { command, previous, current } => {
+                parts.push(format!("{}: {} → {}", command, previous, current));
+            }
+            EnvironmentDifference::OsRelease { previous, current } => {
+                is_os_changed = true;
+                parts.push(format!("OS: {} → {}", previous, current));
+            }
+            EnvironmentDifference::EnvironmentVariable { .. } => changed_variables += 1,
+        }
+    }
+    if !is_os_changed {
+        parts.push("OS unchanged".to_string());
+    }
+    if changed_variables > 0 {
+        let noun: &str = if changed_variables == 1 { "env var" } else { "env vars" };
+        parts.push(format!("{} {} changed", changed_variables, noun));
+    }
+
+    Some(format!("The environment differs from the last successful run: {}", parts.join(", ")))
+}
+
+/// Get the name and the version of the OS, e.g. `Ubuntu 22.04.4 LTS`
+pub fn get_os_release() -> Option<String> {
+    let os_release: String = std::fs::read_to_string("/etc/os-release").unwrap_or_default();
+    let pretty_name: Option<String> = os_release.lines().find_map(|line| {
+        line.strip_prefix("PRETTY_NAME=").map(|value| value.trim_matches('"').to_string())
+    });
+
+    pretty_name.or_else(|| Some(std::env::consts::OS.to_string()))
+}
+
+/// Finds out the version of a command
+pub trait VersionProber {
+    /// Get the version of the command, or None when it cannot be
+    /// obtained. Never fails.
+    fn probe_version(&self, command: &str) -> Option<String>;
+}
+
+/// A version of a command as cached, with the modification time of
+/// the executable it was obtained from
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+struct CachedVersion {
+    modified: u64,
+    version: Option<String>,
+}
+
+/// Runs `<command> --version` for the commands in
+/// [`VERSION_PROBED_COMMANDS`], with a timeout, and caches the versions
+/// until the executables change
+pub struct CommandVersionProber {
+    timeout: Duration,
+    cache_path: Option<PathBuf>,
+    cache: RefCell<BTreeMap<String, CachedVersion>>,
+    /// The directories the commands are looked up in, instead of `PATH`
+    search_path: Option<OsString>,
+}
+
+impl CommandVersionProber {
+    pub fn new(timeout: Duration, cache_path: Option<PathBuf>) -> Self {
+        let cache: BTreeMap<String, CachedVersion> = cache_path
+            .as_ref()
+            .and_then(|path| std::fs::read(path).ok())
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default();
+
+        Self { timeout, cache_path, cache: RefCell::new(cache), search_path: None }
+    }
+
+    /// Look the commands up in these directories instead of `PATH`
+    pub fn with_search_path(mut self, search_path: OsString) -> Self {
+        self.search_path = Some(search_path);
+        self
+    }
+
+    /// Cache the versions in the cache directory of cchain
+    pub fn from_storage(storage: &Storage) -> Self {
+        Self::new(VERSION_PROBE_TIMEOUT, Some(storage.get_directory(StorageCategory::Cache).join(VERSION_CACHE_FILE_NAME)))
+    }
+
+    /// Write the versions probed, if they are cached, together with those
+    /// other processes cached in the meantime. The cache is only a
+    /// shortcut, so it failing to save is ignored.
+    pub fn save_cache(&self) {
+        let Some(cache_path) = &self.cache_path else {
+            return;
+        };
+        let Ok(_lock) = FileLock::acquire(cache_path) else {
+            return;
+        };
+        let saved_cache: BTreeMap<String, CachedVersion> = std::fs::read(cache_path)
+            .ok()
+            .and_then(|content| serde_json::from_slice(&content).ok())
+            .unwrap_or_default();
+        let mut cache = self.cache.borrow_mut();
+        for (key, cached) in saved_cache {
+            cache.entry(key).or_insert(cached);
+        }
+        if let Ok(content) = serde_json::to_string(&*cache) {
+            let _ = write_file_atomically(cache_path, content.as_bytes());
+        }
+    }
+}
+
+impl VersionProber for CommandVersionProber {
+    fn probe_version(&self, command: &str) -> Option<String> {
+        if !is_version_probed(command) {
+            return None;
+        }
+        let search_path: Option<OsString> = self.search_path.clone().or_else(|| std::env::var_os("PATH"));
+        let path: PathBuf = which::which_in(command, search_path, std::env::current_dir().ok()?).ok()?;
+        let modified: u64 = std::fs::metadata(&path)
+            .and_then(|metadata| metadata.modified())
+            .ok()
+            .and_then(|modified| modified.duration_since(UNIX_EPOCH).ok())
+            .map_or(0, |duration| duration.as_secs());
+        let key: String = path.to_string_lossy().to_string();
+        if let Some(cached) = self.cache.borrow().get(&key) {
+            if cached.modified == modified {
+                return cached.version.clone();
+            }
+        }
+
+        // A command that does not answer in time is cached without a
+        // version, so that it does not hold up the next runs either
+        let version: Option<String> = run_version_command(&path, self.timeout);
+        self.cache.borrow_mut().insert(key, CachedVersion { modified, version: version.clone() });
+
+        version
+    }
+}
+
+/// Run `<path> --version`, and take the first line it prints to stdout,
+/// or else to stderr. The process is killed after the timeout.
+pub fn run_version_command(path: &Path, timeout: Duration) -> Option<String> {
+    let mut child = Command::new(path)
+        .arg("--version")
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    let started_at: Instant = Instant::now();
+    loop {
+        match child.try_wait() {
+            Ok(Some(_)) => break,
+            Ok(None) if started_at.elapsed() < timeout => std::thread::sleep(Duration::from_millis(10)),
+            _ => {
+                let _ = child.kill();
+                let _ = child.wait();
+                return None;
+            }
+        }
+    }
+
+    let mut output: String = String::new();
+    child.stdout.take()?.read_to_string(&mut output).ok()?;
+    if output.trim().is_empty() {
+        child.stderr.take()?.read_to_string(&mut output).ok()?;
+    }
+
+    output
+        .lines()
+        .map(str::trim)
+        .find(|line| !line.is_empty())
+        .map(|line| line.to_string())
+}
+
+/// Get the salt of the environment digests of this installation, and
+/// create it the first time. The salt is random and never leaves the data
+/// directory.
+pub fn get_fingerprint_salt(storage: &Storage) -> Option<String> {
+    let path: PathBuf = storage.get_data_directory().join(FINGERPRINT_SALT_FILE_NAME);
+    if let Ok(salt) = std::fs::read_to_string(&path) {
+        return Some(salt.trim().to_string()).filter(|salt| !salt.is_empty());
+    }
+
+    // The hashers of the standard library are keyed from the OS
+    let salt: String = (0..4).map(|_| format!("{:016x}", RandomState::new().build_hasher().finish())).collect();
+    std::fs::create_dir_all(storage.get_data_directory()).ok()?;
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+
+        options.mode(0o600);
+    }
+    match options.open(&path) {
+        Ok(mut file) => {
+            file.write_all(salt.as_bytes()).ok()?;
+            Some(salt)
+        }
+        // Another run created it first
+        Err(error) if error.kind() == std::io::ErrorKind::AlreadyExists => {
+            std::fs::read_to_string(&path).ok().map(|salt| salt.trim().to_string())
+        }
+        Err(_) => None,
+    }
+}
@@ -0,0 +1,247 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{anyhow, Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::commons::{lock::{write_file_atomically, FileLock}, storage::Storage};
+
+use super::usage::get_usage_key;
+
+/// Name of the file of the values entered at prompts in the data directory
+const VALUE_HISTORY_FILE_NAME: &str = "value_history.json";
+
+/// Distinct values kept for each variable, offered as quick picks
+pub const VALUE_HISTORY_LENGTH: usize = 5;
+
+/// Version of the value history format written by this version of
+/// cchain. Version 2 adds the times the values were used in `used_at`,
+/// next to the plain values of `chains`, so that earlier versions still
+/// read the values. Files without a version have no times.
+pub const VALUE_HISTORY_FORMAT_VERSION: u32 = 2;
+
+/// The times values were used, by chain, variable and value
+type UsedAtTimes = BTreeMap<String, BTreeMap<String, BTreeMap<String, u64>>>;
+
+/// A value kept for a variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueEntry {
+    pub value: String,
+    /// When the value was last entered or set, in seconds since the Unix
+    /// epoch. Values kept before the times were recorded have none.
+    pub used_at: Option<u64>,
+}
+
+/// A change to the history since loading, which is applied again to the
+/// file as it is when saving
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum ValueHistoryChange {
+    Record { chain_key: String, variable_name: String, entry: ValueEntry },
+    Unset { chain_key: String, variable_name: String },
+    Clear { chain_key: String },
+}
+
+/// The values recently entered at the prompts of each chain, most recent
+/// first, keyed by the canonical path of the chain and the variable name.
+/// Values of secrets are never kept.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ValueHistory {
+    #[serde(skip)]
+    path: PathBuf,
+    #[serde(default)]
+    format_version: u32,
+    #[serde(default)]
+    chains: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    used_at: UsedAtTimes,
+    #[serde(skip)]
+    changes: Vec<ValueHistoryChange>,
+}
+
+impl ValueHistory {
+    /// Read the history at the path. A missing or unreadable file has
+    /// no values, as the history is only a convenience, and neither has a
+    /// file written by a newer version of cchain. Files in the format
+    /// without times are read as they are, and written in the current
+    /// format on saving.
+    pub fn load(path: PathBuf) -> Self {
+        let mut value_history: ValueHistory = Self::read(&path).ok().flatten().unwrap_or_default();
+        value_history.path = path;
+
+        value_history
+    }
+
+    /// Read the history at the path, if it is there and readable
+    ///
+    /// # Errors
+    ///
+    /// The file is in a format newer than `VALUE_HISTORY_FORMAT_VERSION`
+    fn read(path: &Path) -> Result<Option<Self>, Error> {
+        let value_history: Option<ValueHistory> = std::fs::read_to_string(path)
+            .ok()
+            .and_then(|content| serde_json::from_str(&content).ok());
+        if let Some(value_history) = &value_history {
+            if value_history.format_version > VALUE_HISTORY_FORMAT_VERSION {
+                return Err(anyhow!(
+                    "{} is in format version {}, which this version of cchain does not support. The latest is {}.",
+                    path.display(),
+                    value_history.format_version,
+                    VALUE_HISTORY_FORMAT_VERSION
+                ));
+            }
+        }
+
+        Ok(value_history)
+    }
+
+    pub fn from_storage(storage: &Storage) -> Self {
+        Self::load(storage.get_data_directory().join(VALUE_HISTORY_FILE_NAME))
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Get the version of the format the file was read in
+    pub fn get_format_version(&self) -> u32 {
+        self.format_version
+    }
+
+    /// Get the values kept for a variable of a chain with the times they
+    /// were used, most recent first
+    pub fn get_entries(&self, chain_path: &str, variable_name: &str) -> Vec<ValueEntry> {
+        let chain_key: String = get_usage_key(chain_path);
+        let used_at: Option<&BTreeMap<String, u64>> =
+            self.used_at.get(&chain_key).and_then(|variables| variables.get(variable_name));
+        self.get_values(chain_path, variable_name)
+            .into_iter()
+            .map(|value| ValueEntry {
+                used_at: used_at.and_then(|times| times.get(&value)).copied(),
+                value,
+            })
+            .collect()
+    }
+
+    /// Get the values entered for a variable of a chain, most recent first
+    pub fn get_values(&self, chain_path: &str, variable_name: &str) -> Vec<String> {
+        self.chains
+            .get(&get_usage_key(chain_path))
+            .and_then(|variables| variables.get(variable_name))
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Move a value to the front of the values of a variable, keeping
+    /// `VALUE_HISTORY_LENGTH` distinct values
+    pub fn record(&mut self, chain_path: &str, variable_name: &str, value: &str) {
+        let used_at: Option<u64> = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|duration| duration.as_secs());
+        self.apply(ValueHistoryChange::Record {
+            chain_key: get_usage_key(chain_path),
+            variable_name: variable_name.to_string(),
+            entry: ValueEntry { value: value.to_string(), used_at },
+        });
+    }
+
+    /// Forget the values of a variable of a chain
+    ///
+    /// # Returns
+    ///
+    /// Whether the variable had values
+    pub fn unset(&mut self, chain_path: &str, variable_name: &str) -> bool {
+        let has_values: bool = !self.get_values(chain_path, variable_name).is_empty();
+        self.apply(ValueHistoryChange::Unset {
+            chain_key: get_usage_key(chain_path),
+            variable_name: variable_name.to_string(),
+        });
+
+        has_values
+    }
+
+    /// Forget the values of all variables of a chain
+    ///
+    /// # Returns
+    ///
+    /// The number of variables that had values
+    pub fn clear(&mut self, chain_path: &str) -> usize {
+        let chain_key: String = get_usage_key(chain_path);
+        let variables: usize = self.chains.get(&chain_key).map_or(0, |variables| variables.len());
+        self.apply(ValueHistoryChange::Clear { chain_key });
+
+        variables
+    }
+
+    fn apply(&mut self, change: ValueHistoryChange) {
+        apply_change(&mut self.chains, &mut self.used_at, change.clone());
+        self.changes.push(change);
+    }
+
+    /// Apply the changes since loading to the file under its lock, so
+    /// that the values other processes recorded in the meantime are kept
+    ///
+    /// # Errors
+    ///
+    /// The file was written by a newer version of cchain, which is left
+    /// as it is
+    pub fn save(&mut self) -> Result<(), Error> {
+        let _lock: FileLock = FileLock::acquire(&self.path)?;
+        let stored: ValueHistory = Self::read(&self.path)?.unwrap_or_default();
+        let (mut chains, mut used_at) = (stored.chains, stored.used_at);
+        for change in std::mem::take(&mut self.changes) {
+            apply_change(&mut chains, &mut used_at, change);
+        }
+        self.chains = chains;
+        self.used_at = used_at;
+        self.format_version = VALUE_HISTORY_FORMAT_VERSION;
+        write_file_atomically(&self.path, serde_json::to_string_pretty(self)?.as_bytes())?;
+
+        Ok(())
+    }
+}
+
+fn apply_change(
+    chains: &mut BTreeMap<String, BTreeMap<String, Vec<String>>>,
+    used_at: &mut UsedAtTimes,
+    change: ValueHistoryChange,
+) {
+    match change {
+        ValueHistoryChange::Record { chain_key, variable_name, entry } => {
+            // Keep `VALUE_HISTORY_LENGTH` distinct values, the recorded
+            // one first, and the times of those kept
+            let values: &mut Vec<String> =
+                chains.entry(chain_key.clone()).or_default().entry(variable_name.clone()).or_default();
+            values.retain(|value| *value != entry.value);
+            values.insert(0, entry.value.clone());
+            values.truncate(VALUE_HISTORY_LENGTH);
+
+            let times: &mut BTreeMap<String, u64> = used_at.entry(chain_key).or_default().entry(variable_name).or_default();
+            if let Some(time) = entry.used_at {
+                times.insert(entry.value, time);
+            }
+            times.retain(|value, _| values.contains(value));
+        }
+        ValueHistoryChange::Unset { chain_key, variable_name } => {
+            if let Some(variables) = chains.get_mut(&chain_key) {
+                variables.remove(&variable_name);
+                if variables.is_empty() {
+                    chains.remove(&chain_key);
+                }
+            }
+            if let Some(variables) = used_at.get_mut(&chain_key) {
+                variables.remove(&variable_name);
+                if variables.is_empty() {
+                    used_at.remove(&chain_key);
+                }
+            }
+        }
+        ValueHistoryChange::Clear { chain_key } => {
+            chains.remove(&chain_key);
+            used_at.remove(&chain_key);
+        }
+    }
+}
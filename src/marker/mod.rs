@@ -1,2 +1,8 @@
+pub mod audit;
 pub mod bookmark;
+pub mod environment_fingerprint;
+pub mod history;
+pub mod presets;
 pub mod reference;
+pub mod run_history;
+pub mod usage;
@@ -0,0 +1,93 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+};
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{commons::{lock::{write_file_atomically, FileLock}, storage::Storage}, variable::normalize_variable_name};
+
+/// Name of the file of the variable presets in the data directory
+const VARIABLE_PRESETS_FILE_NAME: &str = "variable_presets.json";
+
+/// Values of variables shared by all chains, e.g. a registry URL used by
+/// many of them, managed with `cchain preset`. A preset is the default
+/// answer at the prompts of the variable, unless the chain has another.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VariablePresets {
+    #[serde(skip)]
+    path: PathBuf,
+    /// Values keyed by the normalized variable names
+    #[serde(default)]
+    values: BTreeMap<String, String>,
+    /// Values set, or removed as `None`, since loading, which are applied
+    /// to the file as it is when saving
+    #[serde(skip)]
+    changes: BTreeMap<String, Option<String>>,
+}
+
+impl VariablePresets {
+    /// Read the presets at the path. A missing file has no presets.
+    pub fn load(path: PathBuf) -> Result<Self, Error> {
+        let mut variable_presets: VariablePresets = match std::fs::read_to_string(&path) {
+            Ok(content) => serde_json::from_str(&content)?,
+            Err(error) if error.kind() == std::io::ErrorKind::NotFound => Self::default(),
+            Err(error) => return Err(error.into()),
+        };
+        variable_presets.path = path;
+
+        Ok(variable_presets)
+    }
+
+    pub fn from_storage(storage: &Storage) -> Result<Self, Error> {
+        Self::load(storage.get_data_directory().join(VARIABLE_PRESETS_FILE_NAME))
+    }
+
+    pub fn get_path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_value(&self, variable_name: &str) -> Option<&str> {
+        self.values.get(&normalize_variable_name(variable_name)).map(|value| value.as_str())
+    }
+
+    /// Get the presets by the normalized variable names, in order
+    pub fn get_values(&self) -> &BTreeMap<String, String> {
+        &self.values
+    }
+
+    pub fn set_value(&mut self, variable_name: &str, value: String) {
+        let variable_name: String = normalize_variable_name(variable_name);
+        self.changes.insert(variable_name.clone(), Some(value.clone()));
+        self.values.insert(variable_name, value);
+    }
+
+    /// Remove the preset of a variable
+    ///
+    /// # Returns
+    ///
+    /// Whether the variable had a preset
+    pub fn unset_value(&mut self, variable_name: &str) -> bool {
+        let variable_name: String = normalize_variable_name(variable_name);
+        self.changes.insert(variable_name.clone(), None);
+        self.values.remove(&variable_name).is_some()
+    }
+
+    /// Apply the changes since loading to the file under its lock, so
+    /// that the presets other processes set in the meantime are kept
+    pub fn save(&mut self) -> Result<(), Error> {
+        let _lock: FileLock = FileLock::acquire(&self.path)?;
+        let mut values: BTreeMap<String, String> = Self::load(self.path.clone())?.values;
+        for (variable_name, value) in std::mem::take(&mut self.changes) {
+            match value {
+                Some(value) => values.insert(variable_name, value),
+                None => values.remove(&variable_name),
+            };
+        }
+        self.values = values;
+        write_file_atomically(&self.path, serde_json::to_string_pretty(self)?.as_bytes())?;
+
+        Ok(())
+    }
+}
@@ -3,54 +3,153 @@ use std::{collections::HashSet, path::Path, str::FromStr};
 use anyhow::{anyhow, Error, Result};
 use serde::{Deserialize, Serialize};
 
-use crate::{commons::{naming::HumanReadable, packages::{AvailablePackages, Package}}, core::chain::Chain};
+use crate::{commons::{digest::sha256_hex, naming::HumanReadable, packages::{AvailablePackages, Package}}, core::{chain::Chain, metadata::ChainFile}};
 
 /// Provide methods to track the path of a data structure
 pub trait TrackPath {
     fn get_path(&self) -> &str;
 }
 
+/// The file name pattern of chains, which gates directory scanning and bookmarking
+pub const CHAIN_FILE_NAME_PATTERN: &str = "cchain_*.json";
+
+/// Check whether the file name of a path follows `CHAIN_FILE_NAME_PATTERN`
+pub fn follows_naming_convention(path: &Path) -> bool {
+    path.extension().is_some_and(|extension| extension == "json")
+        && path
+            .file_name()
+            .is_some_and(|file_name| file_name.to_string_lossy().starts_with("cchain_"))
+}
+
 /// `Bookmark` is a collection of references to the chains
 /// `ChainRefenence` is a reference to a chain
 #[derive(Debug, Serialize, Deserialize, PartialEq, Eq)]
 pub struct ChainReference {
+    /// Stable ID of the chain in the bookmark, e.g. `deploy-a3f2`.
+    /// Unlike positional indices, it does not change when other
+    /// chains are removed
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    id: String,
     /// Path to the chain
     chain_path: String,
+    /// Tags declared in the metadata of the chain, cached so that
+    /// listing does not need to parse every chain
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    /// Hash of the chain file when the cached data was collected
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    content_hash: Option<String>,
+    /// The chain is bookmarked with `--allow-any-name`, although its
+    /// file name does not follow `CHAIN_FILE_NAME_PATTERN`
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    allow_any_name: bool,
+    /// Arguments that `cchain run` always gets for the chain, e.g.
+    /// `--continue-on-failure --var env=prod`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    run_arguments: Option<String>,
 }
 
 impl ChainReference {
     pub fn new(path: String) -> Self {
-        Self { chain_path: path }
+        Self {
+            id: String::new(),
+            chain_path: path,
+            tags: Vec::new(),
+            content_hash: None,
+            allow_any_name: false,
+            run_arguments: None,
+        }
+    }
+
+    /// Create a reference to the chain file at the path. Unless
+    /// `allow_any_name` is set, the file name needs to follow
+    /// `CHAIN_FILE_NAME_PATTERN`.
+    pub fn from_path(s: &str, allow_any_name: bool) -> Result<Self, Error> {
+        let path = Path::new(s);
+        if !path.exists() {
+            return Err(anyhow!("Chain at {} does not exist", s));
+        }
+        if !path.is_file() {
+            return Err(anyhow!("Chain at {} is not a file", s));
+        }
+
+        if !allow_any_name && !follows_naming_convention(path) {
+            return Err(anyhow!(
+                "Chain at {} is named `{}`, but chains are expected to be named `{}`. Use `--allow-any-name` to bookmark it anyway",
+                s,
+                path.file_name().unwrap_or_default().to_string_lossy(),
+                CHAIN_FILE_NAME_PATTERN
+            ));
+        }
+
+        let mut chain_reference = Self::new(s.to_string());
+        chain_reference.allow_any_name = allow_any_name && !follows_naming_convention(path);
+
+        Ok(chain_reference)
+    }
+
+    pub fn is_any_name_allowed(&self) -> bool {
+        self.allow_any_name
+    }
+
+    pub fn get_id(&self) -> &str {
+        &self.id
+    }
+
+    pub fn set_id(&mut self, id: String) {
+        self.id = id;
     }
 
     /// Return a canonicalized path of the chain
     pub fn get_chain_path_string(&self) -> String {
         self.chain_path.clone()
     }
+
+    pub fn set_chain_path(&mut self, path: String) {
+        self.chain_path = path;
+    }
+
+    pub fn get_run_arguments(&self) -> Option<&str> {
+        self.run_arguments.as_deref()
+    }
+
+    pub fn set_run_arguments(&mut self, run_arguments: Option<String>) {
+        self.run_arguments = run_arguments;
+    }
+
+    pub fn get_tags(&self) -> &Vec<String> {
+        &self.tags
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|item| item == tag)
+    }
+
+    /// Update the cached data when the chain file has changed since it
+    /// was last collected.
+    ///
+    /// # Returns
+    ///
+    /// Whether the cached data is updated
+    pub fn refresh(&mut self) -> Result<bool, Error> {
+        let content: String = std::fs::read_to_string(&self.chain_path)?;
+        let content_hash: String = sha256_hex(content.as_bytes());
+
+        if self.content_hash.as_ref() == Some(&content_hash) {
+            return Ok(false);
+        }
+
+        self.tags = ChainFile::from_path(Path::new(&self.chain_path))?.metadata.tags;
+        self.content_hash = Some(content_hash);
+
+        Ok(true)
+    }
 }
 
 impl FromStr for ChainReference {
     type Err = Error;
     fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
-        let path = Path::new(s);
-        if path.exists() {
-            if path.is_file()
-                && path.extension().map_or(false, |ext| ext == "json")
-                && path
-                    .file_name()
-                    .unwrap()
-                    .to_string_lossy()
-                    .starts_with("cchain_")
-            {
-                return Ok(Self {
-                    chain_path: s.to_string(),
-                });
-            } else {
-                return Err(anyhow!("Chain at {} has a wrong naming convention", s));
-            }
-        } else {
-            return Err(anyhow!("Chain at {} does not exist", s));
-        }
+        Self::from_path(s, false)
     }
 }
 
@@ -0,0 +1,373 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::LazyLock,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Error, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commons::{digest::sha256_hex, jsonl::JsonLinesFile, storage::Storage},
+    core::{
+        chain::{Chain, INLINE_CHAIN_NAME},
+        metadata::format_timestamp,
+        program::{AttemptRecord, ProgramExecutionResult},
+    },
+    display_control::{display_message, Level},
+};
+
+use super::{
+    environment_fingerprint::{
+        get_fingerprint_salt, is_environment_check_enabled, render_environment_notice, CommandVersionProber, EnvironmentFingerprint,
+    },
+    reference::TrackPath,
+    usage::{get_usage_key, is_tracking_usage},
+};
+
+/// Name of the file of the run history in the data directory
+const RUN_HISTORY_FILE_NAME: &str = "run_history.jsonl";
+
+/// Lines at the end of the output that the fingerprint of a failure covers
+const FINGERPRINT_TAIL_LINES: usize = 5;
+
+/// Parts of error texts that differ from run to run, and what they are
+/// replaced with before fingerprinting. Earlier patterns go first, so that
+/// a timestamp is not taken apart by the pattern of numbers.
+static VOLATILE_PATTERNS: LazyLock<Vec<(Regex, &'static str)>> = LazyLock::new(|| {
+    [
+        (r"\d{4}-\d{2}-\d{2}[T ]\d{2}:\d{2}(:\d{2}(\.\d+)?)?(Z|[+-]\d{2}:?\d{2}| ?UTC)?", "<time>"),
+        (r"\b\d{1,2}:\d{2}:\d{2}(\.\d+)?\b", "<time>"),
+        (r"(/private)?/var/folders/\S*|/tmp/\S*|[A-Za-z]:\\\S*\\Temp\\\S*", "<temp>"),
+        (r"(?i)\b[0-9a-f]{8}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{4}-[0-9a-f]{12}\b", "<id>"),
+        (r"(?i)\b(0x[0-9a-f]+|[0-9a-f]{7,})\b", "<hex>"),
+        (r"\b\d+(\.\d+)?\s?(ns|µs|us|ms|s|sec|secs|seconds|m|min|minutes)\b", "<duration>"),
+        (r"\b\d{4,}\b", "<number>"),
+    ]
+    .into_iter()
+    .map(|(pattern, replacement)| (Regex::new(pattern).unwrap(), replacement))
+    .collect()
+});
+
+/// How a program failed, tolerant to the timestamps, temporary paths and
+/// identifiers that differ between runs failing the same way
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct FailureFingerprint {
+    pub program_index: usize,
+    pub command: String,
+    /// The exit status, the signal, or the error of the last attempt
+    pub classification: String,
+    /// The digest of the command, the classification and the normalized
+    /// end of the output
+    pub fingerprint: String,
+    /// The last line of the normalized output, for listings
+    #[serde(default)]
+    pub summary: String,
+}
+
+impl FailureFingerprint {
+    /// Fingerprint the last attempt of a failed program
+    pub fn from_attempt(program_index: usize, command: &str, record: &AttemptRecord) -> Self {
+        let classification: String = match &record.termination {
+            Some(termination) => termination.to_string(),
+            None => normalize_volatile_text(&record.status),
+        };
+        let lines: Vec<String> = record
+            .output_tail
+            .lines()
+            .map(|line| normalize_volatile_text(line).split_whitespace().collect::<Vec<&str>>().join(" "))
+            .filter(|line| !line.is_empty())
+            .collect();
+        let tail: &[String] = &lines[lines.len().saturating_sub(FINGERPRINT_TAIL_LINES)..];
+        let digest: String = sha256_hex(format!("{}\0{}\0{}", command, classification, tail.join("\n")).as_bytes());
+
+        Self {
+            program_index,
+            command: command.to_string(),
+            classification,
+            fingerprint: digest[..16].to_string(),
+            summary: tail.last().cloned().unwrap_or_default(),
+        }
+    }
+}
+
+/// Replace the parts of a text that change between runs, e.g. times,
+/// temporary paths and identifiers, with placeholders
+pub fn normalize_volatile_text(text: &str) -> String {
+    let mut text: String = text.to_string();
+    for (pattern, replacement) in VOLATILE_PATTERNS.iter() {
+        text = pattern.replace_all(&text, *replacement).into_owned();
+    }
+
+    text
+}
+
+/// A run of a chain and how its failed programs failed
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct RunHistoryRecord {
+    /// The canonical path of the chain file
+    pub chain: String,
+    /// Seconds since the Unix epoch
+    pub timestamp: u64,
+    pub succeeded: bool,
+    #[serde(default)]
+    pub failures: Vec<FailureFingerprint>,
+    /// What a successful run ran with, to compare the next runs with
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub environment: Option<EnvironmentFingerprint>,
+}
+
+/// The earlier runs that failed with the same fingerprint
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PriorFailures {
+    pub count: usize,
+    /// When the failure was first seen, in seconds since the Unix epoch
+    pub first_seen: u64,
+    /// Whether a run of the chain succeeded since the failure was first
+    /// seen, as opposed to failing every time
+    pub is_flaky: bool,
+}
+
+impl PriorFailures {
+    /// Describe the earlier failures for the summary of a failed run
+    pub fn get_note(&self, program_index: usize) -> String {
+        format!(
+            "Program #{} failed the same way in {} earlier run(s), first seen {}. {}",
+            program_index,
+            self.count,
+            format_timestamp(self.first_seen),
+            if self.is_flaky {
+                "It is flaky: runs in between succeeded."
+            } else {
+                "It is persistent: no run succeeded since."
+            }
+        )
+    }
+}
+
+/// Find the earlier runs of the chain that failed with the fingerprint
+pub fn find_prior_failures(records: &[RunHistoryRecord], chain: &str, fingerprint: &str) -> Option<PriorFailures> {
+    let runs: Vec<&RunHistoryRecord> = records.iter().filter(|record| record.chain == chain).collect();
+    let first_index: usize = runs
+        .iter()
+        .position(|record| record.failures.iter().any(|failure| failure.fingerprint == fingerprint))?;
+    let count: usize = runs[first_index..]
+        .iter()
+        .filter(|record| record.failures.iter().any(|failure| failure.fingerprint == fingerprint))
+        .count();
+
+    Some(PriorFailures {
+        count,
+        first_seen: runs[first_index].timestamp,
+        is_flaky: runs[first_index..].iter().any(|record| record.succeeded),
+    })
+}
+
+/// A failure seen in more than one run, as listed by `cchain stats --failures`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecurringFailure {
+    pub chain: String,
+    pub program_index: usize,
+    pub command: String,
+    pub classification: String,
+    pub summary: String,
+    pub fingerprint: String,
+    pub count: usize,
+    pub first_seen: u64,
+    pub last_seen: u64,
+    pub is_flaky: bool,
+}
+
+/// Get the failures seen in more than one run, the most frequent first
+pub fn get_recurring_failures(records: &[RunHistoryRecord]) -> Vec<RecurringFailure> {
+    let mut failures: BTreeMap<(String, String), RecurringFailure> = BTreeMap::new();
+    for record in records {
+        for failure in &record.failures {
+            let key: (String, String) = (record.chain.clone(), failure.fingerprint.clone());
+            let recurring_failure = failures.entry(key).or_insert_with(|| RecurringFailure {
+                chain: record.chain.clone(),
+                program_index: failure.program_index,
+                command: failure.command.clone(),
+                classification: failure.classification.clone(),
+                summary: failure.summary.clone(),
+                fingerprint: failure.fingerprint.clone(),
+                count: 0,
+                first_seen: record.timestamp,
+                last_seen: record.timestamp,
+                is_flaky: false,
+            });
+            recurring_failure.count += 1;
+            recurring_failure.last_seen = record.timestamp;
+        }
+    }
+
+    let mut failures: Vec<RecurringFailure> = failures.into_values().filter(|failure| failure.count > 1).collect();
+    for failure in &mut failures {
+        failure.is_flaky = find_prior_failures(records, &failure.chain, &failure.fingerprint)
+            .is_some_and(|prior_failures| prior_failures.is_flaky);
+    }
+    failures.sort_by(|a, b| b.count.cmp(&a.count).then(b.last_seen.cmp(&a.last_seen)));
+
+    failures
+}
+
+/// The file of the runs of the chains and their failures. Each run appends
+/// a line, so that concurrent runs do not overwrite each other, and
+/// `cchain gc` drops the runs older than the log retention.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RunHistory {
+    file: JsonLinesFile,
+}
+
+impl RunHistory {
+    pub fn new(path: PathBuf) -> Self {
+        Self { file: JsonLinesFile::new(path) }
+    }
+
+    pub fn from_storage(storage: &Storage) -> Self {
+        Self::new(storage.get_data_directory().join(RUN_HISTORY_FILE_NAME))
+    }
+
+    pub fn get_path(&self) -> &Path {
+        self.file.get_path()
+    }
+
+    /// Append the record of a run
+    pub fn record_run(&self, record: &RunHistoryRecord) -> Result<(), Error> {
+        self.file.append(record)
+    }
+
+    /// Read the records, oldest first. Lines that cannot be read are
+    /// skipped, and a missing file has no records.
+    pub fn read(&self) -> Vec<RunHistoryRecord> {
+        self.file.read().0
+    }
+
+    /// Drop the runs recorded before the timestamp, except the last
+    /// successful run of each chain that recorded its environment, which
+    /// the next runs are compared with
+    ///
+    /// # Returns
+    ///
+    /// The number of runs dropped
+    pub fn compact(&self, oldest_timestamp: u64) -> Result<usize, Error> {
+        let kept_environments: Vec<RunHistoryRecord> = get_last_environment_records(&self.read());
+        let dropped: Vec<RunHistoryRecord> = self.file.retain(|record: &RunHistoryRecord| {
+            record.timestamp >= oldest_timestamp || kept_environments.contains(record)
+        })?;
+
+        Ok(dropped.len())
+    }
+
+    /// Count the runs that `compact` would drop
+    pub fn count_expired(&self, oldest_timestamp: u64) -> usize {
+        let records: Vec<RunHistoryRecord> = self.read();
+        let kept_environments: Vec<RunHistoryRecord> = get_last_environment_records(&records);
+        records
+            .iter()
+            .filter(|record| record.timestamp < oldest_timestamp && !kept_environments.contains(record))
+            .count()
+    }
+}
+
+/// Get the last successful run of each chain that recorded its environment
+fn get_last_environment_records(records: &[RunHistoryRecord]) -> Vec<RunHistoryRecord> {
+    let mut last_records: BTreeMap<&str, &RunHistoryRecord> = BTreeMap::new();
+    for record in records.iter().filter(|record| record.succeeded && record.environment.is_some()) {
+        last_records.insert(&record.chain, record);
+    }
+
+    last_records.into_values().cloned().collect()
+}
+
+/// Get the environment fingerprint of the last successful run of the
+/// chain that recorded one
+pub fn find_last_environment<'a>(records: &'a [RunHistoryRecord], chain: &str) -> Option<&'a EnvironmentFingerprint> {
+    records
+        .iter()
+        .rev()
+        .filter(|record| record.chain == chain && record.succeeded)
+        .find_map(|record| record.environment.as_ref())
+}
+
+/// Take the environment fingerprint of the chain before it runs, when
+/// tracking is enabled and `--no-env-check` is not given, and note how it
+/// differs from the last successful run. Probing never stops a chain
+/// from running.
+///
+/// # Returns
+///
+/// The fingerprint to record if the run succeeds
+pub fn check_chain_environment(chain: &Chain) -> Option<EnvironmentFingerprint> {
+    if !is_tracking_usage() || !is_environment_check_enabled() || chain.get_path() == INLINE_CHAIN_NAME {
+        return None;
+    }
+    let storage: Storage = Storage::from_environment().ok()?;
+    let salt: String = get_fingerprint_salt(&storage)?;
+    let prober = CommandVersionProber::from_storage(&storage);
+    let environment = EnvironmentFingerprint::from_chain(chain, &prober, &salt);
+    prober.save_cache();
+
+    let records: Vec<RunHistoryRecord> = RunHistory::from_storage(&storage).read();
+    if let Some(previous) = find_last_environment(&records, &get_usage_key(chain.get_path())) {
+        if let Some(notice) = render_environment_notice(previous, &environment) {
+            display_message(Level::Warn, &notice);
+        }
+    }
+
+    Some(environment)
+}
+
+/// Record a run of the chain in the run history when tracking is enabled,
+/// and note the failures that were seen in earlier runs. Recording never
+/// stops a chain from finishing. The environment fingerprint is only
+/// kept for successful runs.
+pub fn record_chain_failures(chain: &Chain, succeeded: bool, environment: Option<EnvironmentFingerprint>) {
+    // Inline chains have no file to keep the history of
+    if !is_tracking_usage() || chain.get_path() == INLINE_CHAIN_NAME {
+        return;
+    }
+    let run_history: RunHistory = match Storage::from_environment() {
+        Ok(storage) => RunHistory::from_storage(&storage),
+        Err(error) => {
+            display_message(Level::Warn, &format!("Cannot record the run of {}: {}", chain.get_path(), error));
+            return;
+        }
+    };
+
+    let chain_key: String = get_usage_key(chain.get_path());
+    let program_results: Vec<ProgramExecutionResult> = chain.get_program_results();
+    let failures: Vec<FailureFingerprint> = chain
+        .get_failed_program_indices()
+        .into_iter()
+        .filter_map(|index| {
+            let result: &ProgramExecutionResult =
+                program_results.iter().find(|result| result.program_index() == Some(index))?;
+            // The output is kept in the run history, so the secrets in it
+            // are masked
+            let mut record: AttemptRecord = result.get_attempts().last()?.clone();
+            record.output_tail = chain.mask_secret_values(&record.output_tail);
+            record.status = chain.mask_secret_values(&record.status);
+            // The program as written, so that the values of its variables
+            // do not tell apart the runs failing the same way
+            Some(FailureFingerprint::from_attempt(index, chain.get_program_template(index)?, &record))
+        })
+        .collect();
+    if !failures.is_empty() {
+        let records: Vec<RunHistoryRecord> = run_history.read();
+        for failure in &failures {
+            if let Some(prior_failures) = find_prior_failures(&records, &chain_key, &failure.fingerprint) {
+                display_message(Level::Warn, &prior_failures.get_note(failure.program_index));
+            }
+        }
+    }
+
+    let timestamp: u64 = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    let environment: Option<EnvironmentFingerprint> = environment.filter(|_| succeeded);
+    let record = RunHistoryRecord { chain: chain_key, timestamp, succeeded, failures, environment };
+    if let Err(error) = run_history.record_run(&record) {
+        display_message(Level::Warn, &format!("Cannot record the run of {}: {}", chain.get_path(), error));
+    }
+}
@@ -0,0 +1,150 @@
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Error, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    commons::{jsonl::JsonLinesFile, lock::FileLock, storage::Storage},
+    display_control::{display_message, Level},
+};
+
+/// Enables counting the runs of chains. Nothing is counted without it.
+pub const TRACK_USAGE_VARIABLE: &str = "CCHAIN_TRACK_USAGE";
+
+/// Name of the file of the run counters in the data directory
+const USAGE_FILE_NAME: &str = "usage.jsonl";
+
+/// How often a chain was run, and when it was run last. This is only
+/// kept on this machine, and never sent anywhere.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct ChainUsage {
+    /// The canonical path of the chain file
+    pub path: String,
+    pub runs: u64,
+    /// Seconds since the Unix epoch
+    pub last_run: u64,
+}
+
+/// The usage of the chains read from the usage file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct UsageSummary {
+    /// The usage of each chain, keyed by the canonical path
+    pub chains: BTreeMap<String, ChainUsage>,
+    /// Lines that could not be read, e.g. written halfway
+    pub skipped_lines: usize,
+}
+
+impl UsageSummary {
+    pub fn get_usage(&self, chain_path: &str) -> Option<&ChainUsage> {
+        self.chains.get(&get_usage_key(chain_path))
+    }
+}
+
+/// The file of the run counters. Each run appends a line, so that
+/// concurrent runs do not overwrite each other, and `cchain gc`
+/// compacts the lines into one per chain.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UsageLog {
+    file: JsonLinesFile,
+}
+
+impl UsageLog {
+    pub fn new(path: PathBuf) -> Self {
+        Self { file: JsonLinesFile::new(path) }
+    }
+
+    pub fn from_storage(storage: &Storage) -> Self {
+        Self::new(storage.get_data_directory().join(USAGE_FILE_NAME))
+    }
+
+    pub fn get_path(&self) -> &Path {
+        self.file.get_path()
+    }
+
+    /// Count a run of the chain
+    pub fn record_run(&self, chain_path: &str, timestamp: u64) -> Result<(), Error> {
+        self.file.append(&ChainUsage { path: get_usage_key(chain_path), runs: 1, last_run: timestamp })
+    }
+
+    /// Add up the lines of the usage file. Lines that cannot be read are
+    /// skipped, and a missing file has no usage.
+    pub fn read(&self) -> UsageSummary {
+        let (lines, skipped_lines): (Vec<ChainUsage>, usize) = self.file.read();
+        let mut summary = UsageSummary { skipped_lines, ..Default::default() };
+        for usage in lines {
+            match summary.chains.get_mut(&usage.path) {
+                Some(total) => {
+                    total.runs += usage.runs;
+                    total.last_run = total.last_run.max(usage.last_run);
+                }
+                None => {
+                    summary.chains.insert(usage.path.clone(), usage);
+                }
+            }
+        }
+
+        summary
+    }
+
+    /// Rewrite the usage file with one line per chain, keeping only the
+    /// chains for which `keep` holds. Unreadable lines are dropped.
+    ///
+    /// # Returns
+    ///
+    /// The paths of the chains that were dropped
+    pub fn compact<F>(&self, keep: F) -> Result<Vec<String>, Error>
+    where
+        F: Fn(&str) -> bool,
+    {
+        let _lock: FileLock = self.file.lock()?;
+        let summary: UsageSummary = self.read();
+        if summary.chains.is_empty() && summary.skipped_lines == 0 {
+            return Ok(Vec::new());
+        }
+
+        let (kept, dropped): (Vec<ChainUsage>, Vec<ChainUsage>) =
+            summary.chains.into_values().partition(|usage| keep(&usage.path));
+        self.file.rewrite(&kept)?;
+
+        Ok(dropped.into_iter().map(|usage| usage.path).collect())
+    }
+}
+
+/// Get the key of a chain in the usage file, which is its canonical path
+pub fn get_usage_key(chain_path: &str) -> String {
+    std::fs::canonicalize(chain_path)
+        .map(|path| path.to_string_lossy().into_owned())
+        .unwrap_or_else(|_| chain_path.to_string())
+}
+
+/// Check whether a chain has not run within the last number of days
+pub fn is_stale(usage: Option<&ChainUsage>, days: u64, now: u64) -> bool {
+    match usage {
+        Some(usage) => usage.last_run < now.saturating_sub(days.saturating_mul(86400)),
+        None => true,
+    }
+}
+
+/// Check whether counting the runs is enabled with `CCHAIN_TRACK_USAGE`
+pub fn is_tracking_usage() -> bool {
+    std::env::var(TRACK_USAGE_VARIABLE).is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+/// Count a run of the chain when tracking is enabled. Counting never
+/// stops a chain from running.
+pub fn record_chain_run(chain_path: &str) {
+    if !is_tracking_usage() {
+        return;
+    }
+
+    let now: u64 = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_secs());
+    let result: Result<(), Error> =
+        Storage::from_environment().and_then(|storage| UsageLog::from_storage(&storage).record_run(chain_path, now));
+    if let Err(error) = result {
+        display_message(Level::Warn, &format!("Cannot count the run of {}: {}", chain_path, error));
+    }
+}
@@ -0,0 +1,103 @@
+use std::{
+    sync::{atomic::Ordering, Arc},
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    commons::interaction::SharedInteractor,
+    core::{
+        chain::Chain,
+        pause::{PauseControl, PauseKey},
+    },
+    dashboard::install_panic_hook,
+    display_control::{display_message, Level},
+    key_mode::{restore_key_mode, take_key_mode, KeyReaderState, SuspendableReader, SuspendingInteractor},
+};
+
+/// What the listener shares with the interactor of the chain
+struct ListenerShared {
+    pause_control: PauseControl,
+    key_reader_state: KeyReaderState,
+}
+
+impl SuspendableReader for ListenerShared {
+    fn suspend(&self) {
+        let mut is_suspended = self.key_reader_state.is_suspended.lock().unwrap();
+        restore_key_mode();
+        *is_suspended = true;
+    }
+
+    fn resume(&self) {
+        let mut is_suspended = self.key_reader_state.is_suspended.lock().unwrap();
+        take_key_mode();
+        *is_suspended = false;
+    }
+}
+
+impl ListenerShared {
+    /// Apply a pressed key, and tell the user what it does
+    fn press(&self, key: PauseKey) {
+        if !self.pause_control.press(key) {
+            return;
+        }
+        match key {
+            PauseKey::TogglePause if self.pause_control.is_paused() => {
+                display_message(Level::Logging, "Pausing after the current program...")
+            }
+            PauseKey::TogglePause => display_message(Level::Logging, "Resuming..."),
+            PauseKey::SkipNext | PauseKey::Abort => {}
+        }
+    }
+}
+
+/// Listens for the keys that pause a running chain, for `cchain run` at a
+/// terminal. `p` pauses after the current program or resumes, and while
+/// paused, `s` skips the next program and `a` aborts the chain.
+pub struct PauseKeyListener {
+    shared: Arc<ListenerShared>,
+    reader: Option<JoinHandle<()>>,
+}
+
+impl PauseKeyListener {
+    /// Read the keys, and pause the chain at them. Prompts of the chain
+    /// suspend the listener until they are answered.
+    pub fn start(chain: &mut Chain) -> Self {
+        let pause_control: PauseControl = PauseControl::new();
+        let shared: Arc<ListenerShared> =
+            Arc::new(ListenerShared { pause_control: pause_control.clone(), key_reader_state: KeyReaderState::new() });
+
+        chain.set_pause_control(Some(pause_control));
+        chain.set_interactor(SharedInteractor::new(SuspendingInteractor::new(shared.clone())));
+        install_panic_hook();
+        take_key_mode();
+
+        #[cfg(unix)]
+        let reader: Option<JoinHandle<()>> = {
+            let reader_shared: Arc<ListenerShared> = shared.clone();
+            Some(thread::spawn(move || read_keys(reader_shared)))
+        };
+        #[cfg(not(unix))]
+        let reader: Option<JoinHandle<()>> = None;
+
+        Self { shared, reader }
+    }
+
+    /// Stop reading the keys, and give the terminal back
+    pub fn finish(mut self) {
+        self.shared.key_reader_state.is_stopped.store(true, Ordering::SeqCst);
+        if let Some(reader) = self.reader.take() {
+            let _ = reader.join();
+        }
+        restore_key_mode();
+    }
+}
+
+/// Read the keys that pause the chain
+#[cfg(unix)]
+fn read_keys(shared: Arc<ListenerShared>) {
+    crate::key_mode::read_keys(&shared.key_reader_state, |keys| {
+        for key in keys.iter().filter_map(|key| PauseKey::from_key(*key)) {
+            shared.press(key);
+        }
+    });
+}
@@ -0,0 +1,201 @@
+use std::{
+    io::Write,
+    sync::{Mutex, MutexGuard},
+};
+
+use crate::{core::events::ChainEvent, dashboard::install_panic_hook};
+
+/// Pushes the title of the terminal on its stack, to be popped once cchain
+/// is done with it
+pub const SAVE_TITLE_SEQUENCE: &str = "\x1b[22;0t";
+
+/// Pops the title pushed with [`SAVE_TITLE_SEQUENCE`]
+pub const RESTORE_TITLE_SEQUENCE: &str = "\x1b[23;0t";
+
+/// Characters of the current program kept in the title, as terminals
+/// show little of a long one
+const TITLE_SUMMARY_LENGTH: usize = 60;
+
+/// The title of the terminal while `cchain run` follows a chain at it
+static TERMINAL_TITLE: Mutex<Option<TerminalTitle<Box<dyn Write + Send>>>> = Mutex::new(None);
+
+/// Where a run is, as shown in the title of the terminal and in the
+/// header of the dashboard
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunProgress<'a> {
+    Starting,
+    /// A program is running, by its index among `total` programs
+    Running { step: usize, total: usize, summary: &'a str },
+    Finished { succeeded: bool },
+}
+
+/// Render the title of a run, e.g. `cchain: deploy.json [step 3/12] cargo
+/// build`. The summary is expected to be masked already, as the labels of
+/// the events are. Control characters are replaced with spaces, so that
+/// nothing in a command line ends the escape sequence of the title.
+pub fn render_progress_title(chain_name: &str, progress: RunProgress) -> String {
+    let title: String = match progress {
+        RunProgress::Starting => format!("cchain: {}", chain_name),
+        RunProgress::Running { step, total, summary } => {
+            let summary: String = if summary.chars().count() > TITLE_SUMMARY_LENGTH {
+                let mut summary: String = summary.chars().take(TITLE_SUMMARY_LENGTH - 1).collect();
+                summary.push('…');
+                summary
+            } else {
+                summary.to_string()
+            };
+            format!("cchain: {} [step {}/{}] {}", chain_name, step + 1, total, summary)
+        }
+        RunProgress::Finished { succeeded: true } => format!("cchain: {} ✓", chain_name),
+        RunProgress::Finished { succeeded: false } => format!("cchain: {} ✗", chain_name),
+    };
+
+    title
+        .chars()
+        .map(|character| if character.is_control() { ' ' } else { character })
+        .collect()
+}
+
+/// Follows the events of a chain for its title. The title shows the
+/// program started last among those still running, or the one that ran
+/// last between two programs.
+#[derive(Debug, Clone, Default)]
+pub struct TitleTracker {
+    program_labels: Vec<String>,
+    running_steps: Vec<usize>,
+    last_step: Option<usize>,
+    outcome: Option<bool>,
+}
+
+impl TitleTracker {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Take an event of the chain
+    ///
+    /// # Returns
+    ///
+    /// Whether the progress changed
+    pub fn apply(&mut self, event: &ChainEvent) -> bool {
+        match event {
+            ChainEvent::ChainStarted { program_labels } => {
+                *self = Self { program_labels: program_labels.clone(), ..Self::default() };
+                true
+            }
+            ChainEvent::ProgramStarted { program_index } => {
+                // A retry starts the same program again
+                if self.running_steps.last() == Some(program_index) {
+                    return false;
+                }
+                self.running_steps.retain(|step| step != program_index);
+                self.running_steps.push(*program_index);
+                self.last_step = Some(*program_index);
+                true
+            }
+            ChainEvent::ProgramFinished { program_index, .. } => {
+                let previous_step: Option<usize> = self.get_current_step();
+                self.running_steps.retain(|step| step != program_index);
+                self.get_current_step() != previous_step
+            }
+            ChainEvent::ChainFinished { succeeded } => {
+                self.outcome = Some(*succeeded);
+                true
+            }
+            ChainEvent::RetryScheduled { .. }
+            | ChainEvent::ProgramOutput { .. }
+            | ChainEvent::ProcessExited { .. }
+            | ChainEvent::Message { .. } => false,
+        }
+    }
+
+    fn get_current_step(&self) -> Option<usize> {
+        self.running_steps.last().copied().or(self.last_step)
+    }
+
+    pub fn get_progress(&self) -> RunProgress<'_> {
+        if let Some(succeeded) = self.outcome {
+            return RunProgress::Finished { succeeded };
+        }
+        match self.get_current_step().and_then(|step| Some((step, self.program_labels.get(step)?))) {
+            Some((step, summary)) => RunProgress::Running { step, total: self.program_labels.len(), summary },
+            None => RunProgress::Starting,
+        }
+    }
+}
+
+/// Sets the title of a terminal to the progress of a chain, and restores
+/// the title it had when dropped, whichever way the run ends
+pub struct TerminalTitle<W: Write> {
+    writer: W,
+    chain_name: String,
+    tracker: TitleTracker,
+    is_restored: bool,
+}
+
+impl<W: Write> TerminalTitle<W> {
+    /// Save the title of the terminal, and show the chain as starting
+    pub fn new(mut writer: W, chain_name: &str) -> Self {
+        let _ = writer.write_all(SAVE_TITLE_SEQUENCE.as_bytes());
+        let mut terminal_title = Self { writer, chain_name: chain_name.to_string(), tracker: TitleTracker::new(), is_restored: false };
+        terminal_title.write_title();
+
+        terminal_title
+    }
+
+    /// Take an event of the chain, and show the progress if it changed
+    pub fn apply(&mut self, event: &ChainEvent) {
+        if !self.is_restored && self.tracker.apply(event) {
+            self.write_title();
+        }
+    }
+
+    fn write_title(&mut self) {
+        let title: String = render_progress_title(&self.chain_name, self.tracker.get_progress());
+        let _ = write!(self.writer, "\x1b]0;{}\x07", title);
+        let _ = self.writer.flush();
+    }
+
+    /// Give the terminal its title back. Does nothing the second time.
+    pub fn restore(&mut self) {
+        if std::mem::replace(&mut self.is_restored, true) {
+            return;
+        }
+        let _ = self.writer.write_all(RESTORE_TITLE_SEQUENCE.as_bytes());
+        let _ = self.writer.flush();
+    }
+}
+
+impl<W: Write> Drop for TerminalTitle<W> {
+    fn drop(&mut self) {
+        self.restore();
+    }
+}
+
+/// Take the title, recovering it from a thread that panicked with it, so
+/// that the panic hook can still restore it
+fn lock_terminal_title() -> MutexGuard<'static, Option<TerminalTitle<Box<dyn Write + Send>>>> {
+    TERMINAL_TITLE.lock().unwrap_or_else(|error| error.into_inner())
+}
+
+/// Show the progress of the chain in the title of the terminal, for
+/// `cchain run` at a terminal. The events come from [`crate::display_control::display_event`].
+pub fn start_terminal_title(chain_name: &str) {
+    install_panic_hook();
+    *lock_terminal_title() = Some(TerminalTitle::new(Box::new(std::io::stdout()), chain_name));
+}
+
+/// Follow an event of the chain in the title of the terminal, if it is set
+pub fn update_terminal_title(event: &ChainEvent) {
+    if let Some(terminal_title) = &mut *lock_terminal_title() {
+        terminal_title.apply(event);
+    }
+}
+
+/// Give the terminal its title back. Does nothing when the title is not
+/// set, so it is safe to call from the panic hook and the interrupt
+/// handler.
+pub fn restore_terminal_title() {
+    // Dropping the title restores it
+    let _ = lock_terminal_title().take();
+}
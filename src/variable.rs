@@ -1,5 +1,13 @@
+use std::{path::Path, str::FromStr, sync::LazyLock};
+
 use anyhow::{anyhow, Error, Ok, Result};
-use regex;
+use regex::{self, Regex};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::scratch::CHAIN_TMP_VARIABLE_NAME,
+    expression::{Expression, TypedValue},
+};
 
 /// note
 /// three conditions in which the value of a variable is supplied
@@ -11,9 +19,9 @@ use regex;
 ///         - unmarked is executed on program startup.
 /// 3. get a program's output as a value.
 ///     - marked by a key called `stdout_stored_to` in the config.
-
+///
 /// For determing the variable lifetime in a chain
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub struct VariableLifetime {
     /// When does this variable gurantee to initialize
     initialization_program_index: usize,
@@ -26,10 +34,15 @@ impl VariableLifetime {
             initialization_program_index: initialization_program_index.unwrap_or(0),
         }
     }
+
+    /// Get the index of the program where the variable is initialized
+    pub fn get_initialization_program_index(&self) -> usize {
+        self.initialization_program_index
+    }
 }
 
 /// Denotes the different times at which a variable should be initialized.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
 pub enum VariableInitializationTime {
     /// Initialized on the chain's startup
     OnChainStartup(VariableLifetime),
@@ -60,16 +73,330 @@ impl VariableInitializationTime {
     }
 }
 
-#[derive(Debug, Clone)]
+/// Types that can be declared for a variable in the chain metadata
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum VariableType {
+    /// Plain text, substituted as is
+    #[default]
+    String,
+    /// A signed integer, optionally bounded by `min` and `max`
+    Integer,
+    /// `true` or `false`. `yes`/`no`, `y`/`n` and `1`/`0` are accepted as input
+    Boolean,
+}
+
+impl std::fmt::Display for VariableType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VariableType::String => write!(f, "string"),
+            VariableType::Integer => write!(f, "integer"),
+            VariableType::Boolean => write!(f, "boolean"),
+        }
+    }
+}
+
+/// Declaration of a variable in the `variables` section of the chain metadata
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VariableDeclaration {
+    /// The type of the variable's value
+    #[serde(rename = "type", default)]
+    pub variable_type: VariableType,
+    /// Inclusive lower bound for integer variables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min: Option<i64>,
+    /// Inclusive upper bound for integer variables
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max: Option<i64>,
+    /// What the value is for, or where to find it. Shown beneath the prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// An example value, shown beneath the prompt
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub example: Option<String>,
+    /// The name shown in the prompt, instead of the title-cased variable name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub human_readable_name: Option<String>,
+    /// The value is sensitive, e.g. a token. It is not exported to
+    /// debug shells unless asked to.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub secret: bool,
+    /// Never use the preset of the variable, for values that must not be
+    /// shared with other chains
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    pub no_preset: bool,
+}
+
+/// Normalize a variable name for matching declarations with placeholders,
+/// so that `<< Host >>` matches a declaration of `host`
+pub fn normalize_variable_name(name: &str) -> String {
+    name.trim().to_lowercase()
+}
+
+impl VariableDeclaration {
+    /// Parse a raw input into a typed value according to the declaration.
+    ///
+    /// Returns `None` for string variables, as they are substituted as is.
+    pub fn parse_value(&self, value: &str) -> Result<Option<TypedValue>, Error> {
+        let value: &str = value.trim();
+        match self.variable_type {
+            VariableType::String => Ok(None),
+            VariableType::Integer => {
+                let number: i64 = value
+                    .parse::<i64>()
+                    .map_err(|_| anyhow!("`{}` is not an integer", value))?;
+                if let Some(min) = self.min {
+                    if number < min {
+                        return Err(anyhow!("{} is smaller than the minimum {}", number, min));
+                    }
+                }
+                if let Some(max) = self.max {
+                    if number > max {
+                        return Err(anyhow!("{} is larger than the maximum {}", number, max));
+                    }
+                }
+                Ok(Some(TypedValue::Integer(number)))
+            }
+            VariableType::Boolean => match value.to_lowercase().as_str() {
+                "true" | "yes" | "y" | "1" => Ok(Some(TypedValue::Boolean(true))),
+                "false" | "no" | "n" | "0" => Ok(Some(TypedValue::Boolean(false))),
+                _ => Err(anyhow!("`{}` is not a boolean, expected true or false", value)),
+            },
+        }
+    }
+
+    /// Check that the declaration itself is consistent
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.variable_type != VariableType::Integer && (self.min.is_some() || self.max.is_some()) {
+            return Err(anyhow!("`min` and `max` are only allowed for integer variables"));
+        }
+        if let (Some(min), Some(max)) = (self.min, self.max) {
+            if min > max {
+                return Err(anyhow!("`min` ({}) is larger than `max` ({})", min, max));
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Placeholders like `<<name>>`, or `<<<<`, which escapes a literal `<<`.
+/// The escape goes first, so that `<<<<name>>` is not a placeholder.
+static PLACEHOLDER_PATTERN: LazyLock<Regex> = LazyLock::new(|| Regex::new(r"<<<<|<<(.*?)>>").unwrap());
+
+/// The default placeholder delimiters
+pub const DEFAULT_OPENING_DELIMITER: &str = "<<";
+pub const DEFAULT_CLOSING_DELIMITER: &str = ">>";
+
+/// The delimiters of the placeholders of a chain, set with
+/// `variable_delimiters` in the metadata for chains whose arguments
+/// contain `<<` and `>>`, e.g. heredocs and templates
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub struct VariableDelimiters {
+    pub open: String,
+    pub close: String,
+}
+
+impl Default for VariableDelimiters {
+    fn default() -> Self {
+        Self { open: DEFAULT_OPENING_DELIMITER.to_string(), close: DEFAULT_CLOSING_DELIMITER.to_string() }
+    }
+}
+
+impl std::fmt::Display for VariableDelimiters {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}name{}", self.open, self.close)
+    }
+}
+
+impl VariableDelimiters {
+    pub fn is_default(&self) -> bool {
+        self == &Self::default()
+    }
+
+    /// Check that placeholders can be told apart from the text around
+    pub fn validate(&self) -> Result<(), Error> {
+        if self.open.is_empty() || self.close.is_empty() {
+            return Err(anyhow!("`open` and `close` of `variable_delimiters` cannot be empty"));
+        }
+        if self.open.contains(&self.close) || self.close.contains(&self.open) {
+            return Err(anyhow!(
+                "`variable_delimiters` `{}` and `{}` cannot be the same or contain each other",
+                self.open,
+                self.close
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Rewrite a text using these delimiters with the default ones, so that
+    /// the rest of cchain only knows `<<` and `>>`. Placeholders become
+    /// `<<name>>`, the literal `<<` in the text becomes the escape `<<<<`,
+    /// and the escape of the delimiter, e.g. `[[[[`, becomes a literal `[[`.
+    pub fn to_default_syntax(&self, text: &str) -> String {
+        if self.is_default() {
+            return text.to_string();
+        }
+
+        let escape: String = self.open.repeat(2);
+        let mut converted: String = String::new();
+        let mut rest: &str = text;
+        while !rest.is_empty() {
+            if let Some(after) = rest.strip_prefix(escape.as_str()) {
+                converted.push_str(&self.open);
+                rest = after;
+            } else if let Some((content, after)) =
+                rest.strip_prefix(self.open.as_str()).and_then(|after| after.split_once(self.close.as_str()))
+            {
+                converted.push_str(&format!("{}{}{}", DEFAULT_OPENING_DELIMITER, content, DEFAULT_CLOSING_DELIMITER));
+                rest = after;
+            } else if let Some(after) = rest.strip_prefix(DEFAULT_OPENING_DELIMITER) {
+                converted.push_str(&DEFAULT_OPENING_DELIMITER.repeat(2));
+                rest = after;
+            } else {
+                let character: char = rest.chars().next().unwrap();
+                converted.push(character);
+                rest = &rest[character.len_utf8()..];
+            }
+        }
+
+        converted
+    }
+}
+
+/// The variable that a field like `stdout_stored_to` stores a value in
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredVariable {
+    pub name: String,
+    /// Written as a bare name without delimiters, which is deprecated
+    pub is_bare: bool,
+}
+
+impl StoredVariable {
+    /// Parse the value of a field that stores a value in a variable, e.g.
+    /// `stdout_stored_to`. It must be exactly one placeholder without a
+    /// qualifier, like `<<branch>>`. A bare name, like `branch`, is still
+    /// accepted.
+    pub fn parse(field: &str, value: &str) -> Result<Self, Error> {
+        let invalid = |reason: String| {
+            anyhow!(
+                "`{}` must be exactly one placeholder without a qualifier, like `<<name>>`, but it is `{}`: {}",
+                field,
+                value,
+                reason
+            )
+        };
+
+        let (name, is_bare): (&str, bool) = match value
+            .strip_prefix(DEFAULT_OPENING_DELIMITER)
+            .and_then(|rest| rest.strip_suffix(DEFAULT_CLOSING_DELIMITER))
+        {
+            Some(name) if !name.contains(DEFAULT_OPENING_DELIMITER) && !name.contains(DEFAULT_CLOSING_DELIMITER) => {
+                (name, false)
+            }
+            _ if !value.contains(['<', '>']) => (value, true),
+            _ => return Err(invalid("it is not a single placeholder".to_string())),
+        };
+
+        if name.trim().is_empty() {
+            return Err(invalid("the variable name is empty".to_string()));
+        }
+        if name.starts_with('?') {
+            return Err(invalid("an anonymous prompt cannot store a value".to_string()));
+        }
+        if let Some((variable_name, qualifier)) = name.split_once(':') {
+            return Err(invalid(format!(
+                "the qualifier `:{}` only applies where a variable is used. Store to `<<{}>>` instead",
+                qualifier, variable_name.trim()
+            )));
+        }
+        if Expression::is_expression(name) {
+            return Err(invalid("an expression cannot store a value".to_string()));
+        }
+        if name.contains(char::is_whitespace) {
+            return Err(invalid("the variable name contains spaces".to_string()));
+        }
+
+        Ok(Self { name: name.to_string(), is_bare })
+    }
+
+    /// Write the variable as a placeholder, e.g. `<<branch>>`
+    pub fn to_placeholder(&self) -> String {
+        format!("{}{}{}", DEFAULT_OPENING_DELIMITER, self.name, DEFAULT_CLOSING_DELIMITER)
+    }
+}
+
+/// A variable as the chain registers it. Only what it is loaded with is
+/// serialized, e.g. for the parse cache, not its value.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Variable {
     /// The name for the variable
     name: String,
     /// The actual value for the variable
+    #[serde(skip)]
     value: Option<String>,
     /// The time when the variable needs to be initialized with a value
     initialization_time: VariableInitializationTime,
     /// The name for users to read on the screen
     human_readable_name: String,
+    /// Declared type and constraints, if any
+    declaration: Option<VariableDeclaration>,
+    /// Whether this is an anonymous prompt, `<<?prompt text>>`, which
+    /// is asked for at its own program and never shared
+    is_anonymous: bool,
+    /// Where the value came from, with the source of the value it
+    /// overwrote if any. The last source is the current one.
+    #[serde(skip)]
+    sources: Vec<VariableSource>,
+}
+
+/// Number of sources kept in the history of a variable
+const SOURCE_HISTORY_LENGTH: usize = 2;
+
+/// Where the value of a variable came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum VariableSource {
+    /// Answered by the user at a prompt
+    Prompt,
+    /// Provided with `--var` or `--var-file`
+    Provided { source: String },
+    /// Stored from the output of a program with `stdout_stored_to`
+    ProgramOutput { program_index: usize },
+    /// Set through `VariableGroupControl::update_value`
+    Assigned,
+    /// Set by cchain itself, e.g. `chain_tmp`
+    Automatic,
+}
+
+impl std::fmt::Display for VariableSource {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VariableSource::Prompt => write!(f, "prompt"),
+            VariableSource::Provided { source } => write!(f, "{}", source),
+            VariableSource::ProgramOutput { program_index } => write!(f, "output of program #{}", program_index),
+            VariableSource::Assigned => write!(f, "assigned"),
+            VariableSource::Automatic => write!(f, "cchain"),
+        }
+    }
+}
+
+/// The value of a variable after a run, and where it came from
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct VariableProvenance {
+    pub name: String,
+    pub value: String,
+    /// The sources of the value, oldest first. The last one is the
+    /// source of the value
+    pub sources: Vec<VariableSource>,
+}
+
+impl VariableProvenance {
+    pub fn get_source(&self) -> &VariableSource {
+        // A provenance is only built for variables with values
+        self.sources.last().unwrap()
+    }
 }
 
 impl Variable {
@@ -99,9 +426,51 @@ impl Variable {
             value,
             human_readable_name: human_readable_name.unwrap(),
             initialization_time,
+            declaration: None,
+            is_anonymous: false,
+            sources: Vec::new(),
         }
     }
 
+    /// Create the variable of an anonymous prompt. The raw variable is
+    /// the content of the placeholder without `?`, preferably numbered
+    /// by `number_anonymous_prompts`, e.g. `3:Commit message`.
+    pub fn new_anonymous(raw_variable: &str, program_index: usize) -> Self {
+        let prompt: &str = match raw_variable.split_once(':') {
+            Some((number, prompt)) if !number.is_empty() && number.chars().all(|c| c.is_ascii_digit()) => prompt,
+            _ => raw_variable,
+        };
+
+        let mut variable = Variable::new(
+            format!("?{}", raw_variable),
+            None,
+            Some(prompt.to_string()),
+            VariableInitializationTime::OnProgramExecution(VariableLifetime::new(Some(program_index))),
+        );
+        variable.is_anonymous = true;
+
+        variable
+    }
+
+    /// Number the anonymous prompts in the string, e.g. `<<?Commit message>>`
+    /// becomes `<<?3:Commit message>>`, so that every occurrence is a
+    /// distinct variable even when the prompt texts are the same.
+    /// The counter is shared by all strings of a chain.
+    pub fn number_anonymous_prompts(s: &str, counter: &mut usize) -> String {
+        Self::replace_placeholders(s, |content| {
+            let numbered: String = format!("<<?{}:{}>>", counter, content.strip_prefix('?')?);
+            *counter += 1;
+            Some(numbered)
+        })
+    }
+
+    /// Show the numbered anonymous prompts in the string as they are
+    /// written, e.g. `<<?3:Commit message>>` becomes `<<?Commit message>>`
+    pub fn denumber_anonymous_prompts(s: &str) -> String {
+        let re = regex::Regex::new(r"<<\?\d+:").unwrap();
+        re.replace_all(s, "<<?").into_owned()
+    }
+
     /// Parses all variables from the input string.
     ///
     /// This is the main method for extracting variables from a string. It searches for substrings
@@ -112,6 +481,12 @@ impl Variable {
     /// the actual name from its qualifier. If the qualifier is `"on_program_execution"`, the variable
     /// is set to initialize during program execution; otherwise, it defaults to chain startup.
     ///
+    /// Placeholders holding an expression, like `<<workers * 2>>`, contribute the variables they
+    /// reference instead, which are initialized on chain startup.
+    ///
+    /// Anonymous prompts, like `<<?Commit message>>`, are initialized on the execution of
+    /// their program, see `new_anonymous`.
+    ///
     /// # Arguments
     ///
     /// * `s` - A string slice that may contain variable placeholders.
@@ -125,6 +500,26 @@ impl Variable {
 
         // Iterate over each occurrence of a variable placeholder in the string.
         for raw_var in Self::extract_variable_names(s) {
+            if let Some(raw_prompt) = raw_var.strip_prefix('?') {
+                variables.push(Variable::new_anonymous(raw_prompt, program_index));
+                continue;
+            }
+
+            // Expressions are not variables themselves, but the variables
+            // they reference need values.
+            if Expression::is_expression(raw_var) {
+                let expression = Expression::from_str(raw_var)?;
+                for identifier in expression.get_identifiers() {
+                    variables.push(Variable::new(
+                        identifier,
+                        None,
+                        None,
+                        VariableInitializationTime::OnChainStartup(VariableLifetime::new(None)),
+                    ));
+                }
+                continue;
+            }
+
             // For each placeholder, determine the variable name and its initialization time.
             let (name, init_time) = Self::parse_initialization_time(raw_var, program_index);
             // Create a new Variable instance with no assigned value and no human readable name override.
@@ -214,29 +609,152 @@ impl Variable {
     ///
     /// A vector of string slices representing the extracted variable names.
     pub fn extract_variable_names(s: &str) -> Vec<&str> {
-        let re = regex::Regex::new(r"<<([^>]*)>>").unwrap();
-        re.find_iter(s)
-            .map(|item| {
-                let trim_pattern_start: [char; 2] = ['<', '<'];
-                let trim_pattern_end: [char; 2] = ['>', '>'];
-                item.as_str()
-                    .trim_start_matches(trim_pattern_start)
-                    .trim_end_matches(trim_pattern_end)
-            })
+        // Non-greedy, so that comparisons like `<<a > 1 ? x : y>>` are
+        // kept whole. Escaped `<<<<` are skipped.
+        PLACEHOLDER_PATTERN
+            .captures_iter(s)
+            .filter_map(|captures| captures.get(1))
+            .map(|content| content.as_str())
             .collect()
     }
 
-    pub fn register_value<S>(&mut self, value: S)
+    /// Replace the placeholders in the string with what the closure
+    /// returns for their content, e.g. `name` of `<<name>>`. Placeholders
+    /// for which it returns `None`, and escaped `<<<<`, are kept.
+    pub fn replace_placeholders(s: &str, mut replace: impl FnMut(&str) -> Option<String>) -> String {
+        PLACEHOLDER_PATTERN
+            .replace_all(s, |captures: &regex::Captures| match captures.get(1) {
+                Some(content) => replace(content.as_str()).unwrap_or_else(|| captures[0].to_string()),
+                None => captures[0].to_string(),
+            })
+            .into_owned()
+    }
+
+    /// Replace the placeholders of a variable in the string with its
+    /// value, escaped. An `on_program_execution` variable may be used
+    /// without its suffix by later programs, so both placeholders are
+    /// replaced.
+    pub fn replace_variable_placeholders(s: &str, raw_variable_name: &str, value: &str) -> String {
+        let placeholder: &str = raw_variable_name
+            .strip_prefix("<<")
+            .and_then(|name| name.strip_suffix(">>"))
+            .unwrap_or(raw_variable_name);
+        let placeholder_without_suffix: &str = placeholder.split(':').next().unwrap_or(placeholder);
+        let value: String = Self::escape_placeholders(value);
+
+        Self::replace_placeholders(s, |content| {
+            (content == placeholder || content == placeholder_without_suffix).then(|| value.clone())
+        })
+    }
+
+    /// Escape the `<<` in a value put in place of a placeholder, so that
+    /// it is neither taken for a placeholder nor unescaped afterwards
+    pub fn escape_placeholders(value: &str) -> String {
+        value.replace(DEFAULT_OPENING_DELIMITER, "<<<<")
+    }
+
+    /// Turn the escaped `<<<<` in the string into literal `<<`, once the
+    /// placeholders are replaced
+    pub fn unescape_placeholders(s: &str) -> String {
+        PLACEHOLDER_PATTERN
+            .replace_all(s, |captures: &regex::Captures| match captures.get(1) {
+                Some(_) => captures[0].to_string(),
+                None => DEFAULT_OPENING_DELIMITER.to_string(),
+            })
+            .into_owned()
+    }
+
+    /// Set the value of the variable, and record where it came from
+    pub fn register_value<S>(&mut self, value: S, source: VariableSource)
     where
         S: ToString,
     {
         self.value = Some(value.to_string());
+        self.sources.push(source);
+        if self.sources.len() > SOURCE_HISTORY_LENGTH {
+            self.sources.remove(0);
+        }
+    }
+
+    /// Get the sources of the value, oldest first. The last one is the
+    /// source of the current value.
+    pub fn get_sources(&self) -> &Vec<VariableSource> {
+        &self.sources
+    }
+
+    /// Get the value of the variable with where it came from, if it has one
+    pub fn get_provenance(&self) -> Option<VariableProvenance> {
+        let value: String = self.value.clone()?;
+        let name: String = if self.is_anonymous {
+            format!("?{}", self.human_readable_name)
+        } else {
+            self.name.clone()
+        };
+
+        Some(VariableProvenance { name, value, sources: self.sources.clone() })
     }
 
     pub fn get_value(&self) -> Result<String, Error> {
         match &self.value {
-            Some(value) => return Ok(value.to_string()),
-            None => return Err(anyhow!("Value for {} is empty", self.name)),
+            Some(value) => Ok(value.to_string()),
+            None => Err(anyhow!("Value for {} is empty", self.name)),
+        }
+    }
+
+    /// Attach a declaration from the chain metadata to this variable
+    pub fn set_declaration(&mut self, declaration: VariableDeclaration) {
+        if let Some(human_readable_name) = &declaration.human_readable_name {
+            self.human_readable_name = human_readable_name.clone();
+        }
+        self.declaration = Some(declaration);
+    }
+
+    /// Compose the prompt asking for the value of this variable, with
+    /// the description and the example of the declaration beneath it
+    pub fn get_prompt_message(&self) -> String {
+        let mut message: String = if self.is_anonymous {
+            format!("{}:", self.human_readable_name)
+        } else {
+            format!("Please input a value for {}:", self.human_readable_name)
+        };
+
+        let declaration: &VariableDeclaration = match &self.declaration {
+            Some(declaration) if declaration.description.is_some() || declaration.example.is_some() => declaration,
+            _ => return message,
+        };
+        if let Some(description) = &declaration.description {
+            message.push_str(&format!("\n     {}", description));
+        }
+        if let Some(example) = &declaration.example {
+            message.push_str(&format!("\n     Example: {}", example));
+        }
+        message.push_str("\n   >");
+
+        message
+    }
+
+    pub fn get_declaration(&self) -> Option<&VariableDeclaration> {
+        self.declaration.as_ref()
+    }
+
+    /// Check a raw input against the declaration of this variable.
+    /// Undeclared variables accept anything.
+    pub fn validate_value(&self, value: &str) -> Result<(), Error> {
+        if let Some(declaration) = &self.declaration {
+            declaration
+                .parse_value(value)
+                .map_err(|error| anyhow!("Invalid value for {}: {}", self.human_readable_name, error))?;
+        }
+
+        Ok(())
+    }
+
+    /// Get the typed value of this variable, if it is declared with a
+    /// non-string type and has a value.
+    pub fn get_typed_value(&self) -> Result<Option<TypedValue>, Error> {
+        match (&self.declaration, &self.value) {
+            (Some(declaration), Some(value)) => declaration.parse_value(value),
+            _ => Ok(None),
         }
     }
 
@@ -253,6 +771,10 @@ impl Variable {
     /// Complete variable name with additional syntax
     /// example: <<var:on_program_execution>>
     pub fn get_raw_variable_name(&self) -> String {
+        if self.is_anonymous {
+            return "<<".to_string() + &self.name + ">>";
+        }
+
         match self.initialization_time {
             VariableInitializationTime::OnProgramExecution { .. } => {
                 "<<".to_string() + &self.name + ":" + "on_program_execution" + ">>"
@@ -261,6 +783,16 @@ impl Variable {
         }
     }
 
+    pub fn is_anonymous(&self) -> bool {
+        self.is_anonymous
+    }
+
+    /// Check whether cchain sets the value of the variable itself, like
+    /// `chain_tmp`, so that it is never asked for or declared
+    pub fn is_automatic(&self) -> bool {
+        !self.is_anonymous && self.get_variable_name() == CHAIN_TMP_VARIABLE_NAME
+    }
+
     pub fn get_initialization_time(&self) -> VariableInitializationTime {
         self.initialization_time
     }
@@ -271,3 +803,161 @@ pub trait VariableGroupControl {
 
     fn get_value(&self, variable_name: &str) -> Result<String, Error>;
 }
+
+/// A variable value provided before the chain runs, with `--var` or
+/// `--var-file`, instead of being prompted for
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProvidedValue {
+    pub value: String,
+    /// Where the value comes from, for error messages
+    pub source: String,
+}
+
+impl ProvidedValue {
+    /// Resolve a `--var name=value` pair. A value of `@path` is read from
+    /// the file verbatim, including the newlines.
+    pub fn from_assignment(name: &str, value: &str) -> Result<Self, Error> {
+        match value.strip_prefix('@') {
+            Some(path) => {
+                let value: String = std::fs::read_to_string(path).map_err(|error| {
+                    anyhow!("Cannot read the value of variable `{}` from {}: {}", name, path, error)
+                })?;
+                Ok(Self { value, source: format!("--var {}=@{}", name, path) })
+            }
+            None => Ok(Self { value: value.to_string(), source: format!("--var {}", name) }),
+        }
+    }
+
+    /// Load the values in a variable file, keyed by the variable names.
+    /// A file is read as JSON when it is an object, and as dotenv-style
+    /// `NAME=value` lines otherwise.
+    pub fn from_file(path: &str) -> Result<Vec<(String, Self)>, Error> {
+        let content: String = std::fs::read_to_string(path)
+            .map_err(|error| anyhow!("Cannot read variable file {}: {}", path, error))?;
+        let source: String = format!("--var-file {}", path);
+
+        let mut values: Vec<(String, Self)> = Vec::new();
+        if content.trim_start().starts_with('{') {
+            let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(&content)
+                .map_err(|error| anyhow!("Variable file {} is not a JSON object: {}", path, error))?;
+            for (name, value) in object {
+                let value: String = match value {
+                    serde_json::Value::String(value) => value,
+                    _ => return Err(anyhow!(
+                        "Variable file {}: the value of `{}` must be a string",
+                        path,
+                        name
+                    )),
+                };
+                values.push((name, Self { value, source: source.clone() }));
+            }
+
+            return Ok(values);
+        }
+
+        Self::parse_dotenv(&content, path, &source)
+    }
+
+    /// Load the values in the `.cchain.env` file next to a chain. The
+    /// file is always read as dotenv-style lines.
+    pub fn from_adjacent_env_file(path: &Path) -> Result<Vec<(String, Self)>, Error> {
+        let content: String = std::fs::read_to_string(path)
+            .map_err(|error| anyhow!("Cannot read {}: {}", path.display(), error))?;
+        let path: String = path.display().to_string();
+
+        Self::parse_dotenv(&content, &path, &path)
+    }
+
+    /// Parse `NAME=value` lines, skipping blank lines and comments
+    fn parse_dotenv(content: &str, path: &str, source: &str) -> Result<Vec<(String, Self)>, Error> {
+        let mut values: Vec<(String, Self)> = Vec::new();
+        for (line_number, line) in content.lines().enumerate() {
+            let line: &str = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+
+            let line: &str = line.strip_prefix("export ").unwrap_or(line);
+            let (name, value) = line.split_once('=').ok_or_else(|| {
+                anyhow!("Variable file {}, line {}: expected `NAME=value`", path, line_number + 1)
+            })?;
+            let value: &str = value.trim();
+            let value: &str = ['"', '\''].iter()
+                .find_map(|quote| value.strip_prefix(*quote)?.strip_suffix(*quote))
+                .unwrap_or(value);
+            values.push((name.trim().to_string(), Self { value: value.to_string(), source: source.to_string() }));
+        }
+
+        Ok(values)
+    }
+}
+
+/// A source of the value of a variable before the chain runs, in the
+/// order of precedence
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ValueOrigin {
+    /// `--var` or `--var-file`
+    Provided,
+    /// The `.cchain.env` file next to the chain
+    AdjacentEnv,
+    Preset,
+    /// The last value entered at the prompt in an earlier run
+    Remembered,
+}
+
+impl ValueOrigin {
+    /// The sources in the order of precedence
+    pub const ALL: [ValueOrigin; 4] =
+        [ValueOrigin::Provided, ValueOrigin::AdjacentEnv, ValueOrigin::Preset, ValueOrigin::Remembered];
+
+    /// The key that picks the source at `--confirm-vars`
+    pub fn get_key(&self) -> &'static str {
+        match self {
+            ValueOrigin::Provided => "v",
+            ValueOrigin::AdjacentEnv => "e",
+            ValueOrigin::Preset => "p",
+            ValueOrigin::Remembered => "r",
+        }
+    }
+
+    /// The source as a choice at `--confirm-vars`, with its key
+    pub fn get_choice(&self) -> &'static str {
+        match self {
+            ValueOrigin::Provided => "[v]ar",
+            ValueOrigin::AdjacentEnv => "[e]nv",
+            ValueOrigin::Preset => "[p]reset",
+            ValueOrigin::Remembered => "[r]emembered",
+        }
+    }
+}
+
+impl std::fmt::Display for ValueOrigin {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ValueOrigin::Provided => write!(f, "--var"),
+            ValueOrigin::AdjacentEnv => write!(f, ".cchain.env"),
+            ValueOrigin::Preset => write!(f, "preset"),
+            ValueOrigin::Remembered => write!(f, "remembered"),
+        }
+    }
+}
+
+/// A value that a source supplies for a variable
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ValueCandidate {
+    pub origin: ValueOrigin,
+    pub value: ProvidedValue,
+}
+
+/// Pick the value a variable gets without being prompted for, from the
+/// candidates in the order of precedence. `--var` and `--var-file` always
+/// win. `.cchain.env` and presets only win without a user to ask, who
+/// otherwise gets them as the default answer. Remembered values are only
+/// offered at the prompt.
+pub fn select_winning_value(candidates: &[ValueCandidate], is_unattended: bool) -> Option<&ValueCandidate> {
+    candidates.iter().find(|candidate| match candidate.origin {
+        ValueOrigin::Provided => true,
+        ValueOrigin::AdjacentEnv | ValueOrigin::Preset => is_unattended,
+        ValueOrigin::Remembered => false,
+    })
+}
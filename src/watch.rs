@@ -0,0 +1,321 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    sync::mpsc::channel,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
+};
+
+use anyhow::{Error, Result};
+use console::style;
+
+use crate::{
+    core::{
+        chain::{Chain, ADJACENT_ENV_FILE_NAME},
+        events::{ChainEvent, EventSender},
+        inheritance::{get_inheritance_paths, resolve_chain_file},
+        metadata::format_timestamp,
+        migration::migrate_legacy_fields,
+        schema::{get_chain_file_schema, validate_against_schema, SchemaViolation},
+    },
+    dashboard::{install_panic_hook, restore_terminal, take_terminal},
+    display_control::with_thread_display_capture,
+};
+
+/// How often the watched files are looked at
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// How long the watched files have to stay unchanged before they are
+/// checked, so that an editor saving in several steps, e.g. writing a
+/// temporary file and renaming it over the chain, is checked once
+const DEBOUNCE_PERIOD: Duration = Duration::from_millis(300);
+
+/// Escape sequence that clears the screen and moves to its top
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Makes the terminal beep when a chain that passed starts to fail
+const BELL: &str = "\x07";
+
+/// What `cchain check` checks besides the syntax
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CheckOptions {
+    /// Report the lints as warnings
+    pub lint: bool,
+    /// Also lint the programs identical to an earlier, not adjacent one
+    pub separated_duplicates: bool,
+    /// Fail on variables that are not declared
+    pub require_declarations: bool,
+    /// Validate against the JSON Schema of chain files first
+    pub schema: bool,
+}
+
+/// The outcome of checking a chain file once
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CheckReport {
+    /// Problems that make the chain invalid, each with where it is, e.g.
+    /// the JSON pointer or the program
+    problems: Vec<String>,
+    /// Lints and the warnings shown while loading the chain
+    warnings: Vec<String>,
+}
+
+impl CheckReport {
+    pub fn new(problems: Vec<String>, warnings: Vec<String>) -> Self {
+        Self { problems, warnings }
+    }
+
+    pub fn get_problems(&self) -> &Vec<String> {
+        &self.problems
+    }
+
+    pub fn get_warnings(&self) -> &Vec<String> {
+        &self.warnings
+    }
+
+    /// Whether the chain passed. Warnings do not fail it.
+    pub fn is_passing(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+
+/// Check a chain file as `cchain check` does, reading it and the chains it
+/// extends from disk again. The messages shown while loading the chain are
+/// collected as warnings instead of being displayed.
+pub fn check_chain_file(chain_path: &str, options: &CheckOptions) -> CheckReport {
+    if options.schema {
+        let resolved = resolve_chain_file(Path::new(chain_path)).and_then(|mut resolved| {
+            migrate_legacy_fields(&mut resolved.value)?;
+            Ok(resolved)
+        });
+        match resolved {
+            Ok(resolved) => {
+                let violations: Vec<SchemaViolation> =
+                    validate_against_schema(&resolved.value, &get_chain_file_schema());
+                if !violations.is_empty() {
+                    return CheckReport::new(
+                        violations.iter().map(|violation| violation.to_string()).collect(),
+                        Vec::new(),
+                    );
+                }
+            }
+            Err(error) => return CheckReport::new(vec![error.to_string()], Vec::new()),
+        }
+    }
+
+    // The messages of this check only, as other checks may run at the
+    // same time
+    let (sender, receiver) = channel();
+    let chain: Result<Chain, Error> = with_thread_display_capture(EventSender::new(sender), || {
+        Chain::from_file(chain_path).and_then(|mut chain| {
+            chain.set_require_declarations(options.require_declarations);
+            chain.set_lint_separated_duplicates(options.separated_duplicates);
+            chain.validate_syntax()?;
+            Ok(chain)
+        })
+    });
+
+    let mut warnings: Vec<String> = receiver
+        .try_iter()
+        .filter_map(|event| match event {
+            ChainEvent::Message { text } => Some(text.trim().to_string()),
+            _ => None,
+        })
+        .filter(|text| !text.is_empty())
+        .collect();
+    match chain {
+        Ok(chain) => {
+            if options.lint {
+                warnings.extend(chain.lint());
+            }
+            CheckReport::new(Vec::new(), warnings)
+        }
+        Err(error) => CheckReport::new(vec![error.to_string()], warnings),
+    }
+}
+
+/// Render a report as the lines of the watch screen
+pub fn render_check_report(report: &CheckReport, chain_path: &str, checked_at: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    if report.is_passing() {
+        lines.push(format!(
+            "{} {} passes the checks ({})",
+            style("✓").green().bold(),
+            chain_path,
+            checked_at
+        ));
+    } else {
+        lines.push(format!(
+            "{} {} fails the checks with {} problem(s) ({})",
+            style("✗").red().bold(),
+            chain_path,
+            report.problems.len(),
+            checked_at
+        ));
+    }
+
+    for problem in &report.problems {
+        lines.push(format!("  {} {}", style("error:").red().bold(), problem));
+    }
+    for warning in &report.warnings {
+        lines.push(format!("  {} {}", style("warning:").yellow(), warning));
+    }
+
+    lines
+}
+
+/// Get the files that a check of the chain depends on: the chain, the
+/// chains it extends, and the `.cchain.env` file next to it
+pub fn get_watched_paths(chain_path: &Path) -> Vec<PathBuf> {
+    let mut paths: Vec<PathBuf> = get_inheritance_paths(chain_path);
+    paths.push(chain_path.parent().unwrap_or(Path::new("")).join(ADJACENT_ENV_FILE_NAME));
+
+    paths
+}
+
+/// What a watched file looked like. `None` for a file that is missing,
+/// e.g. for a moment while an editor renames a new version over it.
+type FileFingerprint = Option<(SystemTime, u64)>;
+
+/// Notices changes to files by looking at their modification times and
+/// lengths
+#[derive(Debug, Clone, Default)]
+pub struct FileWatcher {
+    fingerprints: Vec<(PathBuf, FileFingerprint)>,
+}
+
+impl FileWatcher {
+    pub fn new(paths: Vec<PathBuf>) -> Self {
+        let mut file_watcher = Self::default();
+        file_watcher.set_paths(paths);
+
+        file_watcher
+    }
+
+    /// Watch another set of files, e.g. after the chain changed what it
+    /// extends. Files that were watched already keep what they looked like.
+    pub fn set_paths(&mut self, paths: Vec<PathBuf>) {
+        self.fingerprints = paths
+            .into_iter()
+            .map(|path| {
+                let fingerprint: FileFingerprint = self
+                    .fingerprints
+                    .iter()
+                    .find(|(watched_path, _)| watched_path == &path)
+                    .map_or_else(|| get_file_fingerprint(&path), |(_, fingerprint)| *fingerprint);
+                (path, fingerprint)
+            })
+            .collect();
+    }
+
+    pub fn get_paths(&self) -> Vec<&Path> {
+        self.fingerprints.iter().map(|(path, _)| path.as_path()).collect()
+    }
+
+    /// Whether any of the files changed, appeared or went missing since
+    /// the last time
+    pub fn has_changed(&mut self) -> bool {
+        let mut has_changed: bool = false;
+        for (path, fingerprint) in self.fingerprints.iter_mut() {
+            let current_fingerprint: FileFingerprint = get_file_fingerprint(path);
+            if current_fingerprint != *fingerprint {
+                *fingerprint = current_fingerprint;
+                has_changed = true;
+            }
+        }
+
+        has_changed
+    }
+}
+
+fn get_file_fingerprint(path: &Path) -> FileFingerprint {
+    let metadata: std::fs::Metadata = std::fs::metadata(path).ok()?;
+    Some((metadata.modified().ok()?, metadata.len()))
+}
+
+/// Check a chain again each time it or a file it depends on changes,
+/// showing the outcome on a cleared screen until the user presses `q`
+pub fn watch_chain_checks(chain_path: &str, options: &CheckOptions) -> Result<(), Error> {
+    install_panic_hook();
+    take_terminal();
+
+    let mut file_watcher = FileWatcher::new(get_watched_paths(Path::new(chain_path)));
+    let mut was_passing: Option<bool> = None;
+    // Check once right away
+    let mut changed_at: Option<Instant> = Some(Instant::now() - DEBOUNCE_PERIOD);
+    loop {
+        if is_quit_pressed(POLL_INTERVAL) {
+            break;
+        }
+        if file_watcher.has_changed() {
+            changed_at = Some(Instant::now());
+        }
+        if changed_at.is_none_or(|changed_at| changed_at.elapsed() < DEBOUNCE_PERIOD) {
+            continue;
+        }
+        changed_at = None;
+
+        let report: CheckReport = check_chain_file(chain_path, options);
+        // The chain may now extend another file
+        file_watcher.set_paths(get_watched_paths(Path::new(chain_path)));
+
+        let checked_at: u64 = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |elapsed| elapsed.as_secs());
+        let mut lines: Vec<String> = render_check_report(&report, chain_path, &format_timestamp(checked_at));
+        lines.push(String::new());
+        lines.push(format!(
+            "Watching {}. Press q to quit.",
+            file_watcher
+                .get_paths()
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<String>>()
+                .join(", ")
+        ));
+
+        let mut screen: String = CLEAR_SCREEN.to_string();
+        if was_passing == Some(true) && !report.is_passing() {
+            screen.push_str(BELL);
+        }
+        screen.push_str(&lines.join("\n"));
+        let mut stdout = std::io::stdout().lock();
+        stdout.write_all(screen.as_bytes())?;
+        stdout.flush()?;
+        was_passing = Some(report.is_passing());
+    }
+
+    restore_terminal();
+
+    Ok(())
+}
+
+/// Wait for a key for a while
+///
+/// # Returns
+///
+/// Whether the key is `q`
+#[cfg(unix)]
+fn is_quit_pressed(timeout: Duration) -> bool {
+    use std::io::Read;
+
+    let mut poll_fd = libc::pollfd { fd: libc::STDIN_FILENO, events: libc::POLLIN, revents: 0 };
+    let is_readable: bool = unsafe { libc::poll(&mut poll_fd, 1, timeout.as_millis() as libc::c_int) } > 0;
+    if !is_readable {
+        return false;
+    }
+
+    let mut buffer = [0u8; 16];
+    let read_length: usize = std::io::stdin().lock().read(&mut buffer).unwrap_or(0);
+    if read_length == 0 {
+        // Standard input is closed, so it is readable without keys
+        std::thread::sleep(timeout);
+        return false;
+    }
+
+    buffer[..read_length].contains(&b'q')
+}
+
+/// Keys are not read without a Unix terminal, so the watch stops with
+/// Ctrl-C instead
+#[cfg(not(unix))]
+fn is_quit_pressed(timeout: Duration) -> bool {
+    std::thread::sleep(timeout);
+    false
+}
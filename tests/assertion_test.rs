@@ -0,0 +1,74 @@
+#[cfg(test)]
+mod tests {
+    use cchain::core::assertion::OutputAssertion;
+
+    const OUTPUT: &str = "Compiling cchain\nFinished release in 3.2s";
+
+    #[test]
+    fn test_contains() {
+        assert!(OutputAssertion::Contains("Finished".to_string()).check(OUTPUT, false).is_ok());
+
+        let error = OutputAssertion::Contains("error".to_string())
+            .check(OUTPUT, false)
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Assertion failed: contains \"error\""));
+        assert!(error.contains("Finished release in 3.2s"));
+    }
+
+    #[test]
+    fn test_not_contains() {
+        assert!(OutputAssertion::NotContains("warning".to_string()).check(OUTPUT, false).is_ok());
+        assert!(OutputAssertion::NotContains("Compiling".to_string()).check(OUTPUT, false).is_err());
+    }
+
+    #[test]
+    fn test_matches_regex() {
+        let assertion = OutputAssertion::MatchesRegex(r"in \d+\.\d+s$".to_string());
+        assert!(assertion.validate(false).is_ok());
+        assert!(assertion.check(OUTPUT, false).is_ok());
+        assert!(assertion.check("Finished", false).is_err());
+
+        assert!(OutputAssertion::MatchesRegex("(unclosed".to_string()).validate(false).is_err());
+    }
+
+    #[test]
+    fn test_equals_file_snapshot_flow() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("snapshots/build.txt");
+        let assertion = OutputAssertion::EqualsFile(path.to_string_lossy().into_owned());
+
+        // A missing snapshot is only accepted when updating
+        assert!(assertion.validate(false).is_err());
+        assert!(assertion.validate(true).is_ok());
+
+        assertion.check(OUTPUT, true).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), OUTPUT);
+        assert!(assertion.validate(false).is_ok());
+        assert!(assertion.check(OUTPUT, false).is_ok());
+
+        let error = assertion
+            .check("Compiling cchain\nFinished release in 4.0s", false)
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("-Finished release in 3.2s"));
+        assert!(error.contains("+Finished release in 4.0s"));
+    }
+
+    #[test]
+    fn test_deserialize_assertions() {
+        let assertions: Vec<OutputAssertion> = serde_json::from_str(
+            r#"[{ "contains": "ok" }, { "not_contains": "error" }, { "matches_regex": "^v\\d" }, { "equals_file": "out.txt" }]"#
+        ).unwrap();
+
+        assert_eq!(
+            assertions,
+            vec![
+                OutputAssertion::Contains("ok".to_string()),
+                OutputAssertion::NotContains("error".to_string()),
+                OutputAssertion::MatchesRegex("^v\\d".to_string()),
+                OutputAssertion::EqualsFile("out.txt".to_string()),
+            ]
+        );
+    }
+}
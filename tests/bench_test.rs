@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests {
+    use assert_cmd::Command;
+    use cchain::core::bench::{get_percentile, BenchReport, BenchStep, ChangeSignificance, TimingStatistics};
+
+    #[test]
+    fn test_timing_statistics() {
+        let statistics = TimingStatistics::from_samples(&[40.0, 10.0, 30.0, 20.0]).unwrap();
+        assert_eq!(statistics.count, 4);
+        assert_eq!(statistics.min_ms, 10.0);
+        assert_eq!(statistics.median_ms, 25.0);
+        assert_eq!(statistics.mean_ms, 25.0);
+        assert!((statistics.p95_ms - 38.5).abs() < 1e-9);
+        // Sample standard deviation: sqrt(500 / 3)
+        assert!((statistics.stddev_ms - (500.0f64 / 3.0).sqrt()).abs() < 1e-9);
+
+        let single = TimingStatistics::from_samples(&[7.0]).unwrap();
+        assert_eq!((single.median_ms, single.p95_ms, single.stddev_ms), (7.0, 7.0, 0.0));
+        assert!(!single.is_unstable(0.0));
+        assert!(TimingStatistics::from_samples(&[]).is_none());
+
+        assert_eq!(get_percentile(&[1.0, 2.0, 3.0], 50.0), 2.0);
+        assert_eq!(get_percentile(&[1.0, 2.0, 3.0], 100.0), 3.0);
+    }
+
+    #[test]
+    fn test_unstable_steps() {
+        let stable = BenchStep::new(Some(0), "stable".to_string(), vec![100.0, 101.0, 99.0], 10.0);
+        assert!(!stable.unstable);
+        let unstable = BenchStep::new(Some(1), "unstable".to_string(), vec![100.0, 200.0, 50.0], 10.0);
+        assert!(unstable.unstable);
+        assert!(unstable.statistics.unwrap().get_variation_percent() > 50.0);
+    }
+
+    #[test]
+    fn test_change_significance() {
+        let baseline = TimingStatistics::from_samples(&[100.0, 102.0, 98.0, 101.0, 99.0]).unwrap();
+        let faster = TimingStatistics::from_samples(&[60.0, 61.0, 59.0, 62.0, 58.0]).unwrap();
+        let slower = TimingStatistics::from_samples(&[150.0, 151.0, 149.0, 152.0, 148.0]).unwrap();
+        let noisy = TimingStatistics::from_samples(&[50.0, 150.0, 100.0, 170.0, 40.0]).unwrap();
+        assert_eq!(ChangeSignificance::from_statistics(&baseline, &faster), ChangeSignificance::Faster);
+        assert_eq!(ChangeSignificance::from_statistics(&baseline, &slower), ChangeSignificance::Slower);
+        assert_eq!(ChangeSignificance::from_statistics(&baseline, &noisy), ChangeSignificance::WithinNoise);
+        assert_eq!(ChangeSignificance::from_statistics(&baseline, &baseline), ChangeSignificance::WithinNoise);
+    }
+
+    #[test]
+    fn test_bench_chain() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("cchain_bench.json");
+        std::fs::write(
+            &path,
+            r#"[
+                { "command": "echo", "arguments": ["bench"], "retry": 0 },
+                { "command": "sleep", "arguments": ["0.2"], "retry": 0 }
+            ]"#,
+        )
+        .unwrap();
+        let bench = |arguments: &[&str]| {
+            Command::cargo_bin("cchain")
+                .unwrap()
+                .env("HOME", directory.path())
+                .arg("bench")
+                .arg(&path)
+                .args(["--iterations", "3", "--output", "json"])
+                .args(arguments)
+                .write_stdin("")
+                .output()
+                .unwrap()
+        };
+
+        let output = bench(&["--warmup", "1"]);
+        assert!(output.status.success());
+        let report: BenchReport = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(report.iterations, 3);
+        assert_eq!(report.warmup, 1);
+        assert_eq!(report.steps.len(), 2);
+        assert_eq!(report.steps[1].samples_ms.len(), 3);
+        let sleep = report.steps[1].statistics.as_ref().unwrap();
+        assert!(sleep.min_ms >= 200.0, "{:?}", sleep);
+        assert!(report.overall.statistics.as_ref().unwrap().min_ms >= sleep.min_ms);
+
+        let baseline_path = directory.path().join("baseline.json");
+        std::fs::write(&baseline_path, &output.stdout).unwrap();
+        let output = bench(&["--compare", baseline_path.to_str().unwrap()]);
+        let report: BenchReport = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(report.comparison.len(), 3);
+        assert_eq!(report.comparison[2].index, None);
+
+        // A failing program stops the bench with the iterations before it
+        std::fs::write(&path, r#"[{ "command": "false", "arguments": [], "retry": 0 }]"#).unwrap();
+        let output = bench(&[]);
+        assert_eq!(output.status.code(), Some(3));
+        let report: BenchReport = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(report.iterations, 0);
+        assert!(report.aborted.unwrap().contains("Iteration 1 failed"));
+    }
+}
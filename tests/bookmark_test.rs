@@ -0,0 +1,819 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::{Path, PathBuf}, sync::{Arc, Mutex}};
+
+    use cchain::{
+        commons::{
+            digest::sha256_hex,
+            interaction::{Interactor, ScriptedInteractor, SharedInteractor},
+            lock::FileLock,
+            naming::HumanReadable,
+            utility::{find_bookmark_index, offer_conforming_name, read_into_chain, resolve_chain_argument, run_tagged_chains, BatchOptions, ChainResolutionTrace, ChainRunOutcome, ChainSelection},
+        },
+        marker::{
+            audit::{AuditContext, AuditEntry, BookmarkOperation, AUDIT_FILE_NAME},
+            bookmark::{Bookmark, CleaningCategory, BOOKMARK_FORMAT_VERSION},
+            reference::ChainReference,
+        },
+        core::traits::Execution,
+        variable::ProvidedValue,
+    };
+
+    const CHAIN: &str = r#"[{ "command": "echo", "arguments": ["hello"], "retry": 0 }]"#;
+
+    fn create_chain(directory: &Path, name: &str, content: &str) -> PathBuf {
+        let path = directory.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn create_bookmark(directory: &Path) -> Bookmark {
+        Bookmark::new(directory.join(".cchain").to_string_lossy().into_owned())
+    }
+
+    // Test that references through symlinks and `..` segments are collapsed
+    #[cfg(unix)]
+    #[test]
+    fn test_deduplicate_symlinked_paths() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(directory.path(), "cchain_build.json", CHAIN);
+        let link_directory = directory.path().join("link");
+        std::os::unix::fs::symlink(directory.path(), &link_directory).unwrap();
+        std::fs::create_dir(directory.path().join("nested")).unwrap();
+
+        let mut bookmark = create_bookmark(directory.path());
+        let paths: Vec<String> = vec![
+            chain_path.to_string_lossy().into_owned(),
+            link_directory.join("cchain_build.json").to_string_lossy().into_owned(),
+            directory.path().join("nested/../cchain_build.json").to_string_lossy().into_owned(),
+        ];
+        for path in &paths {
+            bookmark.add_chain_reference(path.clone()).unwrap();
+        }
+
+        let removed_paths = bookmark.deduplicate();
+        assert_eq!(removed_paths, paths[1..].to_vec());
+        assert_eq!(bookmark.get_chain_references().len(), 1);
+        assert_eq!(
+            bookmark.get_chain_references()[0].get_chain_path_string(),
+            std::fs::canonicalize(&chain_path).unwrap().to_string_lossy()
+        );
+    }
+
+    // Test that a relative path and an absolute path to the same chain are collapsed
+    #[test]
+    fn test_deduplicate_relative_and_absolute_paths() {
+        let directory = tempfile::tempdir().unwrap();
+        let home = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(directory.path(), "cchain_test.json", CHAIN);
+        let bookmark_path = home.path().join("bookmark.json");
+        let mut bookmark = Bookmark::new(bookmark_path.to_string_lossy().into_owned());
+        bookmark.add_chain_reference(chain_path.to_string_lossy().into_owned()).unwrap();
+        bookmark.save().unwrap();
+        // `cchain add` saves absolute paths, so the relative one is written
+        // as a bookmark of an earlier version has it
+        let mut value: serde_json::Value =
+            serde_json::from_str(&std::fs::read_to_string(&bookmark_path).unwrap()).unwrap();
+        value["chain_references"]
+            .as_array_mut()
+            .unwrap()
+            .insert(0, serde_json::json!({ "chain_path": "cchain_test.json" }));
+        std::fs::write(&bookmark_path, value.to_string()).unwrap();
+
+        // The relative path is resolved against the working directory of the run
+        let output = assert_cmd::Command::cargo_bin("cchain")
+            .unwrap()
+            .current_dir(directory.path())
+            .env("CCHAIN_HOME", home.path())
+            .arg("clean")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(
+            stdout.contains(&format!("Chain at {} is duplicate, cleaned.", chain_path.display())),
+            "{}",
+            stdout
+        );
+        let bookmark = Bookmark::from_path(&bookmark_path).unwrap();
+        assert_eq!(bookmark.get_chain_references().len(), 1);
+    }
+
+    // Test that cleaning categorizes the removed references
+    #[test]
+    fn test_clean_categories() {
+        let directory = tempfile::tempdir().unwrap();
+        let valid_path = create_chain(directory.path(), "cchain_valid.json", CHAIN);
+        let missing_path = create_chain(directory.path(), "cchain_missing.json", CHAIN);
+        let invalid_path = create_chain(directory.path(), "cchain_invalid.json", CHAIN);
+
+        let mut bookmark = create_bookmark(directory.path());
+        for path in [&valid_path, &missing_path, &invalid_path] {
+            bookmark
+                .add_chain_reference(path.to_string_lossy().into_owned())
+                .unwrap();
+        }
+        std::fs::remove_file(&missing_path).unwrap();
+        std::fs::write(&invalid_path, "not a chain").unwrap();
+
+        // Invalid chains are reported, but kept by default
+        let cleaned_references = bookmark.clean(false).unwrap();
+        assert_eq!(cleaned_references.len(), 1);
+        assert_eq!(cleaned_references[0].category, CleaningCategory::Missing);
+        assert_eq!(bookmark.get_unparsable_paths().len(), 1);
+        assert_eq!(bookmark.get_chain_references().len(), 2);
+
+        let cleaned_references = bookmark.clean(true).unwrap();
+        assert_eq!(cleaned_references.len(), 1);
+        assert_eq!(cleaned_references[0].category, CleaningCategory::Invalid);
+        assert_eq!(bookmark.get_chain_references().len(), 1);
+    }
+
+    // Test that a legacy bookmark in a home directory is migrated
+    #[test]
+    fn test_migrate_legacy_bookmark() {
+        let home = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(home.path(), "cchain_deploy.json", CHAIN);
+        let misnamed_path = create_chain(home.path(), "deploy.json", CHAIN);
+        let missing_path = home.path().join("cchain_missing.json");
+
+        let bookmark_path = home.path().join(".cchain");
+        let legacy_bookmark = serde_json::json!({
+            "configuration_paths": [chain_path, misnamed_path, missing_path]
+        });
+        std::fs::write(&bookmark_path, legacy_bookmark.to_string()).unwrap();
+
+        let bookmark = Bookmark::from_path(&bookmark_path).unwrap();
+        assert_eq!(bookmark.get_format_version(), BOOKMARK_FORMAT_VERSION);
+        assert_eq!(bookmark.get_chain_references().len(), 1);
+        assert_eq!(
+            bookmark.get_chain_references()[0].get_chain_path_string(),
+            chain_path.to_string_lossy()
+        );
+
+        // The legacy file is backed up, and the migrated bookmark is saved
+        let backup = std::fs::read_to_string(home.path().join(".cchain.legacy.bak")).unwrap();
+        assert_eq!(backup, legacy_bookmark.to_string());
+        let bookmark = Bookmark::from_path(&bookmark_path).unwrap();
+        assert_eq!(bookmark.get_chain_references().len(), 1);
+    }
+
+    // Test that bookmarks saved before `format_version` existed still load
+    #[test]
+    fn test_load_unversioned_bookmark() {
+        let home = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(home.path(), "cchain_deploy.json", CHAIN);
+        let bookmark_path = home.path().join(".cchain");
+        let bookmark = serde_json::json!({
+            "chain_references": [{ "chain_path": chain_path }],
+            "bookmark_path": bookmark_path,
+        });
+        std::fs::write(&bookmark_path, bookmark.to_string()).unwrap();
+
+        let bookmark = Bookmark::from_path(&bookmark_path).unwrap();
+        assert_eq!(bookmark.get_format_version(), BOOKMARK_FORMAT_VERSION);
+        assert_eq!(bookmark.get_chain_references().len(), 1);
+
+        std::fs::write(&bookmark_path, "{ \"unexpected\": true }").unwrap();
+        assert!(Bookmark::from_path(&bookmark_path).is_err());
+    }
+
+    fn create_tagged_chain(directory: &Path, name: &str, command: &str, tags: &[&str]) -> PathBuf {
+        let content = serde_json::json!({
+            "metadata": { "tags": tags },
+            "programs": [{ "command": command, "arguments": [], "retry": 0 }]
+        });
+        create_chain(directory, name, &content.to_string())
+    }
+
+    // Test that tags are cached when a chain is added, and refreshed when the file changes
+    #[test]
+    fn test_tags_are_cached_and_refreshed() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain_path = create_tagged_chain(directory.path(), "cchain_release.json", "true", &["release"]);
+
+        let mut bookmark = create_bookmark(directory.path());
+        bookmark.add_chain_reference(chain_path.to_string_lossy().into_owned()).unwrap();
+        assert_eq!(bookmark.get_chain_references()[0].get_tags(), &vec!["release".to_string()]);
+
+        // Nothing to refresh while the file is unchanged
+        assert!(!bookmark.refresh());
+
+        create_tagged_chain(directory.path(), "cchain_release.json", "true", &["release", "maintenance"]);
+        assert!(bookmark.refresh());
+        assert_eq!(
+            bookmark.get_chain_references()[0].get_tags(),
+            &vec!["release".to_string(), "maintenance".to_string()]
+        );
+    }
+
+    // Test that chains are selected by tag in alphabetical order of the names
+    #[test]
+    fn test_get_chain_references_by_tag() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut bookmark = create_bookmark(directory.path());
+        for (name, tags) in [
+            ("cchain_zeta.json", vec!["maintenance"]),
+            ("cchain_alpha.json", vec!["maintenance", "release"]),
+            ("cchain_beta.json", vec!["release"]),
+        ] {
+            let path = create_tagged_chain(directory.path(), name, "true", &tags);
+            bookmark.add_chain_reference(path.to_string_lossy().into_owned()).unwrap();
+        }
+
+        let names: Vec<String> = bookmark
+            .get_chain_references_by_tag("maintenance")
+            .iter()
+            .map(|chain_reference| chain_reference.get_human_readable_name().trim().to_string())
+            .collect();
+        assert_eq!(names, vec!["Alpha".to_string(), "Zeta".to_string()]);
+        assert!(bookmark.get_chain_references_by_tag("local-dev").is_empty());
+    }
+
+    // Test the order and the summary of running tagged chains with one failing chain
+    #[test]
+    fn test_run_tagged_chains() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut bookmark = create_bookmark(directory.path());
+        for (name, command) in [
+            ("cchain_gamma.json", "true"),
+            ("cchain_alpha.json", "true"),
+            ("cchain_beta.json", "false"),
+        ] {
+            let path = create_tagged_chain(directory.path(), name, command, &["maintenance"]);
+            bookmark.add_chain_reference(path.to_string_lossy().into_owned()).unwrap();
+        }
+
+        let records = run_tagged_chains(&bookmark, "maintenance", &BatchOptions::new(), &HashMap::new()).unwrap();
+        let names: Vec<&str> = records.iter().map(|record| record.name.trim()).collect();
+        assert_eq!(names, vec!["Alpha", "Beta", "Gamma"]);
+        assert_eq!(records[0].outcome, ChainRunOutcome::Succeeded);
+        assert!(matches!(records[1].outcome, ChainRunOutcome::Failed(_)));
+        assert_eq!(records[2].outcome, ChainRunOutcome::Skipped);
+
+        let records = run_tagged_chains(&bookmark, "maintenance", &BatchOptions::new().with_keep_going(true), &HashMap::new()).unwrap();
+        assert_eq!(records[2].outcome, ChainRunOutcome::Succeeded);
+
+        assert!(run_tagged_chains(&bookmark, "release", &BatchOptions::new().with_keep_going(true), &HashMap::new()).is_err());
+    }
+
+    /// Answers prompts in order, and records whether any chain of the
+    /// batch had run when each prompt was asked
+    struct BatchInteractor {
+        answers: Vec<String>,
+        log_path: PathBuf,
+        prompts: Arc<Mutex<Vec<(String, bool)>>>,
+    }
+
+    impl Interactor for BatchInteractor {
+        fn is_interactive(&self) -> bool {
+            true
+        }
+
+        fn prompt(&mut self, message: &str) -> Result<String, anyhow::Error> {
+            self.prompts.lock().unwrap().push((message.to_string(), self.log_path.exists()));
+            Ok(self.answers.remove(0))
+        }
+    }
+
+    fn create_logging_chain(directory: &Path, name: &str, placeholders: &str) -> PathBuf {
+        let content = serde_json::json!({
+            "metadata": { "tags": ["batch"] },
+            "programs": [{
+                "command": "sh",
+                "arguments": ["-c", format!("echo {} >> {}", placeholders, directory.join("log").display())],
+                "retry": 0
+            }]
+        });
+        create_chain(directory, name, &content.to_string())
+    }
+
+    // Test that the inputs of every chain are asked for before the first chain runs
+    #[test]
+    fn test_batch_inputs_collected_up_front() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut bookmark = create_bookmark(directory.path());
+        for (name, placeholders) in [
+            ("cchain_alpha.json", "<<first>>"),
+            ("cchain_beta.json", "<<second>> <<third:on_program_execution>>"),
+        ] {
+            let path = create_logging_chain(directory.path(), name, placeholders);
+            bookmark.add_chain_reference(path.to_string_lossy().into_owned()).unwrap();
+        }
+
+        let prompts: Arc<Mutex<Vec<(String, bool)>>> = Arc::new(Mutex::new(Vec::new()));
+        let interactor = BatchInteractor {
+            // The two inputs of the second chain are reviewed together
+            answers: vec!["1".to_string(), "2".to_string(), "3".to_string(), "c".to_string()],
+            log_path: directory.path().join("log"),
+            prompts: prompts.clone(),
+        };
+        let options: BatchOptions = BatchOptions::new().with_interactor(SharedInteractor::new(interactor));
+        let records = run_tagged_chains(&bookmark, "batch", &options, &HashMap::new()).unwrap();
+
+        assert!(records.iter().all(|record| record.outcome == ChainRunOutcome::Succeeded));
+        let prompts = prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 4);
+        assert!(prompts[0].0.contains("First"));
+        assert!(prompts[1].0.contains("Second"));
+        assert!(prompts[2].0.contains("Third"));
+        assert!(prompts.iter().all(|(_, has_run)| !has_run));
+        assert_eq!(std::fs::read_to_string(directory.path().join("log")).unwrap(), "1\n2 3\n");
+    }
+
+    // Test that chains run in parallel only when none of them prompts
+    #[test]
+    fn test_batch_parallel_refuses_prompting_chains() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut bookmark = create_bookmark(directory.path());
+        for (name, placeholders) in [
+            ("cchain_alpha.json", "alpha"),
+            ("cchain_beta.json", "<<second>>"),
+            ("cchain_gamma.json", "gamma"),
+        ] {
+            let path = create_logging_chain(directory.path(), name, placeholders);
+            bookmark.add_chain_reference(path.to_string_lossy().into_owned()).unwrap();
+        }
+
+        let options: BatchOptions = BatchOptions::new().with_parallel(2);
+        let error: String = run_tagged_chains(&bookmark, "batch", &options, &HashMap::new()).unwrap_err().to_string();
+        assert!(error.contains("Beta (`second`)"));
+        assert!(!error.contains("Alpha"));
+        assert!(!directory.path().join("log").exists());
+
+        let provided_values = HashMap::from([(
+            "second".to_string(),
+            ProvidedValue::from_assignment("second", "beta").unwrap(),
+        )]);
+        let records = run_tagged_chains(&bookmark, "batch", &options, &provided_values).unwrap();
+        assert_eq!(records.len(), 3);
+        assert!(records.iter().all(|record| record.outcome == ChainRunOutcome::Succeeded));
+        let mut lines: Vec<String> = std::fs::read_to_string(directory.path().join("log"))
+            .unwrap()
+            .lines()
+            .map(|line| line.to_string())
+            .collect();
+        lines.sort();
+        assert_eq!(lines, vec!["alpha", "beta", "gamma"]);
+    }
+
+    // Test that an index input is resolved through the bookmark
+    #[test]
+    fn test_resolve_chain_argument_index() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(directory.path(), "cchain_build.json", CHAIN);
+        let mut bookmark = create_bookmark(directory.path());
+        bookmark.add_chain_reference(chain_path.to_string_lossy().into_owned()).unwrap();
+
+        let trace: ChainResolutionTrace = resolve_chain_argument("0", &bookmark);
+        assert_eq!(trace.index, Some(0));
+        assert!(trace.checked_paths.is_empty());
+        assert!(trace.keyword_matches.is_empty());
+        assert_eq!(
+            trace.selection,
+            ChainSelection::Selected { path: bookmark.get_chain_references()[0].get_chain_path_string() }
+        );
+
+        let trace: ChainResolutionTrace = resolve_chain_argument("1", &bookmark);
+        assert!(matches!(trace.selection, ChainSelection::NotFound { .. }));
+    }
+
+    // Test that a path to a chain file is selected without searching the keywords
+    #[test]
+    fn test_resolve_chain_argument_exact_filename() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(directory.path(), "cchain_build.json", CHAIN);
+        let other_path = create_chain(directory.path(), "build.json", CHAIN);
+        let mut bookmark = create_bookmark(directory.path());
+        bookmark.add_chain_reference(chain_path.to_string_lossy().into_owned()).unwrap();
+
+        let input: String = chain_path.to_string_lossy().into_owned();
+        let trace: ChainResolutionTrace = resolve_chain_argument(&input, &bookmark);
+        assert_eq!(trace.index, None);
+        assert_eq!(trace.checked_paths.len(), 1);
+        assert!(trace.checked_paths[0].accepted);
+        assert!(trace.keyword_matches.is_empty());
+        assert_eq!(trace.selection, ChainSelection::Selected { path: input });
+
+        // Explicit paths are accepted regardless of the naming convention
+        let input: String = other_path.to_string_lossy().into_owned();
+        let trace: ChainResolutionTrace = resolve_chain_argument(&input, &bookmark);
+        assert!(trace.checked_paths[0].accepted);
+        assert_eq!(trace.selection, ChainSelection::Selected { path: input });
+
+        let text_path = create_chain(directory.path(), "cchain_notes.txt", CHAIN);
+        let trace: ChainResolutionTrace = resolve_chain_argument(&text_path.to_string_lossy(), &bookmark);
+        assert!(!trace.checked_paths[0].accepted);
+        assert_eq!(trace.checked_paths[0].reason, "is not a `.json` file");
+    }
+
+    // Test that keywords matching several chains are reported with their scores
+    #[test]
+    fn test_resolve_chain_argument_ambiguous_keywords() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut bookmark = create_bookmark(directory.path());
+        for name in ["cchain_deploy_app.json", "cchain_deploy_web.json", "cchain_build.json"] {
+            let path = create_chain(directory.path(), name, CHAIN);
+            bookmark.add_chain_reference(path.to_string_lossy().into_owned()).unwrap();
+        }
+
+        let trace: ChainResolutionTrace = resolve_chain_argument("deploy web", &bookmark);
+        assert_eq!(trace.checked_paths.len(), 1);
+        assert!(!trace.checked_paths[0].accepted);
+
+        let matches: Vec<(String, usize)> = trace
+            .keyword_matches
+            .iter()
+            .map(|keyword_match| (keyword_match.name.clone(), keyword_match.score))
+            .collect();
+        assert_eq!(matches, vec![("Deploy Web".to_string(), 2), ("Deploy App".to_string(), 1)]);
+        assert_eq!(
+            trace.selection,
+            ChainSelection::Ambiguous {
+                candidates: trace.keyword_matches.iter().map(|keyword_match| keyword_match.path.clone()).collect()
+            }
+        );
+
+        let trace: ChainResolutionTrace = resolve_chain_argument("build", &bookmark);
+        assert_eq!(trace.keyword_matches.len(), 1);
+        assert!(matches!(trace.selection, ChainSelection::Selected { .. }));
+    }
+
+    // Test that IDs survive removing other chains, re-adding and saving
+    #[test]
+    fn test_stable_ids_across_add_and_remove() {
+        let directory = tempfile::tempdir().unwrap();
+        let deploy_path = create_chain(directory.path(), "cchain_deploy.json", CHAIN);
+        let build_path = create_chain(directory.path(), "cchain_build.json", CHAIN);
+
+        let mut bookmark = create_bookmark(directory.path());
+        bookmark.add_chain_reference(deploy_path.to_string_lossy().into_owned()).unwrap();
+        bookmark.add_chain_reference(build_path.to_string_lossy().into_owned()).unwrap();
+        let deploy_id: String = bookmark.get_chain_references()[0].get_id().to_string();
+        let build_id: String = bookmark.get_chain_references()[1].get_id().to_string();
+        // IDs are derived with SHA-256, so that they do not change across Rust releases
+        let canonical_path: String = deploy_path.canonicalize().unwrap().to_string_lossy().into_owned();
+        assert_eq!(deploy_id, format!("deploy-{}", &sha256_hex(format!("{}\n0", canonical_path).as_bytes())[..4]));
+        assert_ne!(deploy_id, build_id);
+
+        // The build chain moves to index 0, but keeps its ID
+        bookmark.remove_chain_reference_by_index(0).unwrap();
+        assert_eq!(bookmark.get_index_by_id(&build_id), Some(0));
+        assert_eq!(bookmark.get_index_by_id(&deploy_id), None);
+
+        // Adding the deploy chain again gives its previous ID back
+        bookmark.add_chain_reference(deploy_path.to_string_lossy().into_owned()).unwrap();
+        assert_eq!(bookmark.get_chain_references()[1].get_id(), deploy_id);
+
+        bookmark.save().unwrap();
+        let bookmark = Bookmark::from_path(&directory.path().join(".cchain")).unwrap();
+        assert_eq!(bookmark.get_index_by_id(&build_id), Some(0));
+        assert_eq!(bookmark.get_index_by_id(&deploy_id), Some(1));
+    }
+
+    // Test that an ID that looks like a number takes precedence over the positional index
+    #[test]
+    fn test_stable_id_precedence_over_index() {
+        let directory = tempfile::tempdir().unwrap();
+        let first_path = create_chain(directory.path(), "cchain_first.json", CHAIN);
+        let second_path = create_chain(directory.path(), "cchain_second.json", CHAIN);
+        let bookmark_path = directory.path().join(".cchain");
+        std::fs::write(
+            &bookmark_path,
+            serde_json::json!({
+                "format_version": BOOKMARK_FORMAT_VERSION,
+                "chain_references": [
+                    { "id": "1", "chain_path": first_path },
+                    { "chain_path": second_path }
+                ],
+                "bookmark_path": bookmark_path
+            })
+            .to_string(),
+        )
+        .unwrap();
+
+        // The missing ID is assigned when loading
+        let bookmark = Bookmark::from_path(&bookmark_path).unwrap();
+        assert!(bookmark.get_chain_references()[1].get_id().starts_with("second-"));
+
+        assert_eq!(find_bookmark_index("1", &bookmark), Some(0));
+        assert_eq!(find_bookmark_index("0", &bookmark), Some(0));
+        assert_eq!(find_bookmark_index("2", &bookmark), None);
+
+        let trace: ChainResolutionTrace = resolve_chain_argument("1", &bookmark);
+        assert_eq!(trace.id, Some("1".to_string()));
+        assert_eq!(trace.index, None);
+        assert_eq!(trace.selection, ChainSelection::Selected { path: first_path.to_string_lossy().into_owned() });
+    }
+
+    // Test that a chain not following the naming convention is rejected with the pattern, unless allowed
+    #[test]
+    fn test_bookmark_any_name() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_chain(directory.path(), "deploy.json", CHAIN);
+        let path_string: String = path.to_string_lossy().into_owned();
+        let mut bookmark = create_bookmark(directory.path());
+
+        let error = bookmark.add_chain_reference(path_string.clone()).unwrap_err().to_string();
+        assert!(error.contains("`deploy.json`"));
+        assert!(error.contains("`cchain_*.json`"));
+        assert!(error.contains("--allow-any-name"));
+
+        bookmark.add_chain_reference_with_options(path_string.clone(), true).unwrap();
+        assert!(bookmark.get_chain_references()[0].is_any_name_allowed());
+        assert!(ChainReference::from_path(&path_string, false).is_err());
+        // Conforming names are not marked
+        let conforming_path = create_chain(directory.path(), "cchain_build.json", CHAIN);
+        assert!(!ChainReference::from_path(&conforming_path.to_string_lossy(), true).unwrap().is_any_name_allowed());
+    }
+
+    // Test the offer to bookmark a chain through a conforming copy or link, or as it is
+    #[test]
+    fn test_offer_conforming_name() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_chain(directory.path(), "deploy.json", CHAIN);
+        let conforming_path = directory.path().join("cchain_deploy.json");
+
+        let interactor = SharedInteractor::new(ScriptedInteractor::new(vec!["x".to_string(), "c".to_string()]));
+        let (bookmarked_path, allow_any_name) = offer_conforming_name(&path, &interactor).unwrap();
+        assert_eq!(bookmarked_path, conforming_path.to_string_lossy());
+        assert!(!allow_any_name);
+        assert_eq!(std::fs::read_to_string(&conforming_path).unwrap(), CHAIN);
+
+        let interactor = SharedInteractor::new(ScriptedInteractor::new(vec!["s".to_string()]));
+        let (bookmarked_path, allow_any_name) = offer_conforming_name(&path, &interactor).unwrap();
+        assert_eq!(bookmarked_path, path.to_string_lossy());
+        assert!(allow_any_name);
+
+        #[cfg(unix)]
+        {
+            std::fs::remove_file(&conforming_path).unwrap();
+            let interactor = SharedInteractor::new(ScriptedInteractor::new(vec!["l".to_string()]));
+            offer_conforming_name(&path, &interactor).unwrap();
+            assert!(std::fs::symlink_metadata(&conforming_path).unwrap().file_type().is_symlink());
+        }
+
+        let interactor = SharedInteractor::new(ScriptedInteractor::new(vec!["a".to_string()]));
+        assert!(offer_conforming_name(&path, &interactor).unwrap_err().to_string().contains("`cchain_*.json`"));
+    }
+
+    // Test that a bookmarked chain finds its remedy script and working
+    // directory next to the chain file, wherever cchain runs
+    #[cfg(unix)]
+    #[test]
+    fn test_remedy_paths_relative_to_chain() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = tempfile::tempdir().unwrap();
+        let project = directory.path().join("project");
+        std::fs::create_dir_all(project.join("scripts")).unwrap();
+        let script_path = project.join("scripts").join("fix.sh");
+        std::fs::write(&script_path, "#!/bin/sh\ntouch fixed\n").unwrap();
+        std::fs::set_permissions(&script_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let chain_path = create_chain(
+            &project,
+            "cchain_fix.json",
+            r#"[{
+                "command": "test", "arguments": ["-f", "fixed"], "working_directory": ".", "retry": 0,
+                "failure_handling_options": {
+                    "exit_on_failure": false,
+                    "remedy_command_line": { "command": "./scripts/fix.sh", "arguments": [], "working_directory": "." }
+                }
+            }]"#,
+        );
+        let mut bookmark = create_bookmark(directory.path());
+        bookmark.add_chain_reference(chain_path.to_string_lossy().into_owned()).unwrap();
+
+        let mut chain = read_into_chain("0", &bookmark).unwrap();
+        let mut program = chain.get_programs()[0].lock().unwrap();
+        let remedy = program.get_remedy_command_line().unwrap();
+        assert_eq!(remedy.get_command(), script_path.to_string_lossy());
+        assert_eq!(remedy.get_working_directory(), Some(&project.to_string_lossy().into_owned()));
+        drop(program);
+
+        chain.execute().unwrap();
+        assert!(project.join("fixed").exists());
+    }
+
+    // Test that adding and removing a chain are recorded in the audit log
+    #[test]
+    fn test_audit_log_add_and_remove() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(directory.path(), "cchain_deploy.json", CHAIN);
+        let chain_path_string: String = chain_path.to_string_lossy().into_owned();
+
+        let mut bookmark = create_bookmark(directory.path());
+        bookmark.set_audit_context(
+            AuditContext::from_environment().with_user("alice".to_string()).with_command("cchain add".to_string()),
+        );
+        bookmark.add_chain_reference(chain_path_string.clone()).unwrap();
+        let id: String = bookmark.get_chain_references()[0].get_id().to_string();
+        bookmark.save().unwrap();
+        bookmark.remove_chain_reference_by_index(0).unwrap();
+        bookmark.save().unwrap();
+
+        let entries: Vec<AuditEntry> = bookmark.get_audit_log().read();
+        assert_eq!(bookmark.get_audit_log().get_path(), directory.path().join(AUDIT_FILE_NAME));
+        assert_eq!(
+            entries.iter().map(|entry| entry.operation).collect::<Vec<BookmarkOperation>>(),
+            vec![BookmarkOperation::Add, BookmarkOperation::Remove]
+        );
+        for entry in &entries {
+            assert_eq!(entry.user, "alice");
+            assert_eq!(entry.command.as_deref(), Some("cchain add"));
+            assert_eq!(entry.paths, vec![chain_path_string.clone()]);
+            assert_eq!(entry.ids, vec![id.clone()]);
+            assert!(entry.timestamp > 0);
+        }
+
+        // The hashes chain the states of the bookmark file
+        assert_eq!(entries[0].hash_before, None);
+        assert_eq!(entries[0].hash_after, entries[1].hash_before);
+        let content: Vec<u8> = std::fs::read(directory.path().join(".cchain")).unwrap();
+        assert_eq!(entries[1].hash_after, Some(sha256_hex(&content)));
+
+        // Nothing is recorded for a save without changes
+        bookmark.save().unwrap();
+        assert_eq!(bookmark.get_audit_log().read().len(), 2);
+    }
+
+    // Test that an audit log that cannot be written does not stop saving
+    #[test]
+    fn test_audit_log_failure_does_not_break_save() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(directory.path(), "cchain_deploy.json", CHAIN);
+        // A directory in place of the audit log cannot be appended to
+        std::fs::create_dir(directory.path().join(AUDIT_FILE_NAME)).unwrap();
+
+        let mut bookmark = create_bookmark(directory.path());
+        bookmark.add_chain_reference(chain_path.to_string_lossy().into_owned()).unwrap();
+        bookmark.save().unwrap();
+
+        let bookmark = Bookmark::from_path(&directory.path().join(".cchain")).unwrap();
+        assert_eq!(bookmark.get_chain_references().len(), 1);
+        assert!(bookmark.get_audit_log().read().is_empty());
+    }
+
+    // Test that stored run arguments survive saving and loading, and are left out when absent
+    #[test]
+    fn test_stored_run_arguments_round_trip() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(directory.path(), "cchain_deploy.json", CHAIN);
+        let bookmark_path: PathBuf = directory.path().join(".cchain");
+
+        let mut bookmark = create_bookmark(directory.path());
+        bookmark.add_chain_reference(chain_path.to_string_lossy().into_owned()).unwrap();
+        bookmark.save().unwrap();
+        assert!(!std::fs::read_to_string(&bookmark_path).unwrap().contains("run_arguments"));
+
+        bookmark.set_run_arguments(0, Some("--continue-on-failure --var 'env=prod'".to_string())).unwrap();
+        bookmark.save().unwrap();
+        let mut bookmark = Bookmark::from_path(&bookmark_path).unwrap();
+        assert_eq!(bookmark.get_chain_references()[0].get_run_arguments(), Some("--continue-on-failure --var 'env=prod'"));
+        let operations: Vec<BookmarkOperation> =
+            bookmark.get_audit_log().read().iter().map(|entry| entry.operation).collect();
+        assert_eq!(operations.last(), Some(&BookmarkOperation::Configure));
+
+        bookmark.set_run_arguments(0, None).unwrap();
+        bookmark.save().unwrap();
+        let bookmark = Bookmark::from_path(&bookmark_path).unwrap();
+        assert_eq!(bookmark.get_chain_references()[0].get_run_arguments(), None);
+    }
+
+    fn create_saved_bookmark(directory: &Path, count: usize) -> Bookmark {
+        let mut bookmark = create_bookmark(directory);
+        for index in 0..count {
+            let chain_path = create_chain(directory, &format!("cchain_{}.json", index), CHAIN);
+            bookmark.add_chain_reference(chain_path.to_string_lossy().into_owned()).unwrap();
+        }
+        bookmark.save().unwrap();
+
+        Bookmark::from_path(&directory.join(".cchain")).unwrap()
+    }
+
+    // Test that removals at the start, in the middle and at the end map the old indices to the new ones
+    #[test]
+    fn test_index_remapping() {
+        for (removed, expected) in [
+            (vec![0], vec![None, Some(0), Some(1), Some(2)]),
+            (vec![1], vec![Some(0), None, Some(1), Some(2)]),
+            (vec![3], vec![Some(0), Some(1), Some(2), None]),
+            (vec![2, 0], vec![None, Some(0), None, Some(1)]),
+        ] {
+            let directory = tempfile::tempdir().unwrap();
+            let mut bookmark = create_saved_bookmark(directory.path(), 4);
+            assert!(bookmark.get_index_remapping().is_unchanged());
+            for index in &removed {
+                bookmark.remove_chain_reference_by_index(*index).unwrap();
+            }
+
+            let index_remapping = bookmark.get_index_remapping();
+            let new_indices: Vec<Option<usize>> = (0..4).map(|index| index_remapping.get_new_index(index)).collect();
+            assert_eq!(new_indices, expected, "removing {:?}", removed);
+            assert_eq!(index_remapping.get_new_index(4), None);
+
+            // Saving starts the remapping over
+            bookmark.save().unwrap();
+            assert!(bookmark.get_index_remapping().is_unchanged());
+        }
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut bookmark = create_saved_bookmark(directory.path(), 3);
+        bookmark.remove_chain_reference_by_index(0).unwrap();
+        let index_remapping = bookmark.get_index_remapping();
+        assert_eq!(index_remapping.get_changes(), vec![(0, None), (1, Some(0)), (2, Some(1))]);
+        assert_eq!(index_remapping.to_string(), "0 removed, 1 → 0, 2 → 1");
+
+        // The audit log records the remapping with the removal
+        bookmark.save().unwrap();
+        let entry: AuditEntry = bookmark.get_audit_log().read().pop().unwrap();
+        assert_eq!(entry.operation, BookmarkOperation::Remove);
+        assert_eq!(entry.index_remapping.into_iter().collect::<Vec<_>>(), vec![(0, None), (1, Some(0)), (2, Some(1))]);
+    }
+
+    // Test that stored run arguments follow their chain when a clean moves it
+    #[test]
+    fn test_run_arguments_follow_chain_across_clean() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut bookmark = create_saved_bookmark(directory.path(), 3);
+        bookmark.set_run_arguments(2, Some("--continue-on-failure".to_string())).unwrap();
+        bookmark.save().unwrap();
+
+        std::fs::remove_file(directory.path().join("cchain_0.json")).unwrap();
+        let mut bookmark = Bookmark::from_path(&directory.path().join(".cchain")).unwrap();
+        bookmark.clean(false).unwrap();
+        let new_index: usize = bookmark.get_index_remapping().get_new_index(2).unwrap();
+        assert_eq!(new_index, 1);
+        bookmark.save().unwrap();
+
+        let bookmark = Bookmark::from_path(&directory.path().join(".cchain")).unwrap();
+        let chain_reference = &bookmark.get_chain_references()[new_index];
+        assert!(chain_reference.get_chain_path_string().ends_with("cchain_2.json"));
+        assert_eq!(chain_reference.get_run_arguments(), Some("--continue-on-failure"));
+        assert_eq!(bookmark.get_chain_references()[0].get_run_arguments(), None);
+        let entry: AuditEntry = bookmark.get_audit_log().read().pop().unwrap();
+        assert_eq!(entry.operation, BookmarkOperation::Clean);
+        assert_eq!(entry.index_remapping.get(&2), Some(&Some(1)));
+    }
+
+    // Test that bookmarks saved by concurrent processes keep each other's chains
+    #[test]
+    fn test_concurrent_adds_keep_all_chains() {
+        let directory = tempfile::tempdir().unwrap();
+        let bookmark_path: PathBuf = directory.path().join(".cchain");
+        let chain_paths: Vec<PathBuf> = (0..8)
+            .map(|index| create_chain(directory.path(), &format!("cchain_chain{}.json", index), CHAIN))
+            .collect();
+
+        let handles: Vec<std::thread::JoinHandle<()>> = chain_paths
+            .iter()
+            .map(|chain_path| {
+                let bookmark_path = bookmark_path.clone();
+                let chain_path = chain_path.to_string_lossy().into_owned();
+                std::thread::spawn(move || {
+                    // Every thread reads the bookmark before any of them saves
+                    let mut bookmark = Bookmark::from_path(&bookmark_path).unwrap();
+                    std::thread::sleep(std::time::Duration::from_millis(50));
+                    bookmark.add_chain_reference(chain_path).unwrap();
+                    bookmark.save().unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let mut bookmark = Bookmark::from_path(&bookmark_path).unwrap();
+        assert_eq!(bookmark.get_chain_references().len(), 8);
+        let ids: std::collections::HashSet<&str> =
+            bookmark.get_chain_references().iter().map(|chain_reference| chain_reference.get_id()).collect();
+        assert_eq!(ids.len(), 8);
+
+        // A chain removed here is not brought back by the merge
+        let mut other = Bookmark::from_path(&bookmark_path).unwrap();
+        bookmark.remove_chain_reference_by_index(0).unwrap();
+        bookmark.save().unwrap();
+        let extra_path = create_chain(directory.path(), "cchain_extra.json", CHAIN);
+        other.add_chain_reference(extra_path.to_string_lossy().into_owned()).unwrap();
+        other.save().unwrap();
+        let bookmark = Bookmark::from_path(&bookmark_path).unwrap();
+        assert_eq!(bookmark.get_chain_references().len(), 9);
+    }
+
+    // Test that a lock held too long fails with the file and the holder
+    #[cfg(unix)]
+    #[test]
+    fn test_file_lock_timeout() {
+        let directory = tempfile::tempdir().unwrap();
+        let path: PathBuf = directory.path().join("bookmark.json");
+        let lock = FileLock::acquire(&path).unwrap();
+        assert_eq!(lock.get_lock_path(), directory.path().join("bookmark.json.lock"));
+
+        let error = FileLock::acquire_with_timeout(&path, std::time::Duration::from_millis(100)).unwrap_err().to_string();
+        assert!(error.contains(&path.display().to_string()), "{}", error);
+        assert!(error.contains(&format!("locked by process {}", std::process::id())), "{}", error);
+
+        drop(lock);
+        assert!(FileLock::acquire_with_timeout(&path, std::time::Duration::from_millis(100)).is_ok());
+    }
+}
@@ -1,7 +1,7 @@
 #[cfg(test)]
 mod tests {
     use std::io::Write;
-    use cchain::core::{chain::Chain, traits::Execution};
+    use cchain::{commons::{errors::ChainError, interaction::{Interactor, ScriptedInteractor, SharedInteractor, UnattendedInteractor}, utility::{collect_provided_values, run_chain_with_retries}}, core::{builtin::SECRET_MASK, chain_retry::ChainRetryOptions, status::RunOutcome, chain::{Chain, ChainExecutionStatus, OutputAvailability, SuppliedValueFinding}, debug::DebugShellOptions, options::{FailurePolicy, ProgramSelection}, traits::Execution}, marker::{history::ValueHistory, presets::VariablePresets, reference::TrackPath}, variable::{VariableGroupControl, VariableSource}};
     use tempfile::NamedTempFile;
 
     // Test that Chain can be created from a valid JSON file
@@ -94,6 +94,1778 @@ mod tests {
         let result = chain.execute();
 
         assert!(result.is_ok());
-        assert_eq!(chain.get_failed_program_execution_number(), 1);
+        assert_eq!(chain.get_failed_program_indices().len(), 1);
     }
-}
\ No newline at end of file
+
+    const TYPED_CHAIN: &str = r#"{
+        "metadata": {
+            "variables": {
+                "workers": { "type": "integer", "min": 1, "max": 64 },
+                "verbose": { "type": "boolean" }
+            }
+        },
+        "programs": [
+            {
+                "command": "echo",
+                "arguments": ["--workers", "<<workers * 2>>", "<<verbose ? --verbose : >>", "<<name>>"],
+                "retry": 0
+            }
+        ]
+    }"#;
+
+    // Test that typed variables are evaluated in expressions, and that a
+    // false conditional flag produces no argument at all
+    #[test]
+    fn test_expressions_injected_with_typed_variables() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", TYPED_CHAIN).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.update_value("<<workers>>", "3".to_string());
+        chain.update_value("<<verbose>>", "false".to_string());
+        chain.update_value("<<name>>", "a * b".to_string());
+        chain.insert_variable(0).unwrap();
+
+        let program = chain.get_programs()[0].clone();
+        let mut program = program.lock().unwrap();
+        assert_eq!(
+            program.get_command_line().get_arguments(),
+            &vec!["--workers".to_string(), "6".to_string(), "a * b".to_string()]
+        );
+    }
+
+    #[test]
+    fn test_expressions_with_true_conditional_flag() {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", TYPED_CHAIN).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.update_value("<<workers>>", "1".to_string());
+        chain.update_value("<<verbose>>", "yes".to_string());
+        chain.update_value("<<name>>", "x".to_string());
+        chain.insert_variable(0).unwrap();
+
+        let program = chain.get_programs()[0].clone();
+        let mut program = program.lock().unwrap();
+        assert_eq!(
+            program.get_command_line().get_arguments(),
+            &vec!["--workers".to_string(), "2".to_string(), "--verbose".to_string(), "x".to_string()]
+        );
+    }
+
+    // Test that expressions over undeclared variables fail the check
+    #[test]
+    fn test_validate_syntax_rejects_untyped_expression() {
+        let programs = r#"{
+            "metadata": { "variables": { "workers": { "type": "integer" } } },
+            "programs": [
+                { "command": "echo", "arguments": ["<<workers * count>>"], "retry": 0 }
+            ]
+        }"#;
+
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        assert!(chain.validate_syntax().is_err());
+    }
+
+    // Test that invalid declarations are rejected on load
+    #[test]
+    fn test_invalid_declaration_rejected() {
+        let programs = r#"{
+            "metadata": { "variables": { "workers": { "type": "integer", "min": 5, "max": 1 } } },
+            "programs": [
+                { "command": "echo", "arguments": ["<<workers>>"], "retry": 0 }
+            ]
+        }"#;
+
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        assert!(Chain::from_file(temp_file.path().to_str().unwrap()).is_err());
+    }
+
+    /// A chain that starts a `sh` service writing its pid to `pidfile`,
+    /// followed by the given program
+    #[cfg(unix)]
+    fn create_service_chain(pidfile: &str, next_command: &str) -> String {
+        format!(
+            r#"[
+                {{
+                    "command": "sh",
+                    "arguments": ["-c", "echo $$ > {pidfile}; sleep 30"],
+                    "service": true,
+                    "ready_check": {{
+                        "command": "test",
+                        "arguments": ["-s", "{pidfile}"],
+                        "interval_ms": 50
+                    }},
+                    "retry": 0
+                }},
+                {{
+                    "command": "{next_command}",
+                    "arguments": [],
+                    "retry": 0
+                }}
+            ]"#
+        )
+    }
+
+    #[cfg(unix)]
+    fn is_process_alive(pid: &str) -> bool {
+        std::process::Command::new("kill")
+            .args(["-0", pid])
+            .stderr(std::process::Stdio::null())
+            .status()
+            .unwrap()
+            .success()
+    }
+
+    // Test that services are terminated once the chain finishes
+    #[cfg(unix)]
+    #[test]
+    fn test_service_terminated_after_chain() {
+        let directory = tempfile::tempdir().unwrap();
+        let pidfile = directory.path().join("service.pid");
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", create_service_chain(pidfile.to_str().unwrap(), "true")).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        assert!(chain.execute().is_ok());
+
+        let pid = std::fs::read_to_string(&pidfile).unwrap();
+        assert!(!is_process_alive(pid.trim()));
+    }
+
+    // Test that services are terminated when a later program fails
+    #[cfg(unix)]
+    #[test]
+    fn test_service_terminated_after_failure() {
+        let directory = tempfile::tempdir().unwrap();
+        let pidfile = directory.path().join("service.pid");
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", create_service_chain(pidfile.to_str().unwrap(), "false")).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        assert!(chain.execute().is_err());
+        assert_eq!(chain.get_failed_program_indices().len(), 1);
+
+        let pid = std::fs::read_to_string(&pidfile).unwrap();
+        assert!(!is_process_alive(pid.trim()));
+    }
+
+    // Test that only the output of the successful probe is stored, with the capture filter applied
+    #[cfg(unix)]
+    #[test]
+    fn test_ready_check_stores_probe_output() {
+        let directory = tempfile::tempdir().unwrap();
+        let counter = directory.path().join("counter");
+        let stored = directory.path().join("stored");
+        let probe = format!(
+            "n=$(($(cat {0} 2>/dev/null || echo 0) + 1)); echo $n > {0}; echo \"probe $n\"; echo noise; test $n -ge 3",
+            counter.display()
+        );
+        let programs = serde_json::json!([
+            {
+                "command": "sleep", "arguments": ["30"], "service": true, "retry": 0,
+                "ready_check": { "command": "sh", "arguments": ["-c", probe], "interval_ms": 20, "store_output": true },
+                "stdout_stored_to": "<<health>>",
+                "stdout_storage_options": { "without_newline_characters": true, "capture_filter": "^probe" }
+            },
+            { "command": "sh", "arguments": ["-c", format!("printf %s \"$0\" > {}", stored.display()), "<<health>>"], "retry": 0 }
+        ]);
+        let mut chain = Chain::from_json_str(&programs.to_string(), "probe").unwrap();
+        chain.execute().unwrap();
+        assert_eq!(std::fs::read_to_string(&stored).unwrap(), "probe 3");
+
+        // The last failed probe explains the timeout
+        let programs = serde_json::json!([{
+            "command": "sleep", "arguments": ["30"], "service": true, "retry": 0,
+            "ready_check": { "command": "sh", "arguments": ["-c", "echo still starting; false"], "interval_ms": 200, "timeout_seconds": 1 }
+        }]);
+        let mut chain = Chain::from_json_str(&programs.to_string(), "probe").unwrap();
+        let error = chain.execute().unwrap_err().to_string();
+        assert!(error.contains("is not ready after 1 seconds. The last probe (attempt #"), "{}", error);
+        assert!(error.contains("still starting"), "{}", error);
+    }
+
+    // Test that the outputs of the failed probes are logged, without repeating the same output
+    #[cfg(unix)]
+    #[test]
+    fn test_ready_check_logs_failed_probes() {
+        let directory = tempfile::tempdir().unwrap();
+        let counter = directory.path().join("counter");
+        let probe = format!(
+            "n=$(($(cat {0} 2>/dev/null || echo 0) + 1)); echo $n > {0}; if [ $n -ge 2 ]; then echo waiting; fi; test $n -ge 4",
+            counter.display()
+        );
+        let path = directory.path().join("cchain_probe.json");
+        let programs = serde_json::json!([
+            {
+                "command": "sleep", "arguments": ["30"], "service": true, "retry": 0,
+                "ready_check": { "command": "sh", "arguments": ["-c", probe], "interval_ms": 20 }
+            },
+            { "command": "echo", "arguments": ["done"], "retry": 0 }
+        ]);
+        std::fs::write(&path, programs.to_string()).unwrap();
+        let output = assert_cmd::Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", directory.path())
+            .env_remove("CCHAIN_HOME")
+            .arg("run")
+            .arg(&path)
+            .write_stdin("")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains("is not ready yet (probe attempt #1)\n"), "{}", stdout);
+        assert!(stdout.contains("is not ready yet (probe attempt #2): waiting"), "{}", stdout);
+        assert!(!stdout.contains("probe attempt #3"), "{}", stdout);
+    }
+
+    // Test that `--var` overrides `--var-file`, and a multi-line value reaches the argument intact
+    #[cfg(unix)]
+    #[test]
+    fn test_provided_values_flow_into_arguments() {
+        let directory = tempfile::tempdir().unwrap();
+        let output_path = directory.path().join("output.txt");
+        let payload_path = directory.path().join("payload.txt");
+        let payload = "-----BEGIN CERTIFICATE-----\nMIIB\n  indented line\n-----END CERTIFICATE-----\n";
+        std::fs::write(&payload_path, payload).unwrap();
+        let variable_file_path = directory.path().join("vars.env");
+        std::fs::write(&variable_file_path, "payload=from the file\nsuffix=done\n").unwrap();
+
+        let programs = serde_json::json!([{
+            "command": "sh",
+            "arguments": ["-c", "printf '%s|%s' \"$1\" \"$2\" > \"$3\"", "sh", "<<payload>>", "<<suffix>>", output_path],
+            "retry": 0
+        }]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        let provided_values = collect_provided_values(
+            &[("payload".to_string(), format!("@{}", payload_path.display()))],
+            &[variable_file_path.to_string_lossy().into_owned()],
+        )
+        .unwrap();
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.set_provided_values(provided_values);
+        chain.execute().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(&output_path).unwrap(),
+            format!("{}|done", payload)
+        );
+    }
+
+    // Test that provided values are validated against the declarations
+    #[test]
+    fn test_provided_value_is_validated() {
+        let chain_file = r#"{
+            "metadata": { "variables": { "workers": { "type": "integer", "min": 1 } } },
+            "programs": [{ "command": "echo", "arguments": ["<<workers>>"], "retry": 0 }]
+        }"#;
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain_file).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.set_provided_values(
+            collect_provided_values(&[("workers".to_string(), "zero".to_string())], &[]).unwrap()
+        );
+        let error = chain.execute().unwrap_err().to_string();
+        assert!(error.contains("--var workers"));
+    }
+
+    fn create_anonymous_prompt_chain(output_path: &std::path::Path) -> Chain {
+        let programs = serde_json::json!([
+            {
+                "command": "sh",
+                "arguments": ["-c", "printf '%s|%s|%s' \"$1\" \"$2\" \"$3\" > \"$4\"", "sh", "<<name>>", "<<?Commit message>>", "<<?Commit message>>", output_path],
+                "retry": 0
+            },
+            { "command": "echo", "arguments": ["<<?Tag>>"], "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        Chain::from_file(temp_file.path().to_str().unwrap()).unwrap()
+    }
+
+    // Test that anonymous prompts are asked at their programs, in order, and identical texts stay distinct
+    #[cfg(unix)]
+    #[test]
+    fn test_anonymous_prompts_order() {
+        let directory = tempfile::tempdir().unwrap();
+        let output_path = directory.path().join("output.txt");
+        let mut chain = create_anonymous_prompt_chain(&output_path);
+        chain.validate_syntax().unwrap();
+
+        let interactor = ScriptedInteractor::new(
+            ["alice", "first", "second", "v1"].iter().map(|answer| answer.to_string()).collect()
+        );
+        let prompts = interactor.get_prompts();
+        chain.set_interactor(SharedInteractor::new(interactor));
+        chain.execute().unwrap();
+
+        assert_eq!(
+            *prompts.lock().unwrap(),
+            vec!["Please input a value for Name:", "Commit message:", "Commit message:", "Tag:"]
+        );
+        assert_eq!(std::fs::read_to_string(&output_path).unwrap(), "alice|first|second");
+    }
+
+    // Test that anonymous prompts are shown with their texts in dry runs, and listed when input is closed
+    #[cfg(unix)]
+    #[test]
+    fn test_anonymous_prompts_without_input() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_anonymous_prompt_chain(&directory.path().join("output.txt"));
+        assert_eq!(chain.get_dry_run_command_lines()[1], "Program #1: echo '<<?Tag>>'");
+
+        chain.set_interactor(SharedInteractor::new(ScriptedInteractor::new(vec!["alice".to_string(), "first".to_string()])));
+        let error = chain.execute().unwrap_err().to_string();
+        assert!(error.ends_with("`Commit message`, `Tag`"));
+    }
+
+    fn create_declared_chain() -> Chain {
+        let chain_file = r#"{
+            "metadata": { "variables": {
+                "Host": { "description": "The server to deploy to" },
+                "prot": { "example": "8080" }
+            } },
+            "programs": [
+                { "command": "echo", "arguments": ["<<host>>", "<<port>>"], "stdout_stored_to": "<<output>>", "retry": 0 },
+                { "command": "echo", "arguments": ["<<output>>", "<<?Confirm>>"], "retry": 0 }
+            ]
+        }"#;
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain_file).unwrap();
+
+        Chain::from_file(temp_file.path().to_str().unwrap()).unwrap()
+    }
+
+    // Test that declarations are matched on normalized names, and that unused declarations are reported
+    #[test]
+    fn test_unused_declarations() {
+        let mut chain = create_declared_chain();
+        assert_eq!(chain.get_unused_declarations(), vec!["prot".to_string()]);
+        // Unused declarations are only warned about
+        chain.validate_syntax().unwrap();
+    }
+
+    // Test that undeclared placeholders fail the check only when declarations are required
+    #[test]
+    fn test_undeclared_variables() {
+        let mut chain = create_declared_chain();
+        assert_eq!(chain.get_undeclared_variables(), vec!["port".to_string()]);
+
+        chain.set_require_declarations(true);
+        assert!(chain.validate_syntax().is_err());
+    }
+
+    // Test that a bare `stdout_stored_to` is normalized and connects to the placeholders using it
+    #[cfg(unix)]
+    #[test]
+    fn test_stdout_stored_to_normalization() {
+        let programs = serde_json::json!([
+            { "command": "echo", "arguments": ["-n", "main"], "stdout_stored_to": "branch", "retry": 0 },
+            { "command": "echo", "arguments": ["-n", "<<branch>>"], "stdout_stored_to": "<<copy>>", "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        assert_eq!(
+            chain.get_programs()[0].lock().unwrap().get_awaitable_variable(),
+            &Some("<<branch>>".to_string())
+        );
+        chain.set_interactor(SharedInteractor::new(UnattendedInteractor));
+        let results = chain.execute().unwrap();
+        let provenances = results[0].get_variable_provenances();
+        let copy = provenances.iter().find(|provenance| provenance.name == "copy").unwrap();
+        assert_eq!(copy.value, "main");
+
+        // A qualifier fails the loading, naming the program
+        let programs = serde_json::json!([
+            { "command": "echo", "arguments": ["main"], "retry": 0 },
+            { "command": "git", "arguments": ["branch"], "stdout_stored_to": "<<branch:on_program_execution>>", "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+        let error = Chain::from_file(temp_file.path().to_str().unwrap()).err().unwrap();
+        assert!(matches!(error.downcast_ref::<ChainError>(), Some(ChainError::Validation(_))));
+        assert!(error.to_string().starts_with("Program #1: `stdout_stored_to` must be exactly one placeholder"), "{}", error);
+    }
+
+    // Test that each value is traced to the prompt, the `--var` flag or the program that stored it
+    #[cfg(unix)]
+    #[test]
+    fn test_variable_provenance() {
+        let programs = serde_json::json!([
+            { "command": "echo", "arguments": ["-n", "<<tag>>"], "stdout_stored_to": "<<output>>", "retry": 0 },
+            { "command": "echo", "arguments": ["<<name>>", "<<output>>"], "retry": 0 },
+            { "command": "echo", "arguments": ["-n", "v2"], "stdout_stored_to": "<<tag>>", "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.set_provided_values(collect_provided_values(&[("tag".to_string(), "v1".to_string())], &[]).unwrap());
+        chain.set_interactor(SharedInteractor::new(ScriptedInteractor::new(vec!["alice".to_string()])));
+        let results = chain.execute().unwrap();
+
+        let provenances = results[0].get_variable_provenances();
+        let find = |name: &str| provenances.iter().find(|provenance| provenance.name == name).unwrap();
+        assert_eq!(find("name").value, "alice");
+        assert_eq!(find("name").get_source(), &VariableSource::Prompt);
+        assert_eq!(find("output").value, "v1");
+        assert_eq!(find("output").get_source(), &VariableSource::ProgramOutput { program_index: 0 });
+        // The overwritten value keeps the previous source
+        assert_eq!(find("tag").value, "v2");
+        assert_eq!(
+            find("tag").sources,
+            vec![
+                VariableSource::Provided { source: "--var tag".to_string() },
+                VariableSource::ProgramOutput { program_index: 2 },
+            ]
+        );
+    }
+
+    // Test that the provenances shown with `--explain-variables` mask secrets
+    #[test]
+    fn test_masked_variable_provenance() {
+        let programs = serde_json::json!([
+            { "command": "echo", "arguments": ["-n", "<<api_token>>:<<user>>"], "stdout_stored_to": "<<header>>", "retry": 0 },
+            { "command": "echo", "arguments": ["<<header>>"], "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.set_provided_values(
+            collect_provided_values(&[("api_token".to_string(), "hunter2".to_string()), ("user".to_string(), "alice".to_string())], &[])
+                .unwrap(),
+        );
+        chain.execute().unwrap();
+
+        let provenances = chain.get_masked_variable_provenances();
+        let find = |name: &str| provenances.iter().find(|provenance| provenance.name == name).unwrap().value.clone();
+        assert_eq!(find("api_token"), "********");
+        assert_eq!(find("user"), "alice");
+        assert_eq!(find("header"), "********:alice");
+        // The values themselves are kept for the history
+        assert!(chain.get_variable_provenances().iter().any(|provenance| provenance.value == "hunter2"));
+
+        // The output kept after the run is masked, and the programs are
+        // fingerprinted as written
+        assert_eq!(chain.mask_secret_values("denied for hunter2"), "denied for ********");
+        assert_eq!(chain.get_program_template(0), Some("echo -n '<<api_token>>:<<user>>'"));
+    }
+
+    // Test that the working directory and the environment overrides take variables
+    #[cfg(unix)]
+    #[test]
+    fn test_working_directory_variables() {
+        let directory = tempfile::tempdir().unwrap();
+        let repo_path: String = directory.path().canonicalize().unwrap().display().to_string();
+        let chain_json = serde_json::json!([
+            {
+                "command": "sh",
+                "arguments": ["-c", "printf '%s:%s' \"$(pwd -P)\" \"$REPO_NAME\""],
+                "working_directory": "<<repo_path>>",
+                "environment_variables_override": { "REPO_NAME": "<<repo_name>>" },
+                "stdout_stored_to": "<<output>>",
+                "retry": 0
+            },
+            { "command": "echo", "arguments": ["<<output>>"], "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain_json).unwrap();
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        let mut names: Vec<String> = chain.get_variable_completions().into_iter().map(|completion| completion.name).collect();
+        names.sort();
+        assert_eq!(names, vec!["repo_name", "repo_path"]);
+
+        chain.validate_syntax().unwrap();
+
+        // Uninitialized variables there fail the check like in the
+        // arguments, with or without declarations
+        let unstored_json = serde_json::json!([
+            { "command": "pwd", "arguments": [], "working_directory": "<<repo_path:on_program_execution>>", "retry": 0 }
+        ]);
+        let mut unstored_chain = Chain::from_json_str(&unstored_json.to_string(), "unstored").unwrap();
+        assert!(unstored_chain.validate_syntax().is_err());
+
+        chain.set_provided_values(
+            collect_provided_values(
+                &[("repo_path".to_string(), repo_path.clone()), ("repo_name".to_string(), "cchain".to_string())],
+                &[],
+            )
+            .unwrap(),
+        );
+        let results = chain.execute().unwrap();
+        let provenances = results[0].get_variable_provenances();
+        let output = provenances.iter().find(|provenance| provenance.name == "output").unwrap();
+        assert_eq!(output.value, format!("{}:cchain", repo_path));
+    }
+
+    // Test that a chain with custom delimiters keeps `<<` and `>>` in its
+    // arguments, and one with the defaults escapes them with `<<<<`
+    #[test]
+    fn test_variable_delimiters() {
+        let chain_json = serde_json::json!({
+            "metadata": { "variable_delimiters": { "open": "[[", "close": "]]" } },
+            "programs": [
+                { "command": "echo", "arguments": ["-n", "cat <<EOF >> [[file]]", "[[[[literal]]"], "stdout_stored_to": "[[output]]", "retry": 0 },
+                { "command": "echo", "arguments": ["[[output]]"], "retry": 0 }
+            ]
+        });
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain_json).unwrap();
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        let names: Vec<String> = chain.get_variable_completions().into_iter().map(|completion| completion.name).collect();
+        assert_eq!(names, vec!["file"]);
+        assert!(chain.validate_syntax().is_ok());
+        chain.set_provided_values(collect_provided_values(&[("file".to_string(), "notes.txt".to_string())], &[]).unwrap());
+        let results = chain.execute().unwrap();
+        let provenances = results[0].get_variable_provenances();
+        let output = provenances.iter().find(|provenance| provenance.name == "output").unwrap();
+        assert_eq!(output.value, "cat <<EOF >> notes.txt [[literal]]");
+
+        let chain_json = serde_json::json!([
+            { "command": "echo", "arguments": ["-n", "<<<<EOF <<name>>"], "stdout_stored_to": "<<output>>", "retry": 0 },
+            { "command": "echo", "arguments": ["<<output>>"], "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain_json).unwrap();
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.set_provided_values(collect_provided_values(&[("name".to_string(), "x".to_string())], &[]).unwrap());
+        let results = chain.execute().unwrap();
+        let provenances = results[0].get_variable_provenances();
+        assert_eq!(provenances.iter().find(|provenance| provenance.name == "output").unwrap().value, "<<EOF x");
+
+        // Values are put in as they are, even with `<<<<` or placeholders in them
+        let chain_json = serde_json::json!([
+            { "command": "echo", "arguments": ["-n", "<<first>>|<<second>>|<<<<on"], "stdout_stored_to": "<<output>>", "retry": 0 }
+        ]);
+        let mut chain = Chain::from_json_str(&chain_json.to_string(), "inline").unwrap();
+        chain.set_provided_values(
+            collect_provided_values(
+                &[("first".to_string(), "a<<<<b <<second>>".to_string()), ("second".to_string(), "<<flag ? x : y>>".to_string())],
+                &[],
+            )
+            .unwrap(),
+        );
+        let results = chain.execute().unwrap();
+        let provenances = results[0].get_variable_provenances();
+        assert_eq!(
+            provenances.iter().find(|provenance| provenance.name == "output").unwrap().value,
+            "a<<<<b <<second>>|<<flag ? x : y>>|<<on"
+        );
+
+        // Delimiters that cannot be told apart are rejected
+        let chain_json = serde_json::json!({
+            "metadata": { "variable_delimiters": { "open": "%%", "close": "%" } },
+            "programs": [{ "command": "echo", "arguments": ["%%a%"], "retry": 0 }]
+        });
+        let error = Chain::from_json_str(&chain_json.to_string(), "inline").err().unwrap();
+        assert!(error.to_string().contains("cannot be the same or contain each other"));
+    }
+
+    fn create_startup_prompt_chain(output_path: &std::path::Path) -> Chain {
+        let programs = serde_json::json!([{
+            "command": "sh",
+            "arguments": ["-c", "printf '%s|%s' \"$1\" \"$2\" > \"$3\"", "sh", "<<host>>", "<<port>>", output_path],
+            "retry": 0
+        }]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        Chain::from_file(temp_file.path().to_str().unwrap()).unwrap()
+    }
+
+    // Test that a value edited at the review is the one put in place
+    #[cfg(unix)]
+    #[test]
+    fn test_startup_prompt_review_edit() {
+        let directory = tempfile::tempdir().unwrap();
+        let output_path = directory.path().join("output.txt");
+        let mut chain = create_startup_prompt_chain(&output_path);
+
+        let interactor = ScriptedInteractor::new(
+            ["exmaple.com", "8080", "e", "1", "example.com", "c"].iter().map(|answer| answer.to_string()).collect()
+        );
+        let prompts = interactor.get_prompts();
+        chain.set_interactor(SharedInteractor::new(interactor));
+        chain.execute().unwrap();
+
+        assert_eq!(
+            *prompts.lock().unwrap(),
+            vec![
+                "Please input a value for Host:",
+                "Please input a value for Port:",
+                "Run with these values? [c]onfirm/[e]dit:",
+                "Number of the value to edit:",
+                "Please input a value for Host:",
+                "Run with these values? [c]onfirm/[e]dit:",
+            ]
+        );
+        assert_eq!(std::fs::read_to_string(&output_path).unwrap(), "example.com|8080");
+    }
+
+    // Test that the review is skipped when asked to
+    #[cfg(unix)]
+    #[test]
+    fn test_startup_prompt_review_skipped() {
+        let directory = tempfile::tempdir().unwrap();
+        let output_path = directory.path().join("output.txt");
+        let mut chain = create_startup_prompt_chain(&output_path);
+        chain.set_skip_review(true);
+        chain.set_interactor(SharedInteractor::new(ScriptedInteractor::new(vec!["example.com".to_string(), "8080".to_string()])));
+        chain.execute().unwrap();
+
+        assert_eq!(std::fs::read_to_string(&output_path).unwrap(), "example.com|8080");
+    }
+
+    /// An interactor without a user, like a run in CI
+    struct NoInputInteractor;
+
+    impl Interactor for NoInputInteractor {
+        fn is_interactive(&self) -> bool {
+            false
+        }
+
+        fn prompt(&mut self, message: &str) -> Result<String, anyhow::Error> {
+            Err(anyhow::anyhow!("No input for `{}`", message))
+        }
+    }
+
+    /// Write a chain printing three variables, with a `.cchain.env` next to it
+    fn create_adjacent_env_chain(directory: &std::path::Path, load_adjacent_env: bool) -> Chain {
+        let programs = serde_json::json!({
+            "metadata": { "load_adjacent_env": load_adjacent_env },
+            "programs": [{
+                "command": "sh",
+                "arguments": [
+                    "-c", "printf '%s|%s|%s' \"$1\" \"$2\" \"$3\" > \"$4\"", "sh",
+                    "<<registry>>", "<<project>>", "<<owner>>", directory.join("output.txt")
+                ],
+                "retry": 0
+            }]
+        });
+        let chain_path = directory.join("cchain_env.json");
+        std::fs::write(&chain_path, programs.to_string()).unwrap();
+        std::fs::write(
+            directory.join(".cchain.env"),
+            "# Defaults of the project\nregistry=registry.example.com\nproject=\"slug\"\nowner=alice\n",
+        )
+        .unwrap();
+
+        let mut chain = Chain::from_file(chain_path.to_str().unwrap()).unwrap();
+        chain.set_skip_review(true);
+        chain.set_provided_values(collect_provided_values(&[("project".to_string(), "cli".to_string())], &[]).unwrap());
+        chain
+    }
+
+    // Test that `.cchain.env` values are the defaults at the prompts, below `--var` and the answers
+    #[cfg(unix)]
+    #[test]
+    fn test_adjacent_env_precedence() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_adjacent_env_chain(directory.path(), true);
+        let interactor = ScriptedInteractor::new(vec!["".to_string(), "bob".to_string()]);
+        let prompts = interactor.get_prompts();
+        chain.set_interactor(SharedInteractor::new(interactor));
+        chain.execute().unwrap();
+
+        assert_eq!(
+            *prompts.lock().unwrap(),
+            vec![
+                "Please input a value for Registry: [registry.example.com, from .cchain.env]",
+                "Please input a value for Owner: [alice, from .cchain.env]",
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(directory.path().join("output.txt")).unwrap(),
+            "registry.example.com|cli|bob"
+        );
+    }
+
+    // Test that `.cchain.env` values are used without a user to ask, and only when opted in
+    #[cfg(unix)]
+    #[test]
+    fn test_adjacent_env_without_input() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_adjacent_env_chain(directory.path(), true);
+        chain.set_interactor(SharedInteractor::new(NoInputInteractor));
+        let results = chain.execute().unwrap();
+
+        assert_eq!(
+            std::fs::read_to_string(directory.path().join("output.txt")).unwrap(),
+            "registry.example.com|cli|alice"
+        );
+        let provenances = results[0].get_variable_provenances();
+        let owner = provenances.iter().find(|provenance| provenance.name == "owner").unwrap();
+        assert_eq!(
+            owner.get_source(),
+            &VariableSource::Provided { source: directory.path().join(".cchain.env").display().to_string() }
+        );
+
+        let mut chain = create_adjacent_env_chain(directory.path(), false);
+        chain.set_interactor(SharedInteractor::new(NoInputInteractor));
+        assert!(chain.execute().is_err());
+    }
+
+    /// Run a chain, and get the value stored in `<<result>>`
+    fn run_pipeline(programs: serde_json::Value) -> (String, Vec<usize>) {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.validate_syntax().unwrap();
+        let results = chain.execute().unwrap();
+
+        let provenances = results[0].get_variable_provenances();
+        let result = provenances.iter().find(|provenance| provenance.name == "result").unwrap();
+        (result.value.clone(), results[0].get_failed_program_indices().clone())
+    }
+
+    // Test that streaming programs form one pipeline into the capturing program
+    #[cfg(unix)]
+    #[test]
+    fn test_stream_to_next() {
+        let (result, failed_program_indices) = run_pipeline(serde_json::json!([
+            { "command": "printf", "arguments": ["hello world"], "stream_to_next": true, "retry": 0 },
+            { "command": "tr", "arguments": ["a-z", "A-Z"], "stream_to_next": true, "retry": 0 },
+            { "command": "cat", "arguments": [], "stdout_stored_to": "<<result>>", "retry": 0 }
+        ]));
+        assert_eq!(result, "HELLO WORLD");
+        assert!(failed_program_indices.is_empty());
+
+        // A program that stops reading early does not fail the previous one
+        let (result, failed_program_indices) = run_pipeline(serde_json::json!([
+            { "command": "yes", "arguments": [], "stream_to_next": true, "retry": 0 },
+            { "command": "head", "arguments": ["-n", "2"], "stdout_stored_to": "<<result>>", "retry": 0 }
+        ]));
+        assert_eq!(result, "y\ny");
+        assert!(failed_program_indices.is_empty());
+    }
+
+    // Test that a large stream is not buffered in memory
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_stream_to_next_memory_is_bounded() {
+        const STREAM_SIZE: u64 = 512 * 1024 * 1024;
+        let (result, _) = run_pipeline(serde_json::json!([
+            { "command": "head", "arguments": ["-c", STREAM_SIZE.to_string(), "/dev/zero"], "stream_to_next": true, "retry": 0 },
+            { "command": "wc", "arguments": ["-c"], "stdout_stored_to": "<<result>>", "retry": 0 }
+        ]));
+        assert_eq!(result.trim(), STREAM_SIZE.to_string());
+
+        // The peak resident memory of the tests stays far below the stream
+        let status = std::fs::read_to_string("/proc/self/status").unwrap();
+        let peak_kilobytes: u64 = status
+            .lines()
+            .find_map(|line| line.strip_prefix("VmHWM:"))
+            .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+            .unwrap();
+        assert!(peak_kilobytes * 1024 < STREAM_SIZE / 4, "{} kB", peak_kilobytes);
+    }
+
+    // Test that pipelines that cannot run are rejected
+    #[test]
+    fn test_stream_to_next_validation() {
+        let programs = serde_json::json!([
+            { "command": "echo", "arguments": ["a"], "stream_to_next": true, "stdout_stored_to": "<<a>>", "retry": 0 },
+            { "command": "cat", "arguments": [], "retry": 2 },
+            { "command": "echo", "arguments": ["b"], "stream_to_next": true, "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+
+        let problems = chain.get_pipeline_problems();
+        assert_eq!(problems.len(), 3);
+        assert!(problems[0].starts_with("Program #0: a program streaming its output"));
+        assert!(problems[1].starts_with("Program #1: a program in a pipeline cannot retry"));
+        assert!(problems[2].starts_with("Program #2: `stream_to_next` is set, but there is no next program"));
+        assert!(chain.validate_syntax().is_err());
+        assert!(chain.execute().is_err());
+    }
+
+    /// Create a chain whose first program fails until a marker file exists,
+    /// with a stub debug shell that dumps its environment and creates the marker
+    #[cfg(unix)]
+    fn create_debugged_chain(directory: &std::path::Path) -> Chain {
+        use std::os::unix::fs::PermissionsExt;
+
+        let shell_path = directory.join("shell.sh");
+        std::fs::write(&shell_path, "#!/bin/sh\nenv > environment.txt\ntouch marker\n").unwrap();
+        std::fs::set_permissions(&shell_path, std::fs::Permissions::from_mode(0o755)).unwrap();
+
+        let chain_file = serde_json::json!({
+            "metadata": { "variables": { "token": { "secret": true } } },
+            "programs": [
+                { "command": "echo", "arguments": ["<<target>>"], "stdout_stored_to": "<<echoed>>", "retry": 0 },
+                { "command": "test", "arguments": ["-f", "marker"], "working_directory": directory, "retry": 1 },
+                { "command": "touch", "arguments": ["last", "<<token>>"], "working_directory": directory, "retry": 0 }
+            ]
+        });
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain_file).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.set_provided_values(
+            collect_provided_values(&[("target".to_string(), "staging".to_string()), ("token".to_string(), "hunter2".to_string())], &[]).unwrap()
+        );
+        chain.set_debug_shell_options(Some(DebugShellOptions::new().with_shell(shell_path.to_string_lossy().into_owned())));
+        chain
+    }
+
+    // Test debugging a failed program in a shell, then retrying it
+    #[cfg(unix)]
+    #[test]
+    fn test_debug_on_failure_retry() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_debugged_chain(directory.path());
+        let interactor = ScriptedInteractor::new(vec!["y".to_string(), "r".to_string()]);
+        let prompts = interactor.get_prompts();
+        chain.set_interactor(SharedInteractor::new(interactor));
+        chain.execute().unwrap();
+
+        assert_eq!(prompts.lock().unwrap().len(), 2);
+        assert!(chain.get_failed_program_indices().is_empty());
+        assert!(directory.path().join("last").exists());
+        // The retry is a new execution of the program, with its own attempts
+        assert_eq!(chain.get_program_results()[1].attempts(), 1);
+
+        // The shell runs in the working directory, with the variables exported
+        let environment = std::fs::read_to_string(directory.path().join("environment.txt")).unwrap();
+        assert!(environment.contains("CCHAIN_VAR_TARGET=staging\n"));
+        assert!(environment.contains("CCHAIN_VAR_ECHOED=staging\n"));
+        assert!(environment.contains("CCHAIN_FAILED_COMMAND=test -f marker\n"));
+        // Secrets stay out unless exposed
+        assert!(!environment.contains("CCHAIN_VAR_TOKEN"));
+    }
+
+    // Test skipping or aborting after the debug shell, and declining it
+    #[cfg(unix)]
+    #[test]
+    fn test_debug_on_failure_skip_and_abort() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_debugged_chain(directory.path());
+        chain.set_interactor(SharedInteractor::new(ScriptedInteractor::new(vec!["y".to_string(), "s".to_string()])));
+        chain.execute().unwrap();
+        assert_eq!(chain.get_failed_program_indices(), vec![1]);
+        assert!(directory.path().join("last").exists());
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_debugged_chain(directory.path());
+        chain.set_interactor(SharedInteractor::new(ScriptedInteractor::new(vec!["yes".to_string(), "a".to_string()])));
+        assert!(chain.execute().is_err());
+        assert!(!directory.path().join("last").exists());
+
+        // Without the shell, the failure is handled as usual
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_debugged_chain(directory.path());
+        chain.set_interactor(SharedInteractor::new(ScriptedInteractor::new(vec!["n".to_string()])));
+        assert!(chain.execute().is_err());
+        assert!(!directory.path().join("environment.txt").exists());
+    }
+
+    // Test that the `debug_variables` step shows set and unset variables,
+    // masks secrets, and does not fail the chain
+    #[test]
+    fn test_debug_variables_step() {
+        let chain_file = serde_json::json!({
+            "metadata": { "variables": { "token": { "secret": true } } },
+            "programs": [
+                { "command": "echo", "arguments": ["<<target>>"], "stdout_stored_to": "<<echoed>>", "retry": 0 },
+                { "command": "true", "arguments": ["<<token>>"], "retry": 0 },
+                { "command": "false", "arguments": [], "stdout_stored_to": "<<missing>>", "retry": 0,
+                  "failure_handling_options": { "exit_on_failure": false } },
+                { "builtin": "debug_variables" }
+            ]
+        });
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain_file).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.set_provided_values(
+            collect_provided_values(&[("target".to_string(), "staging".to_string()), ("token".to_string(), "hunter2".to_string())], &[]).unwrap()
+        );
+        chain.execute().unwrap();
+        assert_eq!(chain.get_failed_program_indices(), vec![2]);
+
+        let snapshots = chain.get_variable_snapshots();
+        let get_snapshot = |name: &str| snapshots.iter().find(|snapshot| snapshot.name == name).unwrap();
+        assert_eq!(get_snapshot("target").value.as_deref(), Some("staging"));
+        assert_eq!(get_snapshot("target").initialization, "startup");
+        assert_eq!(get_snapshot("token").value.as_deref(), Some(SECRET_MASK));
+        assert!(get_snapshot("echoed").has_value);
+        assert_eq!(get_snapshot("echoed").program_index, Some(0));
+        assert!(!get_snapshot("missing").has_value);
+        assert_eq!(get_snapshot("missing").initialization, "program output");
+        assert_eq!(get_snapshot("missing").program_index, Some(2));
+    }
+
+    fn create_destructive_chain(directory: &std::path::Path, effects: Option<&[&str]>) -> Chain {
+        let target = directory.join("target");
+        std::fs::create_dir_all(&target).unwrap();
+        let mut program = serde_json::json!(
+            { "command": "rm", "arguments": ["-rf", target], "retry": 0 }
+        );
+        if let Some(effects) = effects {
+            program["effects"] = serde_json::json!(effects);
+        }
+        let chain_file = serde_json::json!([
+            { "command": "touch", "arguments": [directory.join("started")], "effects": ["writes-files"], "retry": 0 },
+            program
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain_file).unwrap();
+
+        Chain::from_file(temp_file.path().to_str().unwrap()).unwrap()
+    }
+
+    // Test that an annotated destructive program is confirmed before anything runs
+    #[test]
+    fn test_destructive_gate_on_annotation() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_destructive_chain(directory.path(), Some(&["deletes-files"]));
+        assert_eq!(chain.get_destructive_programs(), vec![(1, "annotated `deletes-files`".to_string())]);
+
+        let interactor = ScriptedInteractor::new(vec!["n".to_string()]);
+        let prompts = interactor.get_prompts();
+        chain.set_interactor(SharedInteractor::new(interactor));
+        assert!(chain.execute().is_err());
+        assert!(prompts.lock().unwrap()[0].contains("--allow-destructive"));
+        assert!(!directory.path().join("started").exists());
+
+        // Without a terminal, the chain cannot be confirmed
+        chain.set_interactor(SharedInteractor::new(UnattendedInteractor));
+        let error = chain.execute().unwrap_err();
+        assert!(matches!(error.downcast_ref::<ChainError>(), Some(ChainError::InputRequired { .. })));
+        assert!(directory.path().join("target").exists());
+
+        // A read-only annotation overrides the heuristics
+        let chain = create_destructive_chain(directory.path(), Some(&["read-only"]));
+        assert!(chain.get_destructive_programs().is_empty());
+    }
+
+    // Test that unannotated destructive commands are detected, confirmed, and linted
+    #[test]
+    fn test_destructive_gate_on_heuristic() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_destructive_chain(directory.path(), None);
+        assert_eq!(chain.get_destructive_programs(), vec![(1, "runs `rm -rf`".to_string())]);
+        assert!(chain.lint().iter().any(|message| message.starts_with("Program #1: `rm -rf` deletes or mutates things")));
+
+        chain.set_interactor(SharedInteractor::new(ScriptedInteractor::new(vec!["y".to_string()])));
+        chain.execute().unwrap();
+        assert!(!directory.path().join("target").exists());
+    }
+
+    // Test that `--allow-destructive` runs the chain without asking
+    #[test]
+    fn test_destructive_gate_allowed() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_destructive_chain(directory.path(), Some(&["deletes-files", "network"]));
+        chain.set_interactor(SharedInteractor::new(UnattendedInteractor));
+        chain.set_allow_destructive(true);
+        chain.execute().unwrap();
+        assert!(directory.path().join("started").exists());
+        assert!(!directory.path().join("target").exists());
+    }
+
+    // Test that `--continue-on-failure` still stops at programs that cannot start
+    #[test]
+    fn test_continue_on_failure_stops_at_spawn_failures() {
+        let directory = tempfile::tempdir().unwrap();
+        let marker = directory.path().join("finished");
+        let programs = serde_json::json!([
+            { "command": "false", "arguments": [], "retry": 0 },
+            { "command": "cchain-command-that-does-not-exist", "arguments": [], "retry": 0,
+              "failure_handling_options": { "exit_on_failure": false, "remedy_command_line": null } },
+            { "command": "touch", "arguments": [marker], "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.set_failure_policy(FailurePolicy::ContinueOnFailure);
+        let error = chain.execute().unwrap_err();
+        assert!(matches!(error.downcast_ref::<ChainError>(), Some(ChainError::ProgramFailed { program_index: 1, .. })));
+        assert_eq!(chain.get_failed_program_indices(), vec![0, 1]);
+        assert!(!marker.exists());
+    }
+
+    // Test that the pinned time zone applies to every program, unless a program overrides it
+    #[cfg(unix)]
+    #[test]
+    fn test_pinned_timezone() {
+        let directory = tempfile::tempdir().unwrap();
+        let pinned_output = directory.path().join("pinned");
+        let overridden_output = directory.path().join("overridden");
+        let chain_file = serde_json::json!({
+            "metadata": { "timezone": "Etc/GMT-3", "locale": "C" },
+            "programs": [
+                { "command": "sh", "arguments": ["-c", format!("date +%z > {}", pinned_output.display())], "retry": 0 },
+                { "command": "sh", "arguments": ["-c", format!("date +%z > {}", overridden_output.display())], "retry": 0,
+                  "environment_variables_override": { "TZ": "Etc/GMT+2" } }
+            ]
+        });
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain_file).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.execute().unwrap();
+        assert_eq!(std::fs::read_to_string(pinned_output).unwrap().trim(), "+0300");
+        assert_eq!(std::fs::read_to_string(overridden_output).unwrap().trim(), "-0200");
+        assert_eq!(
+            chain.get_metadata().get_pinned_environment().into_iter().collect::<Vec<(String, String)>>(),
+            vec![
+                ("LANG".to_string(), "C".to_string()),
+                ("LC_ALL".to_string(), "C".to_string()),
+                ("TZ".to_string(), "Etc/GMT-3".to_string())
+            ]
+        );
+    }
+
+    // Test that a time zone that is not an IANA name is a validation error
+    #[test]
+    fn test_invalid_timezone() {
+        let chain_file = serde_json::json!({
+            "metadata": { "timezone": "+03:00" },
+            "programs": [{ "command": "date", "arguments": [], "retry": 0 }]
+        });
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain_file).unwrap();
+
+        let error = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap_err();
+        assert!(matches!(error.downcast_ref::<ChainError>(), Some(ChainError::Validation(_))));
+        assert!(error.to_string().contains("Invalid `timezone` `+03:00`"));
+    }
+
+    // Test that values entered in successful runs are offered as quick picks, except secrets
+    #[test]
+    fn test_value_history_quick_picks() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain_path = directory.path().join("cchain_deploy.json");
+        let chain_file = serde_json::json!({
+            "metadata": { "variables": { "token": { "secret": true } } },
+            "programs": [{ "command": "echo", "arguments": ["<<branch>>", "<<token>>"], "retry": 0 }]
+        });
+        std::fs::write(&chain_path, chain_file.to_string()).unwrap();
+        let history_path = directory.path().join("value_history.json");
+        let run = |answers: Vec<&str>| {
+            let interactor = ScriptedInteractor::new(answers.into_iter().map(String::from).collect());
+            let prompts = interactor.get_prompts();
+            let mut chain = Chain::from_file(chain_path.to_str().unwrap()).unwrap();
+            chain.set_interactor(SharedInteractor::new(interactor));
+            chain.set_skip_review(true);
+            chain.set_value_history(ValueHistory::load(history_path.clone()));
+            let results = chain.execute().unwrap();
+            chain.record_value_history().unwrap();
+            let prompts = prompts.lock().unwrap().clone();
+            (results, prompts)
+        };
+
+        run(vec!["develop", "secret-1"]);
+        run(vec!["main", "secret-2"]);
+        let (results, prompts) = run(vec!["2", "secret-3"]);
+        assert!(prompts[0].contains("1) main  2) develop  or type a new value"));
+        assert!(!prompts[1].contains("1)"));
+        let provenances = results[0].get_variable_provenances();
+        assert_eq!(provenances.iter().find(|provenance| provenance.name == "branch").unwrap().value, "develop");
+
+        let value_history = ValueHistory::load(history_path.clone());
+        assert_eq!(value_history.get_values(chain_path.to_str().unwrap(), "branch"), ["develop", "main"]);
+        assert!(value_history.get_values(chain_path.to_str().unwrap(), "token").is_empty());
+        assert!(!std::fs::read_to_string(&history_path).unwrap().contains("secret"));
+    }
+
+    /// Answers prompts, and records how many files the programs had
+    /// created when each prompt was asked
+    struct StartObservingInteractor {
+        directory: std::path::PathBuf,
+        answers: Vec<String>,
+        prompts: std::sync::Arc<std::sync::Mutex<Vec<(String, usize)>>>,
+    }
+
+    impl Interactor for StartObservingInteractor {
+        fn is_interactive(&self) -> bool {
+            true
+        }
+
+        fn prompt(&mut self, message: &str) -> Result<String, anyhow::Error> {
+            let created_files: usize = std::fs::read_dir(&self.directory).unwrap().count();
+            self.prompts.lock().unwrap().push((message.to_string(), created_files));
+            Ok(self.answers.remove(0))
+        }
+    }
+
+    // Test that the members of a concurrency group are prompted for before the group starts
+    #[test]
+    fn test_concurrency_group_prompts_before_start() {
+        let directory = tempfile::tempdir().unwrap();
+        let output_directory = directory.path().join("output");
+        std::fs::create_dir_all(&output_directory).unwrap();
+        let touch = |name: &str| {
+            serde_json::json!({
+                "command": "touch",
+                "arguments": [format!("{}/{}", output_directory.display(), name)],
+                "concurrency_group": 1,
+                "retry": 0
+            })
+        };
+        let programs = serde_json::json!([
+            touch("a-<<first:on_program_execution>>"),
+            touch("b-<<second:on_program_execution>>"),
+            touch("c-<<first:on_program_execution>>")
+        ]);
+        let chain_path = directory.path().join("cchain_group.json");
+        std::fs::write(&chain_path, programs.to_string()).unwrap();
+
+        let prompts = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        let mut chain = Chain::from_file(chain_path.to_str().unwrap()).unwrap();
+        chain.set_interactor(SharedInteractor::new(StartObservingInteractor {
+            directory: output_directory.clone(),
+            answers: vec!["x".to_string(), "y".to_string()],
+            prompts: prompts.clone(),
+        }));
+        chain.execute().unwrap();
+
+        // One prompt per variable, each asked before any member started
+        let prompts = prompts.lock().unwrap();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[0].0.contains("First"));
+        assert!(prompts[1].0.contains("Second"));
+        assert!(prompts.iter().all(|(_, created_files)| *created_files == 0));
+        for name in ["a-x", "b-y", "c-x"] {
+            assert!(output_directory.join(name).exists(), "{} is missing", name);
+        }
+    }
+
+    // Test that a member of a concurrency group cannot use the output of another member
+    #[test]
+    fn test_concurrency_group_member_dataflow() {
+        let programs = serde_json::json!([
+            { "command": "echo", "arguments": ["a"], "stdout_stored_to": "<<out>>", "concurrency_group": 1, "retry": 0 },
+            { "command": "echo", "arguments": ["<<out>>"], "concurrency_group": 1, "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        assert_eq!(
+            chain.get_concurrency_group_problems(),
+            vec!["Program #1: `out` is stored by program #0 of the same concurrency group, which may not have finished. The output of a member is only available to the programs after the whole group.".to_string()]
+        );
+        let error = chain.execute().unwrap_err();
+        assert!(matches!(error.downcast_ref::<ChainError>(), Some(ChainError::Validation(_))));
+    }
+
+    // Test that an output is only available to the programs after the whole
+    // group, and a member's remedy cannot use another member's output either
+    #[cfg(unix)]
+    #[test]
+    fn test_concurrency_group_boundary() {
+        let programs = serde_json::json!([
+            { "command": "echo", "arguments": ["-n", "a"], "stdout_stored_to": "<<first>>", "concurrency_group": 2, "retry": 0 },
+            {
+                "command": "false",
+                "arguments": [],
+                "failure_handling_options": { "exit_on_failure": false, "remedy_command_line": { "command": "echo", "arguments": ["<<first>>"] } },
+                "concurrency_group": 2,
+                "retry": 0
+            },
+            { "command": "echo", "arguments": ["<<second>>"], "concurrency_group": 2, "retry": 0 },
+            { "command": "echo", "arguments": ["-n", "b"], "stdout_stored_to": "<<second>>", "concurrency_group": 2, "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        let problems: Vec<String> = chain.get_concurrency_group_problems();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("Program #1: `first` is stored by program #0 of the same concurrency group"));
+        // The member at the earlier index is rejected too
+        assert!(problems[1].starts_with("Program #2: `second` is stored by program #3 of the same concurrency group"));
+        assert!(problems[1].ends_with("only available to the programs after the whole group."));
+        assert!(chain.validate_syntax().is_err());
+
+        // A program right after the group runs before the group starts
+        let programs = serde_json::json!([
+            { "command": "echo", "arguments": ["-n", "a"], "stdout_stored_to": "<<first>>", "concurrency_group": 2, "retry": 0 },
+            { "command": "echo", "arguments": ["-n", "b"], "stdout_stored_to": "<<second>>", "concurrency_group": 2, "retry": 0 },
+            { "command": "echo", "arguments": ["<<first>>"], "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+        let chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        assert_eq!(chain.get_output_dependencies()[0].availability, OutputAvailability::GroupStartsLater);
+        assert!(chain.get_concurrency_group_problems()[0].contains("whose concurrency group only starts after it"));
+
+        // After the group, both outputs are available
+        let programs = serde_json::json!([
+            { "command": "echo", "arguments": ["-n", "a"], "stdout_stored_to": "<<first>>", "concurrency_group": 2, "retry": 0 },
+            { "command": "echo", "arguments": ["-n", "b"], "stdout_stored_to": "<<second>>", "concurrency_group": 2, "retry": 0 },
+            { "builtin": "debug_variables" },
+            { "command": "echo", "arguments": ["-n", "<<first>><<second>>"], "stdout_stored_to": "<<both>>", "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        assert!(chain.get_concurrency_group_problems().is_empty());
+        let dependencies = chain.get_output_dependencies();
+        assert_eq!(dependencies.len(), 2);
+        assert!(dependencies.iter().all(|dependency| dependency.consumer == 3 && dependency.availability == OutputAvailability::Available));
+        chain.validate_syntax().unwrap();
+        let results = chain.execute().unwrap();
+        let provenances = results[0].get_variable_provenances();
+        assert!(provenances.iter().any(|provenance| provenance.name == "both" && provenance.value == "ab"));
+    }
+
+    // Test that a remedy cannot use the output of its own program, nor of a later one
+    #[test]
+    fn test_remedy_variables_exist_before_the_program() {
+        let programs = serde_json::json!([
+            {
+                "command": "sh",
+                "arguments": ["-c", "echo d-42; exit 1"],
+                "stdout_stored_to": "<<deploy_id>>",
+                "failure_handling_options": { "exit_on_failure": true, "remedy_command_line": { "command": "echo", "arguments": ["rollback <<deploy_id>> <<later>>"] } },
+                "retry": 0
+            },
+            { "command": "echo", "arguments": ["-n", "b"], "stdout_stored_to": "<<later>>", "retry": 0 }
+        ]);
+        let chain = Chain::from_json_str(&programs.to_string(), "remedy").unwrap();
+        let problems: Vec<String> = chain.get_remedy_variable_problems();
+        assert_eq!(problems.len(), 2);
+        assert!(problems[0].starts_with("Program #0 remedy command: `deploy_id` is stored by program #0 itself"));
+        assert!(problems[0].contains("before its output is stored"));
+        assert!(problems[1].starts_with("Program #0 remedy command: `later` is only stored by program #1, which runs after it"));
+        let mut chain = Chain::from_json_str(&programs.to_string(), "remedy").unwrap();
+        assert!(chain.validate_syntax().is_err());
+
+        // Prompts and earlier outputs are fine, and variables only in
+        // remedies are asked for like the others
+        let programs = serde_json::json!([
+            { "command": "echo", "arguments": ["-n", "a"], "stdout_stored_to": "<<earlier>>", "retry": 0 },
+            {
+                "command": "false",
+                "arguments": [],
+                "failure_handling_options": { "exit_on_failure": false, "remedy_command_line": { "command": "echo", "arguments": ["<<earlier>> <<owner>>"] } },
+                "retry": 0
+            }
+        ]);
+        let mut chain = Chain::from_json_str(&programs.to_string(), "remedy").unwrap();
+        assert!(chain.get_remedy_variable_problems().is_empty());
+        assert_eq!(chain.get_pending_inputs(), vec!["owner".to_string()]);
+        chain.validate_syntax().unwrap();
+    }
+
+    // Test that a remedy gets the values stored after its program was
+    // prepared, while its concurrency group waited to start
+    #[cfg(unix)]
+    #[test]
+    fn test_remedy_injected_before_it_runs() {
+        let directory = tempfile::tempdir().unwrap();
+        let remedy_path = directory.path().join("remedy");
+        let programs = serde_json::json!([
+            {
+                "command": "false",
+                "arguments": [],
+                "failure_handling_options": {
+                    "exit_on_failure": false,
+                    "remedy_command_line": { "command": "sh", "arguments": ["-c", format!("printf '%s' '<<release>>' > {}", remedy_path.display())] }
+                },
+                "concurrency_group": 1,
+                "retry": 0
+            },
+            { "command": "echo", "arguments": ["-n", "r-7"], "stdout_stored_to": "<<release>>", "retry": 0 },
+            { "builtin": "debug_variables" }
+        ]);
+        let mut chain = Chain::from_json_str(&programs.to_string(), "remedy").unwrap();
+        assert!(chain.get_remedy_variable_problems().is_empty());
+        chain.validate_syntax().unwrap();
+        chain.execute().unwrap();
+        assert_eq!(std::fs::read_to_string(&remedy_path).unwrap(), "r-7");
+    }
+
+    // Test that only the variables of the selected programs are asked for
+    #[cfg(unix)]
+    #[test]
+    fn test_selected_programs_variable_pruning() {
+        let directory = tempfile::tempdir().unwrap();
+        let output_path = directory.path().join("output.txt");
+        let programs = serde_json::json!([
+            { "command": "sh", "arguments": ["-c", format!("echo <<first>> >> {}", output_path.display())], "retry": 0 },
+            { "command": "sh", "arguments": ["-c", format!("echo <<second>> >> {}", output_path.display())], "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.set_program_selection(ProgramSelection::default().with_only([1].into())).unwrap();
+        assert_eq!(chain.get_pending_inputs(), vec!["second".to_string()]);
+        assert_eq!(chain.get_dry_run_command_lines().len(), 1);
+
+        let interactor = ScriptedInteractor::new(vec!["b".to_string()]);
+        let prompts = interactor.get_prompts();
+        chain.set_interactor(SharedInteractor::new(interactor));
+        chain.execute().unwrap();
+        assert_eq!(*prompts.lock().unwrap(), vec!["Please input a value for Second:"]);
+        assert_eq!(std::fs::read_to_string(&output_path).unwrap(), "b\n");
+
+        // Indices out of the chain are rejected
+        let error = chain.set_program_selection(ProgramSelection::default().with_from(2)).unwrap_err();
+        assert!(matches!(error.downcast_ref::<ChainError>(), Some(ChainError::Validation(_))));
+    }
+
+    // Test that presets saved at the same time keep each other's values
+    #[test]
+    fn test_concurrent_preset_saves() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("variable_presets.json");
+        let mut variable_presets = VariablePresets::load(path.clone()).unwrap();
+        variable_presets.set_value("removed", "old".to_string());
+        variable_presets.save().unwrap();
+
+        let barrier = std::sync::Arc::new(std::sync::Barrier::new(8));
+        let handles: Vec<std::thread::JoinHandle<()>> = (0..8)
+            .map(|index| {
+                let path = path.clone();
+                let barrier = barrier.clone();
+                std::thread::spawn(move || {
+                    // Every thread loads the presets before any of them saves
+                    let mut variable_presets = VariablePresets::load(path).unwrap();
+                    variable_presets.set_value(&format!("name{}", index), format!("value{}", index));
+                    if index == 0 {
+                        variable_presets.unset_value("removed");
+                    }
+                    barrier.wait();
+                    variable_presets.save().unwrap();
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let variable_presets = VariablePresets::load(path).unwrap();
+        assert_eq!(variable_presets.get_values().len(), 8);
+        for index in 0..8 {
+            assert_eq!(variable_presets.get_value(&format!("name{}", index)), Some(format!("value{}", index).as_str()));
+        }
+        assert_eq!(variable_presets.get_value("removed"), None);
+    }
+
+    /// Write a chain printing five variables, with a `.cchain.env` next to
+    /// it, and presets for all of them
+    fn create_preset_chain(directory: &std::path::Path) -> Chain {
+        let programs = serde_json::json!({
+            "metadata": {
+                "load_adjacent_env": true,
+                "variables": { "channel": { "no_preset": true }, "token": { "secret": true } }
+            },
+            "programs": [{
+                "command": "sh",
+                "arguments": [
+                    "-c", "printf '%s|%s|%s|%s|%s' \"$1\" \"$2\" \"$3\" \"$4\" \"$5\" > \"$6\"", "sh",
+                    "<<registry>>", "<<project>>", "<<owner>>", "<<channel>>", "<<token>>", directory.join("output.txt")
+                ],
+                "retry": 0
+            }]
+        });
+        let chain_path = directory.join("cchain_presets.json");
+        std::fs::write(&chain_path, programs.to_string()).unwrap();
+        std::fs::write(directory.join(".cchain.env"), "registry=registry.example.com\n").unwrap();
+
+        let mut variable_presets = VariablePresets::load(directory.join("variable_presets.json")).unwrap();
+        for name in ["registry", "project", "owner", "channel", "token"] {
+            variable_presets.set_value(name, format!("preset-{}", name));
+        }
+        variable_presets.save().unwrap();
+
+        let mut chain = Chain::from_file(chain_path.to_str().unwrap()).unwrap();
+        chain.load_adjacent_env(&chain_path).unwrap();
+        chain.set_skip_review(true);
+        chain.set_provided_values(collect_provided_values(&[("project".to_string(), "cli".to_string())], &[]).unwrap());
+        chain.set_variable_presets(VariablePresets::load(directory.join("variable_presets.json")).unwrap());
+        chain
+    }
+
+    // Test that presets are the defaults at the prompts below `.cchain.env` and `--var`, except for opted-out variables and secrets
+    #[cfg(unix)]
+    #[test]
+    fn test_preset_precedence() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_preset_chain(directory.path());
+        let mut value_history = ValueHistory::load(directory.path().join("value_history.json"));
+        value_history.record(chain.get_path(), "owner", "carol");
+        chain.set_value_history(value_history);
+
+        let interactor = ScriptedInteractor::new(["", "", "general", "s3cret"].iter().map(|answer| answer.to_string()).collect());
+        let prompts = interactor.get_prompts();
+        chain.set_interactor(SharedInteractor::new(interactor));
+        chain.execute().unwrap();
+
+        assert_eq!(
+            *prompts.lock().unwrap(),
+            vec![
+                "Please input a value for Registry: [registry.example.com, from .cchain.env]",
+                // The entered values of the chain are offered beside the preset
+                "Please input a value for Owner: [preset-owner, from preset]\n     1) carol  or type a new value\n   >",
+                "Please input a value for Channel:",
+                "Please input a value for Token:",
+            ]
+        );
+        assert_eq!(
+            std::fs::read_to_string(directory.path().join("output.txt")).unwrap(),
+            "registry.example.com|cli|preset-owner|general|s3cret"
+        );
+    }
+
+    // Test that the sources of the values are reviewed with `--confirm-vars`, and a switched source is used
+    #[cfg(unix)]
+    #[test]
+    fn test_confirm_variable_sources() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_preset_chain(directory.path());
+        let mut value_history = ValueHistory::load(directory.path().join("value_history.json"));
+        value_history.record(chain.get_path(), "owner", "carol");
+        chain.set_value_history(value_history);
+        chain.set_confirm_variables(true);
+
+        // Only `--var` wins with a user to ask, so the others are prompted
+        // for with their usual defaults, then reviewed with the sources
+        let interactor = ScriptedInteractor::new(
+            ["", "", "general", "s3cret", "e", "3", "r", "e", "1", "x", "e", "1", "p", "c"]
+                .iter()
+                .map(|answer| answer.to_string())
+                .collect()
+        );
+        let prompts = interactor.get_prompts();
+        chain.set_interactor(SharedInteractor::new(interactor));
+        let results = chain.execute().unwrap();
+
+        let prompts = prompts.lock().unwrap().clone();
+        assert_eq!(prompts[0], "Please input a value for Registry: [registry.example.com, from .cchain.env]");
+        assert_eq!(
+            prompts[1],
+            "Please input a value for Owner: [preset-owner, from preset]\n     1) carol  or type a new value\n   >"
+        );
+        assert_eq!(prompts[4], "Run with these values? [c]onfirm/[e]dit:");
+        assert_eq!(prompts[6], "Source for Owner: [p]reset/[r]emembered, or [n]ew value:");
+        assert_eq!(prompts[9], "Source for Registry: [e]nv/[p]reset, or [n]ew value:");
+        assert_eq!(prompts.len(), 14);
+        assert_eq!(
+            std::fs::read_to_string(directory.path().join("output.txt")).unwrap(),
+            "preset-registry|cli|carol|general|s3cret"
+        );
+
+        let provenances = results[0].get_variable_provenances();
+        let get_source = |name: &str| provenances.iter().find(|provenance| provenance.name == name).unwrap().get_source().clone();
+        assert_eq!(
+            get_source("owner"),
+            VariableSource::Provided { source: "remembered value, picked at --confirm-vars".to_string() }
+        );
+        assert_eq!(get_source("registry"), VariableSource::Provided { source: "preset, picked at --confirm-vars".to_string() });
+        assert_eq!(get_source("project"), VariableSource::Provided { source: "--var project".to_string() });
+        assert_eq!(get_source("channel"), VariableSource::Prompt);
+    }
+
+    // Test that presets are used without a user to ask, except for opted-out variables
+    #[cfg(unix)]
+    #[test]
+    fn test_preset_without_input() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_preset_chain(directory.path());
+        chain.set_interactor(SharedInteractor::new(NoInputInteractor));
+        assert_eq!(chain.get_pending_inputs(), vec!["channel".to_string(), "token".to_string()]);
+        assert!(chain.execute().is_err());
+
+        let mut chain = create_preset_chain(directory.path());
+        chain.set_interactor(SharedInteractor::new(NoInputInteractor));
+        chain.set_provided_values(
+            collect_provided_values(&[("channel".to_string(), "ops".to_string()), ("token".to_string(), "t".to_string())], &[]).unwrap(),
+        );
+        let results = chain.execute().unwrap();
+        assert_eq!(
+            std::fs::read_to_string(directory.path().join("output.txt")).unwrap(),
+            "registry.example.com|preset-project|preset-owner|ops|t"
+        );
+        let provenances = results[0].get_variable_provenances();
+        let owner = provenances.iter().find(|provenance| provenance.name == "owner").unwrap();
+        assert_eq!(owner.get_source(), &VariableSource::Provided { source: "preset".to_string() });
+    }
+
+    // Test that the listed indices are those of the file, whatever is selected
+    #[test]
+    fn test_program_listing() {
+        let mut chain = Chain::from_json_str(
+            r#"[
+                { "command": "echo", "arguments": ["<<name>>"], "retry": 0, "alias": "greet" },
+                { "builtin": "debug_variables" },
+                { "command": "echo", "arguments": ["a"], "retry": 0, "concurrency_group": 1 },
+                { "command": "echo", "arguments": ["done"], "retry": 0, "always_run": true }
+            ]"#,
+            "listing",
+        )
+        .unwrap();
+        chain.set_program_selection(ProgramSelection::default().with_only([0, 2].into())).unwrap();
+
+        let listing = chain.get_program_listing();
+        assert_eq!(listing.iter().map(|program| program.index).collect::<Vec<usize>>(), vec![0, 1, 2, 3]);
+        assert_eq!(listing[0].alias, Some("greet".to_string()));
+        assert_eq!(listing[0].command, "echo '<<name>>'");
+        assert!(listing[1].builtin && listing[1].command == "debug_variables");
+        assert_eq!(listing[2].concurrency_group, Some(1));
+        assert!(listing[3].always_run);
+        assert_eq!(listing.iter().map(|program| program.selected).collect::<Vec<bool>>(), vec![true, false, true, false]);
+    }
+
+    // Test that removed environment variables are shown in the dry run
+    #[test]
+    fn test_dry_run_shows_removed_variables() {
+        let chain = Chain::from_json_str(
+            r#"{
+                "metadata": { "timezone": "UTC" },
+                "programs": [{
+                    "command": "aws", "arguments": ["s3", "ls"], "retry": 0,
+                    "environment_variables_override": { "AWS_PROFILE": null, "TZ": null, "AWS_REGION": "eu-west-1" }
+                }]
+            }"#,
+            "unset",
+        )
+        .unwrap();
+        assert_eq!(chain.get_dry_run_command_lines(), vec!["Program #0: aws s3 ls [UNSET AWS_PROFILE, UNSET TZ]"]);
+    }
+
+    // Test that the result of a chain has the results of its programs, failed ones included
+    #[test]
+    fn test_chain_execution_result() {
+        let programs = serde_json::json!([
+            { "command": "echo", "arguments": ["first"], "retry": 0 },
+            { "command": "false", "arguments": [], "retry": 0, "failure_handling_options": { "exit_on_failure": false } },
+            { "command": "echo", "arguments": ["last"], "retry": 0 }
+        ]);
+        let mut chain = Chain::from_json_str(&programs.to_string(), "results").unwrap();
+        let result = chain.execute().unwrap().remove(0);
+        assert_eq!(result.get_status(), ChainExecutionStatus::CompletedWithFailures);
+        let program_results = result.get_program_results();
+        assert_eq!(program_results.len(), 3);
+        assert_eq!(program_results.iter().map(|result| result.program_index()).collect::<Vec<_>>(), vec![Some(0), Some(1), Some(2)]);
+        assert_eq!(program_results[0].output(), "first");
+        assert!(!program_results[1].is_succeeded());
+        assert_eq!(program_results[1].exit_code(), Some(1));
+        assert_eq!(program_results[2].command_rendered(), "echo last");
+    }
+
+    fn create_reconciled_chain(provided_values: &[(&str, &str)]) -> Chain {
+        let programs = serde_json::json!({
+            "metadata": { "variables": { "release_token": { "secret": true } } },
+            "programs": [
+                { "command": "echo", "arguments": ["<<environment>>", "<<api_token>>", "<<release_token>>"], "stdout_stored_to": "<<build_id>>", "retry": 0 },
+                { "command": "echo", "arguments": ["<<build_id>>"], "retry": 0 }
+            ]
+        });
+        let mut chain = Chain::from_json_str(&programs.to_string(), "reconciled").unwrap();
+        let provided_values: Vec<(String, String)> =
+            provided_values.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect();
+        chain.set_provided_values(collect_provided_values(&provided_values, &[]).unwrap());
+        chain
+    }
+
+    // Test that values for names the chain does not use are found, with the names they may be meant for
+    #[test]
+    fn test_reconcile_unknown_values() {
+        let chain = create_reconciled_chain(&[("enviroment", "prod"), ("unrelated", "x"), ("environment", "prod")]);
+        let findings = chain.reconcile_supplied_values();
+        assert_eq!(findings.len(), 2, "{:?}", findings);
+        assert!(matches!(
+            &findings[0],
+            SuppliedValueFinding::Unknown { name, suggestions, .. } if name == "enviroment" && suggestions == &vec!["environment".to_string()]
+        ));
+        assert!(findings[0].to_string().ends_with("Did you mean `environment`?"), "{}", findings[0]);
+        assert!(matches!(&findings[1], SuppliedValueFinding::Unknown { name, suggestions, .. } if name == "unrelated" && suggestions.is_empty()));
+
+        // Warnings at a terminal, an error without one
+        let mut chain = create_reconciled_chain(&[("enviroment", "prod")]);
+        chain.set_interactor(SharedInteractor::new(ScriptedInteractor::new(Vec::new())));
+        assert!(chain.check_supplied_values().is_ok());
+        chain.set_interactor(SharedInteractor::new(UnattendedInteractor));
+        let error = chain.check_supplied_values().unwrap_err().to_string();
+        assert!(error.contains("`enviroment`"), "{}", error);
+
+        // Presets are only reported when close to a variable without one
+        let directory = tempfile::tempdir().unwrap();
+        let mut variable_presets = VariablePresets::load(directory.path().join("variable_presets.json")).unwrap();
+        variable_presets.set_value("enviroment", "staging".to_string());
+        variable_presets.set_value("registry", "registry.example.com".to_string());
+        let mut chain = create_reconciled_chain(&[]);
+        chain.set_variable_presets(variable_presets.clone());
+        let findings = chain.reconcile_supplied_values();
+        assert_eq!(findings.len(), 1, "{:?}", findings);
+        assert!(matches!(&findings[0], SuppliedValueFinding::Unknown { name, source, .. } if name == "enviroment" && source == "preset"));
+        variable_presets.set_value("environment", "staging".to_string());
+        chain.set_variable_presets(variable_presets);
+        assert!(chain.reconcile_supplied_values().is_empty());
+    }
+
+    // Test that values of variables that a program overwrites before they are used are found
+    #[test]
+    fn test_reconcile_overwritten_values() {
+        let chain = create_reconciled_chain(&[("build_id", "42"), ("environment", "prod")]);
+        let findings = chain.reconcile_supplied_values();
+        assert_eq!(
+            findings,
+            vec![SuppliedValueFinding::Overwritten {
+                name: "build_id".to_string(),
+                source: "--var build_id".to_string(),
+                producer: 0
+            }]
+        );
+        assert!(findings[0].to_string().contains("overwritten by the output of program #0"), "{}", findings[0]);
+        // Overwriting is not an error in CI
+        let mut chain = create_reconciled_chain(&[("build_id", "42")]);
+        chain.set_interactor(SharedInteractor::new(UnattendedInteractor));
+        assert!(chain.check_supplied_values().is_ok());
+    }
+
+    // Test that secrets supplied for variables not declared secret are found
+    #[test]
+    fn test_reconcile_undeclared_secrets() {
+        let chain = create_reconciled_chain(&[("api_token", "abc"), ("release_token", "def")]);
+        let findings = chain.reconcile_supplied_values();
+        assert_eq!(
+            findings,
+            vec![SuppliedValueFinding::UndeclaredSecret { name: "api_token".to_string(), source: "--var api_token".to_string() }]
+        );
+        assert!(findings[0].to_string().contains("is not declared `secret`"), "{}", findings[0]);
+    }
+
+    // Test that a chain run again after an infrastructure error starts over with the answers of the first attempt
+    #[cfg(unix)]
+    #[test]
+    fn test_retry_chain_reuses_answers() {
+        let directory = tempfile::tempdir().unwrap();
+        let counter_path = directory.path().join("runs");
+        let script = format!(
+            "echo <<target>> >> {counter}; if [ $(wc -l < {counter}) -lt 2 ]; then echo 'Connection reset by peer' >&2; exit 1; fi",
+            counter = counter_path.display()
+        );
+        let chain_file = serde_json::json!([
+            { "command": "sh", "arguments": ["-c", script], "retry": 0 },
+            { "command": "echo", "arguments": ["<<?Tag>>"], "retry": 0 }
+        ]);
+        let mut chain = Chain::from_json_str(&chain_file.to_string(), "retry").unwrap();
+        let interactor = ScriptedInteractor::new(vec!["staging".to_string(), "v1".to_string()]);
+        let prompts = interactor.get_prompts();
+        chain.set_interactor(SharedInteractor::new(interactor));
+
+        let (outcome, failed_programs) = run_chain_with_retries(&mut chain, &ChainRetryOptions::new(1).with_delay_ms(0));
+        assert_eq!(outcome, RunOutcome::Succeeded);
+        assert!(failed_programs.is_empty());
+        assert_eq!(std::fs::read_to_string(&counter_path).unwrap(), "staging\nstaging\n");
+        // `target` is asked for once, and `Tag` only in the second
+        // attempt, which reaches its program
+        assert_eq!(prompts.lock().unwrap().len(), 2);
+        assert!(chain.get_failed_program_indices().is_empty());
+
+        let retry_options = ChainRetryOptions::new(1).with_delay_ms(250);
+        assert_eq!(retry_options.get_delay(1).as_millis(), 250);
+        assert_eq!(retry_options.get_delay(3).as_millis(), 1000);
+        assert_eq!(retry_options.get_delay(40).as_secs(), 300);
+    }
+
+    /// Write a chain with `lazy_prompts`, whose first program writes the
+    /// marker file, or fails when `is_failing` is set
+    fn create_lazy_prompt_chain(marker_path: &std::path::Path, is_failing: bool) -> Chain {
+        let first_command: String = match is_failing {
+            true => "exit 1".to_string(),
+            false => format!("touch {}", marker_path.display()),
+        };
+        let programs = serde_json::json!({
+            "metadata": { "lazy_prompts": true },
+            "programs": [
+                {
+                    "command": "sh",
+                    "arguments": ["-c", first_command],
+                    "failure_handling_options": { "exit_on_failure": true },
+                    "retry": 0
+                },
+                { "command": "echo", "arguments": ["<<release>>"], "retry": 0 },
+                { "command": "echo", "arguments": ["<<release>>", "<<channel:on_program_execution>>"], "retry": 0 }
+            ]
+        });
+        Chain::from_json_str(&programs.to_string(), "lazy").unwrap()
+    }
+
+    /// Records the prompts, with whether the marker file existed when
+    /// each was asked
+    struct MarkerInteractor {
+        marker_path: std::path::PathBuf,
+        prompts: std::sync::Arc<std::sync::Mutex<Vec<(String, bool)>>>,
+    }
+
+    impl Interactor for MarkerInteractor {
+        fn is_interactive(&self) -> bool {
+            true
+        }
+
+        fn prompt(&mut self, message: &str) -> Result<String, anyhow::Error> {
+            self.prompts.lock().unwrap().push((message.to_string(), self.marker_path.exists()));
+            Ok("value".to_string())
+        }
+    }
+
+    // Test that a startup variable is asked for with `lazy_prompts` once, right before the first program using it
+    #[cfg(unix)]
+    #[test]
+    fn test_lazy_prompts() {
+        let directory = tempfile::tempdir().unwrap();
+        let marker_path = directory.path().join("marker");
+        let mut chain = create_lazy_prompt_chain(&marker_path, false);
+        let listing: Vec<(String, bool)> = chain
+            .get_pending_input_listing()
+            .into_iter()
+            .map(|input| (input.name, input.conditional))
+            .collect();
+        assert_eq!(listing, vec![("release".to_string(), true), ("channel".to_string(), true)]);
+
+        let prompts = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+        chain.set_interactor(SharedInteractor::new(MarkerInteractor { marker_path: marker_path.clone(), prompts: prompts.clone() }));
+        chain.execute().unwrap();
+        assert_eq!(
+            *prompts.lock().unwrap(),
+            vec![
+                ("Please input a value for Release:".to_string(), true),
+                ("Please input a value for Channel:".to_string(), true)
+            ]
+        );
+        let provenances = chain.get_variable_provenances();
+        let release = provenances.iter().find(|provenance| provenance.name == "release").unwrap();
+        assert_eq!(release.get_source(), &VariableSource::Prompt);
+    }
+
+    // Test that a startup variable is never asked for with `lazy_prompts` when the chain stops before its program
+    #[cfg(unix)]
+    #[test]
+    fn test_lazy_prompts_skipped() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut chain = create_lazy_prompt_chain(&directory.path().join("marker"), true);
+        let interactor = ScriptedInteractor::new(Vec::new());
+        let prompts = interactor.get_prompts();
+        chain.set_interactor(SharedInteractor::new(interactor));
+        assert!(chain.execute().is_err());
+        assert!(prompts.lock().unwrap().is_empty());
+
+        // Without `lazy_prompts`, the variable is asked for before the chain starts
+        let programs = serde_json::json!([{ "command": "echo", "arguments": ["<<release>>"], "retry": 0 }]);
+        let chain = Chain::from_json_str(&programs.to_string(), "eager").unwrap();
+        assert!(chain.get_pending_input_listing().iter().all(|input| !input.conditional));
+    }
+}
@@ -1,8 +1,16 @@
 #[cfg(test)]
 mod tests {
     use anyhow::Result;
-    use std::collections::HashMap;
-    use cchain::core::{command::CommandLine, interpreter::Interpreter, traits::Execution};
+    use std::collections::{BTreeMap, HashMap};
+    use cchain::core::{
+        builtin::SECRET_MASK,
+        command::{quote_for_shell, CommandLine, RenderStyle, SUMMARY_ARGUMENT_COUNT},
+        environment::{diff_environment, EnvironmentChange},
+        events::EventSender,
+        interpreter::Interpreter,
+        privilege::PrivilegeChange,
+        traits::Execution,
+    };
 
     #[test]
     #[cfg(unix)]
@@ -38,6 +46,36 @@ mod tests {
         Ok(())
     }
 
+    // Test that a null override removes an inherited variable, rather
+    // than setting it empty
+    #[test]
+    #[cfg(unix)]
+    fn test_environment_variable_removal() -> Result<()> {
+        let script: &str = r#"if [ -z "${CCHAIN_TEST_REMOVED+set}" ]; then echo unset; else echo "set:$CCHAIN_TEST_REMOVED"; fi"#;
+        // The variable is pinned rather than set in this process, which the
+        // other tests share
+        let create_command_line = |overrides: serde_json::Value| -> CommandLine {
+            let mut cmd: CommandLine = serde_json::from_value(serde_json::json!({
+                "command": "sh",
+                "arguments": ["-c", script],
+                "environment_variables_override": overrides
+            }))
+            .unwrap();
+            cmd.set_pinned_environment(BTreeMap::from([("CCHAIN_TEST_REMOVED".to_string(), "inherited".to_string())]));
+            cmd
+        };
+
+        let mut cmd = create_command_line(serde_json::json!({}));
+        assert_eq!(cmd.execute()?[0].get_output().trim(), "set:inherited");
+        let mut cmd = create_command_line(serde_json::json!({ "CCHAIN_TEST_REMOVED": null }));
+        assert_eq!(cmd.get_removed_environment_variables(), vec!["CCHAIN_TEST_REMOVED"]);
+        assert_eq!(cmd.execute()?[0].get_output().trim(), "unset");
+        let mut cmd = create_command_line(serde_json::json!({ "CCHAIN_TEST_REMOVED": "" }));
+        assert!(cmd.get_removed_environment_variables().is_empty());
+        assert_eq!(cmd.execute()?[0].get_output().trim(), "set:");
+        Ok(())
+    }
+
     #[test]
     fn test_inject_value_into_arguments() -> Result<()> {
         let mut cmd = CommandLine::new(
@@ -52,6 +90,19 @@ mod tests {
         Ok(())
     }
 
+    // Test that command lines are compared by what they run, whatever
+    // follows their runs
+    #[test]
+    fn test_command_line_equality() {
+        let create_command_line = |argument: &str| {
+            CommandLine::new("echo".to_string(), vec![argument.to_string()], None, None, None)
+        };
+        let mut followed = create_command_line("hello");
+        followed.set_event_sender(EventSender::display_only(), 0);
+        assert_eq!(followed, create_command_line("hello"));
+        assert_ne!(followed, create_command_line("bye"));
+    }
+
     #[test]
     fn test_revise_argument_by_index() {
         let mut cmd = CommandLine::new(
@@ -80,4 +131,278 @@ mod tests {
         let error_msg = format!("{}", result.unwrap_err());
         assert!(error_msg.starts_with("Failed to execute Command Line"));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_escalation_argv() {
+        let argv = vec!["apt".to_string(), "install".to_string(), "-y".to_string()];
+
+        let sudo = PrivilegeChange::Escalate {
+            escalation_command: "sudo".to_string(),
+            user: "root".to_string(),
+        };
+        assert_eq!(
+            sudo.wrap_argv(argv.clone()),
+            vec!["sudo", "-n", "-u", "root", "--", "apt", "install", "-y"]
+        );
+
+        let doas = PrivilegeChange::Escalate {
+            escalation_command: "doas".to_string(),
+            user: "root".to_string(),
+        };
+        assert_eq!(
+            doas.wrap_argv(argv.clone()),
+            vec!["doas", "-n", "-u", "root", "apt", "install", "-y"]
+        );
+
+        assert_eq!(PrivilegeChange::Unchanged.wrap_argv(argv.clone()), argv);
+    }
+
+    #[test]
+    fn test_effective_command_line_shows_wrapper() {
+        let cmd = CommandLine::new(
+            "make".to_string(),
+            vec!["install".to_string()],
+            Some(Interpreter::Sh),
+            None,
+            None,
+        );
+        let sudo = PrivilegeChange::Escalate {
+            escalation_command: "sudo".to_string(),
+            user: "root".to_string(),
+        };
+
+        assert_eq!(
+            cmd.get_effective_command_line(&sudo),
+            "sudo -n -u root -- sh -c 'make install'"
+        );
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_run_as_unknown_user() {
+        let mut cmd = CommandLine::new("true".to_string(), vec![], None, None, None)
+            .with_run_as("user-that-does-not-exist".to_string());
+
+        let error_msg = format!("{}", cmd.execute().unwrap_err());
+        assert_eq!(error_msg, "User user-that-does-not-exist does not exist");
+    }
+
+    // Only root can drop privileges, so this is skipped otherwise
+    #[test]
+    #[cfg(unix)]
+    fn test_run_as_drops_privileges() -> Result<()> {
+        if unsafe { libc::geteuid() } != 0 {
+            return Ok(());
+        }
+
+        let mut cmd = CommandLine::new("id".to_string(), vec!["-u".to_string()], None, None, None)
+            .with_run_as("nobody".to_string());
+        assert!(matches!(cmd.resolve_privilege_change()?, PrivilegeChange::Drop { .. }));
+
+        let expected_uid = std::process::Command::new("id").args(["-u", "nobody"]).output()?;
+        let results = cmd.execute()?;
+        assert_eq!(
+            results[0].get_output().trim(),
+            String::from_utf8_lossy(&expected_uid.stdout).trim()
+        );
+        Ok(())
+    }
+
+    fn create_sh_command_line(pipeline: serde_json::Value) -> CommandLine {
+        serde_json::from_value(serde_json::json!({
+            "command": "echo",
+            "arguments": ["hello", pipeline],
+            "interpreter": "sh"
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_raw_argument_is_parsed_by_shell() -> Result<()> {
+        let mut cmd = create_sh_command_line(serde_json::json!({ "raw": "| tr a-z A-Z" }));
+        assert_eq!(cmd.get_shell_command_line(), "echo hello | tr a-z A-Z");
+
+        let results = cmd.execute()?;
+        assert_eq!(results[0].get_output().trim(), "HELLO");
+        Ok(())
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_plain_argument_is_passed_literally() -> Result<()> {
+        let mut cmd = create_sh_command_line(serde_json::json!("| tr a-z A-Z"));
+        assert_eq!(cmd.get_shell_command_line(), "echo hello '| tr a-z A-Z'");
+
+        let results = cmd.execute()?;
+        assert_eq!(results[0].get_output().trim(), "hello | tr a-z A-Z");
+        Ok(())
+    }
+
+    #[test]
+    fn test_quote_for_shell() {
+        assert_eq!(quote_for_shell("--release"), "--release");
+        assert_eq!(quote_for_shell(""), "''");
+        assert_eq!(quote_for_shell("it's $HOME"), "'it'\\''s $HOME'");
+    }
+
+    #[test]
+    fn test_lint_shell_metacharacters() {
+        let raw = create_sh_command_line(serde_json::json!({ "raw": "a && b" }));
+        assert_eq!(
+            raw.lint(),
+            vec!["Argument `a && b` is raw, so `&&` will be interpreted by the shell".to_string()]
+        );
+
+        let plain = create_sh_command_line(serde_json::json!("a && b > c"));
+        assert_eq!(
+            plain.lint(),
+            vec![
+                "Argument `a && b > c` contains `&&`, `>`, which will be escaped and passed literally. Write it as {\"raw\": \"a && b > c\"} if the shell should interpret it".to_string()
+            ]
+        );
+
+        // Without an interpreter, nothing is parsed by a shell
+        let direct = CommandLine::new("echo".to_string(), vec!["a && b".to_string()], None, None, None);
+        assert!(direct.lint().is_empty());
+    }
+
+    #[test]
+    fn test_render_command_styles() {
+        let mut cmd = CommandLine::new(
+            "curl".to_string(),
+            vec!["-H".to_string(), "Authorization: token s3cret".to_string(), "it's".to_string()],
+            None,
+            None,
+            None,
+        );
+        cmd.add_secret_value("s3cret".to_string());
+
+        // Quotes are kept, so that the command can be run again
+        assert_eq!(
+            cmd.render_command(RenderStyle::Full),
+            "curl -H 'Authorization: token s3cret' 'it'\\''s'"
+        );
+        assert_eq!(
+            cmd.render_command(RenderStyle::Masked),
+            "curl -H 'Authorization: token ********' 'it'\\''s'"
+        );
+        assert_eq!(cmd.render_command(RenderStyle::Summary), cmd.render_command(RenderStyle::Masked));
+        assert_eq!(cmd.to_string(), cmd.render_command(RenderStyle::Summary));
+
+        // `echo a b` and `echo "a b"` are told apart
+        let split = CommandLine::new("echo".to_string(), vec!["a".to_string(), "b".to_string()], None, None, None);
+        let joined = CommandLine::new("echo".to_string(), vec!["a b".to_string()], None, None, None);
+        assert_ne!(split.to_string(), joined.to_string());
+    }
+
+    #[test]
+    fn test_render_command_newlines() {
+        let cmd = CommandLine::new(
+            "python3".to_string(),
+            vec!["-c".to_string(), "import sys\nprint(sys.argv)".to_string()],
+            None,
+            None,
+            None,
+        );
+
+        assert_eq!(cmd.render_command(RenderStyle::Full), "python3 -c 'import sys\nprint(sys.argv)'");
+        assert_eq!(cmd.render_command(RenderStyle::Summary), "python3 -c 'import sys\\nprint(sys.argv)'");
+        assert!(!cmd.to_string().contains('\n'));
+    }
+
+    #[test]
+    fn test_render_command_many_arguments() {
+        let arguments: Vec<String> = (1..=40).map(|index| format!("file{}", index)).collect();
+        let cmd = CommandLine::new("touch".to_string(), arguments.clone(), None, None, None);
+
+        assert_eq!(cmd.render_command(RenderStyle::Full), format!("touch {}", arguments.join(" ")));
+        assert_eq!(
+            cmd.render_command(RenderStyle::Summary),
+            format!(
+                "touch {} … (+{} more)",
+                arguments[..SUMMARY_ARGUMENT_COUNT].join(" "),
+                40 - SUMMARY_ARGUMENT_COUNT
+            )
+        );
+
+        // Under an interpreter, the words of the shell command line are summarized
+        let sh = CommandLine::new("touch".to_string(), arguments, Some(Interpreter::Sh), None, None);
+        assert!(sh.render_command(RenderStyle::Full).starts_with("sh -c 'touch file1 file2"));
+        assert!(sh.to_string().starts_with("touch file1 file2"));
+        assert!(sh.to_string().ends_with(&format!("(+{} more)", 40 - SUMMARY_ARGUMENT_COUNT)));
+    }
+
+    #[test]
+    fn test_render_command_with_width() {
+        let arguments: Vec<String> = (1..=6).map(|index| format!("--option-number-{}", index)).collect();
+        let cmd = CommandLine::new("deploy".to_string(), arguments.clone(), None, None, None);
+
+        // Wide terminals show the whole summary
+        let wide: String = cmd.render_command_with_width(RenderStyle::Summary, 200);
+        assert_eq!(wide, format!("deploy {}", arguments.join(" ")));
+
+        // Narrow ones cut it to the width
+        let narrow: String = cmd.render_command_with_width(RenderStyle::Summary, 40);
+        assert_eq!(narrow.chars().count(), 40);
+        assert!(narrow.starts_with("deploy --option-number-1"));
+
+        // Full command lines are never cut
+        assert_eq!(cmd.render_command_with_width(RenderStyle::Full, 40), wide);
+    }
+
+    #[test]
+    fn test_environment_changes() {
+        let mut cmd: CommandLine = serde_json::from_str(
+            r#"{
+                "command": "env",
+                "arguments": [],
+                "environment_variables_override": {
+                    "NEW_VARIABLE": "added",
+                    "LANG": "C.UTF-8",
+                    "UNCHANGED": "same",
+                    "HOME": null,
+                    "NEVER_SET": null,
+                    "API_TOKEN": "hunter2"
+                }
+            }"#,
+        )
+        .unwrap();
+        cmd.set_pinned_environment(BTreeMap::from([("TZ".to_string(), "Europe/Berlin".to_string())]));
+        cmd.add_secret_value("hunter2".to_string());
+
+        let base_environment: BTreeMap<String, String> = BTreeMap::from([
+            ("LANG".to_string(), "en_US.UTF-8".to_string()),
+            ("UNCHANGED".to_string(), "same".to_string()),
+            ("HOME".to_string(), "/home/user".to_string()),
+            ("TZ".to_string(), "UTC".to_string()),
+        ]);
+        let changes: Vec<EnvironmentChange> = diff_environment(&base_environment, &cmd.get_process_command().unwrap());
+        assert_eq!(
+            changes,
+            vec![
+                EnvironmentChange::Added { name: "API_TOKEN".to_string(), value: "hunter2".to_string() },
+                EnvironmentChange::Removed { name: "HOME".to_string(), value: "/home/user".to_string() },
+                EnvironmentChange::Changed {
+                    name: "LANG".to_string(),
+                    old_value: "en_US.UTF-8".to_string(),
+                    new_value: "C.UTF-8".to_string()
+                },
+                EnvironmentChange::Added { name: "NEW_VARIABLE".to_string(), value: "added".to_string() },
+                EnvironmentChange::Changed {
+                    name: "TZ".to_string(),
+                    old_value: "UTC".to_string(),
+                    new_value: "Europe/Berlin".to_string()
+                },
+            ]
+        );
+
+        // Secrets are masked, and long values cut
+        let lines: Vec<String> = cmd.render_environment_changes(&changes);
+        assert_eq!(lines[0], format!("+ API_TOKEN={}", SECRET_MASK));
+        assert_eq!(lines[1], "- HOME");
+        assert_eq!(lines[2], "~ LANG: en_US.UTF-8 -> C.UTF-8");
+        let long_change = EnvironmentChange::Added { name: "LONG".to_string(), value: "x".repeat(500) };
+        assert!(cmd.render_environment_changes(&[long_change])[0].chars().count() < 100);
+    }
+}
@@ -0,0 +1,75 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use assert_cmd::Command;
+    use cchain::{arguments::CompletionShell, completions::generate_completion_script, Chain};
+
+    const FIXTURE_CHAIN: &str = r#"{
+        "metadata": {
+            "variables": { "branch": { "description": "Branch to deploy,\n  from origin" } }
+        },
+        "programs": [
+            {
+                "command": "echo",
+                "arguments": ["<<branch>>", "<<tag:on_program_execution>>", "<<?Anonymous prompt>>", "<<branch>>"],
+                "stdout_stored_to": "<<output>>",
+                "retry": 0
+            },
+            { "command": "echo", "arguments": ["<<output>>"], "retry": 0 }
+        ]
+    }"#;
+
+    fn cchain(home: &Path) -> Command {
+        let mut command = Command::cargo_bin("cchain").unwrap();
+        command.env("HOME", home).current_dir(home);
+        command
+    }
+
+    // Test that the hidden helper prints the variables that `--var` takes
+    #[test]
+    fn test_complete_vars() {
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("cchain_fixture.json"), FIXTURE_CHAIN).unwrap();
+
+        let output = cchain(directory.path()).args(["__complete-vars", "cchain_fixture.json"]).output().unwrap();
+        assert!(output.status.success());
+        assert_eq!(
+            String::from_utf8(output.stdout).unwrap(),
+            "branch=\tstartup: Branch to deploy, from origin\ntag=\ton program execution\n"
+        );
+
+        // A chain that cannot be resolved or loaded prints nothing at all
+        for chain in ["missing.json", "Cargo.toml"] {
+            std::fs::write(directory.path().join("Cargo.toml"), "not a chain").unwrap();
+            let output = cchain(directory.path()).args(["__complete-vars", chain]).output().unwrap();
+            assert!(output.status.success());
+            assert!(output.stdout.is_empty() && output.stderr.is_empty());
+        }
+
+        let chain = Chain::from_json_str(FIXTURE_CHAIN, "fixture").unwrap();
+        let names: Vec<String> = chain.get_variable_completions().into_iter().map(|completion| completion.name).collect();
+        assert_eq!(names, vec!["branch", "tag"]);
+    }
+
+    // Test that the scripts of each shell call the helper with the chain typed
+    #[test]
+    fn test_completion_scripts() {
+        for shell in [CompletionShell::Bash, CompletionShell::Zsh, CompletionShell::Fish] {
+            let script: String = generate_completion_script(shell);
+            assert!(script.contains("cchain __complete-vars"), "{:?}", shell);
+            assert!(script.contains("_cchain_chain_argument"), "{:?}", shell);
+            assert!(script.contains("--var-file"), "{:?}", shell);
+            assert!(!script.contains("__complete-vars\n"), "{:?}", shell);
+        }
+
+        let bash: String = generate_completion_script(CompletionShell::Bash);
+        assert!(bash.contains("complete -o filenames -F _cchain cchain"));
+        // Hidden subcommands are not offered
+        let subcommands: &str = bash.lines().find(|line| line.contains("compgen -W \"run ")).unwrap();
+        assert!(subcommands.contains(" completions ") && !subcommands.contains("complete-vars"));
+        assert!(generate_completion_script(CompletionShell::Zsh).starts_with("#compdef cchain"));
+        assert!(generate_completion_script(CompletionShell::Fish)
+            .contains("-l var -x -a '(cchain __complete-vars (__cchain_chain_argument) 2>/dev/null)'"));
+    }
+}
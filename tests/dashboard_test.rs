@@ -0,0 +1,129 @@
+#[cfg(test)]
+mod tests {
+    use std::{sync::mpsc::channel, time::Duration};
+
+    use cchain::{
+        core::{
+            events::{ChainEvent, EventSender},
+            traits::Execution,
+        },
+        dashboard::{render_frame, DashboardState, StepStatus},
+        Chain,
+    };
+
+    fn started_state() -> DashboardState {
+        let mut state = DashboardState::new("deploy.json");
+        state.apply(
+            &ChainEvent::ChainStarted {
+                program_labels: vec!["cargo build".to_string(), "cargo test".to_string(), "cargo publish".to_string()],
+            },
+            Duration::ZERO,
+        );
+        state
+    }
+
+    fn get_statuses(state: &DashboardState) -> Vec<StepStatus> {
+        state.get_steps().iter().map(|step| step.get_status()).collect()
+    }
+
+    // Test that the events of a run map to the statuses of the steps
+    #[test]
+    fn test_events_to_step_statuses() {
+        let mut state = started_state();
+        assert_eq!(get_statuses(&state), vec![StepStatus::Pending; 3]);
+
+        state.apply(&ChainEvent::ProgramStarted { program_index: 0 }, Duration::from_secs(1));
+        state.apply(
+            &ChainEvent::ProgramOutput { program_index: 0, text: "Compiling cchain\n".to_string() },
+            Duration::from_secs(2),
+        );
+        assert_eq!(get_statuses(&state), vec![StepStatus::Running, StepStatus::Pending, StepStatus::Pending]);
+        state.apply(&ChainEvent::ProgramFinished { program_index: 0, succeeded: true }, Duration::from_secs(5));
+
+        // Retries start the step again without restarting its time
+        state.apply(&ChainEvent::ProgramStarted { program_index: 1 }, Duration::from_secs(5));
+        state.apply(&ChainEvent::ProgramStarted { program_index: 1 }, Duration::from_secs(6));
+        state.apply(&ChainEvent::ProgramFinished { program_index: 1, succeeded: false }, Duration::from_secs(7));
+        state.apply(&ChainEvent::ChainFinished { succeeded: false }, Duration::from_secs(7));
+
+        assert_eq!(get_statuses(&state), vec![StepStatus::Succeeded, StepStatus::Failed, StepStatus::Skipped]);
+        assert_eq!(state.get_steps()[0].get_elapsed(), Some(Duration::from_secs(4)));
+        assert_eq!(state.get_steps()[1].get_elapsed(), Some(Duration::from_secs(2)));
+        assert_eq!(state.get_steps()[0].get_output(), "Compiling cchain\n");
+        assert_eq!(state.get_outcome(), Some(false));
+    }
+
+    // Test that the selection follows the running step until the user selects one
+    #[test]
+    fn test_selection_follows_running_step() {
+        let mut state = started_state();
+        state.apply(&ChainEvent::ProgramStarted { program_index: 1 }, Duration::ZERO);
+        assert_eq!(state.get_selected_step(), 1);
+
+        state.select_previous_step();
+        state.apply(&ChainEvent::ProgramStarted { program_index: 2 }, Duration::ZERO);
+        assert_eq!(state.get_selected_step(), 0);
+    }
+
+    // Test that the ETA is the mean time of the finished steps for each step left
+    #[test]
+    fn test_eta() {
+        let mut state = started_state();
+        assert_eq!(state.get_eta(Duration::ZERO), None);
+
+        state.apply(&ChainEvent::ProgramStarted { program_index: 0 }, Duration::ZERO);
+        state.apply(&ChainEvent::ProgramFinished { program_index: 0, succeeded: true }, Duration::from_secs(10));
+        state.apply(&ChainEvent::ProgramStarted { program_index: 1 }, Duration::from_secs(10));
+        // 6 seconds left of the running step, 10 seconds for the pending one
+        assert_eq!(state.get_eta(Duration::from_secs(14)), Some(Duration::from_secs(16)));
+    }
+
+    // Test that a frame is rendered for a synthetic state within the size
+    #[test]
+    fn test_render_frame() {
+        let mut state = started_state();
+        state.apply(&ChainEvent::ProgramStarted { program_index: 0 }, Duration::ZERO);
+        let output: String = (0..30).map(|line| format!("line {}\n", line)).collect();
+        state.apply(&ChainEvent::ProgramOutput { program_index: 0, text: output }, Duration::ZERO);
+
+        let frame: Vec<String> = render_frame(&state, Duration::from_secs(3), 60, 16);
+        assert_eq!(frame.len(), 16);
+        assert!(frame[0].contains("deploy.json"));
+        assert!(frame.iter().any(|line| line.contains("#1 cargo test")));
+        assert!(frame.iter().any(|line| line.contains("00:03")));
+        // The end of the output of the selected step is shown
+        assert!(frame.iter().any(|line| line == "line 29"));
+        assert!(!frame.iter().any(|line| line == "line 0"));
+        assert!(frame[15].contains("0/3 done"));
+
+        state.scroll_up(100);
+        let frame: Vec<String> = render_frame(&state, Duration::from_secs(3), 60, 16);
+        assert!(frame.iter().any(|line| line == "line 0"));
+    }
+
+    // Test that a chain reports its run as events
+    #[test]
+    fn test_chain_events() {
+        let mut chain = Chain::from_json_str(
+            r#"[
+                { "command": "echo", "arguments": ["hello"], "retry": 0 },
+                { "command": "false", "arguments": [], "retry": 0 },
+                { "command": "echo", "arguments": ["never"], "retry": 0 }
+            ]"#,
+            "events",
+        )
+        .unwrap();
+        let (sender, receiver) = channel();
+        chain.set_event_sender(EventSender::new(sender));
+        assert!(chain.execute().is_err());
+
+        let mut state = DashboardState::new("events");
+        for event in receiver.try_iter() {
+            state.apply(&event, Duration::ZERO);
+        }
+        assert_eq!(get_statuses(&state), vec![StepStatus::Succeeded, StepStatus::Failed, StepStatus::Skipped]);
+        assert_eq!(state.get_steps()[0].get_label(), "echo hello");
+        assert_eq!(state.get_steps()[0].get_output().trim(), "hello");
+        assert_eq!(state.get_outcome(), Some(false));
+    }
+}
@@ -0,0 +1,207 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use assert_cmd::Command;
+    use cchain::marker::environment_fingerprint::{
+        is_version_probed, render_environment_notice, EnvironmentDifference, EnvironmentFingerprint, VersionProber,
+    };
+
+    struct StubProber(BTreeMap<&'static str, &'static str>);
+
+    impl VersionProber for StubProber {
+        fn probe_version(&self, command: &str) -> Option<String> {
+            self.0.get(command).map(|version| version.to_string())
+        }
+    }
+
+    fn fingerprint(versions: &[(&str, Option<&str>)], os_release: &str, digests: &[(&str, &str)]) -> EnvironmentFingerprint {
+        EnvironmentFingerprint {
+            command_versions: versions
+                .iter()
+                .map(|(command, version)| (command.to_string(), version.map(|version| version.to_string())))
+                .collect(),
+            os_release: Some(os_release.to_string()),
+            environment_digests: digests.iter().map(|(name, digest)| (name.to_string(), digest.to_string())).collect(),
+            is_salted: true,
+        }
+    }
+
+    #[test]
+    fn test_environment_notice() {
+        let previous = fingerprint(
+            &[("node", Some("v18.17.0")), ("git", Some("git version 2.40.0")), ("make", None)],
+            "Ubuntu 22.04",
+            &[("TOKEN", "aaaa"), ("REGION", "bbbb")],
+        );
+        assert_eq!(render_environment_notice(&previous, &previous), None);
+
+        // Commands without a version, or new to the chain, are not compared
+        let current = fingerprint(
+            &[("node", Some("v20.5.1")), ("git", Some("git version 2.40.0")), ("make", Some("4.3")), ("npm", Some("9"))],
+            "Ubuntu 22.04",
+            &[("TOKEN", "cccc"), ("REGION", "bbbb"), ("NEW", "dddd")],
+        );
+        assert_eq!(
+            current.get_differences(&previous),
+            vec![
+                EnvironmentDifference::CommandVersion {
+                    command: "node".to_string(),
+                    previous: "v18.17.0".to_string(),
+                    current: "v20.5.1".to_string(),
+                },
+                EnvironmentDifference::EnvironmentVariable { name: "TOKEN".to_string() },
+            ]
+        );
+        assert_eq!(
+            render_environment_notice(&previous, &current).unwrap(),
+            "The environment differs from the last successful run: node: v18.17.0 → v20.5.1, OS unchanged, 1 env var changed"
+        );
+
+        let current = fingerprint(&[("node", Some("v18.17.0"))], "Ubuntu 24.04", &[("TOKEN", "cccc"), ("REGION", "eeee")]);
+        assert_eq!(
+            render_environment_notice(&previous, &current).unwrap(),
+            "The environment differs from the last successful run: OS: Ubuntu 22.04 → Ubuntu 24.04, 2 env vars changed"
+        );
+    }
+
+    #[test]
+    fn test_collect_fingerprint() {
+        let prober = StubProber(BTreeMap::from([("node", "v20.5.1")]));
+        let environment_values = BTreeMap::from([
+            ("CCHAIN_FINGERPRINT_TEST_VALUE".to_string(), Some("secret value".to_string())),
+            ("CCHAIN_FINGERPRINT_TEST_UNSET".to_string(), None),
+        ]);
+        let commands = ["node".to_string(), "missing".to_string()];
+        let environment = EnvironmentFingerprint::collect(&commands, &environment_values, &prober, "salt");
+        assert_eq!(environment.command_versions["node"].as_deref(), Some("v20.5.1"));
+        assert_eq!(environment.command_versions["missing"], None);
+        assert!(environment.os_release.is_some());
+
+        // Values are kept as digests only
+        let digest: &str = &environment.environment_digests["CCHAIN_FINGERPRINT_TEST_VALUE"];
+        assert_eq!(digest.len(), 16);
+        assert!(!serde_json::to_string(&environment).unwrap().contains("secret value"));
+        assert_eq!(environment.environment_digests["CCHAIN_FINGERPRINT_TEST_UNSET"], "unset");
+
+        // The digests depend on the salt of the installation
+        let salted = EnvironmentFingerprint::collect(&commands, &environment_values, &prober, "other salt");
+        assert_ne!(salted.environment_digests["CCHAIN_FINGERPRINT_TEST_VALUE"], digest);
+        assert_ne!(digest, &cchain::commons::digest::sha256_hex(b"secret value")[..16]);
+
+        // Unsalted digests of older runs are not compared
+        let mut previous = environment.clone();
+        previous.is_salted = false;
+        assert!(salted.get_differences(&previous).is_empty());
+        assert_eq!(salted.get_differences(&environment).len(), 1);
+    }
+
+    // Test that only bare names of well-known tools are probed
+    #[test]
+    fn test_probed_commands() {
+        assert!(is_version_probed("node"));
+        assert!(!is_version_probed("./deploy.sh"));
+        assert!(!is_version_probed("scripts/node"));
+        assert!(!is_version_probed("deploy"));
+        assert!(!is_version_probed("<<tool>>"));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_version_probe_timeout() {
+        use std::{
+            os::unix::fs::PermissionsExt,
+            time::{Duration, Instant},
+        };
+
+        use cchain::marker::environment_fingerprint::{run_version_command, CommandVersionProber};
+
+        let directory = tempfile::tempdir().unwrap();
+        let write_script = |name: &str, body: &str| {
+            let path = directory.path().join(name);
+            std::fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+            std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        };
+
+        let versioned = write_script("versioned", "echo\necho 'versioned 1.2.3'\necho 'more details'");
+        assert_eq!(run_version_command(&versioned, Duration::from_secs(5)).as_deref(), Some("versioned 1.2.3"));
+        let on_stderr = write_script("on_stderr", "echo 'on_stderr 0.1' >&2");
+        assert_eq!(run_version_command(&on_stderr, Duration::from_secs(5)).as_deref(), Some("on_stderr 0.1"));
+
+        // A command that hangs is killed, and has no version
+        let hanging = write_script("hanging", "sleep 30");
+        let started_at = Instant::now();
+        assert_eq!(run_version_command(&hanging, Duration::from_millis(200)), None);
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+
+        // The versions are cached until the executable changes. The
+        // commands are looked up in the directory instead of `PATH`.
+        let node = write_script("node", "echo 'v20.5.1'");
+        let search_path = directory.path().as_os_str().to_os_string();
+        let cache_path = directory.path().join("cache").join("command_versions.json");
+        let prober = CommandVersionProber::new(Duration::from_secs(5), Some(cache_path.clone()))
+            .with_search_path(search_path.clone());
+        assert_eq!(prober.probe_version("node").as_deref(), Some("v20.5.1"));
+        prober.save_cache();
+        std::fs::write(&node, "#!/bin/sh\nexit 1\n").unwrap();
+        let modified = std::fs::metadata(&node).unwrap().modified().unwrap();
+        let file = std::fs::File::options().write(true).open(&node).unwrap();
+        file.set_modified(modified - Duration::from_secs(3600)).unwrap();
+        let prober = CommandVersionProber::new(Duration::from_secs(5), Some(cache_path)).with_search_path(search_path.clone());
+        assert_eq!(prober.probe_version("node"), None);
+        assert_eq!(prober.probe_version("npm"), None);
+
+        // Other commands and paths are never run
+        let marker = directory.path().join("ran");
+        write_script("deploy", &format!("touch {}", marker.display()));
+        let prober = CommandVersionProber::new(Duration::from_secs(5), None).with_search_path(search_path);
+        assert_eq!(prober.probe_version("deploy"), None);
+        assert_eq!(prober.probe_version(directory.path().join("deploy").to_str().unwrap()), None);
+        assert!(!marker.exists());
+    }
+
+    #[test]
+    fn test_environment_check_on_run() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("cchain_environment.json");
+        std::fs::write(
+            &path,
+            r#"[{ "command": "bash", "arguments": ["-c", "true", "env('CCHAIN_FINGERPRINT_RUN_VALUE')"], "retry": 0 }]"#,
+        )
+        .unwrap();
+        let run = |value: &str, arguments: &[&str]| {
+            let output = Command::cargo_bin("cchain")
+                .unwrap()
+                .env("HOME", directory.path())
+                .env_remove("CCHAIN_HOME")
+                .env("CCHAIN_TRACK_USAGE", "1")
+                .env("CCHAIN_FINGERPRINT_RUN_VALUE", value)
+                .arg("run")
+                .arg(&path)
+                .args(arguments)
+                .write_stdin("")
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr))
+        };
+
+        assert!(!run("first", &[]).contains("The environment differs"));
+        assert!(!run("first", &[]).contains("The environment differs"));
+        let output = run("second", &[]);
+        assert!(output.contains("OS unchanged, 1 env var changed"), "{}", output);
+        assert!(!run("third", &["--no-env-check"]).contains("The environment differs"));
+
+        let output = Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", directory.path())
+            .env_remove("CCHAIN_HOME")
+            .args(["stats", "--environment"])
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("CCHAIN_FINGERPRINT_RUN_VALUE"), "{}", stdout);
+        assert!(stdout.contains("bash"), "{}", stdout);
+    }
+}
@@ -0,0 +1,121 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, str::FromStr};
+
+    use cchain::{
+        arguments::{Arguments, Commands, ExecArguments, OutputFormat},
+        commons::utility::{build_exec_program, save_exec_program},
+        core::{
+            interpreter::Interpreter,
+            metadata::ChainFile,
+            options::{FailureHandlingOptions, StdoutStorageOptions},
+            program::Program,
+        },
+    };
+    use clap::Parser;
+
+    fn parse_exec_arguments(arguments: &[&str]) -> ExecArguments {
+        let arguments = Arguments::try_parse_from(
+            ["cchain", "exec"].iter().chain(arguments.iter())
+        ).unwrap();
+
+        match arguments.commands {
+            Commands::Exec(exec_arguments) => exec_arguments,
+            _ => panic!("Expected the exec subcommand"),
+        }
+    }
+
+    #[test]
+    fn test_build_program_with_defaults() {
+        let program = build_exec_program(&parse_exec_arguments(&["--", "echo", "hello"]));
+
+        assert_eq!(
+            program,
+            Program::new(
+                "echo".to_string(),
+                vec!["hello".to_string()],
+                None,
+                None,
+                None,
+                StdoutStorageOptions::default(),
+                None,
+                FailureHandlingOptions::default(),
+                None,
+                0,
+            )
+        );
+    }
+
+    #[test]
+    fn test_build_program_with_options() {
+        let program = build_exec_program(&parse_exec_arguments(&[
+            "--retry", "-1",
+            "--env", "GREETING=hello=world",
+            "--env", "EMPTY=",
+            "--workdir", "/tmp",
+            "--interpreter", "sh",
+            "--", "echo", "$GREETING", "--flag",
+        ]));
+
+        let mut environment_variables: HashMap<String, String> = HashMap::new();
+        environment_variables.insert("GREETING".to_string(), "hello=world".to_string());
+        environment_variables.insert("EMPTY".to_string(), "".to_string());
+        assert_eq!(
+            program,
+            Program::new(
+                "echo".to_string(),
+                vec!["$GREETING".to_string(), "--flag".to_string()],
+                Some(environment_variables),
+                Some("/tmp".to_string()),
+                None,
+                StdoutStorageOptions::default(),
+                Some(Interpreter::Sh),
+                FailureHandlingOptions::default(),
+                None,
+                -1,
+            )
+        );
+    }
+
+    #[test]
+    fn test_run_options() {
+        let arguments = parse_exec_arguments(&["--", "echo"]);
+        assert_eq!((arguments.timeout, arguments.store_to_file, arguments.output), (None, None, OutputFormat::Text));
+
+        let arguments = parse_exec_arguments(&[
+            "--timeout", "30", "--store-to-file", "out.txt", "--output", "json", "--", "echo",
+        ]);
+        assert_eq!(arguments.timeout, Some(30));
+        assert_eq!(arguments.store_to_file.as_deref(), Some("out.txt"));
+        assert_eq!(arguments.output, OutputFormat::Json);
+    }
+
+    #[test]
+    fn test_invalid_arguments_rejected() {
+        let exec = |arguments: &[&str]| {
+            Arguments::try_parse_from(["cchain", "exec"].iter().chain(arguments.iter()))
+        };
+
+        assert!(exec(&["--env", "NOT_A_PAIR", "--", "echo"]).is_err());
+        assert!(exec(&["--interpreter", "bash", "--", "echo"]).is_err());
+        assert!(exec(&[]).is_err());
+        assert!(exec(&["--timeout", "-1", "--", "echo"]).is_err());
+    }
+
+    // Test that the saved chain loads back into the same program
+    #[test]
+    fn test_saved_program_round_trips() {
+        let directory = tempfile::tempdir().unwrap();
+
+        let arguments = parse_exec_arguments(&[
+            "--retry", "2", "--env", "KEY=VALUE", "--interpreter", "sh", "--", "ls", "-la",
+        ]);
+        let filename = save_exec_program(&build_exec_program(&arguments), "listing", directory.path()).unwrap();
+        assert_eq!(filename, "cchain_listing.json");
+
+        let chain_file =
+            ChainFile::from_str(&std::fs::read_to_string(directory.path().join(&filename)).unwrap())
+                .unwrap();
+        assert_eq!(chain_file.programs, vec![build_exec_program(&arguments)]);
+    }
+}
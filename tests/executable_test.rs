@@ -0,0 +1,167 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        ffi::{OsStr, OsString},
+        path::{Path, PathBuf},
+    };
+
+    use cchain::commons::executable::{is_batch_script, resolve_argv, simplify_windows_path, ExecutableLookup};
+
+    fn create_file(directory: &Path, name: &str) -> PathBuf {
+        let path = directory.join(name);
+        std::fs::write(&path, "").unwrap();
+        path
+    }
+
+    fn join_paths(directories: &[&Path]) -> OsString {
+        std::env::join_paths(directories).unwrap()
+    }
+
+    fn argv(words: &[&str]) -> Vec<String> {
+        words.iter().map(|word| word.to_string()).collect()
+    }
+
+    // Test that commands without extensions are found with the extensions of `PATHEXT`, in its order
+    #[test]
+    fn test_windows_lookup_with_pathext() {
+        let first = tempfile::tempdir().unwrap();
+        let second = tempfile::tempdir().unwrap();
+        let npm = create_file(second.path(), "npm.cmd");
+        let python = create_file(second.path(), "python.exe");
+        let python_batch = create_file(second.path(), "python.bat");
+        let path = join_paths(&[first.path(), second.path()]);
+        let lookup = ExecutableLookup::windows(Some(OsStr::new(".COM;.EXE;.BAT;.CMD")));
+
+        assert_eq!(lookup.resolve("npm", &path, first.path()), Some(npm.clone()));
+        assert_eq!(lookup.resolve("python", &path, first.path()), Some(python));
+        // A command with an extension is only found with it
+        assert_eq!(lookup.resolve("python.bat", &path, first.path()), Some(python_batch));
+        assert_eq!(lookup.resolve("node", &path, first.path()), None);
+
+        // Only the extensions in `PATHEXT` are tried
+        let lookup = ExecutableLookup::windows(Some(OsStr::new(".EXE")));
+        assert_eq!(lookup.resolve("npm", &path, first.path()), None);
+        // An empty `PATHEXT` falls back to the default extensions
+        let lookup = ExecutableLookup::windows(Some(OsStr::new("")));
+        assert_eq!(lookup.resolve("npm", &path, first.path()), Some(npm));
+    }
+
+    // Test that names and extensions are compared regardless of case
+    #[test]
+    fn test_windows_lookup_ignores_case() {
+        let directory = tempfile::tempdir().unwrap();
+        let npm = create_file(directory.path(), "NPM.CMD");
+        let path = join_paths(&[directory.path()]);
+        let lookup = ExecutableLookup::windows(Some(OsStr::new(".exe;.cmd")));
+
+        assert_eq!(lookup.resolve("npm", &path, directory.path()), Some(npm.clone()));
+        assert_eq!(lookup.resolve("Npm.Cmd", &path, directory.path()), Some(npm));
+        assert_eq!(lookup.list_commands(&path).into_iter().collect::<Vec<String>>(), vec!["NPM".to_string()]);
+    }
+
+    // Test that batch scripts and other commands are spawned directly, so
+    // that `cmd` never parses the arguments unquoted
+    #[test]
+    fn test_resolve_argv() {
+        let directory = tempfile::tempdir().unwrap();
+        let npm = create_file(directory.path(), "npm.cmd");
+        let node = create_file(directory.path(), "node.exe");
+        let path = join_paths(&[directory.path()]);
+        let lookup = ExecutableLookup::windows(None);
+
+        assert_eq!(
+            resolve_argv(argv(&["npm", "install", "a&b|c^"]), &lookup, &path, directory.path()),
+            argv(&[&npm.to_string_lossy(), "install", "a&b|c^"])
+        );
+        assert_eq!(
+            resolve_argv(argv(&["node", "app.js"]), &lookup, &path, directory.path()),
+            argv(&[&node.to_string_lossy(), "app.js"])
+        );
+        // Commands that are not found are left to fail when spawned
+        assert_eq!(resolve_argv(argv(&["missing"]), &lookup, &path, directory.path()), argv(&["missing"]));
+        // Unix looks up commands by itself
+        assert_eq!(
+            resolve_argv(argv(&["npm", "install"]), &ExecutableLookup::Unix, &path, directory.path()),
+            argv(&["npm", "install"])
+        );
+        assert!(is_batch_script(Path::new("C:\\tools\\npm.CMD")));
+        assert!(!is_batch_script(Path::new("C:\\tools\\npm.exe")));
+    }
+
+    // Test that verbatim drive-letter and UNC paths are turned into their plain forms
+    #[test]
+    fn test_simplify_windows_path() {
+        assert_eq!(simplify_windows_path(r"\\?\C:\work\project"), r"C:\work\project");
+        assert_eq!(simplify_windows_path(r"\\?\UNC\server\share\project"), r"\\server\share\project");
+        assert_eq!(simplify_windows_path(r"\\server\share"), r"\\server\share");
+        assert_eq!(simplify_windows_path(r"D:\work"), r"D:\work");
+        assert_eq!(simplify_windows_path("/home/user"), "/home/user");
+        // Verbatim paths without a drive letter have no plain form
+        assert_eq!(simplify_windows_path(r"\\?\Volume{1234}\work"), r"\\?\Volume{1234}\work");
+    }
+
+    // Test that only executable files are found on Unix
+    #[cfg(unix)]
+    #[test]
+    fn test_unix_lookup() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = tempfile::tempdir().unwrap();
+        let script = create_file(directory.path(), "deploy");
+        create_file(directory.path(), "notes");
+        std::fs::set_permissions(&script, std::fs::Permissions::from_mode(0o755)).unwrap();
+        let path = join_paths(&[directory.path()]);
+
+        assert_eq!(ExecutableLookup::Unix.resolve("deploy", &path, directory.path()), Some(script.clone()));
+        assert_eq!(ExecutableLookup::Unix.resolve("notes", &path, directory.path()), None);
+        assert_eq!(ExecutableLookup::Unix.resolve("./deploy", &path, directory.path()), Some(directory.path().join("./deploy")));
+        assert_eq!(ExecutableLookup::Unix.list_commands(&path).into_iter().collect::<Vec<String>>(), vec!["deploy".to_string()]);
+    }
+
+    // Test that a `.cmd` shim in PATH runs as a command without its extension
+    #[cfg(windows)]
+    #[test]
+    fn test_cmd_shim_is_spawned() {
+        use cchain::core::{command::CommandLine, traits::Execution};
+        use std::collections::HashMap;
+
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::write(directory.path().join("greet.cmd"), "@echo hello from %1\r\n").unwrap();
+        let mut path = OsString::from(directory.path());
+        path.push(";");
+        path.push(std::env::var_os("PATH").unwrap_or_default());
+        let environment = HashMap::from([("PATH".to_string(), path.to_string_lossy().into_owned())]);
+        let mut command_line =
+            CommandLine::new("greet".to_string(), vec!["shim".to_string()], None, Some(environment), None);
+
+        let output = command_line.execute().unwrap()[0].get_output();
+        assert_eq!(output.trim(), "hello from shim");
+    }
+
+    // Test that a script is found in the working directory with the `PATHEXT`
+    // of the program, and that `&` in an argument is not run by `cmd`
+    #[cfg(windows)]
+    #[test]
+    fn test_batch_script_in_working_directory() {
+        use cchain::core::{command::CommandLine, traits::Execution};
+        use std::collections::HashMap;
+
+        let directory = tempfile::tempdir().unwrap();
+        std::fs::create_dir(directory.path().join("tools")).unwrap();
+        std::fs::write(directory.path().join("tools").join("greet.bat"), "@echo bat %1\r\n").unwrap();
+        std::fs::write(directory.path().join("tools").join("greet.cmd"), "@echo cmd %1\r\n").unwrap();
+        // `.BAT` comes first in the default `PATHEXT`
+        let environment = HashMap::from([("PATHEXT".to_string(), ".CMD;.BAT".to_string())]);
+        let mut command_line = CommandLine::new(
+            "tools\\greet".to_string(),
+            vec!["a&echo injected".to_string()],
+            None,
+            Some(environment),
+            Some(directory.path().display().to_string()),
+        );
+
+        let output = command_line.execute().unwrap()[0].get_output();
+        assert!(output.trim().starts_with("cmd "), "{}", output);
+        assert!(!output.lines().any(|line| line.trim() == "injected"), "{}", output);
+    }
+}
@@ -0,0 +1,755 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        time::{Duration, Instant},
+    };
+
+    use assert_cmd::Command;
+    use cchain::core::{
+        options::FailurePolicy,
+        status::{RunOutcome, RunStatus},
+    };
+    use tempfile::TempDir;
+
+    /// Write a chain into the directory, which is also used as the home
+    /// directory so that the bookmark of the user is not touched
+    fn create_chain(directory: &TempDir, content: &str) -> PathBuf {
+        let path = directory.path().join("cchain_fixture.json");
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn cchain(home: &Path) -> Command {
+        let mut command = Command::cargo_bin("cchain").unwrap();
+        command.env("HOME", home).current_dir(home);
+        command
+    }
+
+    /// Read the output of the child, and get a message once a line of it
+    /// contains the text
+    #[cfg(unix)]
+    fn wait_for_line(child: &mut std::process::Child, text: &'static str) -> std::sync::mpsc::Receiver<()> {
+        use std::io::BufRead;
+
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let stdout = child.stdout.take().unwrap();
+        std::thread::spawn(move || {
+            for line in std::io::BufReader::new(stdout).lines().map_while(Result::ok) {
+                if line.contains(text) {
+                    let _ = sender.send(());
+                }
+            }
+        });
+
+        receiver
+    }
+
+    fn run_chain(content: &str) -> i32 {
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_chain(&directory, content);
+        let output = cchain(directory.path())
+            .arg("run")
+            .arg(&path)
+            .write_stdin("")
+            .output()
+            .unwrap();
+
+        output.status.code().unwrap()
+    }
+
+    #[test]
+    fn test_exit_code_succeeded() {
+        let code = run_chain(r#"[{ "command": "true", "arguments": [], "retry": 0 }]"#);
+        assert_eq!(code, RunOutcome::Succeeded.get_code());
+        assert_eq!(code, 0);
+    }
+
+    #[test]
+    fn test_exit_code_validation_failed() {
+        let code = run_chain(r#"[{ "command": "true" "#);
+        assert_eq!(code, RunOutcome::ValidationFailed.get_code());
+
+        let code = run_chain(
+            r#"{ "metadata": { "strict": true }, "programs": [{ "command": "true", "arguments": [], "retry": 0, "future_field": 1 }] }"#,
+        );
+        assert_eq!(code, 2);
+    }
+
+    #[test]
+    fn test_exit_code_aborted() {
+        let code = run_chain(
+            r#"[
+                { "command": "false", "arguments": [], "retry": 0 },
+                { "command": "true", "arguments": [], "retry": 0 }
+            ]"#,
+        );
+        assert_eq!(code, RunOutcome::Aborted.get_code());
+        assert_eq!(code, 3);
+    }
+
+    #[test]
+    fn test_exit_code_completed_with_failures() {
+        let code = run_chain(
+            r#"[
+                { "command": "false", "arguments": [], "retry": 0, "failure_handling_options": { "exit_on_failure": false, "remedy_command_line": null } },
+                { "command": "true", "arguments": [], "retry": 0 }
+            ]"#,
+        );
+        assert_eq!(code, RunOutcome::CompletedWithFailures.get_code());
+        assert_eq!(code, 4);
+    }
+
+    #[cfg(feature = "package-check")]
+    #[test]
+    fn test_exit_code_missing_packages() {
+        let code = run_chain(r#"[{ "command": "cchain-package-that-does-not-exist", "arguments": [], "retry": 0 }]"#);
+        assert_eq!(code, RunOutcome::MissingPackages.get_code());
+        assert_eq!(code, 5);
+    }
+
+    #[test]
+    fn test_exit_code_input_required() {
+        let code = run_chain(r#"[{ "command": "echo", "arguments": ["<<name>>"], "retry": 0 }]"#);
+        assert_eq!(code, RunOutcome::InputRequired.get_code());
+        assert_eq!(code, 6);
+    }
+
+    #[test]
+    fn test_exit_code_usage() {
+        let directory = tempfile::tempdir().unwrap();
+        let output = cchain(directory.path()).args(["run", "--unknown-flag"]).output().unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Usage.get_code()));
+        assert_eq!(output.status.code(), Some(64));
+
+        let output = cchain(directory.path()).arg("--help").output().unwrap();
+        assert_eq!(output.status.code(), Some(0));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_exit_code_cancelled() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_chain(
+            &directory,
+            r#"[
+                { "command": "sh", "arguments": ["-c", "echo ready | tr r R; exec sleep 30"], "retry": 3 },
+                { "command": "touch", "arguments": ["skipped"], "retry": 0 },
+                { "command": "touch", "arguments": ["cleaned"], "retry": 0, "always_run": true }
+            ]"#,
+        );
+        let status_path = directory.path().join("status.json");
+
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("cchain"))
+            .env("HOME", directory.path())
+            .current_dir(directory.path())
+            .arg("run")
+            .arg(&path)
+            .arg("--status-file")
+            .arg(&status_path)
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        // The displayed command line does not contain what the program prints
+        let is_ready = wait_for_line(&mut child, "Ready");
+        is_ready.recv_timeout(Duration::from_secs(10)).unwrap();
+        let started_at = Instant::now();
+        unsafe { libc::kill(child.id() as i32, libc::SIGINT) };
+
+        let status = child.wait().unwrap();
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+        assert_eq!(status.code(), Some(RunOutcome::Cancelled.get_code()));
+        assert_eq!(status.code(), Some(130));
+        // Ctrl-C stops the chain, which still runs its cleanup program
+        assert!(!directory.path().join("skipped").exists());
+        assert!(directory.path().join("cleaned").exists());
+
+        let run_status: RunStatus = serde_json::from_str(&std::fs::read_to_string(&status_path).unwrap()).unwrap();
+        assert_eq!(run_status.outcome, RunOutcome::Cancelled);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_sigterm_is_forwarded() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_chain(
+            &directory,
+            r#"[
+                { "command": "sh", "arguments": ["-c", "trap 'echo stopped > trapped; kill $!; exit 143' TERM; echo ready; sleep 30 & wait"], "retry": 3 },
+                { "command": "touch", "arguments": ["skipped"], "retry": 0 },
+                { "command": "touch", "arguments": ["cleaned"], "retry": 0, "always_run": true }
+            ]"#,
+        );
+        let status_path = directory.path().join("status.json");
+
+        let mut child = std::process::Command::new(assert_cmd::cargo::cargo_bin("cchain"))
+            .env("HOME", directory.path())
+            .current_dir(directory.path())
+            .arg("run")
+            .arg(&path)
+            .arg("--status-file")
+            .arg(&status_path)
+            .stdout(std::process::Stdio::piped())
+            .spawn()
+            .unwrap();
+        // The signal is sent once the program has set its trap
+        let is_ready = wait_for_line(&mut child, "ready");
+        is_ready.recv_timeout(Duration::from_secs(10)).unwrap();
+        let started_at = Instant::now();
+        unsafe { libc::kill(child.id() as i32, libc::SIGTERM) };
+
+        let status = child.wait().unwrap();
+        assert!(started_at.elapsed() < Duration::from_secs(10));
+        assert_eq!(status.code(), Some(RunOutcome::Cancelled.get_code()));
+        // The program got the signal, is not retried, and the cleanup
+        // program still ran
+        assert_eq!(std::fs::read_to_string(directory.path().join("trapped")).unwrap(), "stopped\n");
+        assert!(!directory.path().join("skipped").exists());
+        assert!(directory.path().join("cleaned").exists());
+
+        let run_status: RunStatus = serde_json::from_str(&std::fs::read_to_string(&status_path).unwrap()).unwrap();
+        assert_eq!(run_status.outcome, RunOutcome::Cancelled);
+    }
+
+    #[test]
+    fn test_status_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_chain(
+            &directory,
+            r#"[
+                { "command": "true", "arguments": [], "retry": 0 },
+                { "command": "false", "arguments": [], "retry": 0, "failure_handling_options": { "exit_on_failure": false, "remedy_command_line": null } }
+            ]"#,
+        );
+        let status_path = directory.path().join("status.json");
+
+        cchain(directory.path())
+            .arg("run")
+            .arg(&path)
+            .arg("--status-file")
+            .arg(&status_path)
+            .assert()
+            .code(4);
+
+        let run_status: RunStatus = serde_json::from_str(&std::fs::read_to_string(&status_path).unwrap()).unwrap();
+        assert_eq!(run_status.exit_code, 4);
+        assert_eq!(run_status.outcome, RunOutcome::CompletedWithFailures);
+        assert_eq!(run_status.failed_programs, vec![1]);
+        assert_eq!(run_status.attempts.keys().copied().collect::<Vec<usize>>(), vec![0, 1]);
+        assert_eq!(run_status.attempts[&1][0].status, "exit status: 1");
+        assert!(run_status.duration_seconds >= 0.0);
+        assert!(run_status.timestamp > 0);
+    }
+
+    /// Run a concurrency group of a failing program and a slow one
+    /// under a failure policy
+    #[cfg(unix)]
+    fn run_failing_concurrency_group(policy: &str) -> (RunStatus, Duration, bool) {
+        let directory = tempfile::tempdir().unwrap();
+        let marker_path = directory.path().join("finished.txt");
+        let path = create_chain(
+            &directory,
+            &serde_json::json!({
+                "metadata": { "concurrency_groups": { "1": { "on_member_failure": policy } } },
+                "programs": [
+                    { "command": "false", "arguments": [], "concurrency_group": 1, "retry": 0 },
+                    {
+                        "command": "sh",
+                        "arguments": ["-c", format!("sleep 3 && touch {}", marker_path.display())],
+                        "concurrency_group": 1,
+                        "retry": 0
+                    }
+                ]
+            })
+            .to_string(),
+        );
+        let status_path = directory.path().join("status.json");
+
+        let started_at = Instant::now();
+        cchain(directory.path())
+            .arg("run")
+            .arg(&path)
+            .arg("--status-file")
+            .arg(&status_path)
+            .assert()
+            .code(3);
+        let elapsed = started_at.elapsed();
+
+        let run_status: RunStatus = serde_json::from_str(&std::fs::read_to_string(&status_path).unwrap()).unwrap();
+        (run_status, elapsed, marker_path.exists())
+    }
+
+    // Test that `fail_fast` kills the slow member promptly, and records it as cancelled
+    #[cfg(unix)]
+    #[test]
+    fn test_concurrency_group_fail_fast() {
+        let (run_status, elapsed, is_finished) = run_failing_concurrency_group("fail_fast");
+        assert!(elapsed < Duration::from_secs(2), "{:?}", elapsed);
+        assert!(!is_finished);
+        assert_eq!(run_status.failed_programs, vec![0]);
+        assert_eq!(run_status.cancelled_programs, vec![1]);
+        assert_eq!(run_status.attempts[&1][0].status, "cancelled");
+    }
+
+    // Test that `wait_all`, the default, lets the slow member complete
+    #[cfg(unix)]
+    #[test]
+    fn test_concurrency_group_wait_all() {
+        let (run_status, _, is_finished) = run_failing_concurrency_group("wait_all");
+        assert!(is_finished);
+        assert_eq!(run_status.failed_programs, vec![0]);
+        assert!(run_status.cancelled_programs.is_empty());
+    }
+
+    // Test that an `always_run` program runs after an abort, and the chain keeps the original failure code
+    #[cfg(unix)]
+    #[test]
+    fn test_always_run_after_abort() {
+        let directory = tempfile::tempdir().unwrap();
+        let cleaned_path = directory.path().join("cleaned.txt");
+        let skipped_path = directory.path().join("skipped.txt");
+        let path = create_chain(
+            &directory,
+            &serde_json::json!([
+                { "command": "true", "arguments": [], "retry": 0 },
+                { "command": "false", "arguments": [], "retry": 0 },
+                { "command": "touch", "arguments": [skipped_path], "retry": 0 },
+                { "command": "false", "arguments": [], "always_run": true, "retry": 0 },
+                { "command": "touch", "arguments": [cleaned_path], "always_run": true, "retry": 0 }
+            ])
+            .to_string(),
+        );
+        let status_path = directory.path().join("status.json");
+
+        cchain(directory.path())
+            .arg("run")
+            .arg(&path)
+            .arg("--status-file")
+            .arg(&status_path)
+            .write_stdin("")
+            .assert()
+            .code(RunOutcome::Aborted.get_code());
+
+        assert!(cleaned_path.exists());
+        assert!(!skipped_path.exists());
+        // The failure of the cleanup program is recorded after the original one
+        let run_status: RunStatus = serde_json::from_str(&std::fs::read_to_string(&status_path).unwrap()).unwrap();
+        assert_eq!(run_status.failed_programs, vec![1, 3]);
+    }
+
+    /// Run a chain of a failing program with `exit_on_failure` and a
+    /// program that touches a marker, with extra arguments of `cchain run`
+    fn run_with_failure_policy(exit_on_failure: bool, remedy: Option<&str>, arguments: &[&str]) -> (RunStatus, bool) {
+        let directory = tempfile::tempdir().unwrap();
+        let marker_path = directory.path().join("finished.txt");
+        let remedy_command_line = remedy.map(|command| serde_json::json!({ "command": command, "arguments": [] }));
+        let path = create_chain(
+            &directory,
+            &serde_json::json!([
+                {
+                    "command": "false",
+                    "arguments": [],
+                    "retry": 0,
+                    "failure_handling_options": { "exit_on_failure": exit_on_failure, "remedy_command_line": remedy_command_line }
+                },
+                { "command": "touch", "arguments": [marker_path], "retry": 0 }
+            ])
+            .to_string(),
+        );
+        let status_path = directory.path().join("status.json");
+
+        cchain(directory.path())
+            .arg("run")
+            .arg(&path)
+            .args(arguments)
+            .arg("--status-file")
+            .arg(&status_path)
+            .output()
+            .unwrap();
+
+        let run_status: RunStatus = serde_json::from_str(&std::fs::read_to_string(&status_path).unwrap()).unwrap();
+        (run_status, marker_path.exists())
+    }
+
+    #[test]
+    fn test_continue_on_failure_overrides_exit_on_failure() {
+        let (run_status, is_finished) = run_with_failure_policy(true, None, &[]);
+        assert_eq!(run_status.outcome, RunOutcome::Aborted);
+        assert_eq!(run_status.failure_policy, FailurePolicy::PerProgram);
+        assert!(!is_finished);
+
+        let (run_status, is_finished) = run_with_failure_policy(true, None, &["--continue-on-failure"]);
+        assert_eq!(run_status.outcome, RunOutcome::CompletedWithFailures);
+        assert_eq!(run_status.failed_programs, vec![0]);
+        assert_eq!(run_status.failure_policy, FailurePolicy::ContinueOnFailure);
+        assert!(is_finished);
+    }
+
+    #[test]
+    fn test_stop_on_first_failure_overrides_exit_on_failure() {
+        let (run_status, is_finished) = run_with_failure_policy(false, None, &[]);
+        assert_eq!(run_status.outcome, RunOutcome::CompletedWithFailures);
+        assert!(is_finished);
+
+        let (run_status, is_finished) = run_with_failure_policy(false, None, &["--stop-on-first-failure"]);
+        assert_eq!(run_status.outcome, RunOutcome::Aborted);
+        assert_eq!(run_status.failure_policy, FailurePolicy::StopOnFirstFailure { even_if_remedied: false });
+        assert!(!is_finished);
+
+        // A failure that is remedied follows `exit_on_failure`, unless told otherwise
+        let (run_status, is_finished) = run_with_failure_policy(false, Some("true"), &["--stop-on-first-failure"]);
+        assert_eq!(run_status.outcome, RunOutcome::CompletedWithFailures);
+        assert!(is_finished);
+
+        let (run_status, is_finished) =
+            run_with_failure_policy(false, Some("true"), &["--stop-on-first-failure", "--stop-even-if-remedied"]);
+        assert_eq!(run_status.outcome, RunOutcome::Aborted);
+        assert!(!is_finished);
+    }
+
+    #[test]
+    fn test_failure_policies_are_exclusive() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_chain(&directory, r#"[{ "command": "true", "arguments": [], "retry": 0 }]"#);
+        cchain(directory.path())
+            .arg("run")
+            .arg(&path)
+            .args(["--continue-on-failure", "--stop-on-first-failure"])
+            .assert()
+            .code(RunOutcome::Usage.get_code());
+        // `--keep-going` is about the chains of a tag, not the programs of a chain
+        cchain(directory.path())
+            .arg("run")
+            .arg(&path)
+            .arg("--keep-going")
+            .assert()
+            .code(RunOutcome::Usage.get_code());
+    }
+
+    // Test that a chain given on the command line runs like a chain file
+    #[test]
+    fn test_inline_chain() {
+        let directory = tempfile::tempdir().unwrap();
+        let output = cchain(directory.path())
+            .args(["run", "--inline", r#"[{ "command": "echo", "arguments": ["inline-output"], "retry": 0 }]"#])
+            .write_stdin("")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Succeeded.get_code()));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("inline-output"));
+
+        let output = cchain(directory.path())
+            .args([
+                "run",
+                "--inline",
+                r#"[{ "command": "true", "arguments": [], "retry": 0 }, { "command": "false", "arguments": [], "retry": 1 }]"#,
+            ])
+            .write_stdin("")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Aborted.get_code()));
+
+        let output = cchain(directory.path()).args(["run", "--inline", "[{"]).write_stdin("").output().unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::ValidationFailed.get_code()));
+    }
+
+    // Test that a program killed by another one is reported as killed in the summary
+    #[cfg(unix)]
+    #[test]
+    fn test_killed_program_summary() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain = serde_json::json!([
+            { "command": "sh", "arguments": ["-c", "echo $$ > sleeper.pid; exec sleep 30"], "concurrency_group": 1, "retry": 0 },
+            { "command": "sh", "arguments": ["-c", "while [ ! -s sleeper.pid ]; do sleep 0.1; done; kill -KILL $(cat sleeper.pid)"], "concurrency_group": 1, "retry": 0 }
+        ]);
+        let output = cchain(directory.path())
+            .args(["run", "--inline", &chain.to_string()])
+            .write_stdin("")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Aborted.get_code()));
+        assert!(String::from_utf8_lossy(&output.stdout)
+            .contains("Program #0: Ended by SIGKILL: possibly out of memory, or killed by another process."));
+    }
+
+    // Test that the progress is written to the file descriptor while the
+    // normal output stays on stdout
+    #[test]
+    #[cfg(unix)]
+    fn test_progress_fd() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain = r#"[{ "command": "echo", "arguments": ["visible-output"], "retry": 0 }]"#;
+        let binary: PathBuf = assert_cmd::cargo::cargo_bin("cchain");
+        let output = Command::new("sh")
+            .args(["-c", r#"exec "$0" run --progress-fd 3 --inline "$1" 3>progress.jsonl"#])
+            .arg(&binary)
+            .arg(chain)
+            .env("HOME", directory.path())
+            .current_dir(directory.path())
+            .write_stdin("")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Succeeded.get_code()));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("visible-output"));
+
+        let progress: String = std::fs::read_to_string(directory.path().join("progress.jsonl")).unwrap();
+        let events: Vec<String> = progress
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["event"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(events, vec!["chain_started", "program_started", "program_finished", "chain_finished"]);
+
+        // A descriptor that is not open is a usage error
+        let output = cchain(directory.path()).args(["run", "--progress-fd", "97", "--inline", chain]).write_stdin("").output().unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Usage.get_code()));
+
+        // So are the standard streams, which the progress would be mixed into
+        for fd in ["0", "1", "2"] {
+            let output = cchain(directory.path()).args(["run", "--progress-fd", fd, "--inline", chain]).write_stdin("").output().unwrap();
+            assert_eq!(output.status.code(), Some(RunOutcome::Usage.get_code()));
+            let stdout = String::from_utf8_lossy(&output.stdout);
+            assert!(stdout.contains("is a standard stream") && !stdout.contains("visible-output"), "{}", stdout);
+        }
+    }
+
+    // Test that a summarized program shows a marker live, while its
+    // stored output keeps every line, and that `--show-all-output` shows all
+    #[test]
+    fn test_display_output_summary() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain = serde_json::json!([
+            { "command": "seq", "arguments": ["1", "100"], "display_output": "summary", "stdout_stored_to": "<<numbers>>", "retry": 0 },
+            { "command": "sh", "arguments": ["-c", "printf 'count=%s\\n' \"$(echo \"$0\" | wc -l | tr -d ' ')\"", "<<numbers>>"], "retry": 0 }
+        ])
+        .to_string();
+        let output = cchain(directory.path()).args(["run", "--inline", &chain]).write_stdin("").output().unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Succeeded.get_code()));
+        let stdout: String = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(stdout.contains("… suppressed 80 lines …"));
+        assert!(!stdout.contains("    50\n"));
+        assert!(stdout.contains("    100\n"));
+        assert!(stdout.contains("count=100"));
+
+        let output = cchain(directory.path())
+            .args(["run", "--show-all-output", "--inline", &chain])
+            .write_stdin("")
+            .output()
+            .unwrap();
+        let stdout: String = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(!stdout.contains("suppressed"));
+        assert!(stdout.contains("    50\n"));
+    }
+
+    // Test that `--limits` raises the limits on loading chains
+    #[test]
+    fn test_limits_override() {
+        let directory = tempfile::tempdir().unwrap();
+        let programs: Vec<serde_json::Value> =
+            (0..501).map(|_| serde_json::json!({ "command": "echo", "arguments": [], "retry": 0 })).collect();
+        let path = create_chain(&directory, &serde_json::Value::Array(programs).to_string());
+
+        let output = cchain(directory.path()).arg("check").arg(&path).output().unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::ValidationFailed.get_code()));
+        let output = cchain(directory.path()).arg("check").arg(&path).args(["--limits", "max_programs=501"]).output().unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Succeeded.get_code()));
+        let output = cchain(directory.path()).arg("check").arg(&path).args(["--limits", "max_depth=3"]).output().unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Usage.get_code()));
+    }
+
+    // Test that `--only` runs the selected programs, and rejects indices out of the chain
+    #[test]
+    fn test_only_selected_programs() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain = r#"[{ "command": "echo", "arguments": ["<<skipped>>"], "retry": 0 }, { "command": "echo", "arguments": ["selected-output"], "retry": 0 }]"#;
+        let output = cchain(directory.path()).args(["run", "--only", "1", "--inline", chain]).write_stdin("").output().unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Succeeded.get_code()));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("selected-output"));
+
+        let output = cchain(directory.path()).args(["run", "--only", "0,5", "--inline", chain]).write_stdin("").output().unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Usage.get_code()));
+    }
+
+    // Test that an inline chain is read from stdin with `-`, and from a file with `@path`
+    #[test]
+    fn test_inline_chain_sources() {
+        let directory = tempfile::tempdir().unwrap();
+        let output = cchain(directory.path())
+            .args(["run", "--inline", "-"])
+            .write_stdin(r#"[{ "command": "echo", "arguments": ["from-stdin"], "retry": 0 }]"#)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("from-stdin"));
+
+        let path = create_chain(&directory, r#"[{ "command": "echo", "arguments": ["from-file"], "retry": 0 }]"#);
+        let output = cchain(directory.path())
+            .args(["run", "--inline", &format!("@{}", path.display())])
+            .write_stdin("")
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0));
+        assert!(String::from_utf8_lossy(&output.stdout).contains("from-file"));
+
+        // The chain argument and an inline chain are exclusive
+        let output = cchain(directory.path())
+            .args(["run", path.to_str().unwrap(), "--inline", "[]"])
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Usage.get_code()));
+    }
+
+    // Test that listing the programs neither runs them nor asks for variables
+    #[test]
+    fn test_list_programs() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain = r#"[
+            { "command": "touch", "arguments": ["created.txt", "<<file_name>>"], "retry": 0, "alias": "create" },
+            { "command": "echo", "arguments": ["done"], "retry": 0 }
+        ]"#;
+        let path: PathBuf = create_chain(&directory, chain);
+        let output = cchain(directory.path()).arg("run").arg(&path).arg("--list-programs").write_stdin("").output().unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Succeeded.get_code()));
+        let stdout: String = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(stdout.contains("create") && stdout.contains("touch created.txt '<<file_name>>'"));
+        assert!(!stdout.contains("file_name:"));
+        assert!(!directory.path().join("created.txt").exists());
+
+        let output = cchain(directory.path())
+            .arg("run")
+            .arg(&path)
+            .args(["--list-programs", "--from", "1", "--output", "json"])
+            .write_stdin("")
+            .output()
+            .unwrap();
+        let listing: serde_json::Value = serde_json::from_slice(&output.stdout).unwrap();
+        assert_eq!(listing[1]["index"], 1);
+        assert_eq!(listing[0]["selected"], false);
+        assert_eq!(listing[1]["selected"], true);
+    }
+
+    // Test that `--summary-only` shows the statuses with their bytes of
+    // output, and the output of the failed program only
+    #[test]
+    fn test_summary_only() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_chain(
+            &directory,
+            r#"[
+                { "command": "echo", "arguments": ["quiet success"], "retry": 0 },
+                { "command": "sh", "arguments": ["-c", "echo loud failure; exit 2"], "retry": 0 }
+            ]"#,
+        );
+        let output = cchain(directory.path()).args(["run", "--summary-only"]).arg(&path).output().unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Aborted.get_code()));
+
+        let stdout: String = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("Running 2 program(s)"), "{}", stdout);
+        assert!(stdout.contains("✔ #0 echo 'quiet success' ("), "{}", stdout);
+        assert!(stdout.contains("exit 0, 14 bytes of output)"), "{}", stdout);
+        assert!(stdout.contains("exit 2, 13 bytes of output)"), "{}", stdout);
+        assert!(!stdout.contains("    quiet success"), "{}", stdout);
+        assert!(!stdout.contains("Start executing command"), "{}", stdout);
+        assert!(stdout.contains("End of the output of program #1:\n    loud failure"), "{}", stdout);
+    }
+
+    #[test]
+    fn test_diff_env() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_chain(
+            &directory,
+            r#"[{
+                "command": "true",
+                "arguments": [],
+                "environment_variables_override": { "CCHAIN_DIFF_ENV_TEST": "added", "HOME": null },
+                "retry": 0
+            }]"#,
+        );
+        let output = cchain(directory.path()).args(["run", "--diff-env"]).arg(&path).output().unwrap();
+        assert!(output.status.success());
+        let stdout: String = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("differs from the one of cchain in 2 variable(s)"), "{}", stdout);
+        assert!(stdout.contains("+ CCHAIN_DIFF_ENV_TEST=added"), "{}", stdout);
+        assert!(stdout.contains("- HOME"), "{}", stdout);
+
+        let output = cchain(directory.path()).args(["run", "--dry-run", "--diff-env"]).arg(&path).output().unwrap();
+        let stdout: String = String::from_utf8(output.stdout).unwrap();
+        assert!(stdout.contains("+ CCHAIN_DIFF_ENV_TEST=added"), "{}", stdout);
+
+        // Only under the flag
+        let output = cchain(directory.path()).arg("run").arg(&path).output().unwrap();
+        assert!(!String::from_utf8(output.stdout).unwrap().contains("CCHAIN_DIFF_ENV_TEST"));
+    }
+
+    /// Write a chain whose program fails with the message on its first
+    /// run, counted in a file, and succeeds on the second
+    #[cfg(unix)]
+    fn create_flaky_chain(directory: &TempDir, message: &str) -> PathBuf {
+        let counter_path = directory.path().join("runs");
+        let script = format!(
+            "echo <<target>> >> {counter}; if [ $(wc -l < {counter}) -lt 2 ]; then echo '{message}' >&2; exit 1; fi",
+            counter = counter_path.display(),
+            message = message
+        );
+        create_chain(directory, &serde_json::json!([{ "command": "sh", "arguments": ["-c", script], "retry": 0 }]).to_string())
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_retry_chain_on_infrastructure_errors() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_flaky_chain(&directory, "error: HTTP 503 Service Unavailable");
+        let status_path = directory.path().join("status.json");
+        let data_directory = directory.path().join("data");
+        let output = cchain(directory.path())
+            .env("CCHAIN_HOME", &data_directory)
+            .env("CCHAIN_TRACK_USAGE", "1")
+            .args(["run", "--retry-chain", "2", "--retry-chain-delay-ms", "0", "--var", "target=staging"])
+            .arg(&path)
+            .arg("--status-file")
+            .arg(&status_path)
+            .write_stdin("")
+            .output()
+            .unwrap();
+        let stdout: String = String::from_utf8(output.stdout).unwrap();
+        assert_eq!(output.status.code(), Some(0), "{}", stdout);
+        assert!(stdout.contains("Chain attempt #1 failed like an infrastructure error"), "{}", stdout);
+        assert!(stdout.contains("Chain attempt #2 of 3"), "{}", stdout);
+        assert_eq!(std::fs::read_to_string(directory.path().join("runs")).unwrap(), "staging\nstaging\n");
+        // The attempts make up a single run in the run history
+        let run_history = std::fs::read_to_string(data_directory.join("run_history.jsonl")).unwrap();
+        let records: Vec<serde_json::Value> =
+            run_history.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(records.len(), 1, "{}", run_history);
+        assert_eq!(records[0]["succeeded"], true);
+
+        let run_status: RunStatus = serde_json::from_str(&std::fs::read_to_string(&status_path).unwrap()).unwrap();
+        let outcomes: Vec<RunOutcome> = run_status.chain_attempts.iter().map(|attempt| attempt.outcome).collect();
+        assert_eq!(outcomes, vec![RunOutcome::Aborted, RunOutcome::Succeeded]);
+        assert_eq!(run_status.chain_attempts[0].failed_programs, vec![0]);
+        assert!(run_status.chain_attempts[0].retried_because.as_ref().unwrap().starts_with("program #0 matched"));
+
+        // A failure of the chain itself is not retried, unless it matches
+        // a pattern given
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_flaky_chain(&directory, "expected 3 replicas, found 2");
+        let output = cchain(directory.path())
+            .args(["run", "--retry-chain", "2", "--retry-chain-delay-ms", "0", "--var", "target=staging"])
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(RunOutcome::Aborted.get_code()));
+        assert!(String::from_utf8(output.stdout).unwrap().contains("Chain attempt #1 is not retried"));
+        assert_eq!(std::fs::read_to_string(directory.path().join("runs")).unwrap(), "staging\n");
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_flaky_chain(&directory, "expected 3 replicas, found 2");
+        let output = cchain(directory.path())
+            .args(["run", "--retry-chain", "1", "--retry-chain-on", "found \\d+", "--retry-chain-delay-ms", "0"])
+            .args(["--var", "target=staging"])
+            .arg(&path)
+            .output()
+            .unwrap();
+        assert_eq!(output.status.code(), Some(0));
+        assert_eq!(std::fs::read_to_string(directory.path().join("runs")).unwrap(), "staging\nstaging\n");
+    }
+}
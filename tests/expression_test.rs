@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, str::FromStr};
+
+    use cchain::expression::{Expression, TypedValue};
+
+    fn values() -> HashMap<String, TypedValue> {
+        let mut values = HashMap::new();
+        values.insert("workers".to_string(), TypedValue::Integer(4));
+        values.insert("verbose".to_string(), TypedValue::Boolean(true));
+        values.insert("quiet".to_string(), TypedValue::Boolean(false));
+        values
+    }
+
+    #[test]
+    fn test_is_expression() {
+        assert!(Expression::is_expression("workers * 2"));
+        assert!(Expression::is_expression("verbose ? --verbose : "));
+        assert!(Expression::is_expression("a - 1"));
+        assert!(!Expression::is_expression("workers"));
+        assert!(!Expression::is_expression("$name"));
+        assert!(!Expression::is_expression("namespace::var:on_program_execution"));
+        assert!(!Expression::is_expression("my-variable"));
+    }
+
+    #[test]
+    fn test_arithmetic() {
+        let expression = Expression::from_str("workers * 2 + 1").unwrap();
+        assert_eq!(expression.evaluate(&values()).unwrap(), "9");
+
+        let expression = Expression::from_str("(workers + 2) * 3 % 5").unwrap();
+        assert_eq!(expression.evaluate(&values()).unwrap(), "3");
+
+        let expression = Expression::from_str("-workers / 3").unwrap();
+        assert_eq!(expression.evaluate(&values()).unwrap(), "-1");
+    }
+
+    #[test]
+    fn test_comparisons_and_logic() {
+        let expression = Expression::from_str("workers >= 4 && !quiet").unwrap();
+        assert_eq!(expression.evaluate(&values()).unwrap(), "true");
+
+        let expression = Expression::from_str("workers < 2 || quiet").unwrap();
+        assert_eq!(expression.evaluate(&values()).unwrap(), "false");
+    }
+
+    #[test]
+    fn test_ternary() {
+        let expression = Expression::from_str("verbose ? --verbose : ").unwrap();
+        assert_eq!(expression.evaluate(&values()).unwrap(), "--verbose");
+
+        let expression = Expression::from_str("quiet ? --quiet : ").unwrap();
+        assert_eq!(expression.evaluate(&values()).unwrap(), "");
+
+        let expression = Expression::from_str("workers > 2 ? 'http://a:1' : 'http://b:2'").unwrap();
+        assert_eq!(expression.evaluate(&values()).unwrap(), "http://a:1");
+    }
+
+    #[test]
+    fn test_get_identifiers() {
+        let expression = Expression::from_str("workers * workers > 2 ? a : b").unwrap();
+        assert_eq!(expression.get_identifiers(), vec!["workers".to_string()]);
+    }
+
+    #[test]
+    fn test_evaluation_errors() {
+        let expression = Expression::from_str("workers / 0").unwrap();
+        assert!(expression.evaluate(&values()).is_err());
+
+        let expression = Expression::from_str("workers ? a : b").unwrap();
+        assert!(expression.evaluate(&values()).is_err());
+
+        let expression = Expression::from_str("verbose + 1").unwrap();
+        assert!(expression.evaluate(&values()).is_err());
+
+        let expression = Expression::from_str("unknown * 2").unwrap();
+        assert!(expression.evaluate(&values()).is_err());
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!(Expression::from_str("workers *").is_err());
+        assert!(Expression::from_str("(workers + 1").is_err());
+        assert!(Expression::from_str("verbose ? --verbose").is_err());
+    }
+}
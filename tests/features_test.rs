@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use assert_cmd::Command;
+    use cchain::commons::features::Feature;
+
+    fn cchain(home: &Path, arguments: &[&str]) -> (Option<i32>, String) {
+        let output = Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", home)
+            .env_remove("CCHAIN_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .current_dir(home)
+            .args(arguments)
+            .write_stdin("")
+            .output()
+            .unwrap();
+        let text = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        (output.status.code(), text)
+    }
+
+    #[cfg(not(all(feature = "llm", feature = "git", feature = "package-check")))]
+    fn run_chain(home: &Path, content: &str) -> (Option<i32>, String) {
+        let path = home.join("cchain_feature.json");
+        std::fs::write(&path, content).unwrap();
+        cchain(home, &["run", path.to_str().unwrap()])
+    }
+
+    // Test that the version tells the integrations cchain is built without
+    #[test]
+    fn test_version_lists_disabled_features() {
+        let home = tempfile::tempdir().unwrap();
+        let (code, output) = cchain(home.path(), &["version"]);
+        assert_eq!(code, Some(0));
+        for feature in Feature::ALL {
+            assert_eq!(output.contains(feature.get_name()), !feature.is_enabled(), "{}", output);
+        }
+    }
+
+    // Test that generating chains and `llm_generate` fail with a clear error
+    #[cfg(not(feature = "llm"))]
+    #[test]
+    fn test_without_llm() {
+        let home = tempfile::tempdir().unwrap();
+        let (code, output) = cchain(home.path(), &["new", "--prompt", "build the project"]);
+        assert_ne!(code, Some(0));
+        assert!(output.contains("built without LLM support, so generating chains with `--prompt` is unavailable"), "{}", output);
+
+        let (code, output) = run_chain(
+            home.path(),
+            r#"[{ "command": "echo", "arguments": ["llm_generate('Summarize')"], "retry": 0 }]"#,
+        );
+        assert_ne!(code, Some(0));
+        assert!(output.contains("built without LLM support, so `llm_generate` is unavailable"), "{}", output);
+    }
+
+    // Test that `git` steps and repositories fail with a clear error
+    #[cfg(not(feature = "git"))]
+    #[test]
+    fn test_without_git() {
+        let home = tempfile::tempdir().unwrap();
+        let (code, output) = run_chain(home.path(), r#"[{ "git": { "op": "current_branch" }, "retry": 0 }]"#);
+        assert_ne!(code, Some(0));
+        assert!(output.contains("built without git support, so the `git` step is unavailable"), "{}", output);
+
+        let (code, output) = cchain(home.path(), &["add", "https://github.com/AspadaX/cchain-chains"]);
+        assert_eq!(code, Some(1));
+        assert!(output.contains("built without git support, so adding chains from a repository is unavailable"), "{}", output);
+    }
+
+    // Test that missing commands are only found when they run, after a notice
+    #[cfg(not(feature = "package-check"))]
+    #[test]
+    fn test_without_package_check() {
+        let home = tempfile::tempdir().unwrap();
+        let (code, output) = run_chain(
+            home.path(),
+            r#"[{ "command": "cchain-package-that-does-not-exist", "arguments": [], "retry": 0 }]"#,
+        );
+        assert_ne!(code, Some(0));
+        assert_ne!(code, Some(5));
+        assert!(output.contains("built without package checking, so missing commands are only found when they run"), "{}", output);
+        assert!(output.contains("cchain-package-that-does-not-exist"), "{}", output);
+    }
+}
@@ -0,0 +1,293 @@
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use assert_cmd::Command;
+    use cchain::core::{
+        fix::{get_closest_fields, ChainFileFix},
+        format::format_chain_file,
+    };
+
+    fn write_fixture(directory: &Path, name: &str, content: &str) -> PathBuf {
+        let path = directory.join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    /// Apply the fixes, and check that the original is backed up
+    fn apply(path: &Path) -> (ChainFileFix, String) {
+        let original = std::fs::read_to_string(path).unwrap();
+        let chain_file_fix = ChainFileFix::from_path(path, false).unwrap();
+        let backup_path = chain_file_fix.apply().unwrap();
+        assert_eq!(backup_path, PathBuf::from(format!("{}.bak", path.display())));
+        assert_eq!(std::fs::read_to_string(&backup_path).unwrap(), original);
+        let fixed = std::fs::read_to_string(chain_file_fix.get_fixed_path()).unwrap();
+        // Fixed files are canonical
+        assert_eq!(format_chain_file(&fixed, false).unwrap(), fixed);
+
+        (chain_file_fix, fixed)
+    }
+
+    // Test that a chain file changed after it was read is left alone
+    #[test]
+    fn test_fix_changed_file() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = write_fixture(
+            directory.path(),
+            "cchain_changed.json",
+            r#"[{ "command": "echo", "arguments": [], "retry": 0, "awaitable_variable": "out" }]"#,
+        );
+        let chain_file_fix = ChainFileFix::from_path(&path, false).unwrap();
+        std::fs::write(&path, "[]").unwrap();
+        let error = chain_file_fix.apply().unwrap_err().to_string();
+        assert!(error.contains("was changed since it was read"), "{}", error);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "[]");
+        assert!(!directory.path().join("cchain_changed.json.bak").exists());
+    }
+
+    // Test that earlier backups are kept, and the next one is numbered
+    #[test]
+    fn test_fix_keeps_earlier_backups() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = write_fixture(
+            directory.path(),
+            "cchain_backups.json",
+            r#"[{ "command": "echo", "arguments": [], "retry": 0, "awaitable_variable": "out" }]"#,
+        );
+        let earlier_backup_path = directory.path().join("cchain_backups.json.bak");
+        std::fs::write(&earlier_backup_path, "earlier").unwrap();
+        let original = std::fs::read_to_string(&path).unwrap();
+
+        let backup_path = ChainFileFix::from_path(&path, false).unwrap().apply().unwrap();
+        assert_eq!(backup_path, directory.path().join("cchain_backups.json.bak.1"));
+        assert_eq!(std::fs::read_to_string(&backup_path).unwrap(), original);
+        assert_eq!(std::fs::read_to_string(&earlier_backup_path).unwrap(), "earlier");
+    }
+
+    #[test]
+    fn test_fix_legacy_fields() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = write_fixture(
+            directory.path(),
+            "cchain_legacy.json",
+            r#"[{ "command": "echo", "arguments": [], "retry": 0, "awaitable_variable": "out",
+                 "failure_handling_options": { "continue_on_failure": true } }]"#,
+        );
+        let (chain_file_fix, fixed) = apply(&path);
+        assert!(chain_file_fix.get_applied().contains(
+            &"Replaced the legacy `continue_on_failure` of program #0 with `exit_on_failure`".to_string()
+        ));
+        assert!(chain_file_fix.get_applied().contains(
+            &"Replaced the legacy `awaitable_variable` of program #0 with `stdout_stored_to`".to_string()
+        ));
+        assert!(fixed.contains(r#""exit_on_failure": false"#), "{}", fixed);
+        assert!(fixed.contains(r#""stdout_stored_to": "out""#), "{}", fixed);
+        assert!(!fixed.contains("continue_on_failure") && !fixed.contains("awaitable_variable"));
+
+        // Contradicting legacy fields are not for cchain to decide
+        let path = write_fixture(
+            directory.path(),
+            "cchain_contradiction.json",
+            r#"[{ "command": "echo", "arguments": [], "retry": 0,
+                 "failure_handling_options": { "continue_on_failure": true, "exit_on_failure": true } }]"#,
+        );
+        let chain_file_fix = ChainFileFix::from_path(&path, false).unwrap();
+        assert!(!chain_file_fix.is_changed());
+        assert!(chain_file_fix.get_untouched()[0].contains("contradict"));
+    }
+
+    #[test]
+    fn test_fix_misspelled_fields() {
+        assert_eq!(get_closest_fields("argumets", &["arguments", "command"]), vec!["arguments"]);
+        assert_eq!(get_closest_fields("retyr", &["retry", "retry_on"]), vec!["retry"]);
+        assert!(get_closest_fields("xyz", &["command"]).is_empty());
+        // Short fields are too close to everything
+        assert!(get_closest_fields("ab", &["a"]).is_empty());
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = write_fixture(
+            directory.path(),
+            "cchain_misspelled.json",
+            r#"{ "metadata": { "strcit": true },
+                 "programs": [{ "comand": "echo", "argumets": ["hi"], "retry": 0 }] }"#,
+        );
+        let (chain_file_fix, fixed) = apply(&path);
+        assert_eq!(
+            &chain_file_fix.get_applied()[..3],
+            [
+                "Renamed `strcit` in the metadata to `strict`",
+                "Renamed `argumets` in program #0 to `arguments`",
+                "Renamed `comand` in program #0 to `command`",
+            ]
+        );
+        assert!(fixed.contains(r#""strict": true"#) && fixed.contains(r#""command": "echo""#));
+
+        // A field that is already set, or with no close field, is left
+        let path = write_fixture(
+            directory.path(),
+            "cchain_unknown.json",
+            r#"[{ "command": "echo", "arguments": [], "argumets": ["x"], "retry": 0, "unrelated_field": 1 }]"#,
+        );
+        let original = std::fs::read_to_string(&path).unwrap();
+        let chain_file_fix = ChainFileFix::from_path(&path, false).unwrap();
+        assert!(!chain_file_fix.is_changed());
+        assert_eq!(chain_file_fix.get_fixed_content(), original);
+        let untouched = chain_file_fix.get_untouched().join("\n");
+        assert!(untouched.contains("`argumets` in program #0 looks like `arguments`, which is already set"), "{}", untouched);
+        assert!(untouched.contains("`unrelated_field` in program #0 is unknown"), "{}", untouched);
+        assert!(untouched.contains("unknown fields would be lost"), "{}", untouched);
+    }
+
+    #[test]
+    fn test_fix_file_name() {
+        let directory = tempfile::tempdir().unwrap();
+        let content = "[\n  {\n    \"command\": \"echo\",\n    \"arguments\": [],\n    \"retry\": 0\n  }\n]\n";
+        let path = write_fixture(directory.path(), "deploy.json", content);
+
+        // Bookmarked files keep their names
+        let chain_file_fix = ChainFileFix::from_path(&path, true).unwrap();
+        assert!(!chain_file_fix.is_changed());
+        assert!(chain_file_fix.get_untouched()[0].contains("it is bookmarked"));
+
+        let (chain_file_fix, fixed) = apply(&path);
+        assert_eq!(chain_file_fix.get_fixed_path(), directory.path().join("cchain_deploy.json"));
+        assert_eq!(fixed, content);
+        assert!(!path.exists());
+
+        // An existing file is not overwritten
+        let path = write_fixture(directory.path(), "deploy.json", content);
+        let chain_file_fix = ChainFileFix::from_path(&path, false).unwrap();
+        assert!(!chain_file_fix.is_changed());
+        assert!(chain_file_fix.get_untouched()[0].contains("already exists"));
+    }
+
+    #[test]
+    fn test_fix_formatting_and_placeholders() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = write_fixture(
+            directory.path(),
+            "cchain_format.json",
+            r#"[{"retry":0,"arguments":["<< name >>","<<workers * 2>>","<<  >>","<<? Commit message>>"],"command":"echo","concurrency_group":null}]"#,
+        );
+        let (chain_file_fix, fixed) = apply(&path);
+        assert_eq!(
+            chain_file_fix.get_applied(),
+            &vec!["Removed the spaces inside `<< name >>` in program #0".to_string(), "Formatted the file canonically".to_string()]
+        );
+        assert!(fixed.contains(r#""<<name>>""#) && fixed.contains(r#""<<workers * 2>>""#), "{}", fixed);
+        assert!(fixed.contains(r#""<<? Commit message>>""#), "{}", fixed);
+        assert!(chain_file_fix.get_untouched()[0].contains("`<<  >>` in program #0 may not be meant as a placeholder"));
+
+        // Canonical files have nothing to fix
+        let chain_file_fix = ChainFileFix::from_path(&path, false).unwrap();
+        assert!(!chain_file_fix.is_changed());
+        assert!(chain_file_fix.get_applied().is_empty());
+    }
+
+    #[test]
+    fn test_fix_infinite_retries() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = write_fixture(
+            directory.path(),
+            "cchain_retry.json",
+            "[\n  {\n    \"command\": \"curl\",\n    \"arguments\": [],\n    \"retry\": -1\n  }\n]\n",
+        );
+        let chain_file_fix = ChainFileFix::from_path(&path, false).unwrap();
+        assert!(!chain_file_fix.is_changed());
+        assert!(chain_file_fix.get_untouched()[0].starts_with("Program #0 retries forever"));
+    }
+
+    // Test that the verification steps are fixed as the programs are
+    #[test]
+    fn test_fix_verification_steps() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = write_fixture(
+            directory.path(),
+            "cchain_verify.json",
+            r#"{ "programs": [{ "command": "echo", "arguments": [], "retry": 0 }],
+                 "verify": [{ "command": "curl", "argumets": ["<< url >>"], "retry": -1, "awaitable_variable": "out" }] }"#,
+        );
+        let (chain_file_fix, fixed) = apply(&path);
+        let applied = chain_file_fix.get_applied();
+        assert!(applied.contains(&"Renamed `argumets` in verification step #1 to `arguments`".to_string()));
+        assert!(applied.contains(
+            &"Replaced the legacy `awaitable_variable` of verification step #1 with `stdout_stored_to`".to_string()
+        ));
+        assert!(applied.contains(&"Removed the spaces inside `<< url >>` in verification step #1".to_string()));
+        assert!(chain_file_fix.get_untouched()[0].starts_with("Verification step #1 retries forever"));
+        let value: serde_json::Value = serde_json::from_str(&fixed).unwrap();
+        assert_eq!(value["verify"][0]["arguments"][0], "<<url>>");
+        assert_eq!(value["verify"][0]["stdout_stored_to"], "out");
+    }
+
+    #[test]
+    fn test_check_fix_command() {
+        let directory = tempfile::tempdir().unwrap();
+        let original = r#"[{ "command": "echo", "argumets": ["hi"], "retry": 0 }]"#;
+        let path = write_fixture(directory.path(), "cchain_command.json", original);
+        let check = |arguments: &[&str]| {
+            Command::cargo_bin("cchain")
+                .unwrap()
+                .env("HOME", directory.path())
+                .env_remove("CCHAIN_HOME")
+                .arg("check")
+                .arg(&path)
+                .args(arguments)
+                .output()
+                .unwrap()
+        };
+
+        // A dry run shows the diff, and writes nothing
+        let output = check(&["--fix", "--dry-run"]);
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("Renamed `argumets` in program #0 to `arguments`"), "{}", stdout);
+        assert!(stdout.contains("+    \"arguments\": ["), "{}", stdout);
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), original);
+        assert!(!directory.path().join("cchain_command.json.bak").exists());
+
+        let output = check(&["--fix"]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+        assert!(std::fs::read_to_string(&path).unwrap().contains("\"arguments\""));
+        assert_eq!(std::fs::read_to_string(directory.path().join("cchain_command.json.bak")).unwrap(), original);
+
+        let output = check(&["--dry-run"]);
+        assert_eq!(output.status.code(), Some(64));
+    }
+
+    // Test that fixing and formatting keep the permissions of the chain file, and leave no lock file next to it
+    #[cfg(unix)]
+    #[test]
+    fn test_rewrites_keep_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let directory = tempfile::tempdir().unwrap();
+        let home = tempfile::tempdir().unwrap();
+        let path = write_fixture(directory.path(), "cchain_x.json", r#"[{ "command": "echo", "argumets": ["hi"], "retry": 0 }]"#);
+        std::fs::set_permissions(&path, std::fs::Permissions::from_mode(0o640)).unwrap();
+        let cchain = |arguments: &[&str]| {
+            let output = Command::cargo_bin("cchain")
+                .unwrap()
+                .env("CCHAIN_HOME", home.path())
+                .args(arguments)
+                .arg(&path)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+        };
+        let get_mode = |path: &Path| std::fs::metadata(path).unwrap().permissions().mode() & 0o777;
+
+        cchain(&["check", "--fix"]);
+        assert_eq!(get_mode(&path), 0o640);
+        assert_eq!(get_mode(&directory.path().join("cchain_x.json.bak")), 0o640);
+        std::fs::write(&path, r#"[{"command":"echo","arguments":["hi"],"retry":0}]"#).unwrap();
+        cchain(&["fmt"]);
+        assert_eq!(get_mode(&path), 0o640);
+
+        let mut names: Vec<String> =
+            std::fs::read_dir(directory.path()).unwrap().map(|entry| entry.unwrap().file_name().to_string_lossy().to_string()).collect();
+        names.sort();
+        assert_eq!(names, vec!["cchain_x.json", "cchain_x.json.bak"]);
+        assert!(home.path().join("locks").read_dir().unwrap().count() > 0);
+    }
+}
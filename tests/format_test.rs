@@ -0,0 +1,163 @@
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use cchain::{
+        core::format::{dedupe_consecutive_programs, format_chain_file},
+        Chain, Program,
+    };
+    use tempfile::TempDir;
+
+    /// A hand-edited chain, with shuffled keys, explicit nulls and defaults
+    const CHAIN: &str = r#"{
+    "programs": [
+        { "retry": 2, "arguments": ["build", "<<profile>>"], "command": "cargo",
+          "stdout_stored_to": null, "concurrency_group": null,
+          "failure_handling_options": { "remedy_command_line": null, "exit_on_failure": true },
+          "environment_variables_override": { "RUST_LOG": "debug", "CARGO_TERM_COLOR": "always" } },
+        { "command": "sh", "interpreter": "sh", "arguments": ["-c", "echo done"], "retry": 0,
+          "stdout_stored_to": "<<result>>",
+          "stdout_storage_options": { "without_newline_characters": true },
+          "failure_handling_options": { "exit_on_failure": false,
+            "remedy_command_line": { "command": "echo", "arguments": ["remedy"] } },
+          "assertions": [{ "contains": "done" }], "always_run": false, "alias": "finish" }
+    ],
+    "metadata": { "tags": ["release"], "variables": { "profile": { "type": "string", "description": "Cargo profile" } } }
+}"#;
+
+    fn write_chain(directory: &TempDir, name: &str, content: &str) -> PathBuf {
+        let path: PathBuf = directory.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn assert_same_programs(path: &Path, other_path: &Path) {
+        let chain: Chain = Chain::from_file(&path.to_string_lossy()).unwrap();
+        let other_chain: Chain = Chain::from_file(&other_path.to_string_lossy()).unwrap();
+        assert_eq!(chain.get_programs().len(), other_chain.get_programs().len());
+        for (program, other_program) in chain.get_programs().iter().zip(other_chain.get_programs()) {
+            let program: &Program = &program.lock().unwrap();
+            assert_eq!(program, &*other_program.lock().unwrap());
+        }
+    }
+
+    // Test that formatting does not change what the chain does
+    #[test]
+    fn test_format_is_lossless() {
+        let directory = TempDir::new().unwrap();
+        let original: PathBuf = write_chain(&directory, "cchain_original.json", CHAIN);
+        for explicit_defaults in [false, true] {
+            let formatted: String = format_chain_file(CHAIN, explicit_defaults).unwrap();
+            let formatted_path: PathBuf = write_chain(&directory, "cchain_formatted.json", &formatted);
+            assert_same_programs(&original, &formatted_path);
+
+            // Formatting is stable
+            assert_eq!(format_chain_file(&formatted, explicit_defaults).unwrap(), formatted);
+        }
+    }
+
+    // Test the field order, the indentation and the omitted defaults
+    #[test]
+    fn test_format_canonical_form() {
+        let formatted: String = format_chain_file(CHAIN, false).unwrap();
+        assert!(formatted.starts_with("{\n  \"metadata\": {\n    \"variables\": {"));
+        assert!(formatted.contains("\n  \"programs\": [\n    {\n      \"command\": \"cargo\",\n      \"arguments\": [\n"));
+        assert!(!formatted.contains("null"));
+        assert!(!formatted.contains("\"concurrency_group\""));
+        assert_eq!(formatted.matches("\"failure_handling_options\"").count(), 1);
+        assert!(formatted.ends_with("}\n"));
+
+        // Command line fields come first, in the order of their declaration
+        let position = |field: &str| formatted.find(&format!("\"{}\"", field)).unwrap();
+        assert!(position("environment_variables_override") < position("retry"));
+        assert!(position("RUST_LOG") > position("CARGO_TERM_COLOR"));
+
+        let explicit: String = format_chain_file(CHAIN, true).unwrap();
+        assert!(explicit.contains("\"stdout_stored_to\": null"));
+        assert!(explicit.contains("\"concurrency_group\": null"));
+    }
+
+    // Test that plain arrays stay arrays, and that unknown fields are not dropped
+    #[test]
+    fn test_format_array_and_unknown_fields() {
+        let formatted: String =
+            format_chain_file(r#"[{"arguments": ["hi"], "command": "echo", "retry": 0}]"#, false).unwrap();
+        assert_eq!(
+            formatted,
+            "[\n  {\n    \"command\": \"echo\",\n    \"arguments\": [\n      \"hi\"\n    ],\n    \"retry\": 0\n  }\n]\n"
+        );
+
+        let error: String =
+            format_chain_file(r#"[{"command": "echo", "arguments": [], "retry": 0, "timeout": 5}]"#, false)
+                .unwrap_err()
+                .to_string();
+        assert!(error.contains("`timeout` in program #0"));
+    }
+
+    // Test that environment variables removed with null are kept
+    #[test]
+    fn test_format_keeps_removed_environment_variables() {
+        let chain: &str = r#"[{"command": "git", "arguments": ["status"], "retry": 0,
+            "environment_variables_override": {"GIT_DIR": null}, "working_directory": null}]"#;
+        let formatted: String = format_chain_file(chain, false).unwrap();
+        assert!(formatted.contains("\"GIT_DIR\": null"));
+        assert!(!formatted.contains("working_directory"));
+    }
+
+    /// A generated chain with a repeated build, and a status shown twice
+    const DUPLICATED_CHAIN: &str = r#"[
+        { "command": "git", "arguments": ["status"], "retry": 0 },
+        { "command": "cargo", "arguments": ["build"], "retry": 0, "alias": "build" },
+        { "command": "cargo", "arguments": ["build"], "retry": 0, "display_output": "summary" },
+        { "command": "cargo", "arguments": ["build"], "retry": 1 },
+        { "command": "git", "arguments": ["status"], "retry": 0 }
+    ]"#;
+
+    // Test the lints of consecutive and separated identical programs
+    #[test]
+    fn test_lint_duplicate_programs() {
+        let directory = TempDir::new().unwrap();
+        let path: PathBuf = write_chain(&directory, "cchain_duplicated.json", DUPLICATED_CHAIN);
+        let mut chain = Chain::from_file(path.to_str().unwrap()).unwrap();
+
+        // Aliases and the display of the output do not change what runs,
+        // the retries do
+        let messages: Vec<String> = chain.lint();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("Program #2 is identical to program #1 and runs again right after it"));
+
+        chain.set_lint_separated_duplicates(true);
+        let messages: Vec<String> = chain.lint();
+        assert_eq!(messages.len(), 2);
+        assert_eq!(messages[1], "Program #4 is identical to program #0");
+
+        // The effects decide whether the chain asks for a confirmation
+        let programs: Vec<Program> = serde_json::from_str(
+            r#"[
+                { "command": "cargo", "arguments": ["build"], "retry": 0 },
+                { "command": "cargo", "arguments": ["build"], "retry": 0, "effects": ["writes-files"] }
+            ]"#,
+        )
+        .unwrap();
+        assert_ne!(programs[0].execution_fingerprint(), programs[1].execution_fingerprint());
+    }
+
+    // Test that removing the consecutive duplicates keeps the other programs in order
+    #[test]
+    fn test_dedupe_consecutive_programs() {
+        let (deduplicated, duplicates) = dedupe_consecutive_programs(DUPLICATED_CHAIN, false).unwrap();
+        assert_eq!(duplicates.len(), 1);
+        assert_eq!((duplicates[0].index, duplicates[0].original_index), (2, 1));
+
+        let programs: Vec<Program> = serde_json::from_str(&deduplicated).unwrap();
+        let commands: Vec<String> = programs.iter().map(|program| program.to_string()).collect();
+        assert_eq!(commands, vec!["git status", "cargo build", "cargo build", "git status"]);
+        assert_eq!(programs[1].get_alias(), Some(&"build".to_string()));
+        assert_ne!(programs[1].execution_fingerprint(), programs[2].execution_fingerprint());
+
+        // Nothing is left to remove
+        let (again, duplicates) = dedupe_consecutive_programs(&deduplicated, false).unwrap();
+        assert!(duplicates.is_empty());
+        assert_eq!(again, deduplicated);
+    }
+}
@@ -1,6 +1,7 @@
 #[cfg(test)]
 mod tests {
-    use cchain::function::Function;
+    use anyhow::anyhow;
+    use cchain::{core::command::CommandLine, function::Function, Chain};
     use std::str::FromStr;
 
     #[test]
@@ -39,4 +40,146 @@ mod tests {
             &vec!["param1".to_string(), "param2".to_string()]
         );
     }
+
+    // Test that a function with a single parameter is parsed, and `file` reads the file without the trailing newline
+    #[test]
+    fn test_file_function() {
+        let function = Function::from_str("file('/path/to/token')").unwrap();
+        assert_eq!(function.get_name(), "file");
+        assert_eq!(function.get_parameters(), &vec!["/path/to/token".to_string()]);
+
+        let mut temp_file = tempfile::NamedTempFile::new().unwrap();
+        std::io::Write::write_all(&mut temp_file, b"line 1\nline 2\n").unwrap();
+        let function = Function::from_str(&format!("file('{}')", temp_file.path().display())).unwrap();
+        assert_eq!(function.execute().unwrap(), "line 1\nline 2");
+
+        assert!(Function::from_str("file('/does/not/exist')").unwrap().execute().is_err());
+    }
+
+    // Test that `env` reads the environment, with an optional default
+    #[test]
+    fn test_env_function() {
+        // The variable is given to a run of cchain rather than set in this
+        // process, which the other tests share
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("cchain_env.json");
+        std::fs::write(
+            &path,
+            r#"[{ "command": "echo", "arguments": [
+                "env('CCHAIN_TEST_ENV_FUNCTION')",
+                "env('CCHAIN_TEST_ENV_FUNCTION', 'default')",
+                "env('CCHAIN_TEST_ENV_FUNCTION_UNSET', 'fallback value')"
+            ], "retry": 0 }]"#,
+        )
+        .unwrap();
+        let output = assert_cmd::Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", directory.path())
+            .env_remove("CCHAIN_HOME")
+            .env("CCHAIN_TEST_ENV_FUNCTION", "from the environment")
+            .env_remove("CCHAIN_TEST_ENV_FUNCTION_UNSET")
+            .arg("run")
+            .arg(&path)
+            .write_stdin("")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains("from the environment from the environment fallback value"), "{}", stdout);
+
+        let function = Function::from_str("env('CCHAIN_TEST_ENV_FUNCTION_UNSET', 'fallback value')").unwrap();
+        assert_eq!(function.execute().unwrap(), "fallback value");
+        let error: String = Function::from_str("env('CCHAIN_TEST_ENV_FUNCTION_UNSET')")
+            .unwrap()
+            .execute()
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("CCHAIN_TEST_ENV_FUNCTION_UNSET"));
+    }
+
+    // Test that only values that are a function call as a whole are function calls
+    #[test]
+    fn test_function_is_whole_value() {
+        assert!(Function::from_str(" env('HOME') ").is_ok());
+        assert!(Function::from_str("echo env('HOME')").is_err());
+        assert!(Function::from_str("env('HOME') is the home").is_err());
+        assert!(Function::from_str("--path=file('token')").is_err());
+    }
+
+    // Test that values shaped like calls of unknown functions stay text
+    #[test]
+    fn test_unknown_function_is_text() {
+        assert!(Function::from_str("print('hello')").is_err());
+        assert!(Function::from_str("render(<<name>>)").is_err());
+        assert!(Function::from_str("render('template', <<name>>)").is_err());
+
+        let mut command_line = CommandLine::new(
+            "echo".to_string(),
+            vec!["print('<<greeting>>')".to_string(), "render(<<name>>)".to_string()],
+            None,
+            None,
+            None,
+        );
+        command_line.inject_value_to_variables("<<greeting>>", "hello".to_string()).unwrap();
+        command_line.inject_value_to_variables("<<name>>", "cchain".to_string()).unwrap();
+        let arguments: Vec<String> =
+            command_line.get_arguments().iter().map(|argument| argument.get_value().to_string()).collect();
+        assert_eq!(arguments, vec!["print('hello')", "render(cchain)"]);
+        assert!(command_line.get_function_fields().iter().all(|(_, value)| Function::from_str(value).is_err()));
+    }
+
+    // Test that parameters can reference chain variables without quotes
+    #[test]
+    fn test_variable_parameters() {
+        let function = Function::from_str("llm_generate('Summarize this diff', <<diff_output>>)").unwrap();
+        assert_eq!(function.get_parameters(), &vec!["Summarize this diff".to_string(), "diff_output".to_string()]);
+        assert_eq!(function.get_variable_references(), vec!["diff_output"]);
+        assert_eq!(function.to_string(), "llm_generate('Summarize this diff', <<diff_output>>)");
+
+        let mut function = Function::from_str("env(<< name >>, 'fallback')").unwrap();
+        assert_eq!(function.get_variable_references(), vec!["name"]);
+        function
+            .resolve_variables(&|name| if name == "name" { Ok("CCHAIN_TEST_VARIABLE_PARAMETER".to_string()) } else { Err(anyhow!(name.to_string())) })
+            .unwrap();
+        assert_eq!(function.get_parameters(), &vec!["CCHAIN_TEST_VARIABLE_PARAMETER".to_string(), "fallback".to_string()]);
+        assert_eq!(function.execute().unwrap(), "fallback");
+
+        // Quoted placeholders stay literal text
+        assert!(Function::from_str("file('<<path>>')").unwrap().get_variable_references().is_empty());
+        assert!(Function::from_str("file(path)").is_err());
+    }
+
+    // Test that only the quoted parameters get the values injected into
+    // the text of a call, and that the others are resolved when it runs
+    #[test]
+    fn test_variable_parameters_in_command_line() {
+        let mut command_line = CommandLine::new(
+            "echo".to_string(),
+            vec!["env('<<prefix>>_NAME', <<fallback>>)".to_string(), "<<fallback>>".to_string()],
+            None,
+            None,
+            None,
+        );
+        command_line.inject_value_to_variables("<<prefix>>", "CCHAIN".to_string()).unwrap();
+        command_line.inject_value_to_variables("<<fallback>>", "it's, with quotes)".to_string()).unwrap();
+        let arguments: Vec<String> =
+            command_line.get_arguments().iter().map(|argument| argument.get_value().to_string()).collect();
+        assert_eq!(arguments, vec!["env('CCHAIN_NAME', <<fallback>>)", "it's, with quotes)"]);
+    }
+
+    // Test that a variable without a value fails with its name and the program
+    #[test]
+    fn test_unresolved_variable_parameter() {
+        let chain = Chain::from_json_str(
+            r#"[
+                { "command": "echo", "arguments": ["a"], "retry": 0 },
+                { "command": "echo", "arguments": ["llm_generate('Summarize', <<diff_output>>)"], "retry": 0 }
+            ]"#,
+            "cchain_unresolved.json",
+        )
+        .unwrap();
+        let error: String = chain.get_function_variable_value("diff_output", 1).unwrap_err().to_string();
+        assert!(error.contains("Program #1") && error.contains("<<diff_output>>"), "{}", error);
+        assert!(chain.get_function_variable_value("missing", 0).is_err());
+    }
 }
@@ -0,0 +1,382 @@
+#[cfg(all(test, feature = "git"))]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use assert_cmd::Command;
+    use cchain::{
+        commons::discovery::{DiscoveryOutcome, DiscoverySummary},
+        core::git::{clone_sparse, GitOperation, SparseClone},
+    };
+
+    /// Run git in the directory, with an identity for the commits
+    fn git(directory: &Path, arguments: &[&str]) -> String {
+        let output = std::process::Command::new("git")
+            .args(["-c", "user.name=cchain", "-c", "user.email=cchain@example.com"])
+            .args(arguments)
+            .current_dir(directory)
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "git {:?}: {}", arguments, String::from_utf8_lossy(&output.stderr));
+        String::from_utf8(output.stdout).unwrap().trim_end().to_string()
+    }
+
+    /// Create a repository on `main` with a commit per file
+    fn create_repository(directory: &Path, files: &[&str]) {
+        git(directory, &["init", "--quiet", "--initial-branch", "main"]);
+        for file in files {
+            std::fs::write(directory.join(file), file).unwrap();
+            git(directory, &["add", file]);
+            git(directory, &["commit", "--quiet", "-m", file]);
+        }
+    }
+
+    #[test]
+    fn test_git_operation_parsing() {
+        let operation: GitOperation = serde_json::from_str(r#"{ "op": "rev_parse" }"#).unwrap();
+        assert_eq!(operation, GitOperation::RevParse { reference: "HEAD".to_string(), short: false });
+        assert_eq!(operation.to_string(), "git rev-parse HEAD");
+
+        let operation: GitOperation =
+            serde_json::from_str(r#"{ "op": "clone", "url": "https://example.com/a.git", "path": "a", "depth": 1 }"#)
+                .unwrap();
+        assert_eq!(operation.to_string(), "git clone https://example.com/a.git a --depth 1");
+
+        assert!(serde_json::from_str::<GitOperation>(r#"{ "op": "push" }"#).is_err());
+    }
+
+    #[test]
+    fn test_rev_parse_and_current_branch() {
+        let directory = tempfile::tempdir().unwrap();
+        create_repository(directory.path(), &["a", "b"]);
+
+        let head: String = git(directory.path(), &["rev-parse", "HEAD"]);
+        let operation = GitOperation::RevParse { reference: "HEAD".to_string(), short: false };
+        assert_eq!(operation.execute(directory.path()).unwrap(), head);
+
+        let operation = GitOperation::RevParse { reference: "HEAD~1".to_string(), short: true };
+        assert_eq!(operation.execute(directory.path()).unwrap(), git(directory.path(), &["rev-parse", "--short", "HEAD~1"]));
+
+        let operation = GitOperation::RevParse { reference: "missing".to_string(), short: false };
+        assert!(operation.execute(directory.path()).unwrap_err().to_string().contains("Cannot resolve `missing`"));
+
+        // The repository is found from its subdirectories
+        std::fs::create_dir(directory.path().join("nested")).unwrap();
+        assert_eq!(GitOperation::CurrentBranch.execute(&directory.path().join("nested")).unwrap(), "main");
+    }
+
+    #[test]
+    fn test_checkout() {
+        let directory = tempfile::tempdir().unwrap();
+        create_repository(directory.path(), &["a"]);
+        git(directory.path(), &["branch", "feature"]);
+        git(directory.path(), &["switch", "--quiet", "feature"]);
+        std::fs::write(directory.path().join("b"), "b").unwrap();
+        git(directory.path(), &["add", "b"]);
+        git(directory.path(), &["commit", "--quiet", "-m", "b"]);
+        let feature: String = git(directory.path(), &["rev-parse", "HEAD"]);
+
+        let operation = GitOperation::Checkout { reference: "main".to_string() };
+        let main: String = operation.execute(directory.path()).unwrap();
+        assert_eq!(main, git(directory.path(), &["rev-parse", "main"]));
+        assert_eq!(GitOperation::CurrentBranch.execute(directory.path()).unwrap(), "main");
+        assert!(!directory.path().join("b").exists());
+
+        // Commits detach HEAD, and there is no current branch then
+        let operation = GitOperation::Checkout { reference: feature.clone() };
+        assert_eq!(operation.execute(directory.path()).unwrap(), feature);
+        assert!(directory.path().join("b").exists());
+        let error = GitOperation::CurrentBranch.execute(directory.path()).unwrap_err();
+        assert!(error.to_string().contains("detached"));
+    }
+
+    #[test]
+    fn test_clone_and_pull() {
+        let directory = tempfile::tempdir().unwrap();
+        let origin = directory.path().join("origin");
+        std::fs::create_dir(&origin).unwrap();
+        create_repository(&origin, &["a"]);
+
+        let operation = GitOperation::Clone {
+            url: origin.to_string_lossy().to_string(),
+            path: "clone".to_string(),
+            branch: Some("main".to_string()),
+            depth: None,
+        };
+        let clone: String = operation.execute(directory.path()).unwrap();
+        assert_eq!(Path::new(&clone), directory.path().join("clone"));
+        assert!(directory.path().join("clone/a").exists());
+
+        // Up to date first, then fast-forwarded to the new commit
+        let clone = Path::new(&clone);
+        assert_eq!(GitOperation::Pull.execute(clone).unwrap(), git(&origin, &["rev-parse", "HEAD"]));
+        std::fs::write(origin.join("b"), "b").unwrap();
+        git(&origin, &["add", "b"]);
+        git(&origin, &["commit", "--quiet", "-m", "b"]);
+        assert_eq!(GitOperation::Pull.execute(clone).unwrap(), git(&origin, &["rev-parse", "HEAD"]));
+        assert!(clone.join("b").exists());
+
+        // Diverged branches are not merged
+        std::fs::write(clone.join("c"), "c").unwrap();
+        git(clone, &["add", "c"]);
+        git(clone, &["commit", "--quiet", "-m", "c"]);
+        std::fs::write(origin.join("d"), "d").unwrap();
+        git(&origin, &["add", "d"]);
+        git(&origin, &["commit", "--quiet", "-m", "d"]);
+        let error = GitOperation::Pull.execute(clone).unwrap_err();
+        assert!(error.to_string().contains("diverged"));
+    }
+
+    #[test]
+    fn test_git_step_in_chain() {
+        let directory = tempfile::tempdir().unwrap();
+        create_repository(directory.path(), &["a"]);
+        let head: String = git(directory.path(), &["rev-parse", "HEAD"]);
+        let chain = r#"[
+            { "git": { "op": "rev_parse" }, "stdout_stored_to": "<<commit>>" },
+            { "command": "echo", "arguments": ["built <<commit>>"], "retry": 0 }
+        ]"#;
+        let path = directory.path().join("cchain_git.json");
+        std::fs::write(&path, chain).unwrap();
+
+        let output = Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", directory.path())
+            .current_dir(directory.path())
+            .arg("run")
+            .arg(&path)
+            .write_stdin("")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains(&format!("built {}", head)), "{}", stdout);
+
+        let chain = r#"[{ "git": { "op": "current_branch" }, "command": "echo", "arguments": [], "retry": 0 }]"#;
+        std::fs::write(&path, chain).unwrap();
+        let output = Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", directory.path())
+            .current_dir(directory.path())
+            .args(["check", "cchain_git.json"])
+            .output()
+            .unwrap();
+        assert!(!output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("a git step cannot also have a `command`"));
+    }
+
+    #[test]
+    fn test_git_step_variables() {
+        let directory = tempfile::tempdir().unwrap();
+        create_repository(directory.path(), &["a", "b"]);
+        let parent: String = git(directory.path(), &["rev-parse", "HEAD~1"]);
+        let chain = r#"[
+            { "command": "echo", "arguments": ["-n", "HEAD~1"], "stdout_stored_to": "<<reference>>", "retry": 0 },
+            { "git": { "op": "rev_parse", "ref": "<<reference>>" }, "stdout_stored_to": "<<commit>>" },
+            { "command": "echo", "arguments": ["parent <<commit>>"], "retry": 0 }
+        ]"#;
+        let path = directory.path().join("cchain_git.json");
+        std::fs::write(&path, chain).unwrap();
+
+        let output = Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", directory.path())
+            .current_dir(directory.path())
+            .arg("run")
+            .arg(&path)
+            .write_stdin("")
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains(&format!("parent {}", parent)), "{}", stdout);
+    }
+
+    /// Create a monorepo with chains in nested directories, one of them
+    /// invalid
+    fn create_monorepo(directory: &Path) {
+        let chain = r#"[{ "command": "echo", "arguments": ["hi"], "retry": 0 }]"#;
+        for (path, content) in [
+            ("cchain_root.json", chain),
+            ("services/api/cchain_build.json", chain),
+            ("services/api/cchain_broken.json", "not a chain"),
+            ("services/web/cchain_build.json", chain),
+            ("services/web/README.md", "web"),
+            ("tools/cchain_lint.json", chain),
+        ] {
+            let path = directory.join(path);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        }
+        git(directory, &["init", "--quiet", "--initial-branch", "main"]);
+        git(directory, &["add", "."]);
+        git(directory, &["commit", "--quiet", "-m", "chains"]);
+    }
+
+    #[test]
+    fn test_clone_sparse() {
+        let directory = tempfile::tempdir().unwrap();
+        let origin = directory.path().join("origin");
+        create_monorepo(&origin);
+
+        let clone_path = directory.path().join("clone");
+        let sparse_clone: SparseClone =
+            clone_sparse(&origin.to_string_lossy(), &clone_path, &["services/web/".to_string()]).unwrap();
+        assert_eq!(sparse_clone.tree_paths.len(), 6);
+        assert!(clone_path.join("services/web/cchain_build.json").exists());
+        assert!(clone_path.join("services/web/README.md").exists());
+        assert!(!clone_path.join("services/api").exists());
+        assert!(!clone_path.join("cchain_root.json").exists());
+        assert_eq!(git(&clone_path, &["config", "core.sparseCheckout"]), "true");
+    }
+
+    // Test that a sparse clone refuses a directory with files, which the
+    // forced checkout would overwrite
+    #[test]
+    fn test_clone_sparse_refuses_existing_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        let origin = directory.path().join("origin");
+        create_monorepo(&origin);
+
+        let clone_path = directory.path().join("clone");
+        std::fs::create_dir_all(clone_path.join("services/web")).unwrap();
+        std::fs::write(clone_path.join("services/web/cchain_build.json"), "mine").unwrap();
+        let error = clone_sparse(&origin.to_string_lossy(), &clone_path, &["services/web/".to_string()]).unwrap_err();
+        assert!(error.to_string().contains("is not an empty directory"), "{}", error);
+        assert_eq!(std::fs::read_to_string(clone_path.join("services/web/cchain_build.json")).unwrap(), "mine");
+        assert!(!clone_path.join(".git").exists());
+    }
+
+    // Test that a full clone finds the same chains as a sparse one
+    #[test]
+    fn test_add_repository_finds_nested_chains() {
+        let directory = tempfile::tempdir().unwrap();
+        let origin = directory.path().join("origin");
+        create_monorepo(&origin);
+        let list = |arguments: &[&str]| {
+            let output = Command::cargo_bin("cchain")
+                .unwrap()
+                .env("HOME", directory.path())
+                .env_remove("CCHAIN_HOME")
+                .env_remove("XDG_DATA_HOME")
+                .current_dir(directory.path())
+                .arg("add")
+                .arg(format!("file://{}", origin.to_string_lossy()))
+                .arg("--list-only")
+                .args(arguments)
+                .output()
+                .unwrap();
+            assert!(output.status.success());
+            String::from_utf8_lossy(&output.stdout).to_string()
+        };
+
+        let full: String = list(&[]);
+        let sparse: String = list(&["--sparse", "services", "tools", "cchain_root.json"]);
+        for directory in ["services/api", "services/web", "tools"] {
+            assert!(full.contains(directory), "{}", full);
+            assert!(sparse.contains(directory), "{}", sparse);
+        }
+    }
+
+    // Test that chains are found across the sparse paths, and only listed with `--list-only`
+    #[test]
+    fn test_add_sparse_repository() {
+        let directory = tempfile::tempdir().unwrap();
+        let origin = directory.path().join("origin");
+        create_monorepo(&origin);
+        let url: String = format!("file://{}", origin.to_string_lossy());
+        let workspace = directory.path().join("workspace");
+        std::fs::create_dir(&workspace).unwrap();
+        let add = |arguments: &[&str]| {
+            Command::cargo_bin("cchain")
+                .unwrap()
+                .env("HOME", directory.path())
+                .env_remove("CCHAIN_HOME")
+                .env_remove("XDG_DATA_HOME")
+                .current_dir(&workspace)
+                .arg("add")
+                .arg(&url)
+                .args(arguments)
+                .output()
+                .unwrap()
+        };
+
+        let output = add(&["--sparse", "services", "docs", "--list-only"]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains("Would be added") && stdout.contains("services/api"), "{}", stdout);
+        assert!(stdout.contains("No file of the repository is under the sparse path `docs`"), "{}", stdout);
+        assert!(stdout.contains("Nothing is added with `--list-only`"), "{}", stdout);
+        assert!(!workspace.join("origin").exists());
+        assert!(!directory.path().join(".cchain/repos").exists());
+        let bookmark = std::fs::read_to_string(directory.path().join(".cchain/bookmark.json")).unwrap_or_default();
+        assert!(!bookmark.contains("cchain_build.json"), "{}", bookmark);
+
+        let output = add(&["--sparse", "services"]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        // Clones are kept in the storage, not in the current directory
+        assert!(std::fs::read_dir(&workspace).unwrap().next().is_none());
+        let clone_path: PathBuf = get_clones(&directory.path().join(".cchain/repos")).remove(0);
+        assert!(clone_path.file_name().unwrap().to_string_lossy().starts_with("origin-"));
+        assert!(clone_path.join("services/api/cchain_build.json").exists());
+        assert!(!clone_path.join("tools").exists());
+        let bookmark = std::fs::read_to_string(directory.path().join(".cchain/bookmark.json")).unwrap();
+        assert!(bookmark.contains("services/web/cchain_build.json"), "{}", bookmark);
+        assert!(!bookmark.contains("cchain_broken.json") && !bookmark.contains("cchain_lint.json"), "{}", bookmark);
+    }
+
+    fn get_clones(repos_directory: &Path) -> Vec<PathBuf> {
+        match std::fs::read_dir(repos_directory) {
+            Ok(entries) => entries.map(|entry| entry.unwrap().path()).collect(),
+            Err(_) => Vec::new(),
+        }
+    }
+
+    // Test that `cchain gc` keeps the clones of bookmarked chains, and removes those no bookmarked chain is in
+    #[test]
+    fn test_gc_removes_orphaned_clones() {
+        let directory = tempfile::tempdir().unwrap();
+        let origin = directory.path().join("origin");
+        create_monorepo(&origin);
+        let home = directory.path().join("home");
+        let repos_directory = home.join("repos");
+        let cchain = |arguments: &[&str]| {
+            let output = Command::cargo_bin("cchain")
+                .unwrap()
+                .env("CCHAIN_HOME", &home)
+                .current_dir(directory.path())
+                .args(arguments)
+                .output()
+                .unwrap();
+            assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+        };
+
+        cchain(&["add", &format!("file://{}", origin.to_string_lossy())]);
+        let clones: Vec<PathBuf> = get_clones(&repos_directory);
+        assert_eq!(clones.len(), 1);
+        cchain(&["gc"]);
+        assert!(clones[0].join("cchain_root.json").exists());
+
+        cchain(&["remove", "--reset"]);
+        cchain(&["gc", "--dry-run"]);
+        assert!(clones[0].exists());
+        cchain(&["gc"]);
+        assert!(get_clones(&repos_directory).is_empty());
+    }
+
+    #[test]
+    fn test_discovery_summary() {
+        let mut discovery_summary = DiscoverySummary::new();
+        discovery_summary.push(PathBuf::from("cchain_root.json"), DiscoveryOutcome::OutsideSparsePaths);
+        discovery_summary.push(PathBuf::from("services/api/cchain_build.json"), DiscoveryOutcome::Added);
+        discovery_summary.push(PathBuf::from("services/api/cchain_broken.json"), DiscoveryOutcome::Invalid("broken".to_string()));
+        discovery_summary.push(PathBuf::from("services/web/cchain_build.json"), DiscoveryOutcome::Duplicate);
+
+        let directory_summaries = discovery_summary.get_directory_summaries();
+        let directories: Vec<&str> = directory_summaries.iter().map(|summary| summary.directory.as_str()).collect();
+        assert_eq!(directories, vec![".", "services/api", "services/web"]);
+        assert_eq!((directory_summaries[1].found, directory_summaries[1].added, directory_summaries[1].invalid), (2, 1, 1));
+        let total = discovery_summary.get_total();
+        assert_eq!((total.found, total.added, total.duplicates, total.outside_sparse_paths), (4, 1, 1, 1));
+    }
+}
@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+    use cchain::{
+        commons::interaction::{ScriptedInteractor, SharedInteractor},
+        core::interpreter::Interpreter,
+        generations::{
+            create::ChainCreation,
+            history::{
+                create_history_program, get_recent_commands, parse_history, parse_selection, split_shell_words,
+                HistoryFormat,
+            },
+        },
+        Chain,
+    };
+    use serde_json::Value;
+
+    // Test that the commands are read from each shell's history format
+    #[test]
+    fn test_parse_history_formats() {
+        let bash = "ls -la\n#1700000000\ngit status\n\ncargo build\n";
+        assert_eq!(parse_history(bash, HistoryFormat::Bash), vec!["ls -la", "git status", "cargo build"]);
+
+        let zsh = ": 1700000000:0;git status\n: 1700000010:2;echo one \\\ntwo\nplain command\n";
+        assert_eq!(parse_history(zsh, HistoryFormat::Zsh), vec!["git status", "echo one \ntwo", "plain command"]);
+
+        let fish = "- cmd: git status\n  when: 1700000000\n- cmd: echo a\\nb\n  when: 1700000001\n  paths:\n    - src\n";
+        assert_eq!(parse_history(fish, HistoryFormat::Fish), vec!["git status", "echo a\nb"]);
+
+        assert_eq!(HistoryFormat::from_content(zsh), HistoryFormat::Zsh);
+        assert_eq!(HistoryFormat::from_content(fish), HistoryFormat::Fish);
+        assert_eq!(HistoryFormat::from_content(bash), HistoryFormat::Bash);
+        assert_eq!(HistoryFormat::from_shell("/usr/bin/zsh"), Some(HistoryFormat::Zsh));
+        assert_eq!(HistoryFormat::from_shell("/bin/tcsh"), None);
+
+        // Repeats of the command just before count once
+        let commands: Vec<String> = ["a", "b", "b", "c", "c"].iter().map(|command| command.to_string()).collect();
+        assert_eq!(get_recent_commands(&commands, 2), vec!["b", "c"]);
+    }
+
+    // Test that commands are split as the shell does, and those that need a
+    // shell keep running under it
+    #[test]
+    fn test_history_programs() {
+        assert_eq!(
+            split_shell_words(r#"git commit -m "fix \"it\"" 'a b'"#).unwrap(),
+            vec!["git", "commit", "-m", "fix \"it\"", "a b"]
+        );
+        assert!(split_shell_words("echo 'open").is_none());
+
+        let mut program = create_history_program("git commit -m 'a | b'");
+        assert_eq!(program.get_command_line().get_interpreter(), None);
+        assert_eq!(program.get_command_line().get_arguments()[2], "a | b");
+
+        let mut program = create_history_program("cat Cargo.toml | grep version");
+        assert_eq!(program.get_command_line().get_interpreter(), Some(&Interpreter::Sh));
+        assert_eq!(program.to_string(), "cat Cargo.toml | grep version");
+        assert!(program.get_command_line().get_arguments()[0].is_raw());
+
+        assert_eq!(parse_selection("1,3-4", 5).unwrap(), vec![0, 2, 3]);
+        assert_eq!(parse_selection("", 2).unwrap(), vec![0, 1]);
+        assert!(parse_selection("6", 5).is_err());
+    }
+
+    // Test that the paths and branch names chosen become variables in the
+    // generated chain, which loads
+    #[test]
+    fn test_replace_literals_with_variables() {
+        let commands: Vec<String> = vec![
+            "git checkout feature/login".to_string(),
+            "cargo test --manifest-path ./app/Cargo.toml".to_string(),
+            "git push origin feature/login".to_string(),
+        ];
+        let scripted = ScriptedInteractor::new(vec!["branch".to_string(), String::new()]);
+        let prompts = scripted.get_prompts();
+        let interactor = SharedInteractor::new(scripted);
+
+        let json: String = ChainCreation::new(Some("history".to_string()))
+            .generate_from_history(&commands, &interactor)
+            .unwrap();
+        let prompts: Vec<String> = prompts.lock().unwrap().clone();
+        assert_eq!(prompts.len(), 2);
+        assert!(prompts[0].contains("branch `feature/login`"));
+        assert!(prompts[1].contains("path `./app/Cargo.toml`"));
+
+        let programs: Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(programs[0]["arguments"], serde_json::json!(["checkout", "<<branch>>"]));
+        assert_eq!(programs[1]["arguments"][2], "./app/Cargo.toml");
+        assert_eq!(programs[2]["arguments"], serde_json::json!(["push", "origin", "<<branch>>"]));
+        assert_eq!(programs[2]["retry"], 0);
+        assert!(Chain::from_json_str(&json, "history").is_ok());
+
+        // Variable names are checked
+        let interactor = SharedInteractor::new(ScriptedInteractor::new(vec!["not a name".to_string()]));
+        assert!(ChainCreation::new(None).generate_from_history(&commands, &interactor).is_err());
+    }
+}
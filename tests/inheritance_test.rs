@@ -0,0 +1,174 @@
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use cchain::core::metadata::ChainFile;
+    use serde_json::{json, Value};
+    use tempfile::TempDir;
+
+    const BASE: &str = r#"{
+        "metadata": { "tags": ["base"], "strict": true },
+        "programs": [
+            { "alias": "build", "command": "cargo", "arguments": ["build"], "retry": 0,
+              "environment_variables_override": { "PROFILE": "debug", "LOG": "info" } },
+            { "alias": "test", "command": "cargo", "arguments": ["test"], "retry": 0 },
+            { "alias": "deploy", "command": "echo", "arguments": ["deploy"], "retry": 0 }
+        ]
+    }"#;
+
+    fn write_chain(directory: &TempDir, name: &str, content: &str) -> PathBuf {
+        let path: PathBuf = directory.path().join(name);
+        std::fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn get_aliases(chain_file: &ChainFile) -> Vec<String> {
+        chain_file.programs.iter().map(|program| program.get_alias().cloned().unwrap_or_default()).collect()
+    }
+
+    fn get_program_value(chain_file: &ChainFile, index: usize) -> Value {
+        serde_json::to_value(&chain_file.programs[index]).unwrap()
+    }
+
+    #[test]
+    fn test_override_precedence() {
+        let directory = TempDir::new().unwrap();
+        write_chain(&directory, "cchain_base.json", BASE);
+        let child: PathBuf = write_chain(
+            &directory,
+            "cchain_release.json",
+            r#"{
+                "metadata": { "extends": "cchain_base.json", "tags": ["release"] },
+                "programs": [
+                    { "alias": "build", "arguments": ["build", "--release"],
+                      "environment_variables_override": { "PROFILE": "release" } }
+                ]
+            }"#,
+        );
+
+        let chain_file: ChainFile = ChainFile::from_path(&child).unwrap();
+        assert_eq!(get_aliases(&chain_file), vec!["build", "test", "deploy"]);
+
+        // The child wins field by field, and the rest of the base is kept
+        let build: Value = get_program_value(&chain_file, 0);
+        assert_eq!(build["command"], json!("cargo"));
+        assert_eq!(build["arguments"], json!(["build", "--release"]));
+        assert_eq!(build["environment_variables_override"], json!({ "PROFILE": "release", "LOG": "info" }));
+
+        assert_eq!(chain_file.metadata.tags, vec!["release".to_string()]);
+        assert!(chain_file.metadata.strict);
+        assert_eq!(chain_file.metadata.extends, None);
+        let origin: String = format!("cchain_base.json, overridden in {}", child.display());
+        assert!(chain_file.program_origins[0].ends_with(&origin));
+    }
+
+    #[test]
+    fn test_insertion_ordering() {
+        let directory = TempDir::new().unwrap();
+        write_chain(&directory, "cchain_base.json", BASE);
+        let child: PathBuf = write_chain(
+            &directory,
+            "cchain_child.json",
+            r#"{
+                "metadata": { "extends": "cchain_base.json" },
+                "programs": [
+                    { "alias": "lint", "insert_after": "build", "command": "cargo", "arguments": ["clippy"], "retry": 0 },
+                    { "alias": "notify", "command": "echo", "arguments": ["done"], "retry": 0 },
+                    { "alias": "format", "insert_after": "build", "command": "cargo", "arguments": ["fmt"], "retry": 0 }
+                ]
+            }"#,
+        );
+
+        let chain_file: ChainFile = ChainFile::from_path(&child).unwrap();
+        assert_eq!(get_aliases(&chain_file), vec!["build", "lint", "format", "test", "deploy", "notify"]);
+        assert!(get_program_value(&chain_file, 1).get("insert_after").is_none());
+    }
+
+    #[test]
+    fn test_removal() {
+        let directory = TempDir::new().unwrap();
+        write_chain(&directory, "cchain_base.json", BASE);
+        let child: PathBuf = write_chain(
+            &directory,
+            "cchain_child.json",
+            r#"{
+                "metadata": { "extends": "cchain_base.json" },
+                "programs": [{ "alias": "deploy", "remove": true }]
+            }"#,
+        );
+        let chain_file: ChainFile = ChainFile::from_path(&child).unwrap();
+        assert_eq!(get_aliases(&chain_file), vec!["build", "test"]);
+
+        // Removing a program that the base does not have is a mistake
+        let child: PathBuf = write_chain(
+            &directory,
+            "cchain_child.json",
+            r#"{
+                "metadata": { "extends": "cchain_base.json" },
+                "programs": [{ "alias": "publish", "remove": true }]
+            }"#,
+        );
+        let error: String = ChainFile::from_path(&child).unwrap_err().to_string();
+        assert!(error.contains("`publish`"));
+
+        // Directives need a base
+        let error: String = ChainFile::from_path(&write_chain(
+            &directory,
+            "cchain_plain.json",
+            r#"[{ "alias": "deploy", "remove": true, "command": "echo", "arguments": [], "retry": 0 }]"#,
+        ))
+        .unwrap_err()
+        .to_string();
+        assert!(error.contains("needs `extends`"));
+    }
+
+    #[test]
+    fn test_two_level_inheritance() {
+        let directory = TempDir::new().unwrap();
+        std::fs::create_dir(directory.path().join("shared")).unwrap();
+        write_chain(&directory, "shared/cchain_base.json", BASE);
+        write_chain(
+            &directory,
+            "shared/cchain_ci.json",
+            r#"{
+                "metadata": { "extends": "cchain_base.json" },
+                "programs": [{ "alias": "test", "arguments": ["test", "--locked"] }]
+            }"#,
+        );
+        let child: PathBuf = write_chain(
+            &directory,
+            "cchain_service.json",
+            r#"{
+                "metadata": { "extends": "shared/cchain_ci.json" },
+                "programs": [
+                    { "alias": "test", "retry": 2 },
+                    { "alias": "build", "remove": true }
+                ]
+            }"#,
+        );
+
+        let chain_file: ChainFile = ChainFile::from_path(&child).unwrap();
+        assert_eq!(get_aliases(&chain_file), vec!["test", "deploy"]);
+        let test: Value = get_program_value(&chain_file, 0);
+        assert_eq!(test["arguments"], json!(["test", "--locked"]));
+        assert_eq!(test["retry"], json!(2));
+        assert_eq!(chain_file.program_origins.len(), 2);
+        assert!(chain_file.program_origins[0].contains("cchain_ci.json, overridden in"));
+    }
+
+    #[test]
+    fn test_inheritance_errors() {
+        let directory = TempDir::new().unwrap();
+        write_chain(&directory, "cchain_a.json", r#"{ "metadata": { "extends": "cchain_b.json" }, "programs": [] }"#);
+        let child: PathBuf =
+            write_chain(&directory, "cchain_b.json", r#"{ "metadata": { "extends": "cchain_a.json" }, "programs": [] }"#);
+        let error: String = ChainFile::from_path(&child).unwrap_err().to_string();
+        assert!(error.contains("cycle"));
+        assert!(error.contains("cchain_b.json -> ") && error.contains("cchain_a.json -> "));
+
+        let child: PathBuf =
+            write_chain(&directory, "cchain_c.json", r#"{ "metadata": { "extends": "cchain_missing.json" }, "programs": [] }"#);
+        let error: String = ChainFile::from_path(Path::new(&child)).unwrap_err().to_string();
+        assert!(error.contains("cchain_c.json -> ") && error.contains("cchain_missing.json"));
+    }
+}
@@ -0,0 +1,64 @@
+#[cfg(test)]
+mod tests {
+    use cchain::commons::interaction::{
+        parse_quick_pick, render_quick_picks, ScriptedInteractor, SharedInteractor, UnattendedInteractor,
+    };
+
+    fn suggestions() -> Vec<String> {
+        vec!["main".to_string(), "develop".to_string(), "release/2.3".to_string()]
+    }
+
+    // Test that the quick picks are numbered beneath the prompt
+    #[test]
+    fn test_render_quick_picks() {
+        assert_eq!(
+            render_quick_picks("Please input a value for Branch:", &suggestions()),
+            "Please input a value for Branch:\n     1) main  2) develop  3) release/2.3  or type a new value\n   >"
+        );
+        // The picks go beneath the description, before the input marker
+        assert_eq!(
+            render_quick_picks("Please input a value for Branch:\n     The branch to deploy\n   >", &suggestions()[..1]),
+            "Please input a value for Branch:\n     The branch to deploy\n     1) main  or type a new value\n   >"
+        );
+    }
+
+    // Test that numbers select the quick picks, and other answers are values
+    #[test]
+    fn test_parse_quick_pick() {
+        assert_eq!(parse_quick_pick("1", &suggestions()), "main");
+        assert_eq!(parse_quick_pick("3", &suggestions()), "release/2.3");
+        assert_eq!(parse_quick_pick("feature/x", &suggestions()), "feature/x");
+        // Numbers without a quick pick are values
+        assert_eq!(parse_quick_pick("0", &suggestions()), "0");
+        assert_eq!(parse_quick_pick("4", &suggestions()), "4");
+        assert_eq!(parse_quick_pick("", &suggestions()), "");
+    }
+
+    // Test that a number in quotes is entered literally, instead of selecting a quick pick
+    #[test]
+    fn test_parse_quoted_number() {
+        assert_eq!(parse_quick_pick("\"2\"", &suggestions()), "2");
+        assert_eq!(parse_quick_pick("'2'", &suggestions()), "2");
+        // Other quoted values are kept as they are
+        assert_eq!(parse_quick_pick("\"main\"", &suggestions()), "\"main\"");
+    }
+
+    // Test that the quick picks are only offered to a user
+    #[test]
+    fn test_prompt_with_quick_picks() {
+        let interactor = ScriptedInteractor::new(vec!["2".to_string(), "2".to_string()]);
+        let prompts = interactor.get_prompts();
+        let interactor = SharedInteractor::new(interactor);
+
+        assert_eq!(interactor.prompt_with_quick_picks("Branch:", &suggestions()).unwrap(), "develop");
+        assert!(prompts.lock().unwrap()[0].contains("1) main  2) develop"));
+        // Without suggestions, the answer is taken as it is
+        assert_eq!(interactor.prompt_with_quick_picks("Branch:", &[]).unwrap(), "2");
+        assert_eq!(prompts.lock().unwrap()[1], "Branch:");
+
+        let error = SharedInteractor::new(UnattendedInteractor)
+            .prompt_with_quick_picks("Branch:", &suggestions())
+            .unwrap_err();
+        assert_eq!(error.to_string(), "Input stream is closed while waiting for: Branch:");
+    }
+}
@@ -0,0 +1,113 @@
+#[cfg(test)]
+mod tests {
+    use cchain::core::io_profile::{format_bytes, parse_proc_io, parse_proc_stat, IoProfile, IoUsage, ProcessEntry};
+
+    fn entry(process_id: u32, parent_process_id: u32, process_group_id: u32) -> ProcessEntry {
+        ProcessEntry { process_id, parent_process_id, process_group_id }
+    }
+
+    fn usage(write_bytes: u64, user_cpu_ms: u64) -> IoUsage {
+        IoUsage { write_bytes, user_cpu_ms, ..Default::default() }
+    }
+
+    // Test that the processes of a program are its descendants and the members of its group
+    #[test]
+    fn test_io_profile_members() {
+        let profile = IoProfile::new(100);
+        let processes: Vec<ProcessEntry> = vec![
+            entry(1, 0, 1),
+            entry(50, 1, 50),
+            entry(100, 50, 100),
+            entry(101, 100, 100),
+            // A grandchild, whatever its group
+            entry(102, 101, 102),
+            // Orphaned, but still in the group
+            entry(103, 1, 100),
+            // A sibling of the program
+            entry(104, 50, 50),
+        ];
+        assert_eq!(profile.get_members(&processes), vec![100, 101, 103, 102]);
+    }
+
+    // Test that readings are summed, and the largest sum of each counter is kept
+    #[test]
+    fn test_io_profile_record() {
+        let mut profile = IoProfile::new(100);
+        assert_eq!(profile.get_usage(), None);
+
+        // Readings without the process of the program are ignored
+        profile.record(&[(101, usage(4096, 10))]);
+        assert_eq!(profile.get_usage(), None);
+
+        profile.record(&[(100, usage(1000, 10)), (101, usage(4000, 30))]);
+        assert_eq!(profile.get_usage(), Some(usage(5000, 40)));
+
+        // The child exited without being waited for, which a lower sum must not hide
+        profile.record(&[(100, usage(2000, 20))]);
+        assert_eq!(profile.get_usage(), Some(usage(5000, 40)));
+
+        // The child was waited for, so its counters are in the program's
+        profile.record(&[(100, usage(7000, 55))]);
+        assert_eq!(profile.get_usage(), Some(usage(7000, 55)));
+
+        assert_eq!(IoUsage::mean(&[usage(1000, 10), usage(3000, 30)]), Some(usage(2000, 20)));
+        assert_eq!(IoUsage::mean(&[]), None);
+    }
+
+    // Test parsing the files of /proc
+    #[test]
+    fn test_parse_proc_files() {
+        let io: &str = "rchar: 8395584\nwchar: 8388702\nsyscr: 18\nsyscw: 11\nread_bytes: 167936\nwrite_bytes: 8409088\ncancelled_write_bytes: 0\n";
+        assert_eq!(
+            parse_proc_io(io),
+            Some(IoUsage {
+                read_bytes: 167936,
+                write_bytes: 8409088,
+                all_read_bytes: 8395584,
+                all_write_bytes: 8388702,
+                ..Default::default()
+            })
+        );
+        assert_eq!(parse_proc_io("rchar: 1\n"), None);
+
+        // The command name may contain spaces and parentheses
+        let stat: &str = "4242 (my (odd) cmd) S 4200 4242 4200 0 -1 4194560 100 0 0 0 150 50 25 5 20 0 1 0 100 0 0";
+        let (process, cpu_usage) = parse_proc_stat(4242, stat, 100).unwrap();
+        assert_eq!(process, entry(4242, 4200, 4242));
+        assert_eq!((cpu_usage.user_cpu_ms, cpu_usage.system_cpu_ms), (1750, 550));
+
+        assert_eq!(format_bytes(512), "512 B");
+        assert_eq!(format_bytes(8 * 1024 * 1024), "8.0 MiB");
+    }
+
+    // Test that the bytes written by `dd` are measured
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn test_profile_io_measures_writes() {
+        use std::io::Write;
+
+        use cchain::{core::traits::Execution, Chain};
+        use tempfile::{NamedTempFile, TempDir};
+
+        // The target directory is on a disk, where writes are accounted,
+        // unlike on tmpfs
+        let directory = TempDir::new_in(env!("CARGO_TARGET_TMPDIR")).unwrap();
+        let output = directory.path().join("written");
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(
+            temp_file,
+            r#"[{{"command": "dd", "arguments": ["if=/dev/zero", "of={}", "bs=1M", "count=8"], "retry": 0}}]"#,
+            output.display()
+        )
+        .unwrap();
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.set_profile_io(true);
+        chain.execute().unwrap();
+
+        let io_usage: IoUsage = chain.get_program_results()[0].io_usage().unwrap();
+        let megabyte: u64 = 1024 * 1024;
+        assert!((4 * megabyte..=64 * megabyte).contains(&io_usage.write_bytes), "{:?}", io_usage);
+        assert!(io_usage.all_write_bytes >= 8 * megabyte, "{:?}", io_usage);
+    }
+}
@@ -0,0 +1,114 @@
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use cchain::{
+        core::limits::{AcknowledgedLimits, ChainLimits},
+        Chain,
+    };
+    use serde_json::{json, Value};
+
+    fn create_programs(count: usize, arguments: Vec<&str>) -> Vec<Value> {
+        (0..count).map(|_| json!({ "command": "echo", "arguments": arguments, "retry": 0 })).collect()
+    }
+
+    fn write_chain(directory: &Path, name: &str, value: &Value) -> PathBuf {
+        let path: PathBuf = directory.join(name);
+        std::fs::write(&path, value.to_string()).unwrap();
+        path
+    }
+
+    fn load_error(json: &Value) -> String {
+        Chain::from_json_str(&json.to_string(), "limits").err().map(|error| error.to_string()).unwrap_or_default()
+    }
+
+    // Test that the number of programs is limited, unless the chain acknowledges it
+    #[test]
+    fn test_program_limit() {
+        assert!(Chain::from_json_str(&Value::Array(create_programs(500, vec![])).to_string(), "limits").is_ok());
+
+        let error: String = load_error(&Value::Array(create_programs(501, vec![])));
+        assert!(error.contains("501 programs, more than the limit of 500"));
+        assert!(error.contains(r#""limits": {"max_programs": 501}"#) && error.contains("--limits max_programs=501"));
+
+        let chain = json!({ "metadata": { "limits": { "max_programs": 501 } }, "programs": create_programs(501, vec![]) });
+        assert!(Chain::from_json_str(&chain.to_string(), "limits").is_ok());
+    }
+
+    // Test that the arguments of each program and the placeholders are limited
+    #[test]
+    fn test_argument_and_placeholder_limits() {
+        let arguments: Vec<String> = (0..1000).map(|index| index.to_string()).collect();
+        let arguments: Vec<&str> = arguments.iter().map(String::as_str).collect();
+        assert!(load_error(&Value::Array(create_programs(1, arguments.clone()))).is_empty());
+        let mut too_many_arguments: Vec<&str> = arguments.clone();
+        too_many_arguments.push("1000");
+        assert!(load_error(&Value::Array(create_programs(1, too_many_arguments))).contains("1001 arguments in program #0"));
+
+        // 10 programs with 1000 placeholders each is at the limit
+        let placeholders: Vec<&str> = vec!["<<name>>"; 1000];
+        let chain_limits: ChainLimits = ChainLimits::default();
+        assert!(chain_limits.check_programs(&Value::Array(create_programs(10, placeholders.clone()))).is_ok());
+        let mut programs: Vec<Value> = create_programs(10, placeholders);
+        programs.push(json!({ "command": "echo", "arguments": ["<<name>>"], "retry": 0 }));
+        let error: String = chain_limits.check_programs(&Value::Array(programs.clone())).unwrap_err().to_string();
+        assert!(error.contains("10001 placeholders of variables, more than the limit of 10000"));
+        // Loading the chain checks the placeholders too
+        assert!(load_error(&Value::Array(programs)).contains("--limits max_placeholders=10001"));
+    }
+
+    // Test that the size of a chain file is limited, counting the chains it extends
+    #[test]
+    fn test_file_size_limit() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain_at_size = |size: usize| -> Value {
+            let padding_length: usize = size - json!([{ "command": "echo", "arguments": [""], "retry": 0 }]).to_string().len();
+            json!([{ "command": "echo", "arguments": ["x".repeat(padding_length)], "retry": 0 }])
+        };
+        let limit: usize = ChainLimits::default().max_file_size as usize;
+
+        let path: PathBuf = write_chain(directory.path(), "at_limit.json", &chain_at_size(limit));
+        assert!(Chain::from_file(path.to_str().unwrap()).is_ok());
+        let path: PathBuf = write_chain(directory.path(), "over_limit.json", &chain_at_size(limit + 1));
+        let error: String = Chain::from_file(path.to_str().unwrap()).err().unwrap().to_string();
+        assert!(error.contains("larger than the limit of 5242880 bytes") && error.contains("--limits max_file_size="));
+
+        // Each file is within the limit, but not both together
+        let base: Value = json!({ "programs": [{ "command": "echo", "arguments": ["x".repeat(limit / 2)], "retry": 0, "alias": "base" }] });
+        write_chain(directory.path(), "base.json", &base);
+        let chain: Value = json!({
+            "metadata": { "extends": "base.json" },
+            "programs": [{ "command": "echo", "arguments": ["x".repeat(limit / 2)], "retry": 0 }]
+        });
+        let path: PathBuf = write_chain(directory.path(), "extending.json", &chain);
+        assert!(Chain::from_file(path.to_str().unwrap()).err().unwrap().to_string().contains("larger than the limit"));
+    }
+
+    // Test that the programs of extended chains count towards the limit together
+    #[test]
+    fn test_program_limit_with_extends() {
+        let directory = tempfile::tempdir().unwrap();
+        write_chain(directory.path(), "base.json", &json!({ "programs": create_programs(300, vec![]) }));
+        let chain: Value = json!({ "metadata": { "extends": "base.json" }, "programs": create_programs(201, vec![]) });
+        let path: PathBuf = write_chain(directory.path(), "extending.json", &chain);
+        assert!(Chain::from_file(path.to_str().unwrap()).err().unwrap().to_string().contains("501 programs"));
+    }
+
+    // Test the limits given on the command line
+    #[test]
+    fn test_limit_overrides() {
+        let overrides: Vec<(String, String)> =
+            vec![("max_programs".to_string(), "2000".to_string()), ("max_file_size".to_string(), "20M".to_string())];
+        let chain_limits: ChainLimits = ChainLimits::default().with_overrides(&overrides).unwrap();
+        assert_eq!(chain_limits.max_programs, 2000);
+        assert_eq!(chain_limits.max_file_size, 20 * 1024 * 1024);
+        assert!(ChainLimits::default().with_overrides(&[("max_depth".to_string(), "3".to_string())]).is_err());
+        assert!(ChainLimits::default().with_overrides(&[("max_programs".to_string(), "many".to_string())]).is_err());
+
+        // Acknowledged limits only raise the limits
+        let acknowledged = AcknowledgedLimits { max_programs: Some(10), max_arguments: Some(5000), ..Default::default() };
+        let chain_limits: ChainLimits = ChainLimits::default().with_acknowledged(&acknowledged);
+        assert_eq!(chain_limits.max_programs, 500);
+        assert_eq!(chain_limits.max_arguments, 5000);
+    }
+}
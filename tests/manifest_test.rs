@@ -0,0 +1,177 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::HashMap, path::Path};
+
+    use assert_cmd::Command;
+    use cchain::{
+        commons::utility::{collect_provided_values, run_manifest, BatchOptions, ChainRunOutcome},
+        core::manifest::{is_manifest_file, ChainManifest},
+        marker::bookmark::Bookmark,
+    };
+
+    fn create_chain(directory: &Path, name: &str, script: &str) {
+        let chain = serde_json::json!([{ "command": "sh", "arguments": ["-c", script], "retry": 0 }]);
+        std::fs::write(directory.join(name), chain.to_string()).unwrap();
+    }
+
+    /// Build and test start together, deploy needs both, and notify runs
+    /// whatever deploy does. The starts and finishes of build and test go
+    /// to `log`, and build waits for test to start unless `rendezvous` is
+    /// set to something else than `yes`.
+    fn create_release_manifest(directory: &Path, is_test_failing: bool) -> std::path::PathBuf {
+        let marker = |name: &str| directory.join(name).to_string_lossy().to_string();
+        let log = marker("log");
+        create_chain(
+            directory,
+            "cchain_build.json",
+            &format!(
+                "echo start build >> {log}; echo <<target>> > {built}; \
+                 if [ <<rendezvous>> = yes ]; then for i in $(seq 200); do [ -f {started} ] && break; sleep 0.05; done; fi; \
+                 echo finish build >> {log}",
+                log = log,
+                built = marker("built"),
+                started = marker("test_started"),
+            ),
+        );
+        create_chain(
+            directory,
+            "cchain_test.json",
+            &format!(
+                "echo start test >> {log}; touch {started}; echo finish test >> {log}; {outcome}",
+                log = log,
+                started = marker("test_started"),
+                outcome = if is_test_failing { "false" } else { "true" },
+            ),
+        );
+        create_chain(directory, "cchain_deploy.json", &format!("touch {}", marker("deployed")));
+        create_chain(directory, "cchain_notify.json", &format!("touch {}", marker("notified")));
+
+        let path = directory.join("release.json");
+        let manifest = serde_json::json!({
+            "parallel": 2,
+            "chains": [
+                { "name": "build", "chain": "cchain_build.json", "variables": { "target": "staging", "rendezvous": "yes" } },
+                { "name": "test", "chain": "cchain_test.json" },
+                { "name": "deploy", "chain": "cchain_deploy.json", "depends_on": ["build", "test"] },
+                { "name": "notify", "chain": "cchain_notify.json", "depends_on": ["deploy"], "always_run": true }
+            ]
+        });
+        std::fs::write(&path, manifest.to_string()).unwrap();
+
+        path
+    }
+
+    fn read_log(directory: &Path) -> Vec<String> {
+        std::fs::read_to_string(directory.join("log")).unwrap().lines().map(str::to_string).collect()
+    }
+
+    #[test]
+    fn test_manifest_validation() {
+        // Manifests are told from chains by their content, whatever their names
+        let directory = tempfile::tempdir().unwrap();
+        let manifest_path = create_release_manifest(directory.path(), false);
+        assert!(is_manifest_file(&manifest_path));
+        assert!(!is_manifest_file(&directory.path().join("cchain_build.json")));
+        std::fs::write(directory.path().join("cchain_meta_chain.json"), r#"{ "programs": [] }"#).unwrap();
+        assert!(!is_manifest_file(&directory.path().join("cchain_meta_chain.json")));
+        assert!(!is_manifest_file(&directory.path().join("missing.json")));
+
+        let manifest = ChainManifest::from_json_str(
+            r#"{ "chains": [
+                { "chain": "cchain_a.json" },
+                { "name": "b", "chain": "cchain_b.json", "depends_on": ["cchain_a.json"] },
+                { "name": "c", "chain": "cchain_c.json" },
+                { "name": "d", "chain": "cchain_d.json", "depends_on": ["b", "c"] }
+            ] }"#,
+            "test",
+        )
+        .unwrap();
+        assert_eq!(manifest.get_stages().unwrap(), vec![vec![0, 2], vec![1], vec![3]]);
+
+        for (content, message) in [
+            (r#"{ "chains": [] }"#, "it has no chains"),
+            (r#"{ "chains": [{ "chain": "a" }, { "chain": "a" }] }"#, "more than one entry is named `a`"),
+            (r#"{ "chains": [{ "chain": "a", "depends_on": ["b"] }] }"#, "`a` depends on `b`, which is not in the manifest"),
+            (
+                r#"{ "chains": [{ "chain": "a", "depends_on": ["b"] }, { "chain": "b", "depends_on": ["a"] }] }"#,
+                "the dependencies of `a`, `b` form a cycle",
+            ),
+            (r#"{ "chains": [{ "chain": "a", "depend_on": [] }] }"#, "unknown field `depend_on`"),
+        ] {
+            let error = ChainManifest::from_json_str(content, "test").unwrap_err().to_string();
+            assert!(error.contains(message), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_run_manifest() {
+        let directory = tempfile::tempdir().unwrap();
+        let bookmark = Bookmark::new(directory.path().join(".cchain").to_string_lossy().into_owned());
+        let path = create_release_manifest(directory.path(), false);
+        let records = run_manifest(&path, &bookmark, &BatchOptions::new(), &HashMap::new()).unwrap();
+        let names: Vec<&str> = records.iter().map(|record| record.name.as_str()).collect();
+        assert_eq!(names, vec!["build", "test", "deploy", "notify"]);
+        assert!(records.iter().all(|record| record.outcome == ChainRunOutcome::Succeeded), "{:?}", records);
+        assert!(records.iter().all(|record| record.duration.is_some() && record.failed_programs == 0));
+        assert_eq!(std::fs::read_to_string(directory.path().join("built")).unwrap().trim(), "staging");
+        // Test starts before build finishes
+        let log = read_log(directory.path());
+        let position = |line: &str| log.iter().position(|logged| logged == line).unwrap();
+        assert!(position("start test") < position("finish build"), "{:?}", log);
+
+        // A failure skips the dependents, but not those that always run
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_release_manifest(directory.path(), true);
+        let records = run_manifest(&path, &bookmark, &BatchOptions::new(), &HashMap::new()).unwrap();
+        assert_eq!(records[0].outcome, ChainRunOutcome::Succeeded);
+        assert!(matches!(records[1].outcome, ChainRunOutcome::Failed(_)));
+        assert_eq!(records[2].outcome, ChainRunOutcome::Blocked("test".to_string()));
+        assert_eq!(records[2].duration, None);
+        assert_eq!(records[3].outcome, ChainRunOutcome::Succeeded);
+        assert!(!directory.path().join("deployed").exists());
+        assert!(directory.path().join("notified").exists());
+
+        // One chain at a time, each finishes before the next starts
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_release_manifest(directory.path(), false);
+        let provided_values = collect_provided_values(&[("rendezvous".to_string(), "no".to_string())], &[]).unwrap();
+        let records = run_manifest(&path, &bookmark, &BatchOptions::new().with_parallel(1), &provided_values).unwrap();
+        assert!(records.iter().all(|record| record.outcome == ChainRunOutcome::Succeeded), "{:?}", records);
+        assert_eq!(read_log(directory.path()), vec!["start build", "finish build", "start test", "finish test"]);
+    }
+
+    #[test]
+    fn test_manifest_commands() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = create_release_manifest(directory.path(), true);
+        let cchain = |arguments: &[&str]| {
+            Command::cargo_bin("cchain")
+                .unwrap()
+                .env("HOME", directory.path())
+                .env_remove("CCHAIN_HOME")
+                .args(arguments)
+                .arg(&path)
+                .write_stdin("")
+                .output()
+                .unwrap()
+        };
+
+        let output = cchain(&["check"]);
+        assert!(output.status.success());
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("Stage 1:") && stdout.contains("Stage 3:"), "{}", stdout);
+        assert!(stdout.contains("notify: ") && stdout.contains("(after deploy; always runs)"), "{}", stdout);
+
+        let output = cchain(&["run", "--var", "target=production"]);
+        assert_eq!(output.status.code(), Some(4));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("skipped: `test` did not succeed"), "{}", stdout);
+        assert_eq!(std::fs::read_to_string(directory.path().join("built")).unwrap().trim(), "production");
+
+        std::fs::remove_file(directory.path().join("cchain_deploy.json")).unwrap();
+        let output = cchain(&["check"]);
+        assert_eq!(output.status.code(), Some(2));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("Chain `cchain_deploy.json` of `deploy` is neither"), "{}", stdout);
+    }
+}
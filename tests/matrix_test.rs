@@ -0,0 +1,240 @@
+#[cfg(test)]
+mod tests {
+    use cchain::{
+        commons::errors::ChainError,
+        core::{
+            chain::ProgramListing,
+            format::format_chain_file,
+            matrix::{add_variable_suffix, count_matrix_combinations, get_matrix_combinations, get_matrix_placeholder_keys, Matrix},
+            options::ProgramSelection,
+            traits::Execution,
+        },
+        Chain,
+    };
+
+    fn matrix(keys: &[(&str, &[&str])]) -> Matrix {
+        Matrix::new(
+            keys.iter()
+                .map(|(key, values)| (key.to_string(), values.iter().map(|value| value.to_string()).collect()))
+                .collect(),
+        )
+    }
+
+    fn load_chain(programs: serde_json::Value) -> Result<Chain, anyhow::Error> {
+        Chain::from_json_str(&programs.to_string(), "matrix")
+    }
+
+    // Test that the combinations cover the grid, with the first key changing the slowest
+    #[test]
+    fn test_matrix_combinations() {
+        let matrix = matrix(&[("mode", &["debug", "release"]), ("os", &["linux", "macos", "windows"])]);
+        assert_eq!(count_matrix_combinations(&matrix), 6);
+
+        let combinations = get_matrix_combinations(&matrix);
+        let labels: Vec<String> = combinations.iter().map(|combination| combination.get_label()).collect();
+        assert_eq!(
+            labels,
+            vec!["debug,linux", "debug,macos", "debug,windows", "release,linux", "release,macos", "release,windows"]
+        );
+        assert_eq!(combinations[4].get_value("os"), Some("macos"));
+        assert_eq!(combinations[4].get_variable_suffix(), "_release_macos");
+
+        // Escaped placeholders and those of other keys are kept
+        assert_eq!(
+            combinations[0].substitute("--target=<<matrix.os>>-<<matrix.mode>> <<<<matrix.os>> <<matrix.arch>> <<name>>"),
+            "--target=linux-debug <<<<matrix.os>> <<matrix.arch>> <<name>>"
+        );
+        assert_eq!(get_matrix_placeholder_keys("<<matrix.os>> <<<<matrix.mode>> <<matrix.arch>>"), vec!["os", "arch"]);
+
+        assert_eq!(add_variable_suffix("<<artifact>>", "_linux"), "<<artifact_linux>>");
+        assert_eq!(add_variable_suffix("artifact", "_linux"), "artifact_linux");
+    }
+
+    // Test that a program is expanded in place, with its values substituted and aliases generated
+    #[test]
+    fn test_matrix_expansion() {
+        let chain = load_chain(serde_json::json!([
+            { "command": "echo", "arguments": ["start"], "retry": 0 },
+            {
+                "command": "cargo",
+                "arguments": ["build", "--target", "<<matrix.os>>", "--<<matrix.mode>>"],
+                "environment_variables_override": { "TARGET_OS": "<<matrix.os>>" },
+                "working_directory": "/tmp/<<matrix.os>>",
+                "matrix": { "os": ["linux", "macos", "windows"], "mode": ["debug", "release"] },
+                "alias": "build",
+                "retry": 0
+            },
+            { "command": "echo", "arguments": ["done"], "retry": 0 }
+        ]))
+        .unwrap();
+
+        let program_listing: Vec<ProgramListing> = chain.get_program_listing();
+        assert_eq!(program_listing.len(), 8);
+        assert_eq!(program_listing[1].alias.as_deref(), Some("build[debug,linux]"));
+        assert_eq!(program_listing[6].alias.as_deref(), Some("build[release,windows]"));
+        assert_eq!(program_listing[7].command, "echo done");
+        assert!(program_listing[1..7].iter().all(|program| program.concurrency_group.is_none()));
+
+        let program = serde_json::to_value(&*chain.get_programs()[6].lock().unwrap()).unwrap();
+        assert_eq!(program["arguments"], serde_json::json!(["build", "--target", "windows", "--release"]));
+        assert_eq!(program["environment_variables_override"], serde_json::json!({ "TARGET_OS": "windows" }));
+        assert_eq!(program["working_directory"], "/tmp/windows");
+        assert!(program.get("matrix").is_none());
+    }
+
+    // Test that the keys are taken in the order they are declared, not in alphabetical order
+    #[test]
+    fn test_matrix_declared_key_order() {
+        let content: &str = r#"[
+  {
+    "command": "echo",
+    "arguments": ["<<matrix.profile>>"],
+    "stdout_stored_to": "<<artifact>>",
+    "matrix": { "target": ["linux", "mac"], "profile": ["dev", "release"] },
+    "alias": "build",
+    "retry": 0
+  }
+]"#;
+        let chain = Chain::from_json_str(content, "matrix").unwrap();
+        let aliases: Vec<Option<String>> =
+            chain.get_program_listing().into_iter().map(|program| program.alias).collect();
+        assert_eq!(
+            aliases,
+            vec![
+                Some("build[linux,dev]".to_string()),
+                Some("build[linux,release]".to_string()),
+                Some("build[mac,dev]".to_string()),
+                Some("build[mac,release]".to_string()),
+            ]
+        );
+        assert_eq!(
+            chain.get_programs()[1].lock().unwrap().get_awaitable_variable().clone(),
+            Some("<<artifact_linux_release>>".to_string())
+        );
+
+        // Formatting keeps the order too
+        let formatted: String = format_chain_file(content, false).unwrap();
+        assert!(formatted.find("\"target\"").unwrap() < formatted.find("\"profile\"").unwrap(), "{}", formatted);
+    }
+
+    // Test that the variables stored by the expanded programs are suffixed with their values
+    #[test]
+    fn test_matrix_stored_variable_suffix() {
+        let chain = load_chain(serde_json::json!([
+            {
+                "command": "echo",
+                "arguments": ["<<matrix.os>>"],
+                "stdout_stored_to": "<<artifact>>",
+                "matrix": { "os": ["linux", "mac-os"] },
+                "matrix_parallel": true,
+                "retry": 0
+            },
+            {
+                "command": "echo",
+                "arguments": ["<<matrix.os>>"],
+                "stdout_stored_to": "<<checksum_<<matrix.os>>>>",
+                "matrix": { "os": ["linux"] },
+                "retry": 0
+            },
+            { "command": "echo", "arguments": ["<<artifact_linux>>", "<<artifact_mac_os>>", "<<checksum_linux>>"], "retry": 0, "concurrency_group": 3 }
+        ]))
+        .unwrap();
+
+        let stored_to: Vec<Option<String>> = chain
+            .get_programs()
+            .iter()
+            .map(|program| program.lock().unwrap().get_awaitable_variable().clone())
+            .collect();
+        assert_eq!(
+            stored_to,
+            vec![Some("<<artifact_linux>>".to_string()), Some("<<artifact_mac_os>>".to_string()), Some("<<checksum_linux>>".to_string()), None]
+        );
+        // Stored variables are not prompted for
+        assert!(chain.get_pending_inputs().is_empty());
+
+        // The parallel programs get a concurrency group after those of the chain
+        let program_listing: Vec<ProgramListing> = chain.get_program_listing();
+        assert_eq!(program_listing[0].concurrency_group, Some(4));
+        assert_eq!(program_listing[1].concurrency_group, Some(4));
+        assert_eq!(program_listing[2].concurrency_group, None);
+    }
+
+    // Test that the values are put in the assertions and the git operations too
+    #[test]
+    fn test_matrix_substituted_fields() {
+        let mut chain = load_chain(serde_json::json!([
+            {
+                "command": "echo",
+                "arguments": ["built-<<matrix.os>>"],
+                "assertions": [{ "contains": "built-<<matrix.os>>" }, { "not_contains": "<<matrix" }],
+                "matrix": { "os": ["linux", "mac"] },
+                "retry": 0
+            },
+            { "git": { "op": "rev_parse", "ref": "<<matrix.reference>>" }, "matrix": { "reference": ["HEAD", "HEAD~1"] } }
+        ]))
+        .unwrap();
+
+        let references: Vec<String> = chain.get_programs()[2..]
+            .iter()
+            .map(|program| program.lock().unwrap().get_git_operation().unwrap().to_string())
+            .collect();
+        assert_eq!(references, vec!["git rev-parse HEAD", "git rev-parse HEAD~1"]);
+
+        chain.set_program_selection(ProgramSelection::default().with_only([0, 1].into_iter().collect())).unwrap();
+        chain.execute().unwrap();
+        assert!(chain.get_failed_program_indices().is_empty());
+    }
+
+    // Test that combinations storing the same variable are rejected
+    #[test]
+    fn test_matrix_stored_variable_collisions() {
+        for matrix in [
+            serde_json::json!({ "os": ["x-y", "x_y"] }),
+            serde_json::json!({ "a": ["a_b", "a"], "b": ["c", "b_c"] }),
+        ] {
+            let error = load_chain(serde_json::json!([{
+                "command": "echo", "arguments": [], "stdout_stored_to": "<<artifact>>", "matrix": matrix, "retry": 0
+            }]))
+            .unwrap_err();
+            assert!(error.to_string().contains("more than one combination of `matrix` stores"), "{}", error);
+        }
+
+        // The placeholders of the values in `stdout_stored_to` keep them apart
+        assert!(load_chain(serde_json::json!([{
+            "command": "echo", "arguments": [], "stdout_stored_to": "<<artifact_<<matrix.os>>>>",
+            "matrix": { "os": ["x", "y"] }, "retry": 0
+        }]))
+        .is_ok());
+    }
+
+    // Test the matrices that are rejected
+    #[test]
+    fn test_invalid_matrix() {
+        let error_of = |program: serde_json::Value| -> String {
+            let error = load_chain(serde_json::json!([program])).unwrap_err();
+            assert!(matches!(error.downcast_ref::<ChainError>(), Some(ChainError::Validation(_))), "{}", error);
+            error.to_string()
+        };
+
+        assert!(error_of(serde_json::json!({ "command": "echo", "arguments": [], "retry": 0, "matrix": { "os": [] } }))
+            .contains("the key `os` of `matrix` has no values"));
+        assert!(error_of(serde_json::json!({ "command": "echo", "arguments": ["<<matrix.arch>>"], "retry": 0, "matrix": { "os": ["linux"] } }))
+            .contains("`arch` is not a key of `matrix`"));
+        assert!(error_of(serde_json::json!({ "command": "echo", "arguments": ["<<matrix.os>>"], "retry": 0 }))
+            .contains("there is no `matrix`"));
+        assert!(error_of(serde_json::json!({
+            "command": "echo", "arguments": [], "retry": 0, "matrix": { "os": ["linux"] }, "matrix_parallel": true, "concurrency_group": 0
+        }))
+        .contains("`concurrency_group` cannot be set"));
+        // The values are never put in the command
+        assert!(error_of(serde_json::json!({ "command": "<<matrix.os>>", "arguments": [], "retry": 0, "matrix": { "os": ["linux"] } }))
+            .contains("`<<matrix.os>>` is only replaced in"));
+
+        // The combinations count against the limit of programs
+        let values: Vec<String> = (0..30).map(|value| value.to_string()).collect();
+        let error: String = error_of(serde_json::json!({
+            "command": "echo", "arguments": [], "retry": 0, "matrix": { "a": values, "b": values }
+        }));
+        assert!(error.contains("900 programs once its matrices are expanded"), "{}", error);
+    }
+}
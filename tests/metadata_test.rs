@@ -0,0 +1,163 @@
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, str::FromStr};
+
+    use cchain::{
+        commons::digest::sha256_hex,
+        core::{
+            chain::Chain,
+            metadata::{ChainFile, ChainMetadata},
+            schema::{get_chain_file_schema, validate_against_schema},
+            traits::Execution,
+        },
+        generations::create::ChainCreation,
+    };
+    use tempfile::NamedTempFile;
+
+    fn metadata_requiring(requirement: &str) -> ChainMetadata {
+        ChainMetadata {
+            requires_cchain: Some(requirement.to_string()),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_version_requirement_satisfied() {
+        assert!(metadata_requiring(">=0.3.0").check_version_requirement("0.3.42").is_ok());
+        assert!(metadata_requiring("^0.3").check_version_requirement("0.3.42").is_ok());
+        assert!(ChainMetadata::default().check_version_requirement("0.3.42").is_ok());
+    }
+
+    #[test]
+    fn test_version_requirement_unsatisfied() {
+        let error = metadata_requiring(">=0.4.0")
+            .check_version_requirement("0.3.42")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains(">=0.4.0"));
+        assert!(error.contains("0.3.42"));
+    }
+
+    #[test]
+    fn test_version_requirement_malformed() {
+        let error = metadata_requiring("at least 0.4")
+            .check_version_requirement("0.3.42")
+            .unwrap_err()
+            .to_string();
+        assert!(error.starts_with("Invalid `requires_cchain` requirement"));
+    }
+
+    // Test that the requirement is checked before parsing the programs
+    #[test]
+    fn test_version_requirement_checked_before_programs() {
+        let chain = r#"{
+            "metadata": { "requires_cchain": ">=999.0.0" },
+            "programs": [{ "command": "echo", "retry": "forever" }]
+        }"#;
+
+        let error = ChainFile::from_str(chain).unwrap_err().to_string();
+        assert!(error.contains(">=999.0.0"));
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        let chain = r#"{
+            "metadata": { "strict": STRICT },
+            "programs": [{ "command": "echo", "arguments": [], "retry": 0, "run_if": "false" }]
+        }"#;
+
+        let chain_file = ChainFile::from_str(&chain.replace("STRICT", "false")).unwrap();
+        assert_eq!(chain_file.unknown_fields, vec!["`run_if` in program #0".to_string()]);
+
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain.replace("STRICT", "false")).unwrap();
+        assert!(Chain::from_file(temp_file.path().to_str().unwrap()).is_ok());
+
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", chain.replace("STRICT", "true")).unwrap();
+        let error = Chain::from_file(temp_file.path().to_str().unwrap())
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("`run_if` in program #0"));
+        assert!(error.contains("newer cchain"));
+    }
+
+    /// Generate a chain file from programs, as `cchain new --prompt` does
+    fn create_generated_chain(programs: &str, embed_prompt: bool) -> String {
+        ChainCreation::new(None)
+            .with_embedded_prompt(embed_prompt)
+            .create_generated_chain_file(
+                "print a greeting",
+                serde_json::from_str(programs).unwrap(),
+                "https://api.example.com/v1",
+                "example-model",
+                1_760_000_000,
+            )
+            .unwrap()
+    }
+
+    // Test that SHA-256 digests match the known values
+    #[test]
+    fn test_sha256() {
+        assert_eq!(sha256_hex(b""), "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855");
+        assert_eq!(sha256_hex(b"abc"), "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad");
+        assert_eq!(
+            sha256_hex("a".repeat(1000).as_bytes()),
+            "41edece42d63e8d9bf515a9ba6932e1c20cbc9f5a5d134645adb5db1b9737ea3"
+        );
+    }
+
+    // Test that the provenance of a generated chain is embedded, with the prompt only when asked
+    #[test]
+    fn test_generated_by_embedded() {
+        let programs = r#"[{ "command": "echo", "arguments": ["hello"], "retry": 0 }]"#;
+        let chain_file = ChainFile::from_str(&create_generated_chain(programs, false)).unwrap();
+        let generated_by = chain_file.metadata.generated_by.as_ref().unwrap();
+        assert_eq!(generated_by.tool, format!("cchain {}", env!("CARGO_PKG_VERSION")));
+        assert_eq!(generated_by.model, "example-model");
+        assert_eq!(generated_by.prompt_sha256, sha256_hex(b"print a greeting"));
+        assert_eq!(generated_by.prompt, None);
+        assert!(!generated_by.is_modified(&chain_file.programs).unwrap());
+        assert!(generated_by.to_string().contains("2025-10-09 08:53:20 UTC"));
+
+        let content = create_generated_chain(programs, true);
+        let value: serde_json::Value = serde_json::from_str(&content).unwrap();
+        assert_eq!(value["metadata"]["generated_by"]["prompt"], "print a greeting");
+        assert_eq!(validate_against_schema(&value, &get_chain_file_schema()), vec![]);
+    }
+
+    // Test that edits to the programs of a generated chain are linted, but reformatting is not
+    #[test]
+    fn test_generated_by_modified_lint() {
+        let programs = r#"[{ "command": "echo", "arguments": ["hello"], "retry": 0 }]"#;
+        let content = create_generated_chain(programs, false);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        let reformatted: serde_json::Value = serde_json::from_str(&content).unwrap();
+        write!(temp_file, "{}", reformatted).unwrap();
+        let chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        assert!(chain.lint().is_empty());
+
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", content.replace("hello", "goodbye")).unwrap();
+        let chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        let messages = chain.lint();
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].starts_with("The programs have been modified since the chain was generated"));
+    }
+
+    // Test that a chain with provenance runs as usual
+    #[cfg(unix)]
+    #[test]
+    fn test_generated_chain_execution() {
+        let directory = tempfile::tempdir().unwrap();
+        let output_path = directory.path().join("output.txt");
+        let programs = serde_json::json!([{ "command": "touch", "arguments": [output_path], "retry": 0 }]);
+        let path = directory.path().join("cchain_generated.json");
+        std::fs::write(&path, create_generated_chain(&programs.to_string(), false)).unwrap();
+
+        let mut chain = Chain::from_file(path.to_str().unwrap()).unwrap();
+        chain.validate_syntax().unwrap();
+        chain.execute().unwrap();
+        assert!(output_path.exists());
+    }
+}
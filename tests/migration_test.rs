@@ -0,0 +1,98 @@
+#[cfg(test)]
+mod tests {
+    use std::{io::Write, str::FromStr};
+
+    use cchain::{
+        commons::errors::ChainError,
+        core::{format::format_chain_file, metadata::ChainFile, traits::Execution},
+        Chain,
+    };
+    use tempfile::NamedTempFile;
+
+    /// A chain in the early format, which continues after its first program
+    /// fails and stores the output of the second one
+    const LEGACY_CHAIN: &str = r#"[
+        {
+            "command": "false",
+            "arguments": [],
+            "awaitable_variable": null,
+            "failure_handling_options": { "continue_on_failure": true },
+            "retry": 0
+        },
+        {
+            "command": "echo",
+            "arguments": ["-n", "hello"],
+            "awaitable_variable": "<<greeting>>",
+            "failure_handling_options": { "continue_on_failure": false },
+            "retry": 0
+        },
+        { "command": "echo", "arguments": ["<<greeting>>"], "retry": 0 }
+    ]"#;
+
+    fn write_chain(content: &str) -> NamedTempFile {
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", content).unwrap();
+        temp_file
+    }
+
+    // Test that the legacy fields keep the meaning the chain was written with
+    #[test]
+    fn test_legacy_fields_mapped() {
+        let chain_file = ChainFile::from_str(LEGACY_CHAIN).unwrap();
+        assert!(chain_file.unknown_fields.is_empty());
+        assert_eq!(chain_file.deprecated_fields.len(), 4);
+        assert!(chain_file.deprecated_fields[0].contains("Program #0: `awaitable_variable` is deprecated, use `stdout_stored_to`"));
+        assert!(chain_file.deprecated_fields[1]
+            .contains("Program #0: `failure_handling_options.continue_on_failure` is deprecated, use `\"exit_on_failure\": false`"));
+
+        let temp_file = write_chain(LEGACY_CHAIN);
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        let programs = chain.get_programs().clone();
+        assert!(!programs[0].lock().unwrap().get_failure_handling_options().exit_on_failure);
+        assert!(programs[1].lock().unwrap().get_failure_handling_options().exit_on_failure);
+        assert_eq!(programs[1].lock().unwrap().get_awaitable_variable(), &Some("<<greeting>>".to_string()));
+
+        // The first program fails, and the chain goes on to store the greeting
+        let results = chain.execute().unwrap();
+        assert_eq!(results[0].get_failed_program_indices(), &vec![0]);
+        let provenances = results[0].get_variable_provenances();
+        assert!(provenances.iter().any(|provenance| provenance.name == "greeting" && provenance.value == "hello"));
+    }
+
+    // Test that a legacy field contradicting the current one fails loading
+    #[test]
+    fn test_conflicting_legacy_fields() {
+        let chain = r#"[{
+            "command": "false",
+            "arguments": [],
+            "failure_handling_options": { "continue_on_failure": true, "exit_on_failure": true },
+            "retry": 0
+        }]"#;
+        let temp_file = write_chain(chain);
+        let error = Chain::from_file(temp_file.path().to_str().unwrap()).err().unwrap();
+        assert!(matches!(error.downcast_ref::<ChainError>(), Some(ChainError::Validation(_))));
+        assert!(error.to_string().contains("Program #0 has both `continue_on_failure`: true and `exit_on_failure`: true"));
+
+        // Fields that agree are accepted
+        assert!(ChainFile::from_str(&chain.replace("\"exit_on_failure\": true", "\"exit_on_failure\": false")).is_ok());
+
+        let chain = r#"[{
+            "command": "echo", "arguments": [], "retry": 0,
+            "awaitable_variable": "old", "stdout_stored_to": "new"
+        }]"#;
+        let error: String = ChainFile::from_str(chain).unwrap_err().to_string();
+        assert!(error.contains("`awaitable_variable` is the deprecated name of `stdout_stored_to`"));
+    }
+
+    // Test that formatting rewrites the legacy fields to the current ones
+    #[test]
+    fn test_format_rewrites_legacy_fields() {
+        let formatted: String = format_chain_file(LEGACY_CHAIN, false).unwrap();
+        assert!(!formatted.contains("continue_on_failure") && !formatted.contains("awaitable_variable"));
+        assert!(formatted.contains("\"exit_on_failure\": false"));
+        assert!(formatted.contains("\"stdout_stored_to\": \"<<greeting>>\""));
+
+        let chain_file = ChainFile::from_str(&formatted).unwrap();
+        assert!(chain_file.deprecated_fields.is_empty());
+    }
+}
@@ -0,0 +1,168 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        path::{Path, PathBuf},
+        sync::Arc,
+    };
+
+    use cchain::{
+        core::parse_cache::{evict_cached_chain, get_cache_hit_count, get_parse_count, set_parse_cache_directory},
+        variable::Variable,
+        Chain,
+    };
+    use serde_json::{json, Value};
+
+    fn write_chain(path: &Path, value: &Value) {
+        std::fs::write(path, value.to_string()).unwrap();
+    }
+
+    fn load(path: &Path) -> Chain {
+        Chain::from_file(path.to_str().unwrap()).unwrap()
+    }
+
+    // Test that an untouched chain is reused, and a modified one parsed again
+    #[test]
+    fn test_modified_chain_parsed_again() {
+        let directory = tempfile::tempdir().unwrap();
+        let base_path: PathBuf = directory.path().join("base.json");
+        let path: PathBuf = directory.path().join("chain.json");
+        write_chain(&base_path, &json!({ "programs": [{ "command": "echo", "arguments": ["base"], "retry": 0 }] }));
+        write_chain(
+            &path,
+            &json!({
+                "metadata": { "extends": "base.json" },
+                "programs": [{ "command": "echo", "arguments": ["<<name>>"], "retry": 0 }]
+            }),
+        );
+
+        let chain: Chain = load(&path);
+        let cached_chain: Chain = load(&path);
+        assert_eq!(get_parse_count(&path), 1);
+        assert_eq!(cached_chain.get_programs().len(), 2);
+        // Each load has programs of its own
+        assert!(!Arc::ptr_eq(&chain.get_programs()[0], &cached_chain.get_programs()[0]));
+        assert_eq!(*chain.get_programs()[1].lock().unwrap(), *cached_chain.get_programs()[1].lock().unwrap());
+
+        write_chain(
+            &path,
+            &json!({
+                "metadata": { "extends": "base.json" },
+                "programs": [{ "command": "echo", "arguments": ["<<other_name>>"], "retry": 0 }]
+            }),
+        );
+        assert_eq!(load(&path).get_programs()[1].lock().unwrap().to_string(), "echo '<<other_name>>'");
+        assert_eq!(get_parse_count(&path), 2);
+
+        // A change to the chain it extends counts too
+        write_chain(&base_path, &json!({ "programs": [{ "command": "echo", "arguments": ["changed"], "retry": 0 }] }));
+        assert_eq!(load(&path).get_programs()[0].lock().unwrap().to_string(), "echo changed");
+        assert_eq!(get_parse_count(&path), 3);
+        load(&path);
+        assert_eq!(get_parse_count(&path), 3);
+
+        // Inline chains are never cached
+        let inline: String = json!([{ "command": "echo", "arguments": [], "retry": 0 }]).to_string();
+        assert!(Chain::from_json_str(&inline, "inline").is_ok());
+        assert_eq!(get_parse_count(Path::new("inline")), 0);
+    }
+
+    // Test that loading 100 chains again reuses them instead of parsing them
+    #[test]
+    fn test_parse_cache_reuses_loaded_chains() {
+        let directory = tempfile::tempdir().unwrap();
+        let paths: Vec<PathBuf> = (0..100)
+            .map(|index| {
+                let programs: Vec<Value> = (0..3)
+                    .map(|program_index| {
+                        json!({
+                            "command": "echo",
+                            "arguments": [
+                                format!("<<first_{}>>", program_index),
+                                format!("<<second_{}:on_program_execution>>", program_index),
+                                "<<?Anonymous prompt>>"
+                            ],
+                            "retry": 0
+                        })
+                    })
+                    .collect();
+                let path: PathBuf = directory.path().join(format!("chain_{}.json", index));
+                write_chain(&path, &Value::Array(programs));
+                path
+            })
+            .collect();
+
+        for path in paths.iter().chain(&paths) {
+            load(path);
+        }
+        assert!(paths.iter().all(|path| get_parse_count(path) == 1));
+        assert!(paths.iter().all(|path| get_cache_hit_count(path) == 1));
+    }
+
+    // Test that a chain kept on the disk is loaded as it was parsed, until its file changes
+    #[test]
+    fn test_parse_cache_on_disk() {
+        let directory = tempfile::tempdir().unwrap();
+        let cache_directory: PathBuf = directory.path().join("parsed_chains");
+        let path: PathBuf = directory.path().join("cchain_disk.json");
+        write_chain(
+            &path,
+            &json!({
+                "metadata": { "timezone": "UTC", "variables": { "branch": { "description": "Branch to build" } } },
+                "programs": [
+                    { "command": "echo", "arguments": ["<<branch>>", "<<?Anonymous prompt>>"], "stdout_stored_to": "<<built>>", "retry": 0 },
+                    { "command": "echo", "arguments": ["<<built>>", "<<later:on_program_execution>>"], "retry": 0 }
+                ]
+            }),
+        );
+        set_parse_cache_directory(Some(cache_directory.clone()));
+
+        let parsed: Chain = load(&path);
+        assert_eq!(std::fs::read_dir(&cache_directory).unwrap().count(), 1);
+        // Another process has nothing of the chain in memory
+        evict_cached_chain(&path);
+        let stored: Chain = load(&path);
+        assert_eq!((get_parse_count(&path), get_cache_hit_count(&path)), (1, 1));
+        for (parsed_program, stored_program) in parsed.get_programs().iter().zip(stored.get_programs()) {
+            assert_eq!(*parsed_program.lock().unwrap(), *stored_program.lock().unwrap());
+        }
+        let get_variables = |chain: &Chain| -> Vec<Variable> {
+            chain.get_variables().iter().map(|variable| variable.lock().unwrap().clone()).collect()
+        };
+        assert_eq!(get_variables(&parsed), get_variables(&stored));
+        let branch: Vec<Variable> =
+            get_variables(&stored).into_iter().filter(|variable| variable.get_variable_name() == "branch").collect();
+        assert_eq!(branch[0].get_declaration().unwrap().description.as_deref(), Some("Branch to build"));
+
+        // A change to the file is parsed again
+        write_chain(&path, &json!([{ "command": "echo", "arguments": ["changed"], "retry": 0 }]));
+        evict_cached_chain(&path);
+        assert_eq!(load(&path).get_programs()[0].lock().unwrap().to_string(), "echo changed");
+        assert_eq!((get_parse_count(&path), get_cache_hit_count(&path)), (2, 1));
+        set_parse_cache_directory(None);
+    }
+
+    // Test that a chain cached with higher limits is checked again against lower ones
+    #[test]
+    fn test_cached_chain_checked_against_limits() {
+        let directory = tempfile::tempdir().unwrap();
+        let path: PathBuf = directory.path().join("cchain_limits.json");
+        write_chain(&path, &Value::Array((0..3).map(|_| json!({ "command": "echo", "arguments": [], "retry": 0 })).collect()));
+        let check = |limits: &str| {
+            assert_cmd::Command::cargo_bin("cchain")
+                .unwrap()
+                .env("CCHAIN_HOME", directory.path())
+                .arg("check")
+                .arg(&path)
+                .args(["--limits", limits])
+                .output()
+                .unwrap()
+                .status
+                .code()
+        };
+
+        assert_eq!(check("max_programs=2"), Some(2));
+        assert_eq!(check("max_programs=5"), Some(0));
+        assert_eq!(check("max_programs=2"), Some(2));
+        assert_eq!(check("max_programs=5"), Some(0));
+    }
+}
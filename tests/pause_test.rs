@@ -0,0 +1,85 @@
+#[cfg(test)]
+mod tests {
+    use cchain::core::pause::{PauseControl, PauseDecision, PauseKey, PauseState};
+
+    // Test that the keys map to the controls, whatever their case
+    #[test]
+    fn test_pause_key_from_key() {
+        assert_eq!(PauseKey::from_key(b'p'), Some(PauseKey::TogglePause));
+        assert_eq!(PauseKey::from_key(b'S'), Some(PauseKey::SkipNext));
+        assert_eq!(PauseKey::from_key(b'a'), Some(PauseKey::Abort));
+        assert_eq!(PauseKey::from_key(b'q'), None);
+    }
+
+    // Test pausing, skipping and resuming
+    #[test]
+    fn test_pause_state() {
+        let mut state = PauseState::new();
+        assert_eq!(state.take_decision(), Some(PauseDecision::Continue));
+
+        // Skipping and aborting only apply while paused
+        assert!(!state.press(PauseKey::SkipNext));
+        assert!(!state.press(PauseKey::Abort));
+        assert_eq!(state.take_decision(), Some(PauseDecision::Continue));
+
+        assert!(state.press(PauseKey::TogglePause));
+        assert!(state.is_paused());
+        assert_eq!(state.take_decision(), None);
+
+        // A skip applies to one program, and the chain stays paused
+        assert!(state.press(PauseKey::SkipNext));
+        assert!(!state.press(PauseKey::SkipNext));
+        assert_eq!(state.take_decision(), Some(PauseDecision::Skip));
+        assert_eq!(state.take_decision(), None);
+
+        assert!(state.press(PauseKey::TogglePause));
+        assert!(!state.is_paused());
+        assert_eq!(state.take_decision(), Some(PauseDecision::Continue));
+    }
+
+    // Test that aborting outlasts resuming
+    #[test]
+    fn test_pause_state_abort() {
+        let mut state = PauseState::new();
+        state.press(PauseKey::TogglePause);
+        state.press(PauseKey::SkipNext);
+        assert!(state.press(PauseKey::Abort));
+        state.press(PauseKey::TogglePause);
+        assert_eq!(state.take_decision(), Some(PauseDecision::Abort));
+        assert_eq!(state.take_decision(), Some(PauseDecision::Abort));
+    }
+
+    // Test that a paused chain waits, skips the next program, and continues once resumed
+    #[cfg(unix)]
+    #[test]
+    fn test_paused_chain_skips_and_resumes() {
+        use std::{io::Write, thread, time::Duration};
+
+        use cchain::{core::traits::Execution, Chain};
+        use tempfile::NamedTempFile;
+
+        let directory = tempfile::tempdir().unwrap();
+        let output_path = directory.path().join("output.txt");
+        let programs = serde_json::json!([
+            { "command": "sh", "arguments": ["-c", format!("echo first >> {}", output_path.display())], "retry": 0 },
+            { "command": "sh", "arguments": ["-c", format!("echo second >> {}", output_path.display())], "retry": 0 }
+        ]);
+        let mut temp_file = NamedTempFile::new().unwrap();
+        write!(temp_file, "{}", programs).unwrap();
+
+        let pause_control = PauseControl::new();
+        pause_control.press(PauseKey::TogglePause);
+        pause_control.press(PauseKey::SkipNext);
+        let resuming_control: PauseControl = pause_control.clone();
+        let resumer = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(300));
+            resuming_control.press(PauseKey::TogglePause);
+        });
+
+        let mut chain = Chain::from_file(temp_file.path().to_str().unwrap()).unwrap();
+        chain.set_pause_control(Some(pause_control));
+        chain.execute().unwrap();
+        resumer.join().unwrap();
+        assert_eq!(std::fs::read_to_string(&output_path).unwrap(), "second\n");
+    }
+}
@@ -1,9 +1,15 @@
 #[cfg(test)]
 mod tests {
-    use std::str::FromStr;
+    use std::{collections::HashSet, str::FromStr};
 
     use anyhow::Result;
-    use cchain::core::{command::CommandLine, interpreter::Interpreter, options::{FailureHandlingOptions, StdoutStorageOptions}, program::Program, traits::Execution};
+    use cchain::commons::errors::{CommandLineError, SpawnErrorKind};
+    use cchain::commons::interaction::{ScriptedInteractor, SharedInteractor};
+    use cchain::commons::packages::{get_edit_distance, Package};
+    use cchain::commons::termination::ProcessTermination;
+    use cchain::display_control::{LiveOutputFilter, SUMMARY_OUTPUT_LINES};
+    use cchain::core::{assertion::OutputAssertion, command::{get_not_found_cause, CommandLine}, interpreter::Interpreter, options::{DisplayOutput, FailureHandlingOptions, StdoutStorageOptions}, program::Program, traits::Execution};
+    use cchain::function::VariableParameterValue;
 
     #[test]
     fn test_execute_success() -> Result<()> {
@@ -20,7 +26,7 @@ mod tests {
             0,
         );
         let result = program.execute()?;
-        assert!(!result[0].clone().get_output().is_empty());
+        assert!(!result[0].output().is_empty());
         Ok(())
     }
 
@@ -52,6 +58,7 @@ mod tests {
             None,
             StdoutStorageOptions {
                 without_newline_characters: true,
+                ..Default::default()
             },
             None,
             FailureHandlingOptions::default(),
@@ -59,7 +66,7 @@ mod tests {
             0,
         );
         let result = program.execute()?;
-        assert_eq!(result[0].clone().get_output(), "test");
+        assert_eq!(result[0].output(), "test");
         Ok(())
     }
 
@@ -88,7 +95,7 @@ mod tests {
             None,
             0,
         );
-        program.execute_remedy_command_line()?;
+        program.execute_remedy_command_line(&|name| Err(anyhow::anyhow!("no variable {}", name)))?;
         Ok(())
     }
 
@@ -126,4 +133,519 @@ mod tests {
         assert!(program.get_awaitable_variable().is_none());
         assert_eq!(program.get_concurrency_group(), None);
     }
-}
\ No newline at end of file
+
+    /// A script failing with a different message on each run,
+    /// using a counter file to remember the number of runs
+    fn create_flaky_program(directory: &std::path::Path, retry: i32) -> Program {
+        let counter = directory.join("counter");
+        let script = format!(
+            r#"n=$(cat {0} 2>/dev/null || echo 0); n=$((n+1)); echo $n > {0};
+            case $n in
+                1) echo "connection reset by peer"; exit 1;;
+                2) echo "error: could not compile"; exit 1;;
+                *) echo "done";;
+            esac"#,
+            counter.display()
+        );
+
+        Program::new(
+            "sh".to_string(),
+            vec!["-c".to_string(), script],
+            None,
+            None,
+            None,
+            StdoutStorageOptions::default(),
+            None,
+            FailureHandlingOptions::default(),
+            None,
+            retry,
+        )
+    }
+
+    fn read_counter(directory: &std::path::Path) -> String {
+        std::fs::read_to_string(directory.join("counter")).unwrap().trim().to_string()
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_retry_on_stops_when_no_pattern_matches() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut program = create_flaky_program(directory.path(), 5)
+            .with_retry_on(vec!["connection reset".to_string(), "5\\d\\d".to_string()]);
+
+        assert!(program.execute().is_err());
+        // The first failure is retried, the compile error is not
+        assert_eq!(read_counter(directory.path()), "2");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_retry_on_retries_until_success() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut program = create_flaky_program(directory.path(), 5)
+            .with_retry_on(vec!["connection reset".to_string(), "could not compile".to_string()]);
+
+        let result = program.execute().unwrap();
+        assert_eq!(result[0].output(), "done");
+        assert_eq!(read_counter(directory.path()), "3");
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_fail_fast_on_aborts_regardless_of_retries() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut program = create_flaky_program(directory.path(), 5)
+            .with_fail_fast_on(vec!["could not compile".to_string()]);
+
+        assert!(program.execute().is_err());
+        assert_eq!(read_counter(directory.path()), "2");
+    }
+
+    #[test]
+    fn test_validate_retry_patterns() {
+        let program = Program::default().with_retry_on(vec!["(unclosed".to_string()]);
+        assert!(program.validate_retry_patterns().is_err());
+
+        let program = Program::default().with_fail_fast_on(vec!["fatal: .*".to_string()]);
+        assert!(program.validate_retry_patterns().is_ok());
+    }
+
+    // Test that assertions see the output after the stdout storage options
+    #[test]
+    fn test_assertions_after_stdout_storage_options() {
+        let program = |without_newline_characters: bool, assertion: OutputAssertion| {
+            Program::new(
+                "printf".to_string(),
+                vec!["ready\n".to_string()],
+                None,
+                None,
+                None,
+                StdoutStorageOptions { without_newline_characters, ..Default::default() },
+                None,
+                FailureHandlingOptions::default(),
+                None,
+                0,
+            )
+            .with_assertions(vec![assertion])
+        };
+
+        let ends_with_ready = || OutputAssertion::MatchesRegex("ready$".to_string());
+        let ends_with_newline = || OutputAssertion::MatchesRegex("\n$".to_string());
+        assert!(program(true, ends_with_ready()).execute().is_ok());
+        assert!(program(true, ends_with_newline()).execute().is_err());
+        assert!(program(false, ends_with_newline()).execute().is_ok());
+
+        let error = program(true, OutputAssertion::Contains("done".to_string()))
+            .execute()
+            .unwrap_err();
+        assert!(error.to_string().starts_with("Assertion failed: contains \"done\""));
+    }
+
+    /// Create a program printing Terraform-like output, which stores
+    /// what the capture filter keeps
+    fn create_capturing_program(capture_filter: &str, capture_group: Option<usize>, required_capture: bool) -> Program {
+        Program::new(
+            "printf".to_string(),
+            vec!["Applying...\nOutputs:\nurl = https://example.com\nid = 42\nDone\n".to_string()],
+            None,
+            None,
+            Some("<<output>>".to_string()),
+            StdoutStorageOptions {
+                capture_filter: Some(capture_filter.to_string()),
+                capture_group,
+                required_capture,
+                ..Default::default()
+            },
+            None,
+            FailureHandlingOptions::default(),
+            None,
+            0,
+        )
+    }
+
+    // Test that only the lines matching the capture filter are stored, or their capture groups
+    #[test]
+    fn test_capture_filter() {
+        let results = create_capturing_program(r"^(url|id) = ", None, false).execute().unwrap();
+        assert_eq!(results[0].output(), "url = https://example.com\nid = 42");
+
+        let results = create_capturing_program(r"^\w+ = (.*)$", Some(1), false).execute().unwrap();
+        assert_eq!(results[0].output(), "https://example.com\n42");
+
+        // No match stores an empty value, unless a capture is required
+        let results = create_capturing_program("^error", None, false).execute().unwrap();
+        assert_eq!(results[0].output(), "");
+        let error = create_capturing_program("^error", None, true).execute().unwrap_err();
+        assert_eq!(error.to_string(), "No line of the output matches the `capture_filter` pattern `^error`");
+
+        assert!(create_capturing_program(r"^\w+ = (.*)$", Some(2), false).validate_capture_filter().is_err());
+        assert!(create_capturing_program("(", None, false).validate_capture_filter().is_err());
+    }
+
+    fn create_never_succeeding_program() -> Program {
+        Program::new(
+            "false".to_string(),
+            vec![],
+            None,
+            None,
+            None,
+            StdoutStorageOptions::default(),
+            None,
+            FailureHandlingOptions::default(),
+            None,
+            -1,
+        )
+        .with_retry_escalation_attempts(3)
+    }
+
+    // Test that infinite retries escalate to a prompt every few attempts, and can be aborted
+    #[test]
+    fn test_infinite_retry_escalation_and_abort() {
+        let interactor = ScriptedInteractor::new(vec!["c".to_string(), "a".to_string()]);
+        let prompts = interactor.get_prompts();
+        let mut program = create_never_succeeding_program();
+        program.set_interactor(SharedInteractor::new(interactor));
+
+        let error = program.execute().unwrap_err().to_string();
+        assert!(error.starts_with("Retrying is aborted after 6 attempts"));
+        assert_eq!(program.get_attempts(), 6);
+        assert_eq!(prompts.lock().unwrap().len(), 2);
+        assert!(prompts.lock().unwrap()[0].contains("Keep retrying?"));
+    }
+
+    // Test that the attempts are counted for finite retries too
+    #[test]
+    fn test_finite_retry_records_attempts() {
+        let mut program = Program::new(
+            "false".to_string(),
+            vec![],
+            None,
+            None,
+            None,
+            StdoutStorageOptions::default(),
+            None,
+            FailureHandlingOptions::default(),
+            None,
+            2,
+        );
+
+        let error = program.execute().unwrap_err();
+        assert!(error.to_string().ends_with("(after 3 attempts)"));
+        // The failure can still be told apart after the attempts
+        assert!(matches!(error.downcast_ref::<CommandLineError>(), Some(CommandLineError::NonZeroExit { .. })), "{:?}", error);
+        assert_eq!(program.get_attempts(), 3);
+    }
+
+    // Test that every attempt is recorded, and only the successful output is stored by default
+    #[cfg(unix)]
+    #[test]
+    fn test_attempt_records() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut program = create_flaky_program(directory.path(), 3);
+
+        let result = program.execute().unwrap().remove(0);
+        let records = result.get_attempts().clone();
+        assert_eq!(records.len(), 3);
+        assert_eq!(records.iter().map(|record| record.attempt).collect::<Vec<u32>>(), vec![1, 2, 3]);
+        assert_eq!(records.iter().map(|record| record.succeeded).collect::<Vec<bool>>(), vec![false, false, true]);
+        assert_eq!(records[0].status, "exit status: 1");
+        assert_eq!(records[0].output_tail, "connection reset by peer");
+        assert_eq!(records[2].status, "succeeded");
+        assert_eq!(program.get_attempt_records(), &records);
+        assert_eq!(result.output(), "done");
+    }
+
+    // Test the results of a program that succeeds, one that fails, and one that succeeds after retries
+    #[cfg(unix)]
+    #[test]
+    fn test_program_execution_results() {
+        let create_program = |script: &str, retry: i32| {
+            Program::new(
+                "sh".to_string(),
+                vec!["-c".to_string(), script.to_string()],
+                None,
+                None,
+                None,
+                StdoutStorageOptions::default(),
+                None,
+                FailureHandlingOptions::default(),
+                None,
+                retry,
+            )
+        };
+
+        let mut program = create_program("echo ok", 0);
+        let result = program.execute().unwrap().remove(0);
+        assert_eq!(result.output(), "ok");
+        assert_eq!(result.exit_code(), Some(0));
+        assert_eq!(result.attempts(), 1);
+        assert!(!result.was_retried() && result.is_succeeded());
+        assert_eq!(result.command_rendered(), "sh -c 'echo ok'");
+        assert_eq!(program.get_last_result(), Some(&result));
+
+        // Failed programs have results too
+        let mut program = create_program("echo broken; exit 3", 0);
+        assert!(program.execute().is_err());
+        let result = program.get_last_result().unwrap();
+        assert_eq!(result.output().trim(), "broken");
+        assert_eq!(result.exit_code(), Some(3));
+        assert_eq!(result.attempts(), 1);
+        assert!(!result.is_succeeded());
+
+        let directory = tempfile::tempdir().unwrap();
+        let mut program = create_flaky_program(directory.path(), 3);
+        let result = program.execute().unwrap().remove(0);
+        assert_eq!(result.attempts(), 3);
+        assert!(result.was_retried() && result.is_succeeded());
+        assert_eq!(result.exit_code(), Some(0));
+        assert_eq!(result.get_attempts()[0].exit_code, Some(1));
+        let attempt_duration_ms: u64 = result.get_attempts().iter().map(|record| record.duration_ms).sum();
+        assert!(result.duration().as_millis() as u64 >= attempt_duration_ms);
+    }
+
+    // Test that `store_all_attempts` joins the outputs of the attempts under headers
+    #[cfg(unix)]
+    #[test]
+    fn test_store_all_attempts() {
+        let directory = tempfile::tempdir().unwrap();
+        let mut program = create_flaky_program(directory.path(), 3).with_store_all_attempts(true);
+
+        let output = program.execute().unwrap().remove(0).output().to_string();
+        assert_eq!(
+            output,
+            "--- Attempt 1: exit status: 1 ---\nconnection reset by peer\n--- Attempt 2: exit status: 1 ---\nerror: could not compile\n--- Attempt 3: succeeded ---\ndone"
+        );
+    }
+
+    // Test that function calls are detected and replaced in environment variable overrides and the working directory
+    #[cfg(unix)]
+    #[test]
+    fn test_functions_in_environment_and_working_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        let token_path = directory.path().join("token.txt");
+        std::fs::write(&token_path, "secret-token\n").unwrap();
+        let working_directory_path = directory.path().join("working_directory.txt");
+        std::fs::write(&working_directory_path, directory.path().to_string_lossy().as_ref()).unwrap();
+
+        let mut program = Program::new(
+            "sh".to_string(),
+            vec!["-c".to_string(), "printf '%s|%s' \"$TOKEN\" \"$(pwd)\"".to_string()],
+            Some(std::collections::HashMap::from([
+                ("TOKEN".to_string(), format!("file('{}')", token_path.display())),
+                ("PLAIN".to_string(), "value".to_string()),
+            ])),
+            Some(format!("file('{}')", working_directory_path.display())),
+            None,
+            StdoutStorageOptions::default(),
+            None,
+            FailureHandlingOptions::default(),
+            None,
+            0,
+        );
+
+        let fields: Vec<String> = program.get_function_calls().into_iter().map(|(field, _)| field).collect();
+        assert_eq!(fields, vec!["environment_variables_override.TOKEN", "working_directory"]);
+
+        program.execute_argument_functions(&|name| Err(anyhow::anyhow!("no variable {}", name))).unwrap();
+        let output = program.execute().unwrap().remove(0).output().to_string();
+        assert_eq!(
+            output,
+            format!("secret-token|{}", directory.path().canonicalize().unwrap().display())
+        );
+    }
+
+    // Test that the value of a secret passed to a function is masked in
+    // the command line
+    #[test]
+    fn test_secret_function_parameter_masked() {
+        let mut program: Program = serde_json::from_str(
+            r#"{ "command": "echo", "arguments": ["env('CCHAIN_UNSET_SECRET_PARAMETER', <<token>>)"], "retry": 0 }"#,
+        )
+        .unwrap();
+        program
+            .execute_argument_functions(&|_| Ok(VariableParameterValue { value: "hunter2".to_string(), is_secret: true }))
+            .unwrap();
+        let command_line = program.get_command_line();
+        assert_eq!(command_line.get_arguments()[0].get_value(), "hunter2");
+        assert!(!command_line.to_string().contains("hunter2"), "{}", command_line);
+    }
+
+    fn create_missing_command_program(retry: i32) -> Program {
+        Program::new(
+            "cchain-missing-command".to_string(),
+            vec![],
+            None,
+            None,
+            None,
+            StdoutStorageOptions::default(),
+            None,
+            FailureHandlingOptions::default(),
+            None,
+            retry,
+        )
+    }
+
+    // Test that a command that is not found fails on the first attempt, whatever the retries
+    #[test]
+    fn test_spawn_errors_are_not_retried() {
+        let mut program = create_missing_command_program(5);
+
+        let error = program.execute().unwrap_err();
+        assert_eq!(program.get_attempts(), 1);
+        assert!(matches!(
+            error.downcast_ref::<CommandLineError>(),
+            Some(CommandLineError::SpawnFailed { kind: SpawnErrorKind::NotFound, .. })
+        ));
+        assert!(error.to_string().contains("`cchain-missing-command` is not found in PATH"));
+    }
+
+    // Test that `retry_spawn_errors` retries a command that is not found
+    #[test]
+    fn test_retry_spawn_errors() {
+        let mut program = create_missing_command_program(2).with_retry_spawn_errors(true);
+
+        let error = program.execute().unwrap_err().to_string();
+        assert!(error.ends_with("(after 3 attempts)"));
+        assert_eq!(program.get_attempts(), 3);
+    }
+
+    // Test that a missing working directory is told apart from a missing command
+    #[test]
+    fn test_invalid_working_directory_spawn_error() {
+        let directory = tempfile::tempdir().unwrap();
+        let missing_directory = directory.path().join("missing").display().to_string();
+        let mut program = Program::new(
+            "echo".to_string(),
+            vec![],
+            None,
+            Some(missing_directory.clone()),
+            None,
+            StdoutStorageOptions::default(),
+            None,
+            FailureHandlingOptions::default(),
+            None,
+            3,
+        );
+
+        let error = program.execute().unwrap_err();
+        assert_eq!(program.get_attempts(), 1);
+        assert!(matches!(
+            error.downcast_ref::<CommandLineError>(),
+            Some(CommandLineError::SpawnFailed { kind: SpawnErrorKind::InvalidWorkingDirectory, .. })
+        ));
+        assert!(error.to_string().contains(&format!("The working directory `{}` does not exist", missing_directory)));
+    }
+
+    // Test the did-you-mean suggestion for a mistyped command
+    #[test]
+    fn test_not_found_suggestion() {
+        let packages: HashSet<Package> = ["cargo", "cat", "carton"]
+            .into_iter()
+            .map(|name| Package::new(name.to_string()))
+            .collect();
+
+        let suggestion = Package::find_similar_package("cargp", &packages).map(|package| package.access_package_name());
+        assert_eq!(suggestion, Some("cargo"));
+        assert_eq!(
+            get_not_found_cause("cargp", suggestion),
+            "`cargp` is not found in PATH. Did you mean `cargo`?"
+        );
+        // Names that are too far apart are not suggested
+        assert!(Package::find_similar_package("kubectl", &packages).is_none());
+        assert_eq!(get_not_found_cause("kubectl", None), "`kubectl` is not found in PATH");
+        assert_eq!(get_edit_distance("cargp", "cargo"), 1);
+        assert_eq!(get_edit_distance("", "cat"), 3);
+    }
+
+    fn create_shell_program(script: &str, retry: i32) -> Program {
+        Program::new(
+            "sh".to_string(),
+            vec!["-c".to_string(), script.to_string()],
+            None,
+            None,
+            None,
+            StdoutStorageOptions::default(),
+            None,
+            FailureHandlingOptions::default(),
+            None,
+            retry,
+        )
+    }
+
+    // Test that a program killed by a signal is told apart from one exiting with an error
+    #[cfg(unix)]
+    #[test]
+    fn test_signal_termination() {
+        let mut program = create_shell_program("kill -KILL $$", 0);
+        let error = program.execute().unwrap_err();
+        let termination = error.downcast_ref::<CommandLineError>().and_then(|error| error.get_termination()).unwrap();
+        assert_eq!(termination.name, "SIGKILL");
+        assert!(!termination.is_crash);
+        assert!(error.to_string().contains("Ended by SIGKILL: possibly out of memory, or killed by another process"));
+        assert_eq!(program.get_attempt_records()[0].termination.as_ref(), Some(termination));
+
+        let error = create_shell_program("exit 1", 0).execute().unwrap_err();
+        assert!(error.downcast_ref::<CommandLineError>().unwrap().get_termination().is_none());
+    }
+
+    // Test that crashes are not retried unless `retry_crashes` is set
+    #[cfg(unix)]
+    #[test]
+    fn test_crashes_not_retried() {
+        let mut program = create_shell_program("kill -SEGV $$", 2);
+        let error = program.execute().unwrap_err();
+        assert!(error.to_string().contains("Ended by SIGSEGV: crashed on a segmentation fault"));
+        assert_eq!(program.get_attempts(), 1);
+
+        let mut program = create_shell_program("kill -SEGV $$", 2).with_retry_crashes(true);
+        program.execute().unwrap_err();
+        assert_eq!(program.get_attempts(), 3);
+    }
+
+    // Test the descriptions of signals and of Windows crash codes
+    #[test]
+    fn test_termination_descriptions() {
+        let termination = ProcessTermination::from_signal(11, true);
+        assert!(termination.is_crash);
+        assert_eq!(termination.to_string(), "Ended by SIGSEGV: crashed on a segmentation fault (core dumped)");
+        assert_eq!(ProcessTermination::from_signal(15, false).to_string(), "Ended by SIGTERM: terminated by another process");
+        assert_eq!(ProcessTermination::from_signal(64, false).name, "signal 64");
+
+        let termination = ProcessTermination::from_windows_status(0xC0000005).unwrap();
+        assert_eq!(termination.name, "STATUS_ACCESS_VIOLATION");
+        assert!(termination.is_crash);
+        assert!(!ProcessTermination::from_windows_status(0xC0000017).unwrap().is_crash);
+        assert!(ProcessTermination::from_windows_status(1).is_none());
+    }
+
+    // Test that the summary shows the first and the last lines around a
+    // marker, across chunks that split lines
+    #[test]
+    fn test_live_output_summary() {
+        let mut live_output = LiveOutputFilter::new(DisplayOutput::Summary);
+        let output: String = (0..5000).map(|line| format!("line {}\n", line)).collect();
+        let mut shown: String = String::new();
+        for chunk in output.as_bytes().chunks(7) {
+            shown.push_str(&live_output.push(std::str::from_utf8(chunk).unwrap()));
+        }
+        shown.push_str(&live_output.finish());
+
+        let lines: Vec<&str> = shown.lines().collect();
+        assert_eq!(lines.len(), SUMMARY_OUTPUT_LINES * 2 + 1);
+        assert_eq!(lines[0], "line 0");
+        assert_eq!(lines[SUMMARY_OUTPUT_LINES], "… suppressed 4,980 lines …");
+        assert_eq!(lines.last(), Some(&"line 4999"));
+
+        let mut live_output = LiveOutputFilter::new(DisplayOutput::Silent);
+        assert_eq!(live_output.push("hello\n"), "");
+        assert_eq!(live_output.finish(), "");
+    }
+
+    // Test that a program showing less of its output still stores all of it
+    #[test]
+    fn test_display_output_keeps_full_output() {
+        let mut program = create_shell_program("seq 1 100", 0).with_display_output(DisplayOutput::Silent);
+        let result = program.execute().unwrap();
+        assert_eq!(result[0].output().lines().count(), 100);
+    }
+}
@@ -0,0 +1,205 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::{Read, Write},
+        sync::{Arc, Mutex},
+        time::Duration,
+    };
+
+    use cchain::{
+        core::{
+            events::ChainEvent,
+            progress::{ProgressConverter, ProgressEvent, ProgressRecord, ProgressReporter, ProgressStatus, PROGRESS_EVENTS_VERSION},
+            schema::{get_progress_event_schema, validate_against_schema},
+            traits::Execution,
+        },
+        display_control::{render_banner, render_form, render_status_line, SummaryDisplay},
+        Chain,
+    };
+
+    fn parse_records(content: &str) -> Vec<ProgressRecord> {
+        content.lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    // Test that retries are reported once each, and programs with their durations
+    #[test]
+    fn test_progress_converter() {
+        let mut converter = ProgressConverter::new("deploy.json", vec![Some("build".to_string()), None]);
+        let events: Vec<(ChainEvent, u64)> = vec![
+            (ChainEvent::ChainStarted { program_labels: vec!["cargo build".to_string(), "cargo test".to_string()] }, 0),
+            (ChainEvent::ProgramStarted { program_index: 0 }, 0),
+            (ChainEvent::ProgramOutput { program_index: 0, text: "Compiling\n".to_string() }, 1),
+            (ChainEvent::RetryScheduled { program_index: 0, attempt: 2 }, 2),
+            (ChainEvent::ProgramStarted { program_index: 0 }, 2),
+            (ChainEvent::ProgramFinished { program_index: 0, succeeded: true }, 5),
+            (ChainEvent::ProgramStarted { program_index: 1 }, 5),
+            (ChainEvent::ProgramFinished { program_index: 1, succeeded: false }, 6),
+            (ChainEvent::ChainFinished { succeeded: true }, 6),
+        ];
+        let progress_events: Vec<ProgressEvent> = events
+            .iter()
+            .filter_map(|(event, at)| converter.convert(event, Duration::from_secs(*at)))
+            .collect();
+
+        assert_eq!(
+            progress_events,
+            vec![
+                ProgressEvent::ChainStarted { chain: "deploy.json".to_string(), total_steps: 2 },
+                ProgressEvent::ProgramStarted { index: 0, alias: Some("build".to_string()) },
+                ProgressEvent::RetryScheduled { index: 0, alias: Some("build".to_string()), attempt: 2 },
+                ProgressEvent::ProgramFinished {
+                    index: 0,
+                    alias: Some("build".to_string()),
+                    status: ProgressStatus::Succeeded,
+                    duration_ms: 5000,
+                },
+                ProgressEvent::ProgramStarted { index: 1, alias: None },
+                ProgressEvent::ProgramFinished { index: 1, alias: None, status: ProgressStatus::Failed, duration_ms: 1000 },
+                // The chain kept going after a failure
+                ProgressEvent::ChainFinished { status: ProgressStatus::Failed, failures: vec![1], dropped_events: 0 },
+            ]
+        );
+    }
+
+    // Test that `--summary-only` shows a line per program, with the output
+    // and the exit code of its last attempt
+    #[test]
+    fn test_summary_display() {
+        let mut summary_display = SummaryDisplay::default();
+        let events: Vec<(ChainEvent, u64)> = vec![
+            (ChainEvent::ChainStarted { program_labels: vec!["cargo build".to_string(), "cargo test".to_string()] }, 0),
+            (ChainEvent::ProgramStarted { program_index: 0 }, 0),
+            (ChainEvent::ProgramOutput { program_index: 0, text: "error\n".to_string() }, 1),
+            (ChainEvent::ProcessExited { program_index: 0, exit_code: Some(101), io_usage: None }, 1),
+            (ChainEvent::RetryScheduled { program_index: 0, attempt: 2 }, 1),
+            (ChainEvent::ProgramStarted { program_index: 0 }, 2),
+            (ChainEvent::ProgramOutput { program_index: 0, text: "Finished\n".to_string() }, 2),
+            (ChainEvent::ProcessExited { program_index: 0, exit_code: Some(0), io_usage: None }, 3),
+            (ChainEvent::ProgramFinished { program_index: 0, succeeded: true }, 3),
+            (ChainEvent::ProgramStarted { program_index: 1 }, 3),
+            (ChainEvent::ProgramFinished { program_index: 1, succeeded: false }, 4),
+            // Reported again by the chain
+            (ChainEvent::ProgramFinished { program_index: 1, succeeded: false }, 4),
+        ];
+        let lines: Vec<(bool, String)> = events
+            .iter()
+            .filter_map(|(event, at)| summary_display.push(event, Duration::from_secs(*at)))
+            .collect();
+
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[1], (true, "✔ #0 cargo build (3000ms, exit 0, 9 bytes of output)".to_string()));
+        assert_eq!(lines[2], (false, "✘ #1 cargo test (1000ms, no exit code, 0 bytes of output)".to_string()));
+    }
+
+    // Test that a run writes its progress to a pipe, in the documented form
+    #[test]
+    fn test_progress_to_pipe() {
+        let mut chain = Chain::from_json_str(
+            r#"[
+                { "command": "echo", "arguments": ["hello"], "retry": 0, "alias": "greet" },
+                { "command": "false", "arguments": [], "retry": 1 }
+            ]"#,
+            "progress",
+        )
+        .unwrap();
+        let (mut reader, writer) = std::io::pipe().unwrap();
+        let progress_reporter = ProgressReporter::start(&mut chain, Box::new(writer));
+        assert!(chain.execute().is_err());
+        progress_reporter.finish(false);
+
+        let mut content = String::new();
+        reader.read_to_string(&mut content).unwrap();
+        let records: Vec<ProgressRecord> = parse_records(&content);
+        let events: Vec<String> = content
+            .lines()
+            .map(|line| serde_json::from_str::<serde_json::Value>(line).unwrap()["event"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(
+            events,
+            vec![
+                "chain_started",
+                "program_started",
+                "program_finished",
+                "program_started",
+                "retry_scheduled",
+                "program_finished",
+                "chain_finished"
+            ]
+        );
+        assert!(records.iter().all(|record| record.version == PROGRESS_EVENTS_VERSION));
+        assert_eq!(records[1].event, ProgressEvent::ProgramStarted { index: 0, alias: Some("greet".to_string()) });
+        assert_eq!(
+            records[6].event,
+            ProgressEvent::ChainFinished { status: ProgressStatus::Failed, failures: vec![1], dropped_events: 0 }
+        );
+
+        let schema = get_progress_event_schema();
+        for line in content.lines() {
+            let value: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(validate_against_schema(&value, &schema), Vec::new(), "{}", line);
+        }
+    }
+
+    /// A reader that takes a while for each line
+    struct SlowWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SlowWriter {
+        fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+            std::thread::sleep(Duration::from_millis(100));
+            self.0.lock().unwrap().extend_from_slice(buffer);
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    // Test that a slow reader does not hold up the chain, and that the
+    // dropped events are counted in the last one
+    #[test]
+    fn test_slow_progress_reader() {
+        let programs: Vec<serde_json::Value> = (0..10)
+            .map(|index| serde_json::json!({ "command": "echo", "arguments": [index.to_string()], "retry": 0 }))
+            .collect();
+        let mut chain = Chain::from_json_str(&serde_json::Value::Array(programs).to_string(), "slow").unwrap();
+        let buffer: Arc<Mutex<Vec<u8>>> = Arc::new(Mutex::new(Vec::new()));
+        let progress_reporter = ProgressReporter::start_with_capacity(&mut chain, Box::new(SlowWriter(buffer.clone())), 1);
+        assert!(chain.execute().is_ok());
+        progress_reporter.finish(true);
+
+        let records: Vec<ProgressRecord> = parse_records(&String::from_utf8(buffer.lock().unwrap().clone()).unwrap());
+        match &records.last().unwrap().event {
+            ProgressEvent::ChainFinished { status, dropped_events, .. } => {
+                assert_eq!(*status, ProgressStatus::Succeeded);
+                assert!(*dropped_events > 0);
+                assert_eq!(records.len() as u64 + dropped_events, 22);
+            }
+            event => panic!("unexpected last event: {:?}", event),
+        }
+    }
+
+    #[test]
+    fn test_width_dependent_rendering() {
+        // Banners and status lines fit narrow terminals
+        let message: String = "Running the chain of the release of the project".to_string();
+        let (rule, line) = render_banner(&message, 40);
+        assert_eq!(line.chars().count(), 40);
+        assert_eq!(rule.chars().count(), 40);
+        let (rule, line) = render_banner(&message, 200);
+        assert_eq!(line, format!("== {} ==", message));
+        assert_eq!(rule.chars().count(), line.chars().count());
+
+        let status_line: String = render_status_line(&"Same failure again, retrying... ".repeat(3), 40);
+        assert_eq!(status_line.chars().count(), 39);
+        assert!(status_line.starts_with(">> Same failure again"));
+
+        // Forms cut their widest columns to fit narrow terminals
+        let rows: Vec<Vec<String>> = vec![vec!["0".to_string(), "x".repeat(100), "Done".to_string()]];
+        let narrow: String = render_form(&["#", "Command", "Notes"], &rows, 40).to_string();
+        assert!(narrow.lines().all(|line| line.chars().count() <= 40), "{}", narrow);
+        assert!(narrow.contains("Done"));
+        let wide: String = render_form(&["#", "Command", "Notes"], &rows, 200).to_string();
+        assert!(wide.contains(&"x".repeat(100)));
+    }
+}
@@ -0,0 +1,131 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use assert_cmd::Command;
+    use cchain::arguments::{
+        mask_secret_run_arguments, mask_stored_run_arguments, merge_stored_run_arguments, parse_stored_run_arguments, Commands,
+        RunArguments,
+    };
+    use cchain::core::options::FailurePolicy;
+
+    /// Merge the stored arguments into `cchain run` with the arguments given
+    fn merge(given: &[&str], stored: &str) -> (RunArguments, Vec<String>) {
+        let command_line: Vec<String> = ["cchain", "run"].iter().chain(given).map(|word| word.to_string()).collect();
+        let (arguments, applied) = merge_stored_run_arguments(&command_line, stored).unwrap();
+        match arguments.commands {
+            Commands::Run(run_arguments) => (run_arguments, applied),
+            _ => panic!("not a run"),
+        }
+    }
+
+    // Test that the arguments given on the command line take precedence over the stored ones
+    #[test]
+    fn test_merge_precedence() {
+        let stored: &str = "--continue-on-failure --term-grace-seconds 5 --var env=prod --var 'region=eu west' --only 1,3";
+        let (run_arguments, applied) = merge(&["deploy"], stored);
+        assert_eq!(run_arguments.chain.as_deref(), Some("deploy"));
+        assert!(run_arguments.continue_on_failure);
+        assert_eq!(run_arguments.term_grace_seconds, 5);
+        assert_eq!(run_arguments.only, vec![1, 3]);
+        assert_eq!(
+            applied,
+            vec!["--continue-on-failure", "--term-grace-seconds=5", "--var=env=prod", "--var=region=eu west", "--only=1,3"]
+        );
+
+        // Given values replace the stored ones, and so do conflicting flags
+        let (run_arguments, applied) =
+            merge(&["deploy", "--term-grace-seconds", "9", "--stop-on-first-failure", "--var", "env=staging"], stored);
+        assert_eq!(run_arguments.term_grace_seconds, 9);
+        assert!(!run_arguments.continue_on_failure);
+        assert_eq!(run_arguments.get_failure_policy(), FailurePolicy::StopOnFirstFailure { even_if_remedied: false });
+        assert!(!applied.contains(&"--continue-on-failure".to_string()));
+        // Variables are combined, and the given ones come last to win
+        assert_eq!(
+            run_arguments.variables,
+            vec![
+                ("env".to_string(), "prod".to_string()),
+                ("region".to_string(), "eu west".to_string()),
+                ("env".to_string(), "staging".to_string()),
+            ]
+        );
+    }
+
+    // Test that the values of variables named like secrets are not shown
+    #[test]
+    fn test_masked_run_arguments() {
+        let (_, applied) = merge(&["deploy"], "--var api_token=hunter2 --var env=prod --continue-on-failure");
+        assert_eq!(
+            mask_secret_run_arguments(&applied),
+            vec!["--continue-on-failure", "--var=api_token=********", "--var=env=prod"]
+        );
+
+        assert_eq!(
+            mask_stored_run_arguments("--var 'DB_PASSWORD=a b' --var=region=eu"),
+            "--var 'DB_PASSWORD=********' --var=region=eu"
+        );
+        // Arguments without secrets are shown as they are stored
+        assert_eq!(mask_stored_run_arguments("--var 'region=eu west'"), "--var 'region=eu west'");
+    }
+
+    // Test that stored arguments are checked as `cchain run` parses them
+    #[test]
+    fn test_parse_stored_run_arguments() {
+        assert!(parse_stored_run_arguments("--continue-on-failure --var env=prod").is_ok());
+        assert!(parse_stored_run_arguments("").is_ok());
+        assert!(parse_stored_run_arguments("--no-such-flag").unwrap_err().to_string().contains("--no-such-flag"));
+        assert!(parse_stored_run_arguments("--continue-on-failure --stop-on-first-failure").is_err());
+        assert!(parse_stored_run_arguments("--var 'unbalanced").is_err());
+        // The chain is not for the stored arguments to select
+        assert!(parse_stored_run_arguments("other_chain").is_err());
+        assert!(parse_stored_run_arguments("--tag nightly").is_err());
+    }
+
+    fn cchain(home: &Path, arguments: &[&str]) -> std::process::Output {
+        Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", home)
+            .env_remove("CCHAIN_HOME")
+            .args(arguments)
+            .write_stdin("")
+            .output()
+            .unwrap()
+    }
+
+    // Test that `cchain run` tells which stored arguments it applies
+    #[test]
+    fn test_stored_run_arguments_notice() {
+        let home = tempfile::tempdir().unwrap();
+        let chain_path = home.path().join("cchain_greet.json");
+        std::fs::write(
+            &chain_path,
+            r#"[{ "command": "echo", "arguments": ["hello <<name>>"], "retry": 0 }]"#,
+        )
+        .unwrap();
+        assert!(cchain(home.path(), &["add", chain_path.to_str().unwrap()]).status.success());
+
+        let output = cchain(home.path(), &["config", "0", "--set-run-args", "--var name=stored --yes --bogus"]);
+        assert!(!output.status.success());
+        assert!(cchain(home.path(), &["config", "0", "--set-run-args", "--var name=stored --yes"]).status.success());
+
+        let output = cchain(home.path(), &["list", "--with-run-args"]);
+        let stdout: String = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(stdout.contains("Run arguments") && stdout.contains("--var name=stored --yes"), "{}", stdout);
+
+        let output = cchain(home.path(), &["run", "0"]);
+        let stdout: String = String::from_utf8_lossy(&output.stdout).to_string();
+        assert!(output.status.success(), "{}", stdout);
+        assert!(stdout.contains("Applying the stored run arguments of Greet: --var=name=stored --yes"), "{}", stdout);
+        assert!(stdout.contains("hello stored"), "{}", stdout);
+
+        let output = cchain(home.path(), &["run", "0", "--var", "name=given"]);
+        assert!(String::from_utf8_lossy(&output.stdout).contains("hello given"));
+
+        // Paths outside the bookmark and cleared entries get nothing
+        assert!(cchain(home.path(), &["config", "0", "--clear"]).status.success());
+        let output = cchain(home.path(), &["run", "0", "--var", "name=given", "--yes"]);
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("stored run arguments"));
+        let output = cchain(home.path(), &["list", "--with-run-args"]);
+        assert!(!String::from_utf8_lossy(&output.stdout).contains("Greet"));
+    }
+}
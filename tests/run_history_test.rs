@@ -0,0 +1,155 @@
+#[cfg(test)]
+mod tests {
+    use cchain::{
+        core::program::AttemptRecord,
+        marker::environment_fingerprint::EnvironmentFingerprint,
+        marker::run_history::{
+            find_prior_failures, get_recurring_failures, normalize_volatile_text, FailureFingerprint, RunHistory,
+            RunHistoryRecord,
+        },
+    };
+
+    fn failed_attempt(output_tail: &str) -> AttemptRecord {
+        AttemptRecord {
+            attempt: 1,
+            duration_ms: 10,
+            succeeded: false,
+            status: "exit status: 1".to_string(),
+            output_tail: output_tail.to_string(),
+            termination: None,
+            exit_code: Some(1),
+            io_usage: None,
+        }
+    }
+
+    fn run(chain: &str, timestamp: u64, failures: Vec<FailureFingerprint>) -> RunHistoryRecord {
+        RunHistoryRecord { chain: chain.to_string(), timestamp, succeeded: failures.is_empty(), failures, environment: None }
+    }
+
+    // Test that the volatile parts of error texts do not change the fingerprint
+    #[test]
+    fn test_fingerprint_ignores_volatile_text() {
+        let first = FailureFingerprint::from_attempt(
+            1,
+            "cargo test",
+            &failed_attempt("2026-10-13T09:12:44Z error: cannot open /tmp/.tmpA8f3kd/lock (pid 48213)\n"),
+        );
+        let second = FailureFingerprint::from_attempt(
+            1,
+            "cargo test",
+            &failed_attempt("2026-10-15T18:02:01Z error: cannot open /tmp/.tmpZq91xv/lock (pid 51377)\n"),
+        );
+        assert_eq!(first.fingerprint, second.fingerprint);
+        assert_eq!(first.summary, "<time> error: cannot open <temp> (pid <number>)");
+
+        // Another error, or another exit status, is another failure
+        let other = FailureFingerprint::from_attempt(1, "cargo test", &failed_attempt("error: disk full"));
+        assert_ne!(first.fingerprint, other.fingerprint);
+        let mut attempt = failed_attempt("2026-10-13T09:12:44Z error: cannot open /tmp/.tmpA8f3kd/lock (pid 48213)");
+        attempt.status = "exit status: 2".to_string();
+        assert_ne!(first.fingerprint, FailureFingerprint::from_attempt(1, "cargo test", &attempt).fingerprint);
+
+        assert_eq!(
+            normalize_volatile_text("took 1.52s, commit 3f9a2c1d4e, at 12:01:02"),
+            "took <duration>, commit <hex>, at <time>"
+        );
+    }
+
+    // Test that a failure is persistent until a run in between succeeds,
+    // and is flaky after
+    #[test]
+    fn test_flaky_and_persistent_failures() {
+        let failure = FailureFingerprint::from_attempt(2, "make deploy", &failed_attempt("error: 503"));
+        let other_failure = FailureFingerprint::from_attempt(0, "make lint", &failed_attempt("warning as error"));
+        let directory = tempfile::tempdir().unwrap();
+        let run_history = RunHistory::new(directory.path().join("run_history.jsonl"));
+        for record in [
+            run("/chains/deploy.json", 1_700_000_000, vec![failure.clone()]),
+            run("/chains/other.json", 1_700_000_050, vec![failure.clone()]),
+            run("/chains/deploy.json", 1_700_000_100, vec![failure.clone(), other_failure.clone()]),
+            run("/chains/deploy.json", 1_700_000_200, vec![failure.clone()]),
+        ] {
+            run_history.record_run(&record).unwrap();
+        }
+        let records: Vec<RunHistoryRecord> = run_history.read();
+        assert_eq!(records.len(), 4);
+
+        let prior_failures = find_prior_failures(&records, "/chains/deploy.json", &failure.fingerprint).unwrap();
+        assert_eq!(prior_failures.count, 3);
+        assert_eq!(prior_failures.first_seen, 1_700_000_000);
+        assert!(!prior_failures.is_flaky);
+        assert_eq!(
+            prior_failures.get_note(2),
+            "Program #2 failed the same way in 3 earlier run(s), first seen 2023-11-14 22:13:20 UTC. It is persistent: no run succeeded since."
+        );
+        assert!(find_prior_failures(&records, "/chains/deploy.json", "0000000000000000").is_none());
+
+        run_history.record_run(&run("/chains/deploy.json", 1_700_000_300, Vec::new())).unwrap();
+        run_history.record_run(&run("/chains/deploy.json", 1_700_000_400, vec![failure.clone()])).unwrap();
+        let records: Vec<RunHistoryRecord> = run_history.read();
+        let prior_failures = find_prior_failures(&records, "/chains/deploy.json", &failure.fingerprint).unwrap();
+        assert_eq!(prior_failures.count, 4);
+        assert!(prior_failures.is_flaky);
+        assert!(prior_failures.get_note(2).ends_with("It is flaky: runs in between succeeded."));
+
+        // Failures seen once are not recurring, and the most frequent come first
+        let recurring_failures = get_recurring_failures(&records);
+        assert_eq!(recurring_failures.len(), 1);
+        assert_eq!(recurring_failures[0].chain, "/chains/deploy.json");
+        assert_eq!(recurring_failures[0].count, 4);
+        assert_eq!(recurring_failures[0].last_seen, 1_700_000_400);
+        assert!(recurring_failures[0].is_flaky);
+    }
+
+    // Test that old runs are dropped, except the last successful run of a
+    // chain that the next runs compare their environment with
+    #[test]
+    fn test_compaction() {
+        let directory = tempfile::tempdir().unwrap();
+        let run_history = RunHistory::new(directory.path().join("run_history.jsonl"));
+        let failure = FailureFingerprint::from_attempt(0, "make deploy", &failed_attempt("error: 503"));
+        let mut succeeded = run("/chains/deploy.json", 100, Vec::new());
+        succeeded.environment = Some(EnvironmentFingerprint::default());
+        for record in [
+            run("/chains/deploy.json", 50, Vec::new()),
+            succeeded.clone(),
+            run("/chains/deploy.json", 150, vec![failure.clone()]),
+            run("/chains/deploy.json", 250, vec![failure]),
+        ] {
+            run_history.record_run(&record).unwrap();
+        }
+
+        assert_eq!(run_history.count_expired(200), 2);
+        assert_eq!(run_history.compact(200).unwrap(), 2);
+        let timestamps: Vec<u64> = run_history.read().iter().map(|record| record.timestamp).collect();
+        assert_eq!(timestamps, vec![100, 250]);
+        assert_eq!(run_history.compact(200).unwrap(), 0);
+    }
+
+    // Test that runs recorded at the same time are all kept whole
+    #[test]
+    fn test_concurrent_records() {
+        let directory = tempfile::tempdir().unwrap();
+        let run_history = RunHistory::new(directory.path().join("run_history.jsonl"));
+        let handles: Vec<std::thread::JoinHandle<()>> = (0..8)
+            .map(|index| {
+                let run_history = run_history.clone();
+                std::thread::spawn(move || {
+                    for timestamp in 0..20 {
+                        let failure = FailureFingerprint::from_attempt(index, "cargo test", &failed_attempt(&"x".repeat(4096)));
+                        run_history.record_run(&run(&format!("chain{}", index), timestamp, vec![failure])).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let records = run_history.read();
+        assert_eq!(records.len(), 160);
+        for index in 0..8 {
+            assert_eq!(records.iter().filter(|record| record.chain == format!("chain{}", index)).count(), 20);
+        }
+    }
+}
@@ -0,0 +1,117 @@
+#[cfg(test)]
+mod tests {
+    use cchain::core::{
+        options::{FailureHandlingOptions, StdoutStorageOptions},
+        program::Program,
+        traits::Execution,
+        sanitize::{
+            collapse_carriage_returns, get_escape_sequence_length, sanitize_output, strip_ansi_sequences,
+            OutputChunkBuffer, MAX_PENDING_ESCAPE_BYTES,
+        },
+    };
+
+    /// npm-like output: a spinner drawn with carriage returns and cursor
+    /// movements, a colored warning, and a window title
+    const NPM_OUTPUT: &str = "\x1b]0;npm install\x07\x1b[?25l\r\x1b[K⠋ reify: timing\r\x1b[K⠙ reify: timing\r\x1b[K\x1b[?25h\x1b[33mnpm\x1b[39m \x1b[30;43mWARN\x1b[0m deprecated glob@7.2.3\r\n\r\nadded 312 packages in 4s\r\n";
+
+    /// cargo-like output: bold green verbs and a progress bar rewritten
+    /// in place, then cleared
+    const CARGO_OUTPUT: &str = "\x1b[1m\x1b[32m   Compiling\x1b[0m serde v1.0.210\n\x1b[1m\x1b[36m    Building\x1b[0m [=====>    ] 12/24: serde\r\x1b[1m\x1b[36m    Building\x1b[0m [=========>] 23/24: cchain\r\x1b[K\x1b[1m\x1b[32m    Finished\x1b[0m `release` profile [optimized] target(s) in 9.81s\n";
+
+    #[test]
+    fn test_sanitize_fixtures() {
+        assert_eq!(sanitize_output(NPM_OUTPUT), "npm WARN deprecated glob@7.2.3\n\nadded 312 packages in 4s\n");
+        assert_eq!(
+            sanitize_output(CARGO_OUTPUT),
+            "   Compiling serde v1.0.210\n    Finished `release` profile [optimized] target(s) in 9.81s\n"
+        );
+        // A line erased after its last carriage return is left empty,
+        // and a style reset after it does not count as text
+        assert_eq!(sanitize_output("downloading 50%\r\x1b[2K\ndone"), "\ndone");
+        assert_eq!(sanitize_output("downloading 100%\r\x1b[0m\ndone"), "downloading 100%\ndone");
+    }
+
+    #[test]
+    fn test_strip_ansi_sequences() {
+        assert_eq!(strip_ansi_sequences("plain text"), "plain text");
+        assert_eq!(strip_ansi_sequences("\x1b[1;31merror\x1b[0m: failed"), "error: failed");
+        // Window titles ended by `\x1b\`, character sets, and cursor saves
+        assert_eq!(strip_ansi_sequences("\x1b]2;título\x1b\\a\x1b(Bb\x1b7c\x1b8"), "abc");
+        // A malformed sequence ends before the unexpected character
+        assert_eq!(strip_ansi_sequences("\x1b[1é"), "é");
+        // An unfinished sequence at the end is dropped
+        assert_eq!(strip_ansi_sequences("done\x1b[3"), "done");
+        assert_eq!(strip_ansi_sequences("\x1b\x1b[0mok"), "ok");
+
+        assert_eq!(get_escape_sequence_length(b"\x1b[38;5;208mx"), Some(11));
+        assert_eq!(get_escape_sequence_length(b"\x1b]0;title"), None);
+        assert_eq!(get_escape_sequence_length(b"\x1b"), None);
+        // A stray escape character does not take the newline after it
+        assert_eq!(get_escape_sequence_length(b"\x1b\nnext"), Some(1));
+        assert_eq!(strip_ansi_sequences("a\x1b\nb"), "a\nb");
+    }
+
+    #[test]
+    fn test_collapse_carriage_returns() {
+        assert_eq!(collapse_carriage_returns("a\r\nb\r\n"), "a\nb\n");
+        assert_eq!(collapse_carriage_returns(" 10%\r 50%\r100%\ndone"), "100%\ndone");
+        // A line ending with a carriage return keeps what was drawn last
+        assert_eq!(collapse_carriage_returns("100%\r"), "100%");
+        // Mixed line endings
+        assert_eq!(collapse_carriage_returns("one\r\ntwo\nthree 1/2\rthree 2/2\r\n"), "one\ntwo\nthree 2/2\n");
+    }
+
+    #[test]
+    fn test_output_chunk_buffer() {
+        // Every split of the fixtures gives the same text back
+        for fixture in [NPM_OUTPUT, CARGO_OUTPUT] {
+            let bytes: &[u8] = fixture.as_bytes();
+            for split in 1..bytes.len() {
+                let mut buffer = OutputChunkBuffer::new();
+                let first: String = buffer.push(&bytes[..split]);
+                // Nothing shown ends inside an escape sequence
+                if let Some(start) = first.rfind('\x1b') {
+                    assert!(get_escape_sequence_length(&first.as_bytes()[start..]).is_some(), "{:?}", first);
+                }
+                let text: String = first + &buffer.push(&bytes[split..]) + &buffer.finish();
+                assert_eq!(text, fixture, "split at {}", split);
+            }
+        }
+
+        // A stray escape character does not hold back the output for long
+        let mut buffer = OutputChunkBuffer::new();
+        assert_eq!(buffer.push(b"\x1b]0;"), "");
+        let long: String = "x".repeat(MAX_PENDING_ESCAPE_BYTES);
+        assert_eq!(buffer.push(long.as_bytes()), format!("\x1b]0;{}", long));
+        // Invalid bytes are shown as replacement characters
+        assert_eq!(buffer.push(b"\xff ok"), "\u{fffd} ok");
+    }
+
+    fn create_colored_program(preserve_ansi: bool) -> Program {
+        Program::new(
+            "printf".to_string(),
+            vec!["\\033[32mok\\033[0m 1/2\\r\\033[32mok\\033[0m 2/2\\r\\n".to_string()],
+            None,
+            None,
+            Some("<<output>>".to_string()),
+            StdoutStorageOptions { preserve_ansi, ..Default::default() },
+            None,
+            FailureHandlingOptions::default(),
+            None,
+            0,
+        )
+    }
+
+    // Test that stored outputs are plain text unless the escape sequences are preserved
+    #[test]
+    fn test_stored_output_is_sanitized() {
+        let results = create_colored_program(false).execute().unwrap();
+        assert_eq!(results[0].output(), "ok 2/2");
+
+        let results = create_colored_program(true).execute().unwrap();
+        assert_eq!(
+            results[0].output(),
+            "\x1b[32mok\x1b[0m 1/2\r\x1b[32mok\x1b[0m 2/2\r"
+        );
+    }
+}
@@ -0,0 +1,105 @@
+#[cfg(test)]
+mod tests {
+    use std::{collections::BTreeSet, str::FromStr};
+
+    use cchain::{
+        core::{
+            metadata::{ChainFile, CHAIN_FILE_FIELD_NAMES, METADATA_FIELD_NAMES},
+            program::Program,
+            schema::{get_chain_file_schema, validate_against_schema, SCHEMA_DIALECT},
+        },
+        generations::create::ChainCreation,
+    };
+    use serde_json::Value;
+
+    fn get_property_names(schema: &Value, pointer: &str) -> BTreeSet<String> {
+        schema.pointer(pointer).unwrap().as_object().unwrap().keys().cloned().collect()
+    }
+
+    fn get_field_names(field_names: &[&str]) -> BTreeSet<String> {
+        field_names.iter().map(|field_name| field_name.to_string()).collect()
+    }
+
+    // Test that the schema covers exactly the fields accepted by the parser
+    #[test]
+    fn test_schema_matches_field_names() {
+        let schema = get_chain_file_schema();
+        assert_eq!(schema["$schema"], SCHEMA_DIALECT);
+        assert_eq!(get_property_names(&schema, "/oneOf/1/properties"), get_field_names(CHAIN_FILE_FIELD_NAMES));
+        assert_eq!(get_property_names(&schema, "/$defs/metadata/properties"), get_field_names(METADATA_FIELD_NAMES));
+        assert_eq!(get_property_names(&schema, "/$defs/program/properties"), get_field_names(Program::FIELD_NAMES));
+    }
+
+    // Test that the template and every example chain conform to the schema
+    #[test]
+    fn test_fixtures_conform_to_schema() {
+        let schema = get_chain_file_schema();
+        let template: Value = serde_json::from_str(&ChainCreation::new(None).generate_template().unwrap()).unwrap();
+        assert_eq!(validate_against_schema(&template, &schema), vec![]);
+
+        for entry in std::fs::read_dir("examples").unwrap() {
+            let path = entry.unwrap().path();
+            let value: Value = serde_json::from_str(&std::fs::read_to_string(&path).unwrap()).unwrap();
+            assert_eq!(validate_against_schema(&value, &schema), vec![], "{}", path.display());
+        }
+    }
+
+    // Test that violations are reported at their JSON pointers
+    #[test]
+    fn test_schema_violation_pointers() {
+        let value: Value = serde_json::from_str(
+            r#"{
+                "metadata": { "variables": { "x": { "type": "int" } } },
+                "programs": [{
+                    "command": "echo",
+                    "arguments": ["a", { "raw": 1 }],
+                    "retry": "3",
+                    "failure_handling_options": { "exit_on_failure": true, "remedy_command_line": { "command": "x" } },
+                    "typo": 1
+                }]
+            }"#,
+        )
+        .unwrap();
+
+        let pointers: Vec<String> = validate_against_schema(&value, &get_chain_file_schema())
+            .into_iter()
+            .map(|violation| violation.pointer)
+            .collect();
+        assert_eq!(
+            pointers,
+            vec![
+                "/metadata/variables/x/type",
+                "/programs/0/arguments/1/raw",
+                "/programs/0/failure_handling_options/remedy_command_line",
+                "/programs/0/retry",
+                "/programs/0/typo",
+            ]
+        );
+    }
+
+    // Test that `$schema` is accepted by the parser, even in strict mode
+    #[test]
+    fn test_schema_key_is_not_unknown() {
+        let chain_file = ChainFile::from_str(
+            r#"{ "$schema": "./chain.schema.json", "metadata": { "strict": true }, "programs": [] }"#,
+        )
+        .unwrap();
+        assert!(chain_file.unknown_fields.is_empty());
+        assert_eq!(chain_file.schema.as_deref(), Some("./chain.schema.json"));
+    }
+
+    // Test that built-in steps are accepted next to programs, and that
+    // programs still need their command
+    #[test]
+    fn test_builtin_step() {
+        let content: &str = r#"[{ "command": "echo", "arguments": [], "retry": 0 }, { "builtin": "debug_variables" }]"#;
+        let value: Value = serde_json::from_str(content).unwrap();
+        assert_eq!(validate_against_schema(&value, &get_chain_file_schema()), vec![]);
+        assert!(ChainFile::from_str(content).unwrap().unknown_fields.is_empty());
+
+        let error: String = ChainFile::from_str(r#"[{ "arguments": [], "retry": 0 }]"#).unwrap_err().to_string();
+        assert_eq!(error, "missing field `command` in program #0");
+        let chain_file = ChainFile::from_str(r#"[{ "builtin": "debug_variables", "retry": 0 }]"#).unwrap();
+        assert_eq!(chain_file.unknown_fields, vec!["`retry` in program #0".to_string()]);
+    }
+}
@@ -0,0 +1,115 @@
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use assert_cmd::Command;
+    use cchain::core::scratch::ScratchDirectory;
+
+    /// A chain that writes a file into its temporary directory, checks it
+    /// from another program, and prints the directory
+    const CHAIN: &str = r#"[
+        { "command": "sh", "arguments": ["-c", "echo scratch > <<chain_tmp>>/file"], "retry": 0 },
+        { "command": "cat", "arguments": ["<<chain_tmp>>/file"], "retry": 0 },
+        { "command": "echo", "arguments": ["dir=<<chain_tmp>>"], "retry": 0 }
+    ]"#;
+
+    /// Run a chain with the temporary directories created under `tmp` in
+    /// the directory
+    fn run(directory: &Path, chain: &str, arguments: &[&str]) -> (bool, String) {
+        let path = directory.join("cchain_scratch.json");
+        std::fs::write(&path, chain).unwrap();
+        let output = Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", directory)
+            .env("CCHAIN_TMPDIR", directory.join("tmp"))
+            .current_dir(directory)
+            .arg("run")
+            .args(arguments)
+            .arg(&path)
+            .write_stdin("")
+            .output()
+            .unwrap();
+
+        (output.status.success(), String::from_utf8_lossy(&output.stdout).to_string())
+    }
+
+    fn get_printed_directory(stdout: &str) -> PathBuf {
+        let line: &str = stdout.lines().find(|line| line.trim().starts_with("dir=")).unwrap();
+        PathBuf::from(line.trim().trim_start_matches("dir="))
+    }
+
+    #[test]
+    fn test_chain_tmp_is_removed() {
+        let directory = tempfile::tempdir().unwrap();
+        let (succeeded, stdout) = run(directory.path(), CHAIN, &[]);
+        assert!(succeeded, "{}", stdout);
+        assert!(stdout.contains("    scratch"), "{}", stdout);
+
+        let chain_tmp: PathBuf = get_printed_directory(&stdout);
+        assert!(chain_tmp.starts_with(directory.path().join("tmp")));
+        assert!(!chain_tmp.exists());
+
+        // Also when a program fails
+        let failing_chain: String = CHAIN.replace(r#""cat""#, r#""false""#);
+        let (succeeded, _) = run(directory.path(), &failing_chain, &[]);
+        assert!(!succeeded);
+        assert_eq!(std::fs::read_dir(directory.path().join("tmp")).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn test_chain_tmp_is_kept() {
+        let directory = tempfile::tempdir().unwrap();
+        let (succeeded, stdout) = run(directory.path(), CHAIN, &["--keep-tmp"]);
+        assert!(succeeded, "{}", stdout);
+        let chain_tmp: PathBuf = get_printed_directory(&stdout);
+        assert_eq!(std::fs::read_to_string(chain_tmp.join("file")).unwrap(), "scratch\n");
+        assert!(stdout.contains(&format!("kept at {}", chain_tmp.display())), "{}", stdout);
+
+        let chain: String = format!(r#"{{ "metadata": {{ "keep_tmp": true }}, "programs": {} }}"#, CHAIN);
+        let (succeeded, stdout) = run(directory.path(), &chain, &[]);
+        assert!(succeeded, "{}", stdout);
+        assert!(get_printed_directory(&stdout).join("file").exists());
+    }
+
+    #[test]
+    fn test_chain_tmp_needs_no_declaration() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("cchain_scratch.json");
+        std::fs::write(&path, CHAIN).unwrap();
+        let output = Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", directory.path())
+            .args(["check", "--require-declarations"])
+            .arg(&path)
+            .output()
+            .unwrap();
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(output.status.success(), "{}", stdout);
+        assert!(!stdout.contains("chain_tmp"), "{}", stdout);
+
+        // Chains that do not use it get no directory
+        let (succeeded, _) = run(directory.path(), r#"[{ "command": "true", "arguments": [], "retry": 0 }]"#, &[]);
+        assert!(succeeded);
+        assert!(!directory.path().join("tmp").exists());
+    }
+
+    #[test]
+    fn test_scratch_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain_path: String = directory.path().join("chain.json").to_string_lossy().to_string();
+        let first = ScratchDirectory::create(&chain_path, false).unwrap();
+        let second = ScratchDirectory::create(&chain_path, false).unwrap();
+        assert_ne!(first.get_path(), second.get_path());
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode: u32 = std::fs::metadata(first.get_path()).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o700);
+        }
+        std::fs::write(first.get_path().join("file"), "").unwrap();
+
+        first.remove().unwrap();
+        assert!(!first.get_path().exists());
+        second.remove().unwrap();
+    }
+}
@@ -0,0 +1,128 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs::File,
+        path::{Path, PathBuf},
+        time::{Duration, SystemTime},
+    };
+
+    use assert_cmd::Command;
+    use cchain::commons::storage::{RetentionRules, Storage, StorageCategory, CCHAIN_HOME_VARIABLE};
+
+    const DAY: Duration = Duration::from_secs(86400);
+
+    fn create_file(path: &Path, content: &str, age: Duration) {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+        File::options()
+            .write(true)
+            .open(path)
+            .unwrap()
+            .set_modified(SystemTime::now() - age)
+            .unwrap();
+    }
+
+    // Test that `CCHAIN_HOME` holds everything, and XDG directories split data and caches
+    #[test]
+    fn test_storage_resolution() {
+        let home = PathBuf::from("/home/user");
+
+        let storage = Storage::resolve(Some(PathBuf::from("/opt/cchain")), Some(PathBuf::from("/xdg/data")), None, home.clone());
+        assert_eq!(storage.get_bookmark_path(), PathBuf::from("/opt/cchain/bookmark.json"));
+        assert_eq!(storage.get_directory(StorageCategory::Cache), PathBuf::from("/opt/cchain/cache"));
+
+        let storage = Storage::resolve(None, Some(PathBuf::from("/xdg/data")), Some(PathBuf::from("/xdg/cache")), home.clone());
+        assert_eq!(storage.get_bookmark_path(), PathBuf::from("/xdg/data/cchain/bookmark.json"));
+        assert_eq!(storage.get_directory(StorageCategory::Repos), PathBuf::from("/xdg/data/cchain/repos"));
+        assert_eq!(storage.get_directory(StorageCategory::Cache), PathBuf::from("/xdg/cache/cchain"));
+
+        let storage = Storage::resolve(None, None, None, home);
+        assert_eq!(storage.get_bookmark_path(), PathBuf::from("/home/user/.cchain/bookmark.json"));
+        assert_eq!(storage.get_directory(StorageCategory::Cache), PathBuf::from("/home/user/.cchain/cache"));
+    }
+
+    // Test that a bookmark file at `~/.cchain` becomes `~/.cchain/bookmark.json`
+    #[test]
+    fn test_legacy_bookmark_migration() {
+        let home = tempfile::tempdir().unwrap();
+        std::fs::write(home.path().join(".cchain"), "legacy").unwrap();
+
+        let storage = Storage::resolve(None, None, None, home.path().to_path_buf());
+        assert!(storage.migrate_legacy_bookmark().unwrap());
+        assert!(home.path().join(".cchain").is_dir());
+        assert_eq!(std::fs::read_to_string(storage.get_bookmark_path()).unwrap(), "legacy");
+        // Nothing is left to migrate
+        assert!(!storage.migrate_legacy_bookmark().unwrap());
+
+        // A bookmark already at the new location is kept, and the legacy one is backed up
+        let data_home = tempfile::tempdir().unwrap();
+        std::fs::remove_dir_all(home.path().join(".cchain")).unwrap();
+        std::fs::write(home.path().join(".cchain"), "legacy").unwrap();
+        let storage = Storage::resolve(None, Some(data_home.path().to_path_buf()), None, home.path().to_path_buf());
+        std::fs::create_dir_all(storage.get_data_directory()).unwrap();
+        std::fs::write(storage.get_bookmark_path(), "newer").unwrap();
+        assert!(storage.migrate_legacy_bookmark().unwrap());
+        assert_eq!(std::fs::read_to_string(storage.get_bookmark_path()).unwrap(), "newer");
+        assert_eq!(
+            std::fs::read_to_string(storage.get_directory(StorageCategory::Backups).join("bookmark.legacy-location.bak")).unwrap(),
+            "legacy"
+        );
+        assert!(!home.path().join(".cchain").exists());
+    }
+
+    // Test that `CCHAIN_HOME` overrides where the bookmark is saved, without touching the legacy one
+    #[test]
+    fn test_cchain_home_override() {
+        let home = tempfile::tempdir().unwrap();
+        let cchain_home = home.path().join("custom");
+        std::fs::write(home.path().join(".cchain"), "legacy").unwrap();
+        let chain_path = home.path().join("cchain_fixture.json");
+        std::fs::write(&chain_path, r#"[{ "command": "true", "arguments": [], "retry": 0 }]"#).unwrap();
+
+        Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", home.path())
+            .env(CCHAIN_HOME_VARIABLE, &cchain_home)
+            .arg("add")
+            .arg(&chain_path)
+            .assert()
+            .success();
+
+        assert!(std::fs::read_to_string(cchain_home.join("bookmark.json")).unwrap().contains("cchain_fixture.json"));
+        assert_eq!(std::fs::read_to_string(home.path().join(".cchain")).unwrap(), "legacy");
+    }
+
+    // Test which entries are pruned under the retention rules
+    #[test]
+    fn test_garbage_collection_plan() {
+        let root = tempfile::tempdir().unwrap();
+        let storage = Storage::resolve(Some(root.path().to_path_buf()), None, None, PathBuf::from("/unused"));
+        let directory = |category| storage.get_directory(category);
+
+        create_file(&directory(StorageCategory::Repos).join("used").join("cchain_a.json"), "[]", Duration::ZERO);
+        create_file(&directory(StorageCategory::Repos).join("orphan").join("README.md"), "orphan", Duration::ZERO);
+        create_file(&directory(StorageCategory::Cache).join("stale"), "stale", 8 * DAY);
+        create_file(&directory(StorageCategory::Cache).join("fresh"), "fresh", Duration::ZERO);
+        create_file(&directory(StorageCategory::Backups).join("bookmark.bak"), "{}", 400 * DAY);
+
+        let rules = RetentionRules { log_retention: 30 * DAY, cache_ttl: 7 * DAY };
+        let bookmarked_paths: Vec<PathBuf> = vec![directory(StorageCategory::Repos).join("used").join("cchain_a.json")];
+        let candidates = storage.plan_garbage_collection(&rules, &bookmarked_paths, SystemTime::now());
+
+        let pruned: Vec<(StorageCategory, PathBuf)> = candidates
+            .iter()
+            .map(|candidate| (candidate.category, candidate.path.strip_prefix(root.path()).unwrap().to_path_buf()))
+            .collect();
+        assert_eq!(
+            pruned,
+            vec![
+                (StorageCategory::Cache, PathBuf::from("cache/stale")),
+                (StorageCategory::Repos, PathBuf::from("repos/orphan")),
+            ]
+        );
+        assert_eq!(candidates[1].bytes, 6);
+
+        let usage = storage.get_disk_usage();
+        assert_eq!((usage[0].category, usage[0].entries, usage[0].bytes), (StorageCategory::Repos, 2, 8));
+    }
+}
@@ -0,0 +1,109 @@
+#[cfg(test)]
+mod tests {
+    use std::{
+        io::Write,
+        sync::{Arc, Mutex},
+    };
+
+    use anyhow::{anyhow, Error};
+    use cchain::{
+        core::events::ChainEvent,
+        terminal_title::{
+            render_progress_title, RunProgress, TerminalTitle, TitleTracker, RESTORE_TITLE_SEQUENCE, SAVE_TITLE_SEQUENCE,
+        },
+    };
+
+    /// Keeps what is written, to be read after the title is dropped
+    #[derive(Clone, Default)]
+    struct SharedWriter(Arc<Mutex<Vec<u8>>>);
+
+    impl SharedWriter {
+        fn get_text(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    impl Write for SharedWriter {
+        fn write(&mut self, buffer: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().extend_from_slice(buffer);
+            Ok(buffer.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn chain_started() -> ChainEvent {
+        ChainEvent::ChainStarted {
+            program_labels: vec!["cargo build".to_string(), "cargo test".to_string(), "deploy --token ****".to_string()],
+        }
+    }
+
+    fn get_title(tracker: &TitleTracker) -> String {
+        render_progress_title("deploy.json", tracker.get_progress())
+    }
+
+    // Test the title as the steps of a chain start and finish
+    #[test]
+    fn test_title_step_transitions() {
+        let mut tracker = TitleTracker::new();
+        assert!(tracker.apply(&chain_started()));
+        assert_eq!(get_title(&tracker), "cchain: deploy.json");
+
+        assert!(tracker.apply(&ChainEvent::ProgramStarted { program_index: 0 }));
+        assert_eq!(get_title(&tracker), "cchain: deploy.json [step 1/3] cargo build");
+        // A retry and the output change nothing
+        assert!(!tracker.apply(&ChainEvent::ProgramStarted { program_index: 0 }));
+        assert!(!tracker.apply(&ChainEvent::ProgramOutput { program_index: 0, text: "Compiling\n".to_string() }));
+
+        // The finished step is kept until the next one starts
+        assert!(!tracker.apply(&ChainEvent::ProgramFinished { program_index: 0, succeeded: true }));
+        assert_eq!(get_title(&tracker), "cchain: deploy.json [step 1/3] cargo build");
+
+        // Of the steps running together, the one started last is shown
+        tracker.apply(&ChainEvent::ProgramStarted { program_index: 1 });
+        tracker.apply(&ChainEvent::ProgramStarted { program_index: 2 });
+        assert_eq!(get_title(&tracker), "cchain: deploy.json [step 3/3] deploy --token ****");
+        assert!(tracker.apply(&ChainEvent::ProgramFinished { program_index: 2, succeeded: true }));
+        assert_eq!(get_title(&tracker), "cchain: deploy.json [step 2/3] cargo test");
+
+        tracker.apply(&ChainEvent::ChainFinished { succeeded: false });
+        assert_eq!(get_title(&tracker), "cchain: deploy.json ✗");
+        assert_eq!(render_progress_title("deploy.json", RunProgress::Finished { succeeded: true }), "cchain: deploy.json ✓");
+    }
+
+    // Test that control characters and long commands are kept out of the title
+    #[test]
+    fn test_title_sanitized() {
+        let summary: String = format!("echo \x1b]0;hijacked\x07{}", "x".repeat(100));
+        let title: String = render_progress_title("deploy.json", RunProgress::Running { step: 0, total: 1, summary: &summary });
+        assert!(!title.chars().any(|character| character.is_control()), "{:?}", title);
+        assert!(title.ends_with('…'));
+        assert_eq!(title.chars().count(), "cchain: deploy.json [step 1/1] ".chars().count() + 60);
+    }
+
+    fn run_with_title(writer: SharedWriter) -> Result<(), Error> {
+        let mut terminal_title = TerminalTitle::new(writer, "deploy.json");
+        terminal_title.apply(&chain_started());
+        terminal_title.apply(&ChainEvent::ProgramStarted { program_index: 1 });
+        Err(anyhow!("cargo test failed"))?;
+        terminal_title.apply(&ChainEvent::ChainFinished { succeeded: true });
+
+        Ok(())
+    }
+
+    // Test that the title is restored when a run returns early with an error
+    #[test]
+    fn test_title_restored_on_error() {
+        let writer = SharedWriter::default();
+        assert!(run_with_title(writer.clone()).is_err());
+
+        let text: String = writer.get_text();
+        assert!(text.starts_with(SAVE_TITLE_SEQUENCE));
+        assert!(text.contains("\x1b]0;cchain: deploy.json [step 2/3] cargo test\x07"));
+        assert!(!text.contains('✓'));
+        assert!(text.ends_with(RESTORE_TITLE_SEQUENCE));
+        assert_eq!(text.matches(RESTORE_TITLE_SEQUENCE).count(), 1);
+    }
+}
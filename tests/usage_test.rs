@@ -0,0 +1,77 @@
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use cchain::marker::usage::{get_usage_key, is_stale, ChainUsage, UsageLog, UsageSummary};
+    use tempfile::TempDir;
+
+    fn create_chain(directory: &TempDir, name: &str) -> String {
+        let path: PathBuf = directory.path().join(name);
+        std::fs::write(&path, "[]").unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    // Test that each run is counted, with the time of the last one
+    #[test]
+    fn test_usage_incremented_on_run() {
+        let directory = TempDir::new().unwrap();
+        let chain_path: String = create_chain(&directory, "cchain_build.json");
+        let other_path: String = create_chain(&directory, "cchain_deploy.json");
+        let usage_log = UsageLog::new(directory.path().join("data").join("usage.jsonl"));
+        assert_eq!(usage_log.read(), UsageSummary::default());
+
+        usage_log.record_run(&chain_path, 100).unwrap();
+        usage_log.record_run(&other_path, 150).unwrap();
+        // The same chain through another path is the same chain
+        usage_log.record_run(&format!("{}/./cchain_build.json", directory.path().display()), 200).unwrap();
+
+        let summary: UsageSummary = usage_log.read();
+        let usage: &ChainUsage = summary.get_usage(&chain_path).unwrap();
+        assert_eq!((usage.runs, usage.last_run), (2, 200));
+        assert_eq!(summary.get_usage(&other_path).unwrap().runs, 1);
+
+        // Compacting keeps the totals, and drops the chains that are not kept
+        let dropped: Vec<String> = usage_log.compact(|path| path == get_usage_key(&chain_path)).unwrap();
+        assert_eq!(dropped, vec![get_usage_key(&other_path)]);
+        assert_eq!(std::fs::read_to_string(usage_log.get_path()).unwrap().lines().count(), 1);
+        assert_eq!(usage_log.read().get_usage(&chain_path), Some(usage));
+    }
+
+    // Test that a chain run exactly at the edge of the window is not stale
+    #[test]
+    fn test_stale_boundary() {
+        let day: u64 = 86400;
+        let now: u64 = 100 * day;
+        let usage = |last_run: u64| ChainUsage { path: "cchain_build.json".to_string(), runs: 1, last_run };
+
+        assert!(!is_stale(Some(&usage(now - 7 * day)), 7, now));
+        assert!(is_stale(Some(&usage(now - 7 * day - 1)), 7, now));
+        assert!(!is_stale(Some(&usage(now)), 0, now));
+        assert!(is_stale(None, 7, now));
+
+        // A window too long to count in seconds covers every run
+        assert!(!is_stale(Some(&usage(0)), u64::MAX, now));
+    }
+
+    // Test that unreadable lines are skipped, and dropped by compacting
+    #[test]
+    fn test_usage_corruption_recovery() {
+        let directory = TempDir::new().unwrap();
+        let chain_path: String = create_chain(&directory, "cchain_build.json");
+        let usage_log = UsageLog::new(directory.path().join("usage.jsonl"));
+        usage_log.record_run(&chain_path, 100).unwrap();
+        let mut content: Vec<u8> = std::fs::read(usage_log.get_path()).unwrap();
+        content.extend_from_slice(b"{\"path\": \"/half/writ\n\xff\xfe garbage\n");
+        std::fs::write(usage_log.get_path(), content).unwrap();
+        usage_log.record_run(&chain_path, 200).unwrap();
+
+        let summary: UsageSummary = usage_log.read();
+        assert_eq!(summary.skipped_lines, 2);
+        assert_eq!(summary.get_usage(&chain_path).unwrap().runs, 2);
+
+        usage_log.compact(|_| true).unwrap();
+        let summary: UsageSummary = usage_log.read();
+        assert_eq!(summary.skipped_lines, 0);
+        assert_eq!(summary.get_usage(&chain_path).unwrap().last_run, 200);
+    }
+}
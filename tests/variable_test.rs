@@ -1,6 +1,6 @@
 #[cfg(test)]
 mod tests {
-    use cchain::variable::{Variable, VariableInitializationTime, VariableLifetime};
+    use cchain::{expression::TypedValue, variable::{ProvidedValue, StoredVariable, Variable, VariableDeclaration, VariableDelimiters, VariableInitializationTime, VariableLifetime, VariableSource, VariableType}};
 
 
     #[test]
@@ -168,7 +168,7 @@ mod tests {
             None,
             VariableInitializationTime::OnChainStartup(VariableLifetime::new(None)),
         );
-        var.register_value("value");
+        var.register_value("value", VariableSource::Assigned);
         assert_eq!(var.get_value().unwrap(), "value");
     }
 
@@ -219,4 +219,226 @@ mod tests {
         assert!(init_await.is_initialized(3));
         assert!(!init_await.is_initialized(2));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_integer_declaration_bounds() {
+        let declaration = VariableDeclaration {
+            variable_type: VariableType::Integer,
+            min: Some(1),
+            max: Some(64),
+            ..Default::default()
+        };
+
+        assert_eq!(declaration.parse_value("8").unwrap(), Some(TypedValue::Integer(8)));
+        assert_eq!(declaration.parse_value("64").unwrap(), Some(TypedValue::Integer(64)));
+        assert!(declaration.parse_value("0").is_err());
+        assert!(declaration.parse_value("65").is_err());
+        assert!(declaration.parse_value("eight").is_err());
+    }
+
+    #[test]
+    fn test_boolean_declaration() {
+        let declaration = VariableDeclaration {
+            variable_type: VariableType::Boolean,
+            ..Default::default()
+        };
+
+        assert_eq!(declaration.parse_value("yes").unwrap(), Some(TypedValue::Boolean(true)));
+        assert_eq!(declaration.parse_value("False").unwrap(), Some(TypedValue::Boolean(false)));
+        assert!(declaration.parse_value("maybe").is_err());
+        assert!(declaration.validate().is_ok());
+
+        let invalid_declaration = VariableDeclaration {
+            variable_type: VariableType::Boolean,
+            min: Some(1),
+            max: None,
+            ..Default::default()
+        };
+        assert!(invalid_declaration.validate().is_err());
+    }
+
+    #[test]
+    fn test_validate_value_against_declaration() {
+        let mut var = Variable::new(
+            "workers".to_string(),
+            None,
+            None,
+            VariableInitializationTime::OnChainStartup(VariableLifetime::new(None)),
+        );
+        assert!(var.validate_value("anything").is_ok());
+
+        var.set_declaration(VariableDeclaration {
+            variable_type: VariableType::Integer,
+            min: Some(1),
+            max: None,
+            ..Default::default()
+        });
+        assert!(var.validate_value("0").is_err());
+        assert!(var.validate_value("3").is_ok());
+
+        var.register_value("3", VariableSource::Assigned);
+        assert_eq!(var.get_typed_value().unwrap(), Some(TypedValue::Integer(3)));
+    }
+
+    #[test]
+    fn test_parse_expression_placeholder() {
+        let vars = Variable::parse_variables_from_str("--workers=<<workers * 2>>", 0).unwrap();
+        assert_eq!(vars.len(), 1);
+        assert_eq!(vars[0].get_variable_name(), "workers");
+        assert!(matches!(
+            vars[0].get_initialization_time(),
+            VariableInitializationTime::OnChainStartup(_)
+        ));
+
+        assert!(Variable::parse_variables_from_str("<<workers *>>", 0).is_err());
+    }
+
+    #[test]
+    fn test_provided_value_from_assignment() {
+        let value = ProvidedValue::from_assignment("name", "plain").unwrap();
+        assert_eq!(value.value, "plain");
+
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("payload.json");
+        std::fs::write(&path, "{\n  \"a\": 1\n}\n").unwrap();
+        let value = ProvidedValue::from_assignment("payload", &format!("@{}", path.display())).unwrap();
+        assert_eq!(value.value, "{\n  \"a\": 1\n}\n");
+
+        let error = ProvidedValue::from_assignment("payload", "@/path/that/does/not/exist")
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("`payload`"));
+        assert!(error.contains("/path/that/does/not/exist"));
+    }
+
+    #[test]
+    fn test_provided_values_from_files() {
+        let directory = tempfile::tempdir().unwrap();
+        let json_path = directory.path().join("vars.json");
+        std::fs::write(&json_path, r#"{ "host": "example.com", "sql": "SELECT 1;\nSELECT 2;" }"#).unwrap();
+        let values = ProvidedValue::from_file(json_path.to_str().unwrap()).unwrap();
+        assert_eq!(values.len(), 2);
+        assert!(values.iter().any(|(name, value)| name == "sql" && value.value == "SELECT 1;\nSELECT 2;"));
+
+        let env_path = directory.path().join("vars.env");
+        std::fs::write(&env_path, "# comment\nexport host=example.com\nport = \"8080\"\n").unwrap();
+        let values = ProvidedValue::from_file(env_path.to_str().unwrap()).unwrap();
+        let values: Vec<(&str, &str)> = values.iter().map(|(name, value)| (name.as_str(), value.value.as_str())).collect();
+        assert_eq!(values, vec![("host", "example.com"), ("port", "8080")]);
+
+        // Values that are not strings are rejected with the file and the variable named
+        std::fs::write(&json_path, r#"{ "port": 8080 }"#).unwrap();
+        let error = ProvidedValue::from_file(json_path.to_str().unwrap()).unwrap_err().to_string();
+        assert!(error.contains(json_path.to_str().unwrap()));
+        assert!(error.contains("`port`"));
+
+        let error = ProvidedValue::from_file("/path/that/does/not/exist.env").unwrap_err().to_string();
+        assert!(error.contains("/path/that/does/not/exist.env"));
+    }
+
+    // Test that anonymous prompts are numbered into distinct variables initialized at their program
+    #[test]
+    fn test_parse_anonymous_prompts() {
+        let mut counter: usize = 0;
+        let numbered = Variable::number_anonymous_prompts("-m <<?Commit message>> <<?Commit message>>", &mut counter);
+        assert_eq!(numbered, "-m <<?0:Commit message>> <<?1:Commit message>>");
+        assert_eq!(counter, 2);
+        assert_eq!(Variable::denumber_anonymous_prompts(&numbered), "-m <<?Commit message>> <<?Commit message>>");
+
+        let variables = Variable::parse_variables_from_str(&numbered, 3).unwrap();
+        assert_eq!(variables.len(), 2);
+        assert!(variables.iter().all(|variable| variable.is_anonymous()));
+        assert!(variables.iter().all(|variable| variable.get_human_readable_name() == "Commit message"));
+        assert_ne!(variables[0].get_variable_name(), variables[1].get_variable_name());
+        assert_eq!(variables[1].get_raw_variable_name(), "<<?1:Commit message>>");
+        assert_eq!(
+            variables[0].get_initialization_time(),
+            VariableInitializationTime::OnProgramExecution(VariableLifetime::new(Some(3)))
+        );
+
+        // `?` in the prompt text does not make it an expression
+        let variables = Variable::parse_variables_from_str("<<?Continue? (y/n)>>", 0).unwrap();
+        assert_eq!(variables[0].get_human_readable_name(), "Continue? (y/n)");
+    }
+
+    // Test that the prompt shows the declared name, description and example
+    #[test]
+    fn test_prompt_message_with_declaration() {
+        let mut variable = Variable::new(
+            "container_folder_path".to_string(),
+            None,
+            None,
+            VariableInitializationTime::OnChainStartup(VariableLifetime::new(None)),
+        );
+        assert_eq!(variable.get_prompt_message(), "Please input a value for Container Folder Path:");
+
+        variable.set_declaration(VariableDeclaration {
+            description: Some("The directory mounted into the containers".to_string()),
+            example: Some("/srv/containers".to_string()),
+            human_readable_name: Some("Mount directory".to_string()),
+            ..Default::default()
+        });
+        assert_eq!(variable.get_human_readable_name(), "Mount directory");
+        assert_eq!(
+            variable.get_prompt_message(),
+            "Please input a value for Mount directory:\n     The directory mounted into the containers\n     Example: /srv/containers\n   >"
+        );
+    }
+
+    // Test that `<<<<` escapes a literal `<<`, and custom delimiters are
+    // rewritten with the default ones
+    #[test]
+    fn test_placeholder_escapes_and_delimiters() {
+        assert_eq!(Variable::extract_variable_names("<<<<EOF <<name>> <<<<x>>"), vec!["name"]);
+        assert_eq!(
+            Variable::replace_placeholders("<<<<name>> <<name>>", |content| (content == "name").then(|| "v".to_string())),
+            "<<<<name>> v"
+        );
+        assert_eq!(Variable::unescape_placeholders("<<<<name>> <<other>>"), "<<name>> <<other>>");
+
+        let delimiters = VariableDelimiters { open: "[[".to_string(), close: "]]".to_string() };
+        assert_eq!(
+            delimiters.to_default_syntax("{{ a }} << [[name]] >> [[[[b]]"),
+            "{{ a }} <<<< <<name>> >> [[b]]"
+        );
+        assert!(VariableDelimiters::default().is_default());
+        assert!(VariableDelimiters { open: "{{".to_string(), close: "".to_string() }.validate().is_err());
+        assert!(VariableDelimiters { open: "@".to_string(), close: "@".to_string() }.validate().is_err());
+    }
+
+    // Test that a stored variable must be exactly one placeholder without a qualifier
+    #[test]
+    fn test_parse_stored_variable() {
+        let stored_variable = StoredVariable::parse("stdout_stored_to", "<<branch>>").unwrap();
+        assert_eq!(stored_variable, StoredVariable { name: "branch".to_string(), is_bare: false });
+
+        let malformed_values = [
+            ("<<branch:on_program_execution>>", "the qualifier `:on_program_execution` only applies where a variable is used. Store to `<<branch>>` instead"),
+            ("<<branch>><<tag>>", "it is not a single placeholder"),
+            ("refs/<<branch>>", "it is not a single placeholder"),
+            ("<<branch", "it is not a single placeholder"),
+            ("<<>>", "the variable name is empty"),
+            ("<<?Branch name>>", "an anonymous prompt cannot store a value"),
+            ("<<count + 1>>", "an expression cannot store a value"),
+            ("<< branch >>", "the variable name contains spaces"),
+            ("branch:on_program_execution", "the qualifier `:on_program_execution` only applies"),
+        ];
+        for (value, reason) in malformed_values {
+            let error: String = StoredVariable::parse("stdout_stored_to", value).unwrap_err().to_string();
+            assert!(
+                error.starts_with(&format!(
+                    "`stdout_stored_to` must be exactly one placeholder without a qualifier, like `<<name>>`, but it is `{}`: ",
+                    value
+                )),
+                "{}",
+                error
+            );
+            assert!(error.contains(reason), "{}", error);
+        }
+
+        // Bare names are accepted, and written as placeholders
+        let stored_variable = StoredVariable::parse("stdout_stored_to", "branch").unwrap();
+        assert!(stored_variable.is_bare);
+        assert_eq!(stored_variable.to_placeholder(), "<<branch>>");
+    }
+}
@@ -0,0 +1,153 @@
+#[cfg(test)]
+mod tests {
+    use std::path::{Path, PathBuf};
+
+    use assert_cmd::Command;
+    use cchain::marker::{
+        history::{ValueHistory, VALUE_HISTORY_FORMAT_VERSION},
+        usage::get_usage_key,
+    };
+
+    const CHAIN: &str = r#"{
+        "metadata": { "variables": {
+            "branch": { "description": "Branch to deploy" },
+            "replicas": { "type": "integer" },
+            "token": { "secret": true }
+        } },
+        "programs": [{ "command": "echo", "arguments": ["<<branch>>", "<<replicas>>", "<<token>>"], "retry": 0 }]
+    }"#;
+
+    fn vars(home: &Path, arguments: &[&str]) -> std::process::Output {
+        Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", home)
+            .env_remove("CCHAIN_HOME")
+            .env_remove("XDG_DATA_HOME")
+            .arg("vars")
+            .args(arguments)
+            .output()
+            .unwrap()
+    }
+
+    fn list(home: &Path, chain_path: &str) -> Vec<serde_json::Value> {
+        let output = vars(home, &["list", chain_path, "--output", "json"]);
+        assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stdout));
+        serde_json::from_slice(&output.stdout).unwrap()
+    }
+
+    fn create_chain(directory: &Path) -> String {
+        let path: PathBuf = directory.join("cchain_deploy.json");
+        std::fs::write(&path, CHAIN).unwrap();
+        path.to_string_lossy().into_owned()
+    }
+
+    // Test that values are set, listed, unset and cleared without running the chain
+    #[test]
+    fn test_vars_actions() {
+        let home = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(home.path());
+
+        let variables = list(home.path(), &chain_path);
+        let names: Vec<&str> = variables.iter().map(|variable| variable["name"].as_str().unwrap()).collect();
+        assert_eq!(names, vec!["branch", "replicas", "token"]);
+        assert_eq!(variables[0]["description"], "Branch to deploy");
+        assert_eq!(variables[1]["kind"], "integer");
+        assert!(variables.iter().all(|variable| variable["cached_value"].is_null()));
+
+        assert!(vars(home.path(), &["set", &chain_path, "branch", "develop"]).status.success());
+        assert!(vars(home.path(), &["set", &chain_path, "replicas", "3"]).status.success());
+        let variables = list(home.path(), &chain_path);
+        assert_eq!(variables[0]["cached_value"], "develop");
+        assert!(variables[0]["last_used"].as_u64().is_some());
+        assert_eq!(variables[1]["cached_value"], "3");
+
+        // Secrets are never kept
+        assert_eq!(vars(home.path(), &["set", &chain_path, "token", "s3cret"]).status.code(), Some(64));
+
+        assert!(vars(home.path(), &["unset", &chain_path, "branch"]).status.success());
+        let variables = list(home.path(), &chain_path);
+        assert!(variables[0]["cached_value"].is_null());
+        assert_eq!(variables[1]["cached_value"], "3");
+
+        let output = vars(home.path(), &["clear", &chain_path]);
+        assert!(output.status.success());
+        assert!(String::from_utf8_lossy(&output.stdout).contains("Values of 1 variable are forgotten"));
+        assert!(list(home.path(), &chain_path).iter().all(|variable| variable["cached_value"].is_null()));
+
+        let output = vars(home.path(), &["list", &chain_path]);
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("Cached value") && stdout.contains("Branch to deploy"), "{}", stdout);
+    }
+
+    // Test that names the chain does not prompt for are refused with the closest name
+    #[test]
+    fn test_vars_unknown_name() {
+        let home = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(home.path());
+
+        let output = vars(home.path(), &["set", &chain_path, "brnch", "main"]);
+        assert_eq!(output.status.code(), Some(64));
+        let stdout = String::from_utf8_lossy(&output.stdout);
+        assert!(stdout.contains("has no variable `brnch`. Did you mean `branch`?"), "{}", stdout);
+        assert!(!home.path().join(".cchain").join("value_history.json").exists());
+    }
+
+    // Test that a history of plain values is read, and written with the times on saving
+    #[test]
+    fn test_value_history_migration() {
+        let home = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(home.path());
+        let history_path: PathBuf = home.path().join(".cchain").join("value_history.json");
+        std::fs::create_dir_all(history_path.parent().unwrap()).unwrap();
+        let legacy = serde_json::json!({ "chains": { get_usage_key(&chain_path): { "branch": ["main", "develop"] } } });
+        std::fs::write(&history_path, legacy.to_string()).unwrap();
+
+        let value_history = ValueHistory::load(history_path.clone());
+        assert_eq!(value_history.get_format_version(), 0);
+        assert_eq!(value_history.get_values(&chain_path, "branch"), ["main", "develop"]);
+        assert_eq!(value_history.get_entries(&chain_path, "branch")[0].used_at, None);
+        let variables = list(home.path(), &chain_path);
+        assert_eq!(variables[0]["cached_value"], "main");
+        assert!(variables[0]["last_used"].is_null());
+
+        assert!(vars(home.path(), &["set", &chain_path, "branch", "release"]).status.success());
+        let value_history = ValueHistory::load(history_path.clone());
+        assert_eq!(value_history.get_format_version(), VALUE_HISTORY_FORMAT_VERSION);
+        assert_eq!(value_history.get_values(&chain_path, "branch"), ["release", "main", "develop"]);
+        assert!(value_history.get_entries(&chain_path, "branch")[0].used_at.is_some());
+        assert_eq!(value_history.get_entries(&chain_path, "branch")[1].used_at, None);
+
+        // The values stay plain strings, for earlier versions to read
+        let stored: serde_json::Value = serde_json::from_str(&std::fs::read_to_string(&history_path).unwrap()).unwrap();
+        assert_eq!(
+            stored["chains"][get_usage_key(&chain_path)]["branch"],
+            serde_json::json!(["release", "main", "develop"])
+        );
+        assert!(stored["used_at"][get_usage_key(&chain_path)]["branch"]["release"].is_u64());
+    }
+
+    // Test that a history written by a newer version is neither read nor
+    // overwritten
+    #[test]
+    fn test_newer_value_history() {
+        let home = tempfile::tempdir().unwrap();
+        let chain_path = create_chain(home.path());
+        let history_path: PathBuf = home.path().join(".cchain").join("value_history.json");
+        std::fs::create_dir_all(history_path.parent().unwrap()).unwrap();
+        let newer = serde_json::json!({
+            "format_version": VALUE_HISTORY_FORMAT_VERSION + 1,
+            "chains": { get_usage_key(&chain_path): { "branch": ["main"] } }
+        })
+        .to_string();
+        std::fs::write(&history_path, &newer).unwrap();
+
+        let value_history = ValueHistory::load(history_path.clone());
+        assert!(value_history.get_values(&chain_path, "branch").is_empty());
+
+        let output = vars(home.path(), &["set", &chain_path, "branch", "release"]);
+        assert!(!output.status.success());
+        let messages = format!("{}{}", String::from_utf8_lossy(&output.stdout), String::from_utf8_lossy(&output.stderr));
+        assert!(messages.contains("which this version of cchain does not support"), "{}", messages);
+        assert_eq!(std::fs::read_to_string(&history_path).unwrap(), newer);
+    }
+}
@@ -0,0 +1,96 @@
+#[cfg(test)]
+mod tests {
+    use std::path::Path;
+
+    use assert_cmd::Command;
+    use cchain::{
+        core::status::{RunOutcome, RunStatus},
+        Chain,
+    };
+
+    /// A chain that writes a version file, then verifies it with two
+    /// steps, the second of which fails
+    const CHAIN: &str = r#"{
+        "programs": [
+            { "command": "sh", "arguments": ["-c", "echo 1.2.3 > version"], "retry": 0 }
+        ],
+        "verify": [
+            { "command": "sh", "arguments": ["-c", "grep -q 1.2.3 version && touch first"], "retry": 0 },
+            { "command": "sh", "arguments": ["-c", "touch second; grep -q 9.9.9 version"], "retry": 0 }
+        ]
+    }"#;
+
+    /// Run a chain in the directory, with the status file written next to it
+    fn run(directory: &Path, chain: &str) -> (Option<i32>, String, RunStatus) {
+        let path = directory.join("cchain_verify.json");
+        std::fs::write(&path, chain).unwrap();
+        let status_path = directory.join("status.json");
+        let output = Command::cargo_bin("cchain")
+            .unwrap()
+            .env("HOME", directory)
+            .current_dir(directory)
+            .arg("run")
+            .arg(&path)
+            .arg("--status-file")
+            .arg(&status_path)
+            .write_stdin("")
+            .output()
+            .unwrap();
+        let run_status: RunStatus = serde_json::from_str(&std::fs::read_to_string(&status_path).unwrap()).unwrap();
+
+        (output.status.code(), String::from_utf8_lossy(&output.stdout).to_string(), run_status)
+    }
+
+    #[test]
+    fn test_failed_verification() {
+        let directory = tempfile::tempdir().unwrap();
+        let (code, stdout, run_status) = run(directory.path(), CHAIN);
+        assert_eq!(code, Some(RunOutcome::Unverified.get_code()), "{}", stdout);
+        assert_eq!(code, Some(7));
+
+        // Both steps ran, although the second one failed
+        assert!(directory.path().join("first").exists());
+        assert!(directory.path().join("second").exists());
+        assert_eq!(run_status.outcome, RunOutcome::Unverified);
+        assert!(run_status.failed_programs.is_empty());
+        assert_eq!(run_status.failed_verifications, vec![2]);
+        assert!(stdout.contains("Verification: 1 of 2 steps failed."), "{}", stdout);
+        assert!(stdout.contains("Verification step #2: sh -c"), "{}", stdout);
+    }
+
+    #[test]
+    fn test_verification_after_programs_succeed() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain: String = CHAIN.replace("9.9.9", "1.2.3");
+        let (code, stdout, run_status) = run(directory.path(), &chain);
+        assert_eq!(code, Some(0), "{}", stdout);
+        assert!(run_status.failed_verifications.is_empty());
+
+        // Nothing is verified when a program fails
+        std::fs::remove_file(directory.path().join("first")).unwrap();
+        let chain: String = CHAIN.replace("echo 1.2.3 > version", "exit 1");
+        let (code, _, run_status) = run(directory.path(), &chain);
+        assert_eq!(code, Some(RunOutcome::Aborted.get_code()));
+        assert_eq!(run_status.failed_programs, vec![0]);
+        assert!(!directory.path().join("first").exists());
+    }
+
+    #[test]
+    fn test_verification_steps_are_checks() {
+        let chain: String = CHAIN.replace(
+            r#"touch first"], "retry": 0 }"#,
+            r#"touch first"], "retry": 0,
+                "failure_handling_options": { "exit_on_failure": true, "remedy_command_line": { "command": "true", "arguments": [] } } }"#,
+        );
+        let mut chain = Chain::from_json_str(&chain, "verify").unwrap();
+        assert!(chain.validate_syntax().is_err());
+        assert!(chain.get_verification_problems()[0].contains("Verification step #1"));
+
+        let chain = Chain::from_json_str(CHAIN, "verify").unwrap();
+        assert_eq!(chain.get_verification_indices(), 1..3);
+        let listing = chain.get_program_listing();
+        assert!(!listing[0].verification);
+        assert!(listing[1].verification && listing[2].verification);
+        assert!(chain.get_dry_run_command_lines()[2].starts_with("Verification step #2: sh -c"));
+    }
+}
@@ -0,0 +1,116 @@
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use cchain::watch::{check_chain_file, get_watched_paths, render_check_report, CheckOptions, CheckReport, FileWatcher};
+
+    // Test that a report renders its outcome, problems and warnings
+    #[test]
+    fn test_render_check_report() {
+        let report = CheckReport::new(Vec::new(), vec!["Program #0: effect `x` is unknown".to_string()]);
+        let lines: Vec<String> = render_check_report(&report, "deploy.json", "2026-10-15 12:00:00");
+        assert_eq!(lines.len(), 2);
+        assert!(lines[0].contains("deploy.json passes the checks (2026-10-15 12:00:00)"));
+        assert!(lines[1].contains("warning:") && lines[1].contains("Program #0"));
+
+        let report = CheckReport::new(
+            vec!["`/programs/0/retry`: expected an integer".to_string(), "Program #1 is invalid".to_string()],
+            Vec::new(),
+        );
+        let lines: Vec<String> = render_check_report(&report, "deploy.json", "2026-10-15 12:00:01");
+        assert!(!report.is_passing());
+        assert!(lines[0].contains("deploy.json fails the checks with 2 problem(s)"));
+        assert!(lines[1].contains("error:") && lines[1].contains("/programs/0/retry"));
+        assert!(lines[2].contains("Program #1 is invalid"));
+    }
+
+    // Test that each check reads the chain from disk again
+    #[test]
+    fn test_check_reloads_chain() {
+        let directory = tempfile::tempdir().unwrap();
+        let path: PathBuf = directory.path().join("chain.json");
+        let chain_path: String = path.to_string_lossy().to_string();
+        let options = CheckOptions { lint: true, ..Default::default() };
+
+        std::fs::write(&path, r#"[{ "command": "echo", "arguments": ["hello"], "retry": 0 }]"#).unwrap();
+        assert!(check_chain_file(&chain_path, &options).is_passing());
+
+        std::fs::write(&path, r#"[{ "command": "echo", "arguments": "hello", "retry": 0 }]"#).unwrap();
+        let report: CheckReport = check_chain_file(&chain_path, &options);
+        assert_eq!(report.get_problems().len(), 1);
+
+        // A missing chain fails instead of stopping the watch
+        std::fs::remove_file(&path).unwrap();
+        assert!(!check_chain_file(&chain_path, &options).is_passing());
+    }
+
+    // Test that checks running at the same time each get their own warnings
+    #[test]
+    fn test_concurrent_checks_keep_their_warnings() {
+        let directory = tempfile::tempdir().unwrap();
+        let chain_paths: Vec<String> = (0..8)
+            .map(|index| {
+                let path: PathBuf = directory.path().join(format!("chain_{}.json", index));
+                let content = format!(
+                    r#"{{ "metadata": {{ "variables": {{ "unused_{}": {{}} }} }}, "programs": [{{ "command": "echo", "arguments": [], "retry": 0 }}] }}"#,
+                    index
+                );
+                std::fs::write(&path, content).unwrap();
+                path.to_string_lossy().to_string()
+            })
+            .collect();
+
+        std::thread::scope(|scope| {
+            for (index, chain_path) in chain_paths.iter().enumerate() {
+                scope.spawn(move || {
+                    for _ in 0..10 {
+                        let report: CheckReport = check_chain_file(chain_path, &CheckOptions::default());
+                        let warnings: &Vec<String> = report.get_warnings();
+                        assert!(warnings.iter().any(|warning| warning.contains(&format!("`unused_{}`", index))), "{:?}", warnings);
+                        assert!(warnings.iter().all(|warning| !warning.contains("`unused_") || warning.contains(&format!("`unused_{}`", index))), "{:?}", warnings);
+                    }
+                });
+            }
+        });
+    }
+
+    // Test that the chains a chain extends and its env file are watched
+    #[test]
+    fn test_watched_paths() {
+        let directory = tempfile::tempdir().unwrap();
+        let base_path: PathBuf = directory.path().join("base.json");
+        let path: PathBuf = directory.path().join("service.json");
+        std::fs::write(&base_path, r#"{ "programs": [{ "command": "echo", "arguments": [], "retry": 0 }] }"#).unwrap();
+        std::fs::write(&path, r#"{ "metadata": { "extends": "base.json" }, "programs": [] }"#).unwrap();
+
+        assert_eq!(
+            get_watched_paths(&path),
+            vec![path.clone(), directory.path().join("base.json"), directory.path().join(".cchain.env")]
+        );
+    }
+
+    // Test that the watcher notices changes, including files that go
+    // missing and come back
+    #[test]
+    fn test_file_watcher() {
+        let directory = tempfile::tempdir().unwrap();
+        let path: PathBuf = directory.path().join("chain.json");
+        let env_path: PathBuf = directory.path().join(".cchain.env");
+        std::fs::write(&path, "[]").unwrap();
+
+        let mut file_watcher = FileWatcher::new(vec![path.clone(), env_path.clone()]);
+        assert!(!file_watcher.has_changed());
+
+        std::fs::write(&path, "[ ]").unwrap();
+        assert!(file_watcher.has_changed());
+        assert!(!file_watcher.has_changed());
+
+        std::fs::remove_file(&path).unwrap();
+        assert!(file_watcher.has_changed());
+        std::fs::write(&path, "[]").unwrap();
+        assert!(file_watcher.has_changed());
+
+        std::fs::write(&env_path, "NAME=value\n").unwrap();
+        assert!(file_watcher.has_changed());
+    }
+}